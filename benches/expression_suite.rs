@@ -0,0 +1,64 @@
+//! Criterion benchmarks over the `bench` corpus, one group per phase.
+//!
+//! Run with `cargo bench --features bench --bench expression_suite`.
+
+use bumpalo::Bump;
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use exp_rs::bench::CORPUS;
+use exp_rs::engine::parse_expression;
+use exp_rs::{EvalContext, Expression};
+use std::rc::Rc;
+
+fn compile<'a>(case: &exp_rs::bench::BenchCase, arena: &'a Bump) -> Expression<'a> {
+    let mut batch = Expression::new(arena);
+    for &(name, value) in case.params {
+        batch.add_parameter(name, value).unwrap();
+    }
+    batch.add_expression(case.expression).unwrap();
+    batch
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for case in CORPUS {
+        group.bench_function(case.name, |b| {
+            b.iter(|| {
+                let arena = Bump::new();
+                black_box(parse_expression(black_box(case.expression), &arena).unwrap());
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_compile(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compile");
+    for case in CORPUS {
+        group.bench_function(case.name, |b| {
+            b.iter(|| {
+                let arena = Bump::new();
+                black_box(compile(case, &arena).expression_count());
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_eval(c: &mut Criterion) {
+    let ctx = Rc::new(EvalContext::new());
+    let mut group = c.benchmark_group("eval");
+    for case in CORPUS {
+        let arena = Bump::new();
+        let mut batch = compile(case, &arena);
+        group.bench_function(case.name, |b| {
+            b.iter(|| {
+                batch.eval(&ctx).unwrap();
+                black_box(batch.get_result(0));
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse, bench_compile, bench_eval);
+criterion_main!(benches);
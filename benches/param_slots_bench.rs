@@ -0,0 +1,49 @@
+use bumpalo::Bump;
+use criterion::{BenchmarkId, Criterion, black_box, criterion_group, criterion_main};
+use exp_rs::{EvalContext, Expression};
+use std::rc::Rc;
+
+// Batches with many parameters, where every evaluation sets all parameter slots and
+// every variable lookup goes through them before reaching the context
+fn bench_param_slots(c: &mut Criterion) {
+    let mut group = c.benchmark_group("param_slots");
+
+    let mut ctx = EvalContext::new();
+    ctx.set_parameter("ambient", 21.0).unwrap();
+    let ctx = Rc::new(ctx);
+
+    for count in [10, 100, 500] {
+        let arena = Bump::with_capacity(1024 * 1024);
+        let mut batch = Expression::new(&arena);
+        for i in 0..count {
+            batch.add_parameter(&format!("p{i}"), i as f64).unwrap();
+        }
+        batch
+            .register_expression_function("offset", &["x"], &format!("x - p{}", count / 2))
+            .unwrap();
+
+        // Each expression reads ten parameters spread over the batch, one through an
+        // expression function, and one context variable that misses every slot
+        for e in 0..10 {
+            let terms: Vec<String> = (0..10)
+                .map(|t| format!("p{}", (e * 7 + t * count / 10) % count))
+                .collect();
+            let expr = format!("{} + offset(p{e}) + ambient", terms.join(" + "));
+            batch.add_named_expression(&format!("r{e}"), &expr).unwrap();
+        }
+        batch.add_expression("r0 + r9").unwrap();
+
+        group.bench_with_input(BenchmarkId::new("eval", count), &count, |b, _| {
+            b.iter(|| {
+                batch.set_param(0, black_box(1.0)).unwrap();
+                batch.eval(&ctx).unwrap();
+                black_box(batch.get_result(10))
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_param_slots);
+criterion_main!(benches);
@@ -1,4 +1,129 @@
+/// Capacity limits that can be overridden at build time.
+///
+/// Each entry is `(name, default, must_be_power_of_two, description)`. Set an environment
+/// variable with the same name (for example in `.cargo/config.toml` under `[env]`) to override
+/// the default. Limits backing heapless `FnvIndexMap`s must be powers of two.
+const CAPACITY_LIMITS: &[(&str, usize, bool, &str)] = &[
+    (
+        "EXP_RS_MAX_VARIABLES",
+        16,
+        true,
+        "Maximum number of variables in an EvalContext",
+    ),
+    (
+        "EXP_RS_MAX_BATCH_PARAMS",
+        64,
+        true,
+        "Maximum number of parameters in an Expression batch",
+    ),
+    (
+        "EXP_RS_MAX_CONSTANTS",
+        8,
+        true,
+        "Maximum number of constants in an EvalContext",
+    ),
+    (
+        "EXP_RS_MAX_ARRAYS",
+        4,
+        true,
+        "Maximum number of arrays in an EvalContext",
+    ),
+    (
+        "EXP_RS_MAX_ATTRIBUTES",
+        4,
+        true,
+        "Maximum number of attribute objects in an EvalContext",
+    ),
+    (
+        "EXP_RS_MAX_NESTED_ARRAYS",
+        2,
+        true,
+        "Maximum number of nested arrays in an EvalContext",
+    ),
+    (
+        "EXP_RS_MAX_AST_CACHE",
+        16,
+        true,
+        "Maximum number of cached ASTs",
+    ),
+    (
+        "EXP_RS_MAX_NATIVE_FUNCTIONS",
+        128,
+        true,
+        "Maximum number of native functions in an EvalContext",
+    ),
+    (
+        "EXP_RS_MAX_EXPRESSION_FUNCTIONS",
+        8,
+        true,
+        "Maximum number of expression functions per batch",
+    ),
+    (
+        "EXP_RS_MAX_ATTR_KEYS",
+        4,
+        true,
+        "Maximum number of attributes per object",
+    ),
+    (
+        "EXP_RS_MAX_STACK_DEPTH",
+        1000,
+        false,
+        "Maximum depth of the iterative evaluator's operation stack",
+    ),
+    (
+        "EXP_RS_MAX_CONTEXTS",
+        128,
+        true,
+        "Maximum number of contexts tracked during one evaluation",
+    ),
+];
+
+/// Write `capacity.rs` into OUT_DIR and return `#define` lines for the C header.
+fn generate_capacity_config(out_dir: &str) -> Vec<String> {
+    let mut rust_src =
+        String::from("// Generated by build.rs from CAPACITY_LIMITS. Do not edit.\n");
+    let mut defines = Vec::new();
+
+    for &(name, default, power_of_two, description) in CAPACITY_LIMITS {
+        println!("cargo:rerun-if-env-changed={name}");
+        let value = match std::env::var(name) {
+            Ok(raw) => raw
+                .trim()
+                .parse::<usize>()
+                .unwrap_or_else(|_| panic!("{name} must be a positive integer, got '{raw}'")),
+            Err(_) => default,
+        };
+        if value == 0 || (power_of_two && !value.is_power_of_two()) {
+            panic!(
+                "{name} = {value} is invalid: must be non-zero{}",
+                if power_of_two {
+                    " and a power of two"
+                } else {
+                    ""
+                }
+            );
+        }
+        rust_src.push_str(&format!(
+            "/// {description} (default {default}, override with the `{name}` env var)\npub const {name}: usize = {value};\n"
+        ));
+        defines.push(format!("#define {name} {value}"));
+    }
+
+    let path = std::path::Path::new(out_dir).join("capacity.rs");
+    std::fs::write(path, rust_src).expect("Failed to write capacity.rs");
+    defines
+}
+
 fn main() {
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    let capacity_defines = generate_capacity_config(&out_dir);
+
+    // Emitting rerun-if-env-changed disables the default "rerun on any change" behaviour,
+    // so the inputs of the header generation must be listed explicitly.
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-changed=src");
+
     // Skip FFI build if requested
     if std::env::var("SKIP_FFI_BUILD").is_ok() {
         println!("cargo:warning=Skipping FFI build due to SKIP_FFI_BUILD environment variable");
@@ -11,7 +136,6 @@ fn main() {
     // }
 
     let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
-    let out_dir = std::env::var("OUT_DIR").unwrap();
 
     // Ensure the include directory exists
     std::fs::create_dir_all(out_dir.clone()).expect("Failed to create include directory");
@@ -31,6 +155,9 @@ fn main() {
 
     let mut after_includes_string = Vec::new();
 
+    // Capacity limits come from build.rs, so cbindgen cannot see them in the sources
+    after_includes_string.extend(capacity_defines);
+
     // after_includes_string.push(r#"#ifdef __cplusplus"#.to_string());
     // after_includes_string.push(r#"extern "C" {"#.to_string());
     // after_includes_string.push(r#"#endif"#.to_string());
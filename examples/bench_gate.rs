@@ -0,0 +1,71 @@
+//! Performance regression gate over the `bench` corpus on the host.
+//!
+//! Record a baseline (ticks are nanoseconds):
+//!     cargo run --release --features bench --example bench_gate -- --record bench_results/baseline-0.2.0.txt
+//! Check against it, failing if a phase is more than 10% slower:
+//!     cargo run --release --features bench --example bench_gate -- --check bench_results/baseline-0.2.0.txt 0.1
+
+use exp_rs::bench::{compare, run, to_baseline};
+use std::process::ExitCode;
+use std::time::Instant;
+
+const ITERATIONS: u32 = 2000;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (mode, path) = match args.as_slice() {
+        [mode, path, ..] => (mode.as_str(), path.as_str()),
+        _ => {
+            eprintln!("usage: bench_gate (--record | --check) <baseline> [max-slowdown]");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let epoch = Instant::now();
+    let mut clock = || epoch.elapsed().as_nanos() as u32;
+    let results = run(&mut clock, ITERATIONS).expect("benchmark corpus failed to evaluate");
+
+    match mode {
+        "--record" => {
+            let header = format!(
+                "# exp-rs {} on {}, nanoseconds\n",
+                env!("CARGO_PKG_VERSION"),
+                std::env::consts::ARCH
+            );
+            std::fs::write(path, header + &to_baseline(&results)).expect("cannot write baseline");
+            println!("Recorded {} measurements in {}", results.len(), path);
+            ExitCode::SUCCESS
+        }
+        "--check" => {
+            let max_slowdown = args
+                .get(2)
+                .map_or(0.1, |s| s.parse().expect("invalid slowdown"));
+            let baseline = std::fs::read_to_string(path).expect("cannot read baseline");
+            let regressions = compare(&results, &baseline, max_slowdown).expect("invalid baseline");
+            for r in &regressions {
+                println!(
+                    "{} {}: {} -> {} ns (+{:.0}%)",
+                    r.case,
+                    r.phase.name(),
+                    r.baseline,
+                    r.current,
+                    r.slowdown() * 100.0
+                );
+            }
+            if regressions.is_empty() {
+                println!(
+                    "No phase is more than {:.0}% slower than {}",
+                    max_slowdown * 100.0,
+                    path
+                );
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+        _ => {
+            eprintln!("unknown mode {}", mode);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! Compile-time expressions for exp-rs.
+//!
+//! The [`expr!`] macro parses an expression when the crate using it is compiled and
+//! expands to a `&'static exp_rs::types::AstExpr<'static>`, so a syntax error fails the
+//! build and firmware evaluating fixed formulas does no parsing at run time. Use it
+//! through exp-rs with the `macros` feature, which re-exports it as `exp_rs::expr`.
+//!
+//! The macro accepts the standard grammar, as parsed by `exp_rs::parse_expression`
+//! without [`ParseOptions`](https://docs.rs/exp-rs/latest/exp_rs/engine/struct.ParseOptions.html),
+//! and produces the same AST. Expressions using the opt-in syntax (implicit
+//! multiplication, unit or percent literals) have to be parsed at run time.
+
+use proc_macro::{Delimiter, Group, Literal, TokenStream, TokenTree};
+
+mod parser;
+
+/// Parses an expression string literal at compile time into a static AST.
+///
+/// ```ignore
+/// use exp_rs::{EvalContext, expr};
+/// use exp_rs::eval::eval_ast;
+/// use bumpalo::Bump;
+/// use std::rc::Rc;
+///
+/// let mut ctx = EvalContext::new();
+/// ctx.set_parameter("t", 0.5).unwrap();
+/// let arena = Bump::new();
+/// let brightness = expr!("0.5 + 0.5 * sin(t * 2 * pi)");
+/// assert!((eval_ast(brightness, Some(Rc::new(ctx)), &arena).unwrap() - 0.5).abs() < 1e-9);
+///
+/// // Fails to compile: Expected closing parenthesis ')' but found end of input
+/// // let broken = expr!("sin(t");
+/// ```
+#[proc_macro]
+pub fn expr(input: TokenStream) -> TokenStream {
+    let mut tokens = input.into_iter();
+    let literal = match (tokens.next(), tokens.next()) {
+        (Some(TokenTree::Literal(literal)), None) => literal,
+        // A literal passed on by a declarative macro arrives in an invisible group
+        (Some(TokenTree::Group(group)), None) if group.delimiter() == Delimiter::None => {
+            return expr(group.stream());
+        }
+        _ => return compile_error("expr! expects a single string literal", None),
+    };
+    let source = match unquote(&literal.to_string()) {
+        Some(source) => source,
+        None => return compile_error("expr! expects a single string literal", Some(&literal)),
+    };
+    match parser::parse(&source) {
+        Ok(ast) => format!(
+            "{{ static AST: ::exp_rs::types::AstExpr<'static> = {}; &AST }}",
+            ast.to_rust()
+        )
+        .parse()
+        .expect("generated AST is valid Rust"),
+        Err(message) => compile_error(&format!("invalid expression: {}", message), Some(&literal)),
+    }
+}
+
+/// `compile_error!(message)`, reported at the literal when there is one.
+fn compile_error(message: &str, literal: Option<&Literal>) -> TokenStream {
+    let tokens: TokenStream = format!("::core::compile_error!({:?})", message)
+        .parse()
+        .expect("compile_error! invocation is valid Rust");
+    match literal {
+        Some(literal) => tokens
+            .into_iter()
+            .map(|mut token| {
+                if let TokenTree::Group(group) = &token {
+                    let mut group = Group::new(group.delimiter(), group.stream());
+                    group.set_span(literal.span());
+                    token = TokenTree::Group(group);
+                } else {
+                    token.set_span(literal.span());
+                }
+                token
+            })
+            .collect(),
+        None => tokens,
+    }
+}
+
+/// Returns the contents of a string literal as written in the source, `"..."` or
+/// `r#"..."#`, with its escapes resolved.
+fn unquote(literal: &str) -> Option<String> {
+    if let Some(raw) = literal.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        let body = raw.get(hashes..raw.len().checked_sub(hashes)?)?;
+        return Some(body.strip_prefix('"')?.strip_suffix('"')?.to_string());
+    }
+    let body = literal.strip_prefix('"')?.strip_suffix('"')?;
+    let mut result = String::with_capacity(body.len());
+    let mut chars = body.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => result.push('\n'),
+            'r' => result.push('\r'),
+            't' => result.push('\t'),
+            '0' => result.push('\0'),
+            c @ ('\\' | '"' | '\'') => result.push(c),
+            // A line continuation skips the newline and the indentation after it
+            '\n' => {
+                let rest = chars.as_str().trim_start();
+                chars = rest.chars();
+            }
+            'u' => {
+                let rest = chars.as_str().strip_prefix('{')?;
+                let end = rest.find('}')?;
+                let code = u32::from_str_radix(&rest[..end].replace('_', ""), 16).ok()?;
+                result.push(char::from_u32(code)?);
+                chars = rest[end + 1..].chars();
+            }
+            'x' => {
+                let rest = chars.as_str();
+                let code = u8::from_str_radix(rest.get(..2)?, 16).ok()?;
+                result.push(char::from(code));
+                chars = rest[2..].chars();
+            }
+            _ => return None,
+        }
+    }
+    Some(result)
+}
@@ -0,0 +1,641 @@
+//! The standard expression grammar, mirroring the lexer and Pratt parser of exp-rs.
+//!
+//! exp-rs depends on this crate, so it cannot be used here; changes to the grammar of
+//! `exp_rs::engine` have to be made here as well. The `expr!` tests of exp-rs compare
+//! both parsers.
+
+use std::fmt::Write;
+
+/// Maximum length of the input in bytes, as `ParserLimits::DEFAULT`
+const MAX_LENGTH: usize = 10000;
+/// Maximum nesting of subexpressions, as `ParserLimits::DEFAULT`
+const MAX_NESTING: usize = 2000;
+
+/// An AST node, the compile-time counterpart of `exp_rs::types::AstExpr`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ast {
+    /// A number, with its literal as written
+    Constant(f64, String),
+    Variable(String),
+    Function {
+        name: String,
+        args: Vec<Ast>,
+    },
+    Array {
+        name: String,
+        index: Box<Ast>,
+    },
+    Attribute {
+        base: String,
+        attr: String,
+    },
+    LogicalOp {
+        and: bool,
+        left: Box<Ast>,
+        right: Box<Ast>,
+    },
+    Conditional {
+        condition: Box<Ast>,
+        true_branch: Box<Ast>,
+        false_branch: Box<Ast>,
+    },
+}
+
+impl Ast {
+    /// Renders the node as a constant Rust expression of type `AstExpr<'static>`.
+    pub fn to_rust(&self) -> String {
+        let mut out = String::new();
+        self.write_rust(&mut out);
+        out
+    }
+
+    fn write_rust(&self, out: &mut String) {
+        const AST: &str = "::exp_rs::types::AstExpr";
+        match self {
+            Ast::Constant(value, _) if value.is_infinite() => {
+                let _ = write!(out, "{AST}::Constant(::exp_rs::Real::INFINITY)");
+            }
+            Ast::Constant(_, text) => {
+                let _ = write!(out, "{AST}::Constant({})", float_literal(text));
+            }
+            Ast::Variable(name) => {
+                let _ = write!(out, "{AST}::Variable({name:?})");
+            }
+            Ast::Function { name, args } => {
+                let _ = write!(out, "{AST}::Function {{ name: {name:?}, args: &[");
+                for arg in args {
+                    arg.write_rust(out);
+                    out.push_str(", ");
+                }
+                out.push_str("] }");
+            }
+            Ast::Array { name, index } => {
+                let _ = write!(out, "{AST}::Array {{ name: {name:?}, index: &");
+                index.write_rust(out);
+                out.push_str(" }");
+            }
+            Ast::Attribute { base, attr } => {
+                let _ = write!(out, "{AST}::Attribute {{ base: {base:?}, attr: {attr:?} }}");
+            }
+            Ast::LogicalOp { and, left, right } => {
+                let op = if *and { "And" } else { "Or" };
+                let _ = write!(
+                    out,
+                    "{AST}::LogicalOp {{ op: ::exp_rs::types::LogicalOperator::{op}, left: &"
+                );
+                left.write_rust(out);
+                out.push_str(", right: &");
+                right.write_rust(out);
+                out.push_str(" }");
+            }
+            Ast::Conditional {
+                condition,
+                true_branch,
+                false_branch,
+            } => {
+                let _ = write!(out, "{AST}::Conditional {{ condition: &");
+                condition.write_rust(out);
+                out.push_str(", true_branch: &");
+                true_branch.write_rust(out);
+                out.push_str(", false_branch: &");
+                false_branch.write_rust(out);
+                out.push_str(" }");
+            }
+        }
+    }
+}
+
+/// A number as a Rust float literal, keeping its digits so that it is rounded to
+/// `Real` directly, as the run-time lexer does: `.5` is `0.5`, `2` is `2.0`.
+fn float_literal(text: &str) -> String {
+    let (mantissa, exponent) = match text.find(['e', 'E']) {
+        Some(at) => text.split_at(at),
+        None => (text, ""),
+    };
+    let mut mantissa = mantissa.to_string();
+    if mantissa.starts_with('.') {
+        mantissa.insert(0, '0');
+    }
+    if !mantissa.contains('.') {
+        mantissa.push('.');
+    }
+    if mantissa.ends_with('.') {
+        mantissa.push('0');
+    }
+    mantissa + exponent
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    Number(f64),
+    Variable,
+    Operator,
+    Open,
+    Close,
+    Separator,
+    Error,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: Kind,
+    text: String,
+    position: usize,
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn is_identifier_continue(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Splits `input` into tokens as `exp_rs::lexer::Lexer` does.
+fn tokenize(input: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while let Some(c) = input[pos..].chars().next() {
+        if c.is_whitespace() {
+            pos += c.len_utf8();
+            continue;
+        }
+        let start = pos;
+        let rest = &input[pos..];
+        let mut text = None;
+        let starts_number =
+            c.is_ascii_digit() || (c == '.' && rest[1..].starts_with(|d: char| d.is_ascii_digit()));
+        let kind = if starts_number {
+            let mut end = 1;
+            let (mut saw_dot, mut saw_e) = (c == '.', false);
+            let mut exponent_digits = false;
+            let bytes = rest.as_bytes();
+            while end < bytes.len() {
+                match bytes[end] {
+                    b'0'..=b'9' => exponent_digits |= saw_e,
+                    b'.' if !saw_dot => saw_dot = true,
+                    b'e' | b'E' if !saw_e => {
+                        saw_e = true;
+                        if matches!(bytes.get(end + 1), Some(b'+' | b'-')) {
+                            end += 1;
+                        }
+                    }
+                    _ => break,
+                }
+                end += 1;
+            }
+            pos += end;
+            let text = &rest[..end];
+            let number = if text.starts_with('.') {
+                format!("0{text}")
+            } else {
+                text.to_string()
+            };
+            match number.parse::<f64>() {
+                Ok(value) if !saw_e || exponent_digits => Kind::Number(value),
+                _ => Kind::Error,
+            }
+        } else if "+-*/^%.<>=!&|~?:".contains(c) {
+            let len = [
+                "<<<", ">>>", "**", "&&", "||", "<<", ">>", "<>", "<=", ">=", "==", "!=",
+            ]
+            .iter()
+            .find(|op| rest.starts_with(*op))
+            .map_or(1, |op| op.len());
+            if len == 3 {
+                // The run-time lexer reads `<<<` and `>>>` as `<<` and `>>`
+                text = Some(&rest[..2]);
+            }
+            pos += len;
+            Kind::Operator
+        } else if is_identifier_start(c) {
+            let mut end = 0;
+            while let Some(nc) = rest[end..].chars().next() {
+                if is_identifier_continue(nc) {
+                    end += nc.len_utf8();
+                } else if let Some(after) = rest[end..].strip_prefix("::")
+                    && after.starts_with(is_identifier_start)
+                {
+                    end += 2;
+                } else {
+                    break;
+                }
+            }
+            pos += end;
+            Kind::Variable
+        } else {
+            pos += c.len_utf8();
+            match c {
+                '(' | '[' => Kind::Open,
+                ')' | ']' => Kind::Close,
+                ',' | ';' => Kind::Separator,
+                _ => Kind::Error,
+            }
+        };
+        tokens.push(Token {
+            kind,
+            text: text.unwrap_or(&input[start..pos]).to_string(),
+            position: start,
+        });
+    }
+    tokens
+}
+
+/// Binding power `(left, right)` of an infix operator, as `exp_rs::engine`.
+fn infix_binding_power(op: &str) -> Option<(u8, u8)> {
+    let left_assoc = |power| Some((power, power + 1));
+    match op {
+        "," | ";" => left_assoc(1),
+        "?" => Some((1, 1)),
+        "||" => left_assoc(2),
+        "&&" => left_assoc(3),
+        "|" => left_assoc(4),
+        "&" => left_assoc(6),
+        "==" | "!=" | "<" | ">" | "<=" | ">=" | "<>" => left_assoc(7),
+        "<<" | ">>" | "<<<" | ">>>" => left_assoc(8),
+        "+" | "-" => left_assoc(9),
+        "*" | "/" | "%" => left_assoc(10),
+        "^" => Some((15, 15)),
+        "**" => Some((16, 16)),
+        _ => None,
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+    depth: usize,
+    input_len: usize,
+}
+
+/// Parses `input` into an AST, or returns the syntax error the run-time parser reports.
+pub fn parse(input: &str) -> Result<Ast, String> {
+    if input.len() > MAX_LENGTH {
+        return Err(format!(
+            "Expression too long: {} characters (maximum is {})",
+            input.len(),
+            MAX_LENGTH
+        ));
+    }
+    let mut parser = Parser {
+        tokens: tokenize(input),
+        next: 0,
+        depth: 0,
+        input_len: input.len(),
+    };
+    let ast = parser.expression(0, true)?;
+    match parser.peek() {
+        None => Ok(ast),
+        Some(tok) if tok.kind == Kind::Error => Err(format!(
+            "Unexpected token '{}' at position {}",
+            tok.text, tok.position
+        )),
+        Some(tok) if tok.kind == Kind::Close => Err(format!(
+            "Unexpected closing parenthesis at position {}: check for balanced parentheses",
+            tok.position
+        )),
+        Some(tok) => Err(format!(
+            "Unexpected token at position {}: '{}'",
+            tok.position, tok.text
+        )),
+    }
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn peek_is(&self, kind: Kind, text: &str) -> bool {
+        self.peek()
+            .is_some_and(|tok| tok.kind == kind && tok.text == text)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let tok = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        tok
+    }
+
+    fn expression(&mut self, min_bp: u8, allow_comma: bool) -> Result<Ast, String> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING {
+            return Err(format!(
+                "Expression too complex: exceeded maximum recursion depth of {}",
+                MAX_NESTING
+            ));
+        }
+        let lhs = self.prefix_or_primary(allow_comma)?;
+        let lhs = self.postfix(lhs)?;
+        let lhs = self.infix(lhs, min_bp, allow_comma)?;
+        self.depth -= 1;
+        Ok(lhs)
+    }
+
+    fn prefix_or_primary(&mut self, allow_comma: bool) -> Result<Ast, String> {
+        let Some(tok) = self.peek().cloned() else {
+            return self.primary();
+        };
+        if tok.kind == Kind::Error {
+            return Err(format!(
+                "Unexpected token '{}' at position {}",
+                tok.text, tok.position
+            ));
+        }
+        if tok.kind != Kind::Operator || !matches!(tok.text.as_str(), "+" | "-" | "~") {
+            return self.primary();
+        }
+        self.advance();
+        if self.peek().is_none() {
+            return Err(format!(
+                "Expected expression after '{}' at position {}",
+                tok.text, tok.position
+            ));
+        }
+        let rhs = self.expression(14, allow_comma)?;
+        Ok(if tok.text == "-" {
+            Ast::Function {
+                name: "neg".to_string(),
+                args: vec![rhs],
+            }
+        } else {
+            rhs
+        })
+    }
+
+    fn primary(&mut self) -> Result<Ast, String> {
+        let Some(tok) = self.peek().cloned() else {
+            return Err("Unexpected end of input".to_string());
+        };
+        match tok.kind {
+            Kind::Number(value) => {
+                self.advance();
+                Ok(Ast::Constant(value, tok.text))
+            }
+            Kind::Variable => {
+                self.advance();
+                Ok(Ast::Variable(tok.text))
+            }
+            Kind::Open if tok.text == "(" => {
+                self.advance();
+                let expr = self.expression(0, true)?;
+                match self.advance() {
+                    Some(close) if close.kind == Kind::Close => Ok(expr),
+                    Some(found) => Err(format!(
+                        "Expected closing parenthesis ')' but found '{}' at position {} (opening at position {})",
+                        found.text, found.position, tok.position
+                    )),
+                    None => Err(format!(
+                        "Expected closing parenthesis ')' but found end of input (opening at position {})",
+                        tok.position
+                    )),
+                }
+            }
+            Kind::Close => Err(format!(
+                "Unexpected closing parenthesis at position {}: '{}'",
+                tok.position, tok.text
+            )),
+            _ => Err(format!(
+                "Unexpected token at position {}: '{}'",
+                tok.position, tok.text
+            )),
+        }
+    }
+
+    fn postfix(&mut self, mut result: Ast) -> Result<Ast, String> {
+        loop {
+            if self.peek_is(Kind::Open, "(") {
+                result = self.call(result)?;
+            } else if self.peek_is(Kind::Open, "[") {
+                result = self.array(result)?;
+            } else if self.peek_is(Kind::Operator, ".") {
+                return self.attribute(result);
+            } else {
+                return Ok(result);
+            }
+        }
+    }
+
+    fn call(&mut self, expr: Ast) -> Result<Ast, String> {
+        let name = match expr {
+            Ast::Variable(name) => name,
+            Ast::Attribute { attr, .. } => attr,
+            _ => return Err("Function call on non-function expression".to_string()),
+        };
+        let open = self.advance().map_or(0, |tok| tok.position);
+        let mut args = Vec::new();
+        if self.peek().is_some_and(|tok| tok.kind != Kind::Close) {
+            args.push(self.expression(0, false)?);
+            while let Some(tok) = self.peek().cloned() {
+                if tok.kind == Kind::Separator && tok.text == "," {
+                    self.advance();
+                    args.push(self.expression(0, false)?);
+                } else if tok.kind == Kind::Close {
+                    break;
+                } else {
+                    return Err(format!(
+                        "Expected ',' or ')' but found '{}' at position {} in function call",
+                        tok.text, tok.position
+                    ));
+                }
+            }
+        }
+        match self.advance() {
+            Some(tok) if tok.kind == Kind::Close => {}
+            Some(tok) => {
+                return Err(format!(
+                    "Expected closing parenthesis ')' but found '{}' at position {} in function call",
+                    tok.text, tok.position
+                ));
+            }
+            None => return Err(format!("Unmatched parenthesis at position {}", open)),
+        }
+        // `pow(x)` is `pow(x, 2)` and `atan2(y)` is `atan2(y, 1)`
+        if args.len() == 1 && name == "pow" {
+            args.push(Ast::Constant(2.0, "2".to_string()));
+        } else if args.len() == 1 && name == "atan2" {
+            args.push(Ast::Constant(1.0, "1".to_string()));
+        }
+        Ok(Ast::Function { name, args })
+    }
+
+    fn array(&mut self, expr: Ast) -> Result<Ast, String> {
+        let open = self.peek().map_or(0, |tok| tok.position);
+        let name = match expr {
+            Ast::Variable(name) => name,
+            Ast::Attribute { base, attr } => format!("{base}.{attr}"),
+            _ => {
+                return Err(format!(
+                    "Array access on non-array expression at position {}",
+                    open
+                ));
+            }
+        };
+        self.advance();
+        let mut index = self.expression(0, true)?;
+        if self.peek_is(Kind::Operator, ":") {
+            self.advance();
+            let end = self.expression(0, true)?;
+            index = Ast::Function {
+                name: ":".to_string(),
+                args: vec![index, end],
+            };
+        }
+        match self.advance() {
+            Some(tok) if tok.kind == Kind::Close => Ok(Ast::Array {
+                name,
+                index: Box::new(index),
+            }),
+            Some(tok) => Err(format!(
+                "Expected closing bracket ']' at position {}, found '{}' (opening at position {})",
+                tok.position, tok.text, open
+            )),
+            None => Err(format!(
+                "Expected closing bracket ']' but found end of input (opening at position {})",
+                open
+            )),
+        }
+    }
+
+    fn attribute(&mut self, expr: Ast) -> Result<Ast, String> {
+        let dot = self.advance().map_or(self.input_len, |tok| tok.position);
+        let attr = match self.advance() {
+            Some(tok) if tok.kind == Kind::Variable => tok.text,
+            _ => return Err("Expected attribute name".to_string()),
+        };
+        let base = match expr {
+            Ast::Variable(base) => base,
+            Ast::Attribute { base, attr: inner } => format!("{base}.{inner}"),
+            _ => {
+                return Err(format!(
+                    "Attribute access on non-object expression at position {}",
+                    dot
+                ));
+            }
+        };
+        self.postfix(Ast::Attribute { base, attr })
+    }
+
+    fn infix(&mut self, mut lhs: Ast, min_bp: u8, allow_comma: bool) -> Result<Ast, String> {
+        while let Some(tok) = self.peek() {
+            let op = match tok.kind {
+                Kind::Operator => tok.text.clone(),
+                Kind::Separator if allow_comma => tok.text.clone(),
+                _ => break,
+            };
+            let Some((left, right)) = infix_binding_power(&op) else {
+                break;
+            };
+            if left < min_bp {
+                break;
+            }
+            self.advance();
+            lhs = match op.as_str() {
+                "?" => {
+                    let true_branch = self.expression(0, allow_comma)?;
+                    match self.advance() {
+                        Some(tok) if tok.kind == Kind::Operator && tok.text == ":" => {}
+                        Some(tok) => {
+                            return Err(format!(
+                                "Expected ':' in ternary expression, found '{}'",
+                                tok.text
+                            ));
+                        }
+                        None => {
+                            return Err("Expected ':' in ternary expression, found end of input"
+                                .to_string());
+                        }
+                    }
+                    let false_branch = self.expression(0, allow_comma)?;
+                    Ast::Conditional {
+                        condition: Box::new(lhs),
+                        true_branch: Box::new(true_branch),
+                        false_branch: Box::new(false_branch),
+                    }
+                }
+                "&&" | "||" => Ast::LogicalOp {
+                    and: op == "&&",
+                    left: Box::new(lhs),
+                    right: Box::new(self.expression(right, allow_comma)?),
+                },
+                _ => {
+                    let right = if op == "^" || op == "**" {
+                        right - 1
+                    } else {
+                        right
+                    };
+                    let rhs = self.expression(right, allow_comma)?;
+                    Ast::Function {
+                        name: op,
+                        args: vec![lhs, rhs],
+                    }
+                }
+            };
+        }
+        Ok(lhs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_standard_grammar() {
+        let function = |name: &str, args: Vec<Ast>| Ast::Function {
+            name: name.to_string(),
+            args,
+        };
+        let constant = |text: &str| Ast::Constant(text.parse().unwrap(), text.to_string());
+        let variable = |name: &str| Ast::Variable(name.to_string());
+
+        // -2^2 is -(2^2), and ^ is right-associative
+        assert_eq!(
+            parse("-2^3^.5").unwrap(),
+            function(
+                "neg",
+                vec![function(
+                    "^",
+                    vec![
+                        constant("2"),
+                        function("^", vec![constant("3"), constant(".5")])
+                    ]
+                )]
+            )
+        );
+        assert_eq!(
+            parse("a.b[i] + pow(x)").unwrap(),
+            function(
+                "+",
+                vec![
+                    Ast::Array {
+                        name: "a.b".to_string(),
+                        index: Box::new(variable("i")),
+                    },
+                    function(
+                        "pow",
+                        vec![variable("x"), Ast::Constant(2.0, "2".to_string())]
+                    ),
+                ]
+            )
+        );
+        assert!(matches!(
+            parse("x > 0 && y ? 1 : 2").unwrap(),
+            Ast::Conditional { condition, .. } if matches!(*condition, Ast::LogicalOp { and: true, .. })
+        ));
+
+        for (input, error) in [
+            ("sin(x", "Unmatched parenthesis at position 3"),
+            ("1 +", "Unexpected end of input"),
+            ("2 x", "Unexpected token at position 2: 'x'"),
+            ("1e+", "Unexpected token '1e+' at position 0"),
+            ("(1))", "Unexpected closing parenthesis at position 3"),
+        ] {
+            assert!(parse(input).unwrap_err().starts_with(error), "{input}");
+        }
+
+        assert_eq!(float_literal("2"), "2.0");
+        assert_eq!(float_literal(".5e-3"), "0.5e-3");
+        assert_eq!(float_literal("1.E3"), "1.0E3");
+    }
+}
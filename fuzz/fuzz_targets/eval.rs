@@ -0,0 +1,20 @@
+//! Evaluates generated expressions with generated variable values; errors are fine,
+//! panics are not.
+
+#![no_main]
+
+use exp_rs::EvalContext;
+use exp_rs::engine::interp;
+use exp_rs::fuzzing::{ByteSource, Generator};
+use libfuzzer_sys::fuzz_target;
+use std::rc::Rc;
+
+fuzz_target!(|data: &[u8]| {
+    let mut source = ByteSource::new(data);
+    let mut ctx = EvalContext::new();
+    for name in ["x", "y", "z"] {
+        let _ = ctx.set_parameter(name, source.real());
+    }
+    let expression = Generator::default().expression(&mut source);
+    let _ = interp(&expression, Some(Rc::new(ctx)));
+});
@@ -0,0 +1,21 @@
+//! Parses arbitrary text, which may fail but must not panic, and generated
+//! expressions, which must parse.
+
+#![no_main]
+
+use bumpalo::Bump;
+use exp_rs::engine::parse_expression;
+use exp_rs::fuzzing::{ByteSource, Generator};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let arena = Bump::new();
+    if let Ok(text) = core::str::from_utf8(data) {
+        let _ = parse_expression(text, &arena);
+    }
+
+    let expression = Generator::default().expression(&mut ByteSource::new(data));
+    if let Err(error) = parse_expression(&expression, &arena) {
+        panic!("generated '{expression}' does not parse: {error}");
+    }
+});
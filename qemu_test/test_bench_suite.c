@@ -0,0 +1,53 @@
+/**
+ * Cycle-count benchmark of the exp-rs corpus on QEMU
+ *
+ * Runs expr_bench_run with the harness timer and prints the ticks of each phase in
+ * the baseline format of the Rust `bench` module ("case phase ticks"), so the output
+ * can be saved to bench_results/ and compared across releases.
+ */
+#include "exp_rs.h"
+#include "qemu_harness/qemu_test_harness.h"
+#include <stdint.h>
+#include <stdlib.h>
+
+#define BENCH_ITERATIONS 50
+#define MAX_BENCH_CASES 32
+
+/* The harness timer counts down; the benchmark needs a counter that counts up */
+static uint32_t bench_clock(void) {
+  return ~qemu_get_tick_count();
+}
+
+int main(void) {
+#ifdef EXP_RS_CUSTOM_ALLOC
+  static uint8_t heap_memory[256 * 1024];
+  if (exp_rs_heap_init(heap_memory, sizeof(heap_memory)) != 0) {
+    qemu_printf("ERROR: Heap initialization failed\n");
+    qemu_exit(EXIT_FAILURE);
+    return 1;
+  }
+#endif
+
+  init_hardware_timer();
+
+  static ExprBenchResult results[MAX_BENCH_CASES];
+  uintptr_t count = expr_bench_case_count();
+  test_assert(count <= MAX_BENCH_CASES, "benchmark corpus fits the result array");
+
+  int32_t written = expr_bench_run(bench_clock, BENCH_ITERATIONS, results, MAX_BENCH_CASES);
+  if (written < 0) {
+    qemu_printf("ERROR: expr_bench_run failed with code %d\n", (int)written);
+    qemu_exit(EXIT_FAILURE);
+    return 1;
+  }
+
+  qemu_printf("# exp-rs on QEMU Cortex-M7, timer ticks\n");
+  for (int32_t i = 0; i < written; i++) {
+    qemu_printf("%s parse %u\n", results[i].name, (unsigned)results[i].parse_ticks);
+    qemu_printf("%s compile %u\n", results[i].name, (unsigned)results[i].compile_ticks);
+    qemu_printf("%s eval %u\n", results[i].name, (unsigned)results[i].eval_ticks);
+  }
+
+  qemu_exit(EXIT_SUCCESS);
+  return 0;
+}
@@ -0,0 +1,380 @@
+//! Forward-mode automatic differentiation (requires the `autodiff` feature).
+//!
+//! [`eval_with_derivative`] evaluates a parsed expression over dual numbers, returning
+//! its value and its derivative with respect to one variable in a single pass. Values
+//! are computed with the functions of the context, like normal evaluation, and
+//! derivatives follow the chain rule with a derivative rule for every builtin operator
+//! and math function. This gives exact gradients of user-written cost functions, for
+//! example for optimization at runtime.
+
+use crate::Real;
+use crate::context::EvalContext;
+use crate::error::ExprError;
+use crate::functions;
+use crate::types::{AstExpr, LogicalOperator, TryIntoHeaplessString};
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+/// A value with its derivative with respect to the differentiation variable.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Dual {
+    /// Value of the expression
+    pub value: Real,
+    /// Derivative of the expression
+    pub derivative: Real,
+}
+
+impl Dual {
+    /// Creates a dual number from a value and its derivative.
+    pub const fn new(value: Real, derivative: Real) -> Self {
+        Self { value, derivative }
+    }
+
+    /// Creates a dual number for a value that does not depend on the variable.
+    pub const fn constant(value: Real) -> Self {
+        Self::new(value, 0.0)
+    }
+}
+
+/// Step of the central differences used for functions without a derivative rule,
+/// about the cube root of the machine epsilon.
+#[cfg(not(feature = "f32"))]
+const DIFFERENCE_STEP: Real = 6.055_454_452_393_343e-6;
+#[cfg(feature = "f32")]
+const DIFFERENCE_STEP: Real = 4.921_566e-3;
+
+/// Evaluates an expression and its derivative with respect to the variable `wrt`.
+///
+/// `wrt` names a variable, constant or parameter of the context, or an attribute path
+/// such as `motor.rpm`; expressions that do not read it have a derivative of 0.
+///
+/// Derivative rules cover the arithmetic operators, `^`/`**`/`pow`, `sqrt`, `exp`,
+/// `expm1`, the logarithms, the trigonometric and hyperbolic functions and their
+/// inverses, `atan2`, `abs`, `hypot`, `fma`, `min` and `max`, which take the derivative
+/// of the argument they select. Comparisons, `&&`/`||`, `floor`, `ceil`, `round`,
+/// `trunc`, `sign` and array reads are piecewise constant and have a derivative of 0,
+/// and `?:` has the derivative of the branch it takes. Other native functions of the
+/// context are differentiated numerically with central differences. Rules are chosen
+/// by name, so a builtin overridden in the context keeps the derivative of the builtin.
+///
+/// Expression functions are not supported, as with the complex evaluator. Without a
+/// context, a default one is used.
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::autodiff::eval_with_derivative;
+/// use exp_rs::context::EvalContext;
+/// use exp_rs::engine::parse_expression;
+/// use bumpalo::Bump;
+///
+/// let mut ctx = EvalContext::new();
+/// ctx.set_parameter("u", 2.0).unwrap();
+/// ctx.set_parameter("r", 5.0).unwrap();
+///
+/// // Quadratic tracking cost with a control penalty
+/// let arena = Bump::new();
+/// let ast = parse_expression("(u * 3 - r)^2 + 0.1 * u^2", &arena).unwrap();
+/// let cost = eval_with_derivative(&ast, Some(&ctx), "u").unwrap();
+/// assert!((cost.value - 1.4).abs() < 1e-12);
+/// assert!((cost.derivative - 6.4).abs() < 1e-12); // 6*(3u - r) + 0.2u
+/// ```
+pub fn eval_with_derivative(
+    expr: &AstExpr,
+    ctx: Option<&EvalContext>,
+    wrt: &str,
+) -> Result<Dual, ExprError> {
+    let default_ctx;
+    let ctx = match ctx {
+        Some(ctx) => ctx,
+        None => {
+            default_ctx = EvalContext::new();
+            &default_ctx
+        }
+    };
+    Evaluator { ctx, wrt }.eval(expr)
+}
+
+struct Evaluator<'a> {
+    ctx: &'a EvalContext,
+    wrt: &'a str,
+}
+
+impl Evaluator<'_> {
+    fn eval(&self, expr: &AstExpr) -> Result<Dual, ExprError> {
+        match expr {
+            AstExpr::Constant(value) => Ok(Dual::constant(*value)),
+            AstExpr::Variable(name) => {
+                let value = self.lookup_variable(name)?;
+                Ok(Dual::new(value, if *name == self.wrt { 1.0 } else { 0.0 }))
+            }
+            AstExpr::Array { name, index } => {
+                let idx = self.eval(index)?.value as usize;
+                let array = self
+                    .ctx
+                    .get_array(name)
+                    .ok_or_else(|| ExprError::UnknownVariable {
+                        name: name.to_string(),
+                    })?;
+                array.get(idx).map(|v| Dual::constant(*v)).ok_or_else(|| {
+                    ExprError::ArrayIndexOutOfBounds {
+                        name: name.to_string(),
+                        index: idx,
+                        len: array.len(),
+                    }
+                })
+            }
+            AstExpr::Attribute { base, attr } => {
+                let value = self
+                    .ctx
+                    .get_attribute_map(base)
+                    .and_then(|m| m.get(&attr.try_into_heapless().ok()?).copied())
+                    .ok_or_else(|| ExprError::AttributeNotFound {
+                        base: base.to_string(),
+                        attr: attr.to_string(),
+                    })?;
+                let is_wrt = self.wrt.split_once('.') == Some((base, attr));
+                Ok(Dual::new(value, if is_wrt { 1.0 } else { 0.0 }))
+            }
+            AstExpr::LogicalOp { op, left, right } => {
+                let left = self.eval(left)?.value != 0.0;
+                let result = match op {
+                    LogicalOperator::And => left && self.eval(right)?.value != 0.0,
+                    LogicalOperator::Or => left || self.eval(right)?.value != 0.0,
+                };
+                Ok(Dual::constant(if result { 1.0 } else { 0.0 }))
+            }
+            AstExpr::Conditional {
+                condition,
+                true_branch,
+                false_branch,
+            } => {
+                if self.eval(condition)?.value != 0.0 {
+                    self.eval(true_branch)
+                } else {
+                    self.eval(false_branch)
+                }
+            }
+            AstExpr::Function { name, args } => {
+                self.ctx.check_function_permitted(name)?;
+                let mut duals = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    duals.push(self.eval(arg)?);
+                }
+                let values: Vec<Real> = duals.iter().map(|d| d.value).collect();
+                let value = self.call(name, &values)?;
+                if duals.iter().all(|d| d.derivative == 0.0) {
+                    return Ok(Dual::constant(value));
+                }
+                let derivative = match derivative_rule(name, &duals, value) {
+                    Some(derivative) => derivative,
+                    None => self.numerical_derivative(name, &duals)?,
+                };
+                Ok(Dual::new(value, derivative))
+            }
+        }
+    }
+
+    fn lookup_variable(&self, name: &str) -> Result<Real, ExprError> {
+        if let Some(value) = self
+            .ctx
+            .get_variable(name)
+            .or_else(|| self.ctx.get_constant(name))
+        {
+            return Ok(value);
+        }
+        match name {
+            "pi" | "PI" => Ok(core::f64::consts::PI as Real),
+            "e" | "E" => Ok(core::f64::consts::E as Real),
+            "tau" | "TAU" => Ok(2.0 * core::f64::consts::PI as Real),
+            _ => self
+                .ctx
+                .resolve_variable(name)
+                .ok_or_else(|| ExprError::UnknownVariable {
+                    name: name.to_string(),
+                }),
+        }
+    }
+
+    fn call(&self, name: &str, args: &[Real]) -> Result<Real, ExprError> {
+        self.ctx.call_function(name, args)
+    }
+
+    /// Chain rule with partial derivatives estimated by central differences.
+    fn numerical_derivative(&self, name: &str, args: &[Dual]) -> Result<Real, ExprError> {
+        let mut values: Vec<Real> = args.iter().map(|d| d.value).collect();
+        let mut derivative = 0.0;
+        for (i, arg) in args.iter().enumerate() {
+            if arg.derivative == 0.0 {
+                continue;
+            }
+            let step = DIFFERENCE_STEP * arg.value.abs().max(1.0);
+            values[i] = arg.value + step;
+            let above = self.call(name, &values)?;
+            values[i] = arg.value - step;
+            let below = self.call(name, &values)?;
+            values[i] = arg.value;
+            derivative += (above - below) / (2.0 * step) * arg.derivative;
+        }
+        Ok(derivative)
+    }
+}
+
+/// Derivative of the builtin `name` applied to `args`, given its `value`, or `None` if
+/// the builtin has no rule.
+fn derivative_rule(name: &str, args: &[Dual], value: Real) -> Option<Real> {
+    let d = match (name, args) {
+        ("+" | "add", [a, b]) => a.derivative + b.derivative,
+        ("-" | "sub", [a, b]) => a.derivative - b.derivative,
+        ("*" | "mul", [a, b]) => a.derivative * b.value + a.value * b.derivative,
+        ("/" | "div", [a, b]) => {
+            (a.derivative * b.value - a.value * b.derivative) / (b.value * b.value)
+        }
+        // a % b = a - trunc(a / b) * b
+        ("%" | "fmod", [a, b]) => a.derivative - (a.value - value) / b.value * b.derivative,
+        ("neg", [a]) => -a.derivative,
+        ("^" | "**" | "pow", [a, b]) => {
+            let mut d = 0.0;
+            if a.derivative != 0.0 {
+                d += b.value * functions::pow(a.value, b.value - 1.0) * a.derivative;
+            }
+            if b.derivative != 0.0 {
+                d += value * functions::ln(a.value, 0.0) * b.derivative;
+            }
+            d
+        }
+        ("sqrt", [a]) => a.derivative / (2.0 * value),
+        ("exp", [a]) => value * a.derivative,
+        ("expm1", [a]) => (value + 1.0) * a.derivative,
+        ("ln" | "log", [a]) => a.derivative / a.value,
+        ("log10", [a]) => a.derivative / (a.value * core::f64::consts::LN_10 as Real),
+        ("log2", [a]) => a.derivative / (a.value * core::f64::consts::LN_2 as Real),
+        ("log1p", [a]) => a.derivative / (1.0 + a.value),
+        ("sin", [a]) => functions::cos(a.value, 0.0) * a.derivative,
+        ("cos", [a]) => -functions::sin(a.value, 0.0) * a.derivative,
+        ("tan", [a]) => (1.0 + value * value) * a.derivative,
+        ("asin", [a]) => a.derivative / functions::sqrt(1.0 - a.value * a.value, 0.0),
+        ("acos", [a]) => -a.derivative / functions::sqrt(1.0 - a.value * a.value, 0.0),
+        ("atan", [a]) => a.derivative / (1.0 + a.value * a.value),
+        ("atan2", [y, x]) => {
+            (x.value * y.derivative - y.value * x.derivative)
+                / (x.value * x.value + y.value * y.value)
+        }
+        ("sinh", [a]) => functions::cosh(a.value, 0.0) * a.derivative,
+        ("cosh", [a]) => functions::sinh(a.value, 0.0) * a.derivative,
+        ("tanh", [a]) => (1.0 - value * value) * a.derivative,
+        ("abs", [a]) if a.value > 0.0 => a.derivative,
+        ("abs", [a]) if a.value < 0.0 => -a.derivative,
+        ("abs", [_]) => 0.0,
+        ("hypot", [a, b]) => (a.value * a.derivative + b.value * b.derivative) / value,
+        ("fma", [a, b, c]) => a.derivative * b.value + a.value * b.derivative + c.derivative,
+        ("min" | "max", _) => args
+            .iter()
+            .find(|arg| arg.value == value)
+            .map_or(0.0, |arg| arg.derivative),
+        ("," | ";" | "comma", [.., last]) => last.derivative,
+        (
+            "<" | ">" | "<=" | ">=" | "==" | "!=" | "<>" | "&&" | "||" | "floor" | "ceil" | "round"
+            | "trunc" | "sign" | "isnan" | "isinf" | "isfinite",
+            _,
+        ) => 0.0,
+        _ => return None,
+    };
+    Some(d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::parse_expression;
+    use bumpalo::Bump;
+
+    fn derivative(input: &str, ctx: &EvalContext, wrt: &str) -> Dual {
+        let arena = Bump::new();
+        let ast = parse_expression(input, &arena).unwrap();
+        eval_with_derivative(&ast, Some(ctx), wrt).unwrap()
+    }
+
+    #[test]
+    fn test_derivative_rules() {
+        let mut ctx = EvalContext::new();
+        ctx.set_parameter("x", 0.5).unwrap();
+        ctx.set_parameter("y", 2.0).unwrap();
+        ctx.set_attribute("motor", "rpm", 3.0).unwrap();
+        ctx.register_native_function("cube", 1, |args| args[0] * args[0] * args[0])
+            .unwrap();
+
+        // Each case is checked against a central difference of the expression itself
+        let cases = [
+            "x * y + x / y - y % (x + 0.3)",
+            "x ^ y + y ^ x",
+            "sqrt(x) * exp(x) - ln(y * x) + log10(x) + log2(y / x)",
+            "sin(x) * cos(x) + tan(x) + asin(x) + acos(x) + atan(x)",
+            "atan2(x, y) + sinh(x) + cosh(x) + tanh(x)",
+            "abs(-x) + hypot(x, y) + fma(x, y, x)",
+            "max(x, y * x, 0.1) + min(x, -x)",
+            "x > 0.2 ? x^3 : -x",
+            "cube(x * y) + floor(x * 3)",
+            "motor.rpm * x",
+        ];
+        for case in cases {
+            let exact = derivative(case, &ctx, "x");
+            let h = 1e-6;
+            let mut shifted = ctx.clone();
+            shifted.set_parameter("x", 0.5 + h).unwrap();
+            let above = derivative(case, &shifted, "x").value;
+            shifted.set_parameter("x", 0.5 - h).unwrap();
+            let below = derivative(case, &shifted, "x").value;
+            let numeric = (above - below) / (2.0 * h);
+            assert!(
+                (exact.derivative - numeric).abs() < 1e-5 * numeric.abs().max(1.0),
+                "{case}: {} != {numeric}",
+                exact.derivative
+            );
+        }
+
+        let rpm = derivative("motor.rpm ^ 2 + x", &ctx, "motor.rpm");
+        assert_eq!(rpm, Dual::new(9.5, 6.0));
+        assert_eq!(derivative("y * 3", &ctx, "x"), Dual::constant(6.0));
+        assert_eq!(derivative("x && y", &ctx, "x"), Dual::constant(1.0));
+
+        let arena = Bump::new();
+        let ast = parse_expression("x + nope(x)", &arena).unwrap();
+        assert!(matches!(
+            eval_with_derivative(&ast, Some(&ctx), "x"),
+            Err(ExprError::UnknownFunction { .. })
+        ));
+        let ast = parse_expression("2 * pi", &arena).unwrap();
+        assert_eq!(
+            eval_with_derivative(&ast, None, "x").unwrap().derivative,
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_function_policy() {
+        use crate::context::FunctionPolicy;
+        use alloc::rc::Rc;
+        use core::cell::Cell;
+
+        let calls = Rc::new(Cell::new(0));
+        let mut ctx = EvalContext::new();
+        ctx.set_parameter("x", 0.5).unwrap();
+        let counter = calls.clone();
+        ctx.register_native_function("write_reg", 1, move |args| {
+            counter.set(counter.get() + 1);
+            args[0]
+        })
+        .unwrap();
+        ctx.set_function_policy(Some(FunctionPolicy::deny(["write_reg"])));
+
+        let arena = Bump::new();
+        let ast = parse_expression("x^2 + write_reg(x)", &arena).unwrap();
+        assert!(matches!(
+            eval_with_derivative(&ast, Some(&ctx), "x"),
+            Err(ExprError::FunctionNotPermitted { .. })
+        ));
+        assert_eq!(calls.get(), 0);
+        assert_eq!(derivative("x^2", &ctx, "x"), Dual::new(0.25, 1.0));
+    }
+}
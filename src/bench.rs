@@ -0,0 +1,296 @@
+//! Benchmark corpus and tick-count harness (`bench` feature).
+//!
+//! [`CORPUS`] holds representative expressions, and [`run`] times parsing, compiling and
+//! evaluating each of them with a caller-provided counter: a cycle counter on a target
+//! (the QEMU suite passes its hardware timer to `expr_bench_run`) or a nanosecond clock
+//! on a host. Results are written as a text baseline with [`to_baseline`], and
+//! [`compare`] reports the phases that got slower than a recorded baseline, so changes to
+//! the evaluator can be gated on their cost. The baselines published for each release
+//! are kept in `bench_results/`.
+//!
+//! ```
+//! use exp_rs::bench::{compare, run, to_baseline};
+//!
+//! let mut ticks = 0u32;
+//! let mut clock = || {
+//!     ticks = ticks.wrapping_add(10);
+//!     ticks
+//! };
+//! let results = run(&mut clock, 3).unwrap();
+//! let baseline = to_baseline(&results);
+//! assert!(compare(&results, &baseline, 0.1).unwrap().is_empty());
+//! ```
+
+use crate::Real;
+use crate::context::EvalContext;
+use crate::engine::parse_expression;
+use crate::error::ExprError;
+use crate::expression::Expression;
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use bumpalo::Bump;
+use core::fmt::Write;
+use core::hint::black_box;
+
+/// A benchmarked expression with the parameters it reads.
+#[derive(Debug, Clone, Copy)]
+pub struct BenchCase {
+    /// Short name identifying the case in baselines
+    pub name: &'static str,
+    /// Expression text
+    pub expression: &'static str,
+    /// Parameters of the expression with their values
+    pub params: &'static [(&'static str, Real)],
+}
+
+/// The benchmarked expressions, covering arithmetic, builtin calls, conditionals,
+/// short-circuit operators and deep nesting.
+pub const CORPUS: &[BenchCase] = &[
+    BenchCase {
+        name: "arithmetic",
+        expression: "a * b + c / 2 - 1",
+        params: &[("a", 1.5), ("b", 2.0), ("c", 3.0)],
+    },
+    BenchCase {
+        name: "polynomial",
+        expression: "3*x^3 - 2*x^2 + x - 7",
+        params: &[("x", 1.25)],
+    },
+    BenchCase {
+        name: "trigonometry",
+        expression: "a*sin(b*pi/180) + c*cos(b*pi/180)",
+        params: &[("a", 2.0), ("b", 30.0), ("c", 0.5)],
+    },
+    BenchCase {
+        name: "distance",
+        expression: "sqrt((x2 - x1)^2 + (y2 - y1)^2)",
+        params: &[("x1", 1.0), ("y1", 2.0), ("x2", 4.0), ("y2", 6.0)],
+    },
+    BenchCase {
+        name: "conditional",
+        expression: "x > 0 ? x * gain : x * gain / 2",
+        params: &[("x", -3.0), ("gain", 1.5)],
+    },
+    BenchCase {
+        name: "logical",
+        expression: "(a > 0 && b < 10) || c == 3",
+        params: &[("a", 1.0), ("b", 12.0), ("c", 3.0)],
+    },
+    BenchCase {
+        name: "nested",
+        expression: "((((a + 1) * 2 + 3) * 4 + 5) * 6 + 7) / 8",
+        params: &[("a", 0.5)],
+    },
+    BenchCase {
+        name: "functions",
+        expression: "max(a, b) + min(a, c) + abs(a - c) + floor(b * 2.5)",
+        params: &[("a", 1.0), ("b", 2.2), ("c", 3.7)],
+    },
+];
+
+/// Stage of processing an expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    /// Parsing the text into an AST
+    Parse,
+    /// Building a batch with the parameters and the expression
+    Compile,
+    /// Evaluating the compiled batch
+    Eval,
+}
+
+impl Phase {
+    /// All phases, in processing order.
+    pub const ALL: [Phase; 3] = [Phase::Parse, Phase::Compile, Phase::Eval];
+
+    /// Returns the name of the phase in baselines.
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Parse => "parse",
+            Phase::Compile => "compile",
+            Phase::Eval => "eval",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|phase| phase.name() == name)
+    }
+}
+
+/// Cost of one phase of a [`CORPUS`] case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Measurement {
+    /// Name of the case
+    pub case: &'static str,
+    /// Phase measured
+    pub phase: Phase,
+    /// Fewest ticks taken by the phase over the iterations
+    pub ticks: u32,
+}
+
+/// A phase that got slower than its baseline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Regression {
+    /// Name of the case
+    pub case: &'static str,
+    /// Phase that got slower
+    pub phase: Phase,
+    /// Ticks recorded in the baseline
+    pub baseline: u32,
+    /// Ticks measured now
+    pub current: u32,
+}
+
+impl Regression {
+    /// Returns the relative slowdown, such as 0.25 for a phase taking 25% longer.
+    pub fn slowdown(&self) -> Real {
+        (self.current as Real - self.baseline as Real) / (self.baseline as Real).max(1.0)
+    }
+}
+
+/// Times each phase of every [`CORPUS`] case.
+///
+/// `clock` returns a free-running counter in any unit; differences are taken with
+/// wrapping arithmetic, so a 32-bit cycle counter can be used directly as long as one
+/// phase takes less than a full wrap. Each phase runs `iterations` times (at least once)
+/// and the fewest ticks are kept, which filters out interrupts and cache misses.
+/// Results are in corpus order, with the phases of a case in processing order.
+pub fn run(clock: &mut dyn FnMut() -> u32, iterations: u32) -> Result<Vec<Measurement>, ExprError> {
+    let ctx = Rc::new(EvalContext::new());
+    let mut results = Vec::with_capacity(CORPUS.len() * Phase::ALL.len());
+    for case in CORPUS {
+        let mut best = [u32::MAX; 3];
+        for _ in 0..iterations.max(1) {
+            let arena = Bump::new();
+            let start = clock();
+            black_box(parse_expression(case.expression, &arena)?);
+            best[0] = best[0].min(clock().wrapping_sub(start));
+
+            let arena = Bump::new();
+            let start = clock();
+            let mut batch = compile(case, &arena)?;
+            best[1] = best[1].min(clock().wrapping_sub(start));
+
+            let start = clock();
+            batch.eval(&ctx)?;
+            black_box(batch.get_result(0));
+            best[2] = best[2].min(clock().wrapping_sub(start));
+        }
+        for (phase, ticks) in Phase::ALL.into_iter().zip(best) {
+            results.push(Measurement {
+                case: case.name,
+                phase,
+                ticks,
+            });
+        }
+    }
+    Ok(results)
+}
+
+fn compile<'arena>(case: &BenchCase, arena: &'arena Bump) -> Result<Expression<'arena>, ExprError> {
+    let mut batch = Expression::new(arena);
+    for &(name, value) in case.params {
+        batch.add_parameter(name, value)?;
+    }
+    batch.add_expression(case.expression)?;
+    Ok(batch)
+}
+
+/// Writes `results` as a baseline: one `case phase ticks` line per measurement.
+pub fn to_baseline(results: &[Measurement]) -> String {
+    let mut out = String::new();
+    for m in results {
+        let _ = writeln!(out, "{} {} {}", m.case, m.phase.name(), m.ticks);
+    }
+    out
+}
+
+/// Compares `results` with a baseline written by [`to_baseline`], returning the phases
+/// that take more than `max_slowdown` longer (0.1 for 10%).
+///
+/// Blank lines and lines starting with `#` in the baseline are ignored, as are cases
+/// that are no longer in the corpus. Fails with `ExprError::Syntax` naming the line of
+/// a malformed entry.
+pub fn compare(
+    results: &[Measurement],
+    baseline: &str,
+    max_slowdown: Real,
+) -> Result<Vec<Regression>, ExprError> {
+    let mut regressions = Vec::new();
+    for (index, line) in baseline.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let entry = match (fields.next(), fields.next(), fields.next(), fields.next()) {
+            (Some(case), Some(phase), Some(ticks), None) => Phase::from_name(phase)
+                .zip(ticks.parse::<u32>().ok())
+                .map(|(phase, ticks)| (case, phase, ticks)),
+            _ => None,
+        };
+        let Some((case, phase, baseline_ticks)) = entry else {
+            return Err(ExprError::Syntax {
+                message: format!("Baseline line {}: expected `case phase ticks`", index + 1),
+                position: None,
+            });
+        };
+        let current = results.iter().find(|m| m.case == case && m.phase == phase);
+        if let Some(m) = current
+            && m.ticks as Real > baseline_ticks as Real * (1.0 + max_slowdown)
+        {
+            regressions.push(Regression {
+                case: m.case,
+                phase,
+                baseline: baseline_ticks,
+                current: m.ticks,
+            });
+        }
+    }
+    Ok(regressions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bench_run_and_compare() {
+        // Every corpus expression is valid and evaluates
+        let mut ticks = 0u32;
+        let mut clock = || {
+            ticks = ticks.wrapping_add(7);
+            ticks
+        };
+        let results = run(&mut clock, 2).unwrap();
+        assert_eq!(results.len(), CORPUS.len() * 3);
+        assert!(results.iter().all(|m| m.ticks == 7));
+
+        let baseline = "# host\nnested eval 5\n\nnested parse 7\nremoved eval 1\n";
+        let regressions = compare(&results, baseline, 0.2).unwrap();
+        assert_eq!(
+            regressions,
+            [Regression {
+                case: "nested",
+                phase: Phase::Eval,
+                baseline: 5,
+                current: 7
+            }]
+        );
+        assert!((regressions[0].slowdown() - 0.4).abs() < 1e-6);
+        assert!(compare(&results, baseline, 0.5).unwrap().is_empty());
+
+        assert!(compare(&results, "nested eval", 0.1).is_err());
+        assert!(compare(&results, "nested link 5", 0.1).is_err());
+
+        // A wrapping counter still gives the elapsed ticks
+        let mut wrapping = u32::MAX - 20;
+        let mut clock = || {
+            wrapping = wrapping.wrapping_add(15);
+            wrapping
+        };
+        assert!(run(&mut clock, 1).unwrap().iter().all(|m| m.ticks == 15));
+    }
+}
@@ -0,0 +1,363 @@
+//! Command-line evaluator, built with the `cli` feature.
+//!
+//! Evaluates expressions given as arguments, read from batch files or, without either,
+//! from stdin, one expression per line:
+//!
+//!     cargo run --features cli -- --var x=1.5 "sin(x) * 2" "x^2"
+//!     cargo run --features cli -- --ast "a + b * c"
+//!     cargo run --features cli -- --file checks.txt
+//!     echo "2 * pi" | cargo run --features cli
+//!
+//! Blank lines and lines starting with `#` are skipped. A line `x = 2 * pi` sets a
+//! variable and `scale(v, gain=2) = v * gain` defines an expression function for the
+//! lines after it. A result is printed per line, errors go to stderr, and the exit
+//! status is 1 if any line failed.
+//!
+//! Run from a terminal without expressions, or with `--repl` after them, it starts a
+//! REPL keeping its variables and functions across lines, with commands to list them
+//! and to toggle AST and timing output (`:help` lists them).
+
+use bumpalo::Bump;
+use exp_rs::error::ExprError;
+use exp_rs::expression::Expression;
+use exp_rs::{EvalContext, Real, parse_expression};
+use std::io::{BufRead, IsTerminal, Write};
+use std::process::ExitCode;
+use std::rc::Rc;
+use std::time::Instant;
+
+const USAGE: &str = "usage: exp-rs [OPTION]... [EXPRESSION]...
+
+Evaluates each EXPRESSION, each line of each batch file, or without either, each line
+of stdin, or from a terminal, starts a REPL. Blank lines and lines starting with '#'
+are skipped. 'NAME = EXPRESSION' sets a variable and 'NAME(A, B=1) = EXPRESSION'
+defines an expression function for the lines that follow.
+
+options:
+  -v, --var NAME=VALUE  set a variable (repeatable)
+  -f, --file PATH       evaluate the expressions of a batch file (repeatable)
+      --f32             round variables and results to f32, as on firmware built with
+                        the f32 feature (intermediate results keep the build's precision)
+      --ast             print the parsed AST before each result
+      --time            print the parse and evaluation times after each result
+  -i, --repl            start a REPL after evaluating the other input
+  -h, --help            print this help";
+
+#[derive(Default)]
+struct Options {
+    vars: Vec<(String, Real)>,
+    files: Vec<String>,
+    expressions: Vec<String>,
+    f32: bool,
+    ast: bool,
+    time: bool,
+    repl: bool,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{name} needs a value"));
+        match arg.as_str() {
+            "-v" | "--var" => {
+                let assignment = value(&arg)?;
+                let (name, number) = assignment
+                    .split_once('=')
+                    .ok_or(format!("expected NAME=VALUE, got '{assignment}'"))?;
+                let number = number
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("invalid value for {}: '{number}'", name.trim()))?;
+                options.vars.push((name.trim().to_string(), number));
+            }
+            "-f" | "--file" => options.files.push(value(&arg)?),
+            "--f32" => options.f32 = true,
+            "--ast" => options.ast = true,
+            "--time" => options.time = true,
+            "-i" | "--repl" => options.repl = true,
+            "-h" | "--help" => return Err(String::new()),
+            _ if arg.starts_with("--") => return Err(format!("unknown option '{arg}'")),
+            // A single '-' starts an expression such as "-x^2"
+            _ => options.expressions.push(arg),
+        }
+    }
+    Ok(options)
+}
+
+/// Rounds to the nearest `f32` when `enabled`.
+fn round(value: Real, enabled: bool) -> Real {
+    if enabled { value as f32 as Real } else { value }
+}
+
+/// Evaluates lines against a context and expression functions kept across lines.
+struct Session {
+    ctx: Rc<EvalContext>,
+    /// Expression functions defined so far, as name, parameters and body
+    functions: Vec<(String, Vec<String>, String)>,
+    f32: bool,
+    ast: bool,
+    time: bool,
+    failed: bool,
+}
+
+impl Session {
+    /// Evaluates one line, printing its result or reporting its error under `origin`.
+    ///
+    /// A line `name = expression` sets a variable and `name(a, b=1) = expression`
+    /// defines an expression function, both used by the lines that follow.
+    fn run(&mut self, line: &str, origin: &str) {
+        let result = match split_definition(line) {
+            Some((target, body)) => self.define(target, body),
+            None => self
+                .evaluate(line)
+                .map(|value| round(value, self.f32).to_string()),
+        };
+        match result {
+            Ok(output) => println!("{output}"),
+            Err(error) => {
+                eprintln!("{origin}: {error}");
+                self.failed = true;
+            }
+        }
+    }
+
+    /// Parses and evaluates an expression with the session's functions.
+    fn evaluate(&self, expression: &str) -> Result<Real, ExprError> {
+        let arena = Bump::new();
+        let mut batch = Expression::new(&arena);
+        for (name, params, body) in &self.functions {
+            let params: Vec<&str> = params.iter().map(String::as_str).collect();
+            batch.register_expression_function(name, &params, body)?;
+        }
+        let started = Instant::now();
+        batch.add_expression(expression)?;
+        let parsed = started.elapsed();
+        if self.ast
+            && let Some(ast) = batch.expression_ast(0)
+        {
+            println!("{ast:#?}");
+        }
+        let started = Instant::now();
+        batch.eval(&self.ctx)?;
+        let evaluated = started.elapsed();
+        if self.time {
+            println!("# parse {parsed:?}, eval {evaluated:?}");
+        }
+        Ok(batch.get_result(0).unwrap_or(Real::NAN))
+    }
+
+    /// Sets the variable or defines the function `target` as `body`.
+    fn define(&mut self, target: &str, body: &str) -> Result<String, ExprError> {
+        if is_identifier(target) {
+            let value = round(self.evaluate(body)?, self.f32);
+            Rc::make_mut(&mut self.ctx).set_parameter(target, value)?;
+            return Ok(format!("{target} = {value}"));
+        }
+        let (name, params) = target
+            .strip_suffix(')')
+            .and_then(|target| target.split_once('('))
+            .filter(|(name, _)| is_identifier(name.trim()))
+            .ok_or_else(|| ExprError::syntax(format!("cannot assign to '{target}'")))?;
+        let name = name.trim().to_string();
+        let params: Vec<String> = params
+            .split(',')
+            .map(|param| param.trim().to_string())
+            .filter(|param| !param.is_empty())
+            .collect();
+        if let Some(param) = params
+            .iter()
+            .find(|param| !is_identifier(param.split('=').next().unwrap_or("").trim()))
+        {
+            return Err(ExprError::syntax(format!("invalid parameter '{param}'")));
+        }
+        // Check the definition before keeping it
+        let arena = Bump::new();
+        parse_expression(body, &arena)?;
+        let param_refs: Vec<&str> = params.iter().map(String::as_str).collect();
+        Expression::new(&arena).register_expression_function(&name, &param_refs, body)?;
+
+        let definition = format!("{name}({}) = {body}", params.join(", "));
+        self.functions.retain(|(existing, ..)| *existing != name);
+        self.functions.push((name, params, body.to_string()));
+        Ok(definition)
+    }
+
+    /// Evaluates each line of `input` that is neither blank nor a comment.
+    fn run_lines(&mut self, input: impl BufRead, name: &str) -> std::io::Result<()> {
+        for (number, line) in input.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if !line.is_empty() && !line.starts_with('#') {
+                self.run(line, &format!("{name}:{}", number + 1));
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads and evaluates lines interactively until `:quit` or the end of stdin.
+    fn repl(&mut self) -> std::io::Result<()> {
+        let stdin = std::io::stdin();
+        let interactive = stdin.is_terminal();
+        let initial = (self.ctx.clone(), self.functions.clone());
+        if interactive {
+            println!("exp-rs {}, :help for commands", env!("CARGO_PKG_VERSION"));
+        }
+        let mut line = String::new();
+        loop {
+            if interactive {
+                print!("> ");
+                std::io::stdout().flush()?;
+            }
+            line.clear();
+            if stdin.lock().read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let command = line.trim();
+            match command.split_whitespace().collect::<Vec<_>>().as_slice() {
+                [] => {}
+                [":q" | ":quit"] => return Ok(()),
+                [":h" | ":help"] => println!("{REPL_HELP}"),
+                [":ast"] => {
+                    self.ast = !self.ast;
+                    println!("AST output {}", if self.ast { "on" } else { "off" });
+                }
+                [":time"] => {
+                    self.time = !self.time;
+                    println!("timing {}", if self.time { "on" } else { "off" });
+                }
+                [":vars"] => {
+                    for name in self.ctx.list_variables() {
+                        let value = self.ctx.get_variable(&name).unwrap_or(Real::NAN);
+                        println!("{name} = {value}");
+                    }
+                }
+                [":funcs"] => {
+                    for (name, params, body) in &self.functions {
+                        println!("{name}({}) = {body}", params.join(", "));
+                    }
+                }
+                [":reset"] => {
+                    (self.ctx, self.functions) = initial.clone();
+                    println!("session reset");
+                }
+                [other, ..] if other.starts_with(':') => {
+                    eprintln!("error: unknown command '{other}', :help for commands");
+                }
+                _ => {
+                    self.run(command, "error");
+                    // Errors at the prompt do not make the exit status fail
+                    self.failed = false;
+                }
+            }
+        }
+    }
+}
+
+const REPL_HELP: &str = "  EXPRESSION               evaluate an expression
+  NAME = EXPRESSION        set a variable
+  NAME(A, B=1) = EXPRESSION  define an expression function
+  :vars                    list the variables
+  :funcs                   list the expression functions
+  :ast                     toggle printing ASTs
+  :time                    toggle printing parse and evaluation times
+  :reset                   forget the variables and functions defined in the session
+  :quit                    leave";
+
+/// Splits `target = body` at an `=` outside parentheses that is not part of `==`,
+/// `!=`, `<=` or `>=`.
+fn split_definition(line: &str) -> Option<(&str, &str)> {
+    let bytes = line.as_bytes();
+    let mut depth = 0usize;
+    for (i, &byte) in bytes.iter().enumerate() {
+        match byte {
+            b'(' => depth += 1,
+            b')' => depth = depth.saturating_sub(1),
+            b'=' if depth == 0
+                && bytes.get(i + 1) != Some(&b'=')
+                && !matches!(
+                    i.checked_sub(1).map(|j| bytes[j]),
+                    Some(b'=' | b'!' | b'<' | b'>')
+                ) =>
+            {
+                return Some((line[..i].trim(), line[i + 1..].trim()));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) if message.is_empty() => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("error: {message}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let mut ctx = EvalContext::new();
+    for (name, value) in &options.vars {
+        if let Err(error) = ctx.set_parameter(name, round(*value, options.f32)) {
+            eprintln!("error: cannot set {name}: {error}");
+            return ExitCode::from(2);
+        }
+    }
+    let mut session = Session {
+        ctx: Rc::new(ctx),
+        functions: Vec::new(),
+        f32: options.f32,
+        ast: options.ast,
+        time: options.time,
+        failed: false,
+    };
+
+    for path in &options.files {
+        let file = match std::fs::File::open(path) {
+            Ok(file) => file,
+            Err(error) => {
+                eprintln!("error: cannot open {path}: {error}");
+                return ExitCode::from(2);
+            }
+        };
+        if let Err(error) = session.run_lines(std::io::BufReader::new(file), path) {
+            eprintln!("error: cannot read {path}: {error}");
+            return ExitCode::from(2);
+        }
+    }
+    for (index, expression) in options.expressions.iter().enumerate() {
+        session.run(expression, &format!("argument {}", index + 1));
+    }
+    let stdin = std::io::stdin();
+    let no_input = options.files.is_empty() && options.expressions.is_empty();
+    if options.repl || (no_input && stdin.is_terminal()) {
+        if let Err(error) = session.repl() {
+            eprintln!("error: cannot read stdin: {error}");
+            return ExitCode::from(2);
+        }
+    } else if no_input {
+        if let Err(error) = session.run_lines(stdin.lock(), "stdin") {
+            eprintln!("error: cannot read stdin: {error}");
+            return ExitCode::from(2);
+        }
+    }
+
+    if session.failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,103 @@
+//! Time builtins backed by a user-provided clock (`now`, `dt` and `elapsed`).
+//!
+//! The context does not read any system time itself: [`EvalContext::set_clock`]
+//! installs a callback returning the current time in seconds, which can wrap a cycle
+//! counter on bare metal or `std::time::Instant` on a host.
+//!
+//! [`EvalContext::set_clock`]: crate::context::EvalContext::set_clock
+
+use crate::Real;
+use alloc::collections::BTreeMap;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use core::cell::{Cell, RefCell};
+
+/// Callback returning the current time in seconds.
+pub type ClockSource = Rc<dyn Fn() -> Real>;
+
+/// A clock source together with the state of `dt` and the named timers.
+pub struct Clock {
+    source: ClockSource,
+    last_tick: Cell<Option<Real>>,
+    timers: RefCell<BTreeMap<String, Real>>,
+}
+
+impl core::fmt::Debug for Clock {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Clock")
+            .field("last_tick", &self.last_tick.get())
+            .field("timers", &self.timers.borrow())
+            .finish()
+    }
+}
+
+impl Clock {
+    /// Creates a clock reading time from `source`.
+    pub fn new(source: ClockSource) -> Self {
+        Self {
+            source,
+            last_tick: Cell::new(None),
+            timers: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    /// Returns the current time. This is the `now()` builtin.
+    pub fn now(&self) -> Real {
+        (self.source)()
+    }
+
+    /// Returns the time since the previous call, or 0 on the first call. This is the
+    /// `dt()` builtin.
+    pub fn dt(&self) -> Real {
+        let now = self.now();
+        let dt = self.last_tick.get().map_or(0.0, |last| now - last);
+        self.last_tick.set(Some(now));
+        dt
+    }
+
+    /// Returns the time since the timer `name` was started, starting it (and returning
+    /// 0) if it is not running. This is the `elapsed(name)` builtin.
+    pub fn elapsed(&self, name: &str) -> Real {
+        let now = self.now();
+        let mut timers = self.timers.borrow_mut();
+        match timers.get(name) {
+            Some(start) => now - start,
+            None => {
+                timers.insert(name.to_string(), now);
+                0.0
+            }
+        }
+    }
+
+    /// Restarts the timer `name` from the current time.
+    pub fn reset_timer(&self, name: &str) {
+        let now = self.now();
+        self.timers.borrow_mut().insert(name.to_string(), now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_dt_and_timers() {
+        let time = Rc::new(Cell::new(10.0));
+        let source = time.clone();
+        let clock = Clock::new(Rc::new(move || source.get()));
+
+        assert_eq!(clock.now(), 10.0);
+        assert_eq!(clock.dt(), 0.0);
+        assert_eq!(clock.elapsed("warmup"), 0.0);
+
+        time.set(10.5);
+        assert_eq!(clock.dt(), 0.5);
+        assert_eq!(clock.elapsed("warmup"), 0.5);
+
+        time.set(12.0);
+        assert_eq!(clock.dt(), 1.5);
+        clock.reset_timer("warmup");
+        time.set(13.0);
+        assert_eq!(clock.elapsed("warmup"), 1.0);
+    }
+}
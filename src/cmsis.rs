@@ -0,0 +1,163 @@
+//! Builtins backed by CMSIS-DSP, with the `cmsis-dsp` feature.
+//!
+//! On Cortex-M targets the CMSIS-DSP library provides fast single-precision
+//! implementations of common functions. With this feature, contexts register `sin`,
+//! `cos`, `sqrt`, `atan2`, `exp` and `ln` backed by them in place of the libm versions,
+//! so firmware no longer needs to register each one by hand through
+//! `expr_context_add_function`.
+//!
+//! The functions are declared here and resolved when the firmware is linked, so the
+//! CMSIS-DSP library (built for the target, as in the QEMU tests) must be linked into
+//! the final binary. `atan2`, `exp` and `ln` use `arm_atan2_f32`, `arm_vexp_f32` and
+//! `arm_vlog_f32`, which need CMSIS-DSP 1.10 or later. The computations are in `f32`,
+//! also when [`Real`] is `f64`.
+//!
+//! ```ignore
+//! // Requires the CMSIS-DSP library at link time
+//! let ctx = exp_rs::EvalContext::new();
+//! assert!(ctx.get_native_function("sin").is_some()); // arm_sin_f32
+//! ```
+
+use crate::Real;
+use crate::context::EvalContext;
+
+/// `ARM_MATH_SUCCESS`, the `arm_status` of a successful call
+const ARM_MATH_SUCCESS: i32 = 0;
+
+unsafe extern "C" {
+    fn arm_sin_f32(x: f32) -> f32;
+    fn arm_cos_f32(x: f32) -> f32;
+    fn arm_sqrt_f32(x: f32, out: *mut f32) -> i32;
+    fn arm_atan2_f32(y: f32, x: f32, out: *mut f32) -> i32;
+    fn arm_vexp_f32(src: *const f32, dst: *mut f32, block_size: u32);
+    fn arm_vlog_f32(src: *const f32, dst: *mut f32, block_size: u32);
+}
+
+/// Sine of `x` in radians, by `arm_sin_f32`.
+pub fn sin(x: Real) -> Real {
+    unsafe { arm_sin_f32(x as f32) as Real }
+}
+
+/// Cosine of `x` in radians, by `arm_cos_f32`.
+pub fn cos(x: Real) -> Real {
+    unsafe { arm_cos_f32(x as f32) as Real }
+}
+
+/// Square root of `x` by `arm_sqrt_f32`, NaN for negative `x` as with libm.
+pub fn sqrt(x: Real) -> Real {
+    let mut out = 0.0f32;
+    match unsafe { arm_sqrt_f32(x as f32, &mut out) } {
+        ARM_MATH_SUCCESS => out as Real,
+        _ => Real::NAN,
+    }
+}
+
+/// Angle of the point `(x, y)` in radians, by `arm_atan2_f32`.
+pub fn atan2(y: Real, x: Real) -> Real {
+    let mut out = 0.0f32;
+    match unsafe { arm_atan2_f32(y as f32, x as f32, &mut out) } {
+        ARM_MATH_SUCCESS => out as Real,
+        _ => Real::NAN,
+    }
+}
+
+/// Exponential of `x`, by `arm_vexp_f32`.
+pub fn exp(x: Real) -> Real {
+    let (src, mut out) = (x as f32, 0.0f32);
+    unsafe { arm_vexp_f32(&src, &mut out, 1) };
+    out as Real
+}
+
+/// Natural logarithm of `x`, by `arm_vlog_f32`.
+pub fn ln(x: Real) -> Real {
+    let (src, mut out) = (x as f32, 0.0f32);
+    unsafe { arm_vlog_f32(&src, &mut out, 1) };
+    out as Real
+}
+
+/// Registers the CMSIS-DSP backed builtins in `ctx` as pure functions, replacing
+/// functions of the same name. Contexts created with the `cmsis-dsp` feature already
+/// have them.
+pub fn register_functions(ctx: &mut EvalContext) {
+    let _ = ctx.register_native_function("sin", 1, |args| sin(args[0]));
+    let _ = ctx.register_native_function("cos", 1, |args| cos(args[0]));
+    let _ = ctx.register_native_function("sqrt", 1, |args| sqrt(args[0]));
+    let _ = ctx.register_native_function("atan2", 2, |args| atan2(args[0], args[1]));
+    let _ = ctx.register_native_function("exp", 1, |args| exp(args[0]));
+    let _ = ctx.register_native_function("ln", 1, |args| ln(args[0]));
+    for name in ["sin", "cos", "sqrt", "atan2", "exp", "ln"] {
+        let _ = ctx.set_function_pure(name, true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::interp;
+    use alloc::rc::Rc;
+
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Calls of the sine, which tell the library from the libm version
+    static SIN_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    // Host stand-ins for the library, which is only linked into firmware
+    #[unsafe(no_mangle)]
+    extern "C" fn arm_sin_f32(x: f32) -> f32 {
+        SIN_CALLS.fetch_add(1, Ordering::Relaxed);
+        x.sin()
+    }
+
+    #[unsafe(no_mangle)]
+    extern "C" fn arm_cos_f32(x: f32) -> f32 {
+        x.cos()
+    }
+
+    #[unsafe(no_mangle)]
+    extern "C" fn arm_sqrt_f32(x: f32, out: *mut f32) -> i32 {
+        // ARM_MATH_ARGUMENT_ERROR, with the output set to 0
+        let (value, status) = if x >= 0.0 { (x.sqrt(), 0) } else { (0.0, -1) };
+        unsafe { *out = value };
+        status
+    }
+
+    #[unsafe(no_mangle)]
+    extern "C" fn arm_atan2_f32(y: f32, x: f32, out: *mut f32) -> i32 {
+        unsafe { *out = y.atan2(x) };
+        0
+    }
+
+    #[unsafe(no_mangle)]
+    extern "C" fn arm_vexp_f32(src: *const f32, dst: *mut f32, block_size: u32) {
+        for i in 0..block_size as usize {
+            unsafe { *dst.add(i) = (*src.add(i)).exp() };
+        }
+    }
+
+    #[unsafe(no_mangle)]
+    extern "C" fn arm_vlog_f32(src: *const f32, dst: *mut f32, block_size: u32) {
+        for i in 0..block_size as usize {
+            unsafe { *dst.add(i) = (*src.add(i)).ln() };
+        }
+    }
+
+    #[test]
+    fn test_cmsis_builtins() {
+        assert_eq!(sqrt(16.0), 4.0);
+        assert!(sqrt(-1.0).is_nan());
+        assert!((atan2(1.0, 1.0) - 0.785_398).abs() < 1e-5);
+        assert!((exp(1.0) - 2.718_282).abs() < 1e-5);
+        assert!((ln(2.0) - 0.693_147).abs() < 1e-5);
+        assert!((cos(0.0) - 1.0).abs() < 1e-6);
+
+        // New contexts use the library, and registering replaces custom versions
+        let mut ctx = EvalContext::new();
+        let calls = SIN_CALLS.load(Ordering::Relaxed);
+        assert_eq!(interp("sin(0)", Some(Rc::new(ctx.clone()))).unwrap(), 0.0);
+        assert!(SIN_CALLS.load(Ordering::Relaxed) > calls);
+        ctx.register_native_function("sin", 1, |_| 42.0).unwrap();
+        register_functions(&mut ctx);
+        assert!(ctx.is_function_pure("sin"));
+        assert_eq!(interp("sin(0) + sqrt(9)", Some(Rc::new(ctx))).unwrap(), 3.0);
+    }
+}
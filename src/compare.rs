@@ -0,0 +1,328 @@
+//! Structural equality and hashing of ASTs.
+//!
+//! `AstExpr` implements `PartialEq`, `Eq` and `Hash` by structure: two trees are equal
+//! if they have the same shape, names and constants, wherever they are allocated.
+//! Constants compare by value, except that all NaNs are equal to each other and `-0`
+//! equals `0`, so equality is an equivalence and agrees with hashing. Parentheses and
+//! spacing of the source text do not matter, since they are not part of the tree.
+//!
+//! [`AstExpr::eq_commutative`] and the [`Commutative`] key additionally treat the
+//! operands of commutative operators as unordered, so `a + b` matches `b + a`.
+//!
+//! ```
+//! use exp_rs::compare::Commutative;
+//! use exp_rs::engine::parse_expression;
+//! use bumpalo::Bump;
+//! use std::collections::HashSet;
+//!
+//! let arena = Bump::new();
+//! let a = parse_expression("(x + 1) * gain", &arena).unwrap();
+//! let b = parse_expression("(x+1)*gain", &arena).unwrap();
+//! let c = parse_expression("gain * (1 + x)", &arena).unwrap();
+//! assert_eq!(a, b);
+//! assert_ne!(a, c);
+//! assert!(a.eq_commutative(&c));
+//!
+//! let unique: HashSet<_> = [&a, &b, &c].into_iter().map(Commutative).collect();
+//! assert_eq!(unique.len(), 1);
+//! ```
+
+use crate::Real;
+use crate::types::AstExpr;
+use core::hash::{Hash, Hasher};
+
+/// Operators and functions whose operands can be reordered without changing the result,
+/// assuming their default meaning.
+const COMMUTATIVE: &[&str] = &[
+    "+", "*", "add", "mul", "==", "!=", "<>", "max", "min", "hypot",
+];
+
+/// Bits identifying a constant: NaNs share one pattern and `-0` is `0`.
+#[allow(clippy::unnecessary_cast)] // `Real` may be `f32`
+fn constant_bits(value: Real) -> u64 {
+    if value.is_nan() {
+        u64::MAX
+    } else if value == 0.0 {
+        0
+    } else {
+        value.to_bits() as u64
+    }
+}
+
+impl PartialEq for AstExpr<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        structural_eq(self, other, false)
+    }
+}
+
+impl Eq for AstExpr<'_> {}
+
+impl Hash for AstExpr<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        structural_hash(self, false, state);
+    }
+}
+
+impl AstExpr<'_> {
+    /// Compares two trees by structure, treating the operands of commutative operators
+    /// (`+`, `*`, `==`, `!=`, `max`, `min` and `hypot`) as unordered.
+    ///
+    /// Only operand order is ignored: `(a + b) + c` and `a + (b + c)` still differ, as
+    /// they round differently. Operators are assumed to have their default meaning.
+    pub fn eq_commutative(&self, other: &AstExpr<'_>) -> bool {
+        structural_eq(self, other, true)
+    }
+
+    /// Returns a 64-bit hash of the tree's structure that is stable across platforms
+    /// and builds, optionally ignoring the order of commutative operands as
+    /// [`eq_commutative`](Self::eq_commutative) does.
+    pub fn structural_hash(&self, commutative: bool) -> u64 {
+        let mut hasher = Fnv::default();
+        structural_hash(self, commutative, &mut hasher);
+        hasher.finish()
+    }
+}
+
+/// A hash map or set key comparing ASTs with [`AstExpr::eq_commutative`].
+#[derive(Debug, Clone, Copy)]
+pub struct Commutative<'a, 'arena>(pub &'a AstExpr<'arena>);
+
+impl PartialEq for Commutative<'_, '_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.eq_commutative(other.0)
+    }
+}
+
+impl Eq for Commutative<'_, '_> {}
+
+impl Hash for Commutative<'_, '_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.0.structural_hash(true));
+    }
+}
+
+fn structural_eq(a: &AstExpr<'_>, b: &AstExpr<'_>, commutative: bool) -> bool {
+    match (a, b) {
+        (AstExpr::Constant(x), AstExpr::Constant(y)) => constant_bits(*x) == constant_bits(*y),
+        (AstExpr::Variable(x), AstExpr::Variable(y)) => x == y,
+        (
+            AstExpr::Function { name, args },
+            AstExpr::Function {
+                name: other_name,
+                args: other_args,
+            },
+        ) => {
+            if name != other_name || args.len() != other_args.len() {
+                return false;
+            }
+            if commutative && COMMUTATIVE.contains(name) {
+                same_operands(args, other_args)
+            } else {
+                args.iter()
+                    .zip(other_args.iter())
+                    .all(|(x, y)| structural_eq(x, y, commutative))
+            }
+        }
+        (
+            AstExpr::Array { name, index },
+            AstExpr::Array {
+                name: other_name,
+                index: other_index,
+            },
+        ) => name == other_name && structural_eq(index, other_index, commutative),
+        (
+            AstExpr::Attribute { base, attr },
+            AstExpr::Attribute {
+                base: other_base,
+                attr: other_attr,
+            },
+        ) => base == other_base && attr == other_attr,
+        (
+            AstExpr::LogicalOp { op, left, right },
+            AstExpr::LogicalOp {
+                op: other_op,
+                left: other_left,
+                right: other_right,
+            },
+        ) => {
+            op == other_op
+                && structural_eq(left, other_left, commutative)
+                && structural_eq(right, other_right, commutative)
+        }
+        (
+            AstExpr::Conditional {
+                condition,
+                true_branch,
+                false_branch,
+            },
+            AstExpr::Conditional {
+                condition: other_condition,
+                true_branch: other_true,
+                false_branch: other_false,
+            },
+        ) => {
+            structural_eq(condition, other_condition, commutative)
+                && structural_eq(true_branch, other_true, commutative)
+                && structural_eq(false_branch, other_false, commutative)
+        }
+        _ => false,
+    }
+}
+
+/// Whether the operands match in some order. Matching greedily is exact because
+/// commutative equality is an equivalence relation.
+fn same_operands(args: &[AstExpr<'_>], other_args: &[AstExpr<'_>]) -> bool {
+    if let ([a, b], [c, d]) = (args, other_args) {
+        return (structural_eq(a, c, true) && structural_eq(b, d, true))
+            || (structural_eq(a, d, true) && structural_eq(b, c, true));
+    }
+    let mut used = alloc::vec![false; other_args.len()];
+    args.iter().all(|arg| {
+        let found = other_args
+            .iter()
+            .enumerate()
+            .position(|(i, other)| !used[i] && structural_eq(arg, other, true));
+        if let Some(i) = found {
+            used[i] = true;
+        }
+        found.is_some()
+    })
+}
+
+fn structural_hash<H: Hasher>(expr: &AstExpr<'_>, commutative: bool, state: &mut H) {
+    match expr {
+        AstExpr::Constant(value) => {
+            state.write_u8(0);
+            state.write_u64(constant_bits(*value));
+        }
+        AstExpr::Variable(name) => {
+            state.write_u8(1);
+            name.hash(state);
+        }
+        AstExpr::Function { name, args } => {
+            state.write_u8(2);
+            name.hash(state);
+            state.write_u64(args.len() as u64);
+            if commutative && COMMUTATIVE.contains(name) {
+                // Summing the operand hashes makes the result independent of their order
+                let sum = args
+                    .iter()
+                    .fold(0u64, |sum, arg| sum.wrapping_add(arg.structural_hash(true)));
+                state.write_u64(sum);
+            } else {
+                for arg in args.iter() {
+                    structural_hash(arg, commutative, state);
+                }
+            }
+        }
+        AstExpr::Array { name, index } => {
+            state.write_u8(3);
+            name.hash(state);
+            structural_hash(index, commutative, state);
+        }
+        AstExpr::Attribute { base, attr } => {
+            state.write_u8(4);
+            base.hash(state);
+            attr.hash(state);
+        }
+        AstExpr::LogicalOp { op, left, right } => {
+            state.write_u8(5);
+            op.hash(state);
+            structural_hash(left, commutative, state);
+            structural_hash(right, commutative, state);
+        }
+        AstExpr::Conditional {
+            condition,
+            true_branch,
+            false_branch,
+        } => {
+            state.write_u8(6);
+            structural_hash(condition, commutative, state);
+            structural_hash(true_branch, commutative, state);
+            structural_hash(false_branch, commutative, state);
+        }
+    }
+}
+
+/// 64-bit FNV-1a, as used for [`ExpressionFunction::definition_hash`](crate::types::ExpressionFunction::definition_hash).
+pub(crate) struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::parse_expression;
+    use bumpalo::Bump;
+
+    #[test]
+    fn test_structural_equality_and_hashing() {
+        let arena = Bump::new();
+        let parse = |s: &str| parse_expression(s, &arena).unwrap();
+
+        assert_eq!(
+            parse("a*(b+1) > 0 ? x[i] : p.q"),
+            parse("(a) * (b + 1)>0?x[i]:p.q")
+        );
+        assert_eq!(
+            parse("0 / 0"),
+            AstExpr::Function {
+                name: "/",
+                args: &[AstExpr::Constant(0.0), AstExpr::Constant(-0.0)],
+            }
+        );
+        assert_eq!(AstExpr::Constant(Real::NAN), AstExpr::Constant(-Real::NAN));
+        assert_ne!(parse("a - b"), parse("b - a"));
+        assert_ne!(parse("a && b"), parse("a || b"));
+        assert_ne!(parse("f(a)"), parse("f(a, a)"));
+
+        // Equal trees hash equally, also for the standard hasher
+        let hash = |e: &AstExpr| {
+            let mut h = std::collections::hash_map::DefaultHasher::new();
+            e.hash(&mut h);
+            h.finish()
+        };
+        assert_eq!(hash(&parse("sin(x) + 1")), hash(&parse("sin( x )+1")));
+        assert_eq!(
+            parse("sin(x) + 1").structural_hash(false),
+            parse("sin(x)+1").structural_hash(false)
+        );
+        assert_ne!(
+            parse("x + 1").structural_hash(false),
+            parse("1 + x").structural_hash(false)
+        );
+
+        // Commutative operands are unordered, others are not
+        for (a, b) in [
+            ("x + 1", "1 + x"),
+            ("(a * b) + max(c, d, e)", "max(e, c, d) + b * a"),
+            ("a == b ? 1 : 0", "b == a ? 1 : 0"),
+        ] {
+            assert!(parse(a).eq_commutative(&parse(b)), "{a} vs {b}");
+            assert_eq!(
+                parse(a).structural_hash(true),
+                parse(b).structural_hash(true)
+            );
+        }
+        assert!(!parse("a - b").eq_commutative(&parse("b - a")));
+        assert!(!parse("max(a, a, b)").eq_commutative(&parse("max(a, b, b)")));
+        assert!(!parse("(a + b) + c").eq_commutative(&parse("a + (b + c)")));
+    }
+}
@@ -0,0 +1,304 @@
+//! Differential testing against recorded reference results (`compat` feature).
+//!
+//! A fixture lists expressions with the results another implementation, such as
+//! tinyexpr++, gave for them. [`run`] evaluates every case with exp-rs and reports the
+//! cases whose results differ, overall and per operator, so that semantic differences
+//! can be found before migrating expressions from the reference.
+//!
+//! The fixture is plain text with one case per line: the expression, a tab, and the
+//! expected result, which is a number (including `nan` and `inf`) or `error` for
+//! expressions the reference rejects. Blank lines and lines starting with `#` are
+//! ignored.
+//!
+//! ```
+//! use exp_rs::compat::{parse_fixture, run};
+//!
+//! let fixture = "\
+//! ## recorded with the reference implementation
+//! 1 + 2 * 3\t7
+//! 2 ^ 3 ^ 2\t64
+//! sqrt(-1)\tnan
+//! 1 +\terror
+//! ";
+//! let cases = parse_fixture(fixture).unwrap();
+//! let report = run(&cases, None, 1e-9);
+//! assert_eq!(report.total, 4);
+//!
+//! // exp-rs evaluates `^` right to left, giving 2^9 = 512
+//! assert_eq!(report.divergences.len(), 1);
+//! assert_eq!(report.divergences[0].case.line, 3);
+//! let pow = report.operators().into_iter().find(|op| op.operator == "^").unwrap();
+//! assert_eq!((pow.cases, pow.divergences), (1, 1));
+//! ```
+
+use crate::Real;
+use crate::context::EvalContext;
+use crate::error::ExprError;
+use crate::types::{AstExpr, LogicalOperator};
+use crate::visit::{AstVisitor, walk_ast};
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Result recorded for a case.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Expected {
+    /// The reference evaluated the expression to this value
+    Value(Real),
+    /// The reference rejected the expression
+    Error,
+}
+
+/// An expression with its recorded result.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Case<'a> {
+    /// Line of the case in the fixture, starting at 1
+    pub line: usize,
+    /// Expression text
+    pub expression: &'a str,
+    /// Result recorded from the reference
+    pub expected: Expected,
+}
+
+/// A case for which exp-rs gives a different result than the reference.
+#[derive(Debug, Clone)]
+pub struct Divergence<'a> {
+    /// The case
+    pub case: Case<'a>,
+    /// Result of exp-rs
+    pub actual: Result<Real, ExprError>,
+}
+
+/// Number of cases using an operator or function, and how many of them diverge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OperatorParity {
+    /// Operator or function name; `&&`, `||` and `?:` for the logical and conditional
+    /// operators
+    pub operator: String,
+    /// Cases whose expression uses the operator
+    pub cases: usize,
+    /// Diverging cases whose expression uses the operator
+    pub divergences: usize,
+}
+
+/// Outcome of [`run`].
+#[derive(Debug, Clone)]
+pub struct Report<'a> {
+    /// Number of cases run
+    pub total: usize,
+    /// Cases with a different result, in fixture order
+    pub divergences: Vec<Divergence<'a>>,
+    /// Operators used by each case, by line
+    operators: BTreeMap<usize, Vec<String>>,
+}
+
+impl Report<'_> {
+    /// Returns whether every case matched the reference.
+    pub fn is_clean(&self) -> bool {
+        self.divergences.is_empty()
+    }
+
+    /// Returns the parity of each operator and function used by the cases, sorted by
+    /// name. Expressions that exp-rs cannot parse do not count towards any operator.
+    pub fn operators(&self) -> Vec<OperatorParity> {
+        let mut parity: BTreeMap<&str, OperatorParity> = BTreeMap::new();
+        for (line, operators) in &self.operators {
+            let diverges = self.divergences.iter().any(|d| d.case.line == *line);
+            for operator in operators {
+                let entry = parity.entry(operator).or_insert_with(|| OperatorParity {
+                    operator: operator.clone(),
+                    cases: 0,
+                    divergences: 0,
+                });
+                entry.cases += 1;
+                entry.divergences += diverges as usize;
+            }
+        }
+        parity.into_values().collect()
+    }
+}
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} of {} cases diverge from the reference",
+            self.divergences.len(),
+            self.total
+        )?;
+        for divergence in &self.divergences {
+            let case = &divergence.case;
+            write!(f, "  line {}: {} expected ", case.line, case.expression)?;
+            match case.expected {
+                Expected::Value(value) => write!(f, "{}", value)?,
+                Expected::Error => write!(f, "an error")?,
+            }
+            match &divergence.actual {
+                Ok(value) => writeln!(f, ", got {}", value)?,
+                Err(err) => writeln!(f, ", got error: {}", err)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses a fixture into its cases.
+///
+/// Fails with `ExprError::Syntax` naming the line of a malformed case.
+pub fn parse_fixture(text: &str) -> Result<Vec<Case<'_>>, ExprError> {
+    let mut cases = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let malformed = |message: &str| ExprError::Syntax {
+            message: format!("Fixture line {}: {}", index + 1, message),
+            position: None,
+        };
+        let (expression, expected) = line
+            .rsplit_once('\t')
+            .ok_or_else(|| malformed("expected an expression and a result separated by a tab"))?;
+        let expected = match expected.trim() {
+            "error" => Expected::Error,
+            value => Expected::Value(
+                value
+                    .parse()
+                    .map_err(|_| malformed("the result is neither a number nor `error`"))?,
+            ),
+        };
+        cases.push(Case {
+            line: index + 1,
+            expression: expression.trim(),
+            expected,
+        });
+    }
+    Ok(cases)
+}
+
+/// Evaluates every case with `ctx` and compares the results with the recorded ones.
+///
+/// Values match if both are NaN, or if they differ by at most `tolerance` relative to
+/// the expected value (absolute for expected values below 1). Infinities only match
+/// infinities of the same sign.
+pub fn run<'a>(cases: &[Case<'a>], ctx: Option<Rc<EvalContext>>, tolerance: Real) -> Report<'a> {
+    let ctx = ctx.unwrap_or_else(|| Rc::new(EvalContext::new()));
+    let mut report = Report {
+        total: cases.len(),
+        divergences: Vec::new(),
+        operators: BTreeMap::new(),
+    };
+    for case in cases {
+        let arena = bumpalo::Bump::new();
+        if let Ok(ast) = crate::engine::parse_expression(case.expression, &arena) {
+            let mut collector = Operators::default();
+            walk_ast(&mut collector, &ast);
+            report.operators.insert(case.line, collector.0);
+        }
+
+        let actual = crate::engine::interp(case.expression, Some(ctx.clone()));
+        let matches = match (&actual, case.expected) {
+            (Ok(value), Expected::Value(expected)) => values_match(*value, expected, tolerance),
+            (Err(_), Expected::Error) => true,
+            _ => false,
+        };
+        if !matches {
+            report.divergences.push(Divergence {
+                case: *case,
+                actual,
+            });
+        }
+    }
+    report
+}
+
+fn values_match(actual: Real, expected: Real, tolerance: Real) -> bool {
+    if actual.is_nan() || expected.is_nan() {
+        return actual.is_nan() && expected.is_nan();
+    }
+    if actual.is_infinite() || expected.is_infinite() {
+        return actual == expected;
+    }
+    (actual - expected).abs() <= tolerance * expected.abs().max(1.0)
+}
+
+/// Collects the distinct operators and functions of an expression.
+#[derive(Default)]
+struct Operators(Vec<String>);
+
+impl Operators {
+    fn add(&mut self, name: &str) {
+        if !self.0.iter().any(|n| n == name) {
+            self.0.push(name.to_string());
+        }
+    }
+}
+
+impl<'arena> AstVisitor<'arena> for Operators {
+    fn visit_function(&mut self, name: &'arena str, args: &'arena [AstExpr<'arena>]) {
+        self.add(name);
+        for arg in args {
+            self.visit_expr(arg);
+        }
+    }
+
+    fn visit_logical_op(
+        &mut self,
+        op: LogicalOperator,
+        left: &'arena AstExpr<'arena>,
+        right: &'arena AstExpr<'arena>,
+    ) {
+        self.add(match op {
+            LogicalOperator::And => "&&",
+            LogicalOperator::Or => "||",
+        });
+        self.visit_expr(left);
+        self.visit_expr(right);
+    }
+
+    fn visit_conditional(
+        &mut self,
+        condition: &'arena AstExpr<'arena>,
+        true_branch: &'arena AstExpr<'arena>,
+        false_branch: &'arena AstExpr<'arena>,
+    ) {
+        self.add("?:");
+        self.visit_expr(condition);
+        self.visit_expr(true_branch);
+        self.visit_expr(false_branch);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_differential_report() {
+        let fixture =
+            "1 / 0\tinf\n\n0 / 0\tNaN\nx > 1 && 2 % 3 == 2\t1\nmax(1, 2\t2\n1 ? 2 : 3\t3\n";
+        let cases = parse_fixture(fixture).unwrap();
+        assert_eq!(cases.len(), 5);
+        assert_eq!(cases[3].line, 5);
+
+        let mut ctx = EvalContext::new();
+        ctx.set_parameter("x", 2.0).unwrap();
+        let report = run(&cases, Some(Rc::new(ctx)), 1e-6);
+        let lines: Vec<usize> = report.divergences.iter().map(|d| d.case.line).collect();
+        assert_eq!(lines, [5, 6]);
+        assert!(!report.is_clean());
+        assert!(report.to_string().starts_with("2 of 5 cases diverge"));
+
+        let parity = report.operators();
+        let find = |name: &str| parity.iter().find(|p| p.operator == name).unwrap();
+        assert_eq!((find("&&").cases, find("&&").divergences), (1, 0));
+        assert_eq!((find("?:").cases, find("?:").divergences), (1, 1));
+        assert_eq!(find("/").cases, 2);
+
+        assert!(parse_fixture("1 + 1").is_err());
+        assert!(parse_fixture("1 + 1\tseven").is_err());
+    }
+}
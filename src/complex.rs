@@ -0,0 +1,463 @@
+//! Complex-number evaluation (requires the `complex` feature).
+//!
+//! [`eval_complex`] evaluates a parsed expression over complex numbers. Real values from
+//! the context are promoted to complex values with a zero imaginary part, and the names
+//! `i` and `j` refer to the imaginary unit unless the context defines them. This makes it
+//! possible to evaluate impedance formulas such as `R + 1/(j*w*C)` directly.
+//!
+//! To write imaginary literals like `2i`, parse with
+//! [`ParseOptions::unit_literals`](crate::engine::ParseOptions::unit_literals) (or
+//! `implicit_multiplication`), which turns `2i` into `2*i`.
+
+use crate::Real;
+use crate::context::EvalContext;
+use crate::error::ExprError;
+use crate::functions;
+use crate::types::{AstExpr, TryIntoHeaplessString};
+use alloc::format;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::ops::{Add, Div, Mul, Neg, Sub};
+
+/// A complex number with `Real` components.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Complex {
+    /// Real part
+    pub re: Real,
+    /// Imaginary part
+    pub im: Real,
+}
+
+impl Complex {
+    /// The imaginary unit.
+    pub const I: Complex = Complex::new(0.0, 1.0);
+
+    /// Creates a complex number from its real and imaginary parts.
+    pub const fn new(re: Real, im: Real) -> Self {
+        Self { re, im }
+    }
+
+    /// Returns true if the imaginary part is zero.
+    pub fn is_real(&self) -> bool {
+        self.im == 0.0
+    }
+
+    /// Magnitude `|z|`.
+    pub fn abs(self) -> Real {
+        functions::sqrt(self.re * self.re + self.im * self.im, 0.0)
+    }
+
+    /// Phase angle in radians, in `(-pi, pi]`.
+    pub fn arg(self) -> Real {
+        // Treat -0.0 (e.g. from negating a real) as 0.0 so that arg(-1) is pi, not -pi
+        let im = if self.im == 0.0 { 0.0 } else { self.im };
+        functions::atan2(im, self.re)
+    }
+
+    /// Complex conjugate.
+    pub fn conj(self) -> Self {
+        Self::new(self.re, -self.im)
+    }
+
+    /// Complex exponential `e^z`.
+    pub fn exp(self) -> Self {
+        let r = functions::exp(self.re, 0.0);
+        Self::new(
+            r * functions::cos(self.im, 0.0),
+            r * functions::sin(self.im, 0.0),
+        )
+    }
+
+    /// Principal natural logarithm.
+    pub fn ln(self) -> Self {
+        Self::new(functions::ln(self.abs(), 0.0), self.arg())
+    }
+
+    /// Principal square root.
+    pub fn sqrt(self) -> Self {
+        if self.is_real() && self.re >= 0.0 {
+            return Self::new(functions::sqrt(self.re, 0.0), 0.0);
+        }
+        let r = functions::sqrt(self.abs(), 0.0);
+        let half = self.arg() / 2.0;
+        Self::new(r * functions::cos(half, 0.0), r * functions::sin(half, 0.0))
+    }
+
+    /// Raises `self` to a complex power using the principal branch.
+    pub fn pow(self, exponent: Complex) -> Self {
+        if exponent.is_real() && self.is_real() && (self.re >= 0.0 || exponent.re % 1.0 == 0.0) {
+            return Self::new(functions::pow(self.re, exponent.re), 0.0);
+        }
+        if self == Complex::default() {
+            return if exponent == Complex::default() {
+                Self::new(1.0, 0.0)
+            } else {
+                Complex::default()
+            };
+        }
+        (self.ln() * exponent).exp()
+    }
+
+    /// Complex sine.
+    pub fn sin(self) -> Self {
+        Self::new(
+            functions::sin(self.re, 0.0) * functions::cosh(self.im, 0.0),
+            functions::cos(self.re, 0.0) * functions::sinh(self.im, 0.0),
+        )
+    }
+
+    /// Complex cosine.
+    pub fn cos(self) -> Self {
+        Self::new(
+            functions::cos(self.re, 0.0) * functions::cosh(self.im, 0.0),
+            -functions::sin(self.re, 0.0) * functions::sinh(self.im, 0.0),
+        )
+    }
+}
+
+impl From<Real> for Complex {
+    fn from(re: Real) -> Self {
+        Self::new(re, 0.0)
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, rhs: Complex) -> Complex {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn div(self, rhs: Complex) -> Complex {
+        if rhs.is_real() {
+            return Complex::new(self.re / rhs.re, self.im / rhs.re);
+        }
+        let denom = rhs.re * rhs.re + rhs.im * rhs.im;
+        Complex::new(
+            (self.re * rhs.re + self.im * rhs.im) / denom,
+            (self.im * rhs.re - self.re * rhs.im) / denom,
+        )
+    }
+}
+
+impl Neg for Complex {
+    type Output = Complex;
+
+    fn neg(self) -> Complex {
+        Complex::new(-self.re, -self.im)
+    }
+}
+
+/// Evaluates an expression over complex numbers.
+///
+/// Arithmetic operators, `^`/`**`/`pow`, `==`/`!=`, `&&`/`||` and `?:` accept complex
+/// operands (a value is true if it is non-zero). The builtins `abs`, `arg`, `re`, `im`,
+/// `conj`, `sqrt`, `exp`, `ln`, `sin` and `cos` are complex-aware. Ordering comparisons
+/// and all other functions, including native functions registered in the context,
+/// require real arguments and fail with `ExprError::TypeError` otherwise.
+///
+/// Expression functions are not supported by the complex evaluator.
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::complex::{Complex, eval_complex};
+/// use exp_rs::context::EvalContext;
+/// use exp_rs::engine::parse_expression;
+/// use bumpalo::Bump;
+///
+/// let mut ctx = EvalContext::new();
+/// ctx.set_parameter("w", 1000.0).unwrap();
+/// ctx.set_parameter("C", 1e-6).unwrap();
+///
+/// let arena = Bump::new();
+/// let ast = parse_expression("1/(j*w*C)", &arena).unwrap();
+/// let z = eval_complex(&ast, Some(&ctx)).unwrap();
+/// assert!((z.im + 1000.0).abs() < 1e-9);
+/// assert_eq!(z.re, 0.0);
+/// ```
+pub fn eval_complex(expr: &AstExpr, ctx: Option<&EvalContext>) -> Result<Complex, ExprError> {
+    match expr {
+        AstExpr::Constant(value) => Ok(Complex::from(*value)),
+        AstExpr::Variable(name) => lookup_variable(name, ctx),
+        AstExpr::Array { name, index } => {
+            let index = require_real("array index", eval_complex(index, ctx)?)?;
+            let array =
+                ctx.and_then(|c| c.get_array(name))
+                    .ok_or_else(|| ExprError::UnknownVariable {
+                        name: name.to_string(),
+                    })?;
+            let idx = index as usize;
+            array.get(idx).map(|v| Complex::from(*v)).ok_or_else(|| {
+                ExprError::ArrayIndexOutOfBounds {
+                    name: name.to_string(),
+                    index: idx,
+                    len: array.len(),
+                }
+            })
+        }
+        AstExpr::Attribute { base, attr } => ctx
+            .and_then(|c| c.get_attribute_map(base))
+            .and_then(|m| m.get(&attr.try_into_heapless().ok()?).copied())
+            .map(Complex::from)
+            .ok_or_else(|| ExprError::AttributeNotFound {
+                base: base.to_string(),
+                attr: attr.to_string(),
+            }),
+        AstExpr::LogicalOp { op, left, right } => {
+            let left = is_true(eval_complex(left, ctx)?);
+            let result = match op {
+                crate::types::LogicalOperator::And => left && is_true(eval_complex(right, ctx)?),
+                crate::types::LogicalOperator::Or => left || is_true(eval_complex(right, ctx)?),
+            };
+            Ok(Complex::from(if result { 1.0 } else { 0.0 }))
+        }
+        AstExpr::Conditional {
+            condition,
+            true_branch,
+            false_branch,
+        } => {
+            if is_true(eval_complex(condition, ctx)?) {
+                eval_complex(true_branch, ctx)
+            } else {
+                eval_complex(false_branch, ctx)
+            }
+        }
+        AstExpr::Function { name, args } => {
+            let mut values = Vec::with_capacity(args.len());
+            for arg in args.iter() {
+                values.push(eval_complex(arg, ctx)?);
+            }
+            call_function(name, &values, ctx)
+        }
+    }
+}
+
+fn lookup_variable(name: &str, ctx: Option<&EvalContext>) -> Result<Complex, ExprError> {
+    if let Some(value) = ctx.and_then(|c| c.get_variable(name).or_else(|| c.get_constant(name))) {
+        return Ok(Complex::from(value));
+    }
+    let value = match name {
+        "i" | "j" => return Ok(Complex::I),
+        "pi" | "PI" => core::f64::consts::PI as Real,
+        "e" | "E" => core::f64::consts::E as Real,
+        "tau" | "TAU" => 2.0 * core::f64::consts::PI as Real,
+        _ => ctx.and_then(|c| c.resolve_variable(name)).ok_or_else(|| {
+            ExprError::UnknownVariable {
+                name: name.to_string(),
+            }
+        })?,
+    };
+    Ok(Complex::from(value))
+}
+
+fn call_function(
+    name: &str,
+    args: &[Complex],
+    ctx: Option<&EvalContext>,
+) -> Result<Complex, ExprError> {
+    if let Some(ctx) = ctx {
+        ctx.check_function_permitted(name)?;
+    }
+    let bool_value = |b: bool| Complex::from(if b { 1.0 } else { 0.0 });
+    let result = match (name, args) {
+        ("+", [a, b]) => *a + *b,
+        ("-", [a, b]) => *a - *b,
+        ("*", [a, b]) => *a * *b,
+        ("/", [a, b]) => *a / *b,
+        ("^" | "**" | "pow", [a, b]) => a.pow(*b),
+        ("neg", [a]) => -*a,
+        ("==", [a, b]) => bool_value(a == b),
+        ("!=" | "<>", [a, b]) => bool_value(a != b),
+        ("," | ";" | "comma", [.., last]) => *last,
+        ("abs", [a]) => Complex::from(a.abs()),
+        ("arg", [a]) => Complex::from(a.arg()),
+        ("re", [a]) => Complex::from(a.re),
+        ("im", [a]) => Complex::from(a.im),
+        ("conj", [a]) => a.conj(),
+        ("sqrt", [a]) => a.sqrt(),
+        ("exp", [a]) => a.exp(),
+        ("ln", [a]) => a.ln(),
+        ("sin", [a]) => a.sin(),
+        ("cos", [a]) => a.cos(),
+        _ => return call_real_function(name, args, ctx),
+    };
+    Ok(result)
+}
+
+/// Calls a real-valued operator or context function, rejecting complex arguments.
+fn call_real_function(
+    name: &str,
+    args: &[Complex],
+    ctx: Option<&EvalContext>,
+) -> Result<Complex, ExprError> {
+    let mut reals = Vec::with_capacity(args.len());
+    for arg in args {
+        reals.push(require_real(&format!("argument of '{}'", name), *arg)?);
+    }
+
+    let compare = |result: bool| Ok(Complex::from(if result { 1.0 } else { 0.0 }));
+    match (name, reals.as_slice()) {
+        ("<", [a, b]) => return compare(a < b),
+        (">", [a, b]) => return compare(a > b),
+        ("<=", [a, b]) => return compare(a <= b),
+        (">=", [a, b]) => return compare(a >= b),
+        ("%", [a, b]) => return Ok(Complex::from(a % b)),
+        _ => {}
+    }
+
+    ctx.ok_or_else(|| ExprError::UnknownFunction {
+        name: name.to_string(),
+    })?
+    .call_function(name, &reals)
+    .map(Complex::from)
+}
+
+fn require_real(what: &str, value: Complex) -> Result<Real, ExprError> {
+    if value.is_real() {
+        Ok(value.re)
+    } else {
+        Err(ExprError::TypeError {
+            operand: what.to_string(),
+            expected: "real number",
+            found: "complex number",
+        })
+    }
+}
+
+fn is_true(value: Complex) -> bool {
+    value != Complex::default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::FunctionPolicy;
+    use crate::engine::{ParseOptions, parse_expression, parse_expression_with_options};
+    use alloc::rc::Rc;
+    use bumpalo::Bump;
+    use core::cell::Cell;
+
+    fn eval(input: &str, ctx: Option<&EvalContext>) -> Result<Complex, ExprError> {
+        let arena = Bump::new();
+        let options = ParseOptions {
+            unit_literals: true,
+            ..Default::default()
+        };
+        let ast = parse_expression_with_options(input, &arena, &options)?;
+        eval_complex(&ast, ctx)
+    }
+
+    fn close(a: Complex, re: Real, im: Real) -> bool {
+        (a.re - re).abs() < 1e-9 && (a.im - im).abs() < 1e-9
+    }
+
+    #[test]
+    fn test_arithmetic_and_literals() {
+        assert!(close(eval("(1 + 2i) * (3 - i)", None).unwrap(), 5.0, 5.0));
+        assert!(close(eval("1 / i", None).unwrap(), 0.0, -1.0));
+        assert!(close(eval("i^2", None).unwrap(), -1.0, 0.0));
+        assert!(close(eval("sqrt(-4)", None).unwrap(), 0.0, 2.0));
+        assert!(close(eval("exp(i * pi)", None).unwrap(), -1.0, 0.0));
+        assert!(close(eval("2 ^ 10", None).unwrap(), 1024.0, 0.0));
+    }
+
+    #[test]
+    fn test_complex_builtins() {
+        assert!(close(eval("abs(3 + 4i)", None).unwrap(), 5.0, 0.0));
+        assert!(close(
+            eval("arg(2j)", None).unwrap(),
+            core::f64::consts::FRAC_PI_2 as Real,
+            0.0
+        ));
+        assert!(close(
+            eval("re(3 + 4i) + im(3 + 4i)", None).unwrap(),
+            7.0,
+            0.0
+        ));
+        assert!(close(eval("conj(3 + 4i)", None).unwrap(), 3.0, -4.0));
+        assert!(close(eval("2i == 2j ? 1 : 0", None).unwrap(), 1.0, 0.0));
+    }
+
+    #[test]
+    fn test_context_values_and_errors() {
+        let mut ctx = EvalContext::new();
+        ctx.set_parameter("R", 50.0).unwrap();
+        ctx.set_parameter("i", 0.5).unwrap();
+        ctx.register_native_function("double", 1, |args| args[0] * 2.0)
+            .unwrap();
+
+        // A context variable named i shadows the imaginary unit; j is still available
+        assert!(close(eval("R + i * 2j", Some(&ctx)).unwrap(), 50.0, 1.0));
+        assert!(close(eval("double(R)", Some(&ctx)).unwrap(), 100.0, 0.0));
+
+        assert!(matches!(
+            eval("double(2j)", Some(&ctx)),
+            Err(ExprError::TypeError { .. })
+        ));
+        assert!(matches!(
+            eval("1j < 2", None),
+            Err(ExprError::TypeError { .. })
+        ));
+        assert!(matches!(
+            eval("nope(1)", Some(&ctx)),
+            Err(ExprError::UnknownFunction { .. })
+        ));
+
+        let arena = Bump::new();
+        let ast = parse_expression("1 + x", &arena).unwrap();
+        assert!(matches!(
+            eval_complex(&ast, Some(&ctx)),
+            Err(ExprError::UnknownVariable { .. })
+        ));
+    }
+
+    #[test]
+    fn test_function_policy() {
+        let called = Rc::new(Cell::new(false));
+        let mut ctx = EvalContext::new();
+        let flag = called.clone();
+        ctx.register_native_function("write_reg", 1, move |_| {
+            flag.set(true);
+            0.0
+        })
+        .unwrap();
+        ctx.set_function_policy(Some(FunctionPolicy::deny(["write_reg", "conj"])));
+
+        for input in ["write_reg(1)", "1 + conj(2i)"] {
+            assert!(
+                matches!(
+                    eval(input, Some(&ctx)),
+                    Err(ExprError::FunctionNotPermitted { .. })
+                ),
+                "{input}"
+            );
+        }
+        assert!(!called.get());
+        assert!(close(eval("sqrt(-4) + 1", Some(&ctx)).unwrap(), 1.0, 2.0));
+    }
+}
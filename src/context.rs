@@ -7,7 +7,7 @@ use crate::{Real, String, ToString, Vec};
 #[cfg(not(test))]
 use alloc::rc::Rc;
 // Import heapless types and helper traits
-use crate::types::{TryIntoFunctionName, TryIntoHeaplessString};
+use crate::types::{AstExpr, TryIntoFunctionName, TryIntoHeaplessString};
 #[cfg(test)]
 use std::rc::Rc;
 #[cfg(test)]
@@ -79,6 +79,8 @@ pub struct FunctionRegistry {
 ///
 /// // The child context can access both its own variables and the parent's
 /// ```
+///
+/// [`EvalContext::push_scope`] creates such a child for temporary values.
 pub struct EvalContext {
     /// Variables that can be modified during evaluation
     pub variables: crate::types::VariableMap,
@@ -94,6 +96,396 @@ pub struct EvalContext {
     pub native_functions: Rc<crate::types::NativeFunctionMap>,
     /// Optional parent context for variable/function inheritance
     pub parent: Option<Rc<EvalContext>>,
+    /// Optional callback consulted for variables not found in the context
+    pub variable_resolver: Option<VariableResolver>,
+    /// Optional callback consulted for functions not registered in the context
+    pub function_resolver: Option<FunctionResolver>,
+    /// Optional registry of units used by unit literals such as `10ms`
+    pub units: Option<Rc<crate::units::UnitRegistry>>,
+    /// Objects whose attributes are read on demand through [`AttributeProvider`]
+    pub objects: Vec<(crate::types::HString, Rc<dyn AttributeProvider>)>,
+    /// Arrays backed by caller-owned memory, accessed with `name[index]` like `arrays`
+    pub array_views: Vec<(crate::types::HString, ArrayView)>,
+    /// Ring buffers of recent samples read by `history(name, n)` and the `window_*`
+    /// builtins
+    pub histories: Vec<(crate::types::HString, Rc<crate::history::History>)>,
+    /// Matrices and vectors read by the `linalg` evaluator
+    #[cfg(feature = "linalg")]
+    pub matrices: Vec<(crate::types::HString, crate::linalg::Matrix)>,
+    /// Rounding and comparison settings used by `round`, `==` and `!=`
+    math_config: MathConfig,
+    /// Evaluation depth limit for expressions evaluated with this context
+    max_eval_depth: Option<usize>,
+    /// Total depth up to which evaluations spill pending steps to the heap
+    stack_spill: Option<usize>,
+    /// Whether missing values propagate through calls and conditions, if set
+    missing_propagation: Option<bool>,
+    /// Generator behind `rand`, `rand_range` and `randn`
+    rng: Rc<crate::random::Rng>,
+    /// Clock behind `now`, `dt` and `elapsed`, if one was set
+    clock: Option<Rc<crate::clock::Clock>>,
+    /// Restriction on the functions expressions may call, if one was set
+    function_policy: Option<Rc<FunctionPolicy>>,
+    /// Receiver of parser and evaluator diagnostics, if one was set
+    logger: Option<Rc<dyn crate::log::Logger>>,
+    /// Callback run every so many evaluated nodes, if one was set
+    watchdog: Option<Watchdog>,
+    /// Functions marked with [`deprecate_function`](EvalContext::deprecate_function)
+    deprecations: Vec<Deprecation>,
+    /// Names declared with [`declare_variables`](EvalContext::declare_variables)
+    declarations: Vec<crate::types::HString>,
+    /// Whether references must be declared, if set
+    strict_declarations: Option<bool>,
+    /// Integer and boolean values set with [`set_value`](EvalContext::set_value), kept
+    /// exactly; names here are not in `variables`
+    typed_values: crate::types::TypedValueMap,
+}
+
+/// Finds the first referenced name that is neither declared nor an input.
+struct Undeclared<'a> {
+    ctx: &'a EvalContext,
+    is_input: &'a dyn Fn(&str) -> bool,
+    found: Option<String>,
+}
+
+impl Undeclared<'_> {
+    fn check(&mut self, name: &str) {
+        let is_constant = matches!(name, "pi" | "PI" | "e" | "E" | "tau" | "TAU");
+        if self.found.is_none()
+            && !is_constant
+            && !(self.is_input)(name)
+            && !self.ctx.is_declared(name)
+        {
+            self.found = Some(name.to_string());
+        }
+    }
+}
+
+impl<'arena> crate::visit::AstVisitor<'arena> for Undeclared<'_> {
+    fn visit_variable(&mut self, name: &'arena str) {
+        self.check(name);
+    }
+
+    fn visit_function(&mut self, name: &'arena str, args: &'arena [AstExpr<'arena>]) {
+        // The timer name of `elapsed` is not a variable
+        let args = if name == "elapsed" && args.len() == 1 {
+            &[]
+        } else {
+            args
+        };
+        for arg in args {
+            self.visit_expr(arg);
+        }
+    }
+
+    fn visit_array(&mut self, name: &'arena str, index: &'arena AstExpr<'arena>) {
+        self.check(name);
+        self.visit_expr(index);
+    }
+
+    fn visit_attribute(&mut self, base: &'arena str, attr: &'arena str) {
+        match crate::types::dotted_path::<{ crate::types::EXP_RS_MAX_KEY_LENGTH }>(base, attr) {
+            Some(path) => self.check(&path),
+            // Too long to be declared
+            None if self.found.is_none() => self.found = Some(alloc::format!("{}.{}", base, attr)),
+            None => {}
+        }
+    }
+}
+
+/// A function name marked deprecated with [`EvalContext::deprecate_function`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deprecation {
+    /// The deprecated name
+    pub name: crate::types::FunctionName,
+    /// The function to call instead, if there is one
+    pub replacement: Option<crate::types::FunctionName>,
+}
+
+/// How `round` resolves values exactly halfway between two integers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RoundingMode {
+    /// Round half up, i.e. ties away from zero: `2.5` → `3`, `-2.5` → `-3`.
+    #[default]
+    HalfUp,
+    /// Banker's rounding, ties to the nearest even integer: `2.5` → `2`, `3.5` → `4`.
+    HalfEven,
+}
+
+impl RoundingMode {
+    /// Rounds `x` to the nearest integer using this mode.
+    pub fn round(self, x: Real) -> Real {
+        let floor = crate::functions::floor(x, 0.0);
+        let diff = x - floor;
+        if diff < 0.5 {
+            floor
+        } else if diff > 0.5 {
+            floor + 1.0
+        } else {
+            match self {
+                RoundingMode::HalfUp if x >= 0.0 => floor + 1.0,
+                RoundingMode::HalfUp => floor,
+                RoundingMode::HalfEven if floor % 2.0 == 0.0 => floor,
+                RoundingMode::HalfEven => floor + 1.0,
+            }
+        }
+    }
+
+    /// Rounds `x` to the nearest multiple of `step` using this mode.
+    ///
+    /// Returns NaN if `step` is not a positive finite number.
+    pub fn round_to(self, x: Real, step: Real) -> Real {
+        if !(step > 0.0 && step.is_finite()) {
+            return Real::NAN;
+        }
+        self.round(x / step) * step
+    }
+}
+
+/// Per-context settings for rounding and float comparison.
+///
+/// Applied with [`EvalContext::set_math_config`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MathConfig {
+    /// Tie-breaking rule used by `round`
+    pub rounding: RoundingMode,
+    /// Largest absolute difference at which `==` considers two values equal
+    /// (and `!=` considers them not different). `0.0` means exact comparison.
+    pub equality_epsilon: Real,
+}
+
+/// Callback that resolves variable names not stored in an [`EvalContext`].
+///
+/// Returning `None` means the name is unknown and evaluation fails with
+/// `ExprError::UnknownVariable`.
+pub type VariableResolver = Rc<dyn Fn(&str) -> Option<Real>>;
+
+/// Callback that dispatches calls to functions not registered in an [`EvalContext`].
+///
+/// It receives the function name and the already-evaluated arguments. Returning `None`
+/// means the function is unknown and evaluation fails with `ExprError::UnknownFunction`.
+pub type FunctionResolver = Rc<dyn Fn(&str, &[Real]) -> Option<Real>>;
+
+/// Callback run periodically during evaluation, set with
+/// [`EvalContext::set_watchdog`].
+///
+/// Returning `ControlFlow::Break(())` aborts the evaluation with `ExprError::Aborted`.
+pub type WatchdogCallback = Rc<dyn Fn() -> core::ops::ControlFlow<()>>;
+
+/// A callback and the number of evaluated nodes between its calls.
+#[derive(Clone)]
+pub struct Watchdog {
+    /// Number of AST nodes evaluated between calls, at least 1
+    pub interval: usize,
+    /// The callback
+    pub callback: WatchdogCallback,
+}
+
+/// Restricts which functions expressions may call, set with
+/// [`EvalContext::set_function_policy`].
+///
+/// Operators (`+`, `<`, `&&`, unary minus and so on) are always permitted; the policy
+/// applies to named functions, including expression functions of a batch and the
+/// builtins of the other evaluators such as `eval_complex`. Calling a function the
+/// policy does not permit fails with [`ExprError::FunctionNotPermitted`].
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::{EvalContext, FunctionPolicy};
+/// use exp_rs::engine::interp;
+/// use exp_rs::error::ExprError;
+/// use std::rc::Rc;
+///
+/// let mut ctx = EvalContext::new();
+/// ctx.register_native_function("write_reg", 2, |_| 0.0).unwrap();
+/// ctx.set_function_policy(Some(FunctionPolicy::deny(["write_reg"])));
+/// let ctx = Rc::new(ctx);
+///
+/// assert_eq!(interp("sqrt(16) + 1", Some(ctx.clone())).unwrap(), 5.0);
+/// assert!(matches!(
+///     interp("write_reg(4, 1)", Some(ctx)),
+///     Err(ExprError::FunctionNotPermitted { .. })
+/// ));
+/// ```
+///
+/// [`ExprError::FunctionNotPermitted`]: crate::error::ExprError::FunctionNotPermitted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FunctionPolicy {
+    /// Only the listed functions may be called.
+    Allow(alloc::collections::BTreeSet<String>),
+    /// Every function except the listed ones may be called.
+    Deny(alloc::collections::BTreeSet<String>),
+}
+
+impl FunctionPolicy {
+    /// Creates an allow-list policy.
+    pub fn allow<S: Into<String>>(names: impl IntoIterator<Item = S>) -> Self {
+        Self::Allow(names.into_iter().map(Into::into).collect())
+    }
+
+    /// Creates a deny-list policy.
+    pub fn deny<S: Into<String>>(names: impl IntoIterator<Item = S>) -> Self {
+        Self::Deny(names.into_iter().map(Into::into).collect())
+    }
+
+    /// Returns whether expressions may call the function `name`.
+    pub fn permits(&self, name: &str) -> bool {
+        // The parser turns operators into calls of functions with symbolic names,
+        // and unary minus into `neg`
+        let is_operator =
+            name == "neg" || !name.starts_with(|c: char| c.is_alphabetic() || c == '_');
+        match self {
+            _ if is_operator => true,
+            Self::Allow(names) => names.contains(name),
+            Self::Deny(names) => !names.contains(name),
+        }
+    }
+}
+
+/// A read-only view of caller-owned memory that expressions index like an array.
+///
+/// Unlike [`EvalContext::arrays`], the values are not copied into the context: each
+/// `name[index]` reads the memory at evaluation time, so a buffer filled by DMA or by
+/// another part of the program is seen as it is when the expression runs.
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::context::{ArrayView, EvalContext};
+/// use exp_rs::engine::interp;
+/// use exp_rs::Real;
+/// use std::rc::Rc;
+///
+/// static SAMPLES: [Real; 4] = [0.5, 1.5, 2.5, 3.5];
+///
+/// let mut ctx = EvalContext::new();
+/// ctx.set_array_view("samples", ArrayView::from_static(&SAMPLES)).unwrap();
+/// assert_eq!(interp("samples[1] + samples[3]", Some(Rc::new(ctx))).unwrap(), 5.0);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct ArrayView {
+    ptr: *const Real,
+    len: usize,
+}
+
+impl ArrayView {
+    /// Creates a view of a slice that lives for the whole program.
+    pub fn from_static(values: &'static [Real]) -> Self {
+        Self {
+            ptr: values.as_ptr(),
+            len: values.len(),
+        }
+    }
+
+    /// Creates a view of `len` values starting at `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// Unless `len` is zero, `ptr` must point to `len` initialized, properly aligned
+    /// values that stay valid for as long as any context holding the view (including
+    /// clones and children of it) is used for evaluation. The memory may be modified
+    /// between evaluations, but not while an evaluation is running.
+    pub unsafe fn from_raw_parts(ptr: *const Real, len: usize) -> Self {
+        Self { ptr, len }
+    }
+
+    /// Returns the number of values in the view.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the view has no values.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Reads the value at `index`, or `None` if it is out of bounds.
+    pub fn get(&self, index: usize) -> Option<Real> {
+        // SAFETY: the constructors guarantee `len` readable values at `ptr`
+        (index < self.len).then(|| unsafe { *self.ptr.add(index) })
+    }
+}
+
+/// An object whose attributes can be read from expressions as `name.attr`.
+///
+/// Registering a provider with [`EvalContext::register_object`] makes its attributes
+/// available without copying them into [`EvalContext::attributes`]; they are read each
+/// time an expression accesses them. To update the object between evaluations, register
+/// it wrapped in a `RefCell`, which implements this trait for any provider.
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::context::{AttributeProvider, EvalContext};
+/// use exp_rs::engine::interp;
+/// use exp_rs::Real;
+/// use std::cell::RefCell;
+/// use std::rc::Rc;
+///
+/// struct Imu {
+///     accel_x: Real,
+///     accel_y: Real,
+/// }
+///
+/// impl AttributeProvider for Imu {
+///     fn attribute(&self, name: &str) -> Option<Real> {
+///         match name {
+///             "accel_x" => Some(self.accel_x),
+///             "accel_y" => Some(self.accel_y),
+///             _ => None,
+///         }
+///     }
+/// }
+///
+/// let imu = Rc::new(RefCell::new(Imu { accel_x: 3.0, accel_y: 4.0 }));
+/// let mut ctx = EvalContext::new();
+/// ctx.register_object("imu", imu.clone()).unwrap();
+/// let ctx = Rc::new(ctx);
+///
+/// let magnitude = "sqrt(imu.accel_x^2 + imu.accel_y^2)";
+/// assert_eq!(interp(magnitude, Some(ctx.clone())).unwrap(), 5.0);
+///
+/// imu.borrow_mut().accel_x = 0.0;
+/// assert_eq!(interp(magnitude, Some(ctx)).unwrap(), 4.0);
+/// ```
+pub trait AttributeProvider {
+    /// Returns the value of attribute `name`, or `None` if the object has no such
+    /// attribute, which makes the access fail with `ExprError::AttributeNotFound`.
+    ///
+    /// For `imu.gyro.x` on an object registered as `imu`, `name` is `gyro.x`, so a
+    /// provider can serve nested values itself.
+    fn attribute(&self, name: &str) -> Option<Real>;
+
+    /// Returns the length of the array attribute `name`, read as `object.name[i]`,
+    /// or `None` if the object has no such array.
+    fn array_len(&self, name: &str) -> Option<usize> {
+        let _ = name;
+        None
+    }
+
+    /// Returns element `index` of the array attribute `name`. Only called for indices
+    /// below [`array_len`](Self::array_len).
+    fn element(&self, name: &str, index: usize) -> Option<Real> {
+        let _ = (name, index);
+        None
+    }
+}
+
+impl<T: AttributeProvider + ?Sized> AttributeProvider for core::cell::RefCell<T> {
+    fn attribute(&self, name: &str) -> Option<Real> {
+        self.borrow().attribute(name)
+    }
+
+    fn array_len(&self, name: &str) -> Option<usize> {
+        self.borrow().array_len(name)
+    }
+
+    fn element(&self, name: &str, index: usize) -> Option<Real> {
+        self.borrow().element(name, index)
+    }
 }
 
 impl EvalContext {
@@ -119,6 +511,27 @@ impl EvalContext {
             nested_arrays: crate::types::NestedArrayMap::new(),
             native_functions: Rc::new(crate::types::NativeFunctionMap::new()),
             parent: None,
+            variable_resolver: None,
+            function_resolver: None,
+            units: None,
+            objects: Vec::new(),
+            array_views: Vec::new(),
+            histories: Vec::new(),
+            #[cfg(feature = "linalg")]
+            matrices: Vec::new(),
+            math_config: MathConfig::default(),
+            max_eval_depth: None,
+            stack_spill: None,
+            missing_propagation: None,
+            rng: Rc::new(crate::random::Rng::default()),
+            clock: None,
+            function_policy: None,
+            logger: None,
+            watchdog: None,
+            deprecations: Vec::new(),
+            declarations: Vec::new(),
+            strict_declarations: None,
+            typed_values: crate::types::TypedValueMap::new(),
         };
 
         // Always register default math functions
@@ -166,6 +579,27 @@ impl EvalContext {
             nested_arrays: crate::types::NestedArrayMap::new(),
             native_functions: Rc::new(crate::types::NativeFunctionMap::new()),
             parent: None,
+            variable_resolver: None,
+            function_resolver: None,
+            units: None,
+            objects: Vec::new(),
+            array_views: Vec::new(),
+            histories: Vec::new(),
+            #[cfg(feature = "linalg")]
+            matrices: Vec::new(),
+            math_config: MathConfig::default(),
+            max_eval_depth: None,
+            stack_spill: None,
+            missing_propagation: None,
+            rng: Rc::new(crate::random::Rng::default()),
+            clock: None,
+            function_policy: None,
+            logger: None,
+            watchdog: None,
+            deprecations: Vec::new(),
+            declarations: Vec::new(),
+            strict_declarations: None,
+            typed_values: crate::types::TypedValueMap::new(),
         }
     }
 
@@ -203,22 +637,26 @@ impl EvalContext {
         value: Real,
     ) -> Result<Option<Real>, crate::error::ExprError> {
         let key = name.try_into_heapless()?;
+        if !self.typed_values.is_empty() {
+            self.typed_values.remove(&key);
+        }
         match self.variables.insert(key, value) {
             Ok(old_value) => Ok(old_value),
-            Err(_) => Err(crate::error::ExprError::CapacityExceeded("variables")),
+            Err(_) => Err(crate::error::ExprError::CapacityExceeded {
+                container: "variables",
+            }),
         }
     }
 
-    /// Registers a native function in the context.
-    ///
-    /// Native functions are implemented in Rust and can be called from expressions.
-    /// They take a slice of Real values as arguments and return a Real value.
+    /// Sets a callback used to resolve variables that are not stored in the context.
     ///
-    /// # Parameters
+    /// The resolver is consulted only after the context's variables and constants, its
+    /// parent chain and the built-in constants (`pi`, `e`, `tau`) have been checked, so
+    /// values stored in the context always take precedence. This allows values to be
+    /// read on demand (e.g. from a register map) instead of being copied into the context
+    /// before each evaluation.
     ///
-    /// * `name`: The name of the function as it will be used in expressions
-    /// * `arity`: The number of arguments the function expects
-    /// * `implementation`: A closure or function that implements the function logic
+    /// If this context has no resolver, the parent's resolver (if any) is used.
     ///
     /// # Examples
     ///
@@ -229,17 +667,44 @@ impl EvalContext {
     /// use std::rc::Rc;
     ///
     /// let mut ctx = EvalContext::new();
-    ///
-    /// // Register a function that adds all its arguments
-    /// ctx.register_native_function("sum", 3, |args| {
-    ///     args.iter().sum::<Real>()
+    /// ctx.set_variable_resolver(|name| match name {
+    ///     "reg_12" => Some(3.0 as Real),
+    ///     _ => None,
     /// });
     ///
-    /// let result = interp("sum(10, 20, 30)", Some(Rc::new(ctx))).unwrap();
-    /// assert_eq!(result, 60.0);
+    /// let result = interp("reg_12 * 2", Some(Rc::new(ctx))).unwrap();
+    /// assert_eq!(result, 6.0);
     /// ```
+    pub fn set_variable_resolver<F>(&mut self, resolver: F)
+    where
+        F: Fn(&str) -> Option<Real> + 'static,
+    {
+        self.variable_resolver = Some(Rc::new(resolver));
+    }
+
+    /// Removes the variable resolver from this context.
+    pub fn clear_variable_resolver(&mut self) {
+        self.variable_resolver = None;
+    }
+
+    /// Resolves a variable through this context's resolver, falling back to the parent chain.
+    pub fn resolve_variable(&self, name: &str) -> Option<Real> {
+        match &self.variable_resolver {
+            Some(resolver) => resolver(name),
+            None => self.parent.as_ref().and_then(|p| p.resolve_variable(name)),
+        }
+    }
+
+    /// Sets a fallback callback for calls to functions that are not registered.
     ///
-    /// Functions with variable argument counts:
+    /// The resolver runs after expression functions and native functions (including those
+    /// of parent contexts) have been checked. It receives the function name and the
+    /// evaluated arguments, so any number of arguments is accepted; arity checking is up
+    /// to the resolver. Returning `None` reports the function as unknown.
+    ///
+    /// If this context has no resolver, the parent's resolver (if any) is used.
+    ///
+    /// # Examples
     ///
     /// ```
     /// use exp_rs::context::EvalContext;
@@ -248,215 +713,1217 @@ impl EvalContext {
     /// use std::rc::Rc;
     ///
     /// let mut ctx = EvalContext::new();
-    ///
-    /// // Register a function that calculates the mean of its arguments
-    /// ctx.register_native_function("mean", 5, |args| {
-    ///     args.iter().sum::<Real>() / args.len() as Real
+    /// ctx.set_function_resolver(|name, args| match (name, args) {
+    ///     ("dev_read", [channel]) => Some(*channel * 10.0),
+    ///     _ => None,
     /// });
     ///
-    /// let result = interp("mean(1, 2, 3, 4, 5)", Some(Rc::new(ctx))).unwrap();
-    /// assert_eq!(result, 3.0);
+    /// let result = interp("dev_read(3) + 1", Some(Rc::new(ctx))).unwrap();
+    /// assert_eq!(result, 31.0 as Real);
     /// ```
-    pub fn register_native_function<F>(
+    pub fn set_function_resolver<F>(&mut self, resolver: F)
+    where
+        F: Fn(&str, &[Real]) -> Option<Real> + 'static,
+    {
+        self.function_resolver = Some(Rc::new(resolver));
+    }
+
+    /// Removes the function resolver from this context.
+    pub fn clear_function_resolver(&mut self) {
+        self.function_resolver = None;
+    }
+
+    /// Dispatches a call through this context's function resolver, falling back to the
+    /// parent chain.
+    pub fn resolve_function(&self, name: &str, args: &[Real]) -> Option<Real> {
+        match &self.function_resolver {
+            Some(resolver) => resolver(name, args),
+            None => self
+                .parent
+                .as_ref()
+                .and_then(|p| p.resolve_function(name, args)),
+        }
+    }
+
+    /// Registers an object whose attributes are read through [`AttributeProvider`].
+    ///
+    /// An existing object with the same name is replaced. Attributes set with
+    /// [`set_attribute`](Self::set_attribute) take precedence over the provider's.
+    pub fn register_object<P>(
         &mut self,
         name: &str,
-        arity: usize,
-        implementation: F,
+        provider: Rc<P>,
     ) -> Result<(), crate::error::ExprError>
     where
-        F: Fn(&[Real]) -> Real + 'static,
+        P: AttributeProvider + 'static,
     {
-        let key = name.try_into_function_name()?;
-        let function = crate::types::NativeFunction {
-            arity,
-            implementation: Rc::new(implementation),
-            name: key.clone(),
-            description: None,
-        };
+        let key = name.try_into_heapless()?;
+        let provider: Rc<dyn AttributeProvider> = provider;
+        match self.objects.iter_mut().find(|(n, _)| *n == key) {
+            Some(entry) => entry.1 = provider,
+            None => self.objects.push((key, provider)),
+        }
+        Ok(())
+    }
 
-        match Rc::make_mut(&mut self.native_functions).insert(key, function) {
-            Ok(_) => Ok(()),
-            Err(_) => Err(crate::error::ExprError::CapacityExceeded(
-                "native_functions",
-            )),
+    /// Removes a registered object, returning whether it existed.
+    pub fn unregister_object(&mut self, name: &str) -> bool {
+        let before = self.objects.len();
+        self.objects.retain(|(n, _)| n.as_str() != name);
+        self.objects.len() != before
+    }
+
+    /// Reads an attribute of a registered object, falling back to the parent chain.
+    ///
+    /// For a path such as `imu.gyro` with no object registered under that name, the
+    /// attribute `gyro.x` of the object `imu` is read instead.
+    pub fn get_object_attribute(&self, object: &str, attr: &str) -> Option<Real> {
+        let (provider, name) = self.find_object(object, attr)?;
+        provider.attribute(&name)
+    }
+
+    /// Returns the length of an array attribute of a registered object, resolved like
+    /// [`get_object_attribute`](Self::get_object_attribute).
+    pub fn get_object_array_len(&self, object: &str, attr: &str) -> Option<usize> {
+        let (provider, name) = self.find_object(object, attr)?;
+        provider.array_len(&name)
+    }
+
+    /// Reads element `index` of an array attribute of a registered object, or `None`
+    /// if there is no such array or the index is out of bounds.
+    pub fn get_object_element(&self, object: &str, attr: &str, index: usize) -> Option<Real> {
+        let (provider, name) = self.find_object(object, attr)?;
+        if index < provider.array_len(&name)? {
+            provider.element(&name, index)
+        } else {
+            None
         }
     }
 
-    /// Enables AST caching for this context to improve performance.
+    /// Finds the registered object owning `object.attr`, along with the attribute
+    /// name relative to it.
+    fn find_object(
+        &self,
+        object: &str,
+        attr: &str,
+    ) -> Option<(&dyn AttributeProvider, AttributePath)> {
+        if let Some(provider) = self.lookup_object(object) {
+            return Some((provider, AttributePath::try_from(attr).ok()?));
+        }
+        let (parent_object, member) = object.rsplit_once('.')?;
+        let (provider, name) = self.find_object(parent_object, member)?;
+        Some((provider, crate::types::dotted_path(&name, attr)?))
+    }
+
+    fn lookup_object(&self, object: &str) -> Option<&dyn AttributeProvider> {
+        match self.objects.iter().find(|(n, _)| n.as_str() == object) {
+            Some((_, provider)) => Some(provider.as_ref()),
+            None => self.parent.as_ref()?.lookup_object(object),
+        }
+    }
+
+    /// Attaches a unit registry so that unit names evaluate to their scale factor.
     ///
-    /// When enabled, repeated calls to `interp` with the same expression string
-    /// will reuse the parsed AST, greatly improving performance for repeated evaluations
-    /// with different variable values.
+    /// Unit names are looked up after variables, constants, the built-in constants and
+    /// the variable resolver, so a variable named `V` shadows the volt. If this context has no registry, the
+    /// parent's registry (if any) is used.
     ///
-    /// This is particularly useful in loops or when evaluating the same expression
-    /// multiple times with different parameter values.
+    /// # Examples
     ///
-    /// # Note
+    /// ```
+    /// use exp_rs::context::EvalContext;
+    /// use exp_rs::engine::{ParseOptions, parse_expression_with_options};
+    /// use exp_rs::eval::eval_ast;
+    /// use exp_rs::units::UnitRegistry;
+    /// use bumpalo::Bump;
+    /// use std::rc::Rc;
     ///
-    /// AST caching has been removed in the arena-based implementation.
-    /// The arena architecture provides better performance characteristics
-    /// without the need for explicit caching.
+    /// let mut ctx = EvalContext::new();
+    /// ctx.set_unit_registry(UnitRegistry::si());
     ///
-    /// Disables AST caching and clears the cache.
+    /// let arena = Bump::new();
+    /// let options = ParseOptions { unit_literals: true, ..Default::default() };
+    /// let ast = parse_expression_with_options("2.5kHz * 4ms", &arena, &options).unwrap();
+    /// let cycles = eval_ast(arena.alloc(ast), Some(Rc::new(ctx)), &arena).unwrap();
+    /// assert!((cycles - 10.0).abs() < 1e-6);
+    /// ```
+    pub fn set_unit_registry(&mut self, units: crate::units::UnitRegistry) {
+        self.units = Some(Rc::new(units));
+    }
+
+    /// Configures rounding and float comparison for this context.
     ///
-    /// This is useful if you want to free up memory or if you want to force
-    /// re-parsing of expressions.
+    /// This replaces the `round`, `==` and `!=` functions of this context (but not of its
+    /// parents or children), so it should be called after any custom registration of
+    /// those names. With a non-zero `equality_epsilon`, `a == b` is true when
+    /// `|a - b| <= equality_epsilon`.
     ///
-    /// # Note
+    /// # Examples
     ///
-    /// AST caching has been removed in the arena-based implementation.
-    /// This functionality is no longer available.
+    /// ```
+    /// use exp_rs::context::{EvalContext, MathConfig, RoundingMode};
+    /// use exp_rs::engine::interp;
+    /// use std::rc::Rc;
     ///
-    /// Clear the AST cache if enabled.
+    /// let mut ctx = EvalContext::new();
+    /// ctx.set_math_config(MathConfig {
+    ///     rounding: RoundingMode::HalfEven,
+    ///     equality_epsilon: 1e-9,
+    /// });
+    /// let ctx = Rc::new(ctx);
     ///
-    /// Registers all built-in math functions as native functions in the context.
+    /// assert_eq!(interp("round(2.5)", Some(ctx.clone())).unwrap(), 2.0);
+    /// assert_eq!(interp("0.1 + 0.2 == 0.3", Some(ctx)).unwrap(), 1.0);
+    /// ```
+    pub fn set_math_config(&mut self, config: MathConfig) {
+        self.math_config = config;
+        let rounding = config.rounding;
+        let epsilon = config.equality_epsilon;
+        let equal = move |a: Real, b: Real| a == b || (a - b).abs() <= epsilon;
+        let _ = self.register_native_function("round", 1, move |args| rounding.round(args[0]));
+        let _ = self.register_native_function("round_to", 2, move |args| {
+            rounding.round_to(args[0], args[1])
+        });
+        let _ =
+            self.register_native_function(
+                "==",
+                2,
+                move |args| if equal(args[0], args[1]) { 1.0 } else { 0.0 },
+            );
+        let _ =
+            self.register_native_function(
+                "!=",
+                2,
+                move |args| if equal(args[0], args[1]) { 0.0 } else { 1.0 },
+            );
+    }
+
+    /// Returns the rounding and comparison settings of this context.
+    pub fn math_config(&self) -> MathConfig {
+        self.math_config
+    }
+
+    /// Limits how deep evaluations using this context may nest.
     ///
-    /// # Usage
+    /// The depth counts pending evaluation steps, which grow with nesting and with
+    /// calls to expression functions; exceeding it fails with
+    /// `ExprError::RecursionLimit`. `None` inherits the parent's limit, falling back to
+    /// `EXP_RS_MAX_STACK_DEPTH`. The limit belongs to the context, so separate tasks
+    /// can use different limits without affecting each other.
+    ///
+    /// # Examples
     ///
     /// ```
-    /// # use exp_rs::EvalContext;
+    /// use exp_rs::context::EvalContext;
+    /// use exp_rs::engine::interp;
+    /// use exp_rs::error::ExprError;
+    /// use std::rc::Rc;
+    ///
     /// let mut ctx = EvalContext::new();
-    /// ctx.register_default_math_functions();
+    /// ctx.set_max_eval_depth(Some(4));
+    /// let result = interp("((((1 + 1) + 1) + 1) + 1) + 1", Some(Rc::new(ctx)));
+    /// assert!(matches!(result, Err(ExprError::RecursionLimit { limit: 4, .. })));
     /// ```
+    pub fn set_max_eval_depth(&mut self, depth: Option<usize>) {
+        self.max_eval_depth = depth;
+    }
+
+    /// Lets evaluations nest past the depth limit by spilling to the heap.
     ///
-    /// After calling this, you can override any built-in by registering your own native function
-    /// with the same name using [`register_native_function`](Self::register_native_function).
+    /// The evaluator keeps up to [`max_eval_depth`](Self::max_eval_depth) pending steps
+    /// in its arena. With a spill limit, the oldest steps move to a heap-allocated
+    /// stack when that fills up and come back as the evaluation unwinds, so deeply
+    /// nested machine-generated expressions still evaluate; only beyond `limit` steps
+    /// in total does the evaluation fail with `ExprError::RecursionLimit`. `None`
+    /// inherits the parent's setting, and without one evaluations stop at the depth
+    /// limit.
     ///
-    /// # Feature: `libm`
+    /// # Examples
     ///
-    /// If the `libm` feature is enabled, this will use the libm implementations.
-    /// Otherwise, it will use the standard library implementation which is not available
-    /// in `no_std` environments.
+    /// ```
+    /// use exp_rs::context::EvalContext;
+    /// use exp_rs::engine::interp;
+    /// use std::rc::Rc;
     ///
-    /// Enables default math functions for this context.
+    /// let nested = "(((((((1 + 1) + 1) + 1) + 1) + 1) + 1) + 1)";
+    /// let mut ctx = EvalContext::new();
+    /// ctx.set_max_eval_depth(Some(4));
+    /// assert!(interp(nested, Some(Rc::new(ctx.clone()))).is_err());
     ///
-    /// Alias for `register_default_math_functions()`.
-    pub fn enable_default_functions(&mut self) {
-        self.register_default_math_functions();
+    /// ctx.set_stack_spill(Some(100));
+    /// assert_eq!(interp(nested, Some(Rc::new(ctx))).unwrap(), 8.0);
+    /// ```
+    pub fn set_stack_spill(&mut self, limit: Option<usize>) {
+        self.stack_spill = limit;
     }
 
-    /// Registers all built-in math functions as native functions in the context.
-    pub fn register_default_math_functions(&mut self) {
-        // Basic operators as functions (always available)
-        let _ = self.register_native_function("+", 2, |args| args[0] + args[1]);
-        let _ = self.register_native_function("-", 2, |args| args[0] - args[1]);
-        let _ = self.register_native_function("*", 2, |args| args[0] * args[1]);
-        let _ = self.register_native_function("/", 2, |args| args[0] / args[1]);
-        let _ = self.register_native_function("%", 2, |args| args[0] % args[1]);
+    /// Restarts the random sequence of `rand`, `rand_range` and `randn` from `seed`.
+    ///
+    /// Contexts start from [`DEFAULT_SEED`](crate::random::DEFAULT_SEED), so results are
+    /// reproducible unless a different seed is set. Clones of a context share its
+    /// generator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use exp_rs::context::EvalContext;
+    /// use exp_rs::engine::interp;
+    /// use std::rc::Rc;
+    ///
+    /// let ctx = Rc::new(EvalContext::new());
+    /// ctx.set_seed(1234);
+    /// let first = interp("rand_range(10, 20)", Some(ctx.clone())).unwrap();
+    /// assert!((10.0..20.0).contains(&first));
+    ///
+    /// ctx.set_seed(1234);
+    /// assert_eq!(interp("rand_range(10, 20)", Some(ctx)).unwrap(), first);
+    /// ```
+    pub fn set_seed(&self, seed: u64) {
+        self.rng.seed(seed);
+    }
 
-        // Comparison operators (always available)
-        let _ =
-            self.register_native_function("<", 2, |args| if args[0] < args[1] { 1.0 } else { 0.0 });
-        let _ =
-            self.register_native_function(">", 2, |args| if args[0] > args[1] { 1.0 } else { 0.0 });
-        let _ = self.register_native_function(
-            "<=",
-            2,
-            |args| if args[0] <= args[1] { 1.0 } else { 0.0 },
-        );
-        let _ = self.register_native_function(
-            ">=",
-            2,
-            |args| if args[0] >= args[1] { 1.0 } else { 0.0 },
-        );
-        let _ = self.register_native_function(
-            "==",
-            2,
-            |args| if args[0] == args[1] { 1.0 } else { 0.0 },
-        );
-        let _ = self.register_native_function(
-            "!=",
-            2,
-            |args| if args[0] != args[1] { 1.0 } else { 0.0 },
-        );
+    /// Sets the clock read by the `now()`, `dt()` and `elapsed(name)` builtins.
+    ///
+    /// `source` returns the current time in seconds. `dt()` returns the time since its
+    /// previous call in this context (0 the first time) and `elapsed(name)` the time
+    /// since the timer `name` was first used or [reset](Self::reset_timer). Without a
+    /// clock these builtins are unknown functions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use exp_rs::context::EvalContext;
+    /// use exp_rs::engine::interp;
+    /// use exp_rs::Real;
+    /// use std::cell::Cell;
+    /// use std::rc::Rc;
+    ///
+    /// // A tick counter incremented elsewhere, e.g. by a timer interrupt
+    /// let ticks = Rc::new(Cell::new(0u32));
+    /// let counter = ticks.clone();
+    /// let mut ctx = EvalContext::new();
+    /// ctx.set_clock(move || counter.get() as Real / 1000.0).unwrap();
+    /// let ctx = Rc::new(ctx);
+    ///
+    /// assert_eq!(interp("elapsed(startup) > 2", Some(ctx.clone())).unwrap(), 0.0);
+    /// ticks.set(2500);
+    /// assert_eq!(interp("elapsed(startup) > 2", Some(ctx.clone())).unwrap(), 1.0);
+    /// assert_eq!(interp("now()", Some(ctx)).unwrap(), 2.5);
+    /// ```
+    pub fn set_clock<F>(&mut self, source: F) -> Result<(), crate::error::ExprError>
+    where
+        F: Fn() -> Real + 'static,
+    {
+        let clock = Rc::new(crate::clock::Clock::new(Rc::new(source)));
+        let now = clock.clone();
+        self.register_native_function("now", 0, move |_| now.now())?;
+        let dt = clock.clone();
+        self.register_native_function("dt", 0, move |_| dt.dt())?;
+        self.clock = Some(clock);
+        Ok(())
+    }
 
-        // Logical operators (always available)
-        let _ = self.register_native_function("&&", 2, |args| {
-            if args[0] != 0.0 && args[1] != 0.0 {
-                1.0
-            } else {
-                0.0
+    /// Returns the clock of this context or its nearest ancestor.
+    pub fn clock(&self) -> Option<&crate::clock::Clock> {
+        match &self.clock {
+            Some(clock) => Some(clock),
+            None => self.parent.as_ref().and_then(|p| p.clock()),
+        }
+    }
+
+    /// Restarts the `elapsed` timer `name`. Returns `false` if no clock is set.
+    pub fn reset_timer(&self, name: &str) -> bool {
+        self.clock().map(|clock| clock.reset_timer(name)).is_some()
+    }
+
+    /// Adds a ring buffer of the last `capacity` samples named `name`, read by
+    /// `history(name, n)` and the `window_*` builtins (see [`crate::history`]).
+    ///
+    /// Returns the buffer, through which the host pushes samples also after the context
+    /// is shared. A history with the same name is replaced.
+    pub fn add_history(
+        &mut self,
+        name: &str,
+        capacity: usize,
+    ) -> Result<Rc<crate::history::History>, crate::error::ExprError> {
+        let key = name.try_into_heapless()?;
+        let history = Rc::new(crate::history::History::new(capacity));
+        match self.histories.iter_mut().find(|(n, _)| *n == key) {
+            Some(entry) => entry.1 = history.clone(),
+            None => self.histories.push((key, history.clone())),
+        }
+        Ok(history)
+    }
+
+    /// Removes a history, returning whether it existed.
+    pub fn remove_history(&mut self, name: &str) -> bool {
+        let before = self.histories.len();
+        self.histories.retain(|(n, _)| n.as_str() != name);
+        self.histories.len() != before
+    }
+
+    /// Returns the history `name` of this context or its nearest ancestor.
+    pub fn history(&self, name: &str) -> Option<&Rc<crate::history::History>> {
+        match self.histories.iter().find(|(n, _)| n.as_str() == name) {
+            Some((_, history)) => Some(history),
+            None => self.parent.as_ref().and_then(|p| p.history(name)),
+        }
+    }
+
+    /// Restricts the functions that expressions evaluated with this context may call.
+    ///
+    /// `None` removes the restriction, so the parent's policy (if any) applies again. A
+    /// policy set here replaces the parent's rather than adding to it.
+    pub fn set_function_policy(&mut self, policy: Option<FunctionPolicy>) {
+        self.function_policy = policy.map(Rc::new);
+    }
+
+    /// Returns the function policy of this context or its nearest ancestor.
+    pub fn function_policy(&self) -> Option<&FunctionPolicy> {
+        match &self.function_policy {
+            Some(policy) => Some(policy),
+            None => self.parent.as_ref().and_then(|p| p.function_policy()),
+        }
+    }
+
+    /// Fails with `ExprError::FunctionNotPermitted` if the function policy of this
+    /// context does not permit calling `name`.
+    ///
+    /// Every evaluator checks calls with this, including calls of its own builtins, so
+    /// a policy restricts expressions however they are evaluated.
+    pub fn check_function_permitted(&self, name: &str) -> Result<(), crate::error::ExprError> {
+        match self.function_policy() {
+            Some(policy) if !policy.permits(name) => {
+                Err(crate::error::ExprError::FunctionNotPermitted {
+                    name: name.to_string(),
+                })
             }
+            _ => Ok(()),
+        }
+    }
+
+    /// Marks the function `name` as deprecated, optionally naming its replacement.
+    ///
+    /// Deprecated functions still evaluate as before.
+    /// [`Expression::deprecated_calls`](crate::expression::Expression::deprecated_calls)
+    /// lists the stored expressions that call them and warns about each through the
+    /// logger, which tracks the migration of configurations to the new names.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use exp_rs::context::EvalContext;
+    ///
+    /// let mut ctx = EvalContext::new();
+    /// ctx.deprecate_function("ln", Some("log")).unwrap();
+    /// let deprecation = ctx.function_deprecation("ln").unwrap();
+    /// assert_eq!(deprecation.replacement.as_deref(), Some("log"));
+    /// assert!(ctx.function_deprecation("log").is_none());
+    /// ```
+    pub fn deprecate_function(
+        &mut self,
+        name: &str,
+        replacement: Option<&str>,
+    ) -> Result<(), crate::error::ExprError> {
+        let deprecation = Deprecation {
+            name: name.try_into_function_name()?,
+            replacement: replacement
+                .map(|r| r.try_into_function_name())
+                .transpose()?,
+        };
+        match self
+            .deprecations
+            .iter_mut()
+            .find(|d| d.name == deprecation.name)
+        {
+            Some(entry) => *entry = deprecation,
+            None => self.deprecations.push(deprecation),
+        }
+        Ok(())
+    }
+
+    /// Removes the deprecation of `name` from this context, returning whether it
+    /// existed.
+    pub fn undeprecate_function(&mut self, name: &str) -> bool {
+        let before = self.deprecations.len();
+        self.deprecations.retain(|d| d.name.as_str() != name);
+        self.deprecations.len() != before
+    }
+
+    /// Returns the deprecation of `name` in this context or its nearest ancestor.
+    pub fn function_deprecation(&self, name: &str) -> Option<&Deprecation> {
+        match self.deprecations.iter().find(|d| d.name.as_str() == name) {
+            Some(deprecation) => Some(deprecation),
+            None => self
+                .parent
+                .as_ref()
+                .and_then(|p| p.function_deprecation(name)),
+        }
+    }
+
+    /// Sets the logger receiving diagnostics of parsing and evaluation with this
+    /// context and its children; see [`crate::log`].
+    pub fn set_logger<L: crate::log::Logger + 'static>(&mut self, logger: L) {
+        self.logger = Some(Rc::new(logger));
+    }
+
+    /// Removes the logger, so the parent's logger (if any) is used again.
+    pub fn clear_logger(&mut self) {
+        self.logger = None;
+    }
+
+    /// Returns the logger of this context or its nearest ancestor.
+    pub fn logger(&self) -> Option<&Rc<dyn crate::log::Logger>> {
+        match &self.logger {
+            Some(logger) => Some(logger),
+            None => self.parent.as_ref().and_then(|p| p.logger()),
+        }
+    }
+
+    /// Sets a callback run every `interval` AST nodes during evaluation with this
+    /// context and its children, for kicking a hardware watchdog or polling an abort
+    /// flag during long evaluations.
+    ///
+    /// The count carries over from one evaluation to the next on the same engine, so
+    /// a batch of short expressions runs the callback as regularly as one long
+    /// expression. Returning `ControlFlow::Break(())` from the callback aborts the
+    /// evaluation with `ExprError::Aborted`. An `interval` of 0 is treated as 1.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use exp_rs::context::EvalContext;
+    /// use exp_rs::engine::interp;
+    /// use exp_rs::error::ExprError;
+    /// use std::cell::Cell;
+    /// use std::ops::ControlFlow;
+    /// use std::rc::Rc;
+    ///
+    /// let kicks = Rc::new(Cell::new(0));
+    /// let abort = Rc::new(Cell::new(false));
+    /// let mut ctx = EvalContext::new();
+    /// let (k, a) = (kicks.clone(), abort.clone());
+    /// ctx.set_watchdog(4, move || {
+    ///     k.set(k.get() + 1);
+    ///     if a.get() { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+    /// });
+    /// let ctx = Rc::new(ctx);
+    ///
+    /// assert_eq!(interp("(1 + 2) * (3 + 4) - 5", Some(ctx.clone())).unwrap(), 16.0);
+    /// assert!(kicks.get() >= 2);
+    ///
+    /// abort.set(true);
+    /// assert!(matches!(
+    ///     interp("(1 + 2) * (3 + 4) - 5", Some(ctx)),
+    ///     Err(ExprError::Aborted)
+    /// ));
+    /// ```
+    pub fn set_watchdog<F>(&mut self, interval: usize, callback: F)
+    where
+        F: Fn() -> core::ops::ControlFlow<()> + 'static,
+    {
+        self.watchdog = Some(Watchdog {
+            interval: interval.max(1),
+            callback: Rc::new(callback),
         });
-        let _ = self.register_native_function("||", 2, |args| {
-            if args[0] != 0.0 || args[1] != 0.0 {
-                1.0
-            } else {
-                0.0
+    }
+
+    /// Removes the watchdog callback, so the parent's (if any) is used again.
+    pub fn clear_watchdog(&mut self) {
+        self.watchdog = None;
+    }
+
+    /// Returns the watchdog of this context or its nearest ancestor.
+    pub fn watchdog(&self) -> Option<&Watchdog> {
+        match &self.watchdog {
+            Some(watchdog) => Some(watchdog),
+            None => self.parent.as_ref().and_then(|p| p.watchdog()),
+        }
+    }
+
+    /// Sends a message to the logger, if one is set and `level` is enabled.
+    pub fn log(&self, level: crate::log::Level, args: core::fmt::Arguments<'_>) {
+        if let Some(logger) = self.logger()
+            && logger.enabled(level)
+        {
+            logger.log(level, args);
+        }
+    }
+
+    /// Returns the evaluation depth limit of this context or its nearest ancestor.
+    pub fn max_eval_depth(&self) -> Option<usize> {
+        self.max_eval_depth
+            .or_else(|| self.parent.as_ref().and_then(|p| p.max_eval_depth()))
+    }
+
+    /// Declares variables that expressions may reference, whether or not they are set.
+    ///
+    /// With [`set_strict_declarations`](Self::set_strict_declarations) enabled,
+    /// validating an expression fails on references to names that are neither declared
+    /// nor currently defined in the context, so a typo such as `temprature` is reported
+    /// when the configuration is loaded rather than when the expression is evaluated.
+    /// Declaring `imu` also declares attribute paths such as `imu.gyro`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use exp_rs::context::EvalContext;
+    /// use exp_rs::engine::parse_expression;
+    /// use exp_rs::error::ExprError;
+    /// use bumpalo::Bump;
+    ///
+    /// let mut ctx = EvalContext::new();
+    /// ctx.set_strict_declarations(true);
+    /// ctx.declare_variables(["temperature", "setpoint"]).unwrap();
+    ///
+    /// let arena = Bump::new();
+    /// let ok = parse_expression("temperature > setpoint + 2", &arena).unwrap();
+    /// assert!(ctx.check_declarations(&ok).is_ok());
+    /// let typo = parse_expression("temprature > setpoint + 2", &arena).unwrap();
+    /// assert!(matches!(
+    ///     ctx.check_declarations(&typo),
+    ///     Err(ExprError::UnknownVariable { name }) if name == "temprature"
+    /// ));
+    /// ```
+    pub fn declare_variables<'a>(
+        &mut self,
+        names: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(), crate::error::ExprError> {
+        for name in names {
+            let key = name.try_into_heapless()?;
+            if !self.declarations.contains(&key) {
+                self.declarations.push(key);
             }
-        });
+        }
+        Ok(())
+    }
 
-        // Function aliases for the operators (always available)
-        let _ = self.register_native_function("add", 2, |args| args[0] + args[1]);
-        let _ = self.register_native_function("sub", 2, |args| args[0] - args[1]);
-        let _ = self.register_native_function("mul", 2, |args| args[0] * args[1]);
-        let _ = self.register_native_function("div", 2, |args| args[0] / args[1]);
-        let _ = self.register_native_function("fmod", 2, |args| args[0] % args[1]);
-        let _ = self.register_native_function("neg", 1, |args| -args[0]);
+    /// Removes all declarations of this context.
+    pub fn clear_declarations(&mut self) {
+        self.declarations.clear();
+    }
 
-        // Sequence operators (always available)
-        let _ = self.register_native_function(",", 2, |args| args[1]); // The actual comma operator
-        let _ = self.register_native_function("comma", 2, |args| args[1]); // Function alias for the comma operator
+    /// Sets whether expressions validated with this context may only reference declared
+    /// names (see [`declare_variables`](Self::declare_variables)).
+    pub fn set_strict_declarations(&mut self, strict: bool) {
+        self.strict_declarations = Some(strict);
+    }
 
-        // Core math functions that don't require libm (always available)
-        let _ = self.register_native_function("abs", 1, |args| args[0].abs());
-        let _ = self.register_native_function("max", 2, |args| args[0].max(args[1]));
-        let _ = self.register_native_function("min", 2, |args| args[0].min(args[1]));
-        let _ = self.register_native_function("sign", 1, |args| {
-            if args[0] > 0.0 {
-                1.0
-            } else if args[0] < 0.0 {
-                -1.0
-            } else {
-                0.0
+    /// Returns whether references must be declared, as set in this context or its
+    /// nearest ancestor. Off by default.
+    pub fn strict_declarations(&self) -> bool {
+        self.strict_declarations
+            .or_else(|| self.parent.as_ref().map(|p| p.strict_declarations()))
+            .unwrap_or(false)
+    }
+
+    /// Returns whether `name` is declared in this context or its parents, or currently
+    /// names a variable, constant, array, history, object or attribute map of them.
+    pub fn is_declared(&self, name: &str) -> bool {
+        let base = name.split_once('.').map_or(name, |(base, _)| base);
+        let mut ctx = Some(self);
+        while let Some(current) = ctx {
+            let declared = current
+                .declarations
+                .iter()
+                .chain(current.objects.iter().map(|(n, _)| n))
+                .any(|d| d.as_str() == name || d.as_str() == base);
+            if declared {
+                return true;
             }
-        });
+            ctx = current.parent.as_deref();
+        }
+        self.get_variable(name).is_some()
+            || self.get_constant(name).is_some()
+            || self.get_array(name).is_some()
+            || self.get_array_view(name).is_some()
+            || self.history(name).is_some()
+            || self.get_attribute_map(base).is_some()
+    }
 
-        // Math constants (always available)
-        #[cfg(feature = "f32")]
-        let _ = self.register_native_function("e", 0, |_| core::f32::consts::E);
-        #[cfg(not(feature = "f32"))]
-        let _ = self.register_native_function("e", 0, |_| core::f64::consts::E);
+    /// Checks that every name `expr` references is declared (see
+    /// [`is_declared`](Self::is_declared)) or a builtin constant, failing with
+    /// [`UnknownVariable`](crate::error::ExprError::UnknownVariable) for the first that
+    /// is not. Timer names of `elapsed` are not checked.
+    pub fn check_declarations(&self, expr: &AstExpr<'_>) -> Result<(), crate::error::ExprError> {
+        self.check_declarations_with(expr, &|_| false)
+    }
 
-        #[cfg(feature = "f32")]
-        let _ = self.register_native_function("pi", 0, |_| core::f32::consts::PI);
-        #[cfg(not(feature = "f32"))]
-        let _ = self.register_native_function("pi", 0, |_| core::f64::consts::PI);
+    /// Like [`check_declarations`](Self::check_declarations), also accepting the names
+    /// for which `is_input` holds.
+    pub(crate) fn check_declarations_with(
+        &self,
+        expr: &AstExpr<'_>,
+        is_input: &dyn Fn(&str) -> bool,
+    ) -> Result<(), crate::error::ExprError> {
+        let mut check = Undeclared {
+            ctx: self,
+            is_input,
+            found: None,
+        };
+        crate::visit::walk_ast(&mut check, expr);
+        match check.found {
+            Some(name) => Err(crate::error::ExprError::UnknownVariable { name }),
+            None => Ok(()),
+        }
+    }
 
-        // Advanced math functions with libm
-        #[cfg(feature = "libm")]
+    /// Sets the variable `name` to a typed value (see [`crate::value`]).
+    ///
+    /// A [`Real`](crate::value::Value::Real) is stored like
+    /// [`set_parameter`](Self::set_parameter) does, while integers and booleans are kept
+    /// exactly and read by expressions as [`Real`]. The value replaces a variable of any
+    /// kind with the same name. Returns the previous value of this context, if any.
+    ///
+    /// Without the `std` feature a context holds up to `EXP_RS_MAX_VARIABLES` integer and
+    /// boolean values, besides its real variables; setting another fails with
+    /// `ExprError::CapacityExceeded` and leaves the context unchanged.
+    pub fn set_value(
+        &mut self,
+        name: &str,
+        value: impl Into<crate::value::Value>,
+    ) -> Result<Option<crate::value::Value>, crate::error::ExprError> {
+        use crate::value::Value;
+
+        let value = value.into();
+        let key = name.try_into_heapless()?;
+        match value {
+            Value::Real(x) => {
+                let old = self.typed_values.remove(&key);
+                Ok(old.or(self.set_parameter(name, x)?.map(Value::Real)))
+            }
+            _ => {
+                let old = self.typed_values.insert(key.clone(), value).map_err(|_| {
+                    crate::error::ExprError::CapacityExceeded {
+                        container: "typed values",
+                    }
+                })?;
+                Ok(old.or(self.variables.remove(&key).map(Value::Real)))
+            }
+        }
+    }
+
+    /// Returns the value of the variable `name` of this context or its parents with its
+    /// kind, [`Real`](crate::value::Value::Real) for variables set as reals.
+    pub fn get_value(&self, name: &str) -> Option<crate::value::Value> {
+        if let Ok(key) = name.try_into_heapless()
+            && let Some(&value) = self.variables.get(&key)
         {
+            return Some(crate::value::Value::Real(value));
+        }
+        self.own_typed_value(name)
+            .or_else(|| self.parent.as_ref().and_then(|p| p.get_value(name)))
+    }
+
+    /// Returns the integer or boolean value `name` of this context, not its parents.
+    pub(crate) fn own_typed_value(&self, name: &str) -> Option<crate::value::Value> {
+        let key = name.try_into_heapless().ok()?;
+        self.typed_values.get(&key).copied()
+    }
+
+    /// Sets the variable `name` to the missing value (see [`crate::missing`]).
+    pub fn set_missing(&mut self, name: &str) -> Result<(), crate::error::ExprError> {
+        self.set_parameter(name, crate::missing::MISSING)
+            .map(|_| ())
+    }
+
+    /// Sets whether missing values propagate in evaluations with this context: calls
+    /// with a missing argument (other than `is_missing` and `default`), logical
+    /// operators and conditions on a missing value then give a missing result. See
+    /// [`crate::missing`].
+    pub fn set_missing_propagation(&mut self, enabled: bool) {
+        self.missing_propagation = Some(enabled);
+    }
+
+    /// Returns whether missing values propagate, as set in this context or its nearest
+    /// ancestor. Off by default.
+    pub fn missing_propagation(&self) -> bool {
+        self.missing_propagation
+            .or_else(|| self.parent.as_ref().map(|p| p.missing_propagation()))
+            .unwrap_or(false)
+    }
+
+    /// Returns the stack spill limit of this context or its nearest ancestor.
+    pub fn stack_spill(&self) -> Option<usize> {
+        self.stack_spill
+            .or_else(|| self.parent.as_ref().and_then(|p| p.stack_spill()))
+    }
+
+    /// Returns the unit registry of this context or its nearest ancestor.
+    pub fn unit_registry(&self) -> Option<&crate::units::UnitRegistry> {
+        match &self.units {
+            Some(units) => Some(units),
+            None => self.parent.as_ref().and_then(|p| p.unit_registry()),
+        }
+    }
+
+    /// Registers a native function in the context.
+    ///
+    /// Native functions are implemented in Rust and can be called from expressions.
+    /// They take a slice of Real values as arguments and return a Real value.
+    ///
+    /// # Parameters
+    ///
+    /// * `name`: The name of the function as it will be used in expressions
+    /// * `arity`: The number of arguments the function expects
+    /// * `implementation`: A closure or function that implements the function logic
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use exp_rs::context::EvalContext;
+    /// use exp_rs::engine::interp;
+    /// use exp_rs::Real;
+    /// use std::rc::Rc;
+    ///
+    /// let mut ctx = EvalContext::new();
+    ///
+    /// // Register a function that adds all its arguments
+    /// ctx.register_native_function("sum", 3, |args| {
+    ///     args.iter().sum::<Real>()
+    /// });
+    ///
+    /// let result = interp("sum(10, 20, 30)", Some(Rc::new(ctx))).unwrap();
+    /// assert_eq!(result, 60.0);
+    /// ```
+    ///
+    /// Functions with variable argument counts:
+    ///
+    /// ```
+    /// use exp_rs::context::EvalContext;
+    /// use exp_rs::engine::interp;
+    /// use exp_rs::Real;
+    /// use std::rc::Rc;
+    ///
+    /// let mut ctx = EvalContext::new();
+    ///
+    /// // Register a function that calculates the mean of its arguments
+    /// ctx.register_native_function("mean", 5, |args| {
+    ///     args.iter().sum::<Real>() / args.len() as Real
+    /// });
+    ///
+    /// let result = interp("mean(1, 2, 3, 4, 5)", Some(Rc::new(ctx))).unwrap();
+    /// assert_eq!(result, 3.0);
+    /// ```
+    pub fn register_native_function<F>(
+        &mut self,
+        name: &str,
+        arity: usize,
+        implementation: F,
+    ) -> Result<(), crate::error::ExprError>
+    where
+        F: Fn(&[Real]) -> Real + 'static,
+    {
+        self.insert_native_function(name, arity, false, implementation)
+    }
+
+    /// Registers a native function that takes `min_args` or more arguments.
+    ///
+    /// The implementation receives all arguments of the call as one slice.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use exp_rs::context::EvalContext;
+    /// use exp_rs::engine::interp;
+    /// use exp_rs::Real;
+    /// use std::rc::Rc;
+    ///
+    /// let mut ctx = EvalContext::new();
+    /// ctx.register_variadic_function("sum", 1, |args| args.iter().sum::<Real>())
+    ///     .unwrap();
+    /// let ctx = Rc::new(ctx);
+    ///
+    /// assert_eq!(interp("sum(1, 2, 3, 4)", Some(ctx.clone())).unwrap(), 10.0);
+    /// assert!(interp("sum()", Some(ctx)).is_err());
+    /// ```
+    pub fn register_variadic_function<F>(
+        &mut self,
+        name: &str,
+        min_args: usize,
+        implementation: F,
+    ) -> Result<(), crate::error::ExprError>
+    where
+        F: Fn(&[Real]) -> Real + 'static,
+    {
+        self.insert_native_function(name, min_args, true, implementation)
+    }
+
+    fn insert_native_function<F>(
+        &mut self,
+        name: &str,
+        arity: usize,
+        variadic: bool,
+        implementation: F,
+    ) -> Result<(), crate::error::ExprError>
+    where
+        F: Fn(&[Real]) -> Real + 'static,
+    {
+        let key = name.try_into_function_name()?;
+        let function = crate::types::NativeFunction {
+            arity,
+            variadic,
+            implementation: Rc::new(implementation),
+            name: key.clone(),
+            description: None,
+            pure: false,
+            validator: None,
+            builtin: false,
+            #[cfg(all(feature = "shadow", not(feature = "f32")))]
+            single_precision: None,
+        };
+
+        match Rc::make_mut(&mut self.native_functions).insert(key, function) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(crate::error::ExprError::CapacityExceeded {
+                container: "native_functions",
+            }),
+        }
+    }
+
+    /// Enables AST caching for this context to improve performance.
+    ///
+    /// When enabled, repeated calls to `interp` with the same expression string
+    /// will reuse the parsed AST, greatly improving performance for repeated evaluations
+    /// with different variable values.
+    ///
+    /// This is particularly useful in loops or when evaluating the same expression
+    /// multiple times with different parameter values.
+    ///
+    /// # Note
+    ///
+    /// AST caching has been removed in the arena-based implementation.
+    /// The arena architecture provides better performance characteristics
+    /// without the need for explicit caching.
+    ///
+    /// Disables AST caching and clears the cache.
+    ///
+    /// This is useful if you want to free up memory or if you want to force
+    /// re-parsing of expressions.
+    ///
+    /// # Note
+    ///
+    /// AST caching has been removed in the arena-based implementation.
+    /// This functionality is no longer available.
+    ///
+    /// Clear the AST cache if enabled.
+    ///
+    /// Registers all built-in math functions as native functions in the context.
+    ///
+    /// # Usage
+    ///
+    /// ```
+    /// # use exp_rs::EvalContext;
+    /// let mut ctx = EvalContext::new();
+    /// ctx.register_default_math_functions();
+    /// ```
+    ///
+    /// After calling this, you can override any built-in by registering your own native function
+    /// with the same name using [`register_native_function`](Self::register_native_function).
+    ///
+    /// # Feature: `libm`
+    ///
+    /// If the `libm` feature is enabled, this will use the libm implementations.
+    /// Otherwise, it will use the standard library implementation which is not available
+    /// in `no_std` environments.
+    ///
+    /// Enables default math functions for this context.
+    ///
+    /// Alias for `register_default_math_functions()`.
+    pub fn enable_default_functions(&mut self) {
+        self.register_default_math_functions();
+    }
+
+    /// Registers all built-in math functions as native functions in the context.
+    ///
+    /// Built-ins replace functions of the same name registered before, and all of
+    /// them except the random number generators are marked pure.
+    pub fn register_default_math_functions(&mut self) {
+        let registered = core::mem::take(Rc::make_mut(&mut self.native_functions));
+        self.register_builtin_functions();
+        let functions = Rc::make_mut(&mut self.native_functions);
+        for function in functions.values_mut() {
+            function.pure = !matches!(function.name.as_str(), "rand" | "rand_range" | "randn");
+            function.builtin = true;
+        }
+        for (name, function) in registered.iter() {
+            if !functions.contains_key(name) {
+                let _ = functions.insert(name.clone(), function.clone());
+            }
+        }
+    }
+
+    fn register_builtin_functions(&mut self) {
+        // Basic operators as functions (always available)
+        let _ = self.register_native_function("+", 2, |args| args[0] + args[1]);
+        let _ = self.register_native_function("-", 2, |args| args[0] - args[1]);
+        let _ = self.register_native_function("*", 2, |args| args[0] * args[1]);
+        let _ = self.register_native_function("/", 2, |args| args[0] / args[1]);
+        let _ = self.register_native_function("%", 2, |args| args[0] % args[1]);
+
+        // Comparison operators (always available)
+        let _ =
+            self.register_native_function("<", 2, |args| if args[0] < args[1] { 1.0 } else { 0.0 });
+        let _ =
+            self.register_native_function(">", 2, |args| if args[0] > args[1] { 1.0 } else { 0.0 });
+        let _ = self.register_native_function(
+            "<=",
+            2,
+            |args| if args[0] <= args[1] { 1.0 } else { 0.0 },
+        );
+        let _ = self.register_native_function(
+            ">=",
+            2,
+            |args| if args[0] >= args[1] { 1.0 } else { 0.0 },
+        );
+        let _ = self.register_native_function(
+            "==",
+            2,
+            |args| if args[0] == args[1] { 1.0 } else { 0.0 },
+        );
+        let _ = self.register_native_function(
+            "!=",
+            2,
+            |args| if args[0] != args[1] { 1.0 } else { 0.0 },
+        );
+
+        // Logical operators (always available)
+        let _ = self.register_native_function("&&", 2, |args| {
+            if args[0] != 0.0 && args[1] != 0.0 {
+                1.0
+            } else {
+                0.0
+            }
+        });
+        let _ = self.register_native_function("||", 2, |args| {
+            if args[0] != 0.0 || args[1] != 0.0 {
+                1.0
+            } else {
+                0.0
+            }
+        });
+
+        // Function aliases for the operators (always available)
+        let _ = self.register_native_function("add", 2, |args| args[0] + args[1]);
+        let _ = self.register_native_function("sub", 2, |args| args[0] - args[1]);
+        let _ = self.register_native_function("mul", 2, |args| args[0] * args[1]);
+        let _ = self.register_native_function("div", 2, |args| args[0] / args[1]);
+        let _ = self.register_native_function("fmod", 2, |args| args[0] % args[1]);
+        let _ = self.register_native_function("neg", 1, |args| -args[0]);
+
+        // Sequence operators (always available)
+        let _ = self.register_native_function(",", 2, |args| args[1]); // The actual comma operator
+        let _ = self.register_native_function(";", 2, |args| args[1]); // Semicolon separator
+        let _ = self.register_native_function("comma", 2, |args| args[1]); // Function alias for the comma operator
+
+        // Core math functions that don't require libm (always available)
+        let _ = self.register_native_function("abs", 1, |args| args[0].abs());
+        let _ = self.register_variadic_function("max", 1, |args| {
+            args.iter().copied().fold(Real::NAN, Real::max)
+        });
+        let _ = self.register_variadic_function("min", 1, |args| {
+            args.iter().copied().fold(Real::NAN, Real::min)
+        });
+        let _ = self.register_variadic_function("argmax", 1, |args| {
+            crate::functions::argmax(args.iter().copied())
+        });
+        let _ = self.register_variadic_function("argmin", 1, |args| {
+            crate::functions::argmin(args.iter().copied())
+        });
+
+        // Predicates and fallbacks for missing or invalid inputs
+        let _ = self.register_native_function("isnan", 1, |args| args[0].is_nan() as u8 as Real);
+        let _ =
+            self.register_native_function("isinf", 1, |args| args[0].is_infinite() as u8 as Real);
+        let _ =
+            self.register_native_function("isfinite", 1, |args| args[0].is_finite() as u8 as Real);
+        let _ = self.register_variadic_function("coalesce", 1, |args| {
+            crate::functions::coalesce(args.iter().copied())
+        });
+        let _ = self.register_native_function("nanfallback", 2, |args| {
+            if args[0].is_nan() { args[1] } else { args[0] }
+        });
+        let _ = self.register_native_function("is_missing", 1, |args| {
+            crate::missing::is_missing(args[0]) as u8 as Real
+        });
+        let _ = self.register_native_function("default", 2, |args| {
+            if crate::missing::is_missing(args[0]) {
+                args[1]
+            } else {
+                args[0]
+            }
+        });
+
+        // Truncation and quantization to step sizes
+        let _ =
+            self.register_native_function("trunc", 1, |args| crate::functions::trunc(args[0], 0.0));
+        let _ =
+            self.register_native_function("fract", 1, |args| crate::functions::fract(args[0], 0.0));
+        let _ = self.register_native_function("round_to", 2, |args| {
+            crate::functions::round_to(args[0], args[1])
+        });
+        let _ = self.register_native_function("quantize", 3, |args| {
+            crate::functions::quantize(args[0], args[1], args[2])
+        });
+
+        // Saturating and wrapping arithmetic
+        let _ = self.register_native_function("sat_add", 4, |args| {
+            crate::functions::sat_add(args[0], args[1], args[2], args[3])
+        });
+        let _ = self.register_native_function("wrap", 3, |args| {
+            crate::functions::wrap(args[0], args[1], args[2])
+        });
+        let _ = self.register_native_function("angle_wrap", 1, |args| {
+            crate::functions::angle_wrap(args[0])
+        });
+
+        // Integer arithmetic, exact in i64
+        let _ = self
+            .register_native_function("idiv", 2, |args| crate::functions::idiv(args[0], args[1]));
+        let _ = self
+            .register_native_function("imod", 2, |args| crate::functions::imod(args[0], args[1]));
+        let _ = self.register_native_function("iabs", 1, |args| crate::functions::iabs(args[0]));
+
+        // Bits of integers in two's complement
+        let _ = self.register_native_function("bitand", 2, |args| {
+            crate::functions::bitand(args[0], args[1])
+        });
+        let _ = self
+            .register_native_function("bitor", 2, |args| crate::functions::bitor(args[0], args[1]));
+        let _ = self.register_native_function("bitxor", 2, |args| {
+            crate::functions::bitxor(args[0], args[1])
+        });
+        let _ =
+            self.register_native_function("bitnot", 1, |args| crate::functions::bitnot(args[0]));
+        let _ = self.register_native_function("getbit", 2, |args| {
+            crate::functions::getbit(args[0], args[1])
+        });
+        let _ = self.register_native_function("setbit", 3, |args| {
+            crate::functions::setbit(args[0], args[1], args[2])
+        });
+        let _ = self
+            .register_native_function("popcount", 1, |args| crate::functions::popcount(args[0]));
+
+        // Random numbers from the context's seedable generator
+        let rng = self.rng.clone();
+        let _ = self.register_native_function("rand", 0, move |_| rng.next_real());
+        let rng = self.rng.clone();
+        let _ =
+            self.register_native_function("rand_range", 2, move |args| rng.range(args[0], args[1]));
+        #[cfg(feature = "libm")]
+        {
+            let rng = self.rng.clone();
+            let _ = self.register_native_function("randn", 0, move |_| rng.normal());
+        }
+
+        // Special functions
+        #[cfg(feature = "special-functions")]
+        {
+            let _ = self.register_native_function("tgamma", 1, |args| {
+                crate::functions::tgamma(args[0], 0.0)
+            });
+            let _ = self.register_native_function("lgamma", 1, |args| {
+                crate::functions::lgamma(args[0], 0.0)
+            });
+            let _ =
+                self.register_native_function("erf", 1, |args| crate::functions::erf(args[0], 0.0));
+            let _ = self
+                .register_native_function("erfc", 1, |args| crate::functions::erfc(args[0], 0.0));
+            let _ =
+                self.register_native_function("j0", 1, |args| crate::functions::j0(args[0], 0.0));
+            let _ =
+                self.register_native_function("j1", 1, |args| crate::functions::j1(args[0], 0.0));
+        }
+
+        // Descriptive statistics, over values or a whole array
+        #[cfg(feature = "stats")]
+        {
+            let _ = self.register_variadic_function("mean", 1, crate::stats::mean);
+            let _ = self.register_variadic_function("variance", 1, crate::stats::variance);
+            let _ = self.register_variadic_function("stddev", 1, crate::stats::stddev);
+            let _ = self.register_variadic_function("median", 1, crate::stats::median);
+            // The last argument is the percentage: `percentile(samples, 95)`
+            let _ = self.register_variadic_function("percentile", 2, |args| {
+                let (p, values) = args.split_last().unwrap();
+                crate::stats::percentile(values, *p)
+            });
+        }
+        let _ = self.register_native_function("sign", 1, |args| {
+            if args[0] > 0.0 {
+                1.0
+            } else if args[0] < 0.0 {
+                -1.0
+            } else {
+                0.0
+            }
+        });
+
+        // Math constants (always available)
+        #[cfg(feature = "f32")]
+        let _ = self.register_native_function("e", 0, |_| core::f32::consts::E);
+        #[cfg(not(feature = "f32"))]
+        let _ = self.register_native_function("e", 0, |_| core::f64::consts::E);
+
+        #[cfg(feature = "f32")]
+        let _ = self.register_native_function("pi", 0, |_| core::f32::consts::PI);
+        #[cfg(not(feature = "f32"))]
+        let _ = self.register_native_function("pi", 0, |_| core::f64::consts::PI);
+
+        // Advanced math functions with libm
+        #[cfg(feature = "libm")]
+        {
+            let _ = self
+                .register_native_function("acos", 1, |args| crate::functions::acos(args[0], 0.0));
+            let _ = self
+                .register_native_function("asin", 1, |args| crate::functions::asin(args[0], 0.0));
+            let _ = self
+                .register_native_function("atan", 1, |args| crate::functions::atan(args[0], 0.0));
+            let _ = self.register_native_function("atan2", 2, |args| {
+                crate::functions::atan2(args[0], args[1])
+            });
+            let _ = self
+                .register_native_function("ceil", 1, |args| crate::functions::ceil(args[0], 0.0));
+            let _ =
+                self.register_native_function("cos", 1, |args| crate::functions::cos(args[0], 0.0));
+            let _ = self
+                .register_native_function("cosh", 1, |args| crate::functions::cosh(args[0], 0.0));
+            let _ =
+                self.register_native_function("exp", 1, |args| crate::functions::exp(args[0], 0.0));
             let _ = self
-                .register_native_function("acos", 1, |args| crate::functions::acos(args[0], 0.0));
-            let _ = self
-                .register_native_function("asin", 1, |args| crate::functions::asin(args[0], 0.0));
-            let _ = self
-                .register_native_function("atan", 1, |args| crate::functions::atan(args[0], 0.0));
-            let _ = self.register_native_function("atan2", 2, |args| {
-                crate::functions::atan2(args[0], args[1])
-            });
-            let _ = self
-                .register_native_function("ceil", 1, |args| crate::functions::ceil(args[0], 0.0));
-            let _ =
-                self.register_native_function("cos", 1, |args| crate::functions::cos(args[0], 0.0));
-            let _ = self
-                .register_native_function("cosh", 1, |args| crate::functions::cosh(args[0], 0.0));
-            let _ =
-                self.register_native_function("exp", 1, |args| crate::functions::exp(args[0], 0.0));
-            let _ = self
                 .register_native_function("floor", 1, |args| crate::functions::floor(args[0], 0.0));
             let _ = self
                 .register_native_function("round", 1, |args| crate::functions::round(args[0], 0.0));
             let _ =
                 self.register_native_function("ln", 1, |args| crate::functions::ln(args[0], 0.0));
-            let _ =
-                self.register_native_function("log", 1, |args| crate::functions::log(args[0], 0.0));
+            // log(x) is the base-10 logarithm, log(x, base) uses an explicit base
+            let _ = self.register_variadic_function("log", 1, |args| match args {
+                [x] => crate::functions::log(*x, 0.0),
+                [x, base] => crate::functions::log_base(*x, *base),
+                _ => Real::NAN,
+            });
             let _ = self
                 .register_native_function("log10", 1, |args| crate::functions::log10(args[0], 0.0));
+            let _ = self
+                .register_native_function("log2", 1, |args| crate::functions::log2(args[0], 0.0));
+            let _ = self
+                .register_native_function("log1p", 1, |args| crate::functions::log1p(args[0], 0.0));
+            let _ = self
+                .register_native_function("expm1", 1, |args| crate::functions::expm1(args[0], 0.0));
             let _ = self
                 .register_native_function("pow", 2, |args| crate::functions::pow(args[0], args[1]));
             let _ = self
                 .register_native_function("^", 2, |args| crate::functions::pow(args[0], args[1]));
+            let _ = self
+                .register_native_function("**", 2, |args| crate::functions::pow(args[0], args[1]));
+            let _ = self.register_native_function("hypot", 2, |args| {
+                crate::functions::hypot(args[0], args[1])
+            });
+            let _ = self.register_native_function("copysign", 2, |args| {
+                crate::functions::copysign(args[0], args[1])
+            });
+            let _ = self.register_native_function("fma", 3, |args| {
+                crate::functions::fma(args[0], args[1], args[2])
+            });
             let _ =
                 self.register_native_function("sin", 1, |args| crate::functions::sin(args[0], 0.0));
             let _ = self
@@ -469,258 +1936,1498 @@ impl EvalContext {
                 .register_native_function("tanh", 1, |args| crate::functions::tanh(args[0], 0.0));
         }
 
-        // In test mode without libm, provide std library implementations
-        #[cfg(all(not(feature = "libm"), test))]
-        {
-            let _ = self.register_native_function("acos", 1, |args| args[0].acos());
-            let _ = self.register_native_function("asin", 1, |args| args[0].asin());
-            let _ = self.register_native_function("atan", 1, |args| args[0].atan());
-            let _ = self.register_native_function("atan2", 2, |args| args[0].atan2(args[1]));
-            let _ = self.register_native_function("ceil", 1, |args| args[0].ceil());
-            let _ = self.register_native_function("cos", 1, |args| args[0].cos());
-            let _ = self.register_native_function("cosh", 1, |args| args[0].cosh());
-            let _ = self.register_native_function("exp", 1, |args| args[0].exp());
-            let _ = self.register_native_function("floor", 1, |args| args[0].floor());
-            let _ = self.register_native_function("round", 1, |args| args[0].round());
-            let _ = self.register_native_function("ln", 1, |args| args[0].ln());
-            let _ = self.register_native_function("log", 1, |args| args[0].log10());
-            let _ = self.register_native_function("log10", 1, |args| args[0].log10());
-            let _ = self.register_native_function("pow", 2, |args| args[0].powf(args[1]));
-            let _ = self.register_native_function("^", 2, |args| args[0].powf(args[1]));
-            let _ = self.register_native_function("sin", 1, |args| args[0].sin());
-            let _ = self.register_native_function("sinh", 1, |args| args[0].sinh());
-            let _ = self.register_native_function("sqrt", 1, |args| args[0].sqrt());
-            let _ = self.register_native_function("tan", 1, |args| args[0].tan());
-            let _ = self.register_native_function("tanh", 1, |args| args[0].tanh());
+        // In test mode without libm, provide std library implementations
+        #[cfg(all(not(feature = "libm"), test))]
+        {
+            let _ = self.register_native_function("acos", 1, |args| args[0].acos());
+            let _ = self.register_native_function("asin", 1, |args| args[0].asin());
+            let _ = self.register_native_function("atan", 1, |args| args[0].atan());
+            let _ = self.register_native_function("atan2", 2, |args| args[0].atan2(args[1]));
+            let _ = self.register_native_function("ceil", 1, |args| args[0].ceil());
+            let _ = self.register_native_function("cos", 1, |args| args[0].cos());
+            let _ = self.register_native_function("cosh", 1, |args| args[0].cosh());
+            let _ = self.register_native_function("exp", 1, |args| args[0].exp());
+            let _ = self.register_native_function("floor", 1, |args| args[0].floor());
+            let _ = self.register_native_function("round", 1, |args| args[0].round());
+            let _ = self.register_native_function("ln", 1, |args| args[0].ln());
+            let _ = self.register_variadic_function("log", 1, |args| match args {
+                [x] => x.log10(),
+                [x, base] => x.log(*base),
+                _ => Real::NAN,
+            });
+            let _ = self.register_native_function("log10", 1, |args| args[0].log10());
+            let _ = self.register_native_function("log2", 1, |args| args[0].log2());
+            let _ = self.register_native_function("log1p", 1, |args| args[0].ln_1p());
+            let _ = self.register_native_function("expm1", 1, |args| args[0].exp_m1());
+            let _ = self.register_native_function("pow", 2, |args| args[0].powf(args[1]));
+            let _ = self.register_native_function("^", 2, |args| args[0].powf(args[1]));
+            let _ = self.register_native_function("**", 2, |args| args[0].powf(args[1]));
+            let _ = self.register_native_function("hypot", 2, |args| args[0].hypot(args[1]));
+            let _ = self.register_native_function("copysign", 2, |args| args[0].copysign(args[1]));
+            let _ =
+                self.register_native_function("fma", 3, |args| args[0].mul_add(args[1], args[2]));
+            let _ = self.register_native_function("sin", 1, |args| args[0].sin());
+            let _ = self.register_native_function("sinh", 1, |args| args[0].sinh());
+            let _ = self.register_native_function("sqrt", 1, |args| args[0].sqrt());
+            let _ = self.register_native_function("tan", 1, |args| args[0].tan());
+            let _ = self.register_native_function("tanh", 1, |args| args[0].tanh());
+        }
+
+        // In non-test no_std mode without libm, we don't register advanced math functions
+        // Users must register their own implementations if needed
+
+        // Approximations chosen per call
+        #[cfg(feature = "fast-math")]
+        crate::fastmath::register_builtins(self);
+
+        // Software arithmetic replaces the operators computed by the FPU
+        #[cfg(feature = "softfloat")]
+        crate::softfloat::register_builtins(self);
+
+        // CMSIS-DSP implementations replace the ones above
+        #[cfg(feature = "cmsis-dsp")]
+        crate::cmsis::register_functions(self);
+    }
+
+    /// Declares whether the native function `name` of this context is pure.
+    ///
+    /// Functions registered with [`register_native_function`](Self::register_native_function)
+    /// or [`register_variadic_function`](Self::register_variadic_function) start out
+    /// impure, and the built-ins other than `rand`, `rand_range` and `randn` pure.
+    /// Calls of pure functions with constant arguments may be folded by
+    /// [`specialize`](crate::simplify::specialize), and expressions calling only pure
+    /// functions may be memoized by batches; functions that read hardware, the clock
+    /// or other changing state must stay impure.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use exp_rs::context::EvalContext;
+    ///
+    /// let mut ctx = EvalContext::new();
+    /// ctx.register_native_function("adc_read", 1, |_| 0.5).unwrap();
+    /// ctx.register_native_function("volts", 1, |args| args[0] * 3.3).unwrap();
+    /// ctx.set_function_pure("volts", true).unwrap();
+    ///
+    /// assert!(!ctx.is_function_pure("adc_read"));
+    /// assert!(ctx.is_function_pure("volts"));
+    /// assert!(ctx.is_function_pure("sin"));
+    /// assert!(!ctx.is_function_pure("rand"));
+    /// ```
+    pub fn set_function_pure(
+        &mut self,
+        name: &str,
+        pure: bool,
+    ) -> Result<(), crate::error::ExprError> {
+        let key = name.try_into_function_name()?;
+        match Rc::make_mut(&mut self.native_functions).get_mut(&key) {
+            Some(function) => {
+                function.pure = pure;
+                Ok(())
+            }
+            None => Err(crate::error::ExprError::UnknownFunction {
+                name: name.to_string(),
+            }),
+        }
+    }
+
+    /// Returns whether `name` resolves to a native function declared pure, in this
+    /// context or its parents.
+    pub fn is_function_pure(&self, name: &str) -> bool {
+        self.get_native_function(name).is_some_and(|f| f.pure)
+    }
+
+    /// Sets a check of the arguments of calls to the native function `name` of this
+    /// context, run by [`validate_calls`](Self::validate_calls).
+    ///
+    /// The validator receives the argument expressions, so it can require an argument
+    /// count range or constant arguments within bounds, and returns a message if the
+    /// call is invalid. Validating expressions when a configuration is loaded reports
+    /// such mistakes then, rather than when a formula is first evaluated in the field.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use exp_rs::context::EvalContext;
+    /// use exp_rs::engine::parse_expression;
+    /// use exp_rs::error::ExprError;
+    /// use exp_rs::types::AstExpr;
+    /// use bumpalo::Bump;
+    ///
+    /// let mut ctx = EvalContext::new();
+    /// ctx.register_variadic_function("filter", 2, |args| args[0]).unwrap();
+    /// ctx.set_function_validator("filter", |args: &[AstExpr<'_>]| match args {
+    ///     [_, AstExpr::Constant(order)] if (1.0..=4.0).contains(order) => Ok(()),
+    ///     [_, _] => Err("the order must be a constant from 1 to 4".to_string()),
+    ///     _ => Err("expected filter(x, order)".to_string()),
+    /// })
+    /// .unwrap();
+    ///
+    /// let arena = Bump::new();
+    /// let ok = parse_expression("filter(x, 2) + 1", &arena).unwrap();
+    /// assert!(ctx.validate_calls(&ok).is_ok());
+    /// let bad = parse_expression("1 + filter(x, n)", &arena).unwrap();
+    /// assert!(matches!(ctx.validate_calls(&bad), Err(ExprError::InvalidArguments { .. })));
+    /// ```
+    pub fn set_function_validator<F>(
+        &mut self,
+        name: &str,
+        validator: F,
+    ) -> Result<(), crate::error::ExprError>
+    where
+        F: Fn(&[AstExpr<'_>]) -> Result<(), String> + 'static,
+    {
+        let key = name.try_into_function_name()?;
+        match Rc::make_mut(&mut self.native_functions).get_mut(&key) {
+            Some(function) => {
+                function.validator = Some(Rc::new(validator));
+                Ok(())
+            }
+            None => Err(crate::error::ExprError::UnknownFunction {
+                name: name.to_string(),
+            }),
+        }
+    }
+
+    /// Sets the single-precision implementation of the native function `name` of this
+    /// context, used for the `f32` side of [`shadow`](crate::shadow) evaluation.
+    ///
+    /// Without one, the `f32` side calls the normal implementation with arguments
+    /// rounded to `f32` and rounds its result. Registering the implementation the
+    /// firmware uses, such as `sinf` instead of `sin`, makes the comparison reflect
+    /// the firmware's own rounding.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use exp_rs::context::EvalContext;
+    /// use exp_rs::engine::parse_expression;
+    /// use exp_rs::shadow::eval_shadow;
+    /// use bumpalo::Bump;
+    ///
+    /// let mut ctx = EvalContext::new();
+    /// ctx.register_native_function("gain", 1, |args| args[0] * 1.1).unwrap();
+    /// ctx.set_single_precision_function("gain", |args| args[0] * 1.1f32).unwrap();
+    ///
+    /// let arena = Bump::new();
+    /// let ast = parse_expression("gain(3)", &arena).unwrap();
+    /// let result = eval_shadow(&ast, Some(&ctx)).unwrap();
+    /// assert_eq!(result.single, 3.0f32 * 1.1f32);
+    /// assert!(result.relative_divergence < 1e-6);
+    /// ```
+    #[cfg(all(feature = "shadow", not(feature = "f32")))]
+    pub fn set_single_precision_function<F>(
+        &mut self,
+        name: &str,
+        implementation: F,
+    ) -> Result<(), crate::error::ExprError>
+    where
+        F: Fn(&[f32]) -> f32 + 'static,
+    {
+        let key = name.try_into_function_name()?;
+        match Rc::make_mut(&mut self.native_functions).get_mut(&key) {
+            Some(function) => {
+                function.single_precision = Some(Rc::new(implementation));
+                Ok(())
+            }
+            None => Err(crate::error::ExprError::UnknownFunction {
+                name: name.to_string(),
+            }),
+        }
+    }
+
+    /// Checks the calls in `expr` to native functions of this context and its parents
+    /// without evaluating it: their argument counts, and their validators (see
+    /// [`set_function_validator`](Self::set_function_validator)).
+    ///
+    /// Calls of functions the context does not define are not checked, since a
+    /// function resolver or an expression function may provide them.
+    pub fn validate_calls(&self, expr: &AstExpr<'_>) -> Result<(), crate::error::ExprError> {
+        crate::visit::try_for_each_call(expr, &mut |name, args| {
+            self.validate_native_call(name, args)
+        })
+    }
+
+    /// Checks one call against the native function `name`, if there is one.
+    pub(crate) fn validate_native_call(
+        &self,
+        name: &str,
+        args: &[AstExpr<'_>],
+    ) -> Result<(), crate::error::ExprError> {
+        let Some(function) = self.get_native_function(name) else {
+            return Ok(());
+        };
+        if !function.accepts(args.len()) {
+            return Err(crate::error::ExprError::InvalidFunctionCall {
+                name: name.to_string(),
+                expected: function.arity,
+                found: args.len(),
+            });
+        }
+        match &function.validator {
+            Some(validator) => {
+                validator(args).map_err(|message| crate::error::ExprError::InvalidArguments {
+                    name: name.to_string(),
+                    message,
+                })
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Register a native function with the context.
+    ///
+    /// # Overriding Built-ins
+    ///
+    /// If a function with the same name as a built-in is registered, the user-defined function
+    /// will take precedence over the built-in. This allows users to override any built-in math
+    /// function at runtime.
+    ///
+    /// # Disabling Built-ins
+    ///
+    /// If the `libm` feature is not enabled, built-in math functions are not available,
+    /// and users must register their own implementations for all required functions.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use exp_rs::EvalContext;
+    /// let mut ctx = EvalContext::new();
+    /// // Override the "sin" function
+    /// ctx.register_native_function("sin", 1, |args| 42.0);
+    /// ```
+
+    pub fn get_variable(&self, name: &str) -> Option<Real> {
+        if let Ok(key) = name.try_into_heapless() {
+            if let Some(val) = self.variables.get(&key) {
+                return Some(*val);
+            }
+        }
+        if let Some(value) = self.own_typed_value(name) {
+            return Some(value.to_real());
+        }
+
+        if let Some(parent) = &self.parent {
+            parent.get_variable(name)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_constant(&self, name: &str) -> Option<Real> {
+        if let Ok(key) = name.try_into_heapless() {
+            if let Some(val) = self.constants.get(&key) {
+                return Some(*val);
+            }
+        }
+
+        if let Some(parent) = &self.parent {
+            parent.get_constant(name)
+        } else {
+            None
+        }
+    }
+
+    /// Registers caller-owned memory as an array, replacing any view with the same name.
+    ///
+    /// Arrays in [`arrays`](Self::arrays) take precedence over views with the same name.
+    pub fn set_array_view(
+        &mut self,
+        name: &str,
+        view: ArrayView,
+    ) -> Result<(), crate::error::ExprError> {
+        let key = name.try_into_heapless()?;
+        match self.array_views.iter_mut().find(|(n, _)| *n == key) {
+            Some(entry) => entry.1 = view,
+            None => self.array_views.push((key, view)),
+        }
+        Ok(())
+    }
+
+    /// Removes an array view, returning whether it existed.
+    pub fn remove_array_view(&mut self, name: &str) -> bool {
+        let before = self.array_views.len();
+        self.array_views.retain(|(n, _)| n.as_str() != name);
+        self.array_views.len() != before
+    }
+
+    /// Looks up an array view, falling back to the parent chain.
+    pub fn get_array_view(&self, name: &str) -> Option<ArrayView> {
+        match self.array_views.iter().find(|(n, _)| n.as_str() == name) {
+            Some((_, view)) => Some(*view),
+            None => self.parent.as_ref().and_then(|p| p.get_array_view(name)),
+        }
+    }
+
+    /// Sets a matrix or vector used by [`eval_linalg`](crate::linalg::eval_linalg)
+    /// (requires the `linalg` feature).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use exp_rs::context::EvalContext;
+    /// use exp_rs::linalg::Matrix;
+    ///
+    /// let mut ctx = EvalContext::new();
+    /// ctx.set_matrix("v", Matrix::vector(&[3.0, 4.0]).unwrap()).unwrap();
+    /// assert_eq!(ctx.get_matrix("v").unwrap().rows(), 2);
+    /// ```
+    #[cfg(feature = "linalg")]
+    pub fn set_matrix(
+        &mut self,
+        name: &str,
+        matrix: crate::linalg::Matrix,
+    ) -> Result<(), crate::error::ExprError> {
+        let key = name.try_into_heapless()?;
+        match self.matrices.iter_mut().find(|(n, _)| *n == key) {
+            Some(entry) => entry.1 = matrix,
+            None => self.matrices.push((key, matrix)),
+        }
+        Ok(())
+    }
+
+    /// Removes a matrix, returning whether it existed.
+    #[cfg(feature = "linalg")]
+    pub fn remove_matrix(&mut self, name: &str) -> bool {
+        let before = self.matrices.len();
+        self.matrices.retain(|(n, _)| n.as_str() != name);
+        self.matrices.len() != before
+    }
+
+    /// Looks up a matrix, falling back to the parent chain.
+    #[cfg(feature = "linalg")]
+    pub fn get_matrix(&self, name: &str) -> Option<crate::linalg::Matrix> {
+        match self.matrices.iter().find(|(n, _)| n.as_str() == name) {
+            Some((_, matrix)) => Some(*matrix),
+            None => self.parent.as_ref().and_then(|p| p.get_matrix(name)),
+        }
+    }
+
+    /// Sets an array, read in expressions as `name[index]`.
+    ///
+    /// Arrays belonging to an object are named by their path, so `point.coords[1]`
+    /// reads the array set as `point.coords`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use exp_rs::context::EvalContext;
+    /// use exp_rs::engine::interp;
+    /// use std::rc::Rc;
+    ///
+    /// let mut ctx = EvalContext::new();
+    /// ctx.set_array("point.coords", vec![1.0, 2.0, 3.0]).unwrap();
+    /// ctx.set_path("point.scale", 10.0).unwrap();
+    /// let ctx = Rc::new(ctx);
+    /// assert_eq!(interp("point.coords[1] * point.scale", Some(ctx.clone())).unwrap(), 20.0);
+    /// assert_eq!(interp("max(point.coords)", Some(ctx)).unwrap(), 3.0);
+    /// ```
+    pub fn set_array(
+        &mut self,
+        name: &str,
+        values: alloc::vec::Vec<Real>,
+    ) -> Result<Option<alloc::vec::Vec<Real>>, crate::error::ExprError> {
+        let key = name.try_into_heapless()?;
+        self.arrays
+            .insert(key, values)
+            .map_err(|_| crate::error::ExprError::CapacityExceeded {
+                container: "arrays",
+            })
+    }
+
+    pub fn get_array(&self, name: &str) -> Option<&alloc::vec::Vec<crate::Real>> {
+        if let Ok(key) = name.try_into_heapless() {
+            if let Some(arr) = self.arrays.get(&key) {
+                return Some(arr);
+            }
+        }
+
+        if let Some(parent) = &self.parent {
+            parent.get_array(name)
+        } else {
+            None
+        }
+    }
+
+    /// Helper method to set an attribute value on an object
+    pub fn set_attribute(
+        &mut self,
+        object_name: &str,
+        attr_name: &str,
+        value: Real,
+    ) -> Result<Option<Real>, crate::error::ExprError> {
+        let obj_key = object_name.try_into_heapless()?;
+        let attr_key = attr_name.try_into_heapless()?;
+
+        // Get or create the object's attribute map
+        if !self.attributes.contains_key(&obj_key) {
+            let attr_map = crate::types::AttributeKeyMap::new();
+            self.attributes
+                .insert(obj_key.clone(), attr_map)
+                .map_err(|_| crate::error::ExprError::CapacityExceeded {
+                    container: "attributes",
+                })?;
+        }
+
+        // Get mutable reference to the attribute map and insert the value
+        if let Some(attr_map) = self.attributes.get_mut(&obj_key) {
+            attr_map.insert(attr_key, value).map_err(|_| {
+                crate::error::ExprError::CapacityExceeded {
+                    container: "object attributes",
+                }
+            })
+        } else {
+            unreachable!("Just inserted the object")
+        }
+    }
+
+    pub fn get_attribute_map(&self, base: &str) -> Option<&crate::types::AttributeKeyMap> {
+        if let Ok(key) = base.try_into_heapless() {
+            if let Some(attr_map) = self.attributes.get(&key) {
+                return Some(attr_map);
+            }
+        }
+
+        if let Some(parent) = &self.parent {
+            parent.get_attribute_map(base)
+        } else {
+            None
+        }
+    }
+
+    /// Reads `base.attr` from the attribute maps, falling back to the parent chain for
+    /// attributes this context does not set.
+    pub fn get_attribute(&self, base: &str, attr: &str) -> Option<Real> {
+        let own = match (base.try_into_heapless(), attr.try_into_heapless()) {
+            (Ok(base_key), Ok(attr_key)) => self
+                .attributes
+                .get(&base_key)
+                .and_then(|attrs| attrs.get(&attr_key))
+                .copied(),
+            _ => None,
+        };
+        own.or_else(|| self.parent.as_ref()?.get_attribute(base, attr))
+    }
+
+    /// Sets the value at a hierarchical path such as `drive.motor1.current`.
+    ///
+    /// The last segment is an attribute of the object named by the rest of the path,
+    /// so the value is read back in expressions as `drive.motor1.current`. A path
+    /// without dots, including a namespaced name such as `motor::kp`, sets a variable.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use exp_rs::context::EvalContext;
+    /// use exp_rs::engine::interp;
+    /// use std::rc::Rc;
+    ///
+    /// let mut ctx = EvalContext::new();
+    /// ctx.set_path("drive.motor1.current", 2.5).unwrap();
+    /// ctx.set_path("drive.motor2.current", 1.5).unwrap();
+    /// ctx.set_path("limits::max_current", 5.0).unwrap();
+    /// let total = "drive.motor1.current + drive.motor2.current";
+    /// let ctx = Rc::new(ctx);
+    /// assert_eq!(interp(total, Some(ctx.clone())).unwrap(), 4.0);
+    /// assert_eq!(interp("limits::max_current", Some(ctx)).unwrap(), 5.0);
+    /// ```
+    pub fn set_path(&mut self, path: &str, value: Real) -> Result<(), crate::error::ExprError> {
+        match path.rsplit_once('.') {
+            Some((object, attr)) => self.set_attribute(object, attr, value).map(|_| ()),
+            None => self.set_parameter(path, value).map(|_| ()),
+        }
+    }
+
+    /// Returns the value at a hierarchical path as an expression would read it.
+    ///
+    /// A dotted path is looked up as an attribute of its object, then through the
+    /// object's [`AttributeProvider`], and finally as a variable named by the whole
+    /// path, so flat names like `sensor.ch1.raw` set with `set_parameter` work too.
+    pub fn get_path(&self, path: &str) -> Option<Real> {
+        match path.rsplit_once('.') {
+            Some((object, attr)) => self
+                .get_attribute(object, attr)
+                .or_else(|| self.get_object_attribute(object, attr))
+                .or_else(|| self.get_variable(path)),
+            None => self.get_variable(path),
+        }
+    }
+
+    pub fn get_native_function(&self, name: &str) -> Option<&crate::types::NativeFunction> {
+        if let Ok(key) = name.try_into_function_name() {
+            if let Some(f) = self.native_functions.get(&key) {
+                return Some(f);
+            }
+        }
+
+        if let Some(parent) = &self.parent {
+            parent.get_native_function(name)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the native function `name` of this context or its ancestors, if the
+    /// function policy permits calling it.
+    ///
+    /// Fails with `ExprError::FunctionNotPermitted` if the policy does not permit the
+    /// call, and with `ExprError::UnknownFunction` if there is no such native function,
+    /// in which case the function resolver may still handle the call.
+    pub fn permitted_native_function(
+        &self,
+        name: &str,
+    ) -> Result<&crate::types::NativeFunction, crate::error::ExprError> {
+        self.check_function_permitted(name)?;
+        self.get_native_function(name)
+            .ok_or_else(|| crate::error::ExprError::UnknownFunction {
+                name: name.to_string(),
+            })
+    }
+
+    /// Calls the native function `name` with `args`, or dispatches the call through the
+    /// function resolver if there is none, as the evaluators besides `EvalEngine` do.
+    #[cfg(any(test, feature = "complex", feature = "linalg", feature = "autodiff"))]
+    pub(crate) fn call_function(
+        &self,
+        name: &str,
+        args: &[Real],
+    ) -> Result<Real, crate::error::ExprError> {
+        use crate::error::ExprError;
+
+        match self.permitted_native_function(name) {
+            Ok(func) if !func.accepts(args.len()) => Err(ExprError::InvalidFunctionCall {
+                name: name.to_string(),
+                expected: func.arity,
+                found: args.len(),
+            }),
+            Ok(func) => Ok((func.implementation)(args)),
+            Err(ExprError::UnknownFunction { .. }) => {
+                self.resolve_function(name, args)
+                    .ok_or_else(|| ExprError::UnknownFunction {
+                        name: name.to_string(),
+                    })
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Get a list of all native function names in this context (including parent contexts)
+    pub fn list_native_functions(&self) -> Vec<String> {
+        let mut functions = Vec::new();
+        let mut seen = alloc::collections::BTreeSet::new();
+
+        // Collect from current context
+        for (name, _) in self.native_functions.iter() {
+            let name_str = name.to_string();
+            if seen.insert(name_str.clone()) {
+                functions.push(name_str);
+            }
+        }
+
+        // Collect from parent contexts
+        if let Some(parent) = &self.parent {
+            for name in parent.list_native_functions() {
+                if seen.insert(name.clone()) {
+                    functions.push(name);
+                }
+            }
+        }
+
+        functions.sort();
+        functions
+    }
+
+    /// Removes a native function registered in this context, returning whether it existed.
+    ///
+    /// Built-in functions can be removed as well. A function of the same name in a parent
+    /// context stays visible.
+    pub fn unregister_function(&mut self, name: &str) -> bool {
+        let Ok(key) = name.try_into_function_name() else {
+            return false;
+        };
+        if !self.native_functions.contains_key(&key) {
+            return false;
+        }
+        Rc::make_mut(&mut self.native_functions)
+            .remove(&key)
+            .is_some()
+    }
+
+    /// Names of all functions callable with this context (including parent contexts),
+    /// sorted.
+    ///
+    /// Expression functions belong to an [`Expression`](crate::expression::Expression)
+    /// batch and are not listed.
+    pub fn list_functions(&self) -> Vec<String> {
+        self.list_native_functions()
+    }
+
+    /// Names of all variables in this context and its parents, sorted.
+    pub fn list_variables(&self) -> Vec<String> {
+        let mut variables: Vec<String> = self
+            .variables
+            .keys()
+            .chain(self.typed_values.keys())
+            .map(|k| k.to_string())
+            .collect();
+        if let Some(parent) = &self.parent {
+            variables.extend(parent.list_variables());
+        }
+        variables.sort();
+        variables.dedup();
+        variables
+    }
+
+    /// Returns the arity and description of a function, looking through parent contexts.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use exp_rs::EvalContext;
+    ///
+    /// let ctx = EvalContext::new();
+    /// let signature = ctx.function_signature("atan2").unwrap();
+    /// assert_eq!(signature.arity, 2);
+    /// assert_eq!(signature.to_string(), "atan2(x1, x2)");
+    /// assert_eq!(ctx.function_signature("max").unwrap().to_string(), "max(x1, ...)");
+    /// ```
+    pub fn function_signature(&self, name: &str) -> Option<crate::types::FunctionSignature> {
+        self.get_native_function(name).map(Into::into)
+    }
+
+    /// Get a list of all expression function names in this context (including parent contexts)
+    pub fn list_expression_functions(&self) -> Vec<String> {
+        let mut functions = Vec::new();
+        let mut seen = alloc::collections::BTreeSet::new();
+
+        // Collect from current context
+        // Expression functions no longer exist in context
+
+        // Collect from parent contexts
+        if let Some(parent) = &self.parent {
+            for name in parent.list_expression_functions() {
+                if seen.insert(name.clone()) {
+                    functions.push(name);
+                }
+            }
         }
 
-        // In non-test no_std mode without libm, we don't register advanced math functions
-        // Users must register their own implementations if needed
+        functions.sort();
+        functions
     }
+}
 
-    /// Register a native function with the context.
+impl Clone for EvalContext {
+    fn clone(&self) -> Self {
+        Self {
+            variables: self.variables.clone(),
+            constants: self.constants.clone(),
+            arrays: self.arrays.clone(),
+            attributes: self.attributes.clone(),
+            nested_arrays: self.nested_arrays.clone(),
+            native_functions: self.native_functions.clone(),
+            parent: self.parent.clone(),
+            variable_resolver: self.variable_resolver.clone(),
+            function_resolver: self.function_resolver.clone(),
+            units: self.units.clone(),
+            objects: self.objects.clone(),
+            array_views: self.array_views.clone(),
+            histories: self.histories.clone(),
+            #[cfg(feature = "linalg")]
+            matrices: self.matrices.clone(),
+            math_config: self.math_config,
+            max_eval_depth: self.max_eval_depth,
+            stack_spill: self.stack_spill,
+            missing_propagation: self.missing_propagation,
+            rng: self.rng.clone(),
+            clock: self.clock.clone(),
+            function_policy: self.function_policy.clone(),
+            logger: self.logger.clone(),
+            watchdog: self.watchdog.clone(),
+            deprecations: self.deprecations.clone(),
+            declarations: self.declarations.clone(),
+            strict_declarations: self.strict_declarations,
+            typed_values: self.typed_values.clone(),
+        }
+    }
+}
+
+impl Default for EvalContext {
+    /// Creates a new EvalContext with default values and math functions registered.
+    /// This ensures that EvalContext::default() behaves the same as
+    fn default() -> Self {
+        EvalContext::new()
+        // let mut ctx = Self {
+        //     variables: HashMap::new(),
+        //     constants: HashMap::new(),
+        //     arrays: HashMap::new(),
+        //     attributes: HashMap::new(),
+        //     nested_arrays: HashMap::new(),
+        //     function_registry: Rc::new(FunctionRegistry::default()),
+        //     parent: None,
+        //     ast_cache: None,
+        // };
+        //
+        // // Register default math functions, same as in new()
+        // ctx.register_default_math_functions();
+        //
+        // ctx
+    }
+}
+
+// Helper trait removed - heapless containers support Clone directly
+
+impl EvalContext {
+    /// Opens a scope on top of this context for temporary variables.
     ///
-    /// # Overriding Built-ins
+    /// The scope starts empty and reads everything else (variables, functions, arrays,
+    /// settings) through this context, so nothing is copied. Values set in the scope
+    /// shadow those of this context, which is left unchanged; the scope is discarded
+    /// when the returned [`ScopedContext`] is dropped or popped.
     ///
-    /// If a function with the same name as a built-in is registered, the user-defined function
-    /// will take precedence over the built-in. This allows users to override any built-in math
-    /// function at runtime.
+    /// # Examples
     ///
-    /// # Disabling Built-ins
+    /// ```
+    /// use exp_rs::EvalContext;
+    /// use exp_rs::engine::interp;
+    /// use std::rc::Rc;
     ///
-    /// If the `libm` feature is not enabled, built-in math functions are not available,
-    /// and users must register their own implementations for all required functions.
+    /// let mut ctx = EvalContext::new();
+    /// ctx.set_parameter("gain", 2.0).unwrap();
+    /// ctx.set_parameter("x", 0.0).unwrap();
+    /// let ctx = Rc::new(ctx);
     ///
-    /// # Example
+    /// for sample in [1.0, 2.0, 3.0] {
+    ///     let mut scope = ctx.push_scope();
+    ///     scope.set_parameter("x", sample).unwrap();
+    ///     assert_eq!(interp("x * gain", Some(scope.context())).unwrap(), sample * 2.0);
+    /// }
+    /// assert_eq!(interp("x", Some(ctx)).unwrap(), 0.0);
+    /// ```
+    pub fn push_scope(self: &Rc<Self>) -> ScopedContext {
+        ScopedContext {
+            ctx: Rc::new(self.overlay()),
+        }
+    }
+
+    /// Creates a copy-on-write overlay sharing this context as its base.
+    ///
+    /// Unlike [`clone`](Clone::clone), which copies every variable, constant and array,
+    /// the overlay holds only a reference to the base and the values set on it. Reads
+    /// fall through to the base, writes stay in the overlay and shadow the base's value
+    /// of the same name. This suits many tasks sharing one large base context, each with
+    /// a few parameters of its own.
+    ///
+    /// The overlay shares the base's random number generator and clock.
+    ///
+    /// # Examples
     ///
     /// ```
-    /// # use exp_rs::EvalContext;
-    /// let mut ctx = EvalContext::new();
-    /// // Override the "sin" function
-    /// ctx.register_native_function("sin", 1, |args| 42.0);
+    /// use exp_rs::EvalContext;
+    /// use exp_rs::engine::interp;
+    /// use exp_rs::types::TryIntoHeaplessString;
+    /// use std::rc::Rc;
+    ///
+    /// let mut base = EvalContext::new();
+    /// base.arrays
+    ///     .insert("lut".try_into_heapless().unwrap(), vec![0.0; 4096])
+    ///     .unwrap();
+    /// base.set_parameter("gain", 2.0).unwrap();
+    /// let base = Rc::new(base);
+    ///
+    /// let tasks: Vec<Rc<EvalContext>> = (0..50)
+    ///     .map(|id| {
+    ///         let mut task = base.overlay();
+    ///         task.set_parameter("id", id as f64).unwrap();
+    ///         Rc::new(task)
+    ///     })
+    ///     .collect();
+    ///
+    /// assert_eq!(interp("id * gain + lut[7]", Some(tasks[21].clone())).unwrap(), 42.0);
     /// ```
+    pub fn overlay(self: &Rc<Self>) -> Self {
+        let mut overlay = Self::empty();
+        overlay.parent = Some(self.clone());
+        overlay.math_config = self.math_config;
+        overlay.rng = self.rng.clone();
+        overlay
+    }
+}
 
-    pub fn get_variable(&self, name: &str) -> Option<Real> {
-        if let Ok(key) = name.try_into_heapless() {
-            if let Some(val) = self.variables.get(&key) {
-                return Some(*val);
+impl EvalContext {
+    /// Starts an [`EvalContextBuilder`] for a context with the default math functions.
+    pub fn builder() -> EvalContextBuilder {
+        EvalContextBuilder::default()
+    }
+}
+
+/// Builder for an [`EvalContext`], set up with chained calls.
+///
+/// Invalid names are not reported by the `with_*` calls but by [`build`](Self::build),
+/// which returns the first error. Before inserting anything, `build` also checks that
+/// the entries fit the fixed capacities of the context's containers (the
+/// `EXP_RS_MAX_*` limits, which do not apply with the `std` feature), so setup fails
+/// as a whole with `ExprError::CapacityExceeded` instead of leaving a partly filled
+/// context. Setting a name twice keeps the last value.
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::EvalContext;
+/// use exp_rs::engine::interp;
+/// use exp_rs::error::ExprError;
+/// use std::rc::Rc;
+///
+/// let ctx = EvalContext::builder()
+///     .with_parameter("gain", 2.0)
+///     .with_constant("offset", 0.5)
+///     .with_array("lut", [1.0, 4.0, 9.0])
+///     .with_function("clamp01", 1, |args| args[0].clamp(0.0, 1.0))
+///     .build()
+///     .unwrap();
+/// let result = interp("clamp01(lut[1] * gain + offset)", Some(Rc::new(ctx))).unwrap();
+/// assert_eq!(result, 1.0);
+///
+/// let error = EvalContext::builder()
+///     .with_parameter("a_name_longer_than_thirty_two_bytes", 1.0)
+///     .with_parameter("x", 1.0)
+///     .build();
+/// assert!(matches!(error, Err(ExprError::StringTooLong { .. })));
+/// ```
+#[derive(Default)]
+pub struct EvalContextBuilder {
+    parameters: Vec<(crate::types::HString, Real)>,
+    constants: Vec<(crate::types::HString, Real)>,
+    arrays: Vec<(crate::types::HString, Vec<Real>)>,
+    functions: Vec<(crate::types::FunctionName, NativeImplementation)>,
+    error: Option<crate::error::ExprError>,
+}
+
+/// Arity and implementation of a native function added to an [`EvalContextBuilder`]
+type NativeImplementation = (usize, Rc<dyn Fn(&[Real]) -> Real>);
+
+/// Name of an attribute relative to the registered object owning it, such as `axis.x`
+/// of `imu.axis.x`; it is no longer than an object path and an attribute together
+type AttributePath = heapless::String<{ 2 * crate::types::EXP_RS_MAX_KEY_LENGTH + 1 }>;
+
+/// Adds `value` under `key`, replacing an earlier entry of the same name.
+fn upsert<K: PartialEq, V>(entries: &mut Vec<(K, V)>, key: K, value: V) {
+    match entries.iter_mut().find(|(k, _)| *k == key) {
+        Some(entry) => entry.1 = value,
+        None => entries.push((key, value)),
+    }
+}
+
+/// Fails unless `added` more entries fit a container holding `len` of `capacity`.
+fn check_capacity(
+    container: &'static str,
+    len: usize,
+    added: usize,
+    capacity: usize,
+) -> Result<(), crate::error::ExprError> {
+    if cfg!(not(feature = "std")) && len + added > capacity {
+        return Err(crate::error::ExprError::CapacityExceeded { container });
+    }
+    Ok(())
+}
+
+impl EvalContextBuilder {
+    /// Keeps the first error, to be returned by `build`.
+    fn name<K>(&mut self, key: Result<K, crate::error::ExprError>) -> Option<K> {
+        match key {
+            Ok(key) => Some(key),
+            Err(error) => {
+                self.error.get_or_insert(error);
+                None
             }
         }
+    }
 
-        if let Some(parent) = &self.parent {
-            parent.get_variable(name)
-        } else {
-            None
+    /// Sets a variable, as [`EvalContext::set_parameter`].
+    pub fn with_parameter(mut self, name: &str, value: Real) -> Self {
+        if let Some(key) = self.name(name.try_into_heapless()) {
+            upsert(&mut self.parameters, key, value);
         }
+        self
     }
 
-    pub fn get_constant(&self, name: &str) -> Option<Real> {
-        if let Ok(key) = name.try_into_heapless() {
-            if let Some(val) = self.constants.get(&key) {
-                return Some(*val);
-            }
+    /// Sets a constant.
+    pub fn with_constant(mut self, name: &str, value: Real) -> Self {
+        if let Some(key) = self.name(name.try_into_heapless()) {
+            upsert(&mut self.constants, key, value);
         }
+        self
+    }
 
-        if let Some(parent) = &self.parent {
-            parent.get_constant(name)
-        } else {
-            None
+    /// Sets an array, as [`EvalContext::set_array`].
+    pub fn with_array(mut self, name: &str, values: impl Into<Vec<Real>>) -> Self {
+        if let Some(key) = self.name(name.try_into_heapless()) {
+            upsert(&mut self.arrays, key, values.into());
         }
+        self
     }
 
-    pub fn get_array(&self, name: &str) -> Option<&alloc::vec::Vec<crate::Real>> {
-        if let Ok(key) = name.try_into_heapless() {
-            if let Some(arr) = self.arrays.get(&key) {
-                return Some(arr);
-            }
+    /// Registers a native function, as [`EvalContext::register_native_function`].
+    pub fn with_function<F>(mut self, name: &str, arity: usize, implementation: F) -> Self
+    where
+        F: Fn(&[Real]) -> Real + 'static,
+    {
+        if let Some(key) = self.name(name.try_into_function_name()) {
+            upsert(&mut self.functions, key, (arity, Rc::new(implementation)));
         }
+        self
+    }
 
-        if let Some(parent) = &self.parent {
-            parent.get_array(name)
-        } else {
-            None
+    /// Builds the context, or returns the first error of the calls above.
+    pub fn build(self) -> Result<EvalContext, crate::error::ExprError> {
+        use crate::types::{
+            EXP_RS_MAX_ARRAYS, EXP_RS_MAX_CONSTANTS, EXP_RS_MAX_NATIVE_FUNCTIONS,
+            EXP_RS_MAX_VARIABLES,
+        };
+
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let mut ctx = EvalContext::new();
+        let new_functions = self
+            .functions
+            .iter()
+            .filter(|(name, _)| !ctx.native_functions.contains_key(name))
+            .count();
+        check_capacity(
+            "variables",
+            ctx.variables.len(),
+            self.parameters.len(),
+            EXP_RS_MAX_VARIABLES,
+        )?;
+        check_capacity(
+            "constants",
+            ctx.constants.len(),
+            self.constants.len(),
+            EXP_RS_MAX_CONSTANTS,
+        )?;
+        check_capacity(
+            "arrays",
+            ctx.arrays.len(),
+            self.arrays.len(),
+            EXP_RS_MAX_ARRAYS,
+        )?;
+        check_capacity(
+            "native_functions",
+            ctx.native_functions.len(),
+            new_functions,
+            EXP_RS_MAX_NATIVE_FUNCTIONS,
+        )?;
+
+        for (name, value) in self.parameters {
+            ctx.set_parameter(&name, value)?;
+        }
+        for (name, value) in self.constants {
+            ctx.constants.insert(name, value).map_err(|_| {
+                crate::error::ExprError::CapacityExceeded {
+                    container: "constants",
+                }
+            })?;
         }
+        for (name, values) in self.arrays {
+            ctx.set_array(&name, values)?;
+        }
+        for (name, (arity, implementation)) in self.functions {
+            ctx.register_native_function(&name, arity, move |args| implementation(args))?;
+        }
+        Ok(ctx)
     }
+}
 
-    /// Helper method to set an attribute value on an object
-    pub fn set_attribute(
+/// The changes turning the variables of one context into those of another, from
+/// [`EvalContext::diff`], to be applied with [`EvalContext::apply`].
+///
+/// A host keeping a copy of the variables last sent to a device ships only the patch,
+/// instead of the whole variable set on every update. With the `serde` feature the
+/// patch can be serialized for the link.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ContextPatch {
+    /// Variables added or changed, with their new values
+    pub set: Vec<(crate::types::HString, Real)>,
+    /// Variables removed
+    pub removed: Vec<crate::types::HString>,
+}
+
+impl ContextPatch {
+    /// Returns whether the patch changes nothing.
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl EvalContext {
+    /// Returns the patch turning the variables of this context into those of `other`.
+    ///
+    /// Only the variables stored in the contexts themselves are compared, not those of
+    /// their parents. Values are compared by their bits, so a NaN that stays NaN is not a
+    /// change and `0.0` becoming `-0.0` is. Both lists of the patch are sorted by name.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use exp_rs::EvalContext;
+    ///
+    /// let mut sent = EvalContext::new();
+    /// sent.set_parameter("gain", 2.0).unwrap();
+    /// sent.set_parameter("offset", 0.5).unwrap();
+    ///
+    /// let mut current = sent.clone();
+    /// current.set_parameter("gain", 3.0).unwrap();
+    ///
+    /// // Only the changed variable is shipped to the device's context
+    /// let patch = sent.diff(&current);
+    /// assert_eq!(patch.set.len(), 1);
+    /// let mut device = sent.clone();
+    /// device.apply(&patch).unwrap();
+    /// assert_eq!(device.get_variable("gain"), Some(3.0));
+    /// assert!(device.diff(&current).is_empty());
+    /// ```
+    pub fn diff(&self, other: &EvalContext) -> ContextPatch {
+        let mut set = other
+            .variables
+            .iter()
+            .filter(|(name, value)| {
+                self.variables
+                    .get(*name)
+                    .is_none_or(|old| old.to_bits() != value.to_bits())
+            })
+            .map(|(name, value)| (name.clone(), *value))
+            .collect::<Vec<_>>();
+        let mut removed = self
+            .variables
+            .keys()
+            .filter(|name| !other.variables.contains_key(*name))
+            .cloned()
+            .collect::<Vec<_>>();
+        // Map iteration order varies between builds
+        set.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        removed.sort_unstable();
+        ContextPatch { set, removed }
+    }
+
+    /// Applies a patch from [`diff`](Self::diff) to the variables of this context.
+    ///
+    /// Removals are applied first, so a patch fits a context with the capacity of the
+    /// one it was computed for. Fails with `ExprError::CapacityExceeded` if the variables
+    /// do not fit, leaving the patch partly applied.
+    pub fn apply(&mut self, patch: &ContextPatch) -> Result<(), crate::error::ExprError> {
+        for name in &patch.removed {
+            self.variables.remove(name);
+        }
+        for (name, value) in &patch.set {
+            self.set_parameter(name, *value)?;
+        }
+        Ok(())
+    }
+}
+
+/// A temporary scope created by [`EvalContext::push_scope`].
+///
+/// Dereferences to the scope's [`EvalContext`] for lookups. Dropping the scope (or
+/// calling [`pop_scope`](Self::pop_scope)) discards the values set in it.
+#[derive(Clone)]
+pub struct ScopedContext {
+    ctx: Rc<EvalContext>,
+}
+
+impl ScopedContext {
+    /// Sets a variable in this scope, shadowing any variable of the same name outside it.
+    pub fn set_parameter(
         &mut self,
-        object_name: &str,
-        attr_name: &str,
+        name: &str,
         value: Real,
     ) -> Result<Option<Real>, crate::error::ExprError> {
-        let obj_key = object_name.try_into_heapless()?;
-        let attr_key = attr_name.try_into_heapless()?;
+        self.context_mut().set_parameter(name, value)
+    }
 
-        // Get or create the object's attribute map
-        if !self.attributes.contains_key(&obj_key) {
-            let attr_map = heapless::FnvIndexMap::<
-                crate::types::HString,
-                Real,
-                { crate::types::EXP_RS_MAX_ATTR_KEYS },
-            >::new();
-            self.attributes
-                .insert(obj_key.clone(), attr_map)
-                .map_err(|_| crate::error::ExprError::CapacityExceeded("attributes"))?;
-        }
+    /// Mutable access to the scope's own context, e.g. to add arrays or attributes.
+    ///
+    /// If the scope is still shared with a running evaluation (through
+    /// [`context`](Self::context)), the small scope context is copied first.
+    pub fn context_mut(&mut self) -> &mut EvalContext {
+        Rc::make_mut(&mut self.ctx)
+    }
 
-        // Get mutable reference to the attribute map and insert the value
-        if let Some(attr_map) = self.attributes.get_mut(&obj_key) {
-            attr_map
-                .insert(attr_key, value)
-                .map_err(|_| crate::error::ExprError::CapacityExceeded("object attributes"))
-        } else {
-            unreachable!("Just inserted the object")
+    /// Returns the scope as a context for evaluation.
+    pub fn context(&self) -> Rc<EvalContext> {
+        self.ctx.clone()
+    }
+
+    /// Opens a nested scope on top of this one.
+    pub fn push_scope(&self) -> ScopedContext {
+        self.ctx.push_scope()
+    }
+
+    /// Discards this scope. Equivalent to dropping it.
+    pub fn pop_scope(self) {}
+}
+
+impl core::ops::Deref for ScopedContext {
+    type Target = EvalContext;
+
+    fn deref(&self) -> &EvalContext {
+        &self.ctx
+    }
+}
+
+/// Serialized form of an [`EvalContext`]: its data, without functions or callbacks.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ContextSnapshot {
+    #[serde(default)]
+    variables: alloc::collections::BTreeMap<String, Real>,
+    #[serde(default)]
+    constants: alloc::collections::BTreeMap<String, Real>,
+    #[serde(default)]
+    arrays: alloc::collections::BTreeMap<String, Vec<Real>>,
+    #[serde(default)]
+    attributes: alloc::collections::BTreeMap<String, alloc::collections::BTreeMap<String, Real>>,
+    #[serde(default)]
+    math_config: MathConfig,
+    #[serde(default)]
+    max_eval_depth: Option<usize>,
+    #[serde(default)]
+    stack_spill: Option<usize>,
+    #[serde(default)]
+    missing_propagation: Option<bool>,
+    #[serde(default)]
+    values: alloc::collections::BTreeMap<String, crate::value::Value>,
+}
+
+/// Serializes the variables, constants, arrays, attributes and settings of the context.
+///
+/// Native functions, resolvers, attribute providers, array views, units and the parent
+/// context are not part of the snapshot: they hold code or borrowed memory and are set
+/// up again after restoring. Expression functions belong to an
+/// [`Expression`](crate::expression::Expression) batch and are restored by registering
+/// their definitions there.
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::EvalContext;
+/// use exp_rs::engine::interp;
+/// use std::rc::Rc;
+///
+/// let mut ctx = EvalContext::new();
+/// ctx.set_parameter("gain", 2.0).unwrap();
+/// ctx.set_attribute("motor", "rpm", 1500.0).unwrap();
+///
+/// let json = serde_json::to_string(&ctx).unwrap();
+/// let restored: EvalContext = serde_json::from_str(&json).unwrap();
+/// assert_eq!(interp("motor.rpm * gain", Some(Rc::new(restored))).unwrap(), 3000.0);
+/// ```
+#[cfg(feature = "serde")]
+impl serde::Serialize for EvalContext {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ContextSnapshot {
+            variables: self
+                .variables
+                .iter()
+                .map(|(k, v)| (k.to_string(), *v))
+                .collect(),
+            constants: self
+                .constants
+                .iter()
+                .map(|(k, v)| (k.to_string(), *v))
+                .collect(),
+            arrays: self
+                .arrays
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.clone()))
+                .collect(),
+            attributes: self
+                .attributes
+                .iter()
+                .map(|(k, attrs)| {
+                    (
+                        k.to_string(),
+                        attrs.iter().map(|(a, v)| (a.to_string(), *v)).collect(),
+                    )
+                })
+                .collect(),
+            math_config: self.math_config,
+            max_eval_depth: self.max_eval_depth,
+            stack_spill: self.stack_spill,
+            missing_propagation: self.missing_propagation,
+            values: self
+                .typed_values
+                .iter()
+                .map(|(k, v)| (k.to_string(), *v))
+                .collect(),
         }
+        .serialize(serializer)
     }
+}
 
-    pub fn get_attribute_map(
-        &self,
-        base: &str,
-    ) -> Option<
-        &heapless::FnvIndexMap<crate::types::HString, Real, { crate::types::EXP_RS_MAX_ATTR_KEYS }>,
-    > {
-        if let Ok(key) = base.try_into_heapless() {
-            if let Some(attr_map) = self.attributes.get(&key) {
-                return Some(attr_map);
+/// Restores a context from a snapshot, on top of [`EvalContext::new`].
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for EvalContext {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let snapshot = ContextSnapshot::deserialize(deserializer)?;
+        let mut ctx = EvalContext::new();
+        let key = |name: &str| name.try_into_heapless().map_err(D::Error::custom);
+        let full = |container: &'static str| {
+            D::Error::custom(crate::error::ExprError::CapacityExceeded { container })
+        };
+
+        for (name, value) in snapshot.variables {
+            ctx.variables
+                .insert(key(&name)?, value)
+                .map_err(|_| full("variables"))?;
+        }
+        for (name, value) in snapshot.constants {
+            ctx.constants
+                .insert(key(&name)?, value)
+                .map_err(|_| full("constants"))?;
+        }
+        for (name, values) in snapshot.arrays {
+            ctx.arrays
+                .insert(key(&name)?, values)
+                .map_err(|_| full("arrays"))?;
+        }
+        for (object, attrs) in snapshot.attributes {
+            for (attr, value) in attrs {
+                ctx.set_attribute(&object, &attr, value)
+                    .map_err(D::Error::custom)?;
             }
         }
-
-        if let Some(parent) = &self.parent {
-            parent.get_attribute_map(base)
-        } else {
-            None
+        for (name, value) in snapshot.values {
+            ctx.set_value(&name, value).map_err(D::Error::custom)?;
         }
+        if snapshot.math_config != MathConfig::default() {
+            ctx.set_math_config(snapshot.math_config);
+        }
+        ctx.max_eval_depth = snapshot.max_eval_depth;
+        ctx.stack_spill = snapshot.stack_spill;
+        ctx.missing_propagation = snapshot.missing_propagation;
+        Ok(ctx)
     }
+}
 
-    pub fn get_native_function(&self, name: &str) -> Option<&crate::types::NativeFunction> {
-        if let Ok(key) = name.try_into_function_name() {
-            if let Some(f) = self.native_functions.get(&key) {
-                return Some(f);
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine;
+    use crate::types::TryIntoHeaplessString;
+    use std::rc::Rc;
 
-        if let Some(parent) = &self.parent {
-            parent.get_native_function(name)
-        } else {
-            None
-        }
+    #[test]
+    fn test_function_introspection_and_removal() {
+        let mut parent = EvalContext::new();
+        parent.set_parameter("b", 1.0).unwrap();
+        parent.set_parameter("a", 2.0).unwrap();
+        let parent = Rc::new(parent);
+
+        let mut ctx = parent.overlay();
+        ctx.set_parameter("c", 3.0).unwrap();
+        ctx.set_parameter("a", 4.0).unwrap();
+        ctx.register_native_function("plugin_gain", 1, |args| args[0] * 2.0)
+            .unwrap();
+        assert_eq!(ctx.list_variables(), ["a", "b", "c"]);
+        assert!(ctx.list_functions().contains(&"plugin_gain".to_string()));
+        assert!(ctx.list_functions().contains(&"sin".to_string()));
+
+        let signature = ctx.function_signature("plugin_gain").unwrap();
+        assert_eq!((signature.arity, signature.variadic), (1, false));
+        assert_eq!(signature.to_string(), "plugin_gain(x1)");
+        assert_eq!(ctx.function_signature("pi").unwrap().to_string(), "pi()");
+
+        assert!(ctx.unregister_function("plugin_gain"));
+        assert!(!ctx.unregister_function("plugin_gain"));
+        assert!(ctx.function_signature("plugin_gain").is_none());
+        // Functions inherited from the parent are not removed
+        assert!(!ctx.unregister_function("sin"));
+        assert!(ctx.function_signature("sin").is_some());
     }
 
-    /// Get a list of all native function names in this context (including parent contexts)
-    pub fn list_native_functions(&self) -> Vec<String> {
-        let mut functions = Vec::new();
-        let mut seen = alloc::collections::BTreeSet::new();
+    #[test]
+    fn test_hierarchical_paths() {
+        let mut ctx = EvalContext::new();
+        ctx.set_path("drive.motor1.current", 2.0).unwrap();
+        ctx.set_path("gain", 3.0).unwrap();
+        ctx.set_parameter("sensor.ch1.raw", 7.0).unwrap();
+        ctx.set_parameter("ctrl::Kₚ", 0.5).unwrap();
+        assert_eq!(ctx.get_path("drive.motor1.current"), Some(2.0));
+        assert_eq!(ctx.get_attribute("drive.motor1", "current"), Some(2.0));
+        assert_eq!(ctx.get_path("sensor.ch1.raw"), Some(7.0));
+        assert_eq!(ctx.get_path("drive.motor2.current"), None);
+
+        let ctx = Rc::new(ctx);
+        let expr = "drive.motor1.current * gain + sensor.ch1.raw * ctrl::Kₚ";
+        assert_eq!(crate::engine::interp(expr, Some(ctx.clone())).unwrap(), 9.5);
+        assert!(matches!(
+            crate::engine::interp("drive.motor2.current", Some(ctx)),
+            Err(crate::error::ExprError::AttributeNotFound { .. })
+        ));
+    }
 
-        // Collect from current context
-        for (name, _) in self.native_functions.iter() {
-            let name_str = name.to_string();
-            if seen.insert(name_str.clone()) {
-                functions.push(name_str);
-            }
+    #[test]
+    fn test_nested_objects_and_array_attributes() {
+        struct Telemetry {
+            samples: [Real; 3],
         }
-
-        // Collect from parent contexts
-        if let Some(parent) = &self.parent {
-            for name in parent.list_native_functions() {
-                if seen.insert(name.clone()) {
-                    functions.push(name);
+        impl AttributeProvider for Telemetry {
+            fn attribute(&self, name: &str) -> Option<Real> {
+                match name {
+                    "limits.max" => Some(50.0),
+                    _ => None,
                 }
             }
+            fn array_len(&self, name: &str) -> Option<usize> {
+                (name == "samples").then_some(self.samples.len())
+            }
+            fn element(&self, name: &str, index: usize) -> Option<Real> {
+                (name == "samples").then(|| self.samples[index])
+            }
         }
 
-        functions.sort();
-        functions
+        let mut ctx = EvalContext::new();
+        let telemetry = Telemetry {
+            samples: [4.0, 9.0, 2.0],
+        };
+        ctx.register_object("tm", Rc::new(telemetry)).unwrap();
+        ctx.set_array("point.coords", vec![1.0, 2.0]).unwrap();
+        ctx.set_path("cfg.limits.max", 5.0).unwrap();
+        let ctx = Rc::new(ctx);
+
+        let eval = |expr: &str| crate::engine::interp(expr, Some(ctx.clone()));
+        assert_eq!(eval("tm.limits.max - cfg.limits.max").unwrap(), 45.0);
+        assert_eq!(eval("tm.samples[1] + point.coords[1]").unwrap(), 11.0);
+        assert_eq!(eval("max(tm.samples) + min(point.coords)").unwrap(), 10.0);
+        assert!(matches!(
+            eval("tm.samples[3]"),
+            Err(crate::error::ExprError::ArrayIndexOutOfBounds { len: 3, .. })
+        ));
+        assert!(eval("tm.limits.min").is_err());
     }
 
-    /// Get a list of all expression function names in this context (including parent contexts)
-    pub fn list_expression_functions(&self) -> Vec<String> {
-        let mut functions = Vec::new();
-        let mut seen = alloc::collections::BTreeSet::new();
+    #[test]
+    fn test_function_policy() {
+        use crate::error::ExprError;
 
-        // Collect from current context
-        // Expression functions no longer exist in context
+        let mut base = EvalContext::new();
+        base.register_native_function("write_reg", 2, |_| 0.0)
+            .unwrap();
+        base.set_parameter("x", 4.0).unwrap();
+        let base = Rc::new(base);
+
+        let mut customer = base.overlay();
+        customer.set_function_policy(Some(FunctionPolicy::allow(["sqrt", "max"])));
+        let customer = Rc::new(customer);
+        let eval = |expr: &str| engine::interp(expr, Some(customer.clone()));
+
+        assert_eq!(eval("max(sqrt(x), -x) * 2 + 1 > 0 && x != 3").unwrap(), 1.0);
+        let err = eval("write_reg(1, x)").unwrap_err();
+        assert!(matches!(&err, ExprError::FunctionNotPermitted { name } if name == "write_reg"));
+        assert_eq!(err.error_code(), 19);
+        assert!(matches!(
+            eval("sin(x)"),
+            Err(ExprError::FunctionNotPermitted { .. })
+        ));
+        // Not evaluating the call does not trigger the check
+        assert_eq!(eval("0 && write_reg(1, x)").unwrap(), 0.0);
+
+        // Calls inside expression functions are checked too
+        let arena = bumpalo::Bump::new();
+        let mut batch = crate::expression::Expression::new(&arena);
+        batch
+            .register_expression_function("poke", &["v"], "write_reg(1, v)")
+            .unwrap();
+        batch.add_expression("poke(x)").unwrap();
+        assert!(batch.eval(&customer).is_err());
+
+        // Direct lookups apply the policy too
+        assert!(matches!(
+            customer.permitted_native_function("write_reg"),
+            Err(ExprError::FunctionNotPermitted { .. })
+        ));
+        assert!(customer.permitted_native_function("sqrt").is_ok());
+        assert!(matches!(
+            customer.call_function("write_reg", &[1.0, 2.0]),
+            Err(ExprError::FunctionNotPermitted { .. })
+        ));
+
+        // The base context itself is unrestricted
+        assert!(base.permitted_native_function("write_reg").is_ok());
+        assert!(engine::interp("write_reg(1, x)", Some(base)).is_ok());
+    }
 
-        // Collect from parent contexts
-        if let Some(parent) = &self.parent {
-            for name in parent.list_expression_functions() {
-                if seen.insert(name.clone()) {
-                    functions.push(name);
-                }
-            }
-        }
+    #[test]
+    fn test_overlay_shares_base_data() {
+        let mut base = EvalContext::new();
+        base.arrays
+            .insert("table".try_into_heapless().unwrap(), vec![1.0, 2.0, 3.0])
+            .unwrap();
+        base.set_parameter("gain", 10.0).unwrap();
+        base.set_attribute("motor", "rpm", 1500.0).unwrap();
+        let base = Rc::new(base);
+
+        let mut task = base.overlay();
+        task.set_parameter("gain", 100.0).unwrap();
+        task.set_parameter("offset", 0.5).unwrap();
+        task.set_attribute("motor", "temp", 40.0).unwrap();
+        assert!(task.arrays.is_empty());
+        let task = Rc::new(task);
+
+        // Attributes fall through per attribute, not per object
+        assert_eq!(
+            engine::interp("motor.rpm + motor.temp", Some(task.clone())).unwrap(),
+            1540.0
+        );
 
-        functions.sort();
-        functions
+        assert_eq!(
+            engine::interp("table[1] * gain + offset", Some(task.clone())).unwrap(),
+            200.5
+        );
+        assert_eq!(
+            engine::interp("table[1] * gain", Some(base.clone())).unwrap(),
+            20.0
+        );
+        assert!(engine::interp("offset", Some(base)).is_err());
     }
-}
 
-impl Clone for EvalContext {
-    fn clone(&self) -> Self {
-        Self {
-            variables: self.variables.clone(),
-            constants: self.constants.clone(),
-            arrays: self.arrays.clone(),
-            attributes: self.attributes.clone(),
-            nested_arrays: self.nested_arrays.clone(),
-            native_functions: self.native_functions.clone(),
-            parent: self.parent.clone(),
+    #[test]
+    fn test_push_scope_shadows_without_copying() {
+        let mut ctx = EvalContext::new();
+        ctx.set_parameter("x", 1.0).unwrap();
+        ctx.set_parameter("y", 10.0).unwrap();
+        let ctx = Rc::new(ctx);
+
+        {
+            let mut scope = ctx.push_scope();
+            scope.set_parameter("x", 2.0).unwrap();
+            assert!(scope.native_functions.is_empty());
+            assert_eq!(
+                engine::interp("x + y + sqrt(4)", Some(scope.context())).unwrap(),
+                14.0
+            );
+
+            let mut inner = scope.push_scope();
+            inner.set_parameter("y", 20.0).unwrap();
+            assert_eq!(
+                engine::interp("x + y", Some(inner.context())).unwrap(),
+                22.0
+            );
+            inner.pop_scope();
+
+            assert_eq!(
+                engine::interp("x + y", Some(scope.context())).unwrap(),
+                12.0
+            );
         }
-    }
-}
 
-impl Default for EvalContext {
-    /// Creates a new EvalContext with default values and math functions registered.
-    /// This ensures that EvalContext::default() behaves the same as
-    fn default() -> Self {
-        EvalContext::new()
-        // let mut ctx = Self {
-        //     variables: HashMap::new(),
-        //     constants: HashMap::new(),
-        //     arrays: HashMap::new(),
-        //     attributes: HashMap::new(),
-        //     nested_arrays: HashMap::new(),
-        //     function_registry: Rc::new(FunctionRegistry::default()),
-        //     parent: None,
-        //     ast_cache: None,
-        // };
-        //
-        // // Register default math functions, same as in new()
-        // ctx.register_default_math_functions();
-        //
-        // ctx
+        assert_eq!(ctx.get_variable("x"), Some(1.0));
+        assert_eq!(Rc::strong_count(&ctx), 1);
     }
-}
-
-// Helper trait removed - heapless containers support Clone directly
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::engine;
-    use crate::types::AstExpr;
-    use crate::types::TryIntoHeaplessString;
-    use std::rc::Rc;
 
     #[test]
     fn test_get_variable_parent_chain() {
@@ -998,11 +3705,7 @@ mod tests {
     #[test]
     fn test_attribute_access() {
         let mut ctx = EvalContext::new();
-        let mut foo_map = heapless::FnvIndexMap::<
-            crate::types::HString,
-            crate::Real,
-            { crate::types::EXP_RS_MAX_ATTR_KEYS },
-        >::new();
+        let mut foo_map = crate::types::AttributeKeyMap::new();
         foo_map
             .insert("bar".try_into_heapless().unwrap(), 42.0)
             .unwrap();
@@ -1054,4 +3757,323 @@ mod tests {
         let val = engine::interp("x * 2", Some(Rc::new(ctx.clone()))).unwrap();
         assert_eq!(val, 40.0);
     }
+
+    #[test]
+    fn test_variable_resolver() {
+        let mut ctx = EvalContext::new();
+        ctx.set_parameter("x", 1.0).unwrap();
+        ctx.set_variable_resolver(|name| match name {
+            "x" => Some(100.0),
+            "dev_temp" => Some(21.5),
+            _ => None,
+        });
+        let ctx = Rc::new(ctx);
+
+        // Stored variables take precedence over the resolver
+        assert_eq!(engine::interp("x", Some(ctx.clone())).unwrap(), 1.0);
+        assert_eq!(
+            engine::interp("dev_temp + x", Some(ctx.clone())).unwrap(),
+            22.5
+        );
+
+        let err = engine::interp("missing", Some(ctx.clone())).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::error::ExprError::UnknownVariable { .. }
+        ));
+
+        // Child contexts inherit the parent's resolver
+        let mut child = EvalContext::new();
+        child.parent = Some(ctx);
+        assert_eq!(
+            engine::interp("dev_temp", Some(Rc::new(child))).unwrap(),
+            21.5
+        );
+    }
+
+    #[test]
+    fn test_math_config() {
+        let mut ctx = EvalContext::new();
+        assert_eq!(ctx.math_config(), MathConfig::default());
+        let ctx_rc = Rc::new(ctx.clone());
+        assert_eq!(
+            engine::interp("round(-2.5)", Some(ctx_rc.clone())).unwrap(),
+            -3.0
+        );
+        assert_eq!(
+            engine::interp("0.1 + 0.2 == 0.3", Some(ctx_rc)).unwrap(),
+            0.0
+        );
+
+        for (x, half_up, half_even) in [
+            (2.5, 3.0, 2.0),
+            (3.5, 4.0, 4.0),
+            (-2.5, -3.0, -2.0),
+            (-3.5, -4.0, -4.0),
+            (2.4, 2.0, 2.0),
+            (-2.6, -3.0, -3.0),
+        ] {
+            assert_eq!(RoundingMode::HalfUp.round(x), half_up, "half up {}", x);
+            assert_eq!(
+                RoundingMode::HalfEven.round(x),
+                half_even,
+                "half even {}",
+                x
+            );
+        }
+
+        ctx.set_math_config(MathConfig {
+            rounding: RoundingMode::HalfEven,
+            equality_epsilon: 1e-6,
+        });
+        let ctx = Rc::new(ctx);
+        assert_eq!(
+            engine::interp("round(0.5) + round(1.5)", Some(ctx.clone())).unwrap(),
+            2.0
+        );
+        assert_eq!(
+            engine::interp("round_to(0.625, 0.25)", Some(ctx.clone())).unwrap(),
+            0.5
+        );
+        assert_eq!(
+            engine::interp("0.1 + 0.2 == 0.3", Some(ctx.clone())).unwrap(),
+            1.0
+        );
+        assert_eq!(
+            engine::interp("0.1 + 0.2 != 0.3", Some(ctx.clone())).unwrap(),
+            0.0
+        );
+        assert_eq!(engine::interp("1 == 1.1", Some(ctx.clone())).unwrap(), 0.0);
+        assert_eq!(engine::interp("1/0 == 1/0", Some(ctx)).unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_attribute_provider() {
+        struct Motor {
+            rpm: Real,
+        }
+        impl AttributeProvider for Motor {
+            fn attribute(&self, name: &str) -> Option<Real> {
+                (name == "rpm").then_some(self.rpm)
+            }
+        }
+
+        let mut parent = EvalContext::new();
+        parent
+            .register_object("motor", Rc::new(Motor { rpm: 1200.0 }))
+            .unwrap();
+        let mut child = EvalContext::new();
+        child.parent = Some(Rc::new(parent));
+        child.set_attribute("local", "rpm", 5.0).unwrap();
+        child
+            .register_object("local", Rc::new(Motor { rpm: -1.0 }))
+            .unwrap();
+        let child = Rc::new(child);
+
+        // Objects are inherited, and stored attributes take precedence
+        assert_eq!(
+            engine::interp("motor.rpm + local.rpm", Some(child.clone())).unwrap(),
+            1205.0
+        );
+        assert!(matches!(
+            engine::interp("motor.torque", Some(child.clone())),
+            Err(crate::error::ExprError::AttributeNotFound { .. })
+        ));
+
+        let mut ctx = (*child).clone();
+        ctx.register_object("motor", Rc::new(Motor { rpm: 10.0 }))
+            .unwrap();
+        assert_eq!(ctx.get_object_attribute("motor", "rpm"), Some(10.0));
+        assert!(ctx.unregister_object("motor"));
+        assert!(!ctx.unregister_object("motor"));
+        assert_eq!(ctx.get_object_attribute("motor", "rpm"), Some(1200.0));
+    }
+
+    #[test]
+    fn test_function_resolver() {
+        let mut ctx = EvalContext::new();
+        ctx.register_native_function("gpio", 1, |_| -1.0).unwrap();
+        ctx.set_function_resolver(|name, args| match name {
+            "gpio" => Some(99.0),
+            "dev_read" => Some(args.iter().sum()),
+            _ => None,
+        });
+        let ctx = Rc::new(ctx);
+
+        // Registered functions take precedence over the resolver
+        assert_eq!(engine::interp("gpio(12)", Some(ctx.clone())).unwrap(), -1.0);
+        // The resolver receives evaluated arguments, with any arity
+        assert_eq!(
+            engine::interp("dev_read(1+1, 3)", Some(ctx.clone())).unwrap(),
+            5.0
+        );
+        assert_eq!(
+            engine::interp("dev_read()", Some(ctx.clone())).unwrap(),
+            0.0
+        );
+
+        let err = engine::interp("nope(1)", Some(ctx)).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::error::ExprError::UnknownFunction { .. }
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_std_context_has_no_capacity_limit() {
+        let mut ctx = EvalContext::new();
+        let count = crate::types::EXP_RS_MAX_VARIABLES * 4;
+        for i in 0..count {
+            ctx.set_parameter(&format!("v{}", i), i as Real).unwrap();
+        }
+        assert_eq!(ctx.variables.len(), count);
+
+        let last = format!("v{}", count - 1);
+        let val = engine::interp(&last, Some(Rc::new(ctx))).unwrap();
+        assert_eq!(val, (count - 1) as Real);
+    }
+
+    #[test]
+    fn test_context_builder() {
+        let ctx = EvalContext::builder()
+            .with_parameter("x", 1.0)
+            .with_parameter("x", 3.0)
+            .with_array("lut", vec![1.0, 2.0])
+            .with_function("sin", 1, |_| 42.0)
+            .build()
+            .unwrap();
+        assert_eq!(ctx.variables.len(), 1);
+        let ctx = Rc::new(ctx);
+        assert_eq!(
+            engine::interp("x + lut[1] + sin(0)", Some(ctx)).unwrap(),
+            47.0
+        );
+
+        // Every entry fits or nothing is built
+        let mut builder = EvalContext::builder();
+        for i in 0..=crate::types::EXP_RS_MAX_VARIABLES {
+            builder = builder.with_parameter(&format!("v{}", i), i as Real);
+        }
+        let built = builder.build();
+        if cfg!(feature = "std") {
+            assert!(built.is_ok());
+        } else {
+            assert!(matches!(
+                built,
+                Err(crate::error::ExprError::CapacityExceeded {
+                    container: "variables"
+                })
+            ));
+        }
+        assert!(matches!(
+            EvalContext::builder()
+                .with_function(&"f".repeat(40), 0, |_| 0.0)
+                .with_array(&"a".repeat(40), vec![])
+                .build(),
+            Err(crate::error::ExprError::StringTooLong { max_len: 32, .. })
+        ));
+    }
+
+    #[test]
+    fn test_context_diff_and_apply() {
+        let mut host = EvalContext::new();
+        host.set_parameter("a", 1.0).unwrap();
+        host.set_parameter("b", Real::NAN).unwrap();
+        host.set_parameter("c", 0.0).unwrap();
+        let mut device = host.clone();
+        assert!(device.diff(&host).is_empty());
+
+        host.set_parameter("c", -0.0).unwrap();
+        host.set_parameter("d", 4.0).unwrap();
+        host.variables.remove(&"a".try_into_heapless().unwrap());
+        let patch = device.diff(&host);
+        let names = |entries: &[crate::types::HString]| {
+            let mut names: Vec<_> = entries.iter().map(|n| n.as_str()).collect();
+            names.sort();
+            names.join(",")
+        };
+        let set: Vec<_> = patch.set.iter().map(|(n, _)| n.clone()).collect();
+        assert_eq!(
+            (names(&set), names(&patch.removed)),
+            ("c,d".into(), "a".into())
+        );
+
+        device.apply(&patch).unwrap();
+        assert!(device.diff(&host).is_empty());
+        assert_eq!(device.get_variable("d"), Some(4.0));
+        assert!(device.get_variable("a").is_none());
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&patch).unwrap();
+            assert_eq!(serde_json::from_str::<ContextPatch>(&json).unwrap(), patch);
+        }
+    }
+
+    #[test]
+    fn test_random_builtins_follow_seed() {
+        let ctx = Rc::new(EvalContext::new());
+        let draw = |expr: &str| engine::interp(expr, Some(ctx.clone())).unwrap();
+
+        ctx.set_seed(99);
+        let sequence: Vec<Real> = (0..5).map(|_| draw("rand()")).collect();
+        assert!(sequence.iter().all(|x| (0.0..1.0).contains(x)));
+        assert!(sequence.windows(2).all(|w| w[0] != w[1]));
+
+        ctx.set_seed(99);
+        let again: Vec<Real> = (0..5).map(|_| draw("rand()")).collect();
+        assert_eq!(sequence, again);
+
+        for _ in 0..100 {
+            let x = draw("rand_range(-1, 1)");
+            assert!((-1.0..1.0).contains(&x));
+        }
+
+        #[cfg(feature = "libm")]
+        {
+            let n = 2000;
+            let mean = (0..n).map(|_| draw("randn()")).sum::<Real>() / n as Real;
+            assert!(mean.abs() < 0.1);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_snapshot_roundtrip() {
+        let mut ctx = EvalContext::new();
+        ctx.set_parameter("x", 1.5).unwrap();
+        ctx.constants
+            .insert("limit".try_into_heapless().unwrap(), 10.0)
+            .unwrap();
+        ctx.arrays
+            .insert("lut".try_into_heapless().unwrap(), vec![0.0, 0.5, 1.0])
+            .unwrap();
+        ctx.set_attribute("sensor", "offset", -0.25).unwrap();
+        ctx.set_math_config(MathConfig {
+            rounding: RoundingMode::HalfEven,
+            equality_epsilon: 1e-9,
+        });
+        ctx.set_max_eval_depth(Some(128));
+
+        let json = serde_json::to_string(&ctx).unwrap();
+        let restored: EvalContext = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.get_variable("x"), Some(1.5));
+        assert_eq!(restored.get_constant("limit"), Some(10.0));
+        assert_eq!(restored.get_array("lut"), Some(&vec![0.0, 0.5, 1.0]));
+        assert_eq!(restored.math_config(), ctx.math_config());
+        assert_eq!(restored.max_eval_depth(), Some(128));
+
+        let restored = Rc::new(restored);
+        assert_eq!(
+            engine::interp("lut[2] * x + sensor.offset", Some(restored.clone())).unwrap(),
+            1.25
+        );
+        // The rounding mode is applied again, not just stored
+        assert_eq!(engine::interp("round(2.5)", Some(restored)).unwrap(), 2.0);
+
+        // Missing sections default to empty
+        let empty: EvalContext = serde_json::from_str("{}").unwrap();
+        assert!(empty.variables.is_empty());
+    }
 }
@@ -10,6 +10,7 @@ use crate::Real;
 use crate::Vec;
 use crate::context::EvalContext;
 use crate::error::ExprError;
+use crate::intern::SymbolTable;
 use crate::lexer::{Lexer, Token};
 use crate::types::{AstExpr, TokenKind};
 use bumpalo::Bump;
@@ -25,15 +26,157 @@ use alloc::collections::BTreeSet as HashSet;
 #[cfg(test)]
 use std::collections::HashSet;
 
+pub use crate::printer::canonicalize;
+
 struct PrattParser<'input, 'arena> {
     lexer: Lexer<'input>,
     arena: &'arena Bump, // Arena is now mandatory
     current: Option<Token>,
     errors: Vec<ExprError>,
     recursion_depth: usize,
-    max_recursion_depth: usize,
+    tokens_consumed: usize,
     reserved_vars: Option<HashSet<Cow<'input, str>>>, // Parameter names to treat as variables, not functions
     context_vars: Option<HashSet<Cow<'input, str>>>,  // Variable/constant names from context
+    options: ParseOptions,
+    symbols: Option<SymbolTable<'arena>>, // Interns names when set
+}
+
+/// Options that change how expressions are parsed.
+///
+/// The defaults match the standard grammar; every option is opt-in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ParseOptions {
+    /// Treat an operand directly followed by a variable or `(` as multiplication,
+    /// so `2x`, `3(x+1)`, `2 sin(x)` and `(a+b)(c-d)` parse as products.
+    ///
+    /// Implicit multiplication has the same precedence as `*`, so `2x^2` is `2*(x^2)`
+    /// and `1/2x` is `(1/2)*x`. A variable directly followed by `(` is still a function
+    /// call, and a number after an operand (`x 2`) is still a syntax error.
+    pub implicit_multiplication: bool,
+    /// Treat a number directly followed by a name, with no space between them, as a
+    /// quantity with a unit: `10ms` parses as `(10 * ms)`.
+    ///
+    /// The quantity is a single operand, so `1/10ms` is `1/(10*ms)` and `2m^2` is
+    /// `(2*m)^2`. Unit names are resolved through the context's
+    /// [`UnitRegistry`](crate::units::UnitRegistry); see [`crate::units`].
+    pub unit_literals: bool,
+    /// Treat booleans as a type distinct from numbers.
+    ///
+    /// Comparisons and `&&`/`||` produce booleans; arithmetic, function arguments and
+    /// ordering comparisons require numbers; `&&`, `||` and the condition of `?:` require
+    /// booleans; `==`/`!=` and the branches of `?:` require matching types. Violations
+    /// are reported as `ExprError::TypeError` when the expression is parsed. Values are
+    /// still `1.0`/`0.0` at run time, so `x > 0` has to be written instead of `x` and
+    /// `(x > 0) * 5` is rejected.
+    pub strict_booleans: bool,
+    /// Treat `%` after an operand as a percent sign: `15%` is `0.15` and `x%` is `x / 100`.
+    ///
+    /// `%` is a percent sign when the next token cannot start an operand, and modulo
+    /// otherwise, so `7 % 3` is still `1`. A negative right operand of modulo has to be
+    /// parenthesized: `7 % (-3)`, since `7 % -3` reads as `7% - 3`.
+    pub percent_literals: bool,
+    /// With `percent_literals`, make adding or subtracting a percentage relative to the
+    /// left operand, as in spreadsheets and calculators: `x + 10%` is `x * 1.1` and
+    /// `x - 10%` is `x * 0.9`.
+    ///
+    /// Only a percentage that is the whole right operand is relative; `x + 10% * 2` is
+    /// `x + 0.2`. Has no effect unless `percent_literals` is also set.
+    pub relative_percent: bool,
+    /// Reject `pow` and `atan2` calls with a single argument with
+    /// `ExprError::InvalidFunctionCall`, instead of filling in the second argument
+    /// (`pow(x)` as `pow(x, 2)` and `atan2(y)` as `atan2(y, 1)`).
+    pub strict_arity: bool,
+    /// Meaning of the `^` operator; see [`CaretOperator`].
+    pub caret: CaretOperator,
+    /// Reject the `**` power operator with a syntax error, for grammars that do not
+    /// have it.
+    pub disable_double_star: bool,
+    /// Bounds on the size and complexity of the input
+    pub limits: ParserLimits,
+}
+
+/// Meaning of the `^` operator, selected with [`ParseOptions::caret`].
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::engine::{CaretOperator, ParseOptions, parse_expression_with_options};
+/// use bumpalo::Bump;
+///
+/// let arena = Bump::new();
+/// let c_like = ParseOptions {
+///     caret: CaretOperator::Xor,
+///     ..Default::default()
+/// };
+/// let ast = parse_expression_with_options("a ^ b & 3", &arena, &c_like).unwrap();
+/// assert_eq!(ast.to_expression_string(), "bitxor(a, b & 3)");
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum CaretOperator {
+    /// Exponentiation, right-associative and binding tighter than unary minus:
+    /// `-2^2` is `-4`
+    #[default]
+    Power,
+    /// Bitwise exclusive or of the operands as integers, as in C: a call to `bitxor`,
+    /// left-associative and binding tighter than `|` but looser than `&`, so
+    /// `a | b ^ c & d` is `a | (b ^ (c & d))`
+    Xor,
+    /// `^` is a syntax error, so only `**` and `pow` raise to a power
+    Error,
+}
+
+/// Bounds on the input the parser accepts.
+///
+/// The parser rejects input exceeding any of them with an error instead of spending
+/// time and memory on it, which keeps parsing untrusted input (from a network or a
+/// fuzzer) bounded. The defaults suit hand-written formulas; lower them to tighten
+/// the bounds, or raise them for machine-generated expressions.
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::engine::{ParseOptions, ParserLimits, parse_expression_with_options};
+/// use bumpalo::Bump;
+///
+/// let arena = Bump::new();
+/// let options = ParseOptions {
+///     limits: ParserLimits {
+///         max_nesting: 4,
+///         ..ParserLimits::DEFAULT
+///     },
+///     ..Default::default()
+/// };
+/// assert!(parse_expression_with_options("((x + 1))", &arena, &options).is_ok());
+/// assert!(parse_expression_with_options("(((((x + 1)))))", &arena, &options).is_err());
+/// ```
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ParserLimits {
+    /// Maximum length of the input in bytes
+    pub max_length: usize,
+    /// Maximum number of tokens
+    pub max_tokens: usize,
+    /// Maximum nesting of subexpressions (parentheses, operands, arguments);
+    /// exceeding it fails with `ExprError::RecursionLimit`
+    pub max_nesting: usize,
+    /// Maximum length of a single identifier or number in bytes
+    pub max_token_length: usize,
+}
+
+impl ParserLimits {
+    /// The limits used unless others are set.
+    pub const DEFAULT: Self = Self {
+        max_length: 10000,
+        max_tokens: 10000,
+        max_nesting: 2000,
+        max_token_length: 1000,
+    };
+}
+
+impl Default for ParserLimits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
 }
 
 /// Token binding powers for the Pratt parser
@@ -60,8 +203,9 @@ impl BindingPower {
 }
 
 impl<'input, 'arena> PrattParser<'input, 'arena> {
-    fn new(input: &'input str, arena: &'arena Bump) -> Self {
+    fn with_options(input: &'input str, arena: &'arena Bump, options: ParseOptions) -> Self {
         let mut lexer = Lexer::new(input);
+        lexer.set_max_token_length(options.limits.max_token_length);
         let current = lexer.next_token();
         Self {
             lexer,
@@ -69,9 +213,19 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
             current,
             errors: Vec::new(),
             recursion_depth: 0,
-            max_recursion_depth: 2000,
+            tokens_consumed: 0,
             reserved_vars: None,
             context_vars: None,
+            options,
+            symbols: None,
+        }
+    }
+
+    /// Stores a name in the arena, as its interned copy when parsing with a symbol table.
+    fn name(&mut self, text: &str) -> Result<&'arena str, ExprError> {
+        match &mut self.symbols {
+            Some(symbols) => symbols.canonical(text),
+            None => Ok(self.arena.alloc_str(text)),
         }
     }
 
@@ -80,8 +234,9 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
         arena: &'arena Bump,
         reserved_vars: Option<&'input [String]>,
         context_vars: Option<&'input [String]>,
+        options: ParseOptions,
     ) -> Self {
-        let mut parser = Self::new(input, arena);
+        let mut parser = Self::with_options(input, arena, options);
         if let Some(vars) = reserved_vars {
             let mut set = HashSet::new();
             for v in vars {
@@ -104,6 +259,7 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
     }
 
     fn next(&mut self) -> Option<Token> {
+        self.tokens_consumed += 1;
         let tok = self.current.take();
         self.current = self.lexer.next_token();
         tok
@@ -130,10 +286,10 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
             .and_then(|t| t.text.clone())
             .unwrap_or_else(|| "end of input".to_string());
 
-        let err = ExprError::Syntax(format!(
-            "{} at position {}, found '{}'",
-            error_msg, position, found
-        ));
+        let err = ExprError::syntax_at(
+            format!("{} at position {}, found '{}'", error_msg, position, found),
+            position,
+        );
         self.errors.push(err.clone());
         Err(err)
     }
@@ -174,6 +330,16 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
         loop {
             if let Some(tok) = self.peek() {
                 match (tok.kind, tok.text.as_deref()) {
+                    (TokenKind::Open, Some("("))
+                        if self.options.implicit_multiplication
+                            && !matches!(
+                                result,
+                                AstExpr::Variable(_) | AstExpr::Attribute { .. }
+                            ) =>
+                    {
+                        // Not callable: leave `(` for implicit multiplication
+                        break;
+                    }
                     (TokenKind::Open, Some("(")) => {
                         // Function call
                         result = self.parse_function_call(result)?;
@@ -186,6 +352,16 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
                         // Attribute access
                         result = self.parse_attribute_access(result)?;
                     }
+                    (TokenKind::Operator, Some("%"))
+                        if self.options.percent_literals && !self.operand_follows_current() =>
+                    {
+                        // Percent sign; lowered to a division by `lower_percentages`
+                        self.next();
+                        result = AstExpr::Function {
+                            name: self.name("%")?,
+                            args: self.arena.alloc_slice_clone(&[result]),
+                        };
+                    }
                     _ => break, // No more postfix operators
                 }
             } else {
@@ -196,6 +372,14 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
         Ok(result)
     }
 
+    // Whether the token after the current one can start an operand
+    fn operand_follows_current(&self) -> bool {
+        self.lexer.peek_token().is_some_and(|tok| {
+            matches!(tok.kind, TokenKind::Number | TokenKind::Variable)
+                || (tok.kind == TokenKind::Open && tok.text.as_deref() == Some("("))
+        })
+    }
+
     // Unified error handling for all parenthesis-like structures
     fn expect_closing(
         &mut self,
@@ -213,17 +397,23 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
             let position = tok.position;
             let found = tok.text.clone().unwrap_or_else(|| "unknown".to_string());
 
-            return Err(ExprError::Syntax(format!(
-                "Expected {} at position {}, found '{}' (opening at position {})",
-                expected, position, found, opening_position
-            )));
+            return Err(ExprError::syntax_at(
+                format!(
+                    "Expected {} at position {}, found '{}' (opening at position {})",
+                    expected, position, found, opening_position
+                ),
+                position,
+            ));
         }
 
         // End of input
-        Err(ExprError::Syntax(format!(
-            "Expected {} but found end of input (opening at position {})",
-            expected, opening_position
-        )))
+        Err(ExprError::syntax_at(
+            format!(
+                "Expected {} but found end of input (opening at position {})",
+                expected, opening_position
+            ),
+            self.lexer.get_original_input().len(),
+        ))
     }
 
     // Helper method for parsing parenthesized expressions
@@ -245,17 +435,23 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
             // If not a closing parenthesis, report an error
             let position = tok.position;
             let found = tok.text.clone().unwrap_or_else(|| "unknown".to_string());
-            return Err(ExprError::Syntax(format!(
-                "Expected closing parenthesis ')' but found '{}' at position {} (opening at position {})",
-                found, position, open_position
-            )));
+            return Err(ExprError::syntax_at(
+                format!(
+                    "Expected closing parenthesis ')' but found '{}' at position {} (opening at position {})",
+                    found, position, open_position
+                ),
+                position,
+            ));
         }
 
         // End of input
-        Err(ExprError::Syntax(format!(
-            "Expected closing parenthesis ')' but found end of input (opening at position {})",
-            open_position
-        )))
+        Err(ExprError::syntax_at(
+            format!(
+                "Expected closing parenthesis ')' but found end of input (opening at position {})",
+                open_position
+            ),
+            self.lexer.get_original_input().len(),
+        ))
     }
 
     // Helper method for parsing function calls
@@ -264,7 +460,7 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
             AstExpr::Variable(name) => *name,
             AstExpr::Attribute { attr, .. } => *attr,
             _ => {
-                return Err(ExprError::Syntax(
+                return Err(ExprError::syntax(
                     "Function call on non-function expression".to_string(),
                 ));
             }
@@ -300,10 +496,13 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
                             .text
                             .clone()
                             .unwrap_or_else(|| "unknown".to_string());
-                        return Err(ExprError::Syntax(format!(
-                            "Expected ',' or ')' but found '{}' at position {} in function call",
-                            found, position
-                        )));
+                        return Err(ExprError::syntax_at(
+                            format!(
+                                "Expected ',' or ')' but found '{}' at position {} in function call",
+                                found, position
+                            ),
+                            position,
+                        ));
                     }
                 }
             }
@@ -317,10 +516,13 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
                 // If not a closing parenthesis, report an error
                 let position = tok.position;
                 let found = tok.text.clone().unwrap_or_else(|| "unknown".to_string());
-                return Err(ExprError::Syntax(format!(
-                    "Expected closing parenthesis ')' but found '{}' at position {} in function call",
-                    found, position
-                )));
+                return Err(ExprError::syntax_at(
+                    format!(
+                        "Expected closing parenthesis ')' but found '{}' at position {} in function call",
+                        found, position
+                    ),
+                    position,
+                ));
             }
         } else {
             // End of input - this is an error because we're missing a closing parenthesis
@@ -332,6 +534,14 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
             });
         }
 
+        if self.options.strict_arity && (name == "pow" || name == "atan2") && args.len() == 1 {
+            return Err(ExprError::InvalidFunctionCall {
+                name: name.to_string(),
+                expected: 2,
+                found: 1,
+            });
+        }
+
         // Special handling for pow function to ensure it has 2 arguments
         if name == "pow" && args.len() == 1 {
             // If pow has only one argument, add a default second argument of 2.0
@@ -355,21 +565,37 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
     // Helper method for parsing array access
     fn parse_array_access(&mut self, expr: AstExpr<'arena>) -> Result<AstExpr<'arena>, ExprError> {
         let name = match &expr {
-            AstExpr::Variable(name) => name,
+            AstExpr::Variable(name) => *name,
+            // An array attribute of an object is named by its path
+            AstExpr::Attribute { base, attr } => self.name(&format!("{}.{}", base, attr))?,
             _ => {
                 let position = self.peek().map(|t| t.position).unwrap_or(0);
-                return Err(ExprError::Syntax(format!(
-                    "Array access on non-array expression at position {}",
-                    position
-                )));
+                return Err(ExprError::syntax_at(
+                    format!(
+                        "Array access on non-array expression at position {}",
+                        position
+                    ),
+                    position,
+                ));
             }
         };
 
         let open_position = self.peek().map(|t| t.position).unwrap_or(0);
         self.next(); // consume '['
 
-        // Parse index expression
-        let index = self.parse_expr_unified(0, true)?;
+        // Parse index expression, or the bounds of a slice `name[start:end]`
+        let mut index = self.parse_expr_unified(0, true)?;
+        if let Some(tok) = self.peek()
+            && tok.kind == TokenKind::Operator
+            && tok.text.as_deref() == Some(":")
+        {
+            self.next(); // consume ':'
+            let end = self.parse_expr_unified(0, true)?;
+            index = AstExpr::Function {
+                name: self.name(":")?,
+                args: self.arena.alloc_slice_clone(&[index, end]),
+            };
+        }
 
         // Always expect closing bracket
         self.expect_closing(TokenKind::Close, "closing bracket ']'", open_position)?;
@@ -391,30 +617,27 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
         // Expect identifier
         let attr_tok = self.expect(TokenKind::Variable, "Expected attribute name")?;
 
-        let attr = self.arena.alloc_str(&attr_tok.text.unwrap_or_default());
-
-        #[cfg(test)]
-        println!("Parsing attribute access: expr={:?}, attr={}", expr, attr);
+        let attr = self.name(&attr_tok.text.unwrap_or_default())?;
 
-        // Only allow attribute access on variables
+        // Only allow attribute access on variables and on paths of attributes, where
+        // `a.b.c` reads the attribute `c` of the object `a.b`
         match expr {
             AstExpr::Variable(base) => {
-                #[cfg(test)]
-                println!("Creating attribute node: {}.{}", base, attr);
-
                 let result = AstExpr::Attribute { base, attr };
                 // Apply any postfix operators to the attribute access result
                 self.parse_postfix(result)
             }
-            _ => {
-                #[cfg(test)]
-                println!("Error: Attribute access on non-variable expression");
-
-                Err(ExprError::Syntax(format!(
+            AstExpr::Attribute { base, attr: inner } => {
+                let base = self.name(&format!("{}.{}", base, inner))?;
+                self.parse_postfix(AstExpr::Attribute { base, attr })
+            }
+            _ => Err(ExprError::syntax_at(
+                format!(
                     "Attribute access on non-object expression at position {}",
                     dot_position
-                )))
-            }
+                ),
+                dot_position,
+            )),
         }
     }
 
@@ -425,14 +648,19 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
         allow_comma: bool,
     ) -> Result<AstExpr<'arena>, ExprError> {
         // Check recursion depth to prevent stack overflow
+        let max_nesting = self.options.limits.max_nesting;
         self.recursion_depth += 1;
-        if self.recursion_depth > self.max_recursion_depth {
+        if self.recursion_depth > max_nesting {
             self.recursion_depth -= 1;
-            return Err(ExprError::RecursionLimit(format!(
-                "Expression too complex: exceeded maximum recursion depth of {}",
-                self.max_recursion_depth
-            )));
+            return Err(ExprError::RecursionLimit {
+                limit: max_nesting,
+                message: format!(
+                    "Expression too complex: exceeded maximum recursion depth of {}",
+                    max_nesting
+                ),
+            });
         }
+        self.check_token_count()?;
 
         // Parse prefix or primary expression
         let mut lhs = self.parse_prefix_or_primary(allow_comma)?;
@@ -455,11 +683,14 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
         if let Some(tok) = self.peek() {
             // Check for error tokens and report them immediately
             if tok.kind == TokenKind::Error {
-                return Err(ExprError::Syntax(format!(
-                    "Unexpected token '{}' at position {}",
-                    tok.text.as_deref().unwrap_or("unknown"),
-                    tok.position
-                )));
+                return Err(ExprError::syntax_at(
+                    format!(
+                        "Unexpected token '{}' at position {}",
+                        tok.text.as_deref().unwrap_or("unknown"),
+                        tok.position
+                    ),
+                    tok.position,
+                ));
             }
             if tok.kind == TokenKind::Operator {
                 let op = tok.text.as_deref().unwrap_or("");
@@ -473,10 +704,13 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
 
                     // Handle the case where there's nothing after the operator
                     if self.peek().is_none() {
-                        return Err(ExprError::Syntax(format!(
-                            "Expected expression after '{}' at position {}",
-                            op_str, op_position
-                        )));
+                        return Err(ExprError::syntax_at(
+                            format!(
+                                "Expected expression after '{}' at position {}",
+                                op_str, op_position
+                            ),
+                            op_position,
+                        ));
                     }
 
                     // Parse the right-hand side expression
@@ -487,7 +721,7 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
                         let mut args = bumpalo::collections::Vec::new_in(self.arena);
                         args.push(rhs);
                         Ok(AstExpr::Function {
-                            name: self.arena.alloc_str("neg"),
+                            name: self.name("neg")?,
                             args: args.into_bump_slice(),
                         })
                     } else {
@@ -521,13 +755,16 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
             if tok.kind == TokenKind::Operator && tok.text.as_deref() == Some(":") {
                 self.next(); // Consume the ':'
             } else {
-                return Err(ExprError::Syntax(format!(
-                    "Expected ':' in ternary expression, found '{}'",
-                    tok.text.clone().unwrap_or_else(|| "unknown".to_string())
-                )));
+                return Err(ExprError::syntax_at(
+                    format!(
+                        "Expected ':' in ternary expression, found '{}'",
+                        tok.text.clone().unwrap_or_else(|| "unknown".to_string())
+                    ),
+                    tok.position,
+                ));
             }
         } else {
-            return Err(ExprError::Syntax(
+            return Err(ExprError::syntax(
                 "Expected ':' in ternary expression, found end of input".to_string(),
             ));
         }
@@ -549,6 +786,9 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
         allow_comma: bool,
     ) -> Result<AstExpr<'arena>, ExprError> {
         loop {
+            // Set when an adjacent operand is read as multiplication; no token is consumed
+            let mut implicit = false;
+
             // Get the next operator
             let op_text = if let Some(tok) = self.peek() {
                 if tok.kind == TokenKind::Operator {
@@ -562,6 +802,12 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
                     } else {
                         break;
                     }
+                } else if self.options.implicit_multiplication
+                    && (tok.kind == TokenKind::Variable
+                        || (tok.kind == TokenKind::Open && tok.text.as_deref() == Some("(")))
+                {
+                    implicit = true;
+                    "*"
                 } else {
                     break;
                 }
@@ -624,7 +870,13 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
             }
 
             // Get binding power for regular (non-logical) operator
-            let Some(bp) = Self::get_binding_power(&op) else {
+            let caret_xor = op == "^" && self.options.caret == CaretOperator::Xor;
+            let bp = if caret_xor {
+                // Between `|` and `&`, as in C
+                BindingPower::left_assoc(5)
+            } else if let Some(bp) = Self::get_binding_power(&op) {
+                bp
+            } else {
                 break;
             };
 
@@ -633,22 +885,62 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
                 break;
             }
 
+            // Operators left out of the grammar by the options
+            if (op == "^" && self.options.caret == CaretOperator::Error)
+                || (op == "**" && self.options.disable_double_star)
+            {
+                let position = self.peek().map_or(0, |tok| tok.position);
+                return Err(ExprError::syntax_at(
+                    format!(
+                        "Operator '{}' is not supported at position {}",
+                        op, position
+                    ),
+                    position,
+                ));
+            }
+
             // Consume the operator
-            self.next();
+            if !implicit {
+                self.next();
+            }
 
             // Special case for right-associative power operators
-            let rhs = if op == "^" || op == "**" {
+            let rhs = if caret_xor {
+                self.parse_expr_unified(bp.right, allow_comma)?
+            } else if op == "^" || op == "**" {
                 self.parse_expr_unified(bp.right - 1, allow_comma)?
             } else {
                 self.parse_expr_unified(bp.right, allow_comma)?
             };
 
+            // `x + p%` becomes `x * (1 + p/100)` in relative percent mode
+            if self.options.relative_percent
+                && (op == "+" || op == "-")
+                && let AstExpr::Function {
+                    name: "%",
+                    args: [percent],
+                } = rhs
+            {
+                let factor = AstExpr::Function {
+                    name: self.name(&op)?,
+                    args: self.arena.alloc_slice_clone(&[
+                        AstExpr::Constant(1.0),
+                        percent_fraction(percent, self.arena),
+                    ]),
+                };
+                lhs = AstExpr::Function {
+                    name: self.name("*")?,
+                    args: self.arena.alloc_slice_clone(&[lhs, factor]),
+                };
+                continue;
+            }
+
             // Create a function node for the operator
             let mut args = bumpalo::collections::Vec::new_in(self.arena);
             args.push(lhs);
             args.push(rhs);
             lhs = AstExpr::Function {
-                name: self.arena.alloc_str(&op),
+                name: self.name(if caret_xor { "bitxor" } else { &op })?,
                 args: args.into_bump_slice(),
             };
         }
@@ -721,21 +1013,36 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
     fn parse_primary(&mut self) -> Result<AstExpr<'arena>, ExprError> {
         let tok = match self.peek() {
             Some(tok) => tok,
-            None => return Err(ExprError::Syntax("Unexpected end of input".to_string())),
+            None => return Err(ExprError::syntax("Unexpected end of input".to_string())),
         };
 
         match tok.kind {
             TokenKind::Number => {
                 let val = tok.value.unwrap_or(0.0);
+                let end = tok.position + tok.text.as_ref().map_or(0, |t| t.len());
                 self.next();
+                if self.options.unit_literals
+                    && self
+                        .peek()
+                        .is_some_and(|t| t.kind == TokenKind::Variable && t.position == end)
+                {
+                    let unit = self.next().and_then(|t| t.text).unwrap_or_default();
+                    let unit = self.name(&unit)?;
+                    let mut args = bumpalo::collections::Vec::new_in(self.arena);
+                    args.push(AstExpr::Constant(val));
+                    args.push(AstExpr::Variable(unit));
+                    return Ok(AstExpr::Function {
+                        name: "*",
+                        args: args.into_bump_slice(),
+                    });
+                }
                 Ok(AstExpr::Constant(val))
             }
             TokenKind::Variable => {
-                let name = match &tok.text {
-                    Some(name) => self.arena.alloc_str(name),
-                    None => return Err(ExprError::Syntax("Variable name is missing".to_string())),
+                let name = match self.next().and_then(|tok| tok.text) {
+                    Some(name) => self.name(&name)?,
+                    None => return Err(ExprError::syntax("Variable name is missing".to_string())),
                 };
-                self.next();
                 Ok(AstExpr::Variable(name))
             }
             TokenKind::Open if tok.text.as_deref() == Some("(") => self.parse_parenthesized_expr(),
@@ -743,18 +1050,21 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
                 // This is a closing parenthesis without a matching opening parenthesis
                 let position = tok.position;
                 let found = tok.text.clone().unwrap_or_else(|| ")".to_string());
-                Err(ExprError::Syntax(format!(
-                    "Unexpected closing parenthesis at position {}: '{}'",
-                    position, found
-                )))
+                Err(ExprError::syntax_at(
+                    format!(
+                        "Unexpected closing parenthesis at position {}: '{}'",
+                        position, found
+                    ),
+                    position,
+                ))
             }
             _ => {
                 let position = tok.position;
                 let found = tok.text.clone().unwrap_or_else(|| "unknown".to_string());
-                Err(ExprError::Syntax(format!(
-                    "Unexpected token at position {}: '{}'",
-                    position, found
-                )))
+                Err(ExprError::syntax_at(
+                    format!("Unexpected token at position {}: '{}'", position, found),
+                    position,
+                ))
             }
         }
     }
@@ -764,12 +1074,24 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
 
     // Check if the expression is too long
     fn check_expression_length(&self, input: &str) -> Result<(), ExprError> {
-        const MAX_EXPRESSION_LENGTH: usize = 10000; // Reasonable limit
-        if input.len() > MAX_EXPRESSION_LENGTH {
-            return Err(ExprError::Syntax(format!(
+        let max_length = self.options.limits.max_length;
+        if input.len() > max_length {
+            return Err(ExprError::syntax(format!(
                 "Expression too long: {} characters (maximum is {})",
                 input.len(),
-                MAX_EXPRESSION_LENGTH
+                max_length
+            )));
+        }
+        Ok(())
+    }
+
+    // Check if more tokens were consumed than allowed
+    fn check_token_count(&self) -> Result<(), ExprError> {
+        let max_tokens = self.options.limits.max_tokens;
+        if self.tokens_consumed > max_tokens {
+            return Err(ExprError::syntax(format!(
+                "Expression has too many tokens (maximum is {})",
+                max_tokens
             )));
         }
         Ok(())
@@ -778,28 +1100,27 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
     // Parse a complete expression
     fn parse(&mut self) -> Result<AstExpr<'arena>, ExprError> {
         // Check expression length
-        if let Some(remaining) = self.lexer.get_remaining_input() {
-            self.check_expression_length(remaining)?;
-        }
+        self.check_expression_length(self.lexer.get_original_input())?;
 
         // Reset recursion depth before parsing
         self.recursion_depth = 0;
 
         // Parse the expression
         let expr = self.parse_expr(0)?;
-
-        #[cfg(test)]
-        println!("Parsed expression: {:?}", expr);
+        self.check_token_count()?;
 
         // Check for unexpected trailing tokens
         if let Some(tok) = self.peek() {
             // Handle error tokens - return an error instead of skipping
             if tok.kind == TokenKind::Error {
-                return Err(ExprError::Syntax(format!(
-                    "Unexpected token '{}' at position {}",
-                    tok.text.as_deref().unwrap_or("unknown"),
-                    tok.position
-                )));
+                return Err(ExprError::syntax_at(
+                    format!(
+                        "Unexpected token '{}' at position {}",
+                        tok.text.as_deref().unwrap_or("unknown"),
+                        tok.position
+                    ),
+                    tok.position,
+                ));
             }
             // Skip trailing whitespace
             if tok.kind == TokenKind::Operator
@@ -808,17 +1129,23 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
                 self.next();
             } else if tok.kind == TokenKind::Close {
                 // For expressions like "1)", it's an error
-                return Err(ExprError::Syntax(format!(
-                    "Unexpected closing parenthesis at position {}: check for balanced parentheses",
-                    tok.position
-                )));
+                return Err(ExprError::syntax_at(
+                    format!(
+                        "Unexpected closing parenthesis at position {}: check for balanced parentheses",
+                        tok.position
+                    ),
+                    tok.position,
+                ));
             } else {
                 // Any other trailing token is an error
-                return Err(ExprError::Syntax(format!(
-                    "Unexpected token at position {}: '{}'",
+                return Err(ExprError::syntax_at(
+                    format!(
+                        "Unexpected token at position {}: '{}'",
+                        tok.position,
+                        tok.text.clone().unwrap_or_else(|| "unknown".to_string())
+                    ),
                     tok.position,
-                    tok.text.clone().unwrap_or_else(|| "unknown".to_string())
-                )));
+                ));
             }
         }
 
@@ -826,6 +1153,19 @@ impl<'input, 'arena> PrattParser<'input, 'arena> {
     }
 }
 
+/// Binding power `(left, right)` of an infix operator, as used by the parser.
+///
+/// Exposed to the rest of the crate so that code rendering ASTs back to text
+/// agrees with the parser on precedence and associativity.
+pub(crate) fn infix_binding_power(op: &str) -> Option<(u8, u8)> {
+    PrattParser::get_binding_power(op).map(|bp| (bp.left, bp.right))
+}
+
+/// Binding power of a prefix operator (`+`, `-`, `~`), as used by the parser.
+pub(crate) fn prefix_binding_power(op: &str) -> Option<u8> {
+    PrattParser::get_prefix_binding_power(op)
+}
+
 /// Parse an expression string into an AST.
 ///
 /// This is the primary parsing function that requires an explicit arena for memory allocation.
@@ -866,6 +1206,187 @@ pub fn parse_expression<'arena>(
     parse_expression_arena_with_context(input, arena, None, None)
 }
 
+/// Parse an expression string into an AST using the given [`ParseOptions`].
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::engine::{parse_expression_with_options, ParseOptions};
+/// use bumpalo::Bump;
+///
+/// let arena = Bump::new();
+/// let options = ParseOptions {
+///     implicit_multiplication: true,
+///     ..Default::default()
+/// };
+///
+/// let ast = parse_expression_with_options("2x + 3(x + 1)", &arena, &options).unwrap();
+/// assert_eq!(ast.to_expression_string(), "2 * x + 3 * (x + 1)");
+/// ```
+pub fn parse_expression_with_options<'arena>(
+    input: &str,
+    arena: &'arena Bump,
+    options: &ParseOptions,
+) -> Result<AstExpr<'arena>, ExprError> {
+    let mut parser = PrattParser::with_options(input, arena, *options);
+    let mut ast = parser.parse()?;
+    if options.percent_literals {
+        ast = lower_percentages(arena.alloc(ast), arena).clone();
+    }
+    if options.strict_booleans {
+        check_boolean_types(&ast)?;
+    }
+    Ok(ast)
+}
+
+/// Parse an expression string into an AST, interning its names into `symbols`.
+///
+/// Every variable, function, operator and attribute name in the AST is the copy held
+/// by `symbols`, so a name repeated in one or several expressions is stored once and
+/// equal names share an address. `symbols` must use `arena`.
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::engine::{parse_expression_interned, ParseOptions};
+/// use exp_rs::intern::SymbolTable;
+/// use bumpalo::Bump;
+///
+/// let arena = Bump::new();
+/// let mut symbols = SymbolTable::new(&arena);
+/// let options = ParseOptions::default();
+/// parse_expression_interned("x * x + y", &arena, &options, &mut symbols).unwrap();
+/// parse_expression_interned("x - y", &arena, &options, &mut symbols).unwrap();
+/// assert_eq!(symbols.len(), 5); // x, y, *, + and -
+/// ```
+pub fn parse_expression_interned<'arena>(
+    input: &str,
+    arena: &'arena Bump,
+    options: &ParseOptions,
+    symbols: &mut SymbolTable<'arena>,
+) -> Result<AstExpr<'arena>, ExprError> {
+    let mut parser = PrattParser::with_options(input, arena, *options);
+    parser.symbols = Some(core::mem::replace(symbols, SymbolTable::new(arena)));
+    let parsed = parser.parse();
+    if let Some(table) = parser.symbols.take() {
+        *symbols = table;
+    }
+    let mut ast = parsed?;
+    if options.percent_literals {
+        ast = lower_percentages(arena.alloc(ast), arena).clone();
+    }
+    if options.strict_booleans {
+        check_boolean_types(&ast)?;
+    }
+    Ok(ast)
+}
+
+/// `p / 100`, folded when `p` is a number.
+fn percent_fraction<'arena>(percent: &AstExpr<'arena>, arena: &'arena Bump) -> AstExpr<'arena> {
+    match *percent {
+        AstExpr::Constant(value) => AstExpr::Constant(value / 100.0),
+        _ => AstExpr::Function {
+            name: arena.alloc_str("/"),
+            args: arena.alloc_slice_clone(&[percent.clone(), AstExpr::Constant(100.0)]),
+        },
+    }
+}
+
+/// Replaces the one-argument `%` nodes produced under `ParseOptions::percent_literals`.
+fn lower_percentages<'arena>(
+    ast: &'arena AstExpr<'arena>,
+    arena: &'arena Bump,
+) -> &'arena AstExpr<'arena> {
+    crate::visit::rewrite_ast(ast, arena, &mut |node| match node {
+        AstExpr::Function {
+            name: "%",
+            args: [percent],
+        } => Some(percent_fraction(percent, arena)),
+        _ => None,
+    })
+}
+
+/// Static type of an expression under `ParseOptions::strict_booleans`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ValueType {
+    Number,
+    Boolean,
+}
+
+impl ValueType {
+    fn name(self) -> &'static str {
+        match self {
+            ValueType::Number => "number",
+            ValueType::Boolean => "boolean",
+        }
+    }
+}
+
+/// Infers the type of an expression, failing on any mix of numbers and booleans.
+fn check_boolean_types(expr: &AstExpr) -> Result<ValueType, ExprError> {
+    let expect = |expected: ValueType, found: ValueType, what: &str| {
+        if expected == found {
+            Ok(())
+        } else {
+            Err(ExprError::TypeError {
+                operand: what.to_string(),
+                expected: expected.name(),
+                found: found.name(),
+            })
+        }
+    };
+
+    match expr {
+        AstExpr::Constant(_)
+        | AstExpr::Variable(_)
+        | AstExpr::Array { .. }
+        | AstExpr::Attribute { .. } => Ok(ValueType::Number),
+        AstExpr::LogicalOp { op, left, right } => {
+            let what = format!("operand of '{}'", op);
+            expect(ValueType::Boolean, check_boolean_types(left)?, &what)?;
+            expect(ValueType::Boolean, check_boolean_types(right)?, &what)?;
+            Ok(ValueType::Boolean)
+        }
+        AstExpr::Conditional {
+            condition,
+            true_branch,
+            false_branch,
+        } => {
+            let cond = check_boolean_types(condition)?;
+            expect(ValueType::Boolean, cond, "condition of '?:'")?;
+            let t = check_boolean_types(true_branch)?;
+            expect(t, check_boolean_types(false_branch)?, "else branch of '?:'")?;
+            Ok(t)
+        }
+        AstExpr::Function { name, args } => {
+            let mut types = Vec::with_capacity(args.len());
+            for arg in args.iter() {
+                types.push(check_boolean_types(arg)?);
+            }
+            match (*name, types.as_slice()) {
+                ("==" | "!=" | "<>", [l, r]) => {
+                    expect(*l, *r, &format!("right operand of '{}'", name))?;
+                    Ok(ValueType::Boolean)
+                }
+                ("<" | ">" | "<=" | ">=", [l, r]) => {
+                    let what = format!("operand of '{}'", name);
+                    expect(ValueType::Number, *l, &what)?;
+                    expect(ValueType::Number, *r, &what)?;
+                    Ok(ValueType::Boolean)
+                }
+                ("," | ";", [.., last]) => Ok(*last),
+                _ => {
+                    let what = format!("argument of '{}'", name);
+                    for t in &types {
+                        expect(ValueType::Number, *t, &what)?;
+                    }
+                    Ok(ValueType::Number)
+                }
+            }
+        }
+    }
+}
+
 /// Parse an expression with function parameters that should be treated as variables.
 ///
 /// This function is specifically designed for parsing expression function bodies where
@@ -898,6 +1419,75 @@ pub fn parse_expression_with_parameters<'arena>(
     parse_expression_arena_with_context(input, arena, Some(parameters), None)
 }
 
+/// Parse an expression function body like [`parse_expression_with_parameters`], using
+/// the given [`ParseOptions`].
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::engine::{ParseOptions, parse_expression_with_parameters_and_options};
+/// use bumpalo::Bump;
+///
+/// let arena = Bump::new();
+/// let params = vec!["x".to_string()];
+/// let strict = ParseOptions {
+///     strict_arity: true,
+///     ..Default::default()
+/// };
+/// assert!(parse_expression_with_parameters_and_options("pow(x)", &arena, &params, &strict).is_err());
+/// ```
+pub fn parse_expression_with_parameters_and_options<'arena>(
+    input: &str,
+    arena: &'arena Bump,
+    parameters: &[String],
+    options: &ParseOptions,
+) -> Result<AstExpr<'arena>, ExprError> {
+    parse_body(input, arena, parameters, options, None)
+}
+
+/// Parse an expression function body like
+/// [`parse_expression_with_parameters_and_options`], interning its names into `symbols`
+/// as [`parse_expression_interned`] does.
+pub(crate) fn parse_expression_with_parameters_interned<'arena>(
+    input: &str,
+    arena: &'arena Bump,
+    parameters: &[String],
+    options: &ParseOptions,
+    symbols: &mut SymbolTable<'arena>,
+) -> Result<AstExpr<'arena>, ExprError> {
+    parse_body(input, arena, parameters, options, Some(symbols))
+}
+
+fn parse_body<'arena>(
+    input: &str,
+    arena: &'arena Bump,
+    parameters: &[String],
+    options: &ParseOptions,
+    symbols: Option<&mut SymbolTable<'arena>>,
+) -> Result<AstExpr<'arena>, ExprError> {
+    let mut parser =
+        PrattParser::with_reserved_vars_and_context(input, arena, Some(parameters), None, *options);
+    let parsed = match symbols {
+        Some(symbols) => {
+            parser.symbols = Some(core::mem::replace(symbols, SymbolTable::new(arena)));
+            let parsed = parser.parse();
+            if let Some(table) = parser.symbols.take() {
+                *symbols = table;
+            }
+            parsed
+        }
+        None => parser.parse(),
+    };
+    let mut ast = parsed?;
+    if options.percent_literals {
+        ast = lower_percentages(arena.alloc(ast), arena).clone();
+    }
+    if options.strict_booleans {
+        check_boolean_types(&ast)?;
+    }
+    Ok(ast)
+}
+
 /// Parse an expression with reserved variables and context variable names.
 ///
 /// This is the most configurable parsing function that allows specifying both:
@@ -922,8 +1512,13 @@ pub fn parse_expression_arena_with_context<'arena>(
     // Comparison operators (<, >, <=, >=, ==, !=) are also supported
 
     // The lexer now properly handles decimal numbers starting with a dot
-    let mut parser =
-        PrattParser::with_reserved_vars_and_context(input, arena, reserved_vars, context_vars);
+    let mut parser = PrattParser::with_reserved_vars_and_context(
+        input,
+        arena,
+        reserved_vars,
+        context_vars,
+        ParseOptions::default(),
+    );
     parser.parse()
 }
 
@@ -984,7 +1579,7 @@ pub fn parse_expression_arena_with_context<'arena>(
 ///
 /// match interp("2 + * 3", None) {
 ///     Ok(_) => panic!("Expected an error"),
-///     Err(ExprError::Syntax(_)) => {
+///     Err(ExprError::Syntax { .. }) => {
 ///         // This is expected - there's a syntax error in the expression
 ///     }
 ///     Err(e) => panic!("Unexpected error: {:?}", e),
@@ -1009,6 +1604,232 @@ pub fn interp<'a>(expression: &str, ctx: Option<Rc<EvalContext>>) -> crate::erro
     crate::expression::Expression::eval_with_context(expression, &eval_ctx, &arena)
 }
 
+/// What a completion candidate refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompletionKind {
+    /// A variable or parameter
+    Variable,
+    /// A constant
+    Constant,
+    /// An array, read as `name[index]`
+    Array,
+    /// An object with attributes, read as `name.attribute`
+    Object,
+    /// An attribute of the object before the cursor
+    Attribute,
+    /// A function, with its arity
+    Function(crate::types::FunctionSignature),
+}
+
+/// A name that can be inserted at the cursor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletionCandidate {
+    /// The full name, replacing [`Completion::prefix`]
+    pub name: String,
+    /// What the name refers to
+    pub kind: CompletionKind,
+}
+
+/// The result of [`complete_at`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion<'a> {
+    /// Byte offset in the input where the partial name at the cursor starts
+    pub start: usize,
+    /// The partial name before the cursor, possibly empty
+    pub prefix: &'a str,
+    /// Whether an operand (a number, name or `(`) can follow the text before the
+    /// partial name; otherwise an operator is expected there
+    pub expects_operand: bool,
+    /// Names starting with `prefix`, sorted
+    pub candidates: Vec<CompletionCandidate>,
+}
+
+/// Finds the partial name at byte `offset` of `input` and the names from `ctx` that
+/// complete it, for editors with autocompletion.
+///
+/// After `object.`, the candidates are the attributes of `object` and the arrays and
+/// variables named `object.*`. Elsewhere they are the variables, constants, arrays,
+/// objects and functions of the context and its parents, or none where the text before
+/// the cursor ends with an operand and only an operator could follow. An `offset` past
+/// the end of the input means the end.
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::context::EvalContext;
+/// use exp_rs::engine::{CompletionKind, complete_at};
+///
+/// let mut ctx = EvalContext::new();
+/// ctx.set_parameter("speed", 1.0).unwrap();
+/// ctx.set_attribute("motor", "rpm", 0.0).unwrap();
+///
+/// let completion = complete_at("2 * sq", 6, &ctx);
+/// assert_eq!(completion.prefix, "sq");
+/// assert_eq!(completion.start, 4);
+/// let names: Vec<_> = completion.candidates.iter().map(|c| c.name.as_str()).collect();
+/// assert_eq!(names, ["sqrt"]);
+/// assert!(matches!(&completion.candidates[0].kind, CompletionKind::Function(s) if s.arity == 1));
+///
+/// let completion = complete_at("sp + motor.r", 12, &ctx);
+/// assert_eq!(completion.candidates[0].name, "rpm");
+/// assert_eq!(complete_at("sp", 2, &ctx).candidates[0].name, "speed");
+///
+/// // After an operand only an operator can follow
+/// assert!(!complete_at("speed ", 6, &ctx).expects_operand);
+/// ```
+pub fn complete_at<'a>(input: &'a str, offset: usize, ctx: &EvalContext) -> Completion<'a> {
+    let mut offset = offset.min(input.len());
+    while !input.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let before = &input[..offset];
+    let start = before
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| crate::lexer::is_identifier_continue(*c))
+        .last()
+        .map_or(offset, |(i, _)| i);
+    let prefix = &input[start..offset];
+    let mut completion = Completion {
+        start,
+        prefix,
+        expects_operand: false,
+        candidates: Vec::new(),
+    };
+    if prefix
+        .chars()
+        .next()
+        .is_some_and(|c| !crate::lexer::is_identifier_start(c))
+    {
+        // A number, not a name
+        return completion;
+    }
+
+    let head = &input[..start];
+    if let Some(path) = head.strip_suffix('.') {
+        let base_start = path
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| crate::lexer::is_identifier_continue(*c) || *c == '.')
+            .last()
+            .map_or(path.len(), |(i, _)| i);
+        let base = &path[base_start..];
+        if !base.is_empty() {
+            completion.candidates = member_candidates(base, prefix, ctx);
+        }
+        return completion;
+    }
+
+    completion.expects_operand = match head.trim_end().chars().last() {
+        None => true,
+        Some(c) => !(crate::lexer::is_identifier_continue(c) || matches!(c, ')' | ']' | '.')),
+    };
+    if completion.expects_operand {
+        completion.candidates = name_candidates(prefix, ctx);
+    }
+    completion
+}
+
+/// The contexts consulted for names: `ctx` and its parents.
+fn context_chain(ctx: &EvalContext) -> impl Iterator<Item = &EvalContext> {
+    core::iter::successors(Some(ctx), |c| c.parent.as_deref())
+}
+
+fn name_candidates(prefix: &str, ctx: &EvalContext) -> Vec<CompletionCandidate> {
+    let mut candidates = Vec::new();
+    let mut push = |name: &str, kind: CompletionKind| {
+        if name.starts_with(prefix) && !name.contains('.') {
+            candidates.push(CompletionCandidate {
+                name: name.to_string(),
+                kind,
+            });
+        }
+    };
+    for c in context_chain(ctx) {
+        c.variables
+            .keys()
+            .for_each(|k| push(k, CompletionKind::Variable));
+        c.constants
+            .keys()
+            .for_each(|k| push(k, CompletionKind::Constant));
+        c.arrays.keys().for_each(|k| push(k, CompletionKind::Array));
+        c.array_views
+            .iter()
+            .for_each(|(k, _)| push(k, CompletionKind::Array));
+        c.attributes
+            .keys()
+            .for_each(|k| push(k, CompletionKind::Object));
+        c.objects
+            .iter()
+            .for_each(|(k, _)| push(k, CompletionKind::Object));
+    }
+    for name in ctx.list_functions() {
+        if let Some(signature) = ctx.function_signature(&name) {
+            push(&name, CompletionKind::Function(signature));
+        }
+    }
+    sort_candidates(candidates)
+}
+
+fn member_candidates(base: &str, prefix: &str, ctx: &EvalContext) -> Vec<CompletionCandidate> {
+    let mut candidates = Vec::new();
+    let mut push = |name: &str, kind: CompletionKind| {
+        if name.starts_with(prefix) {
+            candidates.push(CompletionCandidate {
+                name: name.to_string(),
+                kind,
+            });
+        }
+    };
+    // Members of `base` stored under dotted names, up to the next dot
+    let mut push_path = |path: &str, kind: CompletionKind| {
+        if let Some(member) = path
+            .strip_prefix(base)
+            .and_then(|rest| rest.strip_prefix('.'))
+        {
+            match member.split_once('.') {
+                Some((object, _)) => push(object, CompletionKind::Object),
+                None => push(member, kind),
+            }
+        }
+    };
+    for c in context_chain(ctx) {
+        c.variables
+            .keys()
+            .for_each(|k| push_path(k, CompletionKind::Attribute));
+        c.arrays
+            .keys()
+            .for_each(|k| push_path(k, CompletionKind::Array));
+        c.attributes
+            .keys()
+            .for_each(|k| push_path(k, CompletionKind::Object));
+    }
+    if let Some(attributes) = ctx.get_attribute_map(base) {
+        for attr in attributes.keys() {
+            push(attr, CompletionKind::Attribute);
+        }
+    }
+    sort_candidates(candidates)
+}
+
+fn sort_candidates(mut candidates: Vec<CompletionCandidate>) -> Vec<CompletionCandidate> {
+    let rank = |kind: &CompletionKind| match kind {
+        CompletionKind::Variable => 0,
+        CompletionKind::Constant => 1,
+        CompletionKind::Array => 2,
+        CompletionKind::Object => 3,
+        CompletionKind::Attribute => 4,
+        CompletionKind::Function(_) => 5,
+    };
+    candidates.sort_by(|a, b| {
+        a.name
+            .cmp(&b.name)
+            .then_with(|| rank(&a.kind).cmp(&rank(&b.kind)))
+    });
+    candidates.dedup();
+    candidates
+}
+
 #[cfg(test)]
 use std::format;
 #[cfg(test)]
@@ -1536,6 +2357,277 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_implicit_multiplication() {
+        let arena = Bump::new();
+        let options = ParseOptions {
+            implicit_multiplication: true,
+            ..Default::default()
+        };
+        let parse = |input| {
+            parse_expression_with_options(input, &arena, &options)
+                .map(|ast| ast.to_expression_string())
+        };
+
+        assert_eq!(parse("2x").unwrap(), "2 * x");
+        assert_eq!(parse("2x^2").unwrap(), "2 * x^2");
+        assert_eq!(parse("3(x+1)").unwrap(), "3 * (x + 1)");
+        assert_eq!(parse("2 sin(x)").unwrap(), "2 * sin(x)");
+        assert_eq!(parse("(a+b)(c-d)").unwrap(), "(a + b) * (c - d)");
+        assert_eq!(parse("a b c").unwrap(), "a * b * c");
+        assert_eq!(parse("1/2x").unwrap(), "1 / 2 * x");
+        assert_eq!(parse("-2x + y").unwrap(), "-2 * x + y");
+        // A variable followed by `(` is still a call
+        assert_eq!(parse("f(x)").unwrap(), "f(x)");
+        // A number can't follow an operand
+        assert!(parse("x 2").is_err());
+
+        // Disabled by default
+        assert!(parse_expression("2x", &arena).is_err());
+        assert!(parse_expression("3(x+1)", &arena).is_err());
+
+        let ctx = Rc::new({
+            let mut ctx = EvalContext::new();
+            ctx.set_parameter("x", 4.0).unwrap();
+            ctx
+        });
+        let mut expr = crate::Expression::new(&arena);
+        expr.set_parse_options(options);
+        expr.add_expression("2x + 3(x - 1)").unwrap();
+        expr.eval(&ctx).unwrap();
+        assert_eq!(expr.get_result(0), Some(17.0));
+    }
+
+    #[test]
+    fn test_strict_arity() {
+        let arena = Bump::new();
+        let strict = ParseOptions {
+            strict_arity: true,
+            ..Default::default()
+        };
+
+        // By default the second argument is filled in
+        assert_eq!(interp("pow(3)", None).unwrap(), 9.0);
+        assert_eq!(
+            parse_expression("atan2(y)", &arena)
+                .unwrap()
+                .to_expression_string(),
+            "atan2(y, 1)"
+        );
+
+        for input in ["pow(3)", "1 + atan2(y)"] {
+            match parse_expression_with_options(input, &arena, &strict) {
+                Err(ExprError::InvalidFunctionCall {
+                    name,
+                    expected: 2,
+                    found: 1,
+                }) => assert!(input.contains(name.as_str())),
+                other => panic!("{input}: {other:?}"),
+            }
+        }
+        assert!(parse_expression_with_options("pow(3, 2) + atan2(y, x)", &arena, &strict).is_ok());
+
+        // Expression function bodies use the options of their batch
+        let mut batch = crate::expression::Expression::new(&arena);
+        batch.set_parse_options(strict);
+        batch
+            .register_expression_function("square", &["x"], "pow(x)")
+            .unwrap();
+        batch.add_expression("square(3)").unwrap();
+        assert!(matches!(
+            batch.eval(&Rc::new(EvalContext::new())),
+            Err(ExprError::InvalidFunctionCall { .. })
+        ));
+    }
+
+    #[test]
+    fn test_strict_booleans() {
+        let arena = Bump::new();
+        let options = ParseOptions {
+            strict_booleans: true,
+            ..Default::default()
+        };
+        let parse = |input| parse_expression_with_options(input, &arena, &options);
+
+        assert!(parse("x > 0 && y < 10").is_ok());
+        assert!(parse("a > b ? x + 1 : y").is_ok());
+        assert!(parse("(x > 0) == (y > 0)").is_ok());
+        assert!(parse("x > 0 ? y > 0 : z > 0").is_ok());
+        assert!(parse("max(x, y) * 2").is_ok());
+
+        for input in [
+            "(x > 0) * 5",
+            "x && y > 0",
+            "x ? 1 : 0",
+            "x > 0 ? 1 : y < 0",
+            "(x > 0) == 1",
+            "(a < b) < c",
+            "abs(x > 0)",
+        ] {
+            match parse(input) {
+                Err(ExprError::TypeError { .. }) => {}
+                other => panic!("{} should be a type error, got {:?}", input, other),
+            }
+        }
+        assert_eq!(
+            parse("(x > 0) + 1").unwrap_err().to_string(),
+            "Type error: argument of '+' must be a number, found boolean"
+        );
+
+        // Without the option booleans are still plain numbers
+        assert!(parse_expression("(x > 0) * 5", &arena).is_ok());
+    }
+
+    #[test]
+    fn test_percent_literals() {
+        let arena = Bump::new();
+        let literals = ParseOptions {
+            percent_literals: true,
+            ..Default::default()
+        };
+        let relative = ParseOptions {
+            relative_percent: true,
+            ..literals
+        };
+        let show = |input, options: &ParseOptions| {
+            parse_expression_with_options(input, &arena, options)
+                .unwrap()
+                .to_expression_string()
+        };
+
+        assert_eq!(show("15%", &literals), "0.15");
+        assert_eq!(show("x%", &literals), "x / 100");
+        assert_eq!(show("-50% * y", &literals), "-0.5 * y");
+        assert_eq!(
+            show("max(1%, (a + b)%)", &literals),
+            "max(0.01, (a + b) / 100)"
+        );
+        assert_eq!(show("x + 10%", &literals), "x + 0.1");
+        // Modulo is still available when an operand follows
+        assert_eq!(show("7 % 3", &literals), "7 % 3");
+        assert_eq!(show("7 % (x)", &literals), "7 % x");
+
+        assert_eq!(show("x + 10%", &relative), "x * (1 + 0.1)");
+        assert_eq!(show("price - rate%", &relative), "price * (1 - rate / 100)");
+        assert_eq!(show("x + 10% * 2", &relative), "x + 0.1 * 2");
+        let ast =
+            arena.alloc(parse_expression_with_options("200 - 10%", &arena, &relative).unwrap());
+        assert_eq!(crate::eval::eval_ast(ast, None, &arena).unwrap(), 180.0);
+
+        // Without the option a trailing % is a syntax error
+        assert!(parse_expression("15%", &arena).is_err());
+    }
+
+    #[test]
+    fn test_caret_operator() {
+        let arena = Bump::new();
+        let xor = ParseOptions {
+            caret: CaretOperator::Xor,
+            ..Default::default()
+        };
+        let show = |input, options: &ParseOptions| {
+            parse_expression_with_options(input, &arena, options)
+                .unwrap()
+                .to_expression_string()
+        };
+        let eval = |input, options: &ParseOptions| {
+            let ast = arena.alloc(parse_expression_with_options(input, &arena, options).unwrap());
+            crate::eval::eval_ast(ast, None, &arena).unwrap()
+        };
+
+        // By default `^` and `**` raise to a power
+        assert_eq!(interp("2^3^2", None).unwrap(), 512.0);
+        assert_eq!(interp("2**3", None).unwrap(), 8.0);
+        assert_eq!(interp("-2**2", None).unwrap(), -4.0);
+
+        // As xor, `^` is left-associative between `|` and `&`
+        assert_eq!(show("a ^ b ^ c", &xor), "bitxor(bitxor(a, b), c)");
+        assert_eq!(show("a | b ^ c & d", &xor), "a | bitxor(b, c & d)");
+        assert_eq!(show("a ^ b == c", &xor), "bitxor(a, b == c)");
+        assert_eq!(show("-a ^ b + 1", &xor), "bitxor(-a, b + 1)");
+        assert_eq!(eval("6 ^ 3", &xor), 5.0);
+        assert_eq!(eval("-1 ^ 255", &xor), -256.0);
+        assert_eq!(eval("2 ** 3 ^ 1", &xor), 9.0);
+        assert!(eval("1.5 ^ 1", &xor).is_nan());
+
+        // Disabled operators are syntax errors at their position
+        let no_caret = ParseOptions {
+            caret: CaretOperator::Error,
+            ..Default::default()
+        };
+        assert!(matches!(
+            parse_expression_with_options("x ^ 2", &arena, &no_caret),
+            Err(ExprError::Syntax {
+                position: Some(2),
+                ..
+            })
+        ));
+        assert_eq!(show("x ** 2 + pow(x, 2)", &no_caret), "x**2 + pow(x, 2)");
+        let no_double_star = ParseOptions {
+            disable_double_star: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            parse_expression_with_options("2 * x ** 2", &arena, &no_double_star),
+            Err(ExprError::Syntax {
+                position: Some(6),
+                ..
+            })
+        ));
+        assert_eq!(show("x ^ 2", &no_double_star), "x^2");
+    }
+
+    #[test]
+    fn test_parser_limits() {
+        let arena = Bump::new();
+        let with_limits = |limits: ParserLimits| ParseOptions {
+            limits,
+            ..Default::default()
+        };
+        let parse = |input: &str, limits| {
+            parse_expression_with_options(input, &arena, &with_limits(limits))
+        };
+        let defaults = ParserLimits::default();
+
+        // Each limit is inclusive
+        let length = ParserLimits {
+            max_length: 9,
+            ..defaults
+        };
+        assert!(parse("a + b * c", length).is_ok());
+        assert!(parse("a + b * cd", length).is_err());
+
+        let tokens = ParserLimits {
+            max_tokens: 5,
+            ..defaults
+        };
+        assert!(parse("a + b * c", tokens).is_ok());
+        assert!(parse("a + b * c - d", tokens).is_err());
+        assert!(parse("max(a, b, c)", tokens).is_err());
+
+        let token_length = ParserLimits {
+            max_token_length: 4,
+            ..defaults
+        };
+        assert!(parse("abcd + 1234", token_length).is_ok());
+        assert!(parse("abcde + 1", token_length).is_err());
+        assert!(parse("12345 + 1", token_length).is_err());
+
+        let nesting = ParserLimits {
+            max_nesting: 3,
+            ..defaults
+        };
+        assert!(parse("(a)", nesting).is_ok());
+        assert!(matches!(
+            parse("(((a)))", nesting),
+            Err(ExprError::RecursionLimit { limit: 3, .. })
+        ));
+
+        // The default length limit is unchanged
+        let long = "1+".repeat(5000) + "1";
+        assert!(parse_expression(&long, &arena).is_err());
+    }
+
     #[test]
     #[cfg(feature = "libm")] // This test requires libm for built-in sin/asin
     fn test_function_recognition() {
@@ -1830,4 +2922,101 @@ mod tests {
         let result2 = interp("pow(2)", None).unwrap();
         assert_eq!(result2, 4.0); // pow(2, 2) = 4.0
     }
+
+    #[test]
+    fn test_complete_at() {
+        let mut parent = EvalContext::new();
+        parent.set_parameter("gain", 2.0).unwrap();
+        parent.set_array("gains", vec![1.0, 2.0]).unwrap();
+        let mut ctx = EvalContext::new();
+        ctx.parent = Some(Rc::new(parent));
+        ctx.set_parameter("gain", 3.0).unwrap();
+        ctx.set_array("imu.gyro", vec![0.0; 3]).unwrap();
+        ctx.set_parameter("imu.accel.x", 0.0).unwrap();
+        ctx.set_attribute("imu", "temp", 20.0).unwrap();
+
+        let names = |input: &str, offset: usize| -> Vec<(String, CompletionKind)> {
+            complete_at(input, offset, &ctx)
+                .candidates
+                .into_iter()
+                .map(|c| (c.name, c.kind))
+                .collect()
+        };
+
+        // Parent names are included once; dotted names only after their object
+        assert_eq!(
+            names("1 + ga", 6),
+            [
+                ("gain".to_string(), CompletionKind::Variable),
+                ("gains".to_string(), CompletionKind::Array),
+            ]
+        );
+        assert_eq!(
+            names("imu", 3),
+            [("imu".to_string(), CompletionKind::Object)]
+        );
+        assert_eq!(
+            names("imu.", 4),
+            [
+                ("accel".to_string(), CompletionKind::Object),
+                ("gyro".to_string(), CompletionKind::Array),
+                ("temp".to_string(), CompletionKind::Attribute),
+            ]
+        );
+        assert_eq!(
+            names("imu.accel.", 10),
+            [("x".to_string(), CompletionKind::Attribute)]
+        );
+
+        // The cursor may be in the middle of the input or past its end
+        let completion = complete_at("max(gai, 1)", 7, &ctx);
+        assert_eq!((completion.start, completion.prefix), (4, "gai"));
+        assert!(completion.expects_operand);
+        assert_eq!(complete_at("ga", 99, &ctx).prefix, "ga");
+        assert_eq!(complete_at("ü", 1, &ctx).prefix, "");
+
+        // Nothing to complete in numbers or where an operator is expected
+        assert!(names("2e", 2).is_empty());
+        assert!(!complete_at("(gain) ", 7, &ctx).expects_operand);
+        assert!(names("gain g", 6).is_empty());
+        assert!(complete_at("", 0, &ctx).expects_operand);
+    }
+
+    #[cfg(feature = "macros")]
+    #[test]
+    fn test_expr_macro_matches_parser() {
+        use std::rc::Rc;
+
+        // The macro has its own copy of the grammar; both must produce the same AST
+        let arena = Bump::new();
+        let same = |ast: &AstExpr, source: &str| {
+            let parsed = parse_expression(source, &arena).unwrap();
+            assert_eq!(format!("{:?}", ast), format!("{:?}", parsed), "{source}");
+        };
+        same(
+            crate::expr!("-2^3**.5e1 * x % 4 - y"),
+            "-2^3**.5e1 * x % 4 - y",
+        );
+        same(
+            crate::expr!("a.b.c[i:j+1] + pow(x) - atan2(y)"),
+            "a.b.c[i:j+1] + pow(x) - atan2(y)",
+        );
+        same(
+            crate::expr!("x > 0 && y <= 1 || ~z != 2 ? max(x, 1e400) : (1, 2; 3)"),
+            "x > 0 && y <= 1 || ~z != 2 ? max(x, 1e400) : (1, 2; 3)",
+        );
+        same(
+            crate::expr!(r"ns::f(1 << 2 >>> 3 & 4 | 5)"),
+            "ns::f(1 << 2 >>> 3 & 4 | 5)",
+        );
+
+        let mut ctx = EvalContext::new();
+        ctx.set_parameter("x", 3.0).unwrap();
+        let value = crate::eval::eval_ast(
+            crate::expr!("x > 2 ? x * 2 : 0"),
+            Some(Rc::new(ctx)),
+            &arena,
+        );
+        assert_eq!(value.unwrap(), 6.0);
+    }
 }
@@ -5,6 +5,7 @@
 
 extern crate alloc;
 use alloc::string::String;
+use alloc::vec::Vec;
 #[cfg(not(test))]
 use core::num::ParseFloatError;
 #[cfg(test)]
@@ -37,14 +38,25 @@ pub enum ExprError {
     /// Error during lexical analysis (tokenization).
     ///
     /// This occurs when the tokenizer encounters invalid tokens or unknown characters
-    /// that cannot be processed. The string contains a detailed error message.
-    Tokenizer(String),
+    /// that cannot be processed.
+    Tokenizer {
+        /// Detailed error message
+        message: String,
+        /// Byte offset of the offending input
+        position: usize,
+    },
 
     /// Error during syntax analysis.
     ///
     /// This occurs when the parser encounters unexpected tokens, incorrect expression
-    /// structure, or other syntax issues. The string contains a detailed error message.
-    Syntax(String),
+    /// structure, or other syntax issues.
+    Syntax {
+        /// Detailed error message
+        message: String,
+        /// Byte offset in the input where the error was detected, if known.
+        /// Errors at the end of the input point just past the last character.
+        position: Option<usize>,
+    },
 
     /// Error for unmatched parentheses in an expression.
     ///
@@ -112,45 +124,194 @@ pub enum ExprError {
     /// General-purpose error for any other error conditions.
     ///
     /// This is used for errors that don't fit into other specific categories.
-    /// The string contains a detailed error message.
-    Other(String),
+    Other {
+        /// Detailed error message
+        message: String,
+    },
 
     /// Error when the recursion limit is exceeded during expression evaluation.
     ///
     /// This usually happens with deeply nested expressions or recursive function calls.
     /// To resolve this, simplify the expression or increase the recursion limit if possible.
-    RecursionLimit(String),
+    RecursionLimit {
+        /// The depth limit that was exceeded
+        limit: usize,
+        /// Detailed error message
+        message: String,
+    },
 
     /// Error when capacity is exceeded for a heapless container.
     ///
     /// This occurs when trying to insert into a full heapless container.
-    /// The string indicates which container type exceeded capacity.
-    CapacityExceeded(&'static str),
+    CapacityExceeded {
+        /// Which container exceeded its capacity
+        container: &'static str,
+    },
 
     /// Error when a string is too long for heapless string buffer.
     ///
     /// This occurs when trying to create a heapless string that exceeds
     /// the maximum string length limit.
-    StringTooLong(String, usize),
+    StringTooLong {
+        /// The string that did not fit
+        value: String,
+        /// Maximum length in bytes
+        max_len: usize,
+    },
 
     /// Error when attempting to add a parameter with a name that already exists.
     ///
     /// This occurs when trying to register a parameter that has already been registered.
-    DuplicateParameter(String),
+    DuplicateParameter {
+        /// Name of the parameter
+        name: String,
+    },
 
     /// Error when attempting to access a parameter by an invalid index.
     ///
     /// This occurs when the provided index is out of bounds for the parameter list.
-    InvalidParameterIndex(usize),
+    InvalidParameterIndex {
+        /// Index that was requested
+        index: usize,
+        /// Number of parameters
+        len: usize,
+    },
+
+    /// Error when the operands of an operation have incompatible physical dimensions.
+    ///
+    /// This is reported by [`UnitRegistry::check`](crate::units::UnitRegistry::check),
+    /// e.g. when adding a voltage to a time. `left` and `right` are the dimensions in SI
+    /// base units; for functions that need a pure number, `right` is `1`.
+    DimensionMismatch {
+        /// The operator or function being applied
+        operation: String,
+        /// Dimension of the left (or offending) operand
+        left: String,
+        /// Dimension of the right operand, or the required dimension
+        right: String,
+    },
+
+    /// Error when a value of the wrong type is used, e.g. a boolean where a number is
+    /// required under `ParseOptions::strict_booleans`, or a complex number passed to a
+    /// real-valued function.
+    TypeError {
+        /// Description of the offending operand, e.g. `argument of '+'`
+        operand: String,
+        /// The type that was required
+        expected: &'static str,
+        /// The type that was found
+        found: &'static str,
+    },
+
+    /// Error when named expressions in a batch depend on each other in a cycle.
+    ///
+    /// `cycle` lists the names along the cycle, starting and ending with the same
+    /// name, e.g. `["a", "b", "a"]`.
+    CyclicDependency {
+        /// Expression names forming the cycle
+        cycle: Vec<String>,
+    },
+
+    /// Error when an expression calls a function that the context's
+    /// [`FunctionPolicy`](crate::context::FunctionPolicy) does not permit.
+    FunctionNotPermitted {
+        /// Name of the function that was called
+        name: String,
+    },
+
+    /// Error when the argument validator of a function rejects a call, as reported by
+    /// [`EvalContext::validate_calls`](crate::context::EvalContext::validate_calls).
+    InvalidArguments {
+        /// Name of the function that was called
+        name: String,
+        /// The validator's explanation
+        message: String,
+    },
+
+    /// Error when the watchdog callback of the context aborts the evaluation, see
+    /// [`EvalContext::set_watchdog`](crate::context::EvalContext::set_watchdog).
+    Aborted,
 }
 
 impl ExprError {
-    /// Convert this error to a numeric error code for FFI
+    /// Creates a syntax error without a known input position.
+    pub fn syntax(message: impl Into<String>) -> Self {
+        ExprError::Syntax {
+            message: message.into(),
+            position: None,
+        }
+    }
+
+    /// Creates a syntax error at a byte offset in the input.
+    pub fn syntax_at(message: impl Into<String>, position: usize) -> Self {
+        ExprError::Syntax {
+            message: message.into(),
+            position: Some(position),
+        }
+    }
+
+    /// Returns the byte offset in the input that the error refers to, if known.
+    ///
+    /// Only errors produced while parsing carry a position; evaluation errors refer to
+    /// symbols rather than locations.
+    pub fn position(&self) -> Option<usize> {
+        match self {
+            ExprError::Tokenizer { position, .. } => Some(*position),
+            ExprError::Syntax { position, .. } => *position,
+            ExprError::UnmatchedParenthesis { position, .. } => Some(*position),
+            _ => None,
+        }
+    }
+
+    /// Returns the variable, function, array or parameter name the error refers to, if any.
+    pub fn symbol(&self) -> Option<&str> {
+        match self {
+            ExprError::UnknownVariable { name }
+            | ExprError::UnknownFunction { name }
+            | ExprError::InvalidFunctionCall { name, .. }
+            | ExprError::FunctionNotPermitted { name }
+            | ExprError::InvalidArguments { name, .. }
+            | ExprError::ArrayIndexOutOfBounds { name, .. }
+            | ExprError::DuplicateParameter { name } => Some(name),
+            ExprError::AttributeNotFound { attr, .. } => Some(attr),
+            _ => None,
+        }
+    }
+
+    /// Convert this error to a numeric error code for FFI.
+    ///
+    /// The codes are stable: a code is never reassigned to a different variant, and
+    /// new variants get new codes.
+    ///
+    /// | Code | Variant |
+    /// |------|---------|
+    /// | 1 | `Parse` |
+    /// | 2 | `Tokenizer` |
+    /// | 3 | `Syntax` |
+    /// | 4 | `UnmatchedParenthesis` |
+    /// | 5 | `UnknownVariable` |
+    /// | 6 | `UnknownFunction` |
+    /// | 7 | `InvalidFunctionCall` |
+    /// | 8 | `ArrayIndexOutOfBounds` |
+    /// | 9 | `AttributeNotFound` |
+    /// | 10 | `DivideByZero` |
+    /// | 11 | `RecursionLimit` |
+    /// | 12 | `CapacityExceeded` |
+    /// | 13 | `StringTooLong` |
+    /// | 14 | `DuplicateParameter` |
+    /// | 15 | `InvalidParameterIndex` |
+    /// | 16 | `DimensionMismatch` |
+    /// | 17 | `TypeError` |
+    /// | 18 | `CyclicDependency` |
+    /// | 19 | `FunctionNotPermitted` |
+    /// | 20 | `InvalidArguments` |
+    /// | 21 | `Aborted` |
+    /// | 99 | `Other` |
     pub fn error_code(&self) -> i32 {
         match self {
             ExprError::Parse(_) => 1,
-            ExprError::Tokenizer(_) => 2,
-            ExprError::Syntax(_) => 3,
+            ExprError::Tokenizer { .. } => 2,
+            ExprError::Syntax { .. } => 3,
             ExprError::UnmatchedParenthesis { .. } => 4,
             ExprError::UnknownVariable { .. } => 5,
             ExprError::UnknownFunction { .. } => 6,
@@ -158,12 +319,18 @@ impl ExprError {
             ExprError::ArrayIndexOutOfBounds { .. } => 8,
             ExprError::AttributeNotFound { .. } => 9,
             ExprError::DivideByZero => 10,
-            ExprError::RecursionLimit(_) => 11,
-            ExprError::CapacityExceeded(_) => 12,
-            ExprError::StringTooLong(_, _) => 13,
-            ExprError::DuplicateParameter(_) => 14,
-            ExprError::InvalidParameterIndex(_) => 15,
-            ExprError::Other(_) => 99,
+            ExprError::RecursionLimit { .. } => 11,
+            ExprError::CapacityExceeded { .. } => 12,
+            ExprError::StringTooLong { .. } => 13,
+            ExprError::DuplicateParameter { .. } => 14,
+            ExprError::InvalidParameterIndex { .. } => 15,
+            ExprError::DimensionMismatch { .. } => 16,
+            ExprError::TypeError { .. } => 17,
+            ExprError::CyclicDependency { .. } => 18,
+            ExprError::FunctionNotPermitted { .. } => 19,
+            ExprError::InvalidArguments { .. } => 20,
+            ExprError::Aborted => 21,
+            ExprError::Other { .. } => 99,
         }
     }
 }
@@ -177,8 +344,8 @@ impl fmt::Display for ExprError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             ExprError::Parse(_) => write!(f, "Parse error"),
-            ExprError::Tokenizer(err) => write!(f, "Tokenizer error: {}", err),
-            ExprError::Syntax(err) => write!(f, "Syntax error: {}", err),
+            ExprError::Tokenizer { message, .. } => write!(f, "Tokenizer error: {}", message),
+            ExprError::Syntax { message, .. } => write!(f, "Syntax error: {}", message),
             ExprError::UnmatchedParenthesis { position, found } => {
                 write!(
                     f,
@@ -214,21 +381,68 @@ impl fmt::Display for ExprError {
                 write!(f, "Attribute not found: '{}' in '{}'", attr, base)
             }
             ExprError::DivideByZero => write!(f, "Division by zero"),
-            ExprError::Other(err) => write!(f, "{}", err),
-            ExprError::RecursionLimit(err) => write!(f, "Recursion limit exceeded: {}", err),
-            ExprError::CapacityExceeded(container_type) => {
-                write!(f, "Capacity exceeded for {}", container_type)
+            ExprError::Other { message } => write!(f, "{}", message),
+            ExprError::RecursionLimit { message, .. } => {
+                write!(f, "Recursion limit exceeded: {}", message)
+            }
+            ExprError::CapacityExceeded { container } => {
+                write!(f, "Capacity exceeded for {}", container)
+            }
+            ExprError::StringTooLong { value, max_len } => write!(
+                f,
+                "String too long for heapless buffer (max {} chars): '{}'",
+                max_len, value
+            ),
+            ExprError::DuplicateParameter { name } => {
+                write!(f, "Parameter '{}' already exists", name)
+            }
+            ExprError::InvalidParameterIndex { index, .. } => {
+                write!(f, "Invalid parameter index: {}", index)
+            }
+            ExprError::DimensionMismatch {
+                operation,
+                left,
+                right,
+            } => write!(
+                f,
+                "Dimension mismatch in '{}': {} vs {}",
+                operation, left, right
+            ),
+            ExprError::TypeError {
+                operand,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Type error: {} must be a {}, found {}",
+                operand, expected, found
+            ),
+            ExprError::CyclicDependency { cycle } => {
+                write!(f, "Cyclic dependency: {}", cycle.join(" -> "))
+            }
+            ExprError::FunctionNotPermitted { name } => {
+                write!(f, "Function not permitted: '{}'", name)
+            }
+            ExprError::InvalidArguments { name, message } => {
+                write!(f, "Invalid arguments to '{}': {}", name, message)
             }
-            ExprError::StringTooLong(s, max_len) => write!(f, "String too long for heapless buffer (max {} chars): '{}'", max_len, s),
-            ExprError::DuplicateParameter(name) => write!(f, "Parameter '{}' already exists", name),
-            ExprError::InvalidParameterIndex(idx) => write!(f, "Invalid parameter index: {}", idx),
+            ExprError::Aborted => write!(f, "Evaluation aborted"),
+        }
+    }
+}
+
+impl core::error::Error for ExprError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            ExprError::Parse(err) => Some(err),
+            _ => None,
         }
     }
 }
 
 impl From<String> for ExprError {
     fn from(err: String) -> ExprError {
-        ExprError::Other(err)
+        ExprError::Other { message: err }
     }
 }
 
@@ -237,3 +451,32 @@ impl From<ParseFloatError> for ExprError {
         ExprError::Parse(err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::parse_expression;
+    use bumpalo::Bump;
+    use std::error::Error;
+
+    #[test]
+    fn test_structured_fields() {
+        let arena = Bump::new();
+        let err = parse_expression("1 + )", &arena).unwrap_err();
+        assert_eq!(err.error_code(), 3);
+        assert_eq!(err.position(), Some(4));
+        assert!(err.symbol().is_none());
+
+        let err = ExprError::InvalidFunctionCall {
+            name: "atan2".to_string(),
+            expected: 2,
+            found: 1,
+        };
+        assert_eq!(err.symbol(), Some("atan2"));
+        assert_eq!(err.position(), None);
+
+        let err: ExprError = "2.x".parse::<f64>().unwrap_err().into();
+        assert!(err.source().is_some());
+        assert!(ExprError::DivideByZero.source().is_none());
+    }
+}
@@ -0,0 +1,216 @@
+//! Evaluation reporting the floating-point exceptions raised along the way.
+//!
+//! [`eval_checked`] evaluates an expression once and returns its value with the
+//! [`FpFlags`] of every intermediate step, so an overflow that a later `min` or
+//! comparison hides from the final value is still noticed. Rust has no portable access
+//! to the status flags of the floating-point unit, so the flags are inferred from the
+//! arguments and result of each function call, operators included, observed through
+//! [`EvalEngine::set_on_function_call`]:
+//!
+//! - invalid: a NaN result from arguments that are not NaN, as of `0/0` or `sqrt(-1)`
+//! - division by zero: an infinite result from finite arguments one of which is zero, as
+//!   of `1/0` or `pow(0, -1)`
+//! - overflow: an infinite result from finite arguments none of which is zero
+//! - underflow: a subnormal result from arguments that are not subnormal, or a zero
+//!   result of `*`, `/`, a power or `exp` from non-zero finite arguments
+//!
+//! A call with a NaN argument raises no flag, so NaN and missing values propagate
+//! quietly, as in IEEE 754.
+
+use crate::Real;
+use crate::context::EvalContext;
+use crate::error::ExprError;
+use crate::eval::iterative::EvalEngine;
+use crate::types::AstExpr;
+use alloc::rc::Rc;
+use core::cell::Cell;
+
+/// IEEE 754 exceptions raised while evaluating an expression.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct FpFlags {
+    /// A finite result was too large and became infinite
+    pub overflow: bool,
+    /// A non-zero result was too small for a normal number and lost precision
+    pub underflow: bool,
+    /// An operation had no meaningful result and gave NaN
+    pub invalid: bool,
+    /// An exact infinite result was produced from finite operands
+    pub div_by_zero: bool,
+}
+
+impl FpFlags {
+    /// Whether any flag is set.
+    pub fn any(&self) -> bool {
+        self.overflow || self.underflow || self.invalid || self.div_by_zero
+    }
+
+    /// The flags of a call of `name` with `args` that gave `result`.
+    fn of_call(name: &str, args: &[Real], result: Real) -> Self {
+        let mut flags = FpFlags::default();
+        if args.iter().any(|arg| arg.is_nan()) {
+            return flags;
+        }
+        let finite = args.iter().all(|arg| arg.is_finite());
+        if result.is_nan() {
+            flags.invalid = true;
+        } else if result.is_infinite() && finite {
+            if args.contains(&0.0) {
+                flags.div_by_zero = true;
+            } else {
+                flags.overflow = true;
+            }
+        } else if result.is_subnormal() {
+            flags.underflow = !args.iter().any(|arg| arg.is_subnormal());
+        } else if result == 0.0 && finite && !args.contains(&0.0) {
+            flags.underflow =
+                matches!(name, "*" | "/" | "mul" | "div" | "^" | "**" | "pow" | "exp");
+        }
+        flags
+    }
+}
+
+impl core::ops::BitOr for FpFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        FpFlags {
+            overflow: self.overflow || other.overflow,
+            underflow: self.underflow || other.underflow,
+            invalid: self.invalid || other.invalid,
+            div_by_zero: self.div_by_zero || other.div_by_zero,
+        }
+    }
+}
+
+/// A value together with the floating-point exceptions raised computing it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CheckedValue {
+    /// The value of the expression
+    pub value: Real,
+    /// The exceptions raised by any step of the evaluation, including steps whose
+    /// result did not affect the value
+    pub flags: FpFlags,
+}
+
+/// Evaluates `ast` and returns its value with the floating-point exceptions raised by
+/// its steps.
+///
+/// Operands skipped by `?:`, `&&` and `||` are not evaluated and raise nothing.
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::engine::parse_expression;
+/// use exp_rs::eval::eval_checked;
+/// use exp_rs::EvalContext;
+/// use bumpalo::Bump;
+/// use std::rc::Rc;
+///
+/// let arena = Bump::new();
+/// let mut ctx = EvalContext::new();
+/// ctx.set_parameter("gain", 1e300).unwrap();
+/// let ast = arena.alloc(parse_expression("min(gain * gain, 100)", &arena).unwrap());
+///
+/// let checked = eval_checked(ast, Some(Rc::new(ctx)), &arena).unwrap();
+/// // The final value looks fine, but the product overflowed on the way
+/// assert_eq!(checked.value, 100.0);
+/// assert!(checked.flags.overflow);
+/// assert!(!checked.flags.invalid);
+/// ```
+pub fn eval_checked<'arena>(
+    ast: &'arena AstExpr<'arena>,
+    ctx: Option<Rc<EvalContext>>,
+    arena: &'arena bumpalo::Bump,
+) -> Result<CheckedValue, ExprError> {
+    let flags = Rc::new(Cell::new(FpFlags::default()));
+    let recorder = flags.clone();
+
+    let mut engine = EvalEngine::new(arena);
+    engine.set_on_function_call(move |name, args, result| {
+        recorder.set(recorder.get() | FpFlags::of_call(name, args, result));
+    });
+    let value = engine.eval(ast, ctx)?;
+    Ok(CheckedValue {
+        value,
+        flags: flags.get(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::parse_expression;
+    use bumpalo::Bump;
+
+    #[test]
+    fn test_eval_checked_flags() {
+        let arena = Bump::new();
+        let check = |input: &str| {
+            let ast = arena.alloc(parse_expression(input, &arena).unwrap());
+            eval_checked(ast, None, &arena).unwrap()
+        };
+        let flags = |input: &str| check(input).flags;
+
+        assert_eq!(flags("1 + 2 * 3 - 4 / 8"), FpFlags::default());
+
+        assert_eq!(
+            flags("0 / 0"),
+            FpFlags {
+                invalid: true,
+                ..Default::default()
+            }
+        );
+        assert!(flags("sqrt(-1)").invalid);
+        assert_eq!(
+            flags("1 / 0"),
+            FpFlags {
+                div_by_zero: true,
+                ..Default::default()
+            }
+        );
+        assert!(flags("pow(0, -1)").div_by_zero);
+        assert_eq!(
+            flags("1e300 * 1e300"),
+            FpFlags {
+                overflow: true,
+                ..Default::default()
+            }
+        );
+        assert!(flags("exp(1000)").overflow);
+        assert!(flags("1e-300 * 1e-300").underflow);
+        assert!(flags("1e-300 / 1e10").underflow);
+        assert!(flags("exp(-1000)").underflow);
+
+        // Exact results are no exceptions
+        assert!(!flags("5 - 5").any());
+        assert!(!flags("floor(0.5) + sin(0)").any());
+        // Infinities and NaNs passed on raise nothing further
+        assert!(!flags("1 / 0 + 1").invalid);
+        assert!(!flags("1 / 0 + 1").overflow);
+        let nan = check("(0 / 0) * 2 + 1");
+        assert!(nan.value.is_nan());
+        assert_eq!(
+            nan.flags,
+            FpFlags {
+                invalid: true,
+                ..Default::default()
+            }
+        );
+
+        // Flags of intermediate steps survive a final value that looks fine
+        let hidden = check("1 / (1e300 * 1e300) + (1 / 0 > 0) + min(0 / 0, 2)");
+        assert_eq!(hidden.value, 3.0);
+        assert!(hidden.flags.overflow && hidden.flags.div_by_zero && hidden.flags.invalid);
+        assert!(!hidden.flags.underflow);
+
+        // Skipped branches are not evaluated
+        assert!(!flags("1 > 0 ? 1 : 1 / 0").any());
+
+        // Errors are reported as usual
+        let ast = arena.alloc(parse_expression("1 / 0 + y", &arena).unwrap());
+        assert!(matches!(
+            eval_checked(ast, None, &arena),
+            Err(ExprError::UnknownVariable { .. })
+        ));
+    }
+}
@@ -12,7 +12,7 @@ use alloc::vec::Vec;
 use heapless::FnvIndexMap;
 
 /// Maximum number of contexts we can track
-const MAX_CONTEXTS: usize = 128;
+const MAX_CONTEXTS: usize = crate::types::EXP_RS_MAX_CONTEXTS;
 
 /// Manages evaluation contexts without recursion
 pub struct ContextStack {
@@ -62,7 +62,9 @@ impl ContextStack {
 
         // Check capacity
         if id >= MAX_CONTEXTS {
-            return Err(ExprError::CapacityExceeded("context stack"));
+            return Err(ExprError::CapacityExceeded {
+                container: "context stack",
+            });
         }
 
         self.next_id += 1;
@@ -89,7 +91,9 @@ impl ContextStack {
         // No parent by default
         self.parent_map
             .insert(id, None)
-            .map_err(|_| ExprError::CapacityExceeded("parent map"))?;
+            .map_err(|_| ExprError::CapacityExceeded {
+                container: "parent map",
+            })?;
 
         Ok(id)
     }
@@ -104,7 +108,9 @@ impl ContextStack {
 
         // Check capacity
         if id >= MAX_CONTEXTS {
-            return Err(ExprError::CapacityExceeded("context stack"));
+            return Err(ExprError::CapacityExceeded {
+                container: "context stack",
+            });
         }
 
         self.next_id += 1;
@@ -123,7 +129,9 @@ impl ContextStack {
         // Set parent relationship
         self.parent_map
             .insert(id, Some(parent_id))
-            .map_err(|_| ExprError::CapacityExceeded("parent map"))?;
+            .map_err(|_| ExprError::CapacityExceeded {
+                container: "parent map",
+            })?;
 
         Ok(id)
     }
@@ -150,6 +158,9 @@ impl ContextStack {
                 if let Some(&value) = ctx.variables.get(name) {
                     return Some(value);
                 }
+                if let Some(value) = ctx.own_typed_value(name) {
+                    return Some(value.to_real());
+                }
 
                 // Check constants
                 if let Some(&value) = ctx.constants.get(name) {
@@ -186,6 +197,9 @@ impl ContextStack {
         if let Some(&value) = ctx.variables.get(name) {
             return Some(value);
         }
+        if let Some(value) = ctx.own_typed_value(name) {
+            return Some(value.to_real());
+        }
 
         // Check constants
         if let Some(&value) = ctx.constants.get(name) {
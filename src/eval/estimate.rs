@@ -0,0 +1,217 @@
+//! Static estimation of the resources an evaluation needs.
+//!
+//! The iterative evaluator fails at runtime when its operation stack grows past the
+//! depth limit (`EXP_RS_MAX_STACK_DEPTH` unless overridden). [`ResourceEstimate`]
+//! computes the peak stack usage from the AST alone, mirroring the operations the
+//! evaluator pushes, so oversized expressions can be rejected when they are loaded.
+
+use crate::types::AstExpr;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+
+/// Resources needed to evaluate an expression, as computed by [`ResourceEstimate::of`]
+/// or [`Expression::resource_estimate`](crate::expression::Expression::resource_estimate).
+///
+/// Stack figures are worst cases over all branches of conditionals and short-circuit
+/// operators. They are `usize::MAX` if an expression function calls itself, since the
+/// recursion depth depends on the arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ResourceEstimate {
+    /// Number of AST nodes, counting the body of each called expression function once
+    pub node_count: usize,
+    /// Peak length of the operation stack, which is what the depth limit applies to
+    pub max_stack_depth: usize,
+    /// Peak number of intermediate values. An array passed whole to an aggregate such
+    /// as `max(samples)` adds its length to this figure.
+    pub max_value_stack: usize,
+    /// Number of context stack entries used, limited by `EXP_RS_MAX_CONTEXTS`
+    pub max_context_stack: usize,
+}
+
+impl ResourceEstimate {
+    /// Estimates the resources needed to evaluate `ast`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use exp_rs::engine::parse_expression;
+    /// use exp_rs::eval::ResourceEstimate;
+    /// use bumpalo::Bump;
+    ///
+    /// let arena = Bump::new();
+    /// let ast = parse_expression("max(a, b * (c + 1))", &arena).unwrap();
+    /// let estimate = ResourceEstimate::of(&ast);
+    /// assert_eq!(estimate.node_count, 7);
+    /// assert!(estimate.fits(exp_rs::types::EXP_RS_MAX_STACK_DEPTH));
+    /// ```
+    pub fn of(ast: &AstExpr<'_>) -> Self {
+        Self::with_functions(ast, &BTreeMap::new())
+    }
+
+    /// Estimates `ast`, following calls to the expression functions in `bodies`.
+    pub(crate) fn with_functions<'b>(ast: &AstExpr<'b>, bodies: &FunctionBodies<'_, 'b>) -> Self {
+        let mut estimator = Estimator {
+            bodies,
+            active: Vec::new(),
+            counted: BTreeSet::new(),
+            node_count: 0,
+        };
+        let (max_stack_depth, max_value_stack) = estimator.visit(ast);
+        Self {
+            node_count: estimator.node_count,
+            max_stack_depth,
+            max_value_stack,
+            max_context_stack: 1,
+        }
+    }
+
+    /// Returns whether an evaluation stays within the depth limit `max_depth` and the
+    /// context stack capacity.
+    pub fn fits(&self, max_depth: usize) -> bool {
+        self.max_stack_depth <= max_depth
+            && self.max_context_stack <= crate::types::EXP_RS_MAX_CONTEXTS
+    }
+
+    /// Combines the estimates of expressions evaluated one after another.
+    pub(crate) fn then(self, other: Self) -> Self {
+        Self {
+            node_count: self.node_count + other.node_count,
+            max_stack_depth: self.max_stack_depth.max(other.max_stack_depth),
+            max_value_stack: self.max_value_stack.max(other.max_value_stack),
+            max_context_stack: self.max_context_stack.max(other.max_context_stack),
+        }
+    }
+}
+
+/// Parsed bodies of expression functions, by function name.
+pub(crate) type FunctionBodies<'n, 'b> = BTreeMap<&'n str, &'b AstExpr<'b>>;
+
+struct Estimator<'m, 'n, 'b> {
+    bodies: &'m FunctionBodies<'n, 'b>,
+    /// Expression functions being visited, to detect recursion
+    active: Vec<&'b str>,
+    /// Expression functions whose body nodes were counted
+    counted: BTreeSet<&'b str>,
+    node_count: usize,
+}
+
+impl<'b> Estimator<'_, '_, 'b> {
+    /// Returns the peak operation and value stack usage of evaluating `expr`, relative
+    /// to the stack lengths when its evaluation starts.
+    fn visit(&mut self, expr: &AstExpr<'b>) -> (usize, usize) {
+        self.node_count += 1;
+        match expr {
+            AstExpr::Constant(_) | AstExpr::Variable(_) | AstExpr::Attribute { .. } => (0, 1),
+            // The access operation stays below the index evaluation
+            AstExpr::Array { index, .. } => {
+                let (ops, values) = self.visit(index);
+                (ops.saturating_add(1), values)
+            }
+            AstExpr::LogicalOp { left, right, .. } => self.short_circuit(left, right),
+            AstExpr::Conditional {
+                condition,
+                true_branch,
+                false_branch,
+            } => {
+                let (cond_ops, cond_values) = self.visit(condition);
+                let (true_ops, true_values) = self.visit(true_branch);
+                let (false_ops, false_values) = self.visit(false_branch);
+                (
+                    cond_ops.saturating_add(1).max(true_ops).max(false_ops),
+                    cond_values.max(true_values).max(false_values),
+                )
+            }
+            AstExpr::Function { name, args } => match (*name, args.len()) {
+                ("&&" | "||", 2) => self.short_circuit(&args[0], &args[1]),
+                ("elapsed", 1) => {
+                    // The timer name is not evaluated
+                    self.node_count += 1;
+                    (0, 1)
+                }
+                _ => self.call(name, args),
+            },
+        }
+    }
+
+    /// A call pushes its application followed by all arguments, which are then
+    /// evaluated first to last with their values accumulating.
+    fn call(&mut self, name: &'b str, args: &[AstExpr<'b>]) -> (usize, usize) {
+        let mut ops = 0usize;
+        let mut values = 1usize;
+        for (i, arg) in args.iter().enumerate() {
+            let (arg_ops, arg_values) = self.visit(arg);
+            ops = ops.max(arg_ops.saturating_add(args.len() - i));
+            values = values.max(arg_values.saturating_add(i));
+        }
+
+        if let Some(&body) = self.bodies.get(name) {
+            if self.active.contains(&name) {
+                return (usize::MAX, usize::MAX);
+            }
+            // Count the body's nodes only the first time it is visited
+            let count_before = self.node_count;
+            self.active.push(name);
+            let (body_ops, body_values) = self.visit(body);
+            self.active.pop();
+            if !self.counted.insert(name) {
+                self.node_count = count_before;
+            }
+            // The body runs above the marker restoring the parameters
+            ops = ops.max(body_ops.saturating_add(1));
+            values = values.max(body_values);
+        }
+        (ops, values)
+    }
+
+    /// `&&` and `||` keep one operation below each operand.
+    fn short_circuit(&mut self, left: &AstExpr<'b>, right: &AstExpr<'b>) -> (usize, usize) {
+        let (left_ops, left_values) = self.visit(left);
+        let (right_ops, right_values) = self.visit(right);
+        (
+            left_ops.max(right_ops).saturating_add(1),
+            left_values.max(right_values),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::EvalContext;
+    use crate::engine::parse_expression;
+    use crate::eval::iterative::EvalEngine;
+    use alloc::format;
+    use alloc::rc::Rc;
+    use bumpalo::Bump;
+
+    #[test]
+    fn test_estimate_matches_evaluator_limit() {
+        let arena = Bump::new();
+        let mut expr = alloc::string::String::from("x");
+        for i in 0..40 {
+            expr = match i % 4 {
+                0 => format!("({expr} + 1)"),
+                1 => format!("max(1, {expr}, 2)"),
+                2 => format!("(x > 0 ? {expr} : 0)"),
+                _ => format!("(1 && {expr})"),
+            };
+        }
+        let ast = parse_expression(&expr, &arena).unwrap();
+        let estimate = ResourceEstimate::of(&ast);
+        assert!(estimate.node_count > 40);
+
+        // The estimate is exact on the taken path: the evaluation fails with a limit
+        // one below it and succeeds with the limit equal to it
+        let mut ctx = EvalContext::new();
+        ctx.set_parameter("x", 1.0).unwrap();
+        let ctx = Rc::new(ctx);
+        let mut engine = EvalEngine::new(&arena);
+        engine.set_max_depth(Some(estimate.max_stack_depth));
+        assert!(engine.eval(&ast, Some(ctx.clone())).is_ok());
+        engine.set_max_depth(Some(estimate.max_stack_depth - 1));
+        assert!(engine.eval(&ast, Some(ctx)).is_err());
+
+        assert!(estimate.fits(estimate.max_stack_depth));
+        assert!(!estimate.fits(estimate.max_stack_depth - 1));
+    }
+}
@@ -0,0 +1,334 @@
+//! Evaluation with a per-node explanation of the result.
+//!
+//! [`eval_explain`] evaluates an expression once, recording the value of every AST node
+//! through [`EvalEngine::set_on_node_eval`], and returns the AST as a tree of
+//! [`ExplainNode`]s annotated with those values. It is meant for showing users which
+//! sub-expression produced a surprising result, not for hot paths.
+
+use crate::Real;
+use crate::context::EvalContext;
+use crate::error::ExprError;
+use crate::eval::iterative::EvalEngine;
+use crate::types::{AstExpr, LogicalOperator};
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt::Write;
+
+/// One AST node annotated with the outcome of its evaluation.
+#[derive(Debug, Clone)]
+pub struct ExplainNode {
+    /// The sub-expression this node represents, as produced by
+    /// [`AstExpr::to_expression_string`]
+    pub expression: String,
+    /// The value of the sub-expression, or `None` if it was skipped or not reached
+    pub value: Option<Real>,
+    /// The error raised by this node itself, if evaluation failed here
+    pub error: Option<ExprError>,
+    /// Whether the node was not evaluated because of short-circuiting, i.e. it is in
+    /// the untaken branch of `?:` or the right operand of a decided `&&`/`||`
+    pub skipped: bool,
+    /// The node's operands, in source order
+    pub children: Vec<ExplainNode>,
+}
+
+impl ExplainNode {
+    /// Returns the first error in the tree, searching depth-first.
+    pub fn error(&self) -> Option<&ExprError> {
+        self.error
+            .as_ref()
+            .or_else(|| self.children.iter().find_map(ExplainNode::error))
+    }
+
+    /// Serializes the tree as JSON.
+    ///
+    /// Each node becomes an object with the keys `expression`, `value`, `skipped` and
+    /// `children`, plus `error` (the error message) when present. A value that was not
+    /// computed is `null`; NaN and infinities, which JSON numbers cannot represent, are
+    /// written as the strings `"NaN"`, `"inf"` and `"-inf"`.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        self.write_json(&mut out);
+        out
+    }
+
+    fn write_json(&self, out: &mut String) {
+        out.push_str("{\"expression\":");
+        write_json_string(out, &self.expression);
+        out.push_str(",\"value\":");
+        match self.value {
+            None => out.push_str("null"),
+            Some(v) if v.is_nan() => out.push_str("\"NaN\""),
+            Some(v) if v.is_infinite() => {
+                out.push_str(if v > 0.0 { "\"inf\"" } else { "\"-inf\"" })
+            }
+            Some(v) => {
+                let _ = write!(out, "{}", v);
+            }
+        }
+        if let Some(error) = &self.error {
+            out.push_str(",\"error\":");
+            write_json_string(out, &format!("{}", error));
+        }
+        let _ = write!(out, ",\"skipped\":{},\"children\":[", self.skipped);
+        for (i, child) in self.children.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            child.write_json(out);
+        }
+        out.push_str("]}");
+    }
+}
+
+/// Writes `s` as a JSON string literal.
+pub(crate) fn write_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Evaluates `ast` and returns it as a tree annotated with the value of every node.
+///
+/// Evaluation errors do not make this function fail: the node that raised the error
+/// carries it in [`ExplainNode::error`], and it and its ancestors have no value.
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::engine::parse_expression;
+/// use exp_rs::eval::eval_explain;
+/// use exp_rs::EvalContext;
+/// use bumpalo::Bump;
+/// use std::rc::Rc;
+///
+/// let arena = Bump::new();
+/// let mut ctx = EvalContext::new();
+/// ctx.set_parameter("temp", 81.0).unwrap();
+/// ctx.set_parameter("limit", 80.0).unwrap();
+/// let ast = arena.alloc(parse_expression("temp > limit || pressure > 2", &arena).unwrap());
+///
+/// let tree = eval_explain(ast, Some(Rc::new(ctx)), &arena);
+/// assert_eq!(tree.value, Some(1.0));
+/// assert_eq!(tree.children[0].expression, "temp > limit");
+/// assert_eq!(tree.children[0].value, Some(1.0));
+/// // The right operand was never evaluated, so the unknown `pressure` is no error
+/// assert!(tree.children[1].skipped);
+/// ```
+pub fn eval_explain<'arena>(
+    ast: &'arena AstExpr<'arena>,
+    ctx: Option<Rc<EvalContext>>,
+    arena: &'arena bumpalo::Bump,
+) -> ExplainNode {
+    let values: Rc<RefCell<BTreeMap<usize, Real>>> = Rc::new(RefCell::new(BTreeMap::new()));
+    let recorder = values.clone();
+
+    let mut engine = EvalEngine::new(arena);
+    engine.set_on_node_eval(move |node, value| {
+        recorder
+            .borrow_mut()
+            .insert(node as *const AstExpr as usize, value);
+    });
+    let mut error = engine.eval(ast, ctx).err();
+    drop(engine);
+
+    let values = values.borrow();
+    build(ast, &values, false, &mut error)
+}
+
+/// A value together with the inputs that determined it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TracedValue {
+    /// The value of the expression
+    pub value: Real,
+    /// The variables, arrays, attributes and histories read, in the order they were
+    /// first read, as by [`EvalEngine::inputs_read`]
+    pub inputs: Vec<String>,
+}
+
+/// Evaluates `ast` and returns its value with the inputs actually read.
+///
+/// Operands skipped by short-circuiting are not read, so they are not listed: of
+/// `temp > limit || pressure > 2`, a true left operand lists only `temp` and `limit`.
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::engine::parse_expression;
+/// use exp_rs::eval::eval_traced;
+/// use exp_rs::EvalContext;
+/// use bumpalo::Bump;
+/// use std::rc::Rc;
+///
+/// let arena = Bump::new();
+/// let mut ctx = EvalContext::new();
+/// ctx.set_parameter("armed", 0.0).unwrap();
+/// ctx.set_parameter("speed", 12.0).unwrap();
+/// let ast = arena.alloc(parse_expression("armed && speed > 10 ? 1 : 0", &arena).unwrap());
+///
+/// let traced = eval_traced(ast, Some(Rc::new(ctx)), &arena).unwrap();
+/// assert_eq!(traced.value, 0.0);
+/// assert_eq!(traced.inputs, ["armed"]);
+/// ```
+pub fn eval_traced<'arena>(
+    ast: &'arena AstExpr<'arena>,
+    ctx: Option<Rc<EvalContext>>,
+    arena: &'arena bumpalo::Bump,
+) -> Result<TracedValue, ExprError> {
+    let mut engine = EvalEngine::new(arena);
+    engine.set_record_inputs(true);
+    let value = engine.eval(ast, ctx)?;
+    let inputs = engine.inputs_read().unwrap_or_default().to_vec();
+    Ok(TracedValue { value, inputs })
+}
+
+/// Builds the explanation for `expr` in evaluation order, handing the pending error to
+/// the first reachable node that has no value although all its operands do.
+fn build(
+    expr: &AstExpr,
+    values: &BTreeMap<usize, Real>,
+    skipped: bool,
+    error: &mut Option<ExprError>,
+) -> ExplainNode {
+    let value = values.get(&(expr as *const AstExpr as usize)).copied();
+    let mut child = |e: &AstExpr, skip: bool| build(e, values, skipped || skip, error);
+
+    let children = match expr {
+        AstExpr::Constant(_) | AstExpr::Variable(_) | AstExpr::Attribute { .. } => Vec::new(),
+        AstExpr::Function { args, .. } => args.iter().map(|a| child(a, false)).collect(),
+        AstExpr::Array { index, .. } => alloc::vec![child(index, false)],
+        AstExpr::LogicalOp { op, left, right } => {
+            let left = child(left, false);
+            let decided = match (op, left.value) {
+                (LogicalOperator::And, Some(v)) => v == 0.0,
+                (LogicalOperator::Or, Some(v)) => v != 0.0,
+                _ => false,
+            };
+            let right = child(right, decided);
+            alloc::vec![left, right]
+        }
+        AstExpr::Conditional {
+            condition,
+            true_branch,
+            false_branch,
+        } => {
+            let condition = child(condition, false);
+            let taken = condition.value.map(|c| c != 0.0);
+            let true_branch = child(true_branch, taken == Some(false));
+            let false_branch = child(false_branch, taken == Some(true));
+            alloc::vec![condition, true_branch, false_branch]
+        }
+    };
+
+    let operands_done = children.iter().all(|c| c.skipped || c.value.is_some());
+    let own_error = if !skipped && value.is_none() && operands_done {
+        error.take()
+    } else {
+        None
+    };
+
+    ExplainNode {
+        expression: expr.to_expression_string(),
+        value,
+        error: own_error,
+        skipped,
+        children,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::parse_expression;
+    use bumpalo::Bump;
+
+    #[test]
+    fn test_explain_locates_error() {
+        let arena = Bump::new();
+        let mut ctx = EvalContext::new();
+        ctx.set_parameter("x", -1.0).unwrap();
+        let ctx = Rc::new(ctx);
+
+        let ast = arena.alloc(parse_expression("x > 0 ? 1 : max(x, y) * 2", &arena).unwrap());
+        let tree = eval_explain(ast, Some(ctx.clone()), &arena);
+
+        assert_eq!(tree.value, None);
+        assert_eq!(tree.children[0].value, Some(0.0));
+        assert!(tree.children[1].skipped);
+        let product = &tree.children[2];
+        assert!(product.error.is_none());
+        let max = &product.children[0];
+        assert_eq!(max.children[0].value, Some(-1.0));
+        assert!(matches!(
+            max.children[1].error,
+            Some(ExprError::UnknownVariable { ref name }) if name == "y"
+        ));
+        assert!(matches!(
+            tree.error(),
+            Some(ExprError::UnknownVariable { .. })
+        ));
+
+        let ast = arena.alloc(parse_expression("sqrt(x) + 1", &arena).unwrap());
+        let json = eval_explain(ast, Some(ctx), &arena).to_json();
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"expression":"sqrt(x) + 1","value":"NaN","skipped":false,"children":["#,
+                r#"{"expression":"sqrt(x)","value":"NaN","skipped":false,"children":["#,
+                r#"{"expression":"x","value":-1,"skipped":false,"children":[]}]},"#,
+                r#"{"expression":"1","value":1,"skipped":false,"children":[]}]}"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_eval_traced_lists_inputs_read() {
+        let arena = Bump::new();
+        let mut ctx = EvalContext::new();
+        ctx.set_parameter("mode", 1.0).unwrap();
+        ctx.set_parameter("speed", 4.0).unwrap();
+        ctx.set_parameter("limit", 3.0).unwrap();
+        ctx.set_array("gains", alloc::vec![1.0, 2.0, 3.0]).unwrap();
+        ctx.set_attribute("imu", "tilt", 0.5).unwrap();
+        let ctx = Rc::new(ctx);
+        let trace = |expr: &str| {
+            let ast = arena.alloc(parse_expression(expr, &arena).unwrap());
+            eval_traced(ast, Some(ctx.clone()), &arena)
+        };
+
+        // Only the taken branch is read, and each input is listed once
+        let traced =
+            trace("mode ? (max(speed, limit) > limit) * gains[1] + speed : imu.tilt").unwrap();
+        assert_eq!(traced.value, 6.0);
+        assert_eq!(traced.inputs, ["mode", "speed", "limit", "gains"]);
+        let traced = trace("mode == 0 || max(gains) * pi > imu.tilt").unwrap();
+        assert_eq!(traced.inputs, ["mode", "gains", "imu.tilt"]);
+        assert_eq!(trace("mode > 0 || unknown").unwrap().inputs, ["mode"]);
+        assert!(trace("speed + unknown").is_err());
+
+        // Parameters of expression functions are not inputs, their arguments are
+        let mut batch = crate::expression::Expression::new(&arena);
+        batch
+            .register_expression_function("over", &["x"], "x > limit")
+            .unwrap();
+        batch.add_expression("over(speed * 2)").unwrap();
+        batch.set_record_inputs(true);
+        batch.eval(&ctx).unwrap();
+        assert_eq!(batch.inputs_read(0).unwrap(), ["speed", "limit"]);
+        batch.set_record_inputs(false);
+        assert_eq!(batch.inputs_read(0), None);
+    }
+}
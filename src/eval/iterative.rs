@@ -8,19 +8,132 @@ use crate::Real;
 use crate::context::EvalContext;
 use crate::error::ExprError;
 use crate::eval::context_stack::ContextStack;
-use crate::eval::stack_ops::EvalOp;
-use crate::eval::types::{FunctionCacheEntry, OwnedNativeFunction};
+use crate::eval::stack_ops::{EvalOp, TriggerOp, TriggerState};
+use crate::eval::types::FunctionCacheEntry;
+use crate::intern::{Symbol, SymbolTable};
 use crate::types::{AstExpr, FunctionName, HString};
 use crate::types::{TryIntoFunctionName, TryIntoHeaplessString};
 
+use alloc::boxed::Box;
 use alloc::collections::BTreeMap;
 use alloc::format;
 use alloc::rc::Rc;
-use alloc::string::ToString;
+use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 
 /// Maximum depth of the operation stack (prevents runaway evaluation)
-const MAX_STACK_DEPTH: usize = 1000;
+const MAX_STACK_DEPTH: usize = crate::types::EXP_RS_MAX_STACK_DEPTH;
+
+/// Functions that accept an array name as their first argument, standing for all of the
+/// array's elements.
+const ARRAY_FUNCTIONS: &[&str] = &[
+    "min",
+    "max",
+    "argmin",
+    "argmax",
+    #[cfg(feature = "stats")]
+    "mean",
+    #[cfg(feature = "stats")]
+    "variance",
+    #[cfg(feature = "stats")]
+    "stddev",
+    #[cfg(feature = "stats")]
+    "median",
+    #[cfg(feature = "stats")]
+    "percentile",
+];
+
+/// Name of the array an aggregate argument may stand for: a variable, or the path of
+/// an attribute such as `point.coords`.
+fn array_path(arg: &AstExpr<'_>) -> Option<HString> {
+    match arg {
+        AstExpr::Variable(name) => HString::try_from(*name).ok(),
+        AstExpr::Attribute { base, attr } => crate::types::dotted_path(base, attr),
+        _ => None,
+    }
+}
+
+/// Array and bounds of a slice such as `data[2:10]`.
+fn array_slice<'a>(arg: &'a AstExpr<'a>) -> Option<(&'a str, &'a AstExpr<'a>, &'a AstExpr<'a>)> {
+    match arg {
+        AstExpr::Array {
+            name,
+            index:
+                AstExpr::Function {
+                    name: ":",
+                    args: [start, end],
+                },
+        } => Some((name, start, end)),
+        _ => None,
+    }
+}
+
+/// Operations, with their number of arguments, that the `int64` fast path computes in
+/// `i64` when the context has their built-in implementations.
+#[cfg(feature = "int64")]
+const INTEGER_OPERATIONS: &[(&str, usize)] = &[
+    ("neg", 1),
+    ("iabs", 1),
+    ("+", 2),
+    ("-", 2),
+    ("*", 2),
+    ("idiv", 2),
+    ("imod", 2),
+    ("bitand", 2),
+    ("bitor", 2),
+    ("bitxor", 2),
+    ("bitnot", 1),
+    ("getbit", 2),
+    ("setbit", 3),
+    ("popcount", 1),
+    ("<", 2),
+    (">", 2),
+    ("<=", 2),
+    (">=", 2),
+    ("==", 2),
+    ("!=", 2),
+];
+
+/// A step of the `int64` fast path.
+#[cfg(feature = "int64")]
+#[derive(Clone, Copy)]
+enum IntegerStep<'arena> {
+    /// Evaluate an operand
+    Eval(&'arena AstExpr<'arena>),
+    /// Apply one of the [`INTEGER_OPERATIONS`] to the last operands
+    Apply(&'arena str, usize),
+}
+
+/// Computes one of the [`INTEGER_OPERATIONS`], or `None` on overflow or an invalid bit
+/// number.
+#[cfg(feature = "int64")]
+fn integer_operation(name: &str, args: &[i64]) -> Option<i64> {
+    use crate::functions::{floor_div, floor_mod, get_bit, set_bit};
+
+    match (name, args) {
+        ("neg", [a]) => a.checked_neg(),
+        ("iabs", [a]) => a.checked_abs(),
+        ("+", [a, b]) => a.checked_add(*b),
+        ("-", [a, b]) => a.checked_sub(*b),
+        ("*", [a, b]) => a.checked_mul(*b),
+        ("idiv", [a, b]) => floor_div(*a, *b),
+        ("imod", [a, b]) => floor_mod(*a, *b),
+        ("bitand", [a, b]) => Some(a & b),
+        ("bitor", [a, b]) => Some(a | b),
+        ("bitxor", [a, b]) => Some(a ^ b),
+        ("bitnot", [a]) => Some(!a),
+        ("getbit", [a, n]) => get_bit(*a, *n),
+        ("setbit", [a, n, bit]) => set_bit(*a, *n, *bit),
+        ("popcount", [a]) => Some(a.count_ones() as i64),
+        ("<", [a, b]) => Some((a < b) as i64),
+        (">", [a, b]) => Some((a > b) as i64),
+        ("<=", [a, b]) => Some((a <= b) as i64),
+        (">=", [a, b]) => Some((a >= b) as i64),
+        ("==", [a, b]) => Some((a == b) as i64),
+        ("!=", [a, b]) => Some((a != b) as i64),
+        _ => None,
+    }
+}
 
 /// Main iterative evaluation function
 pub fn eval_iterative<'arena>(
@@ -39,6 +152,9 @@ pub struct EvalEngine<'arena> {
 
     /// Operation stack (arena-allocated when arena is available)
     op_stack: bumpalo::collections::Vec<'arena, EvalOp<'arena>>,
+    /// Oldest pending operations, moved off `op_stack` when it is full and spilling is
+    /// enabled, so that the arena stack keeps the capacity reserved at the start
+    op_spill: Vec<EvalOp<'arena>>,
     /// Value stack for intermediate results (arena-allocated when arena is available)
     value_stack: bumpalo::collections::Vec<'arena, Real>,
     /// Shared buffer for function arguments to avoid per-call allocations
@@ -55,12 +171,96 @@ pub struct EvalEngine<'arena> {
     func_cache: BTreeMap<HString, Option<FunctionCacheEntry>>,
     /// Parameter overrides for batch evaluation (avoids context modification)
     param_overrides: Option<crate::types::BatchParamMap>,
+    /// Parameter values, indexed by the symbol of their name
+    param_slots: Vec<Option<Real>>,
+    /// Address and length of each interned slot name with its symbol, sorted, so that
+    /// a name is matched to its slot without comparing strings
+    slot_symbols: Vec<((usize, usize), Symbol)>,
+    /// Names interned by the caller, used to parse expression function bodies so that
+    /// their names match the slots
+    symbols: Option<SymbolTable<'arena>>,
+    /// State of the trigger functions (`rising`, `debounce`, ...), by call, preceded by
+    /// the calls of the expression functions it is in
+    trigger_state: Vec<(Vec<&'arena AstExpr<'arena>>, TriggerState)>,
     /// Optional reference to local expression functions
     local_functions: Option<&'arena core::cell::RefCell<crate::types::ExpressionFunctionMap>>,
-    /// Cache for parsed expression functions
-    expr_func_cache: BTreeMap<HString, &'arena AstExpr<'arena>>,
+    /// Parsed expression function bodies, keyed by their definition hash
+    expr_func_cache: BTreeMap<u64, ParsedBody<'arena>>,
+    /// Debugging hook called with every evaluated node and its value
+    on_node_eval: Option<NodeEvalHook<'arena>>,
+    /// Debugging hook called with every native function call
+    on_function_call: Option<FunctionCallHook<'arena>>,
+    /// Logger of the context being evaluated, if any
+    logger: Option<Rc<dyn crate::log::Logger>>,
+    /// Whether missing values propagate, as set by the context being evaluated
+    propagate_missing: bool,
+    /// Names of the inputs read by the current evaluation, when recording them
+    inputs_read: Option<Vec<String>>,
+    /// Depth limit overriding the one of the evaluation context
+    max_depth: Option<usize>,
+    /// Spill limit overriding the one of the evaluation context
+    stack_spill: Option<usize>,
+    /// Limits of the evaluation in progress, while one is started and not finished
+    running: Option<RunLimits>,
+    /// Watchdog of the context being evaluated, if any
+    watchdog: Option<crate::context::Watchdog>,
+    /// Nodes evaluated since the watchdog was last called, across evaluations
+    watchdog_count: usize,
+    /// Whether the current run may evaluate only a limited number of nodes
+    #[cfg(feature = "int64")]
+    limited_run: bool,
+}
+
+/// An expression function body parsed into the arena, with the definition it was parsed
+/// from, so that a definition whose hash collides with it is not given its tree.
+struct ParsedBody<'arena> {
+    expression: String,
+    params: Vec<String>,
+    parse_options: crate::engine::ParseOptions,
+    ast: &'arena AstExpr<'arena>,
+}
+
+impl ParsedBody<'_> {
+    fn is_parsed_from(&self, func: &crate::types::ExpressionFunction) -> bool {
+        self.expression == func.expression
+            && self.params == func.params
+            && self.parse_options == func.parse_options
+    }
+}
+
+/// Depth limits of an evaluation, fixed when it starts.
+#[derive(Clone, Copy)]
+struct RunLimits {
+    max_depth: usize,
+    stack_spill: Option<usize>,
+    /// Operations moved between the arena stack and the heap at a time
+    spill_chunk: usize,
 }
 
+/// Outcome of [`EvalEngine::resume`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EvalStep {
+    /// The node budget ran out; call `resume` again to continue.
+    Pending,
+    /// The evaluation finished with this result.
+    Done(Real),
+}
+
+/// Adds `name` to the recorded inputs, if recording, unless it is already listed.
+fn record_input(inputs: &mut Option<Vec<String>>, name: &str) {
+    if let Some(inputs) = inputs
+        && !inputs.iter().any(|input| input == name)
+    {
+        inputs.push(name.to_string());
+    }
+}
+
+/// Callback receiving an AST node and the value it evaluated to.
+pub type NodeEvalHook<'arena> = Box<dyn FnMut(&AstExpr<'arena>, Real) + 'arena>;
+
+/// Callback receiving a function name, its arguments and its result.
+pub type FunctionCallHook<'arena> = Box<dyn FnMut(&str, &[Real], Real) + 'arena>;
+
 // Note: Default trait removed since EvalEngine now requires an arena parameter
 
 impl<'arena> EvalEngine<'arena> {
@@ -70,6 +270,7 @@ impl<'arena> EvalEngine<'arena> {
             arena: Some(arena),
 
             op_stack: bumpalo::collections::Vec::new_in(arena),
+            op_spill: Vec::new(),
             value_stack: bumpalo::collections::Vec::new_in(arena),
             arg_buffer: bumpalo::collections::Vec::new_in(arena),
 
@@ -82,11 +283,115 @@ impl<'arena> EvalEngine<'arena> {
             ctx_stack: ContextStack::new(),
             func_cache: BTreeMap::new(),
             param_overrides: None,
+            param_slots: Vec::new(),
+            slot_symbols: Vec::new(),
+            symbols: None,
+            trigger_state: Vec::new(),
             local_functions: None,
             expr_func_cache: BTreeMap::new(),
+            on_node_eval: None,
+            on_function_call: None,
+            logger: None,
+            propagate_missing: false,
+            inputs_read: None,
+            max_depth: None,
+            stack_spill: None,
+            running: None,
+            watchdog: None,
+            watchdog_count: 0,
+            #[cfg(feature = "int64")]
+            limited_run: false,
         }
     }
 
+    /// Set the evaluation depth limit of this engine.
+    ///
+    /// It takes precedence over [`EvalContext::set_max_eval_depth`]; with `None` the
+    /// context's limit applies, and without one `EXP_RS_MAX_STACK_DEPTH`.
+    pub fn set_max_depth(&mut self, depth: Option<usize>) {
+        self.max_depth = depth;
+    }
+
+    /// Set the total depth up to which this engine spills pending operations to the
+    /// heap instead of failing at the depth limit.
+    ///
+    /// It takes precedence over [`EvalContext::set_stack_spill`]; with `None` the
+    /// context's setting applies.
+    pub fn set_stack_spill(&mut self, limit: Option<usize>) {
+        self.stack_spill = limit;
+    }
+
+    /// Install a hook that is called after each AST node is evaluated.
+    ///
+    /// The hook receives the node and its value, children before parents, so the last
+    /// call is for the root. Nodes inside the bodies of expression functions are
+    /// reported too. Branches skipped by `?:`, `&&` and `||` are not reported.
+    ///
+    /// # Examples
+    ///
+    /// Finding where a NaN first appears:
+    ///
+    /// ```
+    /// use exp_rs::engine::parse_expression;
+    /// use exp_rs::eval::iterative::EvalEngine;
+    /// use bumpalo::Bump;
+    /// use std::cell::RefCell;
+    /// use std::rc::Rc;
+    ///
+    /// let arena = Bump::new();
+    /// let ast = arena.alloc(parse_expression("1 + sqrt(2 - 3) * 4", &arena).unwrap());
+    ///
+    /// let first_nan = Rc::new(RefCell::new(None));
+    /// let seen = first_nan.clone();
+    /// let mut engine = EvalEngine::new(&arena);
+    /// engine.set_on_node_eval(move |node, value| {
+    ///     if value.is_nan() && seen.borrow().is_none() {
+    ///         *seen.borrow_mut() = Some(node.to_expression_string());
+    ///     }
+    /// });
+    ///
+    /// assert!(engine.eval(ast, None).unwrap().is_nan());
+    /// assert_eq!(first_nan.borrow().as_deref(), Some("sqrt(2 - 3)"));
+    /// ```
+    pub fn set_on_node_eval<F>(&mut self, hook: F)
+    where
+        F: FnMut(&AstExpr<'arena>, Real) + 'arena,
+    {
+        self.on_node_eval = Some(Box::new(hook));
+    }
+
+    /// Install a hook that is called after each native or resolved function call,
+    /// including operators, with the function name, arguments and result.
+    pub fn set_on_function_call<F>(&mut self, hook: F)
+    where
+        F: FnMut(&str, &[Real], Real) + 'arena,
+    {
+        self.on_function_call = Some(Box::new(hook));
+    }
+
+    /// Remove both debugging hooks.
+    pub fn clear_hooks(&mut self) {
+        self.on_node_eval = None;
+        self.on_function_call = None;
+    }
+
+    /// Record the inputs each evaluation reads, for [`inputs_read`](Self::inputs_read).
+    ///
+    /// Recording allocates the names, so it is off by default.
+    pub fn set_record_inputs(&mut self, record: bool) {
+        self.inputs_read = record.then(Vec::new);
+    }
+
+    /// The variables, arrays, attributes and histories the last evaluation read, in the
+    /// order they were first read, if recording is enabled.
+    ///
+    /// Operands skipped by `?:`, `&&` and `||` are not read and so not listed, and
+    /// neither are the parameters of expression functions, whose arguments are listed
+    /// instead. Builtin constants and unit names are not inputs.
+    pub fn inputs_read(&self) -> Option<&[String]> {
+        self.inputs_read.as_deref()
+    }
+
     /// Set the local expression functions for this evaluation
     pub fn set_local_functions(
         &mut self,
@@ -95,6 +400,26 @@ impl<'arena> EvalEngine<'arena> {
         self.local_functions = functions;
     }
 
+    /// Drop the parsed bodies of expression functions.
+    ///
+    /// Bodies are cached by their definition hash, so redefining a function never uses a
+    /// stale body; this only releases the cache entries of replaced or removed functions.
+    /// The arena memory of the parsed trees is not reclaimed until the arena is reset.
+    pub fn clear_expression_function_cache(&mut self) {
+        self.expr_func_cache.clear();
+    }
+
+    /// Reserve room for `ops` operations and `values` intermediate values, so that
+    /// evaluations within these figures do not grow the stacks. Both are capped at the
+    /// depth limit.
+    pub fn reserve_stacks(&mut self, ops: usize, values: usize) {
+        let limit = self.max_depth.unwrap_or(MAX_STACK_DEPTH);
+        self.op_stack
+            .reserve(ops.min(limit).saturating_sub(self.op_stack.len()));
+        self.value_stack
+            .reserve(values.min(limit).saturating_sub(self.value_stack.len()));
+    }
+
     /// Arena-aware clearing of internal stacks
     /// Uses unsafe set_len(0) to avoid triggering Drop on arena-allocated elements
     fn arena_clear_stacks(&mut self) {
@@ -105,6 +430,7 @@ impl<'arena> EvalEngine<'arena> {
             self.value_stack.set_len(0);
             self.arg_buffer.set_len(0);
         }
+        self.op_spill.clear();
     }
 
     /// Reset the engine for reuse with new expression
@@ -127,37 +453,236 @@ impl<'arena> EvalEngine<'arena> {
         ast: &'arena AstExpr<'arena>,
         ctx: Option<Rc<EvalContext>>,
     ) -> Result<Real, ExprError> {
+        self.start(ast, ctx)?;
+        match self.run(usize::MAX)? {
+            EvalStep::Done(value) => Ok(value),
+            EvalStep::Pending => unreachable!("an unlimited run finishes"),
+        }
+    }
+
+    /// Start a resumable evaluation of an expression, without evaluating any node yet.
+    ///
+    /// [`resume`](Self::resume) then evaluates it a bounded number of nodes at a time,
+    /// so a large expression can be spread over several iterations of a main loop.
+    /// All the state of the evaluation lives in the engine, and starting another
+    /// evaluation, or calling [`eval`](Self::eval), abandons the one in progress.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use exp_rs::context::EvalContext;
+    /// use exp_rs::engine::parse_expression;
+    /// use exp_rs::eval::iterative::{EvalEngine, EvalStep};
+    /// use bumpalo::Bump;
+    /// use std::rc::Rc;
+    ///
+    /// let arena = Bump::new();
+    /// let ast = arena.alloc(parse_expression("sin(x)^2 + cos(x)^2 + x * 10", &arena).unwrap());
+    /// let mut ctx = EvalContext::new();
+    /// ctx.set_parameter("x", 0.5).unwrap();
+    ///
+    /// let mut engine = EvalEngine::new(&arena);
+    /// engine.start_eval(ast, Some(Rc::new(ctx))).unwrap();
+    /// let mut ticks = 0;
+    /// let result = loop {
+    ///     ticks += 1;
+    ///     // At most 4 nodes per tick
+    ///     if let EvalStep::Done(value) = engine.resume(4).unwrap() {
+    ///         break value;
+    ///     }
+    /// };
+    /// assert!((result - 6.0).abs() < 1e-12);
+    /// assert!(ticks > 1);
+    /// ```
+    pub fn start_eval(
+        &mut self,
+        ast: &'arena AstExpr<'arena>,
+        ctx: Option<Rc<EvalContext>>,
+    ) -> Result<(), ExprError> {
+        self.start(ast, ctx)
+    }
+
+    /// Continue the evaluation started by [`start_eval`](Self::start_eval), evaluating
+    /// at most `max_nodes` AST nodes before returning.
+    ///
+    /// Returns [`EvalStep::Pending`] while nodes remain and [`EvalStep::Done`] with the
+    /// result once the expression is evaluated. Evaluating an expression function call
+    /// counts the nodes of its body. A budget of 0 still makes progress, evaluating one
+    /// node. An error ends the evaluation, as does the result; resuming afterwards
+    /// returns an error.
+    pub fn resume(&mut self, max_nodes: usize) -> Result<EvalStep, ExprError> {
+        if self.running.is_none() {
+            return Err(ExprError::Other {
+                message: "No evaluation in progress".to_string(),
+            });
+        }
+        self.run(max_nodes.max(1))
+    }
+
+    /// Whether an evaluation started by [`start_eval`](Self::start_eval) is waiting to
+    /// be resumed.
+    pub fn is_pending(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /// Reset the stacks and push the root of `ast`, ready to run.
+    fn start(
+        &mut self,
+        ast: &'arena AstExpr<'arena>,
+        ctx: Option<Rc<EvalContext>>,
+    ) -> Result<(), ExprError> {
         // Clear stacks efficiently for arena allocation
         self.arena_clear_stacks();
         self.ctx_stack.clear();
         self.func_cache.clear();
+        self.running = None;
+
+        let max_depth = self
+            .max_depth
+            .or_else(|| ctx.as_ref().and_then(|c| c.max_eval_depth()))
+            .unwrap_or(MAX_STACK_DEPTH);
+        let stack_spill = self
+            .stack_spill
+            .or_else(|| ctx.as_ref().and_then(|c| c.stack_spill()));
+
+        self.logger = ctx.as_ref().and_then(|c| c.logger().cloned());
+        self.propagate_missing = ctx.as_ref().is_some_and(|c| c.missing_propagation());
+        self.watchdog = ctx.as_ref().and_then(|c| c.watchdog().cloned());
+        if let Some(inputs) = &mut self.inputs_read {
+            inputs.clear();
+        }
+
+        // When spilling, the arena stack is given its full size once and never grows
+        if stack_spill.is_some() {
+            self.op_stack.reserve_exact(max_depth);
+        }
 
         // Initialize with root context
         let root_ctx_id = self.ctx_stack.push_context(ctx)?;
 
         // Push initial operation (no clone needed - just use reference!)
-        self.op_stack.push(EvalOp::Eval {
+        self.push_op(EvalOp::Eval {
             expr: ast,
             ctx_id: root_ctx_id,
         });
+        self.running = Some(RunLimits {
+            max_depth,
+            stack_spill,
+            spill_chunk: (max_depth / 2).max(1),
+        });
+        Ok(())
+    }
+
+    /// Process operations until the result is ready or `max_nodes` nodes have been
+    /// evaluated. The evaluation is over unless this returns `Pending`.
+    fn run(&mut self, max_nodes: usize) -> Result<EvalStep, ExprError> {
+        #[cfg(feature = "int64")]
+        {
+            self.limited_run = max_nodes != usize::MAX;
+        }
+        let result = self.run_operations(max_nodes);
+        if !matches!(result, Ok(EvalStep::Pending)) {
+            self.running = None;
+        }
+        result
+    }
+
+    fn run_operations(&mut self, max_nodes: usize) -> Result<EvalStep, ExprError> {
+        let Some(RunLimits {
+            max_depth,
+            stack_spill,
+            spill_chunk,
+        }) = self.running
+        else {
+            unreachable!("run is only called after start")
+        };
+        let mut nodes = 0;
 
         // Main evaluation loop
-        while let Some(op) = self.op_stack.pop() {
-            // Check depth limit
-            if self.op_stack.len() > MAX_STACK_DEPTH {
-                return Err(ExprError::RecursionLimit(format!(
-                    "Maximum evaluation depth {} exceeded",
-                    MAX_STACK_DEPTH
-                )));
+        loop {
+            if nodes == max_nodes && matches!(self.op_stack.last(), Some(EvalOp::Eval { .. })) {
+                return Ok(EvalStep::Pending);
+            }
+            let Some(op) = self.op_stack.pop() else {
+                if self.op_spill.is_empty() {
+                    break;
+                }
+                // Bring back the most recently spilled operations
+                let start = self.op_spill.len().saturating_sub(spill_chunk);
+                self.op_stack.extend(self.op_spill.drain(start..));
+                continue;
+            };
+            if matches!(op, EvalOp::Eval { .. }) {
+                nodes += 1;
+                self.tick_watchdog()?;
+            }
+
+            // Check depth limit, counting the spilled operations
+            let depth = self.op_stack.len() + self.op_spill.len();
+            let limit = stack_spill.map_or(max_depth, |spill| spill.max(max_depth));
+            if depth > limit {
+                return Err(ExprError::RecursionLimit {
+                    limit,
+                    message: format!("Maximum evaluation depth {} exceeded", limit),
+                });
             }
 
-            self.process_operation(op)?;
+            if let Err(err) = self.process_operation(op) {
+                if let Some(logger) = &self.logger
+                    && logger.enabled(crate::log::Level::Warn)
+                {
+                    logger.log(
+                        crate::log::Level::Warn,
+                        format_args!("evaluation failed: {}", err),
+                    );
+                }
+                return Err(err);
+            }
         }
 
         // Result should be on top of value stack
         self.value_stack
             .pop()
-            .ok_or_else(|| ExprError::Other("No result on value stack".to_string()))
+            .map(EvalStep::Done)
+            .ok_or_else(|| ExprError::Other {
+                message: "No result on value stack".to_string(),
+            })
+    }
+
+    /// Capacity of the arena operation stack, for checking that spilling bounds it.
+    #[cfg(test)]
+    pub(crate) fn op_stack_capacity(&self) -> usize {
+        self.op_stack.capacity()
+    }
+
+    /// Push a pending operation. Once the arena stack is full and spilling is enabled,
+    /// its oldest operations move to the heap first, so it is not reallocated.
+    fn push_op(&mut self, op: EvalOp<'arena>) {
+        if self.op_stack.len() == self.op_stack.capacity()
+            && let Some(RunLimits {
+                stack_spill: Some(_),
+                spill_chunk,
+                ..
+            }) = self.running
+        {
+            let chunk = spill_chunk.min(self.op_stack.len());
+            self.op_spill.extend(self.op_stack.drain(..chunk));
+        }
+        self.op_stack.push(op);
+    }
+
+    /// Count an evaluated node, calling the watchdog when its interval is reached.
+    fn tick_watchdog(&mut self) -> Result<(), ExprError> {
+        if let Some(watchdog) = &self.watchdog {
+            self.watchdog_count += 1;
+            if self.watchdog_count >= watchdog.interval {
+                self.watchdog_count = 0;
+                if (watchdog.callback)().is_break() {
+                    return Err(ExprError::Aborted);
+                }
+            }
+        }
+        Ok(())
     }
 
     /// Process a single operation
@@ -180,13 +705,15 @@ impl<'arena> EvalEngine<'arena> {
 
             EvalOp::ShortCircuitAnd { right_expr, ctx_id } => {
                 let left_val = self.pop_value()?;
-                if left_val == 0.0 {
+                if self.propagate_missing && crate::missing::is_missing(left_val) {
+                    self.value_stack.push(left_val);
+                } else if left_val == 0.0 {
                     // Short circuit - left is false, result is false
                     self.value_stack.push(0.0);
                 } else {
                     // Need to evaluate right side
-                    self.op_stack.push(EvalOp::CompleteAnd);
-                    self.op_stack.push(EvalOp::Eval {
+                    self.push_op(EvalOp::CompleteAnd);
+                    self.push_op(EvalOp::Eval {
                         expr: right_expr,
                         ctx_id,
                     });
@@ -195,13 +722,15 @@ impl<'arena> EvalEngine<'arena> {
 
             EvalOp::ShortCircuitOr { right_expr, ctx_id } => {
                 let left_val = self.pop_value()?;
-                if left_val != 0.0 {
+                if self.propagate_missing && crate::missing::is_missing(left_val) {
+                    self.value_stack.push(left_val);
+                } else if left_val != 0.0 {
                     // Short circuit - left is true, result is true
                     self.value_stack.push(1.0);
                 } else {
                     // Need to evaluate right side
-                    self.op_stack.push(EvalOp::CompleteOr);
-                    self.op_stack.push(EvalOp::Eval {
+                    self.push_op(EvalOp::CompleteOr);
+                    self.push_op(EvalOp::Eval {
                         expr: right_expr,
                         ctx_id,
                     });
@@ -211,21 +740,29 @@ impl<'arena> EvalEngine<'arena> {
             EvalOp::CompleteAnd => {
                 let right = self.pop_value()?;
                 let left = 1.0; // We know left was true or we wouldn't be here
-                self.value_stack.push(if left != 0.0 && right != 0.0 {
-                    1.0
-                } else {
-                    0.0
-                });
+                self.value_stack.push(
+                    if self.propagate_missing && crate::missing::is_missing(right) {
+                        right
+                    } else if left != 0.0 && right != 0.0 {
+                        1.0
+                    } else {
+                        0.0
+                    },
+                );
             }
 
             EvalOp::CompleteOr => {
                 let right = self.pop_value()?;
                 let left = 0.0; // We know left was false or we wouldn't be here
-                self.value_stack.push(if left != 0.0 || right != 0.0 {
-                    1.0
-                } else {
-                    0.0
-                });
+                self.value_stack.push(
+                    if self.propagate_missing && crate::missing::is_missing(right) {
+                        right
+                    } else if left != 0.0 || right != 0.0 {
+                        1.0
+                    } else {
+                        0.0
+                    },
+                );
             }
 
             EvalOp::LookupVariable { name, ctx_id } => {
@@ -238,13 +775,15 @@ impl<'arena> EvalEngine<'arena> {
                 ctx_id,
             } => {
                 let condition = self.pop_value()?;
-                if condition != 0.0 {
-                    self.op_stack.push(EvalOp::Eval {
+                if self.propagate_missing && crate::missing::is_missing(condition) {
+                    self.value_stack.push(condition);
+                } else if condition != 0.0 {
+                    self.push_op(EvalOp::Eval {
                         expr: true_branch,
                         ctx_id,
                     });
                 } else {
-                    self.op_stack.push(EvalOp::Eval {
+                    self.push_op(EvalOp::Eval {
                         expr: false_branch,
                         ctx_id,
                     });
@@ -268,14 +807,143 @@ impl<'arena> EvalEngine<'arena> {
                 name,
                 arg_count,
                 ctx_id,
+                site,
             } => {
-                self.process_function_call(name, arg_count, ctx_id)?;
+                self.process_function_call(name, arg_count, ctx_id, site)?;
             }
 
-            EvalOp::RestoreFunctionParams { params: _ } => {
+            EvalOp::RestoreFunctionParams { .. } => {
                 // No-op: params are scoped to operations on stack
                 // When this operation is popped, the parameters are automatically cleaned up
             }
+
+            EvalOp::ApplyTrigger { op, site } => {
+                let mut args = [0.0; 3];
+                for arg in args[..op.arity()].iter_mut().rev() {
+                    *arg = self.pop_value()?;
+                }
+                // A call in an expression function body has state for every chain of
+                // calls that led to it
+                let path = || {
+                    self.op_spill
+                        .iter()
+                        .chain(self.op_stack.iter())
+                        .filter_map(|op| match op {
+                            EvalOp::RestoreFunctionParams { site, .. } => Some(*site),
+                            _ => None,
+                        })
+                        .chain(core::iter::once(site))
+                };
+                let slot = self.trigger_state.iter().position(|(calls, _)| {
+                    calls.len() == path().count()
+                        && calls.iter().zip(path()).all(|(a, b)| core::ptr::eq(*a, b))
+                });
+                let (value, state) = op.apply(&args, slot.map(|i| self.trigger_state[i].1));
+                match slot {
+                    Some(i) => self.trigger_state[i].1 = state,
+                    None => {
+                        let calls = path().collect();
+                        self.trigger_state.push((calls, state));
+                    }
+                }
+                self.value_stack.push(value);
+            }
+
+            EvalOp::ApplyLut { args, ctx_id } => {
+                let mode = if args.len() == 4 {
+                    self.pop_value()?
+                } else {
+                    0.0
+                };
+                let x = self.pop_value()?;
+
+                // Both tables go on top of the value stack for the interpolation
+                let base = self.value_stack.len();
+                let mut lens = [0; 2];
+                for (len, table) in lens.iter_mut().zip(&args[1..3]) {
+                    let name = array_path(table).unwrap_or_default();
+                    *len = match self.push_array_values(&name, ctx_id)? {
+                        Some(len) => len,
+                        None => {
+                            self.value_stack.truncate(base);
+                            return Err(ExprError::UnknownVariable {
+                                name: name.to_string(),
+                            });
+                        }
+                    };
+                }
+                if lens[0] != lens[1] || lens[0] == 0 {
+                    self.value_stack.truncate(base);
+                    return Err(ExprError::Other {
+                        message: format!(
+                            "lut() needs tables of equal, non-zero length, got {} and {} points",
+                            lens[0], lens[1]
+                        ),
+                    });
+                }
+                let (xs, ys) = self.value_stack[base..].split_at(lens[0]);
+                let value = match mode {
+                    0.0 => crate::functions::lut(x, xs, ys, false),
+                    1.0 => crate::functions::lut(x, xs, ys, true),
+                    _ => Real::NAN,
+                };
+                self.value_stack.truncate(base);
+                self.value_stack.push(value);
+            }
+
+            EvalOp::ApplyHistory { op, name, ctx_id } => {
+                let n = self.pop_value()?;
+                let history = self
+                    .ctx_stack
+                    .get_context(ctx_id)
+                    .and_then(|ctx| ctx.history(name).cloned())
+                    .ok_or_else(|| ExprError::UnknownVariable {
+                        name: name.to_string(),
+                    })?;
+                record_input(&mut self.inputs_read, name);
+                self.value_stack.push(op.apply(&history, n));
+            }
+
+            EvalOp::ApplySliceFunction {
+                name,
+                array,
+                extra,
+                ctx_id,
+                site,
+            } => {
+                let base = self.value_stack.len() - extra - 2;
+                let (start, end) = (self.value_stack[base], self.value_stack[base + 1]);
+                let Some(len) = self.push_array_values(array, ctx_id)? else {
+                    self.value_stack.truncate(base);
+                    return Err(ExprError::UnknownVariable {
+                        name: array.to_string(),
+                    });
+                };
+                if !(0.0 <= start && start <= end && end <= len as Real) {
+                    self.value_stack.truncate(base);
+                    return Err(ExprError::Other {
+                        message: format!(
+                            "Slice {}[{}:{}] is out of range for {} elements",
+                            array, start, end, len
+                        ),
+                    });
+                }
+                let (start, end) = (start as usize, end as usize);
+                // [start, end, extra arguments, array] becomes [array[start..end], extra
+                // arguments]
+                self.value_stack[base + 2..].rotate_right(len);
+                self.value_stack.drain(base + 2 + end..base + 2 + len);
+                self.value_stack.drain(base..base + 2 + start);
+                self.process_function_call(name, end - start + extra, ctx_id, site)?;
+            }
+
+            EvalOp::NodeEvaluated { expr } => {
+                if let Some(hook) = self.on_node_eval.as_mut()
+                    && let Some(&value) = self.value_stack.last()
+                {
+                    hook(expr, value);
+                }
+            }
         }
 
         Ok(())
@@ -287,17 +955,18 @@ impl<'arena> EvalEngine<'arena> {
         expr: &'arena AstExpr<'arena>,
         ctx_id: usize,
     ) -> Result<(), ExprError> {
+        // Popped once every operation for this node has completed
+        if self.on_node_eval.is_some() {
+            self.push_op(EvalOp::NodeEvaluated { expr });
+        }
+
         match expr {
             AstExpr::Constant(val) => {
                 self.value_stack.push(*val);
             }
 
             AstExpr::Variable(name) => {
-                let hname = name.try_into_heapless()?;
-                self.op_stack.push(EvalOp::LookupVariable {
-                    name: hname,
-                    ctx_id,
-                });
+                self.push_op(EvalOp::LookupVariable { name, ctx_id });
             }
 
             AstExpr::LogicalOp { op, left, right } => {
@@ -305,18 +974,18 @@ impl<'arena> EvalEngine<'arena> {
                 use crate::types::LogicalOperator;
                 match op {
                     LogicalOperator::And => {
-                        self.op_stack.push(EvalOp::ShortCircuitAnd {
+                        self.push_op(EvalOp::ShortCircuitAnd {
                             right_expr: right,
                             ctx_id,
                         });
-                        self.op_stack.push(EvalOp::Eval { expr: left, ctx_id });
+                        self.push_op(EvalOp::Eval { expr: left, ctx_id });
                     }
                     LogicalOperator::Or => {
-                        self.op_stack.push(EvalOp::ShortCircuitOr {
+                        self.push_op(EvalOp::ShortCircuitOr {
                             right_expr: right,
                             ctx_id,
                         });
-                        self.op_stack.push(EvalOp::Eval { expr: left, ctx_id });
+                        self.push_op(EvalOp::Eval { expr: left, ctx_id });
                     }
                 }
             }
@@ -326,71 +995,206 @@ impl<'arena> EvalEngine<'arena> {
                 true_branch,
                 false_branch,
             } => {
-                self.op_stack.push(EvalOp::TernaryCondition {
+                self.push_op(EvalOp::TernaryCondition {
                     true_branch,
                     false_branch,
                     ctx_id,
                 });
-                self.op_stack.push(EvalOp::Eval {
+                self.push_op(EvalOp::Eval {
                     expr: condition,
                     ctx_id,
                 });
             }
 
             AstExpr::Function { name, args } => {
+                // Checked before the arguments are evaluated, and for expression
+                // functions too
+                if let Some(ctx) = self.ctx_stack.get_context(ctx_id) {
+                    ctx.check_function_permitted(name)?;
+                }
+
+                // Comparisons and differences of integer values are computed exactly
+                #[cfg(feature = "int64")]
+                if matches!(
+                    *name,
+                    "<" | ">"
+                        | "<="
+                        | ">="
+                        | "=="
+                        | "!="
+                        | "-"
+                        | "idiv"
+                        | "imod"
+                        | "iabs"
+                        | "bitand"
+                        | "bitor"
+                        | "bitxor"
+                        | "bitnot"
+                        | "getbit"
+                        | "setbit"
+                        | "popcount"
+                ) && let Some(value) = self.exact_integer(expr, ctx_id)
+                {
+                    self.value_stack.push(value as Real);
+                    return Ok(());
+                }
+
                 // Special handling for short-circuit operators
                 match (*name, args.len()) {
                     ("&&", 2) => {
                         // Short-circuit AND: evaluate left first, then right only if left is true
-                        self.op_stack.push(EvalOp::ShortCircuitAnd {
+                        self.push_op(EvalOp::ShortCircuitAnd {
                             right_expr: &args[1],
                             ctx_id,
                         });
-                        self.op_stack.push(EvalOp::Eval {
+                        self.push_op(EvalOp::Eval {
                             expr: &args[0],
                             ctx_id,
                         });
                     }
                     ("||", 2) => {
                         // Short-circuit OR: evaluate left first, then right only if left is false
-                        self.op_stack.push(EvalOp::ShortCircuitOr {
+                        self.push_op(EvalOp::ShortCircuitOr {
                             right_expr: &args[1],
                             ctx_id,
                         });
-                        self.op_stack.push(EvalOp::Eval {
+                        self.push_op(EvalOp::Eval {
                             expr: &args[0],
                             ctx_id,
                         });
                     }
+                    ("elapsed", 1) => {
+                        // The argument names a timer rather than a value
+                        let AstExpr::Variable(timer) = &args[0] else {
+                            return Err(ExprError::syntax(
+                                "elapsed() expects a timer name, e.g. elapsed(startup)",
+                            ));
+                        };
+                        let clock_ctx = self.ctx_stack.get_context(ctx_id);
+                        let clock =
+                            clock_ctx
+                                .as_ref()
+                                .and_then(|ctx| ctx.clock())
+                                .ok_or_else(|| ExprError::UnknownFunction {
+                                    name: "elapsed".to_string(),
+                                })?;
+                        self.value_stack.push(clock.elapsed(timer));
+                    }
+                    (name, arg_count)
+                        if let Some(op) = crate::history::HistoryOp::from_call(name, arg_count) =>
+                    {
+                        // The first argument names a history rather than a value
+                        let AstExpr::Variable(history) = &args[0] else {
+                            return Err(ExprError::syntax(format!(
+                                "{}() expects a history name, e.g. {}(temp, 1)",
+                                name, name
+                            )));
+                        };
+                        self.push_op(EvalOp::ApplyHistory {
+                            op,
+                            name: history,
+                            ctx_id,
+                        });
+                        self.push_op(EvalOp::Eval {
+                            expr: &args[1],
+                            ctx_id,
+                        });
+                    }
+                    ("lut", 3 | 4) => {
+                        // The tables are arrays named by the second and third arguments
+                        if array_path(&args[1]).is_none() || array_path(&args[2]).is_none() {
+                            return Err(ExprError::syntax(
+                                "lut() expects array names, e.g. lut(x, temps, volts)",
+                            ));
+                        }
+                        self.push_op(EvalOp::ApplyLut { args, ctx_id });
+                        if let Some(mode) = args.get(3) {
+                            self.push_op(EvalOp::Eval { expr: mode, ctx_id });
+                        }
+                        self.push_op(EvalOp::Eval {
+                            expr: &args[0],
+                            ctx_id,
+                        });
+                    }
+                    (name, arg_count) if let Some(op) = TriggerOp::from_call(name, arg_count) => {
+                        // Triggers keep their state under the address of this call and of
+                        // the expression function calls it is in
+                        self.push_op(EvalOp::ApplyTrigger { op, site: expr });
+                        for arg in args.iter().rev() {
+                            self.push_op(EvalOp::Eval { expr: arg, ctx_id });
+                        }
+                    }
+                    (name, _)
+                        if ARRAY_FUNCTIONS.contains(&name)
+                            && let Some((array, start, end)) =
+                                args.first().and_then(array_slice) =>
+                    {
+                        // A slice such as `mean(data[0:64])` passes the elements in the
+                        // range, known once the bounds are evaluated
+                        self.push_op(EvalOp::ApplySliceFunction {
+                            name: name.try_into_function_name()?,
+                            array,
+                            extra: args.len() - 1,
+                            ctx_id,
+                            site: expr,
+                        });
+                        for arg in args[1..].iter().rev() {
+                            self.push_op(EvalOp::Eval { expr: arg, ctx_id });
+                        }
+                        self.push_op(EvalOp::Eval { expr: end, ctx_id });
+                        self.push_op(EvalOp::Eval {
+                            expr: start,
+                            ctx_id,
+                        });
+                    }
                     _ => {
                         // All other function calls go through the same path to support overrides
                         // The parser represents operators like ^, +, -, etc. as function calls
                         // So we treat them all uniformly to allow user overrides
                         let fname = name.try_into_function_name()?;
 
+                        // An array name as first argument of an aggregate such as
+                        // `max(samples)` passes all elements; pushing them now is the same
+                        // as evaluating that argument first
+                        let mut args = &args[..];
+                        let mut arg_count = args.len();
+                        if ARRAY_FUNCTIONS.contains(name)
+                            && let Some(array) = args.first().and_then(array_path)
+                            && let Some(len) = self.push_array_values(&array, ctx_id)?
+                        {
+                            args = &args[1..];
+                            arg_count = len + args.len();
+                        }
+
                         // Push function application operation
                         // This will execute after all arguments are evaluated
-                        self.op_stack.push(EvalOp::ApplyFunction {
+                        self.push_op(EvalOp::ApplyFunction {
                             name: fname,
-                            arg_count: args.len(),
+                            arg_count,
                             ctx_id,
+                            site: expr,
                         });
 
                         // Push argument evaluations in reverse order
                         // (they'll be evaluated left-to-right, results accumulate on value stack)
                         for arg in args.iter().rev() {
-                            self.op_stack.push(EvalOp::Eval { expr: arg, ctx_id });
+                            self.push_op(EvalOp::Eval { expr: arg, ctx_id });
                         }
                     }
                 }
             }
 
             AstExpr::Array { name, index } => {
+                if array_slice(expr).is_some() {
+                    return Err(ExprError::syntax(format!(
+                        "Slice of '{}' can only be the first argument of an aggregate such as max({}[0:8])",
+                        name, name
+                    )));
+                }
                 let array_name = name.try_into_heapless()?;
 
-                self.op_stack
-                    .push(EvalOp::AccessArray { array_name, ctx_id });
-                self.op_stack.push(EvalOp::Eval {
+                self.push_op(EvalOp::AccessArray { array_name, ctx_id });
+                self.push_op(EvalOp::Eval {
                     expr: index,
                     ctx_id,
                 });
@@ -400,7 +1204,7 @@ impl<'arena> EvalEngine<'arena> {
                 let obj_name = base.try_into_heapless()?;
                 let attr_name = attr.try_into_heapless()?;
 
-                self.op_stack.push(EvalOp::AccessAttribute {
+                self.push_op(EvalOp::AccessAttribute {
                     object_name: obj_name,
                     attr_name,
                     ctx_id,
@@ -412,15 +1216,20 @@ impl<'arena> EvalEngine<'arena> {
     }
 
     /// Process variable lookup
-    fn process_variable_lookup(&mut self, name: HString, ctx_id: usize) -> Result<(), ExprError> {
+    fn process_variable_lookup(
+        &mut self,
+        name: &'arena str,
+        ctx_id: usize,
+    ) -> Result<(), ExprError> {
         // Check operation stack for function parameters first (walk backwards for shadowing)
-        for op in self.op_stack.iter().rev() {
+        for op in self.op_spill.iter().chain(self.op_stack.iter()).rev() {
             if let EvalOp::RestoreFunctionParams {
                 params: Some(params),
+                ..
             } = op
             {
                 for (param_name, value) in params.iter() {
-                    if param_name == &name {
+                    if param_name.as_str() == name {
                         self.value_stack.push(*value);
                         return Ok(());
                     }
@@ -428,9 +1237,18 @@ impl<'arena> EvalEngine<'arena> {
             }
         }
 
-        // Check parameter overrides second (batch evaluation parameters)
+        // Check parameter slots second, by address for interned names
+        if let Some(value) = self.param_slot(name) {
+            record_input(&mut self.inputs_read, name);
+            self.value_stack.push(value);
+            return Ok(());
+        }
+
+        // Then parameter overrides (batch evaluation parameters)
+        let name = name.try_into_heapless()?;
         if let Some(ref overrides) = self.param_overrides {
             if let Some(&value) = overrides.get(&name) {
+                record_input(&mut self.inputs_read, &name);
                 self.value_stack.push(value);
                 return Ok(());
             }
@@ -438,6 +1256,7 @@ impl<'arena> EvalEngine<'arena> {
 
         // Try context stack next
         if let Some(value) = self.ctx_stack.lookup_variable(ctx_id, &name) {
+            record_input(&mut self.inputs_read, &name);
             self.value_stack.push(value);
             return Ok(());
         }
@@ -448,6 +1267,28 @@ impl<'arena> EvalEngine<'arena> {
             "e" | "E" => core::f64::consts::E as Real,
             "tau" | "TAU" => 2.0 * core::f64::consts::PI as Real,
             _ => {
+                // Give the context's variable resolver a chance before failing
+                if let Some(value) = self
+                    .ctx_stack
+                    .get_context(ctx_id)
+                    .and_then(|ctx| ctx.resolve_variable(&name))
+                {
+                    record_input(&mut self.inputs_read, &name);
+                    self.value_stack.push(value);
+                    return Ok(());
+                }
+
+                // Unit names evaluate to their scale in SI base units
+                if let Some(unit) = self
+                    .ctx_stack
+                    .get_context(ctx_id)
+                    .and_then(|ctx| ctx.unit_registry())
+                    .and_then(|units| units.lookup(&name))
+                {
+                    self.value_stack.push(unit.scale);
+                    return Ok(());
+                }
+
                 // Check if this looks like a function name
                 let is_potential_function_name = match name.as_str() {
                     "sin" | "cos" | "tan" | "asin" | "acos" | "atan" | "atan2" | "sinh"
@@ -458,7 +1299,7 @@ impl<'arena> EvalEngine<'arena> {
                 };
 
                 if is_potential_function_name && name.len() > 1 {
-                    return Err(ExprError::Syntax(format!(
+                    return Err(ExprError::syntax(format!(
                         "Function '{}' used without arguments",
                         name
                     )));
@@ -474,6 +1315,108 @@ impl<'arena> EvalEngine<'arena> {
         Ok(())
     }
 
+    /// Evaluates `expr` in `i64` if it consists of integer operations on integers, with
+    /// at least one integer or boolean context value (see [`crate::value`]) and without
+    /// overflow.
+    ///
+    /// Otherwise returns `None`, and `expr` is evaluated as usual, where values beyond
+    /// the precision of [`Real`] are rounded. So is every expression whose operations
+    /// are not the built-in implementations, and every evaluation that observes its
+    /// steps through a hook, logger, watchdog, node budget or function policy.
+    #[cfg(feature = "int64")]
+    fn exact_integer(&mut self, expr: &'arena AstExpr<'arena>, ctx_id: usize) -> Option<i64> {
+        if self.on_node_eval.is_some()
+            || self.on_function_call.is_some()
+            || self.logger.is_some()
+            || self.watchdog.is_some()
+            || self.limited_run
+        {
+            return None;
+        }
+        let ctx = self.ctx_stack.get_context(ctx_id)?.clone();
+        if ctx.function_policy().is_some() {
+            return None;
+        }
+
+        // Operations to apply once their operands are known, and the operands known so
+        // far; deeper expressions are left to the usual evaluation
+        let mut pending: heapless::Vec<IntegerStep<'arena>, 32> = heapless::Vec::new();
+        let mut values: heapless::Vec<i64, 32> = heapless::Vec::new();
+        let mut typed = false;
+        pending.push(IntegerStep::Eval(expr)).ok()?;
+        while let Some(step) = pending.pop() {
+            match step {
+                IntegerStep::Eval(AstExpr::Function { name, args }) => {
+                    if !INTEGER_OPERATIONS.contains(&(*name, args.len()))
+                        || !ctx.get_native_function(name).is_some_and(|f| f.builtin)
+                        || self.local_functions.is_some_and(|functions| {
+                            functions.borrow().keys().any(|key| key.as_str() == *name)
+                        })
+                    {
+                        return None;
+                    }
+                    pending.push(IntegerStep::Apply(name, args.len())).ok()?;
+                    for arg in args.iter().rev() {
+                        pending.push(IntegerStep::Eval(arg)).ok()?;
+                    }
+                }
+                IntegerStep::Eval(AstExpr::Constant(x)) => {
+                    values.push(crate::value::Value::Real(*x).to_int()?).ok()?;
+                }
+                IntegerStep::Eval(AstExpr::Variable(name)) => {
+                    let value = match self.typed_integer(name, ctx_id) {
+                        Some(value) => {
+                            typed = true;
+                            value
+                        }
+                        // Other variables take part if they hold an integer
+                        None => {
+                            self.process_variable_lookup(name, ctx_id).ok()?;
+                            let value = self.value_stack.pop()?;
+                            crate::value::Value::Real(value).to_int()?
+                        }
+                    };
+                    values.push(value).ok()?;
+                }
+                IntegerStep::Eval(_) => return None,
+                IntegerStep::Apply(name, arg_count) => {
+                    let start = values.len().checked_sub(arg_count)?;
+                    let value = integer_operation(name, &values[start..])?;
+                    values.truncate(start);
+                    values.push(value).ok()?;
+                }
+            }
+        }
+        let value = values.pop()?;
+        typed.then_some(value)
+    }
+
+    /// Returns the integer or boolean context value `name` resolves to, if it is not
+    /// shadowed by a function or batch parameter.
+    #[cfg(feature = "int64")]
+    fn typed_integer(&mut self, name: &'arena str, ctx_id: usize) -> Option<i64> {
+        use crate::value::Value;
+
+        let shadowed = self.op_spill.iter().chain(self.op_stack.iter()).any(|op| {
+            matches!(op, EvalOp::RestoreFunctionParams { params: Some(params), .. }
+                if params.iter().any(|(param, _)| param.as_str() == name))
+        }) || self.param_slot(name).is_some()
+            || self
+                .param_overrides
+                .as_ref()
+                .is_some_and(|overrides| overrides.keys().any(|key| key.as_str() == name));
+        if shadowed {
+            return None;
+        }
+        let value = match self.ctx_stack.get_context(ctx_id)?.get_value(name)? {
+            Value::Int(n) => n,
+            Value::Bool(b) => i64::from(b),
+            Value::Real(_) => return None,
+        };
+        record_input(&mut self.inputs_read, name);
+        Some(value)
+    }
+
     /// Process array access
     fn process_array_access(
         &mut self,
@@ -484,7 +1427,9 @@ impl<'arena> EvalEngine<'arena> {
         let idx = index as usize;
 
         if let Some(ctx) = self.ctx_stack.get_context(ctx_id) {
-            if let Some(array) = ctx.arrays.get(&array_name) {
+            // A failed access fails the evaluation, so the name is recorded up front
+            record_input(&mut self.inputs_read, &array_name);
+            if let Some(array) = ctx.get_array(&array_name) {
                 if idx < array.len() {
                     self.value_stack.push(array[idx]);
                     return Ok(());
@@ -496,6 +1441,35 @@ impl<'arena> EvalEngine<'arena> {
                     });
                 }
             }
+            if let Some(view) = ctx.get_array_view(&array_name) {
+                return match view.get(idx) {
+                    Some(value) => {
+                        self.value_stack.push(value);
+                        Ok(())
+                    }
+                    None => Err(ExprError::ArrayIndexOutOfBounds {
+                        name: array_name.to_string(),
+                        index: idx,
+                        len: view.len(),
+                    }),
+                };
+            }
+            // Array attributes of registered objects
+            if let Some((object, attr)) = array_name.rsplit_once('.')
+                && let Some(len) = ctx.get_object_array_len(object, attr)
+            {
+                return match ctx.get_object_element(object, attr, idx) {
+                    Some(value) => {
+                        self.value_stack.push(value);
+                        Ok(())
+                    }
+                    None => Err(ExprError::ArrayIndexOutOfBounds {
+                        name: array_name.to_string(),
+                        index: idx,
+                        len,
+                    }),
+                };
+            }
         }
 
         Err(ExprError::UnknownVariable {
@@ -503,6 +1477,41 @@ impl<'arena> EvalEngine<'arena> {
         })
     }
 
+    /// Push the elements of `array` onto the value stack, returning how many were pushed.
+    ///
+    /// Returns `None` without pushing anything if `array` is not an array or array view
+    /// of the context.
+    fn push_array_values(
+        &mut self,
+        array: &str,
+        ctx_id: usize,
+    ) -> Result<Option<usize>, ExprError> {
+        let Some(ctx) = self.ctx_stack.get_context(ctx_id) else {
+            return Ok(None);
+        };
+        let key = array.try_into_heapless()?;
+        let len = if let Some(values) = ctx.get_array(&key) {
+            self.value_stack.extend_from_slice(values);
+            Some(values.len())
+        } else if let Some(view) = ctx.get_array_view(&key) {
+            self.value_stack
+                .extend((0..view.len()).filter_map(|i| view.get(i)));
+            Some(view.len())
+        } else if let Some((object, attr)) = array.rsplit_once('.')
+            && let Some(len) = ctx.get_object_array_len(object, attr)
+        {
+            self.value_stack
+                .extend((0..len).filter_map(|i| ctx.get_object_element(object, attr, i)));
+            Some(len)
+        } else {
+            None
+        };
+        if len.is_some() {
+            record_input(&mut self.inputs_read, array);
+        }
+        Ok(len)
+    }
+
     /// Process attribute access
     fn process_attribute_access(
         &mut self,
@@ -511,11 +1520,25 @@ impl<'arena> EvalEngine<'arena> {
         ctx_id: usize,
     ) -> Result<(), ExprError> {
         if let Some(ctx) = self.ctx_stack.get_context(ctx_id) {
-            if let Some(obj_attrs) = ctx.attributes.get(&object_name) {
-                if let Some(&value) = obj_attrs.get(&attr_name) {
-                    self.value_stack.push(value);
-                    return Ok(());
+            let path = crate::types::dotted_path::<{ crate::types::EXP_RS_MAX_KEY_LENGTH }>(
+                &object_name,
+                &attr_name,
+            );
+            if let Some(value) = ctx
+                .get_attribute(&object_name, &attr_name)
+                .or_else(|| ctx.get_object_attribute(&object_name, &attr_name))
+                .or_else(|| ctx.get_variable(path.as_deref()?))
+            {
+                match &path {
+                    Some(path) => record_input(&mut self.inputs_read, path),
+                    None if self.inputs_read.is_some() => {
+                        let path = format!("{}.{}", object_name, attr_name);
+                        record_input(&mut self.inputs_read, &path);
+                    }
+                    None => {}
                 }
+                self.value_stack.push(value);
+                return Ok(());
             }
         }
 
@@ -531,6 +1554,7 @@ impl<'arena> EvalEngine<'arena> {
         name: FunctionName,
         arg_count: usize,
         ctx_id: usize,
+        site: &'arena AstExpr<'arena>,
     ) -> Result<(), ExprError> {
         // Arguments are the last arg_count values on the value stack
         let args_start = self.value_stack.len().saturating_sub(arg_count);
@@ -539,18 +1563,37 @@ impl<'arena> EvalEngine<'arena> {
         let ctx = self
             .ctx_stack
             .get_context(ctx_id)
-            .ok_or_else(|| ExprError::Other("Invalid context ID".to_string()))?;
+            .ok_or_else(|| ExprError::Other {
+                message: "Invalid context ID".to_string(),
+            })?;
+
+        // A missing argument makes the result missing, without calling the function
+        if self.propagate_missing
+            && !crate::missing::handles_missing(&name)
+            && let Some(&missing) = self.value_stack[args_start..]
+                .iter()
+                .find(|&&arg| crate::missing::is_missing(arg))
+        {
+            self.value_stack.truncate(args_start);
+            self.value_stack.push(missing);
+            return Ok(());
+        }
 
         // Check local functions first (highest priority)
         if let Some(local_funcs) = self.local_functions {
-            if let Some(func) = local_funcs.borrow().get(&name).cloned() {
-                return self.process_expression_function(&func, args_start, arg_count, ctx_id);
+            if let Some(func) = local_funcs.borrow().get(&name) {
+                return self.process_expression_function(func, args_start, arg_count, ctx_id, site);
             }
         }
 
         // Try native function (expression functions no longer exist in context)
-        if let Some(func) = ctx.get_native_function(&name) {
-            if arg_count != func.arity {
+        let native = match ctx.permitted_native_function(&name) {
+            Ok(func) => Some(func),
+            Err(ExprError::UnknownFunction { .. }) => None,
+            Err(err) => return Err(err),
+        };
+        if let Some(func) = native {
+            if !func.accepts(arg_count) {
                 return Err(ExprError::InvalidFunctionCall {
                     name: name.to_string(),
                     expected: func.arity,
@@ -560,8 +1603,15 @@ impl<'arena> EvalEngine<'arena> {
 
             // Get args slice from value stack
             let args = &self.value_stack[args_start..];
-            let owned_fn = OwnedNativeFunction::from(func);
-            let result = (owned_fn.implementation)(args);
+            let result = (func.implementation)(args);
+            #[cfg(feature = "softfloat")]
+            let result = crate::softfloat::canonicalize(result);
+            if let Some(hook) = self.on_function_call.as_mut() {
+                hook(&name, args, result);
+            }
+            if let Some(logger) = &self.logger {
+                crate::log::log_call(logger.as_ref(), &name, args, result);
+            }
 
             // Pop arguments from stack
             self.value_stack.truncate(args_start);
@@ -571,6 +1621,26 @@ impl<'arena> EvalEngine<'arena> {
             return Ok(());
         }
 
+        // Fall back to the context's function resolver
+        if let Some(result) = ctx.resolve_function(&name, &self.value_stack[args_start..]) {
+            #[cfg(feature = "softfloat")]
+            let result = crate::softfloat::canonicalize(result);
+            if let Some(hook) = self.on_function_call.as_mut() {
+                hook(&name, &self.value_stack[args_start..], result);
+            }
+            if let Some(logger) = &self.logger {
+                crate::log::log_call(
+                    logger.as_ref(),
+                    &name,
+                    &self.value_stack[args_start..],
+                    result,
+                );
+            }
+            self.value_stack.truncate(args_start);
+            self.value_stack.push(result);
+            return Ok(());
+        }
+
         Err(ExprError::UnknownFunction {
             name: name.to_string(),
         })
@@ -578,9 +1648,9 @@ impl<'arena> EvalEngine<'arena> {
 
     /// Pop a value from the value stack
     fn pop_value(&mut self) -> Result<Real, ExprError> {
-        self.value_stack
-            .pop()
-            .ok_or_else(|| ExprError::Other("Value stack underflow".to_string()))
+        self.value_stack.pop().ok_or_else(|| ExprError::Other {
+            message: "Value stack underflow".to_string(),
+        })
     }
 
     /// Set parameter overrides for batch evaluation.
@@ -589,11 +1659,97 @@ impl<'arena> EvalEngine<'arena> {
         self.param_overrides = Some(params);
     }
 
+    /// Set or replace a single parameter override, creating the override map if needed.
+    pub fn set_param_override(&mut self, name: HString, value: Real) -> Result<(), ExprError> {
+        self.param_overrides
+            .get_or_insert_with(crate::types::BatchParamMap::new)
+            .insert(name, value)
+            .map_err(|_| ExprError::CapacityExceeded {
+                container: "parameter overrides",
+            })?;
+        Ok(())
+    }
+
     /// Clear parameter overrides.
     pub fn clear_param_overrides(&mut self) {
         self.param_overrides = None;
     }
 
+    /// Set or replace the value of the parameter slot of `symbol`.
+    ///
+    /// `name` must be the copy of the symbol's name interned by its [`SymbolTable`], as
+    /// returned by [`SymbolTable::name`], and all slots must use symbols of the same
+    /// table. Variables are matched to slots by the address of their name, so only
+    /// expressions parsed with that table (see
+    /// [`parse_expression_interned`](crate::engine::parse_expression_interned)) and
+    /// expression function bodies parsed while it is [lent](Self::set_symbols) to the
+    /// engine see the slots. Slots take precedence over parameter overrides and context
+    /// variables.
+    pub fn set_param_slot(&mut self, symbol: Symbol, name: &'arena str, value: Real) {
+        let index = symbol.index();
+        if index >= self.param_slots.len() {
+            self.param_slots.resize(index + 1, None);
+        }
+        self.param_slots[index] = Some(value);
+
+        let key = (name.as_ptr() as usize, name.len());
+        match self
+            .slot_symbols
+            .binary_search_by_key(&key, |&(key, _)| key)
+        {
+            Ok(pos) => self.slot_symbols[pos].1 = symbol,
+            Err(pos) => self.slot_symbols.insert(pos, (key, symbol)),
+        }
+    }
+
+    /// Returns the value of the parameter slot `name` is the interned name of, if set.
+    fn param_slot(&self, name: &str) -> Option<Real> {
+        let key = (name.as_ptr() as usize, name.len());
+        let pos = self
+            .slot_symbols
+            .binary_search_by_key(&key, |&(key, _)| key)
+            .ok()?;
+        self.param_slots
+            .get(self.slot_symbols[pos].1.index())
+            .copied()
+            .flatten()
+    }
+
+    /// Lend the engine a symbol table, returning the one it held.
+    ///
+    /// While it holds a table, the engine interns the names of the expression function
+    /// bodies it parses into it, so that their variables match the parameter slots.
+    pub fn set_symbols(
+        &mut self,
+        symbols: Option<SymbolTable<'arena>>,
+    ) -> Option<SymbolTable<'arena>> {
+        core::mem::replace(&mut self.symbols, symbols)
+    }
+
+    /// Forget the state remembered by the trigger functions (`rising`, `falling`,
+    /// `changed`, `latch`, `hysteresis` and `debounce`), so that every call behaves
+    /// as on its first evaluation.
+    pub fn reset_triggers(&mut self) {
+        self.trigger_state.clear();
+    }
+
+    /// Whether a trigger function was evaluated since the last reset.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn has_trigger_state(&self) -> bool {
+        !self.trigger_state.is_empty()
+    }
+
+    /// Unset all parameter slots, keeping their storage.
+    pub fn clear_param_slots(&mut self) {
+        self.param_slots.fill(None);
+    }
+
+    /// Reserve room for `additional` more parameter slots.
+    pub fn reserve_param_slots(&mut self, additional: usize) {
+        self.param_slots.reserve(additional);
+        self.slot_symbols.reserve(additional);
+    }
+
     /// Execute a function with parameter overrides, ensuring they are cleared afterwards.
     /// This provides RAII-style cleanup for safe batch evaluation.
     pub fn with_param_overrides<F, R>(&mut self, params: crate::types::BatchParamMap, f: F) -> R
@@ -614,16 +1770,30 @@ impl<'arena> EvalEngine<'arena> {
         args_start: usize,
         arg_count: usize,
         ctx_id: usize,
+        site: &'arena AstExpr<'arena>,
     ) -> Result<(), ExprError> {
         use crate::types::TryIntoHeaplessString;
 
-        if arg_count != func.params.len() {
+        let required = func.params.len() - func.defaults.len();
+        if arg_count < required || arg_count > func.params.len() {
             return Err(ExprError::InvalidFunctionCall {
                 name: func.name.to_string(),
-                expected: func.params.len(),
+                expected: if arg_count < required {
+                    required
+                } else {
+                    func.params.len()
+                },
                 found: arg_count,
             });
         }
+        // Omitted trailing arguments take their default values
+        let arg_value = |stack: &[Real], i: usize| {
+            if i < arg_count {
+                stack[args_start + i]
+            } else {
+                func.defaults[i - required]
+            }
+        };
 
         // Use pre-allocated buffer when available, otherwise allocate on-demand
         let params_slice = if let Some(buffer_ptr) = func.param_buffer {
@@ -631,8 +1801,8 @@ impl<'arena> EvalEngine<'arena> {
             let buffer = unsafe { &mut *buffer_ptr };
 
             // Update parameter values (names are already pre-filled)
-            for i in 0..func.params.len() {
-                buffer[i].1 = self.value_stack[args_start + i];
+            for (i, slot) in buffer.iter_mut().take(func.params.len()).enumerate() {
+                slot.1 = arg_value(&self.value_stack, i);
             }
 
             Some(&*buffer)
@@ -645,7 +1815,7 @@ impl<'arena> EvalEngine<'arena> {
 
             // Fill in both names and values
             for (i, param) in func.params.iter().enumerate() {
-                let value = self.value_stack[args_start + i];
+                let value = arg_value(&self.value_stack, i);
                 let param_key = param.as_str().try_into_heapless()?;
                 params_slice[i] = (param_key, value);
             }
@@ -660,43 +1830,63 @@ impl<'arena> EvalEngine<'arena> {
 
         // Parse expression function on-demand if we have an arena
         if let Some(arena) = self.arena {
-            // Check if we've already parsed this function
-            let func_key = func.name.try_into_heapless()?;
+            // Check if we've already parsed this definition
+            let func_key = func.definition_hash;
 
-            let ast = if let Some(&cached_ast) = self.expr_func_cache.get(&func_key) {
-                cached_ast
+            let cached = self.expr_func_cache.get(&func_key);
+            let ast = if let Some(body) = cached.filter(|body| body.is_parsed_from(func)) {
+                body.ast
             } else {
                 // Parse the expression function body into the arena
                 let param_names: Vec<crate::String> = func.params.clone();
-                let parsed_ast = crate::engine::parse_expression_with_parameters(
-                    &func.expression,
-                    arena,
-                    &param_names,
-                )?;
+                let parsed_ast = match &mut self.symbols {
+                    Some(symbols) => crate::engine::parse_expression_with_parameters_interned(
+                        &func.expression,
+                        arena,
+                        &param_names,
+                        &func.parse_options,
+                        symbols,
+                    )?,
+                    None => crate::engine::parse_expression_with_parameters_and_options(
+                        &func.expression,
+                        arena,
+                        &param_names,
+                        &func.parse_options,
+                    )?,
+                };
 
                 // Allocate the AST in the arena
                 let arena_ast = arena.alloc(parsed_ast);
 
-                // Cache for future use
-                self.expr_func_cache.insert(func_key.clone(), &*arena_ast);
+                // Cache for future use, replacing a definition with the same hash
+                self.expr_func_cache.insert(
+                    func_key,
+                    ParsedBody {
+                        expression: func.expression.clone(),
+                        params: param_names,
+                        parse_options: func.parse_options,
+                        ast: arena_ast,
+                    },
+                );
 
                 &*arena_ast
             };
 
             // Push operations: restore params first, then eval with SAME context
-            self.op_stack.push(EvalOp::RestoreFunctionParams {
+            self.push_op(EvalOp::RestoreFunctionParams {
                 params: params_slice,
+                site,
             });
-            self.op_stack.push(EvalOp::Eval {
+            self.push_op(EvalOp::Eval {
                 expr: ast,
                 ctx_id, // Use SAME context, no new context!
             });
             Ok(())
         } else {
             // No arena available for expression functions
-            Err(ExprError::Other(
-                "Expression functions require an arena-enabled evaluator".to_string(),
-            ))
+            Err(ExprError::Other {
+                message: "Expression functions require an arena-enabled evaluator".to_string(),
+            })
         }
     }
 }
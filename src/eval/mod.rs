@@ -1,27 +1,26 @@
 //! Expression evaluation module for exp-rs
 //!
 //! This module contains the core evaluation logic for expressions,
-//! including AST traversal, variable resolution, function application,
-//! and recursion depth tracking.
+//! including AST traversal, variable resolution and function application.
 
 pub mod ast;
+pub mod checked;
 pub mod context_stack;
+pub mod estimate;
+pub mod explain;
 pub mod iterative;
-pub mod recursion;
+pub mod reentrant;
 pub mod stack_ops;
 pub mod types;
 
 // Re-export the main evaluation functions for backward compatibility
 pub use ast::*;
-pub use recursion::*;
+pub use checked::{CheckedValue, FpFlags, eval_checked};
+pub use estimate::ResourceEstimate;
+pub use explain::{ExplainNode, TracedValue, eval_explain, eval_traced};
+pub use reentrant::{StaticContext, eval_reentrant};
 pub use types::*;
 
-// Re-export recursion tracking functions
-pub use recursion::{
-    check_and_increment_recursion_depth, decrement_recursion_depth, get_recursion_depth,
-    reset_recursion_depth, set_max_recursion_depth,
-};
-
 #[cfg(test)]
 mod tests {
     use crate::types::{TryIntoFunctionName, TryIntoHeaplessString};
@@ -34,7 +33,6 @@ mod tests {
     use crate::error::ExprError;
     use crate::parse_expression;
     use std::rc::Rc;
-    use std::sync::atomic::Ordering;
 
     // Import functions used in tests
     #[cfg(feature = "libm")]
@@ -157,7 +155,7 @@ mod tests {
         let err = test_eval_variable("nosuchvar", None).unwrap_err();
         assert!(matches!(err, ExprError::UnknownVariable { .. }));
         let err2 = test_eval_variable("sin", None).unwrap_err();
-        assert!(matches!(err2, ExprError::Syntax(_)));
+        assert!(matches!(err2, ExprError::Syntax { .. }));
     }
 
     #[test]
@@ -516,7 +514,7 @@ mod tests {
         // Evaluate expression for variable "sin"
         let err = interp("sin", Some(ctx_rc.clone())).unwrap_err();
         match err {
-            ExprError::Syntax(msg) => {
+            ExprError::Syntax { message: msg, .. } => {
                 assert!(
                     msg.contains("Unexpected token")
                         || msg.contains("Function 'sin' used without arguments")
@@ -528,7 +526,7 @@ mod tests {
         // Evaluate expression for variable "abs"
         let err2 = interp("abs", Some(ctx_rc.clone())).unwrap_err();
         match err2 {
-            ExprError::Syntax(msg) => {
+            ExprError::Syntax { message: msg, .. } => {
                 assert!(
                     msg.contains("Unexpected token")
                         || msg.contains("Function 'abs' used without arguments")
@@ -687,90 +685,447 @@ mod tests {
 
 
     #[test]
-    fn test_recursion_depth_with_non_recursive_expressions() {
-        // Test that non-recursive expressions don't accumulate recursion depth
-
-        // Reset the counter
-        RECURSION_DEPTH.store(0, Ordering::Relaxed);
+    fn test_variadic_min_max_and_array_reductions() {
+        let mut ctx = EvalContext::new();
+        ctx.set_parameter("a", 3.0).unwrap();
+        ctx.arrays
+            .insert(
+                "samples".try_into_heapless().unwrap(),
+                vec![2.0, 7.0, -1.0, 7.0],
+            )
+            .unwrap();
+        let ctx = Rc::new(ctx);
+        let eval = |expr: &str| interp(expr, Some(ctx.clone()));
+
+        assert_eq!(eval("max(a, 9, -2, 4)").unwrap(), 9.0);
+        assert_eq!(eval("min(a, 9, -2, 4)").unwrap(), -2.0);
+        assert_eq!(eval("max(a)").unwrap(), 3.0);
+        assert_eq!(eval("argmin(5, a, 4)").unwrap(), 1.0);
+        assert!(matches!(
+            eval("max()"),
+            Err(ExprError::InvalidFunctionCall {
+                expected: 1,
+                found: 0,
+                ..
+            })
+        ));
+
+        assert_eq!(eval("max(samples)").unwrap(), 7.0);
+        assert_eq!(eval("min(samples)").unwrap(), -1.0);
+        // Ties resolve to the first occurrence
+        assert_eq!(eval("argmax(samples)").unwrap(), 1.0);
+        assert_eq!(eval("argmin(samples) + 1").unwrap(), 3.0);
+    }
 
-        // Create a complex but non-recursive expression
-        let expr = "1 + 2 * 3 + 4 * 5 + 6 * 7 + 8 * 9 + 10";
+    #[test]
+    fn test_array_slices_in_aggregates() {
+        let mut ctx = EvalContext::new();
+        ctx.set_parameter("i", 2.0).unwrap();
+        ctx.set_array("data", vec![4.0, 9.0, -3.0, 1.0, 8.0, 2.0])
+            .unwrap();
+        let ctx = Rc::new(ctx);
+        let eval = |expr: &str| interp(expr, Some(ctx.clone()));
+
+        assert_eq!(eval("max(data[0:2])").unwrap(), 9.0);
+        assert_eq!(eval("min(data[i:i + 2]) + 1").unwrap(), -2.0);
+        assert_eq!(eval("max(data[3:6], 5, 10)").unwrap(), 10.0);
+        assert_eq!(eval("argmin(data[1:6])").unwrap(), 1.0);
+        #[cfg(feature = "stats")]
+        assert_eq!(eval("mean(data[2:5])").unwrap(), 2.0);
+        assert_eq!(eval("data[1] + max(data[i > 1 ? 1 : 0:3])").unwrap(), 18.0);
 
-        // Evaluate it
-        let result = interp(expr, None);
+        let arena = bumpalo::Bump::new();
+        let ast = crate::engine::parse_expression("max(data[i - 1:2 * i])", &arena).unwrap();
+        assert_eq!(ast.to_expression_string(), "max(data[i - 1:2 * i])");
+
+        // Empty and out-of-range slices
+        assert!(matches!(
+            eval("max(data[2:2])"),
+            Err(ExprError::InvalidFunctionCall { found: 0, .. })
+        ));
+        for expr in ["max(data[4:7])", "max(data[3:1])", "max(data[-1:2])"] {
+            assert!(matches!(eval(expr), Err(ExprError::Other { .. })), "{expr}");
+        }
+        // A slice is not a value
+        assert!(matches!(
+            eval("data[0:2] + 1"),
+            Err(ExprError::Syntax { .. })
+        ));
+        assert!(matches!(
+            eval("max(1, data[0:2])"),
+            Err(ExprError::Syntax { .. })
+        ));
+    }
 
-        // Verify it works
-        assert!(
-            result.is_ok(),
-            "Failed to evaluate non-recursive expression: {:?}",
-            result.err()
-        );
+    #[test]
+    fn test_nan_predicates_and_fallbacks() {
+        let mut ctx = EvalContext::new();
+        ctx.set_parameter("reading", Real::NAN).unwrap();
+        ctx.set_parameter("last_good", 21.5).unwrap();
+        let ctx = Rc::new(ctx);
+        let eval = |expr: &str| interp(expr, Some(ctx.clone())).unwrap();
+
+        assert_eq!(eval("isnan(reading)"), 1.0);
+        assert_eq!(eval("isnan(last_good)"), 0.0);
+        assert_eq!(eval("isinf(1 / 0) + isinf(-1 / 0)"), 2.0);
         assert_eq!(
-            result.unwrap(),
-            1.0 + 2.0 * 3.0 + 4.0 * 5.0 + 6.0 * 7.0 + 8.0 * 9.0 + 10.0
+            eval("isfinite(last_good) + isfinite(reading) + isfinite(1 / 0)"),
+            1.0
         );
+        assert_eq!(eval("coalesce(reading, 1 / 0, last_good, 0)"), 21.5);
+        assert!(eval("coalesce(reading)").is_nan());
+        assert_eq!(eval("nanfallback(reading, -1)"), -1.0);
+        assert_eq!(eval("nanfallback(last_good, -1)"), 21.5);
+    }
 
-        // Verify the recursion depth stayed low
-        let depth = RECURSION_DEPTH.load(Ordering::Relaxed);
-        assert!(
-            depth < 10,
-            "Unexpectedly high recursion depth for non-recursive expr: {}",
-            depth
-        );
+    #[test]
+    fn test_log_with_base() {
+        let ctx = Rc::new(EvalContext::new());
+        let eval = |expr: &str| interp(expr, Some(ctx.clone())).unwrap();
+
+        assert!((eval("log(100)") - 2.0).abs() < 1e-6);
+        assert!((eval("log(8, 2)") - 3.0).abs() < 1e-6);
+        assert!((eval("log(81, 3)") - 4.0).abs() < 1e-6);
+        assert!(eval("log(8, 1)").is_nan());
+        assert!(eval("log(8, 2, 3)").is_nan());
+        assert_eq!(eval("log2(32)"), 5.0);
+        assert_eq!(eval("log1p(0) + expm1(0)"), 0.0);
     }
 
     #[test]
-    fn test_recursion_tracking_function_specific() {
-        // Test that our recursion tracking is specific to function calls
-        // and doesn't track arithmetic or other AST node evaluation
+    fn test_eval_depth_limit_per_context() {
+        use crate::eval::iterative::EvalEngine;
+        use bumpalo::Bump;
 
-        // Reset counter
-        RECURSION_DEPTH.store(0, Ordering::Relaxed);
+        let expr = "(((((((1 + 1) + 1) + 1) + 1) + 1) + 1) + 1) + 1";
+
+        // Contexts keep independent limits
+        let mut strict = EvalContext::new();
+        strict.set_max_eval_depth(Some(3));
+        let strict = Rc::new(strict);
+        let relaxed = Rc::new(EvalContext::new());
+        assert!(matches!(
+            interp(expr, Some(strict.clone())),
+            Err(ExprError::RecursionLimit { limit: 3, .. })
+        ));
+        assert_eq!(interp(expr, Some(relaxed)).unwrap(), 9.0);
+
+        // Child contexts inherit the limit
+        let mut child = EvalContext::new();
+        child.parent = Some(strict.clone());
+        assert_eq!(child.max_eval_depth(), Some(3));
+        assert!(interp(expr, Some(Rc::new(child))).is_err());
+
+        // An engine-level limit takes precedence over the context's
+        let arena = Bump::new();
+        let ast = arena.alloc(parse_expression(expr, &arena).unwrap());
+        let mut engine = EvalEngine::new(&arena);
+        engine.set_max_depth(Some(64));
+        assert_eq!(engine.eval(ast, Some(strict.clone())).unwrap(), 9.0);
+        engine.set_max_depth(None);
+        assert!(engine.eval(ast, Some(strict)).is_err());
+    }
 
-        // Create a complex expression with many AST nodes but no function calls
-        let expr = "(1 + 2) * (3 + 4) * (5 + 6) * (7 + 8) * (9 + 10) * (11 + 12) * (13 + 14)";
+    #[test]
+    fn test_stack_spill_for_deep_expressions() {
+        use crate::eval::iterative::EvalEngine;
+        use crate::expression::Expression;
+        use bumpalo::Bump;
 
-        // Create a context with the necessary operators
-        let ctx = EvalContext::new();
+        // Machine-generated nesting of 300 terms exceeds the default depth limit
+        let mut expr = String::from("x");
+        for _ in 0..300 {
+            expr = format!("({} + 1) * 1", expr);
+        }
+        let mut ctx = EvalContext::new();
+        ctx.set_parameter("x", 1.0).unwrap();
+        assert!(matches!(
+            interp(&expr, Some(Rc::new(ctx.clone()))),
+            Err(ExprError::RecursionLimit { .. })
+        ));
 
-        // Register the necessary operators if libm is not enabled
-        #[cfg(not(feature = "libm"))]
-        {
-            let _ = ctx.register_native_function("+", 2, |args| args[0] + args[1]);
-            ctx.register_native_function("*", 2, |args| args[0] * args[1]);
+        ctx.set_stack_spill(Some(100_000));
+        let ctx = Rc::new(ctx);
+        assert_eq!(interp(&expr, Some(ctx.clone())).unwrap(), 301.0);
+
+        // The spill limit still bounds the total depth, and an engine setting overrides it
+        let arena = Bump::new();
+        let ast = arena.alloc(parse_expression(&expr, &arena).unwrap());
+        let depth = ResourceEstimate::of(ast).max_stack_depth;
+        let mut engine = EvalEngine::new(&arena);
+        engine.set_stack_spill(Some(depth - 1));
+        assert!(matches!(
+            engine.eval(ast, Some(ctx.clone())),
+            Err(ExprError::RecursionLimit { limit, .. }) if limit == depth - 1
+        ));
+        engine.set_stack_spill(Some(depth));
+        assert_eq!(engine.eval(ast, Some(ctx.clone())).unwrap(), 301.0);
+
+        // Expression function parameters stay visible after their scope is spilled
+        let mut shallow = EvalContext::new();
+        shallow.set_max_eval_depth(Some(6));
+        shallow.set_stack_spill(Some(1000));
+        let shallow = Rc::new(shallow);
+        let mut builder = Expression::new(&arena);
+        builder
+            .register_expression_function("scaled", &["a", "b"], "((((a + 1) + 1) + 1) + 1) * b")
+            .unwrap();
+        builder
+            .add_expression("scaled(2, 3) + scaled(1, 1)")
+            .unwrap();
+        builder.eval(&shallow).unwrap();
+        assert_eq!(builder.get_result(0), Some(23.0));
+
+        // Spilled operations leave the arena stack at its reserved size
+        let nested = format!("{}x{}", "abs(".repeat(1500), ")".repeat(1500));
+        let ast = arena.alloc(parse_expression(&nested, &arena).unwrap());
+        let stack_bytes = |max_depth: usize, spill: Option<usize>| {
+            let stacks = Bump::new();
+            let mut engine = EvalEngine::new(&stacks);
+            engine.set_max_depth(Some(max_depth));
+            engine.set_stack_spill(spill);
+            assert_eq!(engine.eval(ast, Some(ctx.clone())).unwrap(), 1.0);
+            stacks.allocated_bytes()
+        };
+        assert!(stack_bytes(64, Some(100_000)) * 8 < stack_bytes(100_000, None));
+
+        // Array indices nested past the depth limit spill too
+        let mut arrays = EvalContext::new();
+        arrays.set_array("a", vec![0.0, 2.0]).unwrap();
+        let arrays = Rc::new(arrays);
+        let nested = format!("{}0{}", "a[".repeat(500), "]".repeat(500));
+        let deep = arena.alloc(parse_expression(&nested, &arena).unwrap());
+        let shallow = arena.alloc(parse_expression("a[1]", &arena).unwrap());
+        let mut engine = EvalEngine::new(&arena);
+        engine.set_max_depth(Some(64));
+        engine.set_stack_spill(Some(100_000));
+        assert_eq!(engine.eval(shallow, Some(arrays.clone())).unwrap(), 2.0);
+        let capacity = engine.op_stack_capacity();
+        assert_eq!(engine.eval(deep, Some(arrays)).unwrap(), 0.0);
+        assert_eq!(engine.op_stack_capacity(), capacity);
+    }
+
+    #[test]
+    fn test_resumable_eval() {
+        use crate::eval::iterative::{EvalEngine, EvalStep};
+
+        let arena = bumpalo::Bump::new();
+        let mut ctx = EvalContext::new();
+        ctx.set_parameter("x", 2.0).unwrap();
+        ctx.set_array("samples", vec![1.0, 2.0, 3.0]).unwrap();
+        let ctx = Rc::new(ctx);
+        let ast = arena.alloc(
+            parse_expression(
+                "x > 1 ? max(samples) * x + pow(x, 3) * 0.75 : -1 || x",
+                &arena,
+            )
+            .unwrap(),
+        );
+        let mut engine = EvalEngine::new(&arena);
+        let expected = engine.eval(ast, Some(ctx.clone())).unwrap();
+
+        // Any budget gives the same result, in more calls for smaller budgets
+        for budget in [0, 1, 2, 3, 5, 100] {
+            engine.start_eval(ast, Some(ctx.clone())).unwrap();
+            let mut calls = 0;
+            let result = loop {
+                calls += 1;
+                assert!(engine.is_pending());
+                if let EvalStep::Done(value) = engine.resume(budget).unwrap() {
+                    break value;
+                }
+            };
+            assert_eq!(result, expected);
+            assert!(!engine.is_pending());
+            assert_eq!(calls > 1, budget < 10, "budget {budget}");
         }
+        assert!(engine.resume(10).is_err());
+
+        // Starting again abandons the evaluation in progress
+        let other = arena.alloc(parse_expression("x * 2", &arena).unwrap());
+        engine.start_eval(ast, Some(ctx.clone())).unwrap();
+        assert_eq!(engine.resume(1).unwrap(), EvalStep::Pending);
+        assert_eq!(engine.eval(other, Some(ctx.clone())).unwrap(), 4.0);
+        assert!(!engine.is_pending());
+
+        // An error ends the evaluation
+        let failing = arena.alloc(parse_expression("1 + 2 + nope", &arena).unwrap());
+        engine.start_eval(failing, Some(ctx)).unwrap();
+        let error = loop {
+            match engine.resume(1) {
+                Ok(step) => assert_eq!(step, EvalStep::Pending),
+                Err(error) => break error,
+            }
+        };
+        assert!(matches!(error, ExprError::UnknownVariable { .. }));
+        assert!(!engine.is_pending());
+    }
 
-        // Evaluate it with the context
-        let result = interp(expr, Some(Rc::new(ctx)));
+    #[test]
+    fn test_watchdog() {
+        use crate::expression::Expression;
+        use std::cell::Cell;
+        use std::ops::ControlFlow;
 
-        // Verify it works
-        assert!(result.is_ok());
+        let kicks = Rc::new(Cell::new(0));
+        let remaining = Rc::new(Cell::new(usize::MAX));
+        let mut ctx = EvalContext::new();
+        ctx.set_parameter("x", 2.0).unwrap();
+        let (k, r) = (kicks.clone(), remaining.clone());
+        ctx.set_watchdog(3, move || {
+            k.set(k.get() + 1);
+            r.set(r.get().saturating_sub(1));
+            if r.get() == 0 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        // Children use the parent's watchdog
+        let ctx = Rc::new(ctx);
+        let mut child = EvalContext::new();
+        child.parent = Some(ctx.clone());
+        let child = Rc::new(child);
 
-        // Verify the recursion depth stayed at zero or very low
-        // When running without libm, the depth will be higher because
-        // the operators are implemented as function calls
-        #[cfg(feature = "libm")]
-        {
-            let depth = RECURSION_DEPTH.load(Ordering::Relaxed);
-            assert!(
-                depth < 5,
-                "Recursion tracking shouldn't count non-function AST nodes, got depth: {}",
-                depth
-            );
+        // Ten expressions of 3 nodes each kick the watchdog ten times in total
+        let arena = bumpalo::Bump::new();
+        let mut batch = Expression::new(&arena);
+        for i in 0..10 {
+            batch.add_expression(&format!("x + {}", i)).unwrap();
         }
+        batch.eval(&child).unwrap();
+        assert_eq!(batch.get_result(9), Some(11.0));
+        assert_eq!(kicks.get(), 10);
+
+        // Aborting fails the evaluation in progress
+        remaining.set(2);
+        let result = interp("x * (x + 1) * (x + 2) * (x + 3)", Some(ctx.clone()));
+        assert!(matches!(result, Err(ExprError::Aborted)));
+        assert_eq!(ExprError::Aborted.error_code(), 21);
+
+        let mut quiet = EvalContext::new();
+        quiet.parent = Some(ctx);
+        quiet.set_watchdog(1, || ControlFlow::Continue(()));
+        remaining.set(1);
+        assert_eq!(interp("x * 3", Some(Rc::new(quiet))).unwrap(), 6.0);
+    }
 
-        // When not using libm, the test can't be as strict because the operators
-        // are implemented as explicit function calls
-        #[cfg(not(feature = "libm"))]
-        {
-            let depth = RECURSION_DEPTH.load(Ordering::Relaxed);
-            println!(
-                "Without libm, recursion depth is higher due to operator functions: {}",
-                depth
-            );
+    #[test]
+    fn test_evaluation_order() {
+        use crate::expression::Expression;
+        use std::cell::RefCell;
+
+        // `rec(n)` records n and returns it
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let mut ctx = EvalContext::new();
+        let s = seen.clone();
+        ctx.register_native_function("rec", 1, move |args| {
+            s.borrow_mut().push(args[0]);
+            args[0]
+        })
+        .unwrap();
+        ctx.register_variadic_function("f", 1, |args| args.len() as Real)
+            .unwrap();
+        let ctx = Rc::new(ctx);
+        let order = |expr: &str| {
+            seen.borrow_mut().clear();
+            let value = interp(expr, Some(ctx.clone())).unwrap();
+            (value, seen.borrow().clone())
+        };
+
+        // Arguments and operands left to right, nested calls before the next argument
+        assert_eq!(
+            order("f(rec(1), rec(rec(2) + rec(3)), rec(4))"),
+            (3.0, vec![1.0, 2.0, 3.0, 5.0, 4.0])
+        );
+        assert_eq!(
+            order("rec(1) - rec(2) * rec(3) ^ rec(4)"),
+            (-161.0, vec![1.0, 2.0, 3.0, 4.0])
+        );
+        assert_eq!(order("max(rec(3), rec(1), rec(2))").1, vec![3.0, 1.0, 2.0]);
+        // Short-circuit operators and conditionals skip unneeded operands
+        assert_eq!(order("rec(0) && rec(1)").1, vec![0.0]);
+        assert_eq!(order("rec(1) ? rec(2) : rec(3)"), (2.0, vec![1.0, 2.0]));
+        // Lists with either separator, valued by their last item
+        assert_eq!(order("rec(1), rec(2); rec(3)"), (3.0, vec![1.0, 2.0, 3.0]));
+        assert_eq!(order("(rec(1); rec(2)) * 2"), (4.0, vec![1.0, 2.0]));
+
+        // A batch runs in insertion order, named expressions before their first use
+        let arena = bumpalo::Bump::new();
+        let mut batch = Expression::new(&arena);
+        batch.add_expression("rec(10)").unwrap();
+        batch.add_expression("rec(20) + late").unwrap();
+        batch.add_expression("rec(30)").unwrap();
+        batch.add_named_expression("late", "rec(40)").unwrap();
+        seen.borrow_mut().clear();
+        batch.eval(&ctx).unwrap();
+        assert_eq!(*seen.borrow(), vec![10.0, 40.0, 20.0, 30.0]);
+        assert_eq!(batch.get_result(1), Some(60.0));
+
+        // Patches list variables by name whatever the map order
+        let mut before = EvalContext::new();
+        let mut after = EvalContext::new();
+        for name in ["d", "b", "e", "a", "c"] {
+            before.set_parameter(name, 1.0).unwrap();
+        }
+        for name in ["z", "x", "y"] {
+            after.set_parameter(name, 2.0).unwrap();
         }
+        let patch = before.diff(&after);
+        let names = |list: Vec<&str>| list.join(",");
+        assert_eq!(
+            names(patch.set.iter().map(|(n, _)| n.as_str()).collect()),
+            "x,y,z"
+        );
+        assert_eq!(
+            names(patch.removed.iter().map(|n| n.as_str()).collect()),
+            "a,b,c,d,e"
+        );
     }
 
+    #[test]
+    fn test_eval_hooks() {
+        use crate::eval::iterative::EvalEngine;
+        use std::cell::RefCell;
 
+        let arena = bumpalo::Bump::new();
+        let mut ctx = EvalContext::new();
+        ctx.set_parameter("x", 4.0).unwrap();
+        let ctx = Rc::new(ctx);
+        let ast = arena.alloc(parse_expression("x > 0 ? max(x, 3) * 2 + 1 : -1", &arena).unwrap());
+
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let nodes = Rc::new(RefCell::new(Vec::new()));
+        let mut engine = EvalEngine::new(&arena);
+        let log = calls.clone();
+        engine.set_on_function_call(move |name, args, result| {
+            log.borrow_mut()
+                .push(format!("{}{:?}={}", name, args, result));
+        });
+        let log = nodes.clone();
+        engine.set_on_node_eval(move |node, value| {
+            log.borrow_mut().push((node.to_expression_string(), value));
+        });
 
+        assert_eq!(engine.eval(ast, Some(ctx.clone())).unwrap(), 9.0);
+        assert_eq!(
+            *calls.borrow(),
+            [
+                ">[4.0, 0.0]=1",
+                "max[4.0, 3.0]=4",
+                "*[4.0, 2.0]=8",
+                "+[8.0, 1.0]=9"
+            ]
+        );
+        let nodes = nodes.borrow();
+        assert!(nodes.contains(&("max(x, 3) * 2".to_string(), 8.0)));
+        // The skipped false branch is not reported and the root comes last
+        assert!(nodes.iter().all(|(_, v)| *v != -1.0));
+        assert_eq!(nodes.last().unwrap().1, 9.0);
+
+        engine.clear_hooks();
+        calls.borrow_mut().clear();
+        engine.eval(ast, Some(ctx)).unwrap();
+        assert!(calls.borrow().is_empty());
+    }
 }
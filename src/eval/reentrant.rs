@@ -0,0 +1,463 @@
+//! Re-entrant evaluation without shared or heap state.
+//!
+//! [`eval_reentrant`] evaluates a parsed AST against a [`StaticContext`], a context made
+//! of borrowed slices that can be built by `const fn` (and so placed in a `static`).
+//! The evaluator keeps its operation and value stacks in fixed-size arrays on the
+//! caller's stack and touches no statics, so it can run concurrently from several tasks
+//! and from interrupt handlers.
+//!
+//! The success path never allocates. Building an [`ExprError`] that carries a name
+//! does, so an interrupt handler should only evaluate expressions that were checked
+//! once beforehand, e.g. at startup.
+//!
+//! Parsing still needs an arena; parse outside the interrupt and keep the AST.
+
+use crate::Real;
+use crate::error::ExprError;
+use crate::types::{AstExpr, LogicalOperator};
+use alloc::format;
+use alloc::string::ToString;
+use heapless::Vec as FixedVec;
+
+/// A function callable from a [`StaticContext`], receiving its evaluated arguments.
+pub type StaticFunction = fn(&[Real]) -> Real;
+
+/// Operation stack depth used by [`eval_reentrant`].
+pub const DEFAULT_REENTRANT_DEPTH: usize = 64;
+
+/// A minimal evaluation context made of borrowed slices.
+///
+/// Names are looked up linearly, so keep the slices short. Functions in
+/// [`with_functions`](Self::with_functions) take precedence over the built-in
+/// operators and math functions, which can also be disabled altogether.
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::eval::reentrant::{StaticContext, StaticFunction};
+/// use exp_rs::Real;
+///
+/// static GAINS: [Real; 3] = [0.5, 1.0, 2.0];
+/// static FUNCTIONS: [(&str, usize, StaticFunction); 1] =
+///     [("clamp01", 1, |args| args[0].max(0.0).min(1.0))];
+/// static BASE: StaticContext<'static> = StaticContext::new()
+///     .with_arrays(&[("gain", &GAINS)])
+///     .with_functions(&FUNCTIONS);
+///
+/// let arena = bumpalo::Bump::new();
+/// let ast = exp_rs::engine::parse_expression("clamp01(x * gain[2])", &arena).unwrap();
+///
+/// // Per-call values live on the caller's stack
+/// let vars = [("x", 0.3)];
+/// assert_eq!(BASE.with_variables(&vars).eval(&ast).unwrap(), 0.6);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct StaticContext<'a> {
+    variables: &'a [(&'a str, Real)],
+    arrays: &'a [(&'a str, &'a [Real])],
+    functions: &'a [(&'a str, usize, StaticFunction)],
+    builtins: bool,
+}
+
+impl Default for StaticContext<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> StaticContext<'a> {
+    /// Creates a context with only the built-in operators and functions.
+    pub const fn new() -> Self {
+        Self {
+            variables: &[],
+            arrays: &[],
+            functions: &[],
+            builtins: true,
+        }
+    }
+
+    /// Replaces the variables.
+    pub const fn with_variables(self, variables: &'a [(&'a str, Real)]) -> Self {
+        Self { variables, ..self }
+    }
+
+    /// Replaces the arrays, indexed with `name[index]`.
+    pub const fn with_arrays(self, arrays: &'a [(&'a str, &'a [Real])]) -> Self {
+        Self { arrays, ..self }
+    }
+
+    /// Replaces the functions, given as `(name, arity, function)`.
+    pub const fn with_functions(self, functions: &'a [(&'a str, usize, StaticFunction)]) -> Self {
+        Self { functions, ..self }
+    }
+
+    /// Disables the built-in operators and functions, so that only
+    /// [`with_functions`](Self::with_functions) are available.
+    pub const fn without_builtins(self) -> Self {
+        Self {
+            builtins: false,
+            ..self
+        }
+    }
+
+    /// Evaluates `ast` with [`eval_reentrant`].
+    pub fn eval(&self, ast: &AstExpr) -> Result<Real, ExprError> {
+        eval_reentrant(ast, self)
+    }
+
+    fn variable(&self, name: &str) -> Option<Real> {
+        self.variables
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| *v)
+    }
+
+    fn array(&self, name: &str) -> Option<&'a [Real]> {
+        self.arrays
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, values)| *values)
+    }
+
+    fn function(&self, name: &str) -> Option<(usize, StaticFunction)> {
+        let builtins: &[(&str, usize, StaticFunction)] = if self.builtins { BUILTINS } else { &[] };
+        let libm: &[(&str, usize, StaticFunction)] =
+            if self.builtins { LIBM_BUILTINS } else { &[] };
+        self.functions
+            .iter()
+            .chain(builtins)
+            .chain(libm)
+            .find(|(n, _, _)| *n == name)
+            .map(|(_, arity, f)| (*arity, *f))
+    }
+}
+
+fn bool_value(b: bool) -> Real {
+    if b { 1.0 } else { 0.0 }
+}
+
+const BUILTINS: &[(&str, usize, StaticFunction)] = &[
+    #[cfg(not(feature = "softfloat"))]
+    ("+", 2, |a| a[0] + a[1]),
+    #[cfg(feature = "softfloat")]
+    ("+", 2, |a| crate::softfloat::add(a[0], a[1])),
+    #[cfg(not(feature = "softfloat"))]
+    ("-", 2, |a| a[0] - a[1]),
+    #[cfg(feature = "softfloat")]
+    ("-", 2, |a| crate::softfloat::sub(a[0], a[1])),
+    #[cfg(not(feature = "softfloat"))]
+    ("*", 2, |a| a[0] * a[1]),
+    #[cfg(feature = "softfloat")]
+    ("*", 2, |a| crate::softfloat::mul(a[0], a[1])),
+    #[cfg(not(feature = "softfloat"))]
+    ("/", 2, |a| a[0] / a[1]),
+    #[cfg(feature = "softfloat")]
+    ("/", 2, |a| crate::softfloat::div(a[0], a[1])),
+    #[cfg(not(feature = "softfloat"))]
+    ("%", 2, |a| a[0] % a[1]),
+    #[cfg(feature = "softfloat")]
+    ("%", 2, |a| crate::softfloat::rem(a[0], a[1])),
+    #[cfg(not(feature = "softfloat"))]
+    ("neg", 1, |a| -a[0]),
+    #[cfg(feature = "softfloat")]
+    ("neg", 1, |a| crate::softfloat::neg(a[0])),
+    ("<", 2, |a| bool_value(a[0] < a[1])),
+    (">", 2, |a| bool_value(a[0] > a[1])),
+    ("<=", 2, |a| bool_value(a[0] <= a[1])),
+    (">=", 2, |a| bool_value(a[0] >= a[1])),
+    ("==", 2, |a| bool_value(a[0] == a[1])),
+    ("!=", 2, |a| bool_value(a[0] != a[1])),
+    (",", 2, |a| a[1]),
+    (";", 2, |a| a[1]),
+    ("abs", 1, |a| a[0].abs()),
+    ("max", 2, |a| a[0].max(a[1])),
+    ("min", 2, |a| a[0].min(a[1])),
+    ("sign", 1, |a| {
+        if a[0] > 0.0 {
+            1.0
+        } else if a[0] < 0.0 {
+            -1.0
+        } else {
+            0.0
+        }
+    }),
+    ("pi", 0, |_| crate::constants::PI),
+    ("e", 0, |_| crate::constants::E),
+];
+
+#[cfg(feature = "libm")]
+const LIBM_BUILTINS: &[(&str, usize, StaticFunction)] = &[
+    ("^", 2, |a| crate::functions::pow(a[0], a[1])),
+    ("**", 2, |a| crate::functions::pow(a[0], a[1])),
+    ("pow", 2, |a| crate::functions::pow(a[0], a[1])),
+    ("sqrt", 1, |a| crate::functions::sqrt(a[0], 0.0)),
+    ("exp", 1, |a| crate::functions::exp(a[0], 0.0)),
+    ("ln", 1, |a| crate::functions::ln(a[0], 0.0)),
+    ("log", 1, |a| crate::functions::log(a[0], 0.0)),
+    ("log10", 1, |a| crate::functions::log10(a[0], 0.0)),
+    ("sin", 1, |a| crate::functions::sin(a[0], 0.0)),
+    ("cos", 1, |a| crate::functions::cos(a[0], 0.0)),
+    ("tan", 1, |a| crate::functions::tan(a[0], 0.0)),
+    ("asin", 1, |a| crate::functions::asin(a[0], 0.0)),
+    ("acos", 1, |a| crate::functions::acos(a[0], 0.0)),
+    ("atan", 1, |a| crate::functions::atan(a[0], 0.0)),
+    ("atan2", 2, |a| crate::functions::atan2(a[0], a[1])),
+    ("floor", 1, |a| crate::functions::floor(a[0], 0.0)),
+    ("ceil", 1, |a| crate::functions::ceil(a[0], 0.0)),
+    ("round", 1, |a| crate::functions::round(a[0], 0.0)),
+];
+
+#[cfg(not(feature = "libm"))]
+const LIBM_BUILTINS: &[(&str, usize, StaticFunction)] = &[];
+
+enum Op<'a> {
+    Eval(&'a AstExpr<'a>),
+    Call(StaticFunction, usize),
+    Index(&'a str, &'a [Real]),
+    Select(&'a AstExpr<'a>, &'a AstExpr<'a>),
+    And(&'a AstExpr<'a>),
+    Or(&'a AstExpr<'a>),
+    Truthy,
+}
+
+/// Evaluates `ast` against `ctx` with an operation stack of
+/// [`DEFAULT_REENTRANT_DEPTH`] entries.
+///
+/// See the [module documentation](self) for the guarantees of this path.
+pub fn eval_reentrant(ast: &AstExpr, ctx: &StaticContext) -> Result<Real, ExprError> {
+    eval_reentrant_with_depth::<DEFAULT_REENTRANT_DEPTH>(ast, ctx)
+}
+
+/// Evaluates `ast` against `ctx` with an operation stack of `DEPTH` entries.
+///
+/// Both stacks live on the caller's stack, taking roughly `DEPTH * 40` bytes.
+/// Expressions nesting deeper than `DEPTH` fail with [`ExprError::RecursionLimit`].
+pub fn eval_reentrant_with_depth<const DEPTH: usize>(
+    ast: &AstExpr,
+    ctx: &StaticContext,
+) -> Result<Real, ExprError> {
+    let mut ops: FixedVec<Op, DEPTH> = FixedVec::new();
+    let mut values: FixedVec<Real, DEPTH> = FixedVec::new();
+    let depth_exceeded = || ExprError::RecursionLimit {
+        limit: DEPTH,
+        message: format!("Maximum evaluation depth {} exceeded", DEPTH),
+    };
+    macro_rules! push_op {
+        ($op:expr) => {
+            ops.push($op).map_err(|_| depth_exceeded())?
+        };
+    }
+    macro_rules! push_value {
+        ($v:expr) => {
+            values.push($v).map_err(|_| depth_exceeded())?
+        };
+    }
+    macro_rules! pop_value {
+        () => {
+            values.pop().ok_or_else(|| ExprError::Other {
+                message: "Value stack underflow".to_string(),
+            })?
+        };
+    }
+
+    push_op!(Op::Eval(ast));
+    while let Some(op) = ops.pop() {
+        match op {
+            Op::Eval(expr) => match expr {
+                AstExpr::Constant(v) => push_value!(*v),
+                AstExpr::Variable(name) => {
+                    let value = match ctx.variable(name) {
+                        Some(v) => v,
+                        None => match ctx.function(name) {
+                            Some((0, f)) => f(&[]),
+                            _ => {
+                                return Err(ExprError::UnknownVariable {
+                                    name: name.to_string(),
+                                });
+                            }
+                        },
+                    };
+                    push_value!(value);
+                }
+                AstExpr::Function { name, args } if args.len() == 2 && *name == "&&" => {
+                    push_op!(Op::And(&args[1]));
+                    push_op!(Op::Eval(&args[0]));
+                }
+                AstExpr::Function { name, args } if args.len() == 2 && *name == "||" => {
+                    push_op!(Op::Or(&args[1]));
+                    push_op!(Op::Eval(&args[0]));
+                }
+                AstExpr::Function { name, args } => {
+                    let (arity, f) =
+                        ctx.function(name)
+                            .ok_or_else(|| ExprError::UnknownFunction {
+                                name: name.to_string(),
+                            })?;
+                    if arity != args.len() {
+                        return Err(ExprError::InvalidFunctionCall {
+                            name: name.to_string(),
+                            expected: arity,
+                            found: args.len(),
+                        });
+                    }
+                    push_op!(Op::Call(f, arity));
+                    for arg in args.iter().rev() {
+                        push_op!(Op::Eval(arg));
+                    }
+                }
+                AstExpr::Array { name, index } => {
+                    let array = ctx.array(name).ok_or_else(|| ExprError::UnknownVariable {
+                        name: name.to_string(),
+                    })?;
+                    push_op!(Op::Index(name, array));
+                    push_op!(Op::Eval(index));
+                }
+                AstExpr::Attribute { base, attr } => {
+                    return Err(ExprError::AttributeNotFound {
+                        base: base.to_string(),
+                        attr: attr.to_string(),
+                    });
+                }
+                AstExpr::LogicalOp { op, left, right } => {
+                    push_op!(match op {
+                        LogicalOperator::And => Op::And(right),
+                        LogicalOperator::Or => Op::Or(right),
+                    });
+                    push_op!(Op::Eval(left));
+                }
+                AstExpr::Conditional {
+                    condition,
+                    true_branch,
+                    false_branch,
+                } => {
+                    push_op!(Op::Select(true_branch, false_branch));
+                    push_op!(Op::Eval(condition));
+                }
+            },
+            Op::Call(f, arity) => {
+                let start = values
+                    .len()
+                    .checked_sub(arity)
+                    .ok_or_else(|| ExprError::Other {
+                        message: "Value stack underflow".to_string(),
+                    })?;
+                let result = f(&values[start..]);
+                #[cfg(feature = "softfloat")]
+                let result = crate::softfloat::canonicalize(result);
+                values.truncate(start);
+                push_value!(result);
+            }
+            Op::Index(name, array) => {
+                let index = pop_value!();
+                let i = index as usize;
+                if index < 0.0 || i >= array.len() {
+                    return Err(ExprError::ArrayIndexOutOfBounds {
+                        name: name.to_string(),
+                        index: i,
+                        len: array.len(),
+                    });
+                }
+                push_value!(array[i]);
+            }
+            Op::Select(true_branch, false_branch) => {
+                let condition = pop_value!();
+                push_op!(Op::Eval(if condition != 0.0 {
+                    true_branch
+                } else {
+                    false_branch
+                }));
+            }
+            Op::And(right) => {
+                if pop_value!() == 0.0 {
+                    push_value!(0.0);
+                } else {
+                    push_op!(Op::Truthy);
+                    push_op!(Op::Eval(right));
+                }
+            }
+            Op::Or(right) => {
+                if pop_value!() != 0.0 {
+                    push_value!(1.0);
+                } else {
+                    push_op!(Op::Truthy);
+                    push_op!(Op::Eval(right));
+                }
+            }
+            Op::Truthy => {
+                let value = pop_value!();
+                push_value!(bool_value(value != 0.0));
+            }
+        }
+    }
+
+    Ok(pop_value!())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{interp, parse_expression};
+    use bumpalo::Bump;
+
+    #[test]
+    fn test_reentrant_matches_engine() {
+        let arena = Bump::new();
+        let vars = [("x", 2.5), ("y", -1.0)];
+        let ctx = StaticContext::new().with_variables(&vars);
+        let mut full = crate::context::EvalContext::new();
+        full.set_parameter("x", 2.5).unwrap();
+        full.set_parameter("y", -1.0).unwrap();
+        let full = alloc::rc::Rc::new(full);
+
+        for expr in [
+            "x * 2 + y",
+            "-x + abs(y) * pi",
+            "x > 2 && y < 0",
+            "y > 0 || x == 2.5",
+            "x > 3 ? 1 : max(x, y)",
+            "(x, y)",
+        ] {
+            let ast = parse_expression(expr, &arena).unwrap();
+            assert_eq!(
+                eval_reentrant(&ast, &ctx).unwrap(),
+                interp(expr, Some(full.clone())).unwrap(),
+                "{}",
+                expr
+            );
+        }
+
+        // Short-circuiting skips the unknown variable
+        let ast = parse_expression("y > 0 && missing", &arena).unwrap();
+        assert_eq!(eval_reentrant(&ast, &ctx).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_reentrant_limits_and_errors() {
+        let arena = Bump::new();
+        let ast = parse_expression("((((1 + 1) + 1) + 1) + 1)", &arena).unwrap();
+        assert!(matches!(
+            eval_reentrant_with_depth::<2>(&ast, &StaticContext::new()),
+            Err(ExprError::RecursionLimit { limit: 2, .. })
+        ));
+        assert_eq!(eval_reentrant(&ast, &StaticContext::new()).unwrap(), 5.0);
+
+        let ctx = StaticContext::new().without_builtins();
+        assert!(matches!(
+            eval_reentrant(&ast, &ctx),
+            Err(ExprError::UnknownFunction { .. })
+        ));
+
+        let data = [1.0, 2.0];
+        let arrays = [("data", &data[..])];
+        let ctx = StaticContext::new().with_arrays(&arrays);
+        let ast = parse_expression("data[1] + data[2]", &arena).unwrap();
+        assert!(matches!(
+            eval_reentrant(&ast, &ctx),
+            Err(ExprError::ArrayIndexOutOfBounds {
+                index: 2,
+                len: 2,
+                ..
+            })
+        ));
+    }
+}
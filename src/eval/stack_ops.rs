@@ -46,6 +46,8 @@ pub enum EvalOp<'arena> {
         name: FunctionName,
         arg_count: usize,
         ctx_id: usize,
+        /// The call, which scopes the trigger functions of an expression function body
+        site: &'arena AstExpr<'arena>,
     },
 
     /// Handle ternary operator - condition already evaluated
@@ -56,7 +58,7 @@ pub enum EvalOp<'arena> {
     },
 
     /// Variable lookup
-    LookupVariable { name: HString, ctx_id: usize },
+    LookupVariable { name: &'arena str, ctx_id: usize },
 
     /// Array access - index already evaluated
     AccessArray { array_name: HString, ctx_id: usize },
@@ -72,9 +74,72 @@ pub enum EvalOp<'arena> {
     RestoreFunctionParams {
         /// Parameters for the current function scope
         params: Option<&'arena [(crate::types::HString, crate::Real)]>,
+        /// The call of the function, whose trigger functions keep their state apart
+        /// from those of other calls of the same function
+        site: &'arena AstExpr<'arena>,
+    },
+
+    /// Report a node's value (now on top of the value stack) to the node hook
+    NodeEvaluated { expr: &'arena AstExpr<'arena> },
+
+    /// Apply a stateful trigger function after its arguments are evaluated; `site`
+    /// is the call, whose state is kept between evaluations
+    ApplyTrigger {
+        op: TriggerOp,
+        site: &'arena AstExpr<'arena>,
+    },
+
+    /// Interpolate in the tables named by `args[1]` and `args[2]` of a `lut` call,
+    /// after `x` and the optional mode are evaluated
+    ApplyLut {
+        args: &'arena [AstExpr<'arena>],
+        ctx_id: usize,
+    },
+
+    /// Read the history `name` with the sample count now on top of the value stack
+    ApplyHistory {
+        op: crate::history::HistoryOp,
+        name: &'arena str,
+        ctx_id: usize,
+    },
+
+    /// Apply an aggregate whose first argument is the slice `array[start:end]`, after
+    /// the bounds and the `extra` other arguments are evaluated
+    ApplySliceFunction {
+        name: FunctionName,
+        array: &'arena str,
+        extra: usize,
+        ctx_id: usize,
+        site: &'arena AstExpr<'arena>,
     },
 }
 
+/// Stateful trigger functions, remembering a value per call site
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriggerOp {
+    /// `rising(cond)`: 1 when `cond` became true
+    Rising,
+    /// `falling(cond)`: 1 when `cond` became false
+    Falling,
+    /// `changed(x)`: 1 when `x` differs from its previous value
+    Changed,
+    /// `latch(set, reset)`: 1 from a true `set` until a true `reset`
+    Latch,
+    /// `hysteresis(x, low, high)`: 1 once `x` exceeds `high`, 0 once it falls below `low`
+    Hysteresis,
+    /// `debounce(cond, n)`: `cond` once it kept its value for `n` evaluations
+    Debounce,
+}
+
+/// State remembered by a trigger call between evaluations
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TriggerState {
+    /// Previous output, or previous argument for `changed`
+    pub value: Real,
+    /// Consecutive evaluations counted by `debounce`
+    pub count: u32,
+}
+
 /// Unary operators
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum UnaryOp {
@@ -104,7 +169,10 @@ impl UnaryOp {
     /// Apply a unary operation to a value
     pub fn apply(self, operand: Real) -> Real {
         match self {
+            #[cfg(not(feature = "softfloat"))]
             UnaryOp::Negate => -operand,
+            #[cfg(feature = "softfloat")]
+            UnaryOp::Negate => crate::softfloat::neg(operand),
             UnaryOp::Not => {
                 if operand == 0.0 {
                     1.0
@@ -116,15 +184,135 @@ impl UnaryOp {
     }
 }
 
+impl TriggerOp {
+    /// Returns the trigger function called `name` with `arg_count` arguments
+    pub fn from_call(name: &str, arg_count: usize) -> Option<Self> {
+        match (name, arg_count) {
+            ("rising", 1) => Some(TriggerOp::Rising),
+            ("falling", 1) => Some(TriggerOp::Falling),
+            ("changed", 1) => Some(TriggerOp::Changed),
+            ("latch", 2) => Some(TriggerOp::Latch),
+            ("hysteresis", 3) => Some(TriggerOp::Hysteresis),
+            ("debounce", 2) => Some(TriggerOp::Debounce),
+            _ => None,
+        }
+    }
+
+    /// Number of arguments the trigger takes
+    pub fn arity(self) -> usize {
+        match self {
+            TriggerOp::Rising | TriggerOp::Falling | TriggerOp::Changed => 1,
+            TriggerOp::Latch | TriggerOp::Debounce => 2,
+            TriggerOp::Hysteresis => 3,
+        }
+    }
+
+    /// Apply the trigger to its arguments and the state of the previous evaluation
+    /// (`None` the first time), returning the result and the new state
+    pub fn apply(self, args: &[Real], state: Option<TriggerState>) -> (Real, TriggerState) {
+        let truth = |v: Real| if v != 0.0 { 1.0 } else { 0.0 };
+        let previous = state.map(|s| s.value);
+        let output = |value: Real| (value, TriggerState { value, count: 0 });
+        match self {
+            TriggerOp::Rising => {
+                let now = truth(args[0]);
+                let edge = now == 1.0 && previous != Some(1.0);
+                (
+                    edge as u8 as Real,
+                    TriggerState {
+                        value: now,
+                        count: 0,
+                    },
+                )
+            }
+            TriggerOp::Falling => {
+                let now = truth(args[0]);
+                let edge = now == 0.0 && previous == Some(1.0);
+                (
+                    edge as u8 as Real,
+                    TriggerState {
+                        value: now,
+                        count: 0,
+                    },
+                )
+            }
+            TriggerOp::Changed => {
+                let changed =
+                    previous.is_some_and(|p| p != args[0] && !(p.is_nan() && args[0].is_nan()));
+                (
+                    changed as u8 as Real,
+                    TriggerState {
+                        value: args[0],
+                        count: 0,
+                    },
+                )
+            }
+            TriggerOp::Latch => output(if args[1] != 0.0 {
+                0.0
+            } else if args[0] != 0.0 {
+                1.0
+            } else {
+                previous.unwrap_or(0.0)
+            }),
+            TriggerOp::Hysteresis => {
+                let (x, low, high) = (args[0], args[1], args[2]);
+                output(if x > high {
+                    1.0
+                } else if x < low {
+                    0.0
+                } else {
+                    previous.unwrap_or(0.0)
+                })
+            }
+            TriggerOp::Debounce => {
+                // The output follows `cond` once it differed from the output for `n`
+                // evaluations in a row
+                let state = state.unwrap_or_default();
+                let now = truth(args[0]);
+                if now == state.value {
+                    return output(now);
+                }
+                let count = state.count.saturating_add(1);
+                if count as Real >= args[1] {
+                    output(now)
+                } else {
+                    (
+                        state.value,
+                        TriggerState {
+                            value: state.value,
+                            count,
+                        },
+                    )
+                }
+            }
+        }
+    }
+}
+
 impl BinaryOp {
     /// Apply a binary operation to two values
     pub fn apply(self, left: Real, right: Real) -> Real {
         match self {
+            #[cfg(not(feature = "softfloat"))]
             BinaryOp::Add => left + right,
+            #[cfg(not(feature = "softfloat"))]
             BinaryOp::Subtract => left - right,
+            #[cfg(not(feature = "softfloat"))]
             BinaryOp::Multiply => left * right,
+            #[cfg(not(feature = "softfloat"))]
             BinaryOp::Divide => left / right,
+            #[cfg(not(feature = "softfloat"))]
             BinaryOp::Modulo => left % right,
+            #[cfg(feature = "softfloat")]
+            BinaryOp::Add => crate::softfloat::add(left, right),
+            #[cfg(feature = "softfloat")]
+            BinaryOp::Subtract => crate::softfloat::sub(left, right),
+            #[cfg(feature = "softfloat")]
+            BinaryOp::Multiply => crate::softfloat::mul(left, right),
+            #[cfg(feature = "softfloat")]
+            BinaryOp::Divide => crate::softfloat::div(left, right),
+            #[cfg(feature = "softfloat")]
+            BinaryOp::Modulo => crate::softfloat::rem(left, right),
             BinaryOp::Power => {
                 #[cfg(feature = "libm")]
                 {
@@ -198,7 +386,7 @@ pub fn ast_to_stack_op(op: &str) -> Result<BinaryOp, ExprError> {
         ">=" => Ok(BinaryOp::GreaterEqual),
         "==" => Ok(BinaryOp::Equal),
         "!=" => Ok(BinaryOp::NotEqual),
-        _ => Err(ExprError::Syntax(format!("Unknown operator: {}", op))),
+        _ => Err(ExprError::syntax(format!("Unknown operator: {}", op))),
     }
 }
 
@@ -248,6 +436,7 @@ impl<'arena> core::fmt::Debug for EvalOp<'arena> {
                 name,
                 arg_count,
                 ctx_id,
+                ..
             } => {
                 write!(
                     f,
@@ -291,9 +480,36 @@ impl<'arena> core::fmt::Debug for EvalOp<'arena> {
                     object_name, attr_name, ctx_id
                 )
             }
-            EvalOp::RestoreFunctionParams { params } => {
+            EvalOp::RestoreFunctionParams { params, .. } => {
                 write!(f, "RestoreFunctionParams {{ params: {} }}", params.is_some())
             }
+            EvalOp::NodeEvaluated { .. } => write!(f, "NodeEvaluated {{ expr: <AstExpr> }}"),
+            EvalOp::ApplyTrigger { op, .. } => {
+                write!(f, "ApplyTrigger {{ op: {:?}, site: <AstExpr> }}", op)
+            }
+            EvalOp::ApplyLut { ctx_id, .. } => {
+                write!(f, "ApplyLut {{ args: <AstExpr>, ctx_id: {} }}", ctx_id)
+            }
+            EvalOp::ApplyHistory { op, name, ctx_id } => {
+                write!(
+                    f,
+                    "ApplyHistory {{ op: {:?}, name: {:?}, ctx_id: {} }}",
+                    op, name, ctx_id
+                )
+            }
+            EvalOp::ApplySliceFunction {
+                name,
+                array,
+                extra,
+                ctx_id,
+                ..
+            } => {
+                write!(
+                    f,
+                    "ApplySliceFunction {{ name: {:?}, array: {:?}, extra: {}, ctx_id: {} }}",
+                    name, array, extra, ctx_id
+                )
+            }
         }
     }
 }
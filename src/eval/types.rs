@@ -9,6 +9,7 @@ use std::rc::Rc;
 
 pub struct OwnedNativeFunction {
     pub arity: usize,
+    pub variadic: bool,
     pub implementation: Rc<dyn Fn(&[Real]) -> Real>,
     pub name: String, // Fully owned String instead of Cow
     pub description: Option<String>,
@@ -19,6 +20,7 @@ impl From<&crate::types::NativeFunction> for OwnedNativeFunction {
     fn from(nf: &crate::types::NativeFunction) -> Self {
         OwnedNativeFunction {
             arity: nf.arity,
+            variadic: nf.variadic,
             implementation: nf.implementation.clone(),
             name: nf.name.to_string(), // Convert Cow to String
             description: nf.description.clone(),
@@ -36,6 +38,7 @@ impl Clone for FunctionCacheEntry {
         match self {
             FunctionCacheEntry::Native(nf) => FunctionCacheEntry::Native(OwnedNativeFunction {
                 arity: nf.arity,
+                variadic: nf.variadic,
                 implementation: nf.implementation.clone(),
                 name: nf.name.clone(),
                 description: nf.description.clone(),
@@ -5,7 +5,11 @@
 
 use crate::error::ExprError;
 use crate::eval::iterative::{EvalEngine, eval_with_engine};
-use crate::types::{BatchParamMap, TryIntoHeaplessString};
+use crate::intern::{Symbol, SymbolTable};
+use crate::memo::{MemoCache, MemoStats};
+use crate::profile::{ExpressionStats, Profiler};
+use crate::types::TryIntoHeaplessString;
+use crate::visit::{AstVisitor, walk_ast};
 use crate::{AstExpr, EvalContext, Real};
 use alloc::rc::Rc;
 use alloc::string::{String, ToString};
@@ -20,6 +24,168 @@ pub struct Param {
     pub value: Real,
 }
 
+/// A call of a deprecated function found by [`Expression::deprecated_calls`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeprecatedCall {
+    /// Index of the expression containing the call
+    pub expression: usize,
+    /// The deprecated function
+    pub function: String,
+    /// The function to call instead, if there is one
+    pub replacement: Option<String>,
+}
+
+/// Splits parameter specs such as `["x", "gain=1.5"]` into names and the defaults of
+/// the trailing parameters.
+fn split_param_defaults(specs: &[&str]) -> Result<(Vec<String>, Vec<Real>), ExprError> {
+    let mut names = Vec::with_capacity(specs.len());
+    let mut defaults = Vec::new();
+    for spec in specs {
+        match spec.split_once('=') {
+            Some((name, value)) => {
+                names.push(name.trim().to_string());
+                defaults.push(value.trim().parse::<Real>()?);
+            }
+            None if !defaults.is_empty() => {
+                return Err(ExprError::syntax(alloc::format!(
+                    "Parameter '{}' without a default follows a parameter with a default",
+                    spec.trim()
+                )));
+            }
+            None => names.push(spec.trim().to_string()),
+        }
+    }
+    Ok((names, defaults))
+}
+
+/// Returns whether `name` is one of the batch's expression functions.
+fn is_local_function(
+    functions: Option<&RefCell<crate::types::ExpressionFunctionMap>>,
+    name: &str,
+) -> bool {
+    use crate::types::TryIntoFunctionName;
+    functions.is_some_and(|map| {
+        name.try_into_function_name()
+            .is_ok_and(|key| map.borrow().contains_key(&key))
+    })
+}
+
+/// Collects the parameters and named expressions referenced by an AST.
+struct InputRefs<'a, 'arena> {
+    names: &'a [Option<&'arena str>],
+    params: &'a [Param],
+    named: Vec<usize>,
+    param_indices: Vec<usize>,
+}
+
+impl<'arena> AstVisitor<'arena> for InputRefs<'_, 'arena> {
+    fn visit_variable(&mut self, name: &'arena str) {
+        if let Some(idx) = self.names.iter().position(|n| *n == Some(name)) {
+            if !self.named.contains(&idx) {
+                self.named.push(idx);
+            }
+        } else if let Some(idx) = self.params.iter().position(|p| p.name == name)
+            && !self.param_indices.contains(&idx)
+        {
+            self.param_indices.push(idx);
+        }
+    }
+}
+
+/// Collects the variables an AST reads from the evaluation context: variables that
+/// are neither parameters, named expressions nor builtin constants, arrays, and
+/// attribute paths.
+struct ContextRefs<'a, 'arena> {
+    names: &'a [Option<&'arena str>],
+    params: &'a [Param],
+    variables: Vec<String>,
+}
+
+impl ContextRefs<'_, '_> {
+    fn add(&mut self, name: String) {
+        if !self.variables.contains(&name) {
+            self.variables.push(name);
+        }
+    }
+}
+
+impl<'arena> AstVisitor<'arena> for ContextRefs<'_, 'arena> {
+    fn visit_variable(&mut self, name: &'arena str) {
+        let is_input =
+            self.names.contains(&Some(name)) || self.params.iter().any(|p| p.name == name);
+        let is_constant = matches!(name, "pi" | "PI" | "e" | "E" | "tau" | "TAU");
+        if !is_input && !is_constant {
+            self.add(name.to_string());
+        }
+    }
+
+    fn visit_array(&mut self, name: &'arena str, index: &'arena AstExpr<'arena>) {
+        self.add(name.to_string());
+        self.visit_expr(index);
+    }
+
+    fn visit_attribute(&mut self, base: &'arena str, attr: &'arena str) {
+        self.add(alloc::format!("{}.{}", base, attr));
+    }
+}
+
+/// Collects the context variables and functions used by an AST, and whether it reads
+/// no arrays or attributes.
+struct MemoRefs<'a, 'arena> {
+    names: &'a [Option<&'arena str>],
+    params: &'a [Param],
+    variables: Vec<&'arena str>,
+    functions: Vec<&'arena str>,
+    cacheable: bool,
+}
+
+impl<'arena> AstVisitor<'arena> for MemoRefs<'_, 'arena> {
+    fn visit_variable(&mut self, name: &'arena str) {
+        let is_input =
+            self.names.contains(&Some(name)) || self.params.iter().any(|p| p.name == name);
+        if !is_input && !self.variables.contains(&name) {
+            self.variables.push(name);
+        }
+    }
+
+    fn visit_function(&mut self, name: &'arena str, args: &'arena [AstExpr<'arena>]) {
+        if !self.functions.contains(&name) {
+            self.functions.push(name);
+        }
+        for arg in args {
+            self.visit_expr(arg);
+        }
+    }
+
+    fn visit_array(&mut self, _name: &'arena str, _index: &'arena AstExpr<'arena>) {
+        self.cacheable = false;
+    }
+
+    fn visit_attribute(&mut self, _base: &'arena str, _attr: &'arena str) {
+        self.cacheable = false;
+    }
+}
+
+/// Context inputs of an expression whose result may be memoized.
+struct MemoPlan<'arena> {
+    /// Context variables read by the expression
+    variables: Vec<&'arena str>,
+    /// Functions the expression calls, which must be pure when it is evaluated
+    functions: Vec<&'arena str>,
+}
+
+/// Evaluation order and inputs of each expression, derived from the parsed ASTs.
+struct EvalPlan<'arena> {
+    /// Expression indices, each after the named expressions it references
+    order: Vec<usize>,
+    /// Named expressions referenced by each expression
+    named_deps: Vec<Vec<usize>>,
+    /// Parameters referenced by each expression
+    param_deps: Vec<Vec<usize>>,
+    /// Context inputs of each expression that reads no arrays or attributes
+    memo: Vec<Option<MemoPlan<'arena>>>,
+}
+
 /// Arena-aware batch builder for zero-allocation expression evaluation
 ///
 /// This structure is similar to BatchBuilder but uses an arena for all
@@ -31,9 +197,33 @@ pub struct Expression<'arena> {
     /// Pre-parsed expressions with their original strings
     expressions: Vec<(&'arena str, &'arena AstExpr<'arena>)>,
 
+    /// Output name of each expression, for expressions added with `add_named_expression`
+    names: Vec<Option<&'arena str>>,
+
+    /// Symbol of each output name
+    name_symbols: Vec<Option<Symbol>>,
+
+    /// Cached evaluation plan; `None` when it must be rebuilt
+    plan: Option<EvalPlan<'arena>>,
+
+    /// Parameters whose value changed since the last successful evaluation
+    param_changed: Vec<bool>,
+
+    /// Whether `results` reflect a complete successful evaluation
+    results_valid: bool,
+
+    /// Scratch flags marking the expressions whose value changed during an evaluation
+    recomputed: Vec<bool>,
+
     /// Parameters with names and values together
     params: Vec<Param>,
 
+    /// Symbol and interned name of each parameter
+    param_names: Vec<(Symbol, &'arena str)>,
+
+    /// Names of the expressions, parameters and named results, interned in the arena
+    symbols: SymbolTable<'arena>,
+
     /// Results for each expression
     results: Vec<Real>,
 
@@ -42,6 +232,21 @@ pub struct Expression<'arena> {
 
     /// Optional arena-allocated expression functions (lazy-initialized)
     local_functions: Option<&'arena RefCell<crate::types::ExpressionFunctionMap>>,
+
+    /// Parser options used by `add_expression`
+    parse_options: crate::engine::ParseOptions,
+
+    /// Cache of results of pure expressions, when memoization is enabled
+    memo: Option<MemoCache>,
+
+    /// Scratch buffer for the input values of a memoized expression
+    memo_inputs: Vec<Real>,
+
+    /// Inputs read by each expression's last evaluation, when recording them
+    inputs_read: Vec<Vec<String>>,
+
+    /// Evaluation statistics of each expression, when enabled
+    profiler: Option<Profiler<'arena>>,
 }
 
 /// Deprecated: Use `Expression` instead
@@ -54,20 +259,49 @@ impl<'arena> Expression<'arena> {
         Expression {
             arena,
             expressions: Vec::new(),
+            names: Vec::new(),
+            name_symbols: Vec::new(),
+            plan: None,
+            param_changed: Vec::new(),
+            results_valid: false,
+            recomputed: Vec::new(),
             params: Vec::new(),
+            param_names: Vec::new(),
+            symbols: SymbolTable::new(arena),
             results: Vec::new(),
             engine: EvalEngine::new(arena),
             local_functions: None,
+            parse_options: crate::engine::ParseOptions::default(),
+            memo: None,
+            memo_inputs: Vec::new(),
+            inputs_read: Vec::new(),
+            profiler: None,
         }
     }
 
+    /// Set the parser options used for expressions added after this call
+    pub fn set_parse_options(&mut self, options: crate::engine::ParseOptions) {
+        self.parse_options = options;
+    }
+
+    /// Get the parser options used for expressions added from now on
+    pub fn parse_options(&self) -> crate::engine::ParseOptions {
+        self.parse_options
+    }
+
     /// Add an expression to be evaluated
     ///
-    /// The expression is parsed immediately into the arena.
+    /// The expression is parsed immediately into the arena, with its names interned
+    /// in the batch's [`symbols`](Self::symbols).
     /// Returns the index of the added expression.
     pub fn add_expression(&mut self, expr: &str) -> Result<usize, ExprError> {
         // Parse the expression into the arena
-        let ast = crate::engine::parse_expression(expr, self.arena)?;
+        let ast = crate::engine::parse_expression_interned(
+            expr,
+            self.arena,
+            &self.parse_options,
+            &mut self.symbols,
+        )?;
 
         // Allocate expression string in arena
         let expr_str = self.arena.alloc_str(expr);
@@ -77,81 +311,300 @@ impl<'arena> Expression<'arena> {
 
         let idx = self.expressions.len();
         self.expressions.push((expr_str, arena_ast));
+        self.names.push(None);
+        self.name_symbols.push(None);
         self.results.push(0.0); // Pre-allocate result slot
+        self.plan = None;
+        self.results_valid = false;
+        Ok(idx)
+    }
+
+    /// Add an expression whose result other expressions can reference by `name`
+    ///
+    /// Named expressions may refer to each other in any order; `eval` evaluates
+    /// them so that every expression runs after the ones it references. A name
+    /// shares the namespace of parameters, so it must not match an existing
+    /// parameter or named expression. Returns the index of the added expression.
+    ///
+    /// # Example
+    /// ```
+    /// use bumpalo::Bump;
+    /// use exp_rs::{expression::Expression, EvalContext};
+    /// use std::rc::Rc;
+    ///
+    /// let arena = Bump::new();
+    /// let mut batch = Expression::new(&arena);
+    /// batch.add_named_expression("z", "y * 2").unwrap();
+    /// batch.add_named_expression("y", "x + 1").unwrap();
+    /// batch.add_parameter("x", 4.0).unwrap();
+    ///
+    /// batch.eval(&Rc::new(EvalContext::new())).unwrap();
+    /// assert_eq!(batch.get_result_by_name("z"), Some(10.0));
+    /// ```
+    pub fn add_named_expression(&mut self, name: &str, expr: &str) -> Result<usize, ExprError> {
+        if self.params.iter().any(|p| p.name == name) || self.expression_index(name).is_some() {
+            return Err(ExprError::DuplicateParameter {
+                name: name.to_string(),
+            });
+        }
+        let idx = self.add_expression(expr)?;
+        let symbol = self.symbols.intern(name)?;
+        self.names[idx] = self.symbols.name(symbol);
+        self.name_symbols[idx] = Some(symbol);
         Ok(idx)
     }
 
+    /// Get the index of a named expression
+    pub fn expression_index(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| *n == Some(name))
+    }
+
+    /// Get the parsed AST of an expression
+    pub fn expression_ast(&self, idx: usize) -> Option<&'arena AstExpr<'arena>> {
+        self.expressions.get(idx).map(|(_, ast)| *ast)
+    }
+
     /// Add a parameter with an initial value
     ///
     /// Returns an error if a parameter with the same name already exists.
     /// Returns the index of the added parameter.
     pub fn add_parameter(&mut self, name: &str, initial_value: Real) -> Result<usize, ExprError> {
         // Check for duplicates
-        if self.params.iter().any(|p| p.name == name) {
-            return Err(ExprError::DuplicateParameter(name.to_string()));
+        if self.params.iter().any(|p| p.name == name) || self.expression_index(name).is_some() {
+            return Err(ExprError::DuplicateParameter {
+                name: name.to_string(),
+            });
         }
         let idx = self.params.len();
+        let symbol = self.symbols.intern(name)?;
+        let interned = self.symbols.canonical(name)?;
+        self.param_names.push((symbol, interned));
         self.params.push(Param {
             name: name.to_string(),
             value: initial_value,
         });
+        self.param_changed.push(false);
+        self.plan = None;
+        self.results_valid = false;
         Ok(idx)
     }
 
     /// Update a parameter value by index (fastest method)
     pub fn set_param(&mut self, idx: usize, value: Real) -> Result<(), ExprError> {
-        self.params
+        let len = self.params.len();
+        let param = self
+            .params
             .get_mut(idx)
-            .ok_or(ExprError::InvalidParameterIndex(idx))?
-            .value = value;
+            .ok_or(ExprError::InvalidParameterIndex { index: idx, len })?;
+        if param.value != value {
+            param.value = value;
+            self.param_changed[idx] = true;
+        }
         Ok(())
     }
 
     /// Update a parameter value by name (convenient but slower)
     pub fn set_param_by_name(&mut self, name: &str, value: Real) -> Result<(), ExprError> {
-        self.params
-            .iter_mut()
-            .find(|p| p.name == name)
+        let idx = self
+            .params
+            .iter()
+            .position(|p| p.name == name)
             .ok_or_else(|| ExprError::UnknownVariable {
                 name: name.to_string(),
-            })?
-            .value = value;
-        Ok(())
+            })?;
+        self.set_param(idx, value)
     }
 
     /// Evaluate all expressions with current parameter values
+    ///
+    /// Expressions are evaluated in the order they were added, except that a named
+    /// expression runs before the first expression referencing it.
     pub fn eval(&mut self, base_ctx: &Rc<EvalContext>) -> Result<(), ExprError> {
-        // Build parameter override map
-        let mut param_map = BatchParamMap::new();
-        for param in &self.params {
-            let hname = param.name.as_str().try_into_heapless()?;
-            param_map
-                .insert(hname, param.value)
-                .map_err(|_| ExprError::CapacityExceeded("parameter overrides"))?;
+        self.evaluate(base_ctx, false).map(|_| ())
+    }
+
+    /// Re-evaluate only the expressions affected by parameter changes
+    ///
+    /// An expression is re-evaluated when a parameter it references was changed
+    /// through `set_param`/`set` since the last successful evaluation, or when a
+    /// named expression it references produced a new value. The first call, and
+    /// any call after expressions, parameters or local functions were added or
+    /// removed, evaluates everything.
+    ///
+    /// Variables and functions of `base_ctx` are assumed to be unchanged between
+    /// calls, and functions are assumed to be pure; call `eval` after changing
+    /// the context or when using functions such as `random` or triggers such as
+    /// `rising`.
+    ///
+    /// Returns the number of expressions that were evaluated.
+    ///
+    /// # Example
+    /// ```
+    /// use bumpalo::Bump;
+    /// use exp_rs::{expression::Expression, EvalContext};
+    /// use std::rc::Rc;
+    ///
+    /// let arena = Bump::new();
+    /// let ctx = Rc::new(EvalContext::new());
+    /// let mut batch = Expression::new(&arena);
+    /// batch.add_parameter("a", 1.0).unwrap();
+    /// batch.add_parameter("b", 2.0).unwrap();
+    /// batch.add_expression("a * 10").unwrap();
+    /// batch.add_expression("b * 10").unwrap();
+    ///
+    /// assert_eq!(batch.eval_incremental(&ctx).unwrap(), 2);
+    /// batch.set("b", 3.0).unwrap();
+    /// assert_eq!(batch.eval_incremental(&ctx).unwrap(), 1);
+    /// assert_eq!(batch.get_all_results(), &[10.0, 30.0]);
+    /// ```
+    pub fn eval_incremental(&mut self, base_ctx: &Rc<EvalContext>) -> Result<usize, ExprError> {
+        self.evaluate(base_ctx, true)
+    }
+
+    fn evaluate(
+        &mut self,
+        base_ctx: &Rc<EvalContext>,
+        incremental: bool,
+    ) -> Result<usize, ExprError> {
+        // Parameters are looked up by the address of their interned names, which the
+        // parsed expressions share, and the engine interns function bodies likewise
+        self.engine.clear_param_slots();
+        for (&(symbol, name), param) in self.param_names.iter().zip(&self.params) {
+            self.engine.set_param_slot(symbol, name, param.value);
+        }
+        let symbols = core::mem::replace(&mut self.symbols, SymbolTable::new(self.arena));
+        self.engine.set_symbols(Some(symbols));
+
+        let result = self.evaluate_plan(base_ctx, incremental);
+
+        if let Some(symbols) = self.engine.set_symbols(None) {
+            self.symbols = symbols;
         }
+        self.engine.clear_param_slots();
+        result
+    }
 
-        // Set parameter overrides in engine
-        self.engine.set_param_overrides(param_map);
+    fn evaluate_plan(
+        &mut self,
+        base_ctx: &Rc<EvalContext>,
+        incremental: bool,
+    ) -> Result<usize, ExprError> {
+        let incremental = incremental && self.results_valid;
 
         // Set local functions in engine
         self.engine.set_local_functions(self.local_functions);
 
-        // Evaluate each expression with the original context
-        for (i, (_, ast)) in self.expressions.iter().enumerate() {
-            match eval_with_engine(ast, Some(base_ctx.clone()), &mut self.engine) {
-                Ok(value) => self.results[i] = value,
+        // Memoized results are only reused while expressions and functions stay the same
+        if (!self.results_valid || self.plan.is_none())
+            && let Some(memo) = &mut self.memo
+        {
+            memo.clear();
+        }
+
+        if self.plan.is_none() {
+            self.plan = Some(self.build_plan()?);
+        }
+        let plan = self.plan.as_ref().unwrap();
+
+        // Expressions that are skipped still publish their previous result
+        if incremental {
+            for (i, (name, symbol)) in self.names.iter().zip(&self.name_symbols).enumerate() {
+                if let (Some(name), Some(symbol)) = (name, symbol) {
+                    self.engine.set_param_slot(*symbol, name, self.results[i]);
+                }
+            }
+        }
+
+        self.recomputed.clear();
+        self.recomputed.resize(self.expressions.len(), false);
+        let mut evaluated = 0;
+
+        // Evaluate each expression with the original context, publishing named
+        // results as parameters for the expressions that follow
+        for &i in &plan.order {
+            if incremental
+                && !plan.param_deps[i].iter().any(|&p| self.param_changed[p])
+                && !plan.named_deps[i].iter().any(|&d| self.recomputed[d])
+            {
+                continue;
+            }
+
+            // A memoized expression is keyed by the values of its parameters, named
+            // inputs and context variables, in that order
+            let mut memoize = false;
+            let mut cached = None;
+            // Results served from the cache would have no recorded inputs
+            if let (Some(memo), Some(memo_plan)) = (&mut self.memo, &plan.memo[i])
+                && self.engine.inputs_read().is_none()
+                && memo_plan.functions.iter().all(|&name| {
+                    base_ctx.is_function_pure(name)
+                        && !is_local_function(self.local_functions, name)
+                })
+            {
+                let inputs = &mut self.memo_inputs;
+                inputs.clear();
+                inputs.extend(plan.param_deps[i].iter().map(|&p| self.params[p].value));
+                inputs.extend(plan.named_deps[i].iter().map(|&d| self.results[d]));
+                memoize = memo_plan.variables.iter().all(|name| {
+                    let value = base_ctx
+                        .get_variable(name)
+                        .or_else(|| base_ctx.get_constant(name));
+                    inputs.extend(value);
+                    value.is_some()
+                });
+                if memoize {
+                    cached = memo.get(i, inputs);
+                }
+            }
+
+            let result = match cached {
+                Some(value) => Ok(value),
+                None => {
+                    let start = self.profiler.as_mut().map(Profiler::start);
+                    let result = eval_with_engine(
+                        self.expressions[i].1,
+                        Some(base_ctx.clone()),
+                        &mut self.engine,
+                    );
+                    if let (Some(profiler), Some(start)) = (&mut self.profiler, start) {
+                        profiler.finish(i, start, result.as_ref().err());
+                    }
+                    result
+                }
+            };
+            match result {
+                Ok(value) => {
+                    if let (Some(name), Some(symbol)) = (self.names[i], self.name_symbols[i]) {
+                        self.engine.set_param_slot(symbol, name, value);
+                    }
+                    self.recomputed[i] = value != self.results[i];
+                    self.results[i] = value;
+                    if let Some(read) = self.engine.inputs_read()
+                        && cached.is_none()
+                    {
+                        self.inputs_read
+                            .resize_with(self.expressions.len(), Vec::new);
+                        self.inputs_read[i].clear();
+                        self.inputs_read[i].extend_from_slice(read);
+                    }
+                    if cached.is_none() {
+                        if memoize && let Some(memo) = &mut self.memo {
+                            memo.insert(i, &self.memo_inputs, value);
+                        }
+                        evaluated += 1;
+                    }
+                }
                 Err(e) => {
-                    // Clear overrides on error
-                    self.engine.clear_param_overrides();
+                    self.results_valid = false;
                     return Err(e);
                 }
             }
         }
 
-        // Clear parameter overrides when done
-        self.engine.clear_param_overrides();
+        self.param_changed.fill(false);
+        self.results_valid = true;
 
-        Ok(())
+        Ok(evaluated)
     }
 
     /// Get the result of a specific expression by index
@@ -159,6 +612,259 @@ impl<'arena> Expression<'arena> {
         self.results.get(expr_idx).copied()
     }
 
+    /// Get the result of a named expression
+    pub fn get_result_by_name(&self, name: &str) -> Option<Real> {
+        self.get_result(self.expression_index(name)?)
+    }
+
+    /// Record which inputs each expression reads when evaluated, for
+    /// [`inputs_read`](Self::inputs_read)
+    ///
+    /// Recording allocates the names and bypasses the memoization cache, so it is off
+    /// by default.
+    pub fn set_record_inputs(&mut self, record: bool) {
+        self.engine.set_record_inputs(record);
+        self.inputs_read.clear();
+    }
+
+    /// Get the inputs the last evaluation of an expression read, if recording is enabled
+    ///
+    /// These are the parameters, named results and context variables, arrays,
+    /// attributes and histories actually read, in the order they were first read;
+    /// operands skipped by `?:`, `&&` and `||` are not listed. An audit log can record
+    /// them as the inputs that contributed to a result.
+    ///
+    /// # Example
+    /// ```
+    /// use bumpalo::Bump;
+    /// use exp_rs::{expression::Expression, EvalContext};
+    /// use std::rc::Rc;
+    ///
+    /// let arena = Bump::new();
+    /// let mut ctx = EvalContext::new();
+    /// ctx.set_parameter("limit", 80.0).unwrap();
+    /// let ctx = Rc::new(ctx);
+    ///
+    /// let mut batch = Expression::new(&arena);
+    /// batch.add_parameter("temp", 85.0).unwrap();
+    /// batch.add_parameter("pressure", 1.0).unwrap();
+    /// batch.add_expression("temp > limit || pressure > 2").unwrap();
+    /// batch.set_record_inputs(true);
+    ///
+    /// batch.eval(&ctx).unwrap();
+    /// assert_eq!(batch.inputs_read(0).unwrap(), ["temp", "limit"]);
+    /// batch.set("temp", 20.0).unwrap();
+    /// batch.eval(&ctx).unwrap();
+    /// assert_eq!(batch.inputs_read(0).unwrap(), ["temp", "limit", "pressure"]);
+    /// ```
+    pub fn inputs_read(&self, expr_idx: usize) -> Option<&[String]> {
+        self.engine.inputs_read()?;
+        self.inputs_read.get(expr_idx).map(Vec::as_slice)
+    }
+
+    /// Collect the inputs of every expression and order the expressions so each
+    /// runs after the named expressions it references.
+    ///
+    /// Expressions without dependencies between them keep their insertion order.
+    fn build_plan(&self) -> Result<EvalPlan<'arena>, ExprError> {
+        let mut named_deps = Vec::with_capacity(self.expressions.len());
+        let mut param_deps = Vec::with_capacity(self.expressions.len());
+        let mut memo = Vec::with_capacity(self.expressions.len());
+        for (_, ast) in &self.expressions {
+            let mut refs = InputRefs {
+                names: &self.names,
+                params: &self.params,
+                named: Vec::new(),
+                param_indices: Vec::new(),
+            };
+            walk_ast(&mut refs, ast);
+            named_deps.push(refs.named);
+            param_deps.push(refs.param_indices);
+
+            let mut memo_refs = MemoRefs {
+                names: &self.names,
+                params: &self.params,
+                variables: Vec::new(),
+                functions: Vec::new(),
+                cacheable: true,
+            };
+            walk_ast(&mut memo_refs, ast);
+            memo.push(memo_refs.cacheable.then_some(MemoPlan {
+                variables: memo_refs.variables,
+                functions: memo_refs.functions,
+            }));
+        }
+
+        // 0 = unvisited, 1 = on the current path, 2 = done
+        let mut state = alloc::vec![0u8; self.expressions.len()];
+        let mut path = Vec::new();
+        let mut order = Vec::with_capacity(self.expressions.len());
+        for idx in 0..self.expressions.len() {
+            self.visit_dependencies(idx, &named_deps, &mut state, &mut path, &mut order)?;
+        }
+
+        Ok(EvalPlan {
+            order,
+            named_deps,
+            param_deps,
+            memo,
+        })
+    }
+
+    fn visit_dependencies(
+        &self,
+        idx: usize,
+        deps: &[Vec<usize>],
+        state: &mut [u8],
+        path: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+    ) -> Result<(), ExprError> {
+        match state[idx] {
+            2 => return Ok(()),
+            1 => {
+                let start = path.iter().position(|&i| i == idx).unwrap_or(0);
+                let cycle = path[start..]
+                    .iter()
+                    .chain(core::iter::once(&idx))
+                    .map(|&i| self.names[i].unwrap_or(self.expressions[i].0).to_string())
+                    .collect();
+                return Err(ExprError::CyclicDependency { cycle });
+            }
+            _ => {}
+        }
+
+        state[idx] = 1;
+        path.push(idx);
+        for &dep in &deps[idx] {
+            self.visit_dependencies(dep, deps, state, path, order)?;
+        }
+        path.pop();
+        state[idx] = 2;
+        order.push(idx);
+        Ok(())
+    }
+
+    /// Substitute parameters that no longer change and fold the constant subtrees
+    ///
+    /// Each name in `frozen_params` is replaced in every expression by the current
+    /// value of the batch parameter of that name, or else by the value of the context
+    /// variable or constant, and the expressions are folded with
+    /// [`simplify::specialize`](crate::simplify::specialize) using `ctx`. Frozen
+    /// parameters stay in the batch, so indices are unchanged, but setting them has no
+    /// effect on the specialized expressions.
+    ///
+    /// Returns `ExprError::UnknownVariable` if a name is neither a parameter nor
+    /// defined in `ctx`; the expressions are then left unchanged.
+    ///
+    /// # Example
+    /// ```
+    /// use bumpalo::Bump;
+    /// use exp_rs::{expression::Expression, EvalContext};
+    /// use std::rc::Rc;
+    ///
+    /// let arena = Bump::new();
+    /// let mut batch = Expression::new(&arena);
+    /// batch.add_parameter("raw", 0.0).unwrap();
+    /// batch.add_parameter("cal_gain", 1.25).unwrap();
+    /// batch.add_parameter("cal_offset", 0.0).unwrap();
+    /// batch.add_expression("raw * cal_gain * 2 + cal_offset").unwrap();
+    ///
+    /// let ctx = Rc::new(EvalContext::new());
+    /// batch.specialize(&ctx, &["cal_gain", "cal_offset"]).unwrap();
+    /// assert_eq!(batch.expression_ast(0).unwrap().to_expression_string(), "raw * 1.25 * 2");
+    ///
+    /// batch.set_param(0, 4.0).unwrap();
+    /// batch.eval(&ctx).unwrap();
+    /// assert_eq!(batch.get_result(0), Some(10.0));
+    /// ```
+    pub fn specialize(
+        &mut self,
+        ctx: &Rc<EvalContext>,
+        frozen_params: &[&str],
+    ) -> Result<(), ExprError> {
+        let mut values = Vec::with_capacity(frozen_params.len());
+        for &name in frozen_params {
+            let value = match self.params.iter().find(|p| p.name == name) {
+                Some(param) => param.value,
+                None => ctx
+                    .get_variable(name)
+                    .or_else(|| ctx.get_constant(name))
+                    .ok_or_else(|| ExprError::UnknownVariable {
+                        name: name.to_string(),
+                    })?,
+            };
+            values.push((name, value));
+        }
+
+        let arena = self.arena;
+        for (_, ast) in &mut self.expressions {
+            *ast = crate::simplify::specialize(ast, ctx, &values, arena);
+        }
+        self.plan = None;
+        self.results_valid = false;
+        Ok(())
+    }
+
+    /// Get the dependency graph of the batch
+    ///
+    /// The graph links every expression to the parameters, named expressions and
+    /// context variables it references; see [`crate::graph`].
+    pub fn dependency_graph(&self) -> crate::graph::DependencyGraph {
+        use crate::graph::Node;
+
+        let mut graph = crate::graph::DependencyGraph::default();
+        for (index, (expression, _)) in self.expressions.iter().enumerate() {
+            graph.nodes.push(Node::Expression {
+                index,
+                name: self.names[index].map(|n| n.to_string()),
+                expression: expression.to_string(),
+            });
+        }
+        for (index, param) in self.params.iter().enumerate() {
+            graph.nodes.push(Node::Parameter {
+                index,
+                name: param.name.clone(),
+            });
+        }
+        let params_end = graph.nodes.len();
+
+        for (i, (_, ast)) in self.expressions.iter().enumerate() {
+            let mut refs = InputRefs {
+                names: &self.names,
+                params: &self.params,
+                named: Vec::new(),
+                param_indices: Vec::new(),
+            };
+            walk_ast(&mut refs, ast);
+            let mut context = ContextRefs {
+                names: &self.names,
+                params: &self.params,
+                variables: Vec::new(),
+            };
+            walk_ast(&mut context, ast);
+
+            graph.edges.extend(refs.named.iter().map(|&d| (d, i)));
+            let first_param = self.expressions.len();
+            graph
+                .edges
+                .extend(refs.param_indices.iter().map(|&p| (first_param + p, i)));
+            for name in context.variables {
+                let existing = graph.nodes[params_end..]
+                    .iter()
+                    .position(|n| n.label() == name);
+                let node = match existing {
+                    Some(offset) => params_end + offset,
+                    None => {
+                        graph.nodes.push(Node::Variable { name });
+                        graph.nodes.len() - 1
+                    }
+                };
+                graph.edges.push((node, i));
+            }
+        }
+        graph
+    }
+
     /// Get all results as a slice
     pub fn get_all_results(&self) -> &[Real] {
         &self.results
@@ -179,10 +885,38 @@ impl<'arena> Expression<'arena> {
     /// Expression functions are mathematical expressions that can call other functions.
     /// They are specific to this batch and take precedence over context functions.
     ///
+    /// A parameter written as `name=value` has a default, and callers may omit it.
+    /// Parameters with defaults must come after all parameters without one.
+    ///
+    /// Parsed bodies are cached by a hash of the name, parameters and body. Registering
+    /// a function again with an unchanged definition is therefore cheap and keeps the
+    /// parsed body, while a changed body is parsed on its next call. Use
+    /// [`clear_function_cache`](Self::clear_function_cache) to drop the cached bodies of
+    /// replaced functions.
+    ///
     /// # Arguments
     /// * `name` - Function name
-    /// * `params` - Parameter names
+    /// * `params` - Parameter names, optionally with a numeric default (`"gain=1.5"`)
     /// * `body` - Expression string defining the function
+    ///
+    /// # Example
+    /// ```
+    /// use bumpalo::Bump;
+    /// use exp_rs::{expression::Expression, EvalContext};
+    /// use std::rc::Rc;
+    ///
+    /// let arena = Bump::new();
+    /// let mut batch = Expression::new(&arena);
+    /// batch
+    ///     .register_expression_function("scale", &["x", "gain=2", "offset=0"], "x * gain + offset")
+    ///     .unwrap();
+    /// batch.add_expression("scale(5)").unwrap();
+    /// batch.add_expression("scale(5, 3)").unwrap();
+    /// batch.add_expression("scale(5, 3, 1)").unwrap();
+    ///
+    /// batch.eval(&Rc::new(EvalContext::new())).unwrap();
+    /// assert_eq!(batch.get_all_results(), &[10.0, 15.0, 16.0]);
+    /// ```
     pub fn register_expression_function(
         &mut self,
         name: &str,
@@ -191,10 +925,35 @@ impl<'arena> Expression<'arena> {
     ) -> Result<(), ExprError> {
         use crate::types::{ExpressionFunction, ExpressionFunctionMap, TryIntoFunctionName};
 
+        let (params, defaults) = split_param_defaults(params)?;
+        let func_name = name.try_into_function_name()?;
+        let mut definition_hash = ExpressionFunction::definition_hash(name, &params, body);
+        // A body parsed with other options is a different definition
+        if self.parse_options != crate::engine::ParseOptions::default() {
+            use core::hash::{Hash, Hasher};
+            let mut hasher = crate::compare::Fnv::default();
+            hasher.write_u64(definition_hash);
+            self.parse_options.hash(&mut hasher);
+            definition_hash = hasher.finish();
+        }
+
         // Lazy initialization - only allocate map when first function is added
-        if self.local_functions.is_none() {
-            let map = self.arena.alloc(RefCell::new(ExpressionFunctionMap::new()));
-            self.local_functions = Some(map);
+        let map = *self
+            .local_functions
+            .get_or_insert_with(|| self.arena.alloc(RefCell::new(ExpressionFunctionMap::new())));
+
+        // An unchanged definition keeps its parameter buffer and parsed body
+        if let Some(existing) = map.borrow_mut().get_mut(&func_name)
+            && existing.definition_hash == definition_hash
+            && existing.expression == body
+            && existing.params == params
+            && existing.parse_options == self.parse_options
+        {
+            if existing.defaults != defaults {
+                existing.defaults = defaults;
+                self.results_valid = false;
+            }
+            return Ok(());
         }
 
         // Pre-allocate parameter buffer in arena for zero-allocation evaluation
@@ -205,52 +964,498 @@ impl<'arena> Expression<'arena> {
             let slice: &mut [(crate::types::HString, crate::Real)] =
                 self.arena.alloc_slice_fill_default(params.len());
 
-            // Pre-fill parameter names (they never change)
-            for (i, param_name) in params.iter().enumerate() {
-                slice[i].0 = param_name.try_into_heapless()?;
-                slice[i].1 = 0.0; // Default value
-            }
+            // Pre-fill parameter names (they never change)
+            for (i, param_name) in params.iter().enumerate() {
+                slice[i].0 = param_name.as_str().try_into_heapless()?;
+                slice[i].1 = 0.0; // Default value
+            }
+
+            Some(slice as *mut _)
+        };
+
+        // Create the function
+        let expr_func = ExpressionFunction {
+            name: func_name.clone(),
+            params,
+            expression: body.to_string(),
+            description: None,
+            defaults,
+            param_buffer,
+            definition_hash,
+            parse_options: self.parse_options,
+            validator: None,
+        };
+
+        // Add to map through RefCell
+        map.borrow_mut()
+            .insert(func_name, expr_func)
+            .map_err(|_| ExprError::Other {
+                message: "Too many expression functions".to_string(),
+            })?;
+        self.results_valid = false;
+        Ok(())
+    }
+
+    /// Remove a local expression function from this batch
+    ///
+    /// # Arguments
+    /// * `name` - Function name to remove
+    ///
+    /// # Returns
+    /// * `Ok(true)` if the function was removed
+    /// * `Ok(false)` if the function didn't exist
+    /// * `Err` if the name is invalid
+    pub fn unregister_expression_function(&mut self, name: &str) -> Result<bool, ExprError> {
+        use crate::types::TryIntoFunctionName;
+
+        if let Some(map) = self.local_functions {
+            let func_name = name.try_into_function_name()?;
+            self.results_valid = false;
+            Ok(map.borrow_mut().remove(&func_name).is_some())
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Set a check of the arguments of calls to the local expression function `name`
+    ///
+    /// The validator receives the argument expressions and returns a message if the
+    /// call is invalid; [`validate`](Self::validate) runs it for every call. It is
+    /// kept while the function is re-registered unchanged.
+    pub fn set_function_validator<F>(&mut self, name: &str, validator: F) -> Result<(), ExprError>
+    where
+        F: Fn(&[AstExpr<'_>]) -> Result<(), String> + 'static,
+    {
+        use crate::types::TryIntoFunctionName;
+
+        let func_name = name.try_into_function_name()?;
+        let mut map = self.local_functions.map(|map| map.borrow_mut());
+        match map.as_mut().and_then(|map| map.get_mut(&func_name)) {
+            Some(function) => {
+                function.validator = Some(Rc::new(validator));
+                Ok(())
+            }
+            None => Err(ExprError::UnknownFunction {
+                name: name.to_string(),
+            }),
+        }
+    }
+
+    /// Check every call in the batch without evaluating it
+    ///
+    /// Calls of local expression functions, including those in function bodies, are
+    /// checked against their parameter counts and validators, and calls of native
+    /// functions of `ctx` as by [`EvalContext::validate_calls`]. If `ctx` has
+    /// [strict declarations](EvalContext::set_strict_declarations), every referenced name
+    /// must also be a parameter or named expression of the batch, a parameter of the
+    /// enclosing function, or declared in `ctx`. Running this when a configuration is
+    /// loaded reports bad calls then rather than on first evaluation.
+    ///
+    /// # Example
+    /// ```
+    /// use bumpalo::Bump;
+    /// use exp_rs::{expression::Expression, EvalContext};
+    /// use exp_rs::error::ExprError;
+    /// use exp_rs::types::AstExpr;
+    ///
+    /// let arena = Bump::new();
+    /// let mut batch = Expression::new(&arena);
+    /// batch.register_expression_function("lowpass", &["x", "n"], "x / n").unwrap();
+    /// batch
+    ///     .set_function_validator("lowpass", |args: &[AstExpr<'_>]| match args[1] {
+    ///         AstExpr::Constant(n) if n >= 1.0 => Ok(()),
+    ///         _ => Err("n must be a constant of at least 1".to_string()),
+    ///     })
+    ///     .unwrap();
+    /// batch.add_expression("lowpass(x, 4)").unwrap();
+    /// assert!(batch.validate(&EvalContext::new()).is_ok());
+    ///
+    /// batch.add_expression("lowpass(x, 0)").unwrap();
+    /// assert!(matches!(
+    ///     batch.validate(&EvalContext::new()),
+    ///     Err(ExprError::InvalidArguments { .. })
+    /// ));
+    /// ```
+    pub fn validate(&self, ctx: &EvalContext) -> Result<(), ExprError> {
+        let functions = self.local_functions.map(|map| map.borrow());
+        let check = &mut |name: &str, args: &[AstExpr<'_>]| {
+            let local = functions.as_ref().and_then(|map| {
+                crate::types::TryIntoFunctionName::try_into_function_name(name)
+                    .ok()
+                    .and_then(|key| map.get(&key))
+            });
+            let Some(function) = local else {
+                return ctx.validate_native_call(name, args);
+            };
+            let required = function.params.len() - function.defaults.len();
+            if !(required..=function.params.len()).contains(&args.len()) {
+                return Err(ExprError::InvalidFunctionCall {
+                    name: name.to_string(),
+                    expected: function.params.len(),
+                    found: args.len(),
+                });
+            }
+            match &function.validator {
+                Some(validator) => validator(args).map_err(|message| ExprError::InvalidArguments {
+                    name: name.to_string(),
+                    message,
+                }),
+                None => Ok(()),
+            }
+        };
+
+        let strict = ctx.strict_declarations();
+        let is_input = |name: &str| {
+            self.param_names.iter().any(|&(_, param)| param == name)
+                || self.names.contains(&Some(name))
+        };
+        for (_, ast) in &self.expressions {
+            crate::visit::try_for_each_call(ast, check)?;
+            if strict {
+                ctx.check_declarations_with(ast, &is_input)?;
+            }
+        }
+        // Function bodies are parsed into a scratch arena, as by `resource_estimate`
+        let scratch = Bump::new();
+        for function in functions.iter().flat_map(|map| map.values()) {
+            let body = crate::engine::parse_expression_with_parameters_and_options(
+                &function.expression,
+                &scratch,
+                &function.params,
+                &function.parse_options,
+            )?;
+            crate::visit::try_for_each_call(&body, check)?;
+            if strict {
+                let is_input =
+                    |name: &str| function.params.iter().any(|p| p == name) || is_input(name);
+                ctx.check_declarations_with(&body, &is_input)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// List the calls of functions deprecated in `ctx` by the stored expressions
+    ///
+    /// Each deprecated function is listed once per expression calling it, in order of
+    /// the expressions, and logged as a warning through the context's logger.
+    ///
+    /// # Example
+    /// ```
+    /// use bumpalo::Bump;
+    /// use exp_rs::{expression::Expression, EvalContext};
+    ///
+    /// let arena = Bump::new();
+    /// let mut ctx = EvalContext::new();
+    /// ctx.deprecate_function("ln", Some("log")).unwrap();
+    ///
+    /// let mut batch = Expression::new(&arena);
+    /// batch.add_expression("log(x)").unwrap();
+    /// batch.add_expression("ln(x) + ln(y)").unwrap();
+    ///
+    /// let calls = batch.deprecated_calls(&ctx);
+    /// assert_eq!(calls.len(), 1);
+    /// assert_eq!((calls[0].expression, calls[0].function.as_str()), (1, "ln"));
+    /// ```
+    pub fn deprecated_calls(&self, ctx: &EvalContext) -> Vec<DeprecatedCall> {
+        let mut calls: Vec<DeprecatedCall> = Vec::new();
+        for (index, (_, ast)) in self.expressions.iter().enumerate() {
+            let first = calls.len();
+            let _ = crate::visit::try_for_each_call(ast, &mut |name, _| {
+                if let Some(deprecation) = ctx.function_deprecation(name)
+                    && !calls[first..].iter().any(|call| call.function == name)
+                {
+                    calls.push(DeprecatedCall {
+                        expression: index,
+                        function: name.to_string(),
+                        replacement: deprecation.replacement.as_ref().map(|r| r.to_string()),
+                    });
+                }
+                Ok::<(), core::convert::Infallible>(())
+            });
+        }
+
+        for call in &calls {
+            let index = call.expression;
+            let label = self.names[index].unwrap_or(self.expressions[index].0);
+            let function = &call.function;
+            match &call.replacement {
+                Some(replacement) => ctx.log(
+                    crate::log::Level::Warn,
+                    format_args!(
+                        "expression {index} '{label}' calls deprecated function '{function}', use '{replacement}' instead"
+                    ),
+                ),
+                None => ctx.log(
+                    crate::log::Level::Warn,
+                    format_args!("expression {index} '{label}' calls deprecated function '{function}'"),
+                ),
+            }
+        }
+        calls
+    }
+
+    /// Drop the cached parsed bodies of expression functions
+    ///
+    /// Call this after replacing or removing many functions, e.g. on a configuration
+    /// reload, to release cache entries that no current definition uses. Unchanged
+    /// functions are parsed again on their next call.
+    pub fn clear_function_cache(&mut self) {
+        self.engine.clear_expression_function_cache();
+    }
+
+    /// Estimate the stack usage of evaluating this batch, without evaluating it
+    ///
+    /// The figures cover all expressions, including the bodies of the batch's
+    /// expression functions they call. Compare them against the evaluator's limits
+    /// when expressions are loaded to reject those that would fail at runtime.
+    ///
+    /// # Example
+    /// ```
+    /// use bumpalo::Bump;
+    /// use exp_rs::expression::Expression;
+    /// use exp_rs::types::EXP_RS_MAX_STACK_DEPTH;
+    ///
+    /// let arena = Bump::new();
+    /// let mut batch = Expression::new(&arena);
+    /// batch.register_expression_function("sq", &["v"], "v * v").unwrap();
+    /// batch.add_expression("sq(x) + sq(y)").unwrap();
+    ///
+    /// let estimate = batch.resource_estimate().unwrap();
+    /// assert_eq!(estimate.node_count, 8);
+    /// assert!(estimate.fits(EXP_RS_MAX_STACK_DEPTH));
+    /// ```
+    pub fn resource_estimate(&self) -> Result<crate::eval::ResourceEstimate, ExprError> {
+        use crate::eval::ResourceEstimate;
+
+        // Function bodies are parsed into a scratch arena so the batch's arena does
+        // not grow
+        let scratch = Bump::new();
+        let functions = self.local_functions.map(|map| map.borrow());
+        let mut bodies = alloc::collections::BTreeMap::new();
+        for function in functions.iter().flat_map(|map| map.values()) {
+            let body = crate::engine::parse_expression_with_parameters_and_options(
+                &function.expression,
+                &scratch,
+                &function.params,
+                &function.parse_options,
+            )?;
+            bodies.insert(function.name.as_str(), &*scratch.alloc(body));
+        }
+
+        Ok(self
+            .expressions
+            .iter()
+            .map(|(_, ast)| ResourceEstimate::with_functions(ast, &bodies))
+            .fold(ResourceEstimate::default(), ResourceEstimate::then))
+    }
+
+    /// Prepare the batch so that evaluating it does not allocate
+    ///
+    /// Builds the evaluation plan, sizes the scratch buffers and reserves the
+    /// evaluator stacks for the worst case of all expressions (see
+    /// [`resource_estimate`](Self::resource_estimate)). Afterwards `eval`,
+    /// `eval_incremental` and `set_param` use neither the heap nor new arena memory,
+    /// provided that:
+    /// - no expressions, parameters or functions are added or removed,
+    /// - every expression function was called once, e.g. by one evaluation, since
+    ///   its body is parsed on the first call,
+    /// - evaluations succeed, as errors carry allocated messages,
+    /// - the `std` feature is disabled, as it keeps parameters in a `HashMap`.
+    ///
+    /// # Example
+    /// ```
+    /// use bumpalo::Bump;
+    /// use exp_rs::{expression::Expression, EvalContext};
+    /// use std::rc::Rc;
+    ///
+    /// let arena = Bump::with_capacity(8192);
+    /// let ctx = Rc::new(EvalContext::new());
+    /// let mut batch = Expression::new(&arena);
+    /// batch.add_parameter("x", 0.0).unwrap();
+    /// batch.add_expression("x > 0 ? sqrt(x) : -x").unwrap();
+    /// batch.prepare().unwrap();
+    ///
+    /// let arena_bytes = arena.allocated_bytes();
+    /// for i in 0..100 {
+    ///     batch.set_param(0, i as f64 - 50.0).unwrap();
+    ///     batch.eval(&ctx).unwrap();
+    /// }
+    /// assert_eq!(arena.allocated_bytes(), arena_bytes);
+    /// ```
+    pub fn prepare(&mut self) -> Result<(), ExprError> {
+        let estimate = self.resource_estimate()?;
+        if self.plan.is_none() {
+            self.plan = Some(self.build_plan()?);
+        }
+        self.recomputed.reserve(self.expressions.len());
+        let named = self.names.iter().flatten().count();
+        self.engine.reserve_param_slots(self.params.len() + named);
+        self.engine
+            .reserve_stacks(estimate.max_stack_depth, estimate.max_value_stack);
+        Ok(())
+    }
+
+    /// Cache the results of pure expressions, keyed by the values of their inputs
+    ///
+    /// Once enabled, `eval` and `eval_incremental` look up each expression that only
+    /// calls functions the context declares pure (see
+    /// [`EvalContext::set_function_pure`]) and reads parameters, named results and
+    /// context variables or constants (no arrays or attributes) in a cache of
+    /// `capacity` results before evaluating it. A `capacity` of 0 disables memoization.
+    ///
+    /// The cache is emptied whenever expressions, parameters or local functions are
+    /// added or removed; call `clear_memo` after replacing functions of the context.
+    /// Memoized results are not counted by `eval_incremental`.
+    ///
+    /// # Example
+    /// ```
+    /// use bumpalo::Bump;
+    /// use exp_rs::{expression::Expression, EvalContext};
+    /// use std::rc::Rc;
+    ///
+    /// let arena = Bump::new();
+    /// let ctx = Rc::new(EvalContext::new());
+    /// let mut batch = Expression::new(&arena);
+    /// batch.add_parameter("x", 1.0).unwrap();
+    /// batch.add_expression("sqrt(x) * 2").unwrap();
+    /// batch.enable_memoization(16);
+    ///
+    /// for x in [1.0, 4.0, 1.0, 4.0] {
+    ///     batch.set("x", x).unwrap();
+    ///     batch.eval(&ctx).unwrap();
+    /// }
+    /// assert_eq!(batch.get_result(0), Some(4.0));
+    /// let stats = batch.memo_stats().unwrap();
+    /// assert_eq!((stats.hits, stats.misses), (2, 2));
+    /// ```
+    pub fn enable_memoization(&mut self, capacity: usize) {
+        if capacity == 0 {
+            self.memo = None;
+            return;
+        }
+        let max_inputs = self.params.len() + self.names.iter().flatten().count();
+        self.memo = Some(MemoCache::new(capacity, max_inputs));
+        self.memo_inputs.reserve(max_inputs);
+    }
+
+    /// Get the hit and miss counts of the result cache, if memoization is enabled
+    pub fn memo_stats(&self) -> Option<MemoStats> {
+        self.memo.as_ref().map(MemoCache::stats)
+    }
 
-            Some(slice as *mut _)
-        };
+    /// Forget all memoized results, e.g. after changing functions of the context
+    pub fn clear_memo(&mut self) {
+        if let Some(memo) = &mut self.memo {
+            memo.clear();
+        }
+    }
 
-        // Create the function
-        let func_name = name.try_into_function_name()?;
-        let expr_func = ExpressionFunction {
-            name: func_name.clone(),
-            params: params.iter().map(|s| s.to_string()).collect(),
-            expression: body.to_string(),
-            description: None,
-            param_buffer,
-        };
+    /// Collect evaluation statistics of each expression, timed with `clock`
+    ///
+    /// `clock` returns a free-running counter that counts up, such as a cycle counter;
+    /// wrap-around is handled. It is read before and after every evaluation of an
+    /// expression, and [`expression_stats`](Self::expression_stats) then reports the
+    /// counts, the fewest, most and mean ticks, and the last error of each expression.
+    /// Enabling again restarts the statistics. See [`crate::profile`].
+    ///
+    /// # Example
+    /// ```
+    /// use bumpalo::Bump;
+    /// use exp_rs::{expression::Expression, EvalContext};
+    /// use std::cell::Cell;
+    /// use std::rc::Rc;
+    ///
+    /// // A counter advancing 10 ticks per read, standing in for a cycle counter
+    /// let ticks = Cell::new(0u32);
+    ///
+    /// let arena = Bump::new();
+    /// let ctx = Rc::new(EvalContext::new());
+    /// let mut batch = Expression::new(&arena);
+    /// batch.add_parameter("x", 4.0).unwrap();
+    /// batch.add_expression("x * 2").unwrap();
+    /// batch.add_expression("sqrt(x) + sin(x) * cos(x)").unwrap();
+    /// batch.enable_stats(|| {
+    ///     ticks.set(ticks.get() + 10);
+    ///     ticks.get()
+    /// });
+    /// for _ in 0..3 {
+    ///     batch.eval(&ctx).unwrap();
+    /// }
+    ///
+    /// let stats = batch.expression_stats(1).unwrap();
+    /// assert_eq!((stats.evaluations, stats.errors), (3, 0));
+    /// assert_eq!((stats.min_ticks, stats.max_ticks), (10, 10));
+    /// assert_eq!(stats.mean_ticks(), Some(10.0));
+    /// assert!(batch.expression_stats(2).is_none());
+    /// ```
+    pub fn enable_stats<F>(&mut self, clock: F)
+    where
+        F: FnMut() -> u32 + 'arena,
+    {
+        self.profiler = Some(Profiler::new(alloc::boxed::Box::new(clock)));
+    }
 
-        // Add to map through RefCell
-        self.local_functions
-            .unwrap()
-            .borrow_mut()
-            .insert(func_name, expr_func)
-            .map_err(|_| ExprError::Other("Too many expression functions".to_string()))?;
-        Ok(())
+    /// Stop collecting evaluation statistics and drop those collected
+    pub fn disable_stats(&mut self) {
+        self.profiler = None;
     }
 
-    /// Remove a local expression function from this batch
+    /// Get the evaluation statistics of an expression, if statistics are enabled and
+    /// the index is valid
+    pub fn expression_stats(&self, expr_idx: usize) -> Option<&ExpressionStats> {
+        let profiler = self.profiler.as_ref()?;
+        (expr_idx < self.expressions.len()).then(|| profiler.get(expr_idx))
+    }
+
+    /// Restart the evaluation statistics of all expressions
+    pub fn reset_stats(&mut self) {
+        if let Some(profiler) = &mut self.profiler {
+            profiler.clear();
+        }
+    }
+
+    /// Forget the state of the trigger functions of this batch
     ///
-    /// # Arguments
-    /// * `name` - Function name to remove
+    /// `rising(cond)`, `falling(cond)`, `changed(x)`, `latch(set, reset)`,
+    /// `hysteresis(x, low, high)` and `debounce(cond, n)` remember their state from one
+    /// evaluation to the next, separately for every call in every expression. A call in
+    /// an expression function has its own state for every call of that function. After
+    /// a reset, each call behaves as on its first evaluation.
     ///
-    /// # Returns
-    /// * `Ok(true)` if the function was removed
-    /// * `Ok(false)` if the function didn't exist
-    /// * `Err` if the name is invalid
-    pub fn unregister_expression_function(&mut self, name: &str) -> Result<bool, ExprError> {
-        use crate::types::TryIntoFunctionName;
+    /// # Example
+    /// ```
+    /// use bumpalo::Bump;
+    /// use exp_rs::{expression::Expression, EvalContext};
+    /// use std::rc::Rc;
+    ///
+    /// let arena = Bump::new();
+    /// let ctx = Rc::new(EvalContext::new());
+    /// let mut batch = Expression::new(&arena);
+    /// batch.add_parameter("temp", 70.0).unwrap();
+    /// batch.add_expression("rising(temp > 80)").unwrap();
+    ///
+    /// let mut alarms = Vec::new();
+    /// for temp in [70.0, 85.0, 90.0, 75.0, 82.0] {
+    ///     batch.set("temp", temp).unwrap();
+    ///     batch.eval(&ctx).unwrap();
+    ///     alarms.push(batch.get_result(0).unwrap());
+    /// }
+    /// assert_eq!(alarms, [0.0, 1.0, 0.0, 0.0, 1.0]);
+    ///
+    /// batch.reset_triggers();
+    /// batch.eval(&ctx).unwrap();
+    /// assert_eq!(batch.get_result(0), Some(1.0));
+    /// ```
+    pub fn reset_triggers(&mut self) {
+        self.engine.reset_triggers();
+    }
 
-        if let Some(map) = self.local_functions {
-            let func_name = name.try_into_function_name()?;
-            Ok(map.borrow_mut().remove(&func_name).is_some())
-        } else {
-            Ok(false)
-        }
+    /// Get the names interned by this batch
+    ///
+    /// Every parameter, named expression and identifier of the added expressions is
+    /// stored once in the arena, however often it appears.
+    pub fn symbols(&self) -> &SymbolTable<'arena> {
+        &self.symbols
     }
 
     /// Get the current number of bytes allocated in the arena
@@ -281,8 +1486,16 @@ impl<'arena> Expression<'arena> {
     /// ```
     pub fn clear(&mut self) {
         self.expressions.clear();
+        self.names.clear();
+        self.name_symbols.clear();
+        self.plan = None;
+        self.param_changed.clear();
+        self.results_valid = false;
         self.params.clear();
+        self.param_names.clear();
         self.results.clear();
+        self.engine.reset_triggers();
+        self.reset_stats();
 
         // Clear local functions if they exist
         if let Some(funcs) = self.local_functions {
@@ -333,11 +1546,23 @@ impl<'arena> Expression<'arena> {
         arena: &'arena Bump,
     ) -> Result<Real, ExprError> {
         let mut builder = Self::new(arena);
-        builder.add_expression(expr)?;
+        match builder.add_expression(expr) {
+            Ok(_) => ctx.log(
+                crate::log::Level::Trace,
+                format_args!("parsed '{}' as {}", expr, builder.expressions[0].1),
+            ),
+            Err(err) => {
+                ctx.log(
+                    crate::log::Level::Warn,
+                    format_args!("failed to parse '{}': {}", expr, err),
+                );
+                return Err(err);
+            }
+        }
         builder.eval(ctx)?;
-        builder
-            .get_result(0)
-            .ok_or(ExprError::Other("No result".to_string()))
+        builder.get_result(0).ok_or(ExprError::Other {
+            message: "No result".to_string(),
+        })
     }
 
     /// Evaluate a single expression with parameters
@@ -372,9 +1597,9 @@ impl<'arena> Expression<'arena> {
 
         builder.add_expression(expr)?;
         builder.eval(ctx)?;
-        builder
-            .get_result(0)
-            .ok_or(ExprError::Other("No result".to_string()))
+        builder.get_result(0).ok_or(ExprError::Other {
+            message: "No result".to_string(),
+        })
     }
 
     /// Convenience setter using string slices
@@ -396,9 +1621,191 @@ impl<'arena> Expression<'arena> {
     }
 }
 
+#[cfg(feature = "rayon")]
+impl<'arena> Expression<'arena> {
+    /// Evaluate all expressions on the rayon thread pool
+    ///
+    /// Expressions are evaluated in waves: the first wave holds every expression
+    /// that references no named expression, and each later wave holds the
+    /// expressions whose named inputs were all computed by earlier waves. The
+    /// expressions of one wave run in parallel.
+    ///
+    /// `EvalContext` is not thread-safe, so each worker evaluates with its own
+    /// engine and a context returned by `make_ctx`. Local expression functions
+    /// are copied to every worker. Results are the same as with `eval`; on
+    /// failure the error of the first failing expression in evaluation order is
+    /// returned.
+    ///
+    /// Trigger functions such as `rising` remember their state in the batch's own
+    /// engine, which the workers do not share, so they are not supported: an
+    /// expression that evaluates one fails with `ExprError::Other`. Use `eval` for
+    /// batches with triggers.
+    ///
+    /// # Example
+    /// ```
+    /// use bumpalo::Bump;
+    /// use exp_rs::{expression::Expression, EvalContext};
+    ///
+    /// let arena = Bump::new();
+    /// let mut batch = Expression::new(&arena);
+    /// batch.add_parameter("x", 2.0).unwrap();
+    /// batch.add_named_expression("y", "x * x").unwrap();
+    /// batch.add_expression("y + 1").unwrap();
+    /// batch.add_expression("sqrt(x * 8)").unwrap();
+    ///
+    /// batch.eval_all_parallel(EvalContext::new).unwrap();
+    /// assert_eq!(batch.get_all_results(), &[4.0, 5.0, 4.0]);
+    /// ```
+    pub fn eval_all_parallel<F>(&mut self, make_ctx: F) -> Result<(), ExprError>
+    where
+        F: Fn() -> EvalContext + Sync,
+    {
+        use crate::types::{
+            BatchParamMap, ExpressionFunction, ExpressionFunctionMap, FunctionName,
+        };
+        use rayon::prelude::*;
+
+        if self.plan.is_none() {
+            self.plan = Some(self.build_plan()?);
+        }
+        let plan = self.plan.as_ref().unwrap();
+
+        let mut wave = alloc::vec![0usize; self.expressions.len()];
+        for &i in &plan.order {
+            wave[i] = plan.named_deps[i]
+                .iter()
+                .map(|&d| wave[d] + 1)
+                .max()
+                .unwrap_or(0);
+        }
+        let wave_count = wave.iter().max().map_or(0, |w| w + 1);
+
+        let mut overrides = BatchParamMap::new();
+        for param in &self.params {
+            let _ = overrides.insert(param.name.as_str().try_into_heapless()?, param.value);
+        }
+
+        #[allow(clippy::type_complexity)]
+        let functions: Vec<(
+            FunctionName,
+            Vec<String>,
+            String,
+            Vec<Real>,
+            u64,
+            crate::engine::ParseOptions,
+        )> = self
+            .local_functions
+            .map(|map| {
+                map.borrow()
+                    .values()
+                    .map(|f| {
+                        (
+                            f.name.clone(),
+                            f.params.clone(),
+                            f.expression.clone(),
+                            f.defaults.clone(),
+                            f.definition_hash,
+                            f.parse_options,
+                        )
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let expressions = &self.expressions;
+        for w in 0..wave_count {
+            let indices: Vec<usize> = plan
+                .order
+                .iter()
+                .copied()
+                .filter(|&i| wave[i] == w)
+                .collect();
+            let chunk_size = indices.len().div_ceil(rayon::current_num_threads()).max(1);
+
+            let results: Vec<Vec<(usize, Result<Real, ExprError>)>> = indices
+                .par_chunks(chunk_size)
+                .map(|chunk| {
+                    let arena = Bump::new();
+                    let mut engine = EvalEngine::new(&arena);
+                    engine.set_param_overrides(overrides.clone());
+
+                    let local = (!functions.is_empty()).then(|| {
+                        let map = arena.alloc(RefCell::new(ExpressionFunctionMap::new()));
+                        for (name, params, body, defaults, definition_hash, parse_options) in
+                            &functions
+                        {
+                            let _ = map.borrow_mut().insert(
+                                name.clone(),
+                                ExpressionFunction {
+                                    name: name.clone(),
+                                    params: params.clone(),
+                                    expression: body.clone(),
+                                    description: None,
+                                    defaults: defaults.clone(),
+                                    param_buffer: None,
+                                    definition_hash: *definition_hash,
+                                    parse_options: *parse_options,
+                                    validator: None,
+                                },
+                            );
+                        }
+                        &*map
+                    });
+                    engine.set_local_functions(local);
+
+                    let ctx = Rc::new(make_ctx());
+                    let out = chunk
+                        .iter()
+                        .map(|&i| {
+                            let result =
+                                eval_with_engine(expressions[i].1, Some(ctx.clone()), &mut engine);
+                            // A fresh engine gives the first output of a trigger rather
+                            // than continue the state kept by `eval`
+                            let result = match result {
+                                Ok(_) if engine.has_trigger_state() => Err(ExprError::Other {
+                                    message: "Trigger functions are not supported by \
+                                              eval_all_parallel"
+                                        .to_string(),
+                                }),
+                                result => result,
+                            };
+                            (i, result)
+                        })
+                        .collect();
+
+                    // The map lives in the worker arena, which never runs destructors
+                    if let Some(map) = local {
+                        map.borrow_mut().clear();
+                    }
+                    out
+                })
+                .collect();
+
+            for (i, result) in results.into_iter().flatten() {
+                let value = match result {
+                    Ok(value) => value,
+                    Err(e) => {
+                        self.results_valid = false;
+                        return Err(e);
+                    }
+                };
+                self.results[i] = value;
+                if let Some(name) = self.names[i] {
+                    let _ = overrides.insert(name.try_into_heapless()?, value);
+                }
+            }
+        }
+
+        self.param_changed.fill(false);
+        self.results_valid = true;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::{ExpressionFunction, TryIntoFunctionName};
     use bumpalo::Bump;
 
     // === Tests for Expression Convenience Methods ===
@@ -543,6 +1950,129 @@ mod tests {
         assert!(!builder.unregister_expression_function("double").unwrap()); // Already removed
     }
 
+    #[test]
+    fn test_function_bodies_read_parameters() {
+        let arena = Bump::new();
+        let mut ctx = EvalContext::new();
+        ctx.set_parameter("gain", 100.0).unwrap();
+        let ctx = Rc::new(ctx);
+
+        let mut batch = Expression::new(&arena);
+        batch
+            .register_expression_function("offset", &["x"], "x - setpoint + trim(x)")
+            .unwrap();
+        batch
+            .register_expression_function("trim", &["x"], "x * bias + gain")
+            .unwrap();
+        batch.add_named_expression("bias", "setpoint / 10").unwrap();
+        batch.add_parameter("setpoint", 20.0).unwrap();
+        batch.add_expression("offset(25)").unwrap();
+
+        // Parameters and named results are visible in bodies, nested ones included,
+        // and context variables behind them
+        batch.eval(&ctx).unwrap();
+        assert_eq!(batch.get_result(1), Some(5.0 + 50.0 + 100.0));
+        batch.set("setpoint", 10.0).unwrap();
+        batch.eval(&ctx).unwrap();
+        assert_eq!(batch.get_result(1), Some(15.0 + 25.0 + 100.0));
+    }
+
+    #[test]
+    fn test_reregistered_function_cache() {
+        let arena = Bump::new();
+        let ctx = Rc::new(EvalContext::new());
+        let mut batch = Expression::new(&arena);
+        batch
+            .register_expression_function("scale", &["x"], "x * 2")
+            .unwrap();
+        batch.add_expression("scale(5)").unwrap();
+        batch.eval(&ctx).unwrap();
+        assert_eq!(batch.get_result(0), Some(10.0));
+
+        // Re-registering an unchanged definition allocates nothing
+        let allocated = batch.arena_allocated_bytes();
+        for _ in 0..100 {
+            batch
+                .register_expression_function("scale", &["x"], "x * 2")
+                .unwrap();
+        }
+        assert_eq!(batch.arena_allocated_bytes(), allocated);
+        batch.eval(&ctx).unwrap();
+        assert_eq!(batch.get_result(0), Some(10.0));
+
+        // A changed body is parsed again rather than served from the cache
+        batch
+            .register_expression_function("scale", &["x"], "x * 3")
+            .unwrap();
+        batch.eval(&ctx).unwrap();
+        assert_eq!(batch.get_result(0), Some(15.0));
+
+        batch.clear_function_cache();
+        batch.eval(&ctx).unwrap();
+        assert_eq!(batch.get_result(0), Some(15.0));
+
+        // Definitions whose hashes collide are still told apart by their source
+        let hash_of = |batch: &Expression, name: &str| {
+            let name = name.try_into_function_name().unwrap();
+            batch.local_functions.unwrap().borrow()[&name].definition_hash
+        };
+        let set_hash = |batch: &Expression, name: &str, hash: u64| {
+            let name = name.try_into_function_name().unwrap();
+            batch
+                .local_functions
+                .unwrap()
+                .borrow_mut()
+                .get_mut(&name)
+                .unwrap()
+                .definition_hash = hash;
+        };
+        batch
+            .register_expression_function("offset", &["x"], "x + 1")
+            .unwrap();
+        batch.add_expression("offset(5)").unwrap();
+        set_hash(&batch, "offset", hash_of(&batch, "scale"));
+        batch.eval(&ctx).unwrap();
+        assert_eq!(batch.get_all_results(), &[15.0, 6.0]);
+
+        let colliding = ExpressionFunction::definition_hash("scale", &["x".into()], "x * 4");
+        set_hash(&batch, "scale", colliding);
+        batch
+            .register_expression_function("scale", &["x"], "x * 4")
+            .unwrap();
+        batch.eval(&ctx).unwrap();
+        assert_eq!(batch.get_all_results(), &[20.0, 6.0]);
+    }
+
+    #[test]
+    fn test_resource_estimate_with_expression_functions() {
+        let arena = Bump::new();
+        let mut batch = Expression::new(&arena);
+        batch
+            .register_expression_function("f", &["v"], "v * (v + 1)")
+            .unwrap();
+        batch.add_parameter("x", 2.0).unwrap();
+        batch.add_expression("f(f(x)) + 1").unwrap();
+        batch.add_expression("x").unwrap();
+        let estimate = batch.resource_estimate().unwrap();
+        assert_eq!(estimate.node_count, 11);
+
+        let mut ctx = EvalContext::new();
+        ctx.set_max_eval_depth(Some(estimate.max_stack_depth));
+        assert!(batch.eval(&Rc::new(ctx)).is_ok());
+        let mut ctx = EvalContext::new();
+        ctx.set_max_eval_depth(Some(estimate.max_stack_depth - 1));
+        assert!(batch.eval(&Rc::new(ctx)).is_err());
+
+        // Recursion has no static bound
+        batch
+            .register_expression_function("r", &["v"], "v > 0 ? r(v - 1) : 0")
+            .unwrap();
+        batch.add_expression("r(3)").unwrap();
+        let estimate = batch.resource_estimate().unwrap();
+        assert_eq!(estimate.max_stack_depth, usize::MAX);
+        assert!(!estimate.fits(crate::types::EXP_RS_MAX_STACK_DEPTH));
+    }
+
     #[test]
     fn test_arena_batch_local_functions() {
         let arena = Bump::new();
@@ -562,6 +2092,569 @@ mod tests {
             assert_eq!(builder.get_result(0), Some(15.0)); // x * 3 = 15
         }
     }
+
+    #[test]
+    fn test_named_expressions_dependency_order() {
+        let arena = Bump::new();
+        let ctx = Rc::new(EvalContext::new());
+        let mut batch = Expression::new(&arena);
+
+        let total = batch.add_expression("z + y").unwrap();
+        batch.add_named_expression("z", "y * 2").unwrap();
+        batch.add_named_expression("y", "x + 1").unwrap();
+        batch.add_parameter("x", 1.0).unwrap();
+
+        batch.eval(&ctx).unwrap();
+        assert_eq!(batch.get_result_by_name("y"), Some(2.0));
+        assert_eq!(batch.get_result_by_name("z"), Some(4.0));
+        assert_eq!(batch.get_result(total), Some(6.0));
+
+        batch.set("x", 3.0).unwrap();
+        batch.eval(&ctx).unwrap();
+        assert_eq!(batch.get_result(total), Some(12.0));
+
+        // Names share the parameter namespace
+        assert!(matches!(
+            batch.add_parameter("y", 0.0),
+            Err(ExprError::DuplicateParameter { .. })
+        ));
+        assert!(matches!(
+            batch.add_named_expression("x", "1"),
+            Err(ExprError::DuplicateParameter { .. })
+        ));
+    }
+
+    #[test]
+    fn test_named_expressions_cycle() {
+        let arena = Bump::new();
+        let ctx = Rc::new(EvalContext::new());
+        let mut batch = Expression::new(&arena);
+
+        batch.add_named_expression("a", "b + 1").unwrap();
+        batch.add_named_expression("b", "c * 2").unwrap();
+        batch.add_named_expression("c", "a - 1").unwrap();
+
+        match batch.eval(&ctx) {
+            Err(ExprError::CyclicDependency { cycle }) => assert_eq!(cycle, ["a", "b", "c", "a"]),
+            other => panic!("expected cycle error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_eval_incremental_skips_unchanged_inputs() {
+        let arena = Bump::new();
+        let ctx = Rc::new(EvalContext::new());
+        let mut batch = Expression::new(&arena);
+
+        batch.add_parameter("a", 1.0).unwrap();
+        batch.add_parameter("b", 2.0).unwrap();
+        batch.add_named_expression("sum", "a + b").unwrap();
+        batch
+            .add_named_expression("sign", "a > 0 ? 1 : -1")
+            .unwrap();
+        let scaled = batch.add_expression("sum * sign").unwrap();
+        let b_only = batch.add_expression("b * 100").unwrap();
+
+        assert_eq!(batch.eval_incremental(&ctx).unwrap(), 4);
+        assert_eq!(batch.eval_incremental(&ctx).unwrap(), 0);
+
+        // Setting the same value does not mark the parameter dirty
+        batch.set("a", 1.0).unwrap();
+        assert_eq!(batch.eval_incremental(&ctx).unwrap(), 0);
+
+        // sum and sign depend on a; sign is unchanged, sum propagates to scaled
+        batch.set("a", 2.0).unwrap();
+        assert_eq!(batch.eval_incremental(&ctx).unwrap(), 3);
+        assert_eq!(batch.get_result(scaled), Some(4.0));
+        assert_eq!(batch.get_result(b_only), Some(200.0));
+
+        batch.set("b", 3.0).unwrap();
+        assert_eq!(batch.eval_incremental(&ctx).unwrap(), 3);
+        assert_eq!(batch.get_result(scaled), Some(5.0));
+        assert_eq!(batch.get_result(b_only), Some(300.0));
+
+        // Structural changes force a full evaluation
+        batch.add_expression("a - b").unwrap();
+        assert_eq!(batch.eval_incremental(&ctx).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_expression_function_default_params() {
+        let arena = Bump::new();
+        let mut batch = Expression::new(&arena);
+        batch
+            .register_expression_function("lerp", &["a", "b", " t = 0.5 "], "a + (b - a) * t")
+            .unwrap();
+        batch.add_expression("lerp(0, 10)").unwrap();
+        batch.add_expression("lerp(0, 10, 0.1)").unwrap();
+        batch.eval(&Rc::new(EvalContext::new())).unwrap();
+        assert_eq!(batch.get_all_results(), &[5.0, 1.0]);
+
+        batch.add_expression("lerp(0)").unwrap();
+        assert!(matches!(
+            batch.eval(&Rc::new(EvalContext::new())),
+            Err(ExprError::InvalidFunctionCall {
+                expected: 2,
+                found: 1,
+                ..
+            })
+        ));
+
+        assert!(
+            batch
+                .register_expression_function("bad", &["a=1", "b"], "a + b")
+                .is_err()
+        );
+        assert!(
+            batch
+                .register_expression_function("bad", &["a=one"], "a")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_memoized_results_follow_inputs() {
+        let arena = Bump::new();
+        let mut ctx = EvalContext::new();
+        ctx.set_parameter("offset", 10.0).unwrap();
+        ctx.arrays
+            .insert("samples".try_into_heapless().unwrap(), alloc::vec![0.5])
+            .unwrap();
+        let ctx_a = Rc::new(ctx.clone());
+        ctx.set_parameter("offset", 20.0).unwrap();
+        let ctx_b = Rc::new(ctx);
+
+        let mut batch = Expression::new(&arena);
+        batch.add_parameter("x", 1.0).unwrap();
+        batch.add_named_expression("y", "x * 2").unwrap();
+        let total = batch.add_expression("y + offset").unwrap();
+        batch.add_expression("samples[0] + x").unwrap();
+        batch.enable_memoization(64);
+
+        // The first pass misses; array reads are never cached
+        assert_eq!(batch.eval_incremental(&ctx_a).unwrap(), 3);
+        batch.eval(&ctx_a).unwrap();
+        assert_eq!(batch.get_result(total), Some(12.0));
+        assert_eq!(batch.memo_stats(), Some(MemoStats { hits: 2, misses: 2 }));
+
+        // A changed context variable is a different key
+        batch.eval(&ctx_b).unwrap();
+        assert_eq!(batch.get_result(total), Some(22.0));
+        batch.set("x", 3.0).unwrap();
+        batch.eval(&ctx_a).unwrap();
+        assert_eq!(batch.get_result(total), Some(16.0));
+        batch.set("x", 1.0).unwrap();
+        assert_eq!(batch.eval_incremental(&ctx_a).unwrap(), 1);
+        assert_eq!(batch.get_result(total), Some(12.0));
+
+        // Structural changes drop the cache
+        batch.add_expression("x").unwrap();
+        let misses = batch.memo_stats().unwrap().misses;
+        batch.eval(&ctx_a).unwrap();
+        assert_eq!(batch.memo_stats().unwrap().misses, misses + 3);
+
+        batch.enable_memoization(0);
+        assert_eq!(batch.memo_stats(), None);
+
+        // Native functions are only memoized once declared pure
+        let reads = Rc::new(core::cell::Cell::new(0));
+        let counter = reads.clone();
+        let mut ctx = EvalContext::new();
+        ctx.register_native_function("adc_read", 1, move |args| {
+            counter.set(counter.get() + 1);
+            args[0] * 0.5
+        })
+        .unwrap();
+        let mut batch = Expression::new(&arena);
+        batch.add_expression("adc_read(3) + 1").unwrap();
+        batch.enable_memoization(4);
+        let impure = Rc::new(ctx.clone());
+        batch.eval(&impure).unwrap();
+        batch.eval(&impure).unwrap();
+        assert_eq!(reads.get(), 2);
+        ctx.set_function_pure("adc_read", true).unwrap();
+        let pure = Rc::new(ctx);
+        batch.eval(&pure).unwrap();
+        batch.eval(&pure).unwrap();
+        assert_eq!(reads.get(), 3);
+        assert_eq!(batch.get_result(0), Some(2.5));
+    }
+
+    #[test]
+    fn test_trigger_functions_keep_state_per_call() {
+        let arena = Bump::new();
+        let ctx = Rc::new(EvalContext::new());
+        let mut batch = Expression::new(&arena);
+        batch.add_parameter("x", 0.0).unwrap();
+        batch.add_parameter("ack", 0.0).unwrap();
+        batch.add_expression("rising(x > 1)").unwrap();
+        batch.add_expression("falling(x > 1)").unwrap();
+        batch.add_expression("changed(x)").unwrap();
+        batch.add_expression("latch(x > 1, ack)").unwrap();
+        // Two calls with the same argument have separate state
+        batch
+            .add_expression("rising(x > 1) + rising(x > 1) * 10")
+            .unwrap();
+
+        let mut rows = Vec::new();
+        for (x, ack) in [
+            (0.0, 0.0),
+            (2.0, 0.0),
+            (3.0, 0.0),
+            (0.0, 0.0),
+            (0.0, 1.0),
+            (0.0, 0.0),
+        ] {
+            batch.set("x", x).unwrap();
+            batch.set("ack", ack).unwrap();
+            batch.eval(&ctx).unwrap();
+            rows.push(batch.get_all_results().to_vec());
+        }
+        assert_eq!(
+            rows,
+            [
+                [0.0, 0.0, 0.0, 0.0, 0.0],
+                [1.0, 0.0, 1.0, 1.0, 11.0],
+                [0.0, 0.0, 1.0, 1.0, 0.0],
+                [0.0, 1.0, 1.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 0.0, 0.0],
+            ]
+        );
+
+        // hysteresis holds its output between the thresholds; debounce waits for the
+        // condition to hold for n evaluations, restarting the count when it flips back
+        let mut batch = Expression::new(&arena);
+        batch.add_parameter("x", 0.0).unwrap();
+        batch.add_expression("hysteresis(x, 10, 20)").unwrap();
+        batch.add_expression("debounce(x > 15, 3)").unwrap();
+        let mut outputs = Vec::new();
+        for x in [15.0, 25.0, 16.0, 12.0, 16.0, 17.0, 18.0, 5.0, 5.0, 5.0] {
+            batch.set("x", x).unwrap();
+            batch.eval(&ctx).unwrap();
+            outputs.push((batch.get_result(0).unwrap(), batch.get_result(1).unwrap()));
+        }
+        let (hysteresis, debounce): (Vec<Real>, Vec<Real>) = outputs.into_iter().unzip();
+        assert_eq!(
+            hysteresis,
+            [0.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0]
+        );
+        assert_eq!(debounce, [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 0.0]);
+
+        // Only calls that are evaluated update their state
+        let mut batch = Expression::new(&arena);
+        batch.add_parameter("x", 0.0).unwrap();
+        batch.add_parameter("on", 0.0).unwrap();
+        batch.add_expression("on ? changed(x) : -1").unwrap();
+        for (x, on, expected) in [(1.0, 1.0, 0.0), (2.0, 0.0, -1.0), (2.0, 1.0, 1.0)] {
+            batch.set("x", x).unwrap();
+            batch.set("on", on).unwrap();
+            batch.eval(&ctx).unwrap();
+            assert_eq!(batch.get_result(0), Some(expected));
+        }
+
+        // A call in an expression function has state for every call of the function
+        let mut batch = Expression::new(&arena);
+        batch.add_parameter("a", 0.0).unwrap();
+        batch.add_parameter("b", 0.0).unwrap();
+        batch
+            .register_expression_function("edge", &["v"], "rising(v > 0)")
+            .unwrap();
+        batch
+            .register_expression_function("edges", &["v", "w"], "edge(v) + edge(w) * 10")
+            .unwrap();
+        batch.add_expression("edge(a) + edge(b) * 10").unwrap();
+        batch.add_expression("edges(a, b)").unwrap();
+        for (a, b, expected) in [
+            (1.0, 1.0, 11.0),
+            (1.0, 1.0, 0.0),
+            (0.0, 1.0, 0.0),
+            (1.0, 0.0, 1.0),
+            (0.0, 1.0, 10.0),
+        ] {
+            batch.set("a", a).unwrap();
+            batch.set("b", b).unwrap();
+            batch.eval(&ctx).unwrap();
+            assert_eq!(batch.get_all_results(), &[expected, expected]);
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_eval_all_parallel_matches_eval() {
+        let arena = Bump::new();
+        let mut batch = Expression::new(&arena);
+        batch.add_parameter("x", 1.5).unwrap();
+        batch
+            .register_expression_function("sq", &["v"], "v * v")
+            .unwrap();
+        for i in 0..200 {
+            let name = format!("n{}", i);
+            let expr = if i == 0 {
+                "sq(x)".to_string()
+            } else {
+                format!("n{} + sin({})", i - 1, i)
+            };
+            batch.add_named_expression(&name, &expr).unwrap();
+            batch.add_expression(&format!("{} * x", name)).unwrap();
+        }
+
+        batch.eval_all_parallel(EvalContext::new).unwrap();
+        let parallel = batch.get_all_results().to_vec();
+        batch.eval(&Rc::new(EvalContext::new())).unwrap();
+        assert_eq!(parallel, batch.get_all_results());
+
+        batch.add_expression("nope(1)").unwrap();
+        assert!(matches!(
+            batch.eval_all_parallel(EvalContext::new),
+            Err(ExprError::UnknownFunction { .. })
+        ));
+
+        // Triggers would start over in every worker, so they are refused once reached
+        let mut batch = Expression::new(&arena);
+        batch.add_parameter("t", 2.0).unwrap();
+        batch
+            .register_expression_function("edge", &["v"], "rising(v > 1)")
+            .unwrap();
+        batch.add_expression("t * 2").unwrap();
+        batch.add_expression("t > 5 ? edge(t) : 0").unwrap();
+        batch.eval_all_parallel(EvalContext::new).unwrap();
+        assert_eq!(batch.get_all_results(), &[4.0, 0.0]);
+        batch.set("t", 6.0).unwrap();
+        assert!(matches!(
+            batch.eval_all_parallel(EvalContext::new),
+            Err(ExprError::Other { .. })
+        ));
+    }
+
+    #[test]
+    fn test_validate_calls() {
+        let arena = Bump::new();
+        let mut ctx = EvalContext::new();
+        ctx.register_native_function("clamp01", 1, |args| args[0].clamp(0.0, 1.0))
+            .unwrap();
+        ctx.set_function_validator("clamp01", |args: &[AstExpr<'_>]| match args[0] {
+            AstExpr::Constant(_) => Err("the argument is constant".to_string()),
+            _ => Ok(()),
+        })
+        .unwrap();
+        assert!(matches!(
+            ctx.set_function_validator("missing", |_: &[AstExpr<'_>]| Ok(())),
+            Err(ExprError::UnknownFunction { .. })
+        ));
+
+        let mut batch = Expression::new(&arena);
+        batch
+            .register_expression_function("scale", &["x", "gain=2"], "clamp01(x) * gain")
+            .unwrap();
+        batch
+            .set_function_validator("scale", |args: &[AstExpr<'_>]| match args.get(1) {
+                Some(AstExpr::Constant(gain)) if *gain <= 0.0 => {
+                    Err("gain must be positive".to_string())
+                }
+                _ => Ok(()),
+            })
+            .unwrap();
+        batch
+            .add_expression("scale(a) + scale(b, 3) + unknown(1)")
+            .unwrap();
+        assert!(batch.validate(&ctx).is_ok());
+
+        batch.add_expression("scale(a, 0)").unwrap();
+        match batch.validate(&ctx) {
+            Err(ExprError::InvalidArguments { name, message }) => {
+                assert_eq!(
+                    (name.as_str(), message.as_str()),
+                    ("scale", "gain must be positive")
+                );
+            }
+            other => panic!("{other:?}"),
+        }
+
+        let mut batch = Expression::new(&arena);
+        batch.add_expression("1 + clamp01(a, b)").unwrap();
+        assert!(matches!(
+            batch.validate(&ctx),
+            Err(ExprError::InvalidFunctionCall {
+                expected: 1,
+                found: 2,
+                ..
+            })
+        ));
+
+        // Function bodies are checked too
+        let mut batch = Expression::new(&arena);
+        batch
+            .register_expression_function("half", &["x"], "clamp01(0.5) * x")
+            .unwrap();
+        batch.add_expression("half(y)").unwrap();
+        assert!(matches!(
+            batch.validate(&ctx),
+            Err(ExprError::InvalidArguments { .. })
+        ));
+        batch.add_expression("half()").unwrap();
+        assert!(batch.validate(&EvalContext::new()).is_err());
+    }
+
+    #[test]
+    fn test_strict_declarations() {
+        let arena = Bump::new();
+        let mut parent = EvalContext::new();
+        parent.set_strict_declarations(true);
+        parent.declare_variables(["temperature", "imu"]).unwrap();
+        let mut ctx = EvalContext::new();
+        ctx.parent = Some(Rc::new(parent));
+        ctx.set_parameter("gain", 2.0).unwrap();
+        assert!(ctx.strict_declarations());
+
+        let mut batch = Expression::new(&arena);
+        batch.add_parameter("setpoint", 20.0).unwrap();
+        batch
+            .register_expression_function("offset", &["x"], "x - setpoint")
+            .unwrap();
+        batch
+            .add_named_expression("error", "offset(temperature) * gain + imu.gyro * pi")
+            .unwrap();
+        batch
+            .add_expression("error > 1 && elapsed(start) > 5")
+            .unwrap();
+        assert!(batch.validate(&ctx).is_ok());
+
+        // An unset, undeclared name is rejected, though evaluation is not attempted
+        batch.add_expression("temprature > setpoint").unwrap();
+        match batch.validate(&ctx) {
+            Err(ExprError::UnknownVariable { name }) => assert_eq!(name, "temprature"),
+            other => panic!("{other:?}"),
+        }
+        ctx.set_strict_declarations(false);
+        assert!(batch.validate(&ctx).is_ok());
+        ctx.set_strict_declarations(true);
+
+        // Function bodies may only use their parameters and declared names
+        let mut batch = Expression::new(&arena);
+        batch
+            .register_expression_function("scaled", &["x"], "x * gan")
+            .unwrap();
+        assert!(batch.validate(&ctx).is_err());
+        ctx.declare_variables(["gan"]).unwrap();
+        assert!(batch.validate(&ctx).is_ok());
+        ctx.clear_declarations();
+        assert!(batch.validate(&ctx).is_err());
+    }
+
+    #[test]
+    fn test_deprecated_calls() {
+        let messages = Rc::new(RefCell::new(Vec::new()));
+        let sink = messages.clone();
+        let mut parent = EvalContext::new();
+        parent.deprecate_function("ln", Some("log")).unwrap();
+        parent.deprecate_function("old_gain", None).unwrap();
+        parent.set_logger(move |level, args: core::fmt::Arguments<'_>| {
+            sink.borrow_mut().push((level, args.to_string()));
+        });
+        let mut ctx = EvalContext::new();
+        ctx.parent = Some(Rc::new(parent));
+        ctx.deprecate_function("pow", Some("power")).unwrap();
+        assert!(ctx.undeprecate_function("pow"));
+        assert!(!ctx.undeprecate_function("pow"));
+
+        let arena = Bump::new();
+        let mut batch = Expression::new(&arena);
+        batch.add_expression("pow(x, 2)").unwrap();
+        batch
+            .add_named_expression("trim", "ln(x) * old_gain(ln(y))")
+            .unwrap();
+        batch.add_expression("x > 0 ? 1 : ln(2)").unwrap();
+
+        let calls = batch.deprecated_calls(&ctx);
+        let listed: Vec<_> = calls
+            .iter()
+            .map(|c| (c.expression, c.function.as_str(), c.replacement.as_deref()))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                (1, "ln", Some("log")),
+                (1, "old_gain", None),
+                (2, "ln", Some("log"))
+            ]
+        );
+        let messages = messages.borrow();
+        assert_eq!(messages.len(), 3);
+        assert!(
+            messages
+                .iter()
+                .all(|(level, _)| *level == crate::log::Level::Warn)
+        );
+        assert_eq!(
+            messages[0].1,
+            "expression 1 'trim' calls deprecated function 'ln', use 'log' instead"
+        );
+        assert_eq!(
+            messages[1].1,
+            "expression 1 'trim' calls deprecated function 'old_gain'"
+        );
+    }
+
+    #[test]
+    fn test_expression_stats() {
+        // Each read advances the counter by the next step, wrapping around
+        let steps = RefCell::new([5u32, 40, 7, 3, 2].into_iter().cycle());
+        let ticks = core::cell::Cell::new(u32::MAX - 20);
+        let mut ctx = EvalContext::new();
+        ctx.set_parameter("limit", 1.0).unwrap();
+        let ctx = Rc::new(ctx);
+
+        let arena = Bump::new();
+        let mut batch = Expression::new(&arena);
+        batch.add_parameter("x", 4.0).unwrap();
+        batch.add_expression("x * 2").unwrap();
+        batch.add_expression("x > limit ? x : nope").unwrap();
+        assert!(batch.expression_stats(0).is_none());
+        batch.enable_stats(|| {
+            let next = ticks.get().wrapping_add(steps.borrow_mut().next().unwrap());
+            ticks.set(next);
+            next
+        });
+        assert_eq!(batch.expression_stats(1).unwrap().evaluations, 0);
+        assert_eq!(batch.expression_stats(1).unwrap().mean_ticks(), None);
+
+        batch.eval(&ctx).unwrap();
+        batch.set("x", 0.0).unwrap();
+        assert!(batch.eval(&ctx).is_err());
+        batch.set("x", 3.0).unwrap();
+        batch.eval(&ctx).unwrap();
+
+        let first = batch.expression_stats(0).unwrap();
+        assert_eq!((first.evaluations, first.errors), (3, 0));
+        assert_eq!(
+            (first.min_ticks, first.max_ticks, first.total_ticks),
+            (2, 40, 47)
+        );
+        let second = batch.expression_stats(1).unwrap();
+        assert_eq!((second.evaluations, second.errors), (3, 1));
+        assert_eq!(
+            (second.min_ticks, second.max_ticks, second.total_ticks),
+            (3, 40, 50)
+        );
+        assert_eq!(second.mean_ticks(), Some(50.0 / 3.0));
+        assert!(matches!(
+            &second.last_error,
+            Some(ExprError::UnknownVariable { name }) if name == "nope"
+        ));
+
+        // Memoized results are not evaluations
+        batch.reset_stats();
+        batch.enable_memoization(4);
+        for _ in 0..3 {
+            batch.eval(&ctx).unwrap();
+        }
+        assert_eq!(batch.expression_stats(0).unwrap().evaluations, 1);
+        assert_eq!(batch.expression_stats(1).unwrap().evaluations, 3);
+
+        batch.disable_stats();
+        assert!(batch.expression_stats(0).is_none());
+    }
 }
 
 // Implement Drop to manually free heap-allocated strings in ExpressionFunction objects
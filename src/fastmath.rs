@@ -0,0 +1,271 @@
+//! Fast approximations of `sin`, `cos`, `exp`, `ln` and `log`, with the `fast-math`
+//! feature.
+//!
+//! The approximations reduce the argument to a small range and evaluate a short
+//! polynomial, trading accuracy for a fraction of the cycles of libm, e.g. for LED
+//! animations where an error of 0.1% is invisible. Their error bounds, checked by the
+//! tests with `f64`, are:
+//!
+//! | Function | Maximum error                                |
+//! |----------|----------------------------------------------|
+//! | `sin`    | [`SIN_COS_MAX_ERROR`] absolute, for `\|x\| <= 1e4` |
+//! | `cos`    | [`SIN_COS_MAX_ERROR`] absolute, for `\|x\| <= 1e4` |
+//! | `exp`    | [`EXP_MAX_RELATIVE_ERROR`] relative          |
+//! | `ln`     | [`LN_MAX_ERROR`] absolute                    |
+//! | `log`    | [`LN_MAX_ERROR`] absolute (base 10)          |
+//!
+//! With `f32`, the rounding of `f32` adds to these. Larger angles lose accuracy in the
+//! argument reduction as with any `sin`. Results of `exp` below the smallest normal
+//! number are flushed to zero.
+//!
+//! Expressions choose per call with the builtins `fast_sin`, `fast_cos`, `fast_exp`,
+//! `fast_ln` and `fast_log`, and a context can use the approximations for the standard
+//! names with [`register`]:
+//!
+//! ```
+//! use exp_rs::context::EvalContext;
+//! use exp_rs::engine::interp;
+//! use std::rc::Rc;
+//!
+//! let mut ctx = EvalContext::new();
+//! exp_rs::fastmath::register(&mut ctx, &["sin", "cos"]).unwrap();
+//! let ctx = Rc::new(ctx);
+//!
+//! let brightness = interp("0.5 + 0.5 * sin(2.0)", Some(ctx.clone())).unwrap();
+//! assert!((brightness - 0.954_649).abs() < 1e-3);
+//! assert!((interp("fast_exp(1)", Some(ctx)).unwrap() - 2.718_282).abs() < 1e-4);
+//! ```
+
+use crate::Real;
+use crate::context::EvalContext;
+use crate::error::ExprError;
+use crate::functions::floor;
+
+/// Bound of the absolute error of [`sin`] and [`cos`] for arguments up to `1e4`.
+pub const SIN_COS_MAX_ERROR: Real = 2e-4;
+
+/// Bound of the relative error of [`exp`].
+pub const EXP_MAX_RELATIVE_ERROR: Real = 5e-6;
+
+/// Bound of the absolute error of [`ln`] and [`log`].
+pub const LN_MAX_ERROR: Real = 1e-7;
+
+const PI: Real = core::f64::consts::PI as Real;
+const FRAC_PI_2: Real = core::f64::consts::FRAC_PI_2 as Real;
+const FRAC_1_2PI: Real = (0.5 / core::f64::consts::PI) as Real;
+const LN_2: Real = core::f64::consts::LN_2 as Real;
+const LOG2_E: Real = core::f64::consts::LOG2_E as Real;
+const LOG10_E: Real = core::f64::consts::LOG10_E as Real;
+const SQRT_2: Real = core::f64::consts::SQRT_2 as Real;
+
+/// Approximate sine of `x` in radians.
+pub fn sin(x: Real) -> Real {
+    if !x.is_finite() {
+        return Real::NAN;
+    }
+    // Reduce to [-pi, pi], then fold onto [-pi/2, pi/2] where sin is odd and monotonic
+    let mut r = x - 2.0 * PI * floor(x * FRAC_1_2PI + 0.5, 0.0);
+    if r > FRAC_PI_2 {
+        r = PI - r;
+    } else if r < -FRAC_PI_2 {
+        r = -PI - r;
+    }
+    // Taylor series to r^7, whose remainder is below 1.6e-4 at pi/2
+    let r2 = r * r;
+    r * (1.0 - r2 / 6.0 * (1.0 - r2 / 20.0 * (1.0 - r2 / 42.0)))
+}
+
+/// Approximate cosine of `x` in radians.
+pub fn cos(x: Real) -> Real {
+    sin(x + FRAC_PI_2)
+}
+
+/// Approximate exponential of `x`.
+pub fn exp(x: Real) -> Real {
+    if x.is_nan() {
+        return x;
+    }
+    // x = k ln 2 + r with |r| <= ln 2 / 2, so e^x = 2^k e^r
+    let k = floor(x * LOG2_E + 0.5, 0.0);
+    if k > MAX_EXPONENT as Real {
+        return Real::INFINITY;
+    }
+    if k < MIN_EXPONENT as Real {
+        return 0.0;
+    }
+    let r = x - k * LN_2;
+    // Taylor series to r^5, whose remainder is below 3e-6 relative for |r| <= 0.35
+    let e_r = 1.0 + r * (1.0 + r / 2.0 * (1.0 + r / 3.0 * (1.0 + r / 4.0 * (1.0 + r / 5.0))));
+    e_r * pow2(k as i32)
+}
+
+/// Approximate natural logarithm of `x`.
+pub fn ln(x: Real) -> Real {
+    if x.is_nan() || x < 0.0 {
+        return Real::NAN;
+    }
+    if x == 0.0 {
+        return Real::NEG_INFINITY;
+    }
+    if x == Real::INFINITY {
+        return x;
+    }
+    // x = 2^e m with m in [sqrt(2)/2, sqrt(2)]
+    let (mut e, mut m) = decompose(x);
+    if m > SQRT_2 {
+        m /= 2.0;
+        e += 1;
+    }
+    // ln m = 2 atanh(s) with |s| <= 0.172, by its series to s^7
+    let s = (m - 1.0) / (m + 1.0);
+    let s2 = s * s;
+    let ln_m = 2.0 * s * (1.0 + s2 * (1.0 / 3.0 + s2 * (1.0 / 5.0 + s2 / 7.0)));
+    e as Real * LN_2 + ln_m
+}
+
+/// Approximate base-10 logarithm of `x`.
+pub fn log(x: Real) -> Real {
+    ln(x) * LOG10_E
+}
+
+#[cfg(not(feature = "f32"))]
+const MAX_EXPONENT: i32 = 1023;
+#[cfg(not(feature = "f32"))]
+const MIN_EXPONENT: i32 = -1022;
+#[cfg(feature = "f32")]
+const MAX_EXPONENT: i32 = 127;
+#[cfg(feature = "f32")]
+const MIN_EXPONENT: i32 = -126;
+
+/// Returns 2^k for `k` within the normal exponent range.
+#[cfg(not(feature = "f32"))]
+fn pow2(k: i32) -> Real {
+    Real::from_bits(((k + 1023) as u64) << 52)
+}
+
+/// Returns 2^k for `k` within the normal exponent range.
+#[cfg(feature = "f32")]
+fn pow2(k: i32) -> Real {
+    Real::from_bits(((k + 127) as u32) << 23)
+}
+
+/// Splits a positive finite `x` into `e` and `m` in [1, 2) with `x = 2^e m`.
+#[cfg(not(feature = "f32"))]
+fn decompose(x: Real) -> (i32, Real) {
+    let (x, offset) = if x < Real::MIN_POSITIVE {
+        (x * pow2(54), -54)
+    } else {
+        (x, 0)
+    };
+    let bits = x.to_bits();
+    let e = ((bits >> 52) & 0x7ff) as i32 - 1023;
+    let m = Real::from_bits((bits & ((1 << 52) - 1)) | (1023 << 52));
+    (e + offset, m)
+}
+
+/// Splits a positive finite `x` into `e` and `m` in [1, 2) with `x = 2^e m`.
+#[cfg(feature = "f32")]
+fn decompose(x: Real) -> (i32, Real) {
+    let (x, offset) = if x < Real::MIN_POSITIVE {
+        (x * pow2(24), -24)
+    } else {
+        (x, 0)
+    };
+    let bits = x.to_bits();
+    let e = ((bits >> 23) & 0xff) as i32 - 127;
+    let m = Real::from_bits((bits & ((1 << 23) - 1)) | (127 << 23));
+    (e + offset, m)
+}
+
+/// Replaces the functions `names` of `ctx` (any of `sin`, `cos`, `exp`, `ln` and `log`)
+/// with their approximations, as pure functions.
+///
+/// `log` keeps its two-argument form `log(x, base)`. Fails with
+/// [`ExprError::UnknownFunction`] for a name without approximation, before changing
+/// anything.
+pub fn register(ctx: &mut EvalContext, names: &[&str]) -> Result<(), ExprError> {
+    if let Some(name) = names
+        .iter()
+        .find(|name| !matches!(**name, "sin" | "cos" | "exp" | "ln" | "log"))
+    {
+        return Err(ExprError::UnknownFunction {
+            name: name.to_string(),
+        });
+    }
+    for &name in names {
+        match name {
+            "sin" => ctx.register_native_function("sin", 1, |args| sin(args[0]))?,
+            "cos" => ctx.register_native_function("cos", 1, |args| cos(args[0]))?,
+            "exp" => ctx.register_native_function("exp", 1, |args| exp(args[0]))?,
+            "ln" => ctx.register_native_function("ln", 1, |args| ln(args[0]))?,
+            _ => ctx.register_variadic_function("log", 1, |args| match args {
+                [x] => log(*x),
+                [x, base] => ln(*x) / ln(*base),
+                _ => Real::NAN,
+            })?,
+        }
+        ctx.set_function_pure(name, true)?;
+    }
+    Ok(())
+}
+
+/// Registers the builtins `fast_sin`, `fast_cos`, `fast_exp`, `fast_ln` and `fast_log`.
+pub(crate) fn register_builtins(ctx: &mut EvalContext) {
+    let _ = ctx.register_native_function("fast_sin", 1, |args| sin(args[0]));
+    let _ = ctx.register_native_function("fast_cos", 1, |args| cos(args[0]));
+    let _ = ctx.register_native_function("fast_exp", 1, |args| exp(args[0]));
+    let _ = ctx.register_native_function("fast_ln", 1, |args| ln(args[0]));
+    let _ = ctx.register_native_function("fast_log", 1, |args| log(args[0]));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::interp;
+    use alloc::rc::Rc;
+
+    #[test]
+    fn test_fast_math_error_bounds() {
+        let samples =
+            |lo: Real, hi: Real| (0..=20_000).map(move |i| lo + (hi - lo) * i as Real / 20_000.0);
+        for x in samples(-1e4, 1e4).chain(samples(-7.0, 7.0)) {
+            assert!((sin(x) - x.sin()).abs() <= SIN_COS_MAX_ERROR, "sin({x})");
+            assert!((cos(x) - x.cos()).abs() <= SIN_COS_MAX_ERROR, "cos({x})");
+        }
+        for x in samples(-700.0, 700.0).chain(samples(-3.0, 3.0)) {
+            let relative = (exp(x) - x.exp()).abs() / x.exp();
+            assert!(relative <= EXP_MAX_RELATIVE_ERROR, "exp({x})");
+        }
+        for x in samples(1e-3, 10.0)
+            .chain(samples(1.0, 1e300))
+            .chain([1e-310, 5e-324, 1.0])
+        {
+            assert!((ln(x) - x.ln()).abs() <= LN_MAX_ERROR, "ln({x})");
+            assert!((log(x) - x.log10()).abs() <= LN_MAX_ERROR, "log({x})");
+        }
+
+        assert!(sin(Real::INFINITY).is_nan() && ln(-1.0).is_nan() && exp(Real::NAN).is_nan());
+        assert_eq!((exp(1e3), exp(-1e3)), (Real::INFINITY, 0.0));
+        assert_eq!(
+            (ln(0.0), ln(Real::INFINITY)),
+            (Real::NEG_INFINITY, Real::INFINITY)
+        );
+
+        // Per context, the standard names are replaced; the fast_ builtins always exist
+        let mut ctx = EvalContext::new();
+        assert!(matches!(
+            register(&mut ctx, &["sin", "tan"]),
+            Err(ExprError::UnknownFunction { .. })
+        ));
+        assert!(ctx.is_function_pure("sin"));
+        register(&mut ctx, &["sin", "log"]).unwrap();
+        assert!(ctx.is_function_pure("log"));
+        let ctx = Rc::new(ctx);
+        let eval = |expr: &str| interp(expr, Some(ctx.clone())).unwrap();
+        assert_eq!(eval("sin(1)"), sin(1.0));
+        assert_eq!(eval("cos(1)"), (1.0 as Real).cos());
+        assert_eq!(eval("log(100, 10)"), ln(100.0) / ln(10.0));
+        assert_eq!(eval("fast_cos(1) + fast_ln(2)"), cos(1.0) + ln(2.0));
+        assert_eq!(eval("fast_exp(1) + fast_log(1000)"), exp(1.0) + log(1000.0));
+    }
+}
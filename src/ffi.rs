@@ -14,6 +14,30 @@
 //! - Manually manage arena lifetime
 //!
 //!
+//! ## Compiled Expressions (Long-Lived Handles)
+//! - Create a context once with `exp_rs_ctx_new()` and keep it for the program lifetime
+//! - Parse each expression once with `exp_rs_expr_compile()`
+//! - Evaluate repeatedly with `exp_rs_expr_eval()`, updating inputs with
+//!   `exp_rs_ctx_set_variable()`
+//! - Release each handle exactly once with `exp_rs_expr_free()` / `exp_rs_ctx_free()`
+//!
+//! ```c
+//! ExprContext* ctx = exp_rs_ctx_new();
+//! ExprCompiled* expr = NULL;
+//! ExprResult r = exp_rs_expr_compile("gain * x + offset", &expr);
+//! if (r.status != 0) { /* r.error holds the message */ }
+//!
+//! exp_rs_ctx_set_variable(ctx, "gain", 2.0);
+//! exp_rs_ctx_set_variable(ctx, "offset", 0.5);
+//! for (;;) {
+//!     exp_rs_ctx_set_variable(ctx, "x", read_sensor());
+//!     Real y = exp_rs_expr_eval(expr, ctx).value;
+//! }
+//!
+//! exp_rs_expr_free(expr);
+//! exp_rs_ctx_free(ctx);
+//! ```
+//!
 //! ## Function Support
 //!
 //! The FFI supports two types of functions:
@@ -327,9 +351,18 @@ mod system_allocator {
     // Wrapper around System allocator to track allocations
     pub struct TrackingSystemHeap;
 
+    #[cfg(test)]
+    std::thread_local! {
+        // Allocations made by the current thread while a test counts them
+        pub static COUNTED_ALLOCATIONS: core::cell::Cell<Option<usize>> =
+            const { core::cell::Cell::new(None) };
+    }
+
     unsafe impl GlobalAlloc for TrackingSystemHeap {
         #[track_caller]
         unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            #[cfg(test)]
+            let _ = COUNTED_ALLOCATIONS.try_with(|count| count.set(count.get().map(|n| n + 1)));
             let ptr = unsafe { System.alloc(layout) };
             #[cfg(feature = "alloc_tracking")]
             if !ptr.is_null() {
@@ -675,6 +708,16 @@ pub const FFI_ERROR_INVALID_UTF8: i32 = -2;
 pub const FFI_ERROR_NO_ARENA_AVAILABLE: i32 = -3;
 pub const FFI_ERROR_CANNOT_GET_MUTABLE_ACCESS: i32 = -4;
 pub const FFI_ERROR_INVALID_POINTER: i32 = -5;
+pub const FFI_ERROR_INVALID_ARGUMENT: i32 = -6;
+
+/// Notations accepted by expr_format_value()
+pub const EXPR_NOTATION_FIXED: u32 = 0;
+pub const EXPR_NOTATION_SCIENTIFIC: u32 = 1;
+pub const EXPR_NOTATION_ENGINEERING: u32 = 2;
+
+/// Flags accepted by expr_format_value()
+pub const EXPR_FORMAT_TRIM_ZEROS: u32 = 1;
+pub const EXPR_FORMAT_ASCII: u32 = 2;
 
 // ============================================================================
 // Opaque Types with Better Names
@@ -692,6 +735,14 @@ pub struct ExprBatch {
     _private: [u8; 0],
 }
 
+/// Opaque type for a compiled expression
+///
+/// Created by exp_rs_expr_compile() and freed with exp_rs_expr_free().
+#[repr(C)]
+pub struct ExprCompiled {
+    _private: [u8; 0],
+}
+
 /// Opaque type for memory arena
 #[repr(C)]
 pub struct ExprArena {
@@ -705,6 +756,13 @@ pub struct ExprArena {
 /// Native function signature
 pub type NativeFunc = extern "C" fn(args: *const Real, n_args: usize) -> Real;
 
+/// Clock callback signature, returning the current time in seconds
+pub type ClockFunc = extern "C" fn() -> Real;
+
+/// Watchdog callback signature, returning 0 to continue the evaluation and any other
+/// value to abort it
+pub type WatchdogFunc = extern "C" fn() -> i32;
+
 // ============================================================================
 // Context Management
 // ============================================================================
@@ -817,6 +875,123 @@ pub extern "C" fn expr_context_get_native_function_name(
     }
 }
 
+/// Remove a native function from the context
+///
+/// Built-in functions can be removed as well, e.g. to take a capability away
+/// when the module providing it unloads.
+///
+/// # Returns
+/// 1 if the function was removed, 0 if it was not registered, negative error
+/// code on failure
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn expr_context_unregister_function(
+    ctx: *mut ExprContext,
+    name: *const c_char,
+) -> i32 {
+    if ctx.is_null() || name.is_null() {
+        return FFI_ERROR_NULL_POINTER;
+    }
+
+    let ctx_handle = unsafe { &mut *(ctx as *mut alloc::rc::Rc<EvalContext>) };
+    let name_str = match unsafe { CStr::from_ptr(name) }.to_str() {
+        Ok(s) => s,
+        Err(_) => return FFI_ERROR_INVALID_UTF8,
+    };
+
+    match alloc::rc::Rc::get_mut(ctx_handle) {
+        Some(ctx_mut) => ctx_mut.unregister_function(name_str) as i32,
+        None => FFI_ERROR_CANNOT_GET_MUTABLE_ACCESS,
+    }
+}
+
+/// Get the arity of a function
+///
+/// # Parameters
+/// - `ctx`: The context
+/// - `name`: Function name (must be valid UTF-8)
+/// - `arity`: Receives the number of arguments, or the minimum number if the
+///   function is variadic
+/// - `variadic`: Receives whether the function accepts more than `arity`
+///   arguments (may be NULL)
+///
+/// # Returns
+/// 0 on success, negative FFI error code, or the `UnknownFunction` error code if
+/// no function of that name is registered
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn expr_context_function_signature(
+    ctx: *const ExprContext,
+    name: *const c_char,
+    arity: *mut usize,
+    variadic: *mut bool,
+) -> i32 {
+    if ctx.is_null() || name.is_null() || arity.is_null() {
+        return FFI_ERROR_NULL_POINTER;
+    }
+
+    let ctx = unsafe { &*(ctx as *const alloc::rc::Rc<EvalContext>) };
+    let name_str = match unsafe { CStr::from_ptr(name) }.to_str() {
+        Ok(s) => s,
+        Err(_) => return FFI_ERROR_INVALID_UTF8,
+    };
+
+    match ctx.function_signature(name_str) {
+        Some(signature) => {
+            unsafe {
+                *arity = signature.arity;
+                if !variadic.is_null() {
+                    *variadic = signature.variadic;
+                }
+            }
+            0
+        }
+        None => crate::error::ExprError::UnknownFunction {
+            name: name_str.to_string(),
+        }
+        .error_code(),
+    }
+}
+
+/// Get the count of variables in a context, including its parents
+#[unsafe(no_mangle)]
+pub extern "C" fn expr_context_variable_count(ctx: *const ExprContext) -> usize {
+    if ctx.is_null() {
+        return 0;
+    }
+
+    let ctx = unsafe { &*(ctx as *const alloc::rc::Rc<EvalContext>) };
+    ctx.list_variables().len()
+}
+
+/// Get a variable name by index, in the sorted order of the variable names
+/// Returns the length of the name, or 0 if index is out of bounds
+/// If buffer is NULL, just returns the length needed
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn expr_context_get_variable_name(
+    ctx: *const ExprContext,
+    index: usize,
+    buffer: *mut u8,
+    buffer_size: usize,
+) -> usize {
+    if ctx.is_null() {
+        return 0;
+    }
+
+    let ctx = unsafe { &*(ctx as *const alloc::rc::Rc<EvalContext>) };
+    let variables = ctx.list_variables();
+    let Some(name) = variables.get(index) else {
+        return 0;
+    };
+
+    if !buffer.is_null() {
+        let copy_len = core::cmp::min(name.len(), buffer_size);
+        unsafe { core::ptr::copy_nonoverlapping(name.as_ptr(), buffer, copy_len) };
+    }
+    name.len()
+}
+
 /// Add a native function to the context
 ///
 /// # Parameters
@@ -866,6 +1041,250 @@ pub extern "C" fn expr_context_add_function(
     }
 }
 
+/// Expose caller-owned memory as an array without copying it
+///
+/// Expressions evaluated with this context read `name[i]` directly from
+/// `data[i]`, so a buffer that is refilled in place (e.g. by DMA) does not have
+/// to be registered again. Calling this again with the same name re-points the
+/// array.
+///
+/// # Parameters
+/// - `ctx`: The context
+/// - `name`: Array name (must be valid UTF-8)
+/// - `data`: Pointer to the first value (may be NULL if `len` is 0)
+/// - `len`: Number of values
+///
+/// # Returns
+/// 0 on success, negative FFI error code or positive ExprError code on failure
+///
+/// # Safety
+/// - `data` must point to `len` values that stay valid until the view is
+///   replaced or the context is freed
+/// - The buffer must not be written while an evaluation is running
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn expr_context_set_array_view(
+    ctx: *mut ExprContext,
+    name: *const c_char,
+    data: *const Real,
+    len: usize,
+) -> i32 {
+    if ctx.is_null() || name.is_null() || (data.is_null() && len > 0) {
+        return FFI_ERROR_NULL_POINTER;
+    }
+
+    let ctx_handle = unsafe { &mut *(ctx as *mut alloc::rc::Rc<EvalContext>) };
+    let name_str = match unsafe { CStr::from_ptr(name) }.to_str() {
+        Ok(s) => s,
+        Err(_) => return FFI_ERROR_INVALID_UTF8,
+    };
+
+    let view = unsafe { crate::context::ArrayView::from_raw_parts(data, len) };
+    match alloc::rc::Rc::get_mut(ctx_handle) {
+        Some(ctx_mut) => match ctx_mut.set_array_view(name_str, view) {
+            Ok(()) => 0,
+            Err(e) => e.error_code(),
+        },
+        None => FFI_ERROR_CANNOT_GET_MUTABLE_ACCESS,
+    }
+}
+
+/// Set the evaluation depth limit of a context
+///
+/// Evaluations using this context fail with a recursion limit error when they
+/// nest deeper than `max_depth`. Each context has its own limit, so tasks using
+/// different contexts do not affect each other.
+///
+/// # Parameters
+/// - `ctx`: The context
+/// - `max_depth`: Maximum depth, or 0 to use the compile-time default
+///
+/// # Returns
+/// 0 on success, negative error code on failure
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn expr_context_set_max_depth(ctx: *mut ExprContext, max_depth: usize) -> i32 {
+    if ctx.is_null() {
+        return FFI_ERROR_NULL_POINTER;
+    }
+
+    let ctx_handle = unsafe { &mut *(ctx as *mut alloc::rc::Rc<EvalContext>) };
+    match alloc::rc::Rc::get_mut(ctx_handle) {
+        Some(ctx_mut) => {
+            ctx_mut.set_max_eval_depth((max_depth > 0).then_some(max_depth));
+            0
+        }
+        None => FFI_ERROR_CANNOT_GET_MUTABLE_ACCESS,
+    }
+}
+
+/// Let evaluations nest past the depth limit by spilling to the heap
+///
+/// Instead of failing at the depth limit, evaluations using this context move
+/// their oldest pending steps to a heap-allocated stack and fail only beyond
+/// `limit` steps in total. Use it for deeply nested machine-generated
+/// expressions on targets with a heap.
+///
+/// # Parameters
+/// - `ctx`: The context
+/// - `limit`: Maximum total depth, or 0 to fail at the depth limit (the default)
+///
+/// # Returns
+/// 0 on success, negative error code on failure
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn expr_context_set_stack_spill(ctx: *mut ExprContext, limit: usize) -> i32 {
+    if ctx.is_null() {
+        return FFI_ERROR_NULL_POINTER;
+    }
+
+    let ctx_handle = unsafe { &mut *(ctx as *mut alloc::rc::Rc<EvalContext>) };
+    match alloc::rc::Rc::get_mut(ctx_handle) {
+        Some(ctx_mut) => {
+            ctx_mut.set_stack_spill((limit > 0).then_some(limit));
+            0
+        }
+        None => FFI_ERROR_CANNOT_GET_MUTABLE_ACCESS,
+    }
+}
+
+/// Declare variables that expressions may reference
+///
+/// Declared names need not be set. With strict declarations enabled (see
+/// expr_context_set_strict_declarations()), expr_batch_validate() rejects
+/// references to names that are neither declared nor defined, so typos are
+/// reported when expressions are loaded rather than during evaluation.
+///
+/// # Parameters
+/// - `ctx`: The context
+/// - `names`: Array of `count` null-terminated names
+/// - `count`: Number of names
+///
+/// # Returns
+/// 0 on success, negative FFI error code or positive ExprError code on failure
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn expr_context_declare_variables(
+    ctx: *mut ExprContext,
+    names: *const *const c_char,
+    count: usize,
+) -> i32 {
+    if ctx.is_null() || (names.is_null() && count > 0) {
+        return FFI_ERROR_NULL_POINTER;
+    }
+
+    let ctx_handle = unsafe { &mut *(ctx as *mut alloc::rc::Rc<EvalContext>) };
+    let mut name_strs = Vec::with_capacity(count);
+    for i in 0..count {
+        let name = unsafe { *names.add(i) };
+        if name.is_null() {
+            return FFI_ERROR_NULL_POINTER;
+        }
+        match unsafe { CStr::from_ptr(name) }.to_str() {
+            Ok(s) => name_strs.push(s),
+            Err(_) => return FFI_ERROR_INVALID_UTF8,
+        }
+    }
+
+    match alloc::rc::Rc::get_mut(ctx_handle) {
+        Some(ctx_mut) => match ctx_mut.declare_variables(name_strs) {
+            Ok(()) => 0,
+            Err(e) => e.error_code(),
+        },
+        None => FFI_ERROR_CANNOT_GET_MUTABLE_ACCESS,
+    }
+}
+
+/// Require expressions to reference only declared names
+///
+/// # Parameters
+/// - `ctx`: The context
+/// - `strict`: Whether expr_batch_validate() checks references against the
+///   declarations of expr_context_declare_variables()
+///
+/// # Returns
+/// 0 on success, negative error code on failure
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn expr_context_set_strict_declarations(ctx: *mut ExprContext, strict: bool) -> i32 {
+    if ctx.is_null() {
+        return FFI_ERROR_NULL_POINTER;
+    }
+
+    let ctx_handle = unsafe { &mut *(ctx as *mut alloc::rc::Rc<EvalContext>) };
+    match alloc::rc::Rc::get_mut(ctx_handle) {
+        Some(ctx_mut) => {
+            ctx_mut.set_strict_declarations(strict);
+            0
+        }
+        None => FFI_ERROR_CANNOT_GET_MUTABLE_ACCESS,
+    }
+}
+
+/// Set the clock used by the `now()`, `dt()` and `elapsed(name)` functions
+///
+/// # Parameters
+/// - `ctx`: The context
+/// - `clock`: Function returning the current time in seconds, e.g. a cycle
+///   counter divided by the core frequency
+///
+/// # Returns
+/// 0 on success, negative FFI error code or positive ExprError code on failure
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn expr_context_set_clock(ctx: *mut ExprContext, clock: ClockFunc) -> i32 {
+    if ctx.is_null() {
+        return FFI_ERROR_NULL_POINTER;
+    }
+
+    let ctx_handle = unsafe { &mut *(ctx as *mut alloc::rc::Rc<EvalContext>) };
+    match alloc::rc::Rc::get_mut(ctx_handle) {
+        Some(ctx_mut) => match ctx_mut.set_clock(move || clock()) {
+            Ok(()) => 0,
+            Err(e) => e.error_code(),
+        },
+        None => FFI_ERROR_CANNOT_GET_MUTABLE_ACCESS,
+    }
+}
+
+/// Set a callback run every `interval` evaluated AST nodes, e.g. to kick a hardware
+/// watchdog or poll an abort flag during long batch evaluations
+///
+/// # Parameters
+/// - `ctx`: The context
+/// - `interval`: Number of nodes between calls (0 is treated as 1)
+/// - `watchdog`: Function returning 0 to continue, or non-zero to abort the evaluation
+///   with the `Aborted` error (code 21)
+///
+/// # Returns
+/// 0 on success, negative FFI error code on failure
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn expr_context_set_watchdog(
+    ctx: *mut ExprContext,
+    interval: usize,
+    watchdog: WatchdogFunc,
+) -> i32 {
+    if ctx.is_null() {
+        return FFI_ERROR_NULL_POINTER;
+    }
+
+    let ctx_handle = unsafe { &mut *(ctx as *mut alloc::rc::Rc<EvalContext>) };
+    match alloc::rc::Rc::get_mut(ctx_handle) {
+        Some(ctx_mut) => {
+            ctx_mut.set_watchdog(interval, move || {
+                if watchdog() == 0 {
+                    core::ops::ControlFlow::Continue(())
+                } else {
+                    core::ops::ControlFlow::Break(())
+                }
+            });
+            0
+        }
+        None => FFI_ERROR_CANNOT_GET_MUTABLE_ACCESS,
+    }
+}
+
 /// Add an expression function to a batch
 ///
 /// Expression functions are mathematical expressions that can call other functions.
@@ -1326,52 +1745,296 @@ pub extern "C" fn expr_batch_evaluate(batch: *mut ExprBatch, ctx: *mut ExprConte
     }
 }
 
-/// Get the result of an expression
+/// Prepare a batch for evaluation without heap allocation
+///
+/// Reserves the evaluator stacks and scratch buffers for the batch's expressions and
+/// evaluates it once, which also parses the bodies of its expression functions. After
+/// this returns 0, expr_batch_set_variable(), expr_batch_evaluate() with the same
+/// non-NULL `ctx` and expr_batch_get_result() perform no heap allocation and do not
+/// grow the arena, for use on devices that run for a long time with a fragmented heap.
+///
+/// The guarantee holds until expressions, variables or functions are added, the context
+/// is modified, or an evaluation fails (error messages are allocated). Call
+/// expr_batch_prepare() again after changing the batch. Without the `std` feature only.
 ///
 /// # Parameters
 /// - `batch`: The batch
-/// - `index`: Expression index from expr_batch_add_expression()
+/// - `ctx`: The context later evaluations use
 ///
 /// # Returns
-/// Result value, or NaN if index is invalid
+/// 0 on success, negative error code on failure
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[unsafe(no_mangle)]
-pub extern "C" fn expr_batch_get_result(batch: *const ExprBatch, index: usize) -> Real {
-    if batch.is_null() {
-        return Real::NAN;
+pub extern "C" fn expr_batch_prepare(batch: *mut ExprBatch, ctx: *mut ExprContext) -> i32 {
+    if batch.is_null() || ctx.is_null() {
+        return FFI_ERROR_NULL_POINTER;
     }
-
     let wrapper = unsafe { &*(batch as *const BatchWithArena) };
-    let builder = unsafe { &*wrapper.batch };
-    builder.get_result(index).unwrap_or(Real::NAN)
+    if wrapper.magic != BATCH_MAGIC {
+        return FFI_ERROR_INVALID_POINTER;
+    }
+    let builder = unsafe { &mut *wrapper.batch };
+    let eval_ctx = unsafe { &*(ctx as *const alloc::rc::Rc<EvalContext>) };
+
+    match builder.prepare().and_then(|_| builder.eval(eval_ctx)) {
+        Ok(()) => 0,
+        Err(e) => e.error_code(),
+    }
 }
 
-/// Get the high water mark of arena memory usage for a batch
+/// Check the calls and references of a batch without evaluating it
+///
+/// Checks argument counts and function validators and, if `ctx` has strict
+/// declarations enabled, that every referenced name is a batch variable, a
+/// named expression or declared in `ctx`. An undeclared name gives the
+/// unknown variable error code.
 ///
 /// # Parameters
 /// - `batch`: The batch
+/// - `ctx`: The context later evaluations use
 ///
 /// # Returns
-/// Number of bytes currently allocated in the batch's arena.
-/// This represents the maximum memory usage of the arena.
+/// 0 on success, negative FFI error code or positive ExprError code on failure
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[unsafe(no_mangle)]
-pub extern "C" fn expr_batch_arena_bytes(batch: *const ExprBatch) -> usize {
-    if batch.is_null() {
-        return 0;
+pub extern "C" fn expr_batch_validate(batch: *const ExprBatch, ctx: *const ExprContext) -> i32 {
+    if batch.is_null() || ctx.is_null() {
+        return FFI_ERROR_NULL_POINTER;
     }
-
     let wrapper = unsafe { &*(batch as *const BatchWithArena) };
+    if wrapper.magic != BATCH_MAGIC {
+        return FFI_ERROR_INVALID_POINTER;
+    }
     let builder = unsafe { &*wrapper.batch };
-    builder.arena_allocated_bytes()
+    let eval_ctx = unsafe { &*(ctx as *const alloc::rc::Rc<EvalContext>) };
+
+    match builder.validate(eval_ctx) {
+        Ok(()) => 0,
+        Err(e) => e.error_code(),
+    }
 }
 
-/// Evaluate all expressions in the batch with detailed error reporting
+/// Get the result of an expression
 ///
 /// # Parameters
 /// - `batch`: The batch
-/// - `ctx`: Optional context with functions (can be NULL)
+/// - `index`: Expression index from expr_batch_add_expression()
 ///
 /// # Returns
-/// ExprResult with status 0 on success, or error details on failure
+/// Result value, or NaN if index is invalid
+#[unsafe(no_mangle)]
+pub extern "C" fn expr_batch_get_result(batch: *const ExprBatch, index: usize) -> Real {
+    if batch.is_null() {
+        return Real::NAN;
+    }
+
+    let wrapper = unsafe { &*(batch as *const BatchWithArena) };
+    let builder = unsafe { &*wrapper.batch };
+    builder.get_result(index).unwrap_or(Real::NAN)
+}
+
+/// Record which inputs each expression reads when the batch is evaluated
+///
+/// Recording allocates the input names, so it is off by default. Only inputs
+/// actually read are recorded: operands skipped by `?:`, `&&` and `||` are not.
+///
+/// # Parameters
+/// - `batch`: The batch
+/// - `record`: Whether to record inputs
+///
+/// # Returns
+/// 0 on success, negative error code on failure
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn expr_batch_set_record_inputs(batch: *mut ExprBatch, record: bool) -> i32 {
+    if batch.is_null() {
+        return FFI_ERROR_NULL_POINTER;
+    }
+    let wrapper = unsafe { &*(batch as *const BatchWithArena) };
+    if wrapper.magic != BATCH_MAGIC {
+        return FFI_ERROR_INVALID_POINTER;
+    }
+    let builder = unsafe { &mut *wrapper.batch };
+    builder.set_record_inputs(record);
+    0
+}
+
+/// Evaluation statistics of one expression of a batch, in ticks of the clock passed
+/// to expr_batch_enable_stats
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExprStats {
+    /// Evaluations, including failed ones
+    pub evaluations: u64,
+    /// Evaluations that failed
+    pub errors: u64,
+    /// Fewest ticks an evaluation took
+    pub min_ticks: u32,
+    /// Most ticks an evaluation took
+    pub max_ticks: u32,
+    /// Ticks taken by all evaluations together
+    pub total_ticks: u64,
+    /// Error code of the latest failed evaluation, or 0 if none failed
+    pub last_error: i32,
+}
+
+/// Collect evaluation statistics of each expression of a batch
+///
+/// # Parameters
+/// - `batch`: The batch
+/// - `clock`: Function returning a free-running 32-bit counter that counts up, such as
+///   the DWT cycle counter; wrap-around is handled. NULL disables statistics.
+///
+/// # Returns
+/// 0 on success, negative error code on failure
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn expr_batch_enable_stats(
+    batch: *mut ExprBatch,
+    clock: Option<extern "C" fn() -> u32>,
+) -> i32 {
+    if batch.is_null() {
+        return FFI_ERROR_NULL_POINTER;
+    }
+    let wrapper = unsafe { &*(batch as *const BatchWithArena) };
+    if wrapper.magic != BATCH_MAGIC {
+        return FFI_ERROR_INVALID_POINTER;
+    }
+    let builder = unsafe { &mut *wrapper.batch };
+    match clock {
+        Some(clock) => builder.enable_stats(move || clock()),
+        None => builder.disable_stats(),
+    }
+    0
+}
+
+/// Get the evaluation statistics of an expression of a batch
+///
+/// # Parameters
+/// - `batch`: The batch
+/// - `index`: Expression index from expr_batch_add_expression()
+/// - `stats`: Receives the statistics
+///
+/// # Returns
+/// 0 on success, FFI_ERROR_INVALID_ARGUMENT if statistics are disabled or the index
+/// is invalid, or another negative error code
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn expr_batch_get_stats(
+    batch: *const ExprBatch,
+    index: usize,
+    stats: *mut ExprStats,
+) -> i32 {
+    if batch.is_null() || stats.is_null() {
+        return FFI_ERROR_NULL_POINTER;
+    }
+    let wrapper = unsafe { &*(batch as *const BatchWithArena) };
+    if wrapper.magic != BATCH_MAGIC {
+        return FFI_ERROR_INVALID_POINTER;
+    }
+    let builder = unsafe { &*wrapper.batch };
+    let Some(collected) = builder.expression_stats(index) else {
+        return FFI_ERROR_INVALID_ARGUMENT;
+    };
+    unsafe {
+        *stats = ExprStats {
+            evaluations: collected.evaluations,
+            errors: collected.errors,
+            min_ticks: collected.min_ticks,
+            max_ticks: collected.max_ticks,
+            total_ticks: collected.total_ticks,
+            last_error: collected
+                .last_error
+                .as_ref()
+                .map_or(0, crate::error::ExprError::error_code),
+        };
+    }
+    0
+}
+
+/// Get the number of inputs the last evaluation of an expression read
+///
+/// # Parameters
+/// - `batch`: The batch
+/// - `index`: Expression index from expr_batch_add_expression()
+///
+/// # Returns
+/// Number of inputs, or 0 if recording is disabled or index is invalid
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn expr_batch_input_count(batch: *const ExprBatch, index: usize) -> usize {
+    if batch.is_null() {
+        return 0;
+    }
+    let wrapper = unsafe { &*(batch as *const BatchWithArena) };
+    if wrapper.magic != BATCH_MAGIC {
+        return 0;
+    }
+    let builder = unsafe { &*wrapper.batch };
+    builder.inputs_read(index).map_or(0, |inputs| inputs.len())
+}
+
+/// Get the name of an input the last evaluation of an expression read
+/// Returns the length of the name, or 0 if either index is out of bounds
+/// If buffer is NULL, just returns the length needed
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn expr_batch_get_input_name(
+    batch: *const ExprBatch,
+    index: usize,
+    input: usize,
+    buffer: *mut u8,
+    buffer_size: usize,
+) -> usize {
+    if batch.is_null() {
+        return 0;
+    }
+    let wrapper = unsafe { &*(batch as *const BatchWithArena) };
+    if wrapper.magic != BATCH_MAGIC {
+        return 0;
+    }
+    let builder = unsafe { &*wrapper.batch };
+    let Some(name) = builder
+        .inputs_read(index)
+        .and_then(|inputs| inputs.get(input))
+    else {
+        return 0;
+    };
+
+    if !buffer.is_null() {
+        let copy_len = core::cmp::min(name.len(), buffer_size);
+        unsafe { core::ptr::copy_nonoverlapping(name.as_ptr(), buffer, copy_len) };
+    }
+    name.len()
+}
+
+/// Get the high water mark of arena memory usage for a batch
+///
+/// # Parameters
+/// - `batch`: The batch
+///
+/// # Returns
+/// Number of bytes currently allocated in the batch's arena.
+/// This represents the maximum memory usage of the arena.
+#[unsafe(no_mangle)]
+pub extern "C" fn expr_batch_arena_bytes(batch: *const ExprBatch) -> usize {
+    if batch.is_null() {
+        return 0;
+    }
+
+    let wrapper = unsafe { &*(batch as *const BatchWithArena) };
+    let builder = unsafe { &*wrapper.batch };
+    builder.arena_allocated_bytes()
+}
+
+/// Evaluate all expressions in the batch with detailed error reporting
+///
+/// # Parameters
+/// - `batch`: The batch
+/// - `ctx`: Optional context with functions (can be NULL)
+///
+/// # Returns
+/// ExprResult with status 0 on success, or error details on failure
 #[unsafe(no_mangle)]
 pub extern "C" fn expr_batch_evaluate_ex(
     batch: *mut ExprBatch,
@@ -1399,6 +2062,468 @@ pub extern "C" fn expr_batch_evaluate_ex(
     }
 }
 
+// ============================================================================
+// Handle Lifecycle
+// ============================================================================
+//
+// Ownership rules for long-lived handles:
+// - Every handle returned by an `exp_rs_*_new`, `exp_rs_*_clone` or
+//   `exp_rs_expr_compile` call is owned by the caller and must be released
+//   exactly once with the matching `exp_rs_*_free` function.
+// - A cloned context is an independent copy. Functions and variables added to
+//   one afterwards are not visible through the other.
+// - A compiled expression does not borrow the context it is evaluated with, so
+//   the two can be created and freed in any order.
+// - Handles are not thread-safe; use each one from a single thread or guard it
+//   with a lock.
+
+/// Create a new evaluation context with the default functions registered
+///
+/// Equivalent to expr_context_new(); provided so the full handle lifecycle
+/// shares one naming scheme.
+///
+/// # Returns
+/// Pointer to new context, or NULL on allocation failure
+///
+/// # Safety
+/// The returned pointer must be freed with exp_rs_ctx_free()
+#[must_use]
+#[unsafe(no_mangle)]
+pub extern "C" fn exp_rs_ctx_new() -> *mut ExprContext {
+    expr_context_new()
+}
+
+/// Create an independent copy of a context
+///
+/// The copy starts with the same functions, constants and variables as `ctx`.
+/// Later changes to either context do not affect the other, so firmware can
+/// keep a configured template and hand out copies to separate tasks.
+///
+/// # Returns
+/// Pointer to the new context, or NULL if `ctx` is NULL
+///
+/// # Safety
+/// - `ctx` must be a live context from exp_rs_ctx_new() or expr_context_new()
+/// - The returned pointer must be freed with exp_rs_ctx_free()
+#[must_use]
+#[unsafe(no_mangle)]
+pub extern "C" fn exp_rs_ctx_clone(ctx: *const ExprContext) -> *mut ExprContext {
+    if ctx.is_null() {
+        return ptr::null_mut();
+    }
+
+    let ctx_rc = unsafe { &*(ctx as *const alloc::rc::Rc<EvalContext>) };
+    let copy = alloc::rc::Rc::new(EvalContext::clone(ctx_rc));
+    Box::into_raw(Box::new(copy)) as *mut ExprContext
+}
+
+/// Free a context handle
+///
+/// Passing NULL is a no-op.
+///
+/// # Safety
+/// - The pointer must have been created by exp_rs_ctx_new(), exp_rs_ctx_clone()
+///   or expr_context_new()
+/// - The pointer must not be used after calling this function
+#[unsafe(no_mangle)]
+pub extern "C" fn exp_rs_ctx_free(ctx: *mut ExprContext) {
+    expr_context_free(ctx);
+}
+
+/// Set a variable in a context
+///
+/// Variables set here are visible to every compiled expression evaluated
+/// with this context.
+///
+/// # Returns
+/// 0 on success, negative FFI error code or positive ExprError code on failure
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn exp_rs_ctx_set_variable(
+    ctx: *mut ExprContext,
+    name: *const c_char,
+    value: Real,
+) -> i32 {
+    if ctx.is_null() || name.is_null() {
+        return FFI_ERROR_NULL_POINTER;
+    }
+
+    let ctx_handle = unsafe { &mut *(ctx as *mut alloc::rc::Rc<EvalContext>) };
+    let name_str = match unsafe { CStr::from_ptr(name) }.to_str() {
+        Ok(s) => s,
+        Err(_) => return FFI_ERROR_INVALID_UTF8,
+    };
+
+    match alloc::rc::Rc::get_mut(ctx_handle) {
+        Some(ctx_mut) => match ctx_mut.set_parameter(name_str, value) {
+            Ok(_) => 0,
+            Err(e) => e.error_code(),
+        },
+        None => FFI_ERROR_CANNOT_GET_MUTABLE_ACCESS,
+    }
+}
+
+/// Set a variable in a context to a typed value
+fn set_typed_variable(
+    ctx: *mut ExprContext,
+    name: *const c_char,
+    value: crate::value::Value,
+) -> i32 {
+    if ctx.is_null() || name.is_null() {
+        return FFI_ERROR_NULL_POINTER;
+    }
+
+    let ctx_handle = unsafe { &mut *(ctx as *mut alloc::rc::Rc<EvalContext>) };
+    let name_str = match unsafe { CStr::from_ptr(name) }.to_str() {
+        Ok(s) => s,
+        Err(_) => return FFI_ERROR_INVALID_UTF8,
+    };
+
+    match alloc::rc::Rc::get_mut(ctx_handle) {
+        Some(ctx_mut) => match ctx_mut.set_value(name_str, value) {
+            Ok(_) => 0,
+            Err(e) => e.error_code(),
+        },
+        None => FFI_ERROR_CANNOT_GET_MUTABLE_ACCESS,
+    }
+}
+
+/// Set a variable in a context to a 64-bit integer
+///
+/// The integer is stored exactly, e.g. for frame counters or timestamps that
+/// exceed the precision of Real, and expressions read it converted to Real.
+///
+/// # Returns
+/// 0 on success, negative FFI error code or positive ExprError code on failure
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn exp_rs_ctx_set_int(
+    ctx: *mut ExprContext,
+    name: *const c_char,
+    value: i64,
+) -> i32 {
+    set_typed_variable(ctx, name, crate::value::Value::Int(value))
+}
+
+/// Set a variable in a context to a boolean, read by expressions as 1 or 0
+///
+/// # Returns
+/// 0 on success, negative FFI error code or positive ExprError code on failure
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn exp_rs_ctx_set_bool(
+    ctx: *mut ExprContext,
+    name: *const c_char,
+    value: bool,
+) -> i32 {
+    set_typed_variable(ctx, name, crate::value::Value::Bool(value))
+}
+
+/// Get a variable of a context as a 64-bit integer
+///
+/// Integers are returned exactly; booleans as 1 or 0, and Real variables if
+/// they hold an integer.
+///
+/// # Returns
+/// 0 on success, FFI_ERROR_INVALID_ARGUMENT if the variable is not an integer,
+/// negative FFI error code or positive ExprError code on other failures
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn exp_rs_ctx_get_int(
+    ctx: *const ExprContext,
+    name: *const c_char,
+    out: *mut i64,
+) -> i32 {
+    if ctx.is_null() || name.is_null() || out.is_null() {
+        return FFI_ERROR_NULL_POINTER;
+    }
+
+    let ctx = unsafe { &*(ctx as *const alloc::rc::Rc<EvalContext>) };
+    let name_str = match unsafe { CStr::from_ptr(name) }.to_str() {
+        Ok(s) => s,
+        Err(_) => return FFI_ERROR_INVALID_UTF8,
+    };
+
+    let Some(value) = ctx.get_value(name_str) else {
+        return crate::error::ExprError::UnknownVariable {
+            name: name_str.to_string(),
+        }
+        .error_code();
+    };
+    match value.to_int() {
+        Some(n) => {
+            unsafe { *out = n };
+            0
+        }
+        None => FFI_ERROR_INVALID_ARGUMENT,
+    }
+}
+
+/// Parse an expression once so it can be evaluated many times
+///
+/// The compiled expression owns its own arena; parsing does not depend on any
+/// context, so functions may be registered after compiling.
+///
+/// # Parameters
+/// - `expr`: Expression string (must be valid UTF-8)
+/// - `out`: Receives the compiled expression on success, NULL on failure
+///
+/// # Returns
+/// ExprResult with status 0 on success, or error details on failure
+///
+/// # Safety
+/// - `out` must point to writable storage for one pointer
+/// - A handle written to `out` must be freed with exp_rs_expr_free()
+#[must_use]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn exp_rs_expr_compile(
+    expr: *const c_char,
+    out: *mut *mut ExprCompiled,
+) -> ExprResult {
+    if expr.is_null() || out.is_null() {
+        return ExprResult::from_ffi_error(
+            FFI_ERROR_NULL_POINTER,
+            "Null pointer passed to exp_rs_expr_compile",
+        );
+    }
+    unsafe { *out = ptr::null_mut() };
+
+    let expr_str = match unsafe { CStr::from_ptr(expr) }.to_str() {
+        Ok(s) => s,
+        Err(_) => {
+            return ExprResult::from_ffi_error(
+                FFI_ERROR_INVALID_UTF8,
+                "Invalid UTF-8 in expression string",
+            );
+        }
+    };
+
+    let batch = expr_batch_new(0);
+    let wrapper = unsafe { &*(batch as *const BatchWithArena) };
+    if let Err(e) = unsafe { (*wrapper.batch).add_expression(expr_str) } {
+        expr_batch_free(batch);
+        return ExprResult::from_expr_error(e);
+    }
+
+    unsafe { *out = batch as *mut ExprCompiled };
+    ExprResult::success_value(0.0)
+}
+
+/// Evaluate a compiled expression
+///
+/// # Parameters
+/// - `expr`: The compiled expression
+/// - `ctx`: Optional context with functions and variables (can be NULL)
+///
+/// # Returns
+/// ExprResult with the value on success, or error details on failure
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn exp_rs_expr_eval(expr: *mut ExprCompiled, ctx: *const ExprContext) -> ExprResult {
+    if expr.is_null() {
+        return ExprResult::from_ffi_error(FFI_ERROR_NULL_POINTER, "Null expression pointer");
+    }
+
+    let wrapper = unsafe { &*(expr as *const BatchWithArena) };
+    if wrapper.magic != BATCH_MAGIC {
+        return ExprResult::from_ffi_error(
+            FFI_ERROR_INVALID_POINTER,
+            "Invalid or freed compiled expression",
+        );
+    }
+    let compiled = unsafe { &mut *wrapper.batch };
+
+    let eval_ctx = if ctx.is_null() {
+        alloc::rc::Rc::new(EvalContext::new())
+    } else {
+        unsafe { (*(ctx as *const alloc::rc::Rc<EvalContext>)).clone() }
+    };
+
+    match compiled.eval(&eval_ctx) {
+        Ok(()) => ExprResult::success_value(compiled.get_result(0).unwrap_or(Real::NAN)),
+        Err(e) => ExprResult::from_expr_error(e),
+    }
+}
+
+/// Free a compiled expression and its arena
+///
+/// Passing NULL is a no-op.
+///
+/// # Safety
+/// - The pointer must have been created by exp_rs_expr_compile()
+/// - The pointer must not be used after calling this function
+#[unsafe(no_mangle)]
+pub extern "C" fn exp_rs_expr_free(expr: *mut ExprCompiled) {
+    expr_batch_free(expr as *mut ExprBatch);
+}
+
+/// Resources needed to evaluate an expression, filled in by
+/// exp_rs_expr_resource_estimate() and expr_batch_resource_estimate()
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExprResourceEstimate {
+    /// Number of AST nodes
+    pub node_count: usize,
+    /// Peak operation stack length, compared against the evaluation depth limit
+    /// (EXP_RS_MAX_STACK_DEPTH unless set with expr_context_set_max_depth())
+    pub max_stack_depth: usize,
+    /// Peak number of intermediate values
+    pub max_value_stack: usize,
+    /// Context stack entries used, limited by EXP_RS_MAX_CONTEXTS
+    pub max_context_stack: usize,
+}
+
+/// Estimate the stack usage of a batch without evaluating it
+///
+/// Use this when loading expressions to reject those that would exceed the
+/// evaluator's limits at runtime. The estimate covers all expressions of the
+/// batch and the expression functions they call; a recursive expression
+/// function makes the stack figures SIZE_MAX.
+///
+/// # Returns
+/// 0 on success, negative FFI error code or positive ExprError code on failure
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn expr_batch_resource_estimate(
+    batch: *const ExprBatch,
+    out: *mut ExprResourceEstimate,
+) -> i32 {
+    if batch.is_null() || out.is_null() {
+        return FFI_ERROR_NULL_POINTER;
+    }
+
+    let wrapper = unsafe { &*(batch as *const BatchWithArena) };
+    if wrapper.magic != BATCH_MAGIC {
+        return FFI_ERROR_INVALID_POINTER;
+    }
+    match unsafe { (*wrapper.batch).resource_estimate() } {
+        Ok(estimate) => {
+            unsafe {
+                *out = ExprResourceEstimate {
+                    node_count: estimate.node_count,
+                    max_stack_depth: estimate.max_stack_depth,
+                    max_value_stack: estimate.max_value_stack,
+                    max_context_stack: estimate.max_context_stack,
+                };
+            }
+            0
+        }
+        Err(e) => e.error_code(),
+    }
+}
+
+/// Estimate the stack usage of a compiled expression without evaluating it
+///
+/// See expr_batch_resource_estimate().
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn exp_rs_expr_resource_estimate(
+    expr: *const ExprCompiled,
+    out: *mut ExprResourceEstimate,
+) -> i32 {
+    expr_batch_resource_estimate(expr as *const ExprBatch, out)
+}
+
+/// Get the default parser limits, to adjust before expr_batch_set_parser_limits()
+#[unsafe(no_mangle)]
+pub extern "C" fn expr_parser_limits_default() -> crate::engine::ParserLimits {
+    crate::engine::ParserLimits::DEFAULT
+}
+
+/// Set the limits on input accepted by expressions added to a batch from now on
+///
+/// Expressions exceeding the maximum length, number of tokens, nesting or token
+/// length are rejected when they are added, with a syntax or recursion limit error.
+///
+/// # Parameters
+/// - `batch`: The batch
+/// - `limits`: The limits, or NULL to restore the defaults
+///
+/// # Returns
+/// 0 on success, negative error code on failure
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn expr_batch_set_parser_limits(
+    batch: *mut ExprBatch,
+    limits: *const crate::engine::ParserLimits,
+) -> i32 {
+    if batch.is_null() {
+        return FFI_ERROR_NULL_POINTER;
+    }
+
+    let wrapper = unsafe { &mut *(batch as *mut BatchWithArena) };
+    if wrapper.magic != BATCH_MAGIC {
+        return FFI_ERROR_INVALID_POINTER;
+    }
+    let builder = unsafe { &mut *wrapper.batch };
+    let mut options = builder.parse_options();
+    options.limits = if limits.is_null() {
+        crate::engine::ParserLimits::DEFAULT
+    } else {
+        unsafe { *limits }
+    };
+    builder.set_parse_options(options);
+    0
+}
+
+/// Format a value for display as a NUL-terminated string
+///
+/// Engineering notation writes SI prefixes such as `1.25k` or `3.3µ` (`3.3u` with
+/// EXPR_FORMAT_ASCII). No heap memory is used.
+///
+/// # Parameters
+/// - `value`: The value to format
+/// - `notation`: EXPR_NOTATION_FIXED, EXPR_NOTATION_SCIENTIFIC or EXPR_NOTATION_ENGINEERING
+/// - `precision`: Digits after the decimal point
+/// - `flags`: EXPR_FORMAT_TRIM_ZEROS and/or EXPR_FORMAT_ASCII, or 0
+/// - `buffer`: Buffer receiving the text
+/// - `buffer_size`: Size of the buffer, including the terminating NUL
+///
+/// # Returns
+/// 0 on success, negative FFI error code, or the CapacityExceeded error code if the
+/// text does not fit
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn expr_format_value(
+    value: Real,
+    notation: u32,
+    precision: u8,
+    flags: u32,
+    buffer: *mut c_char,
+    buffer_size: usize,
+) -> i32 {
+    use crate::format::{FormatSpec, format_value};
+
+    if buffer.is_null() {
+        return FFI_ERROR_NULL_POINTER;
+    }
+    let mut spec = match notation {
+        EXPR_NOTATION_FIXED => FormatSpec::fixed(precision),
+        EXPR_NOTATION_SCIENTIFIC => FormatSpec::scientific(precision),
+        EXPR_NOTATION_ENGINEERING => FormatSpec::engineering(precision),
+        _ => return FFI_ERROR_INVALID_ARGUMENT,
+    };
+    spec.trim_zeros = flags & EXPR_FORMAT_TRIM_ZEROS != 0;
+    spec.ascii = flags & EXPR_FORMAT_ASCII != 0;
+
+    let text = match format_value(value, &spec) {
+        Ok(text) => text,
+        Err(e) => return e.error_code(),
+    };
+    if text.len() >= buffer_size {
+        let err = crate::error::ExprError::CapacityExceeded {
+            container: "formatted value",
+        };
+        return err.error_code();
+    }
+    unsafe {
+        core::ptr::copy_nonoverlapping(text.as_ptr(), buffer as *mut u8, text.len());
+        *buffer.add(text.len()) = 0;
+    }
+    0
+}
+
 // ============================================================================
 // Utility Functions
 // ============================================================================
@@ -1434,6 +2559,99 @@ pub extern "C" fn expr_estimate_arena_size(
     total + (total / 2)
 }
 
+// ============================================================================
+// Benchmarks
+// ============================================================================
+
+/// Capacity of ExprBenchResult::name, including the terminating NUL
+#[cfg(feature = "bench")]
+pub const EXPR_BENCH_NAME_CAPACITY: usize = 16;
+
+/// Cost of one benchmark case, in ticks of the clock passed to expr_bench_run
+#[cfg(feature = "bench")]
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ExprBenchResult {
+    /// NUL-terminated name of the case
+    pub name: [c_char; EXPR_BENCH_NAME_CAPACITY],
+    /// Ticks to parse the expression
+    pub parse_ticks: u32,
+    /// Ticks to build a batch with its parameters and the expression
+    pub compile_ticks: u32,
+    /// Ticks to evaluate the batch
+    pub eval_ticks: u32,
+}
+
+/// Number of cases run by expr_bench_run
+#[cfg(feature = "bench")]
+#[unsafe(no_mangle)]
+pub extern "C" fn expr_bench_case_count() -> usize {
+    crate::bench::CORPUS.len()
+}
+
+/// Time the benchmark corpus with a cycle counter
+///
+/// Each phase runs `iterations` times and the fewest ticks are reported.
+///
+/// # Parameters
+/// - `clock`: Function returning a free-running 32-bit counter that counts up, such as
+///   the DWT cycle counter; wrap-around is handled
+/// - `iterations`: Runs of each phase (at least 1)
+/// - `results`: Array receiving one entry per case
+/// - `capacity`: Length of the array, at least expr_bench_case_count()
+///
+/// # Returns
+/// Number of cases written, or negative error code
+#[cfg(feature = "bench")]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn expr_bench_run(
+    clock: Option<extern "C" fn() -> u32>,
+    iterations: u32,
+    results: *mut ExprBenchResult,
+    capacity: usize,
+) -> i32 {
+    use crate::bench::{CORPUS, Phase, run};
+
+    let Some(clock) = clock else {
+        return FFI_ERROR_NULL_POINTER;
+    };
+    if results.is_null() {
+        return FFI_ERROR_NULL_POINTER;
+    }
+    if capacity < CORPUS.len() {
+        return FFI_ERROR_INVALID_ARGUMENT;
+    }
+    let measurements = match run(&mut || clock(), iterations) {
+        Ok(measurements) => measurements,
+        Err(e) => return e.error_code(),
+    };
+
+    let out = unsafe { core::slice::from_raw_parts_mut(results, CORPUS.len()) };
+    for (entry, case) in out.iter_mut().zip(CORPUS) {
+        let ticks = |phase| {
+            measurements
+                .iter()
+                .find(|m| m.case == case.name && m.phase == phase)
+                .map_or(0, |m| m.ticks)
+        };
+        let mut name = [0 as c_char; EXPR_BENCH_NAME_CAPACITY];
+        for (dst, &src) in name[..EXPR_BENCH_NAME_CAPACITY - 1]
+            .iter_mut()
+            .zip(case.name.as_bytes())
+        {
+            *dst = src as c_char;
+        }
+        *entry = ExprBenchResult {
+            name,
+            parse_ticks: ticks(Phase::Parse),
+            compile_ticks: ticks(Phase::Compile),
+            eval_ticks: ticks(Phase::Eval),
+        };
+    }
+    CORPUS.len() as i32
+}
+
 // ============================================================================
 // Test-only Panic Trigger
 // ============================================================================
@@ -1567,4 +2785,242 @@ mod tests {
         );
         assert!(recovered_msg.chars().all(|c| c == 'a'));
     }
+
+    #[test]
+    fn test_context_and_compiled_expression_lifecycle() {
+        let ctx = exp_rs_ctx_new();
+        assert_eq!(exp_rs_ctx_set_variable(ctx, c"x".as_ptr(), 3.0), 0);
+
+        let mut expr = ptr::null_mut();
+        let result = exp_rs_expr_compile(c"x * 2 + 1".as_ptr(), &mut expr);
+        assert_eq!(result.status, 0);
+        assert!(!expr.is_null());
+        assert_eq!(exp_rs_expr_eval(expr, ctx).value, 7.0);
+
+        // The clone is independent of the original
+        let copy = exp_rs_ctx_clone(ctx);
+        assert_eq!(exp_rs_ctx_set_variable(copy, c"x".as_ptr(), 10.0), 0);
+        assert_eq!(exp_rs_expr_eval(expr, copy).value, 21.0);
+        assert_eq!(exp_rs_expr_eval(expr, ctx).value, 7.0);
+
+        // Contexts may be freed before the expression
+        exp_rs_ctx_free(ctx);
+        exp_rs_ctx_free(copy);
+        exp_rs_expr_free(expr);
+
+        let mut bad = ptr::null_mut();
+        let result = exp_rs_expr_compile(c"1 +".as_ptr(), &mut bad);
+        assert_ne!(result.status, 0);
+        assert!(bad.is_null());
+    }
+
+    #[test]
+    fn test_array_view_reads_caller_memory() {
+        let mut samples: [Real; 3] = [1.0, 2.0, 3.0];
+        let len = samples.len();
+        // All accesses go through this pointer, as a C caller's would
+        let view = samples.as_mut_ptr();
+        let ctx = exp_rs_ctx_new();
+        assert_eq!(
+            expr_context_set_array_view(ctx, c"buf".as_ptr(), view, len),
+            0
+        );
+
+        let mut expr = ptr::null_mut();
+        assert_eq!(
+            exp_rs_expr_compile(c"buf[0] + buf[2]".as_ptr(), &mut expr).status,
+            0
+        );
+        assert_eq!(exp_rs_expr_eval(expr, ctx).value, 4.0);
+
+        // Updates in place are visible without registering again
+        unsafe { view.add(2).write(10.0) };
+        assert_eq!(exp_rs_expr_eval(expr, ctx).value, 11.0);
+
+        let mut oob = ptr::null_mut();
+        assert_eq!(exp_rs_expr_compile(c"buf[3]".as_ptr(), &mut oob).status, 0);
+        assert_eq!(
+            exp_rs_expr_eval(oob, ctx).status,
+            crate::error::ExprError::ArrayIndexOutOfBounds {
+                name: String::new(),
+                index: 0,
+                len: 0
+            }
+            .error_code()
+        );
+
+        assert_eq!(
+            expr_context_set_array_view(ctx, c"buf".as_ptr(), ptr::null(), 1),
+            FFI_ERROR_NULL_POINTER
+        );
+
+        exp_rs_expr_free(oob);
+        exp_rs_expr_free(expr);
+        exp_rs_ctx_free(ctx);
+    }
+
+    #[test]
+    fn test_batch_parser_limits() {
+        let batch = expr_batch_new(4096);
+        let mut limits = expr_parser_limits_default();
+        assert_eq!(limits.max_length, 10000);
+        limits.max_tokens = 4;
+        assert_eq!(expr_batch_set_parser_limits(batch, &limits), 0);
+        assert_eq!(
+            expr_batch_add_expression(batch, c"a + b".as_ptr()).status,
+            0
+        );
+        assert_ne!(
+            expr_batch_add_expression(batch, c"a + b * c".as_ptr()).status,
+            0
+        );
+
+        limits.max_tokens = 100;
+        limits.max_length = 8;
+        assert_eq!(expr_batch_set_parser_limits(batch, &limits), 0);
+        assert_ne!(
+            expr_batch_add_expression(batch, c"a + b * c".as_ptr()).status,
+            0
+        );
+
+        assert_eq!(expr_batch_set_parser_limits(batch, ptr::null()), 0);
+        assert_eq!(
+            expr_batch_add_expression(batch, c"a + b * c".as_ptr()).status,
+            0
+        );
+        assert_eq!(
+            expr_batch_set_parser_limits(ptr::null_mut(), &limits),
+            FFI_ERROR_NULL_POINTER
+        );
+        expr_batch_free(batch);
+    }
+
+    #[test]
+    fn test_format_value_into_buffer() {
+        let mut buffer = [0 as c_char; 16];
+        let status = expr_format_value(
+            0.0033,
+            EXPR_NOTATION_ENGINEERING,
+            2,
+            EXPR_FORMAT_TRIM_ZEROS | EXPR_FORMAT_ASCII,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+        );
+        assert_eq!(status, 0);
+        let text = unsafe { CStr::from_ptr(buffer.as_ptr()) };
+        assert_eq!(text.to_str().unwrap(), "3.3m");
+
+        assert_eq!(
+            expr_format_value(1.5, 7, 2, 0, buffer.as_mut_ptr(), buffer.len()),
+            FFI_ERROR_INVALID_ARGUMENT
+        );
+        // "1234.57" needs 8 bytes with the NUL
+        assert_ne!(
+            expr_format_value(1234.567, EXPR_NOTATION_FIXED, 2, 0, buffer.as_mut_ptr(), 7),
+            0
+        );
+    }
+
+    #[test]
+    fn test_compiled_expression_resource_estimate() {
+        let mut expr = ptr::null_mut();
+        assert_eq!(
+            exp_rs_expr_compile(c"sin(x) * 2 + 1".as_ptr(), &mut expr).status,
+            0
+        );
+        let mut estimate = ExprResourceEstimate::default();
+        assert_eq!(exp_rs_expr_resource_estimate(expr, &mut estimate), 0);
+        assert_eq!(estimate.node_count, 6);
+        assert_eq!(estimate.max_context_stack, 1);
+        assert!(estimate.max_stack_depth > 0);
+        assert!(estimate.max_stack_depth <= crate::types::EXP_RS_MAX_STACK_DEPTH);
+        assert_eq!(
+            exp_rs_expr_resource_estimate(expr, ptr::null_mut()),
+            FFI_ERROR_NULL_POINTER
+        );
+        exp_rs_expr_free(expr);
+    }
+
+    #[test]
+    fn test_function_removal_and_introspection() {
+        let ctx = exp_rs_ctx_new();
+        assert_eq!(exp_rs_ctx_set_variable(ctx, c"speed".as_ptr(), 1.0), 0);
+        assert_eq!(exp_rs_ctx_set_variable(ctx, c"accel".as_ptr(), 2.0), 0);
+        assert_eq!(expr_context_variable_count(ctx), 2);
+        let mut name = [0u8; 16];
+        let len = expr_context_get_variable_name(ctx, 0, name.as_mut_ptr(), name.len());
+        assert_eq!(&name[..len], b"accel");
+        assert_eq!(
+            expr_context_get_variable_name(ctx, 2, ptr::null_mut(), 0),
+            0
+        );
+
+        let (mut arity, mut variadic) = (0, false);
+        assert_eq!(
+            expr_context_function_signature(ctx, c"max".as_ptr(), &mut arity, &mut variadic),
+            0
+        );
+        assert_eq!((arity, variadic), (1, true));
+
+        let count = expr_context_native_function_count(ctx);
+        assert_eq!(expr_context_unregister_function(ctx, c"atan2".as_ptr()), 1);
+        assert_eq!(expr_context_unregister_function(ctx, c"atan2".as_ptr()), 0);
+        assert_eq!(expr_context_native_function_count(ctx), count - 1);
+        assert_eq!(
+            expr_context_function_signature(ctx, c"atan2".as_ptr(), &mut arity, ptr::null_mut()),
+            crate::error::ExprError::UnknownFunction {
+                name: String::new()
+            }
+            .error_code()
+        );
+
+        exp_rs_ctx_free(ctx);
+    }
+
+    #[test]
+    #[cfg(not(feature = "std"))] // std keeps parameters in a HashMap
+    fn test_prepared_batch_evaluation_does_not_allocate() {
+        use super::system_allocator::COUNTED_ALLOCATIONS;
+
+        let ctx = expr_context_new();
+        let batch = expr_batch_new(1024);
+        let x = expr_batch_add_variable(batch, c"x".as_ptr(), 1.0).value as usize;
+        assert_eq!(
+            expr_batch_add_expression_function(
+                batch,
+                c"sq".as_ptr(),
+                c"v".as_ptr(),
+                c"v * v".as_ptr()
+            ),
+            0
+        );
+        for expr in [
+            c"x * 2 + sin(x)",
+            c"sqrt(abs(x)) && x < 100",
+            c"sq(x) + max(x, 3)",
+            // The deep branch is not taken while preparing
+            c"x > 1 ? ((((((x + 1) * 2 + 1) * 2 + 1) * 2 + 1) * 2 + 1) * 2) : -x",
+        ] {
+            assert_eq!(expr_batch_add_expression(batch, expr.as_ptr()).status, 0);
+        }
+        assert_eq!(expr_batch_prepare(batch, ctx), 0);
+        let arena_bytes = expr_batch_arena_bytes(batch);
+
+        COUNTED_ALLOCATIONS.with(|count| count.set(Some(0)));
+        for i in 0..50 {
+            assert_eq!(expr_batch_set_variable(batch, x, i as Real), 0);
+            assert_eq!(expr_batch_evaluate(batch, ctx), 0);
+        }
+        let allocations = COUNTED_ALLOCATIONS.with(|count| count.replace(None));
+        assert_eq!(allocations, Some(0));
+        assert_eq!(expr_batch_arena_bytes(batch), arena_bytes);
+        assert_eq!(expr_batch_get_result(batch, 2), 49.0 * 49.0 + 49.0);
+
+        assert_eq!(
+            expr_batch_prepare(batch, ptr::null_mut()),
+            FFI_ERROR_NULL_POINTER
+        );
+        expr_batch_free(batch);
+        expr_context_free(ctx);
+    }
 }
@@ -0,0 +1,230 @@
+//! Formatting of results for display, without a formatting crate or the heap.
+//!
+//! [`format_value`] renders a number in fixed, scientific or engineering notation into
+//! a fixed-capacity [`FormattedValue`]. Engineering notation uses SI prefixes, as
+//! measurement displays show values:
+//!
+//! ```
+//! use exp_rs::format::{FormatSpec, eval_formatted, format_value};
+//!
+//! let spec = FormatSpec::engineering(2).trim_zeros();
+//! assert_eq!(format_value(1250.0, &spec).unwrap(), "1.25k");
+//! assert_eq!(format_value(0.0000033, &spec).unwrap(), "3.3µ");
+//! assert_eq!(eval_formatted("2 / 3", None, &FormatSpec::fixed(3)).unwrap(), "0.667");
+//! assert_eq!(eval_formatted("6.02e23", None, &FormatSpec::scientific(2)).unwrap(), "6.02e23");
+//! ```
+
+use crate::Real;
+use crate::context::EvalContext;
+use crate::error::ExprError;
+use alloc::rc::Rc;
+use core::fmt::Write;
+use heapless::String as HeaplessString;
+
+/// Capacity in bytes of a [`FormattedValue`].
+pub const FORMATTED_CAPACITY: usize = 32;
+
+/// A formatted number.
+pub type FormattedValue = HeaplessString<FORMATTED_CAPACITY>;
+
+/// How [`format_value`] writes a number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notation {
+    /// Plain decimal notation, such as `1250.00`
+    Fixed,
+    /// A mantissa between 1 and 10 with a power of ten, such as `1.25e3`
+    Scientific,
+    /// A mantissa between 1 and 1000 with an SI prefix, such as `1.25k`. Values
+    /// beyond the prefixes (below `1e-24` or from `1e27`) get an exponent instead,
+    /// such as `1.25e27`.
+    Engineering,
+}
+
+/// Notation and precision used by [`format_value`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatSpec {
+    /// Notation of the number
+    pub notation: Notation,
+    /// Digits after the decimal point
+    pub precision: u8,
+    /// Whether to drop trailing zeros after the decimal point, and the point itself
+    pub trim_zeros: bool,
+    /// Whether to write the micro prefix as `u` instead of `µ`, for displays
+    /// limited to ASCII
+    pub ascii: bool,
+}
+
+impl FormatSpec {
+    /// Fixed notation with `precision` decimals.
+    pub const fn fixed(precision: u8) -> Self {
+        Self::new(Notation::Fixed, precision)
+    }
+
+    /// Scientific notation with `precision` decimals in the mantissa.
+    pub const fn scientific(precision: u8) -> Self {
+        Self::new(Notation::Scientific, precision)
+    }
+
+    /// Engineering notation with `precision` decimals in the mantissa.
+    pub const fn engineering(precision: u8) -> Self {
+        Self::new(Notation::Engineering, precision)
+    }
+
+    const fn new(notation: Notation, precision: u8) -> Self {
+        Self {
+            notation,
+            precision,
+            trim_zeros: false,
+            ascii: false,
+        }
+    }
+
+    /// Drops trailing zeros after the decimal point.
+    pub const fn trim_zeros(mut self) -> Self {
+        self.trim_zeros = true;
+        self
+    }
+
+    /// Writes the micro prefix as `u`.
+    pub const fn ascii(mut self) -> Self {
+        self.ascii = true;
+        self
+    }
+}
+
+/// SI prefixes from 1e-24 to 1e24, in steps of a thousand.
+const SI_PREFIXES: [&str; 17] = [
+    "y", "z", "a", "f", "p", "n", "µ", "m", "", "k", "M", "G", "T", "P", "E", "Z", "Y",
+];
+
+/// Exponent of the first entry of [`SI_PREFIXES`].
+const SI_MIN_EXPONENT: i32 = -24;
+
+/// Formats `value` as described by `spec`.
+///
+/// NaN and infinities are written as `NaN`, `inf` and `-inf`. Fails with
+/// `ExprError::CapacityExceeded` if the text does not fit in [`FORMATTED_CAPACITY`]
+/// bytes, as happens for large values in fixed notation.
+pub fn format_value(value: Real, spec: &FormatSpec) -> Result<FormattedValue, ExprError> {
+    let mut out = FormattedValue::new();
+    write_value(&mut out, value, spec).map_err(|_| ExprError::CapacityExceeded {
+        container: "formatted value",
+    })?;
+    Ok(out)
+}
+
+/// Evaluates `expression` like [`interp`](crate::engine::interp) and formats the result.
+pub fn eval_formatted(
+    expression: &str,
+    ctx: Option<Rc<EvalContext>>,
+    spec: &FormatSpec,
+) -> Result<FormattedValue, ExprError> {
+    format_value(crate::engine::interp(expression, ctx)?, spec)
+}
+
+fn write_value(out: &mut FormattedValue, value: Real, spec: &FormatSpec) -> core::fmt::Result {
+    if !value.is_finite() {
+        return write!(out, "{}", value);
+    }
+    let precision = spec.precision as usize;
+    match spec.notation {
+        Notation::Fixed => {
+            write!(out, "{:.*}", precision, value)?;
+            trim(out, spec);
+            Ok(())
+        }
+        Notation::Scientific => {
+            let mut digits = FormattedValue::new();
+            write!(digits, "{:.*e}", precision, value)?;
+            let (mantissa, exponent) = digits.split_once('e').unwrap_or((&digits, "0"));
+            out.push_str(mantissa).map_err(|_| core::fmt::Error)?;
+            trim(out, spec);
+            write!(out, "e{}", exponent)
+        }
+        Notation::Engineering => {
+            let (mantissa, exponent) = engineering_parts(value, precision);
+            write!(out, "{:.*}", precision, mantissa)?;
+            trim(out, spec);
+            // Exponents below the smallest prefix wrap to an out-of-range index
+            let index = ((exponent - SI_MIN_EXPONENT) / 3) as usize;
+            match SI_PREFIXES.get(index) {
+                Some(&"µ") if spec.ascii => out.write_str("u"),
+                Some(prefix) => out.write_str(prefix),
+                None => write!(out, "e{}", exponent),
+            }
+        }
+    }
+}
+
+/// Splits `value` into a mantissa in `[1, 1000)` (after rounding to `precision`
+/// decimals) and an exponent that is a multiple of three.
+fn engineering_parts(value: Real, precision: usize) -> (Real, i32) {
+    if value == 0.0 {
+        return (value, 0);
+    }
+    let mut mantissa = value;
+    let mut exponent = 0;
+    while mantissa.abs() >= 1000.0 {
+        mantissa /= 1000.0;
+        exponent += 3;
+    }
+    while mantissa.abs() < 1.0 {
+        mantissa *= 1000.0;
+        exponent -= 3;
+    }
+    // A mantissa that rounds up to 1000 moves to the next prefix
+    let mut half_step = 0.5;
+    for _ in 0..precision {
+        half_step /= 10.0;
+    }
+    if mantissa.abs() >= 1000.0 - half_step {
+        mantissa /= 1000.0;
+        exponent += 3;
+    }
+    (mantissa, exponent)
+}
+
+fn trim(out: &mut FormattedValue, spec: &FormatSpec) {
+    if spec.trim_zeros && out.contains('.') {
+        while out.ends_with('0') {
+            out.pop();
+        }
+        if out.ends_with('.') {
+            out.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_notations() {
+        let fmt = |value: Real, spec: FormatSpec| format_value(value, &spec).unwrap();
+
+        assert_eq!(fmt(3.14659, FormatSpec::fixed(2)), "3.15");
+        assert_eq!(fmt(-2.5, FormatSpec::fixed(3).trim_zeros()), "-2.5");
+        assert_eq!(fmt(40.0, FormatSpec::fixed(2).trim_zeros()), "40");
+        assert_eq!(fmt(0.00125, FormatSpec::scientific(2)), "1.25e-3");
+        assert_eq!(fmt(1000.0, FormatSpec::scientific(3).trim_zeros()), "1e3");
+
+        let eng = FormatSpec::engineering(2);
+        assert_eq!(fmt(1250.0, eng), "1.25k");
+        assert_eq!(fmt(-0.047, eng), "-47.00m");
+        assert_eq!(fmt(3.3e-6, eng.trim_zeros().ascii()), "3.3u");
+        assert_eq!(fmt(999.999, eng), "1.00k");
+        assert_eq!(fmt(0.0, eng), "0.00");
+        assert_eq!(fmt(12.0, eng.trim_zeros()), "12");
+        assert_eq!(fmt(4.7e9, eng.trim_zeros()), "4.7G");
+        assert_eq!(fmt(1.5e30, eng), "1.50e30");
+        assert_eq!(fmt(2.0e-27, eng), "2.00e-27");
+
+        assert_eq!(fmt(Real::NAN, eng), "NaN");
+        assert_eq!(fmt(Real::NEG_INFINITY, FormatSpec::fixed(1)), "-inf");
+        assert!(matches!(
+            format_value(1e30, &FormatSpec::fixed(2)),
+            Err(ExprError::CapacityExceeded { .. })
+        ));
+    }
+}
@@ -14,17 +14,33 @@
 #[cfg(all(feature = "libm", feature = "f32"))]
 use libm::{
     acosf as libm_acos, asinf as libm_asin, atan2f as libm_atan2, atanf as libm_atan,
-    ceilf as libm_ceil, cosf as libm_cos, coshf as libm_cosh, expf as libm_exp,
-    floorf as libm_floor, log10f as libm_log10, logf as libm_ln, powf as libm_pow,
-    sinf as libm_sin, sinhf as libm_sinh, sqrtf as libm_sqrt, tanf as libm_tan, tanhf as libm_tanh,
+    ceilf as libm_ceil, copysignf as libm_copysign, cosf as libm_cos, coshf as libm_cosh,
+    expf as libm_exp, expm1f as libm_expm1, floorf as libm_floor, fmaf as libm_fma,
+    hypotf as libm_hypot, log1pf as libm_log1p, log2f as libm_log2, log10f as libm_log10,
+    logf as libm_ln, powf as libm_pow, sinf as libm_sin, sinhf as libm_sinh, sqrtf as libm_sqrt,
+    tanf as libm_tan, tanhf as libm_tanh,
 };
 
 #[cfg(all(feature = "libm", not(feature = "f32")))]
 use libm::{
     acos as libm_acos, asin as libm_asin, atan as libm_atan, atan2 as libm_atan2,
-    ceil as libm_ceil, cos as libm_cos, cosh as libm_cosh, exp as libm_exp, floor as libm_floor,
-    log as libm_ln, log10 as libm_log10, pow as libm_pow, sin as libm_sin, sinh as libm_sinh,
-    sqrt as libm_sqrt, tan as libm_tan, tanh as libm_tanh,
+    ceil as libm_ceil, copysign as libm_copysign, cos as libm_cos, cosh as libm_cosh,
+    exp as libm_exp, expm1 as libm_expm1, floor as libm_floor, fma as libm_fma,
+    hypot as libm_hypot, log as libm_ln, log1p as libm_log1p, log2 as libm_log2,
+    log10 as libm_log10, pow as libm_pow, sin as libm_sin, sinh as libm_sinh, sqrt as libm_sqrt,
+    tan as libm_tan, tanh as libm_tanh,
+};
+
+#[cfg(all(feature = "special-functions", feature = "f32"))]
+use libm::{
+    erfcf as libm_erfc, erff as libm_erf, j0f as libm_j0, j1f as libm_j1, lgammaf as libm_lgamma,
+    tgammaf as libm_tgamma,
+};
+
+#[cfg(all(feature = "special-functions", not(feature = "f32")))]
+use libm::{
+    erf as libm_erf, erfc as libm_erfc, j0 as libm_j0, j1 as libm_j1, lgamma as libm_lgamma,
+    tgamma as libm_tgamma,
 };
 
 use crate::Real;
@@ -82,6 +98,15 @@ mod internal_math {
     pub fn libm_log10(x: Real) -> Real {
         x.log10()
     }
+    pub fn libm_log2(x: Real) -> Real {
+        x.log2()
+    }
+    pub fn libm_log1p(x: Real) -> Real {
+        x.ln_1p()
+    }
+    pub fn libm_expm1(x: Real) -> Real {
+        x.exp_m1()
+    }
 
     // Power and root functions
     pub fn libm_pow(x: Real, y: Real) -> Real {
@@ -90,6 +115,17 @@ mod internal_math {
     pub fn libm_sqrt(x: Real) -> Real {
         x.sqrt()
     }
+    pub fn libm_hypot(x: Real, y: Real) -> Real {
+        x.hypot(y)
+    }
+    pub fn libm_fma(x: Real, y: Real, z: Real) -> Real {
+        x.mul_add(y, z)
+    }
+
+    // Sign manipulation
+    pub fn libm_copysign(x: Real, y: Real) -> Real {
+        x.copysign(y)
+    }
 
     // Rounding functions
     pub fn libm_ceil(x: Real) -> Real {
@@ -154,6 +190,71 @@ pub fn min(a: Real, b: Real) -> Real {
     if a < b { a } else { b }
 }
 
+/// Returns the position of the largest value.
+///
+/// NaN values are skipped, and ties resolve to the first occurrence.
+///
+/// # Parameters
+///
+/// * `values` - The values to search
+///
+/// # Returns
+///
+/// The zero-based index of the largest value, or NaN if there are no non-NaN values.
+pub fn argmax(values: impl IntoIterator<Item = Real>) -> Real {
+    arg_extreme(values, |candidate, best| candidate > best)
+}
+
+/// Returns the position of the smallest value.
+///
+/// NaN values are skipped, and ties resolve to the first occurrence.
+///
+/// # Parameters
+///
+/// * `values` - The values to search
+///
+/// # Returns
+///
+/// The zero-based index of the smallest value, or NaN if there are no non-NaN values.
+pub fn argmin(values: impl IntoIterator<Item = Real>) -> Real {
+    arg_extreme(values, |candidate, best| candidate < best)
+}
+
+fn arg_extreme(
+    values: impl IntoIterator<Item = Real>,
+    better: impl Fn(Real, Real) -> bool,
+) -> Real {
+    let mut best: Option<(usize, Real)> = None;
+    for (i, v) in values.into_iter().enumerate() {
+        if v.is_nan() {
+            continue;
+        }
+        if best.is_none_or(|(_, b)| better(v, b)) {
+            best = Some((i, v));
+        }
+    }
+    best.map_or(Real::NAN, |(i, _)| i as Real)
+}
+
+/// Returns the first finite value.
+///
+/// This provides a fallback for inputs that may be NaN or infinite, as in
+/// `coalesce(sensor, last_good, 0)`.
+///
+/// # Parameters
+///
+/// * `values` - The candidates, in order of preference
+///
+/// # Returns
+///
+/// The first value that is neither NaN nor infinite, or NaN if there is none.
+pub fn coalesce(values: impl IntoIterator<Item = Real>) -> Real {
+    values
+        .into_iter()
+        .find(|v| v.is_finite())
+        .unwrap_or(Real::NAN)
+}
+
 /// Subtracts the second value from the first.
 ///
 /// # Parameters
@@ -308,15 +409,7 @@ pub fn atan(_: Real, _: Real) -> Real {
 #[cfg(feature = "libm")]
 pub fn atan2(a: Real, b: Real) -> Real {
     // atan2 takes y,x order (not x,y)
-    #[cfg(test)]
-    println!("atan2 called with a={}, b={}", a, b);
-
-    let result = libm_atan2(a, b); // Don't swap the arguments
-
-    #[cfg(test)]
-    println!("atan2 result: {}", result);
-
-    result
+    libm_atan2(a, b) // Don't swap the arguments
 }
 
 #[cfg(all(not(feature = "libm"), test))]
@@ -499,6 +592,57 @@ pub fn log10(_: Real, _: Real) -> Real {
     panic!("log10 requires libm or custom implementation")
 }
 
+/// Logarithm of `a` to the given `base`.
+///
+/// Returns NaN if `a` is not positive or `base` is not a positive number other than 1.
+#[cfg(any(feature = "libm", test))]
+pub fn log_base(a: Real, base: Real) -> Real {
+    if base <= 0.0 || base == 1.0 {
+        return Real::NAN;
+    }
+    ln(a, 0.0) / ln(base, 0.0)
+}
+
+#[cfg(all(not(feature = "libm"), not(test)))]
+pub fn log_base(_: Real, _: Real) -> Real {
+    panic!("log requires libm or custom implementation")
+}
+
+/// Base-2 logarithm of `a`, NaN if `a` is not positive.
+#[cfg(any(feature = "libm", test))]
+pub fn log2(a: Real, _: Real) -> Real {
+    if a <= 0.0 { Real::NAN } else { libm_log2(a) }
+}
+
+#[cfg(all(not(feature = "libm"), not(test)))]
+pub fn log2(_: Real, _: Real) -> Real {
+    panic!("log2 requires libm or custom implementation")
+}
+
+/// Natural logarithm of `1 + a`, accurate even when `a` is close to zero.
+///
+/// Returns NaN if `a` is less than -1.
+#[cfg(any(feature = "libm", test))]
+pub fn log1p(a: Real, _: Real) -> Real {
+    if a < -1.0 { Real::NAN } else { libm_log1p(a) }
+}
+
+#[cfg(all(not(feature = "libm"), not(test)))]
+pub fn log1p(_: Real, _: Real) -> Real {
+    panic!("log1p requires libm or custom implementation")
+}
+
+/// `e^a - 1`, accurate even when `a` is close to zero.
+#[cfg(any(feature = "libm", test))]
+pub fn expm1(a: Real, _: Real) -> Real {
+    libm_expm1(a)
+}
+
+#[cfg(all(not(feature = "libm"), not(test)))]
+pub fn expm1(_: Real, _: Real) -> Real {
+    panic!("expm1 requires libm or custom implementation")
+}
+
 pub fn pi(_: Real, _: Real) -> Real {
     crate::constants::PI
 }
@@ -523,9 +667,6 @@ pub fn pi(_: Real, _: Real) -> Real {
 /// The value of `a` raised to the power of `b`.
 #[cfg(feature = "libm")]
 pub fn pow(a: Real, b: Real) -> Real {
-    #[cfg(test)]
-    println!("pow function called with a={}, b={}", a, b);
-
     // Handle special cases
     if a == 0.0 && b == 0.0 {
         return 1.0; // 0^0 = 1 by convention
@@ -744,10 +885,478 @@ pub fn round(_: Real, _: Real) -> Real {
     panic!("round requires libm or custom implementation")
 }
 
+/// Truncates `a` toward zero: `trunc(-2.7) = -2`.
+pub fn trunc(a: Real, _: Real) -> Real {
+    if a < 0.0 { ceil(a, 0.0) } else { floor(a, 0.0) }
+}
+
+/// Returns the fractional part of `a`, with the sign of `a`: `fract(-2.75) = -0.75`.
+pub fn fract(a: Real, _: Real) -> Real {
+    a - trunc(a, 0.0)
+}
+
+/// Rounds `x` to the nearest multiple of `step`, with ties away from zero.
+///
+/// Returns NaN if `step` is not a positive finite number.
+pub fn round_to(x: Real, step: Real) -> Real {
+    crate::context::RoundingMode::HalfUp.round_to(x, step)
+}
+
+/// Adds `a` and `b`, clamping the sum to `[min, max]`.
+///
+/// Returns NaN if the sum or a bound is NaN, or `min > max`.
+pub fn sat_add(a: Real, b: Real, min: Real, max: Real) -> Real {
+    let sum = a + b;
+    if sum.is_nan() || min.is_nan() || max.is_nan() || min > max {
+        return Real::NAN;
+    }
+    sum.max(min).min(max)
+}
+
+/// Wraps `x` into the range `[lo, hi)`, as a counter or phase that starts over at `lo`
+/// after reaching `hi`: `wrap(370, 0, 360) = 10`, `wrap(-1, 0, 360) = 359`.
+///
+/// Returns NaN if `x` is not finite or `hi <= lo`.
+pub fn wrap(x: Real, lo: Real, hi: Real) -> Real {
+    let width = hi - lo;
+    if !(x.is_finite() && width > 0.0 && width.is_finite()) {
+        return Real::NAN;
+    }
+    let offset = x - lo;
+    let wrapped = lo + (offset - width * floor(offset / width, 0.0));
+    // Rounding can land exactly on `hi` for `x` just below a multiple of the width
+    if wrapped >= hi { lo } else { wrapped }
+}
+
+/// Wraps the angle `x` in radians into `[-pi, pi)`, e.g. for the error between two
+/// headings: `angle_wrap(3 * pi / 2) = -pi / 2`.
+pub fn angle_wrap(x: Real) -> Real {
+    let pi = core::f64::consts::PI as Real;
+    wrap(x, -pi, pi)
+}
+
+/// Divides the integers `a` and `b`, rounding toward negative infinity:
+/// `idiv(-7, 2) = -4`.
+///
+/// Returns NaN if an argument is not an integer, `b` is zero or the quotient overflows
+/// `i64`.
+pub fn idiv(a: Real, b: Real) -> Real {
+    integer_op(a, b, floor_div)
+}
+
+/// Returns the remainder of [`idiv`], which has the sign of `b`: `imod(-7, 2) = 1`, so
+/// `imod(t, period)` counts from 0 to `period - 1` also for negative `t`.
+///
+/// Returns NaN if an argument is not an integer or `b` is zero.
+pub fn imod(a: Real, b: Real) -> Real {
+    integer_op(a, b, floor_mod)
+}
+
+/// Returns the absolute value of the integer `a`, or NaN if `a` is not an integer.
+pub fn iabs(a: Real) -> Real {
+    integer_op(a, 0.0, |a, _| a.checked_abs())
+}
+
+/// Returns the bitwise and of the integers `a` and `b` in two's complement:
+/// `bitand(6, 3) = 2`.
+///
+/// Returns NaN if an argument is not an integer.
+pub fn bitand(a: Real, b: Real) -> Real {
+    integer_op(a, b, |a, b| Some(a & b))
+}
+
+/// Returns the bitwise or of the integers `a` and `b` in two's complement:
+/// `bitor(6, 3) = 7`.
+///
+/// Returns NaN if an argument is not an integer.
+pub fn bitor(a: Real, b: Real) -> Real {
+    integer_op(a, b, |a, b| Some(a | b))
+}
+
+/// Returns the bitwise exclusive or of the integers `a` and `b` in two's complement:
+/// `bitxor(6, 3) = 5`.
+///
+/// Returns NaN if an argument is not an integer.
+pub fn bitxor(a: Real, b: Real) -> Real {
+    integer_op(a, b, |a, b| Some(a ^ b))
+}
+
+/// Returns the bitwise complement of the integer `a` in two's complement:
+/// `bitnot(5) = -6`.
+///
+/// Returns NaN if `a` is not an integer.
+pub fn bitnot(a: Real) -> Real {
+    integer_op(a, 0.0, |a, _| Some(!a))
+}
+
+/// Returns bit `n` of the integer `x` in two's complement, 0 or 1, counting from the
+/// least significant bit 0: `getbit(6, 1) = 1`.
+///
+/// Returns NaN if an argument is not an integer or `n` is not in `0..64`.
+pub fn getbit(x: Real, n: Real) -> Real {
+    integer_op(x, n, get_bit)
+}
+
+/// Returns the integer `x` with bit `n` set if `v` is non-zero or cleared if it is zero:
+/// `setbit(6, 0, 1) = 7` and `setbit(6, 1, 0) = 4`.
+///
+/// Returns NaN if an argument is not an integer or `n` is not in `0..64`.
+pub fn setbit(x: Real, n: Real, v: Real) -> Real {
+    use crate::value::Value;
+
+    match Value::Real(v).to_int() {
+        Some(0) => integer_op(x, n, |x, n| set_bit(x, n, 0)),
+        Some(_) => integer_op(x, n, |x, n| set_bit(x, n, 1)),
+        None => Real::NAN,
+    }
+}
+
+/// Returns the number of one bits of the integer `x` in two's complement:
+/// `popcount(7) = 3` and `popcount(-1) = 64`.
+///
+/// Returns NaN if `x` is not an integer.
+pub fn popcount(x: Real) -> Real {
+    integer_op(x, 0.0, |x, _| Some(x.count_ones() as i64))
+}
+
+/// Applies `op` to `a` and `b` as `i64`, or gives NaN if either is not an integer or
+/// `op` fails.
+fn integer_op(a: Real, b: Real, op: fn(i64, i64) -> Option<i64>) -> Real {
+    use crate::value::Value;
+
+    match (Value::Real(a).to_int(), Value::Real(b).to_int()) {
+        (Some(a), Some(b)) => op(a, b).map_or(Real::NAN, |n| n as Real),
+        _ => Real::NAN,
+    }
+}
+
+/// Integer division rounding toward negative infinity, `None` if `b` is zero or the
+/// quotient overflows.
+pub(crate) fn floor_div(a: i64, b: i64) -> Option<i64> {
+    let quotient = a.checked_div(b)?;
+    if a % b != 0 && (a < 0) != (b < 0) {
+        Some(quotient - 1)
+    } else {
+        Some(quotient)
+    }
+}
+
+/// Bit `n` of `x`, `None` if `n` is not a bit position of `i64`.
+pub(crate) fn get_bit(x: i64, n: i64) -> Option<i64> {
+    let n = u32::try_from(n).ok().filter(|&n| n < i64::BITS)?;
+    Some((x >> n) & 1)
+}
+
+/// `x` with bit `n` set if `v` is non-zero or cleared otherwise, `None` if `n` is not a
+/// bit position of `i64`.
+pub(crate) fn set_bit(x: i64, n: i64, v: i64) -> Option<i64> {
+    let n = u32::try_from(n).ok().filter(|&n| n < i64::BITS)?;
+    let mask = 1i64 << n;
+    Some(if v != 0 { x | mask } else { x & !mask })
+}
+
+/// Remainder of [`floor_div`], with the sign of `b`; `None` if `b` is zero.
+pub(crate) fn floor_mod(a: i64, b: i64) -> Option<i64> {
+    // i64::MIN % -1 overflows although the remainder is 0
+    let remainder = a.checked_rem(b).or((b == -1).then_some(0))?;
+    if remainder != 0 && (remainder < 0) != (b < 0) {
+        Some(remainder + b)
+    } else {
+        Some(remainder)
+    }
+}
+
+/// Snaps `x` to a multiple of `step`, choosing the multiple according to `mode`:
+///
+/// * `0` - nearest, with ties away from zero (as [`round_to`])
+/// * `1` - down, toward negative infinity
+/// * `2` - up, toward positive infinity
+/// * `3` - toward zero
+///
+/// Returns NaN for any other mode or if `step` is not a positive finite number.
+pub fn quantize(x: Real, step: Real, mode: Real) -> Real {
+    if !(step > 0.0 && step.is_finite()) {
+        return Real::NAN;
+    }
+    let steps = x / step;
+    let multiple = match mode {
+        0.0 => crate::context::RoundingMode::HalfUp.round(steps),
+        1.0 => floor(steps, 0.0),
+        2.0 => ceil(steps, 0.0),
+        3.0 => trunc(steps, 0.0),
+        _ => return Real::NAN,
+    };
+    multiple * step
+}
+
+/// Interpolates linearly in the table of points `(xs[i], ys[i])` at `x`.
+///
+/// `xs` must be ascending. Outside the table the result is clamped to the first or
+/// last `ys` value, or with `extrapolate` continues the first or last segment.
+/// Returns NaN if `x` is NaN or the table is empty or of unequal lengths.
+///
+/// This backs the `lut(x, xs, ys)` builtin, whose tables are context arrays, and
+/// `lut(x, xs, ys, mode)` with mode 0 to clamp or 1 to extrapolate:
+///
+/// ```
+/// use exp_rs::EvalContext;
+/// use exp_rs::engine::interp;
+/// use exp_rs::types::TryIntoHeaplessString;
+/// use std::rc::Rc;
+///
+/// let mut ctx = EvalContext::new();
+/// let volts = vec![0.5, 1.0, 2.0, 3.0];
+/// let celsius = vec![-20.0, 0.0, 25.0, 60.0];
+/// ctx.arrays.insert("volts".try_into_heapless().unwrap(), volts).unwrap();
+/// ctx.arrays.insert("celsius".try_into_heapless().unwrap(), celsius).unwrap();
+/// ctx.set_parameter("adc", 1.5).unwrap();
+/// let ctx = Rc::new(ctx);
+///
+/// assert_eq!(interp("lut(adc, volts, celsius)", Some(ctx.clone())).unwrap(), 12.5);
+/// assert_eq!(interp("lut(3.5, volts, celsius)", Some(ctx.clone())).unwrap(), 60.0);
+/// assert_eq!(interp("lut(3.5, volts, celsius, 1)", Some(ctx.clone())).unwrap(), 77.5);
+/// assert!(interp("lut(1, volts, kelvin)", Some(ctx)).is_err());
+/// ```
+pub fn lut(x: Real, xs: &[Real], ys: &[Real], extrapolate: bool) -> Real {
+    let n = xs.len();
+    if n == 0 || n != ys.len() || x.is_nan() {
+        return Real::NAN;
+    }
+    if n == 1 {
+        return ys[0];
+    }
+    // Segment whose end points enclose `x`, or the first or last one outside the table
+    let upper = xs.partition_point(|&p| p < x).clamp(1, n - 1);
+    let (x0, x1, y0, y1) = (xs[upper - 1], xs[upper], ys[upper - 1], ys[upper]);
+    if !extrapolate {
+        if x < xs[0] {
+            return ys[0];
+        }
+        if x > xs[n - 1] {
+            return ys[n - 1];
+        }
+    }
+    if x1 == x0 {
+        return y1;
+    }
+    y0 + (x - x0) * (y1 - y0) / (x1 - x0)
+}
+
+/// Length of the vector `(a, b)`, `sqrt(a² + b²)`, without intermediate overflow or
+/// underflow.
+#[cfg(any(feature = "libm", test))]
+pub fn hypot(a: Real, b: Real) -> Real {
+    libm_hypot(a, b)
+}
+
+#[cfg(all(not(feature = "libm"), not(test)))]
+pub fn hypot(_: Real, _: Real) -> Real {
+    panic!("hypot requires libm or custom implementation")
+}
+
+/// The magnitude of `a` with the sign of `b`.
+#[cfg(any(feature = "libm", test))]
+pub fn copysign(a: Real, b: Real) -> Real {
+    libm_copysign(a, b)
+}
+
+#[cfg(all(not(feature = "libm"), not(test)))]
+pub fn copysign(_: Real, _: Real) -> Real {
+    panic!("copysign requires libm or custom implementation")
+}
+
+/// Fused multiply-add `a * b + c`, computed with a single rounding.
+#[cfg(any(feature = "libm", test))]
+pub fn fma(a: Real, b: Real, c: Real) -> Real {
+    libm_fma(a, b, c)
+}
+
+#[cfg(all(not(feature = "libm"), not(test)))]
+pub fn fma(_: Real, _: Real, _: Real) -> Real {
+    panic!("fma requires libm or custom implementation")
+}
+
+/// Gamma function Γ(a).
+#[cfg(feature = "special-functions")]
+pub fn tgamma(a: Real, _: Real) -> Real {
+    libm_tgamma(a)
+}
+
+/// Natural logarithm of the absolute value of the gamma function, ln|Γ(a)|.
+#[cfg(feature = "special-functions")]
+pub fn lgamma(a: Real, _: Real) -> Real {
+    libm_lgamma(a)
+}
+
+/// Error function erf(a).
+#[cfg(feature = "special-functions")]
+pub fn erf(a: Real, _: Real) -> Real {
+    libm_erf(a)
+}
+
+/// Complementary error function erfc(a) = 1 - erf(a), accurate for large `a`.
+#[cfg(feature = "special-functions")]
+pub fn erfc(a: Real, _: Real) -> Real {
+    libm_erfc(a)
+}
+
+/// Bessel function of the first kind of order 0, J₀(a).
+#[cfg(feature = "special-functions")]
+pub fn j0(a: Real, _: Real) -> Real {
+    libm_j0(a)
+}
+
+/// Bessel function of the first kind of order 1, J₁(a).
+#[cfg(feature = "special-functions")]
+pub fn j1(a: Real, _: Real) -> Real {
+    libm_j1(a)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_trunc_fract_and_quantize() {
+        assert_eq!(trunc(2.7, 0.0), 2.0);
+        assert_eq!(trunc(-2.7, 0.0), -2.0);
+        assert_eq!(fract(2.75, 0.0), 0.75);
+        assert_eq!(fract(-2.75, 0.0), -0.75);
+
+        assert_eq!(round_to(1.12, 0.25), 1.0);
+        assert_eq!(round_to(1.125, 0.25), 1.25);
+        assert_eq!(round_to(-1.125, 0.25), -1.25);
+        assert_eq!(quantize(1.3, 0.25, 1.0), 1.25);
+        assert_eq!(quantize(1.3, 0.25, 2.0), 1.5);
+        assert_eq!(quantize(-1.3, 0.25, 3.0), -1.25);
+        assert!(quantize(1.0, 0.0, 0.0).is_nan());
+        assert!(quantize(1.0, 0.25, 4.0).is_nan());
+    }
+
+    #[test]
+    fn test_saturating_and_wrapping() {
+        assert_eq!(sat_add(90.0, 20.0, 0.0, 100.0), 100.0);
+        assert_eq!(sat_add(-5.0, 2.0, 0.0, 100.0), 0.0);
+        assert_eq!(sat_add(40.0, 2.0, 0.0, 100.0), 42.0);
+        assert_eq!(sat_add(Real::INFINITY, 1.0, 0.0, 1.0), 1.0);
+        assert!(sat_add(Real::NAN, 1.0, 0.0, 1.0).is_nan());
+        assert!(sat_add(1.0, 1.0, 1.0, 0.0).is_nan());
+
+        assert_eq!(wrap(370.0, 0.0, 360.0), 10.0);
+        assert_eq!(wrap(-1.0, 0.0, 360.0), 359.0);
+        assert_eq!(wrap(360.0, 0.0, 360.0), 0.0);
+        assert_eq!(wrap(7.0, 2.0, 5.0), 4.0);
+        assert!(wrap(1.0, 5.0, 5.0).is_nan());
+        assert!(wrap(Real::INFINITY, 0.0, 1.0).is_nan());
+
+        let pi = core::f64::consts::PI as Real;
+        assert!((angle_wrap(1.5 * pi) + 0.5 * pi).abs() < 1e-6);
+        assert!((angle_wrap(-2.5 * pi) + 0.5 * pi).abs() < 1e-6);
+        assert_eq!(angle_wrap(pi), -pi);
+        assert_eq!(angle_wrap(0.25), 0.25);
+        // Near-multiples of the width stay inside the range
+        assert!(wrap(-1e-17, 0.0, 1.0) < 1.0);
+        assert_eq!(
+            crate::engine::interp(
+                "wrap(370, 0, 360) + sat_add(1, 2, 0, 2) + angle_wrap(0)",
+                None
+            )
+            .unwrap(),
+            12.0
+        );
+    }
+
+    #[test]
+    fn test_bit_functions() {
+        assert_eq!(bitand(12.0, 10.0), 8.0);
+        assert_eq!(bitor(12.0, 10.0), 14.0);
+        assert_eq!(bitxor(12.0, 10.0), 6.0);
+        assert_eq!(bitnot(0.0), -1.0);
+        assert_eq!(bitand(-1.0, 255.0), 255.0);
+        assert!(bitand(1.5, 1.0).is_nan());
+        assert!(bitnot(Real::INFINITY).is_nan());
+
+        assert_eq!(getbit(5.0, 0.0), 1.0);
+        assert_eq!(getbit(5.0, 1.0), 0.0);
+        assert_eq!(getbit(-1.0, 63.0), 1.0);
+        assert!(getbit(5.0, 64.0).is_nan());
+        assert!(getbit(5.0, -1.0).is_nan());
+
+        assert_eq!(setbit(0.0, 3.0, 1.0), 8.0);
+        assert_eq!(setbit(15.0, 0.0, 0.0), 14.0);
+        assert_eq!(setbit(8.0, 3.0, 1.0), 8.0);
+        assert_eq!(setbit(0.0, 63.0, 1.0), -(2.0 as Real).powi(63));
+        assert!(setbit(0.0, 3.0, 0.5).is_nan());
+
+        assert_eq!(popcount(0.0), 0.0);
+        assert_eq!(popcount(255.0), 8.0);
+        assert_eq!(popcount(-1.0), 64.0);
+        assert!(popcount(Real::NAN).is_nan());
+
+        // Decoding a status register
+        assert_eq!(
+            crate::engine::interp(
+                "getbit(32773, 15) + popcount(bitand(32773, 255)) + bitor(setbit(0, 4, 1), 1)",
+                None
+            )
+            .unwrap(),
+            20.0
+        );
+    }
+
+    #[test]
+    fn test_coalesce() {
+        assert_eq!(coalesce([Real::NAN, Real::INFINITY, 2.0, 3.0]), 2.0);
+        assert_eq!(coalesce([1.0]), 1.0);
+        assert!(coalesce([Real::NAN, Real::NEG_INFINITY]).is_nan());
+        assert!(coalesce([]).is_nan());
+    }
+
+    #[test]
+    fn test_hypot_copysign_and_fma() {
+        assert_eq!(hypot(3.0, 4.0), 5.0);
+        // The naive sqrt(a*a + b*b) overflows here
+        let big = Real::MAX / 2.0;
+        assert!(hypot(big, big).is_finite());
+        assert_eq!(copysign(2.5, -0.0), -2.5);
+        assert_eq!(copysign(-2.5, 1.0), 2.5);
+        assert_eq!(fma(2.0, 3.0, 4.0), 10.0);
+
+        // (1 + ε)(1 - ε) - 1 = -ε², which is lost when the product is rounded first
+        let eps = Real::EPSILON;
+        assert_eq!(fma(1.0 + eps, 1.0 - eps, -1.0), -eps * eps);
+    }
+
+    #[test]
+    fn test_log_base_log1p_and_expm1() {
+        assert!((log_base(8.0, 2.0) - 3.0).abs() < 1e-6);
+        assert!((log_base(1000.0, 10.0) - 3.0).abs() < 1e-6);
+        assert!(log_base(8.0, 1.0).is_nan());
+        assert!(log_base(8.0, -2.0).is_nan());
+        assert!(log_base(-8.0, 2.0).is_nan());
+
+        assert_eq!(log2(1024.0, 0.0), 10.0);
+        assert!(log2(0.0, 0.0).is_nan());
+
+        // For tiny x, ln(1 + x) loses x entirely in f32 while log1p keeps it
+        let x: Real = 1e-10;
+        assert!((log1p(x, 0.0) / x - 1.0).abs() < 1e-6);
+        assert!((expm1(x, 0.0) / x - 1.0).abs() < 1e-6);
+        assert_eq!(log1p(0.0, 0.0), 0.0);
+        assert!(log1p(-2.0, 0.0).is_nan());
+    }
+
+    #[cfg(feature = "special-functions")]
+    #[test]
+    fn test_special_functions() {
+        assert!((tgamma(5.0, 0.0) - 24.0).abs() < 1e-4);
+        assert!((lgamma(10.0, 0.0) - 12.801827).abs() < 1e-4);
+        assert!((erf(1.0, 0.0) - 0.842700).abs() < 1e-5);
+        assert!((erfc(1.0, 0.0) - 0.157299).abs() < 1e-5);
+        assert_eq!(j0(0.0, 0.0), 1.0);
+        assert!((j1(1.0, 0.0) - 0.440051).abs() < 1e-5);
+    }
+
     #[test]
     #[should_panic(expected = "called dummy!")]
     fn test_dummy_panics() {
@@ -963,6 +1572,24 @@ mod tests {
         assert!((pi(0.0, 0.0) - core::f64::consts::PI).abs() < 1e-10);
     }
 
+    #[test]
+    fn test_lut() {
+        let xs = [0.0, 10.0, 20.0];
+        let ys = [100.0, 50.0, 40.0];
+        assert_eq!(lut(5.0, &xs, &ys, false), 75.0);
+        assert_eq!(lut(10.0, &xs, &ys, false), 50.0);
+        assert_eq!(lut(15.0, &xs, &ys, false), 45.0);
+        assert_eq!(lut(-5.0, &xs, &ys, false), 100.0);
+        assert_eq!(lut(30.0, &xs, &ys, false), 40.0);
+        assert_eq!(lut(-5.0, &xs, &ys, true), 125.0);
+        assert_eq!(lut(30.0, &xs, &ys, true), 30.0);
+        // A repeated x makes a step
+        assert_eq!(lut(10.0, &[0.0, 10.0, 10.0], &[0.0, 1.0, 5.0], false), 1.0);
+        assert_eq!(lut(1.0, &[2.0], &[7.0], true), 7.0);
+        assert!(lut(1.0, &xs, &ys[..2], false).is_nan());
+        assert!(lut(Real::NAN, &xs, &ys, false).is_nan());
+    }
+
     #[test]
     fn test_pow() {
         assert_eq!(pow(2.0, 3.0), 8.0);
@@ -0,0 +1,313 @@
+//! Generators of valid expressions and inputs for fuzzing, with the `fuzzing` feature.
+//!
+//! A [`Generator`] turns fuzzer-provided bytes into an AST or expression text that
+//! always parses, so a fuzzer spends its time in evaluation instead of rejected syntax.
+//! The generator only uses the variables, functions and arrays it is given, which by
+//! default are `x`, `y`, `z` and the builtins. [`Generator::for_context`] takes them from
+//! a context instead, so custom native functions are called with generated arguments.
+//! [`ByteSource::real`] draws values including zeros, infinities, NaN and the extremes
+//! of `Real`, for calling native functions directly.
+//!
+//! The same bytes always give the same expression, and running out of bytes ends the
+//! expression early instead of failing, as coverage-guided fuzzers such as cargo-fuzz
+//! expect. The `fuzz` directory of the repository holds cargo-fuzz targets for the
+//! parser and the evaluator built on these generators.
+//!
+//! ```
+//! use exp_rs::EvalContext;
+//! use exp_rs::engine::{interp, parse_expression};
+//! use exp_rs::fuzzing::{ByteSource, Generator};
+//! use bumpalo::Bump;
+//! use std::rc::Rc;
+//!
+//! let mut ctx = EvalContext::new();
+//! ctx.register_native_function("clamp01", 1, |args| args[0].clamp(0.0, 1.0)).unwrap();
+//! ctx.set_parameter("x", 0.25).unwrap();
+//! let generator = Generator::for_context(&ctx);
+//! let ctx = Rc::new(ctx);
+//!
+//! // In a fuzz target, `data` is the input of the fuzzer
+//! let data = [7u8, 42, 3, 200, 19, 88, 5, 61, 140, 9, 77, 23];
+//! let expression = generator.expression(&mut ByteSource::new(&data));
+//! assert!(parse_expression(&expression, &Bump::new()).is_ok());
+//! // The result may be an error, e.g. for an out-of-range argument, but never a panic
+//! let _ = interp(&expression, Some(ctx));
+//!
+//! // Or call a native function with generated arguments
+//! let mut source = ByteSource::new(&data);
+//! let value = source.real().clamp(0.0, 1.0);
+//! assert!(value.is_nan() || (0.0..=1.0).contains(&value));
+//! ```
+
+use crate::Real;
+use crate::context::EvalContext;
+use crate::types::{AstExpr, FunctionSignature, LogicalOperator};
+use alloc::string::String;
+use alloc::vec::Vec;
+use bumpalo::Bump;
+
+/// Bytes from a fuzzer, consumed by the generators.
+///
+/// Once the bytes are used up every draw returns zero, which the generators map to
+/// their simplest choice, so generation always terminates.
+#[derive(Debug, Clone)]
+pub struct ByteSource<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> ByteSource<'a> {
+    /// Creates a source drawing from `data`.
+    pub fn new(data: &'a [u8]) -> Self {
+        ByteSource { data }
+    }
+
+    /// Returns whether all bytes have been drawn.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Draws a byte, or zero once the bytes are used up.
+    pub fn byte(&mut self) -> u8 {
+        match self.data.split_first() {
+            Some((&byte, rest)) => {
+                self.data = rest;
+                byte
+            }
+            None => 0,
+        }
+    }
+
+    /// Draws a number below `n`, which must not be zero.
+    pub fn below(&mut self, n: usize) -> usize {
+        if n <= 256 {
+            self.byte() as usize % n
+        } else {
+            (self.byte() as usize | (self.byte() as usize) << 8) % n
+        }
+    }
+
+    /// Draws a value, favoring those that trip up numeric code: zeros of both signs,
+    /// infinities, NaN, the extremes of `Real` and small integers.
+    pub fn real(&mut self) -> Real {
+        const SPECIAL: [Real; 8] = [
+            Real::NAN,
+            Real::INFINITY,
+            Real::NEG_INFINITY,
+            -0.0,
+            Real::MAX,
+            Real::MIN,
+            Real::MIN_POSITIVE,
+            Real::EPSILON,
+        ];
+        match self.byte() {
+            tag @ 0..=7 => SPECIAL[tag as usize],
+            8..=15 => {
+                let mut bits = [0u8; core::mem::size_of::<Real>()];
+                for byte in &mut bits {
+                    *byte = self.byte();
+                }
+                Real::from_le_bytes(bits)
+            }
+            _ => self.finite_real(),
+        }
+    }
+
+    /// Draws a finite value: a small integer or a multiple of 1/16, of either sign.
+    pub fn finite_real(&mut self) -> Real {
+        let value = match self.byte() {
+            tag @ 0..=127 => (tag % 16) as Real,
+            _ => self.byte() as Real / 16.0,
+        };
+        if self.byte() & 1 == 1 { -value } else { value }
+    }
+}
+
+/// Binary operators drawn by the generator, all builtin functions.
+const OPERATORS: [&str; 12] = [
+    "+", "-", "*", "/", "%", "^", "<", ">", "<=", ">=", "==", "!=",
+];
+
+/// Generator of valid expressions over a set of variables, functions and arrays.
+#[derive(Debug, Clone)]
+pub struct Generator {
+    /// Maximum nesting depth of generated ASTs.
+    pub max_depth: usize,
+    /// Names of the variables to reference.
+    pub variables: Vec<String>,
+    /// Functions to call, with as many arguments as their arity (up to two more when
+    /// variadic).
+    pub functions: Vec<FunctionSignature>,
+    /// Names of the arrays to index.
+    pub arrays: Vec<String>,
+}
+
+impl Default for Generator {
+    /// A generator over the variables `x`, `y` and `z` and the builtin functions.
+    fn default() -> Self {
+        let mut generator = Generator::for_context(&EvalContext::new());
+        generator.variables = ["x", "y", "z"].map(String::from).to_vec();
+        generator
+    }
+}
+
+impl Generator {
+    /// Creates a generator over the variables and native functions of `ctx` and its
+    /// parents, with a maximum depth of 6.
+    pub fn for_context(ctx: &EvalContext) -> Self {
+        Generator {
+            max_depth: 6,
+            variables: ctx.list_variables(),
+            functions: ctx
+                .list_native_functions()
+                .iter()
+                .filter(|name| name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+                .filter_map(|name| ctx.function_signature(name))
+                .collect(),
+            arrays: Vec::new(),
+        }
+    }
+
+    /// Generates an AST from `source` in `arena`.
+    pub fn ast<'arena>(&self, source: &mut ByteSource, arena: &'arena Bump) -> AstExpr<'arena> {
+        self.node(source, arena, 0)
+    }
+
+    /// Generates expression text from `source`: the AST [`ast`] would generate,
+    /// rendered with [`AstExpr::to_expression_string`].
+    ///
+    /// [`ast`]: Generator::ast
+    pub fn expression(&self, source: &mut ByteSource) -> String {
+        self.ast(source, &Bump::new()).to_expression_string()
+    }
+
+    fn node<'arena>(
+        &self,
+        source: &mut ByteSource,
+        arena: &'arena Bump,
+        depth: usize,
+    ) -> AstExpr<'arena> {
+        let choice = if depth >= self.max_depth {
+            source.below(2)
+        } else {
+            source.below(8)
+        };
+        let child = |source: &mut ByteSource| -> &'arena AstExpr<'arena> {
+            arena.alloc(self.node(source, arena, depth + 1))
+        };
+        match choice {
+            1 if !self.variables.is_empty() => {
+                let name = &self.variables[source.below(self.variables.len())];
+                AstExpr::Variable(arena.alloc_str(name))
+            }
+            2 => AstExpr::Function {
+                name: "neg",
+                args: core::slice::from_ref(child(source)),
+            },
+            3 | 4 => {
+                let op = OPERATORS[source.below(OPERATORS.len())];
+                let left = child(source).clone();
+                let right = child(source).clone();
+                AstExpr::Function {
+                    name: op,
+                    args: arena.alloc_slice_clone(&[left, right]),
+                }
+            }
+            5 if !self.functions.is_empty() => {
+                let function = &self.functions[source.below(self.functions.len())];
+                let count = if function.variadic {
+                    function.arity.max(1) + source.below(3)
+                } else {
+                    function.arity
+                };
+                let args: Vec<AstExpr<'arena>> =
+                    (0..count).map(|_| child(source).clone()).collect();
+                AstExpr::Function {
+                    name: arena.alloc_str(&function.name),
+                    args: arena.alloc_slice_clone(&args),
+                }
+            }
+            6 => AstExpr::LogicalOp {
+                op: if source.byte() & 1 == 0 {
+                    LogicalOperator::And
+                } else {
+                    LogicalOperator::Or
+                },
+                left: child(source),
+                right: child(source),
+            },
+            7 if !self.arrays.is_empty() => AstExpr::Array {
+                name: arena.alloc_str(&self.arrays[source.below(self.arrays.len())]),
+                index: child(source),
+            },
+            7 => AstExpr::Conditional {
+                condition: child(source),
+                true_branch: child(source),
+                false_branch: child(source),
+            },
+            // Constants are finite so that the expression text parses back
+            _ => AstExpr::Constant(source.finite_real()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::parse_expression;
+    use crate::error::ExprError;
+    use crate::eval::eval_ast;
+    use alloc::rc::Rc;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_generated_expressions_parse_and_evaluate() {
+        let mut ctx = EvalContext::new();
+        for name in ["x", "y", "z"] {
+            ctx.set_parameter(name, 0.5).unwrap();
+        }
+        ctx.set_array("samples", alloc::vec![1.0, 2.0, 3.0])
+            .unwrap();
+        let ctx = Rc::new(ctx);
+        let mut generator = Generator::default();
+        generator.arrays.push("samples".to_string());
+
+        // Deterministic pseudo-random inputs of varying length
+        let mut state = 0x2545_f491_u32;
+        for length in 0..500 {
+            let data: Vec<u8> = (0..length % 97)
+                .map(|_| {
+                    state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                    (state >> 24) as u8
+                })
+                .collect();
+            let arena = Bump::new();
+            let expression = generator.expression(&mut ByteSource::new(&data));
+            let ast = parse_expression(&expression, &arena)
+                .unwrap_or_else(|e| panic!("'{expression}' does not parse: {e}"));
+            // Printing the parsed tree parses back to the same tree
+            let reparsed = parse_expression(&ast.to_expression_string(), &arena).unwrap();
+            assert_eq!(reparsed, ast, "'{expression}' does not round-trip");
+            // Same bytes, same expression
+            assert_eq!(
+                generator.expression(&mut ByteSource::new(&data)),
+                expression
+            );
+            match eval_ast(&ast, Some(ctx.clone()), &arena) {
+                Err(ExprError::UnknownFunction { name })
+                | Err(ExprError::UnknownVariable { name }) => {
+                    panic!("'{expression}' uses unknown '{name}'")
+                }
+                _ => {}
+            }
+        }
+
+        let mut source = ByteSource::new(&[0, 9, 1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(source.real().is_nan());
+        assert_eq!(
+            source.real().to_bits(),
+            Real::from_le_bytes([1, 2, 3, 4, 5, 6, 7, 8]).to_bits()
+        );
+        assert!(source.is_empty());
+        assert_eq!((source.byte(), source.finite_real()), (0, 0.0));
+    }
+}
@@ -0,0 +1,281 @@
+//! Dependency graphs of expression batches.
+//!
+//! [`Expression::dependency_graph`](crate::expression::Expression::dependency_graph)
+//! returns a [`DependencyGraph`] whose nodes are the expressions of a batch and the
+//! inputs they read: batch parameters and the variables they take from the evaluation
+//! context. An edge runs from each input or named expression to every expression that
+//! references it. The graph can be written as Graphviz DOT or as JSON for display, and
+//! [`DependencyGraph::inputs`] lists everything an expression depends on, directly or
+//! through other expressions.
+//!
+//! ```
+//! use bumpalo::Bump;
+//! use exp_rs::expression::Expression;
+//! use exp_rs::graph::Node;
+//!
+//! let arena = Bump::new();
+//! let mut batch = Expression::new(&arena);
+//! batch.add_parameter("limit", 80.0).unwrap();
+//! batch.add_named_expression("temp", "(raw_a + raw_b) / 2").unwrap();
+//! let alarm = batch.add_expression("temp > limit").unwrap();
+//!
+//! let graph = batch.dependency_graph();
+//! let alarm = graph.expression_node(alarm).unwrap();
+//! let mut inputs: Vec<&str> = graph
+//!     .inputs(alarm)
+//!     .into_iter()
+//!     .filter_map(|id| match &graph.nodes[id] {
+//!         Node::Variable { name } => Some(name.as_str()),
+//!         _ => None,
+//!     })
+//!     .collect();
+//! inputs.sort();
+//! assert_eq!(inputs, ["raw_a", "raw_b"]);
+//! assert!(graph.to_dot().contains("e0 -> e1;"));
+//! ```
+
+use crate::eval::explain::write_json_string;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+/// A node of a [`DependencyGraph`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node {
+    /// An expression of the batch
+    Expression {
+        /// Index of the expression in the batch
+        index: usize,
+        /// Name of the expression, if added with `add_named_expression`
+        name: Option<String>,
+        /// Expression text
+        expression: String,
+    },
+    /// A parameter of the batch
+    Parameter {
+        /// Index of the parameter in the batch
+        index: usize,
+        /// Parameter name
+        name: String,
+    },
+    /// A variable read from the evaluation context; array elements and attributes
+    /// are named by the array and by the attribute path
+    Variable {
+        /// Variable name
+        name: String,
+    },
+}
+
+impl Node {
+    /// Returns the text shown for the node: the name of a named expression or of an
+    /// input, or the text of an unnamed expression.
+    pub fn label(&self) -> &str {
+        match self {
+            Node::Expression {
+                name: Some(name), ..
+            } => name,
+            Node::Expression { expression, .. } => expression,
+            Node::Parameter { name, .. } | Node::Variable { name } => name,
+        }
+    }
+
+    /// Returns the identifier of the node in DOT and JSON output: `e<index>` for
+    /// expressions, `p<index>` for parameters and `v<position>` for variables.
+    fn id(&self, position: usize) -> String {
+        match self {
+            Node::Expression { index, .. } => alloc::format!("e{}", index),
+            Node::Parameter { index, .. } => alloc::format!("p{}", index),
+            Node::Variable { .. } => alloc::format!("v{}", position),
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Node::Expression { .. } => "expression",
+            Node::Parameter { .. } => "parameter",
+            Node::Variable { .. } => "variable",
+        }
+    }
+}
+
+/// Dependencies between the expressions of a batch and their inputs.
+///
+/// Batches whose named expressions reference each other in a cycle still have a
+/// graph, which shows the cycle.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DependencyGraph {
+    /// Expressions in batch order, followed by the parameters in batch order and the
+    /// context variables in order of first use
+    pub nodes: Vec<Node>,
+    /// `(from, to)` pairs of node positions, where the expression `to` reads `from`
+    pub edges: Vec<(usize, usize)>,
+}
+
+impl DependencyGraph {
+    /// Returns the node position of the expression with batch index `index`.
+    pub fn expression_node(&self, index: usize) -> Option<usize> {
+        self.nodes
+            .iter()
+            .position(|n| matches!(n, Node::Expression { index: i, .. } if *i == index))
+    }
+
+    /// Returns the parameters and variables that `node` depends on, directly or through
+    /// named expressions, as node positions in ascending order.
+    pub fn inputs(&self, node: usize) -> Vec<usize> {
+        let mut seen = vec![false; self.nodes.len()];
+        let mut pending = vec![node];
+        while let Some(to) = pending.pop() {
+            for &(from, _) in self.edges.iter().filter(|&&(_, t)| t == to) {
+                if !seen[from] {
+                    seen[from] = true;
+                    pending.push(from);
+                }
+            }
+        }
+        (0..self.nodes.len())
+            .filter(|&i| seen[i] && !matches!(self.nodes[i], Node::Expression { .. }))
+            .collect()
+    }
+
+    /// Writes the graph in Graphviz DOT.
+    ///
+    /// Nodes are labelled as by [`Node::label`]; expressions are boxes, parameters
+    /// ellipses and context variables diamonds.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph dependencies {\n");
+        for (i, node) in self.nodes.iter().enumerate() {
+            let shape = match node {
+                Node::Expression { .. } => "box",
+                Node::Parameter { .. } => "ellipse",
+                Node::Variable { .. } => "diamond",
+            };
+            let _ = writeln!(
+                out,
+                "    {} [label={}, shape={}];",
+                node.id(i),
+                dot_string(node.label()),
+                shape
+            );
+        }
+        for &(from, to) in &self.edges {
+            let _ = writeln!(
+                out,
+                "    {} -> {};",
+                self.nodes[from].id(from),
+                self.nodes[to].id(to)
+            );
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Serializes the graph as JSON.
+    ///
+    /// The object has a `nodes` array, whose entries have the keys `id`, `kind`
+    /// (`expression`, `parameter` or `variable`) and `label`, plus `index` for
+    /// expressions and parameters and `expression` for expressions; and an `edges`
+    /// array of `{"from": id, "to": id}` objects.
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("{\"nodes\":[");
+        for (i, node) in self.nodes.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str("{\"id\":");
+            write_json_string(&mut out, &node.id(i));
+            let _ = write!(out, ",\"kind\":\"{}\",\"label\":", node.kind());
+            write_json_string(&mut out, node.label());
+            match node {
+                Node::Expression {
+                    index, expression, ..
+                } => {
+                    let _ = write!(out, ",\"index\":{},\"expression\":", index);
+                    write_json_string(&mut out, expression);
+                }
+                Node::Parameter { index, .. } => {
+                    let _ = write!(out, ",\"index\":{}", index);
+                }
+                Node::Variable { .. } => {}
+            }
+            out.push('}');
+        }
+        out.push_str("],\"edges\":[");
+        for (i, &(from, to)) in self.edges.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str("{\"from\":");
+            write_json_string(&mut out, &self.nodes[from].id(from));
+            out.push_str(",\"to\":");
+            write_json_string(&mut out, &self.nodes[to].id(to));
+            out.push('}');
+        }
+        out.push_str("]}");
+        out
+    }
+}
+
+/// Quotes `s` as a DOT string.
+fn dot_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::expression::Expression;
+    use bumpalo::Bump;
+
+    #[test]
+    fn test_dependency_graph_export() {
+        let arena = Bump::new();
+        let mut batch = Expression::new(&arena);
+        batch.add_parameter("gain", 2.0).unwrap();
+        batch.add_parameter("unused", 0.0).unwrap();
+        batch
+            .add_named_expression("scaled", "sensor[1] * gain + pi")
+            .unwrap();
+        batch.add_expression("scaled > motor.temp").unwrap();
+
+        let graph = batch.dependency_graph();
+        let labels: alloc::vec::Vec<&str> = graph.nodes.iter().map(|n| n.label()).collect();
+        assert_eq!(
+            labels,
+            [
+                "scaled",
+                "scaled > motor.temp",
+                "gain",
+                "unused",
+                "sensor",
+                "motor.temp"
+            ]
+        );
+        assert_eq!(graph.edges, [(2, 0), (4, 0), (0, 1), (5, 1)]);
+        assert_eq!(graph.inputs(1), [2, 4, 5]);
+        assert!(graph.inputs(2).is_empty());
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph dependencies {\n"));
+        assert!(dot.contains("    p0 [label=\"gain\", shape=ellipse];\n"));
+        assert!(dot.contains("    e0 -> e1;\n"));
+
+        let json = graph.to_json();
+        assert!(json.starts_with(
+            "{\"nodes\":[{\"id\":\"e0\",\"kind\":\"expression\",\"label\":\"scaled\",\
+             \"index\":0,\"expression\":\"sensor[1] * gain + pi\"}"
+        ));
+        assert!(json.contains("{\"id\":\"v5\",\"kind\":\"variable\",\"label\":\"motor.temp\"}"));
+        assert!(json.ends_with("{\"from\":\"v5\",\"to\":\"e1\"}]}"));
+    }
+}
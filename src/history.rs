@@ -0,0 +1,220 @@
+//! Ring buffers of recent samples, read by the `history(name, n)` and `window_*`
+//! builtins.
+//!
+//! [`EvalContext::add_history`] registers a [`History`] keeping the last `capacity`
+//! samples of a signal and returns a handle through which the host pushes samples, also
+//! after the context is shared. Expressions then read it by name:
+//!
+//! - `history(name, n)`: the sample `n` samples before the latest (`n = 0` is the latest);
+//! - `window_mean(name, n)`, `window_min(name, n)`, `window_max(name, n)` and
+//!   `window_sum(name, n)`: over the latest `n` samples.
+//!
+//! These are NaN until enough samples were pushed, so conditions such as
+//! `window_mean(temp, 100) > limit` stay false while the buffer fills.
+//!
+//! ```
+//! use exp_rs::context::EvalContext;
+//! use exp_rs::engine::interp;
+//! use std::rc::Rc;
+//!
+//! let mut ctx = EvalContext::new();
+//! let temp = ctx.add_history("temp", 100).unwrap();
+//! let ctx = Rc::new(ctx);
+//!
+//! for sample in [20.0, 21.0, 25.0] {
+//!     temp.push(sample);
+//! }
+//! assert_eq!(interp("history(temp, 0) - history(temp, 2)", Some(ctx.clone())).unwrap(), 5.0);
+//! assert_eq!(interp("window_mean(temp, 2) > 22", Some(ctx.clone())).unwrap(), 1.0);
+//! assert!(interp("window_mean(temp, 100)", Some(ctx)).unwrap().is_nan());
+//! ```
+//!
+//! [`EvalContext::add_history`]: crate::context::EvalContext::add_history
+
+use crate::Real;
+use alloc::collections::VecDeque;
+use core::cell::RefCell;
+
+/// The latest samples of a signal, oldest first, up to a fixed capacity.
+#[derive(Debug)]
+pub struct History {
+    samples: RefCell<VecDeque<Real>>,
+    capacity: usize,
+}
+
+impl History {
+    /// Creates an empty history keeping up to `capacity` samples. The storage is
+    /// allocated here, so pushing samples does not allocate.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: RefCell::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    /// Appends a sample, dropping the oldest one if the history is full.
+    pub fn push(&self, value: Real) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut samples = self.samples.borrow_mut();
+        if samples.len() == self.capacity {
+            samples.pop_front();
+        }
+        samples.push_back(value);
+    }
+
+    /// Removes all samples.
+    pub fn clear(&self) {
+        self.samples.borrow_mut().clear();
+    }
+
+    /// Returns the number of samples held.
+    pub fn len(&self) -> usize {
+        self.samples.borrow().len()
+    }
+
+    /// Returns `true` if no samples were pushed since the history was created or cleared.
+    pub fn is_empty(&self) -> bool {
+        self.samples.borrow().is_empty()
+    }
+
+    /// Returns the maximum number of samples held.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the sample `ago` samples before the latest, if there is one.
+    pub fn get(&self, ago: usize) -> Option<Real> {
+        let samples = self.samples.borrow();
+        samples
+            .len()
+            .checked_sub(ago + 1)
+            .map(|index| samples[index])
+    }
+
+    /// Folds the latest `n` samples with `f`, or returns `None` if fewer were pushed or
+    /// `n` is zero.
+    fn fold_window(&self, n: usize, f: impl Fn(Real, Real) -> Real) -> Option<Real> {
+        let samples = self.samples.borrow();
+        let start = samples.len().checked_sub(n).filter(|_| n > 0)?;
+        samples.range(start..).copied().reduce(f)
+    }
+}
+
+/// The builtins reading a [`History`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryOp {
+    /// `history(name, n)`: the sample `n` samples before the latest
+    Ago,
+    /// `window_mean(name, n)`
+    Mean,
+    /// `window_min(name, n)`
+    Min,
+    /// `window_max(name, n)`
+    Max,
+    /// `window_sum(name, n)`
+    Sum,
+}
+
+impl HistoryOp {
+    /// Returns the builtin called by `name` with `arg_count` arguments, if any.
+    pub fn from_call(name: &str, arg_count: usize) -> Option<Self> {
+        if arg_count != 2 {
+            return None;
+        }
+        match name {
+            "history" => Some(Self::Ago),
+            "window_mean" => Some(Self::Mean),
+            "window_min" => Some(Self::Min),
+            "window_max" => Some(Self::Max),
+            "window_sum" => Some(Self::Sum),
+            _ => None,
+        }
+    }
+
+    /// Applies the builtin to `history` with the sample count `n`, giving NaN if `n` is
+    /// not a count of available samples.
+    pub fn apply(self, history: &History, n: Real) -> Real {
+        let count = n as usize;
+        if !(n >= 0.0 && count as Real == n) {
+            return Real::NAN;
+        }
+        let n = count;
+        let value = match self {
+            Self::Ago => history.get(n),
+            Self::Mean => history
+                .fold_window(n, |a, b| a + b)
+                .map(|sum| sum / n as Real),
+            Self::Min => history.fold_window(n, Real::min),
+            Self::Max => history.fold_window(n, Real::max),
+            Self::Sum => history.fold_window(n, |a, b| a + b),
+        };
+        value.unwrap_or(Real::NAN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::EvalContext;
+    use crate::engine::interp;
+    use crate::error::ExprError;
+    use alloc::rc::Rc;
+
+    #[test]
+    fn test_history_builtins() {
+        let history = History::new(4);
+        assert!(history.is_empty());
+        for sample in 1..=6 {
+            history.push(sample as Real);
+        }
+        // The two oldest samples were dropped
+        assert_eq!((history.len(), history.capacity()), (4, 4));
+        assert_eq!(history.get(0), Some(6.0));
+        assert_eq!(history.get(3), Some(3.0));
+        assert_eq!(history.get(4), None);
+
+        let mut parent = EvalContext::new();
+        let speed = parent.add_history("speed", 3).unwrap();
+        let mut ctx = EvalContext::new();
+        ctx.parent = Some(Rc::new(parent));
+        ctx.set_parameter("n", 2.0).unwrap();
+        let ctx = Rc::new(ctx);
+        let eval = |expr: &str| interp(expr, Some(ctx.clone()));
+
+        // Histories of parents are found, and samples pushed after sharing are seen
+        assert!(eval("history(speed, 0)").unwrap().is_nan());
+        for sample in [4.0, -2.0, 7.0, 1.0] {
+            speed.push(sample);
+        }
+        assert_eq!(eval("history(speed, 0)").unwrap(), 1.0);
+        assert_eq!(eval("history(speed, n)").unwrap(), -2.0);
+        assert_eq!(eval("window_sum(speed, 3)").unwrap(), 6.0);
+        assert_eq!(eval("window_mean(speed, n)").unwrap(), 4.0);
+        assert_eq!(
+            eval("window_min(speed, 3) + window_max(speed, 1)").unwrap(),
+            -1.0
+        );
+        for expr in [
+            "history(speed, 3)",
+            "window_mean(speed, 4)",
+            "window_max(speed, 0)",
+            "history(speed, 0.5)",
+            "history(speed, -1)",
+        ] {
+            assert!(eval(expr).unwrap().is_nan(), "{expr}");
+        }
+        speed.clear();
+        assert!(eval("window_sum(speed, 1)").unwrap().is_nan());
+
+        assert!(matches!(
+            eval("history(missing, 0)"),
+            Err(ExprError::UnknownVariable { .. })
+        ));
+        assert!(matches!(
+            eval("history(2 * n, 0)"),
+            Err(ExprError::Syntax { .. })
+        ));
+    }
+}
@@ -0,0 +1,176 @@
+//! Interning of identifiers into per-arena symbols.
+//!
+//! A [`SymbolTable`] stores each distinct name once in its arena and numbers it with a
+//! [`Symbol`]. Parsing with a table (see
+//! [`parse_expression_interned`](crate::engine::parse_expression_interned)) makes every
+//! occurrence of a name in the AST point to that single copy, so repeated identifiers
+//! do not grow the arena and interned names can be matched by address instead of by
+//! content. Batches ([`Expression`](crate::expression::Expression)) intern their
+//! expressions and parameters this way.
+//!
+//! ```
+//! use exp_rs::intern::SymbolTable;
+//! use bumpalo::Bump;
+//!
+//! let arena = Bump::new();
+//! let mut symbols = SymbolTable::new(&arena);
+//! let speed = symbols.intern("speed").unwrap();
+//! assert_eq!(symbols.intern("speed").unwrap(), speed);
+//! assert_eq!(symbols.name(speed), Some("speed"));
+//! assert_eq!(symbols.get("torque"), None);
+//! ```
+
+use crate::error::ExprError;
+use bumpalo::Bump;
+use bumpalo::collections::Vec as BumpVec;
+
+/// Number identifying an interned name within its [`SymbolTable`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    /// Returns the position of the symbol in interning order, starting at 0.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Names interned into an arena, numbered in the order they were first seen.
+///
+/// All storage lives in the arena, so interning never uses the heap.
+pub struct SymbolTable<'arena> {
+    arena: &'arena Bump,
+    /// Interned names, indexed by symbol
+    names: BumpVec<'arena, &'arena str>,
+    /// Symbols sorted by name, for lookups
+    sorted: BumpVec<'arena, Symbol>,
+}
+
+impl core::fmt::Debug for SymbolTable<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.names.iter()).finish()
+    }
+}
+
+impl<'arena> SymbolTable<'arena> {
+    /// Creates an empty table storing names in `arena`.
+    pub fn new(arena: &'arena Bump) -> Self {
+        Self {
+            arena,
+            names: BumpVec::new_in(arena),
+            sorted: BumpVec::new_in(arena),
+        }
+    }
+
+    /// Returns the symbol of `name`, interning it if it is new.
+    ///
+    /// Fails with `ExprError::CapacityExceeded` once `u32::MAX` names are interned.
+    pub fn intern(&mut self, name: &str) -> Result<Symbol, ExprError> {
+        match self.position(name) {
+            Ok(pos) => Ok(self.sorted[pos]),
+            Err(pos) => {
+                let symbol = Symbol(u32::try_from(self.names.len()).map_err(|_| {
+                    ExprError::CapacityExceeded {
+                        container: "symbol table",
+                    }
+                })?);
+                self.names.push(self.arena.alloc_str(name));
+                self.sorted.insert(pos, symbol);
+                Ok(symbol)
+            }
+        }
+    }
+
+    /// Returns the interned copy of `name`, interning it if it is new.
+    pub fn canonical(&mut self, name: &str) -> Result<&'arena str, ExprError> {
+        let symbol = self.intern(name)?;
+        Ok(self.names[symbol.index()])
+    }
+
+    /// Returns the symbol of `name` if it is interned.
+    pub fn get(&self, name: &str) -> Option<Symbol> {
+        self.position(name).ok().map(|pos| self.sorted[pos])
+    }
+
+    /// Returns the name of `symbol`, or `None` if it is not from this table.
+    pub fn name(&self, symbol: Symbol) -> Option<&'arena str> {
+        self.names.get(symbol.index()).copied()
+    }
+
+    /// Returns the number of interned names.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Returns whether no name is interned.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Iterates over the symbols and names in interning order.
+    pub fn iter(&self) -> impl Iterator<Item = (Symbol, &'arena str)> + '_ {
+        self.names
+            .iter()
+            .enumerate()
+            .map(|(i, &name)| (Symbol(i as u32), name))
+    }
+
+    fn position(&self, name: &str) -> Result<usize, usize> {
+        self.sorted
+            .binary_search_by(|symbol| self.names[symbol.index()].cmp(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{ParseOptions, parse_expression_interned};
+    use crate::types::AstExpr;
+
+    #[test]
+    fn test_interned_parsing_shares_names() {
+        let arena = Bump::new();
+        let mut symbols = SymbolTable::new(&arena);
+        let ast = parse_expression_interned(
+            "speed * speed + max(speed, gain) * gain",
+            &arena,
+            &ParseOptions::default(),
+            &mut symbols,
+        )
+        .unwrap();
+
+        // Each distinct variable, function and operator name is stored once
+        let names: alloc::vec::Vec<_> = symbols.iter().map(|(_, name)| name).collect();
+        assert_eq!(names.len(), 5);
+        for name in ["speed", "gain", "max", "*", "+"] {
+            assert!(names.contains(&name), "{name} is not interned");
+        }
+        let speed = symbols.canonical("speed").unwrap();
+        let AstExpr::Function { name: "+", args } = ast else {
+            panic!("unexpected AST {ast:?}");
+        };
+        let AstExpr::Function { args: product, .. } = &args[0] else {
+            panic!("unexpected AST {ast:?}");
+        };
+        for operand in product.iter() {
+            let AstExpr::Variable(name) = operand else {
+                panic!("unexpected operand {operand:?}");
+            };
+            assert!(core::ptr::eq(*name, speed));
+        }
+        assert_eq!(symbols.len(), 5);
+
+        // Names keep their symbol across expressions, and failed parses keep the table
+        let gain = symbols.get("gain").unwrap();
+        parse_expression_interned("gain - 1", &arena, &ParseOptions::default(), &mut symbols)
+            .unwrap();
+        assert!(
+            parse_expression_interned("gain +", &arena, &ParseOptions::default(), &mut symbols)
+                .is_err()
+        );
+        assert_eq!(symbols.get("gain"), Some(gain));
+        assert_eq!(symbols.name(gain), Some("gain"));
+        assert_eq!(symbols.len(), 6);
+        assert!(symbols.get("torque").is_none());
+    }
+}
@@ -17,10 +17,21 @@ pub struct Token {
 }
 
 /// The lexer struct, which produces tokens from an input string.
+/// Whether `c` can start an identifier: a letter of any script, or `_`.
+pub fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+/// Whether `c` can continue an identifier: a letter or digit of any script, or `_`.
+pub fn is_identifier_continue(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
 #[derive(Clone)]
 pub struct Lexer<'a> {
     input: &'a str,
     pub pos: usize,
+    max_token_length: usize,
 }
 
 impl<'a> Lexer<'a> {
@@ -28,7 +39,16 @@ impl<'a> Lexer<'a> {
         // Check for invalid UTF-8 sequences
         // This is a no-op in Rust since the &str type guarantees valid UTF-8
         // But we can check for extremely long input
-        Self { input, pos: 0 }
+        Self {
+            input,
+            pos: 0,
+            max_token_length: crate::engine::ParserLimits::DEFAULT.max_token_length,
+        }
+    }
+
+    /// Set the length in bytes above which identifiers and numbers are error tokens.
+    pub fn set_max_token_length(&mut self, max_token_length: usize) {
+        self.max_token_length = max_token_length;
     }
 
     /// Peek at the current character.
@@ -76,12 +96,11 @@ impl<'a> Lexer<'a> {
 
     /// Check if a token is too long
     fn check_token_length(&self, start_pos: usize, end_pos: usize) -> Result<(), String> {
-        const MAX_TOKEN_LENGTH: usize = 1000; // Reasonable limit
-        if end_pos - start_pos > MAX_TOKEN_LENGTH {
+        if end_pos - start_pos > self.max_token_length {
             return Err(format!(
                 "Token too long: {} characters (maximum is {})",
                 end_pos - start_pos,
-                MAX_TOKEN_LENGTH
+                self.max_token_length
             ));
         }
         Ok(())
@@ -151,8 +170,15 @@ impl<'a> Lexer<'a> {
                 }
 
                 // Parse the number with a leading zero
+                if let Err(err) = self.check_token_length(start_pos, self.pos) {
+                    return Some(Token {
+                        kind: TokenKind::Error,
+                        value: None,
+                        text: Some(err),
+                        position: start_pos,
+                    });
+                }
                 let num_str = format!("0{}", &self.input[start_pos..self.pos]);
-
                 if let Ok(val) = num_str.parse::<Real>() {
                     return Some(Token {
                         kind: TokenKind::Number,
@@ -215,6 +241,14 @@ impl<'a> Lexer<'a> {
             }
 
             let num_str = &self.input[start_pos..self.pos];
+            if let Err(err) = self.check_token_length(start_pos, self.pos) {
+                return Some(Token {
+                    kind: TokenKind::Error,
+                    value: None,
+                    text: Some(err),
+                    position: start_pos,
+                });
+            }
             if let Ok(val) = num_str.parse::<Real>() {
                 return Some(Token {
                     kind: TokenKind::Number,
@@ -291,13 +325,17 @@ impl<'a> Lexer<'a> {
             });
         }
 
-        // Identifier (variable, function, constant)
-        if c.is_ascii_alphabetic() || c == '_' {
+        // Identifier (variable, function, constant), possibly namespaced as `a::b`
+        if is_identifier_start(c) {
             let start_pos = self.pos;
             let mut end = self.pos;
             while let Some(nc) = self.input[end..].chars().next() {
-                if nc.is_ascii_alphanumeric() || nc == '_' {
+                if is_identifier_continue(nc) {
                     end += nc.len_utf8();
+                } else if let Some(rest) = self.input[end..].strip_prefix("::")
+                    && rest.chars().next().is_some_and(is_identifier_start)
+                {
+                    end += 2;
                 } else {
                     break;
                 }
@@ -362,6 +400,25 @@ mod tests {
         assert!(kinds.contains(&TokenKind::Separator));
     }
 
+    #[test]
+    fn test_lexer_unicode_and_namespaced_identifiers() {
+        let mut lexer = Lexer::new("Δt * motor::kp + ω_1 ? a : b::c");
+        let mut texts = Vec::new();
+        while let Some(tok) = lexer.next_token() {
+            texts.push((tok.kind, tok.text.unwrap_or_default()));
+        }
+        let variables: Vec<&str> = texts
+            .iter()
+            .filter(|(kind, _)| *kind == TokenKind::Variable)
+            .map(|(_, text)| text.as_str())
+            .collect();
+        assert_eq!(variables, ["Δt", "motor::kp", "ω_1", "a", "b::c"]);
+
+        // `::` must join two identifiers
+        let mut lexer = Lexer::new("a:: + 1");
+        assert_eq!(lexer.next_token().unwrap().text.as_deref(), Some("a"));
+    }
+
     #[test]
     fn test_lexer_tokenization_error_tokens() {
         let mut lexer = Lexer::new("1 $ 2");
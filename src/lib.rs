@@ -1,5 +1,5 @@
 // #![cfg_attr(all(not(test), target_arch = "arm"), no_std)]
-#![cfg_attr(all(not(test), target_arch = "arm"), no_std)]
+#![cfg_attr(all(not(test), not(feature = "std"), target_arch = "arm"), no_std)]
 //! exp-rs
 //!
 //! A minimal, extensible, no_std-friendly math expression parser and evaluator for Rust.
@@ -13,7 +13,7 @@
 //! - Support for user-defined variables, constants, arrays, attributes, and functions
 //! - Built-in math functions (sin, cos, pow, etc.) that can be enabled/disabled
 //! - Ability to override any built-in function at runtime
-//! - Array access with `array[index]` syntax
+//! - Array access with `array[index]` syntax, and slices `array[start:end]` for aggregates
 //! - Object attributes with `object.attribute` syntax
 //! - Standard function call syntax with parentheses (`sin(x)`, `cos(y)`, etc.)
 //! - Comprehensive error handling
@@ -185,7 +185,9 @@
 //! - Logical, comparison, bitwise, and exponentiation operators with correct precedence and associativity
 //! - List expressions and both comma and semicolon as separators
 //! - Standard function call syntax with parentheses
-//! - Array and attribute access
+//! - Array and attribute access, including hierarchical paths such as `drive.motor1.current`
+//! - Unicode identifiers (`Δt`) and namespaced names (`motor::kp`)
+//! - Programs of several statements with `name = value` assignments, see [`program`]
 //! - Right-associative exponentiation
 //!
 //! ## Operator Precedence and Associativity
@@ -207,6 +209,30 @@
 //! | 15         | `^`                                 | Right              |
 //! | 16         | `**`                                | Right              |
 //!
+//! [`ParseOptions::caret`](engine::ParseOptions::caret) can make `^` a bitwise XOR at
+//! precedence 5, between `|` and `&` as in C, or reject it, and
+//! [`ParseOptions::disable_double_star`](engine::ParseOptions::disable_double_star)
+//! rejects `**`, to match other grammars.
+//!
+//! ## Evaluation Order
+//!
+//! Evaluation order is part of the language, so functions with side effects and
+//! results compared bit for bit between builds behave the same everywhere:
+//!
+//! - Operands and function arguments are evaluated left to right, each completely
+//!   before the next, and the function is called once all of them are known.
+//! - `&&`, `||` and `?:` evaluate their left operand or condition first and then at most
+//!   one further operand.
+//! - The items of a list separated by `,` or `;` are evaluated left to right and the
+//!   list has the value of its last item.
+//! - The expressions of an [`Expression`] batch are evaluated in the order they were
+//!   added, except that a named expression runs before the first expression
+//!   referencing it.
+//!
+//! Names listed by a context, such as [`EvalContext::list_variables`], are sorted, and
+//! [`EvalContext::diff`] orders its patch by name, so neither depends on the order of
+//! the underlying maps.
+//!
 //! ## Built-in Functions
 //!
 //! The following functions are available by default when the `libm` feature is enabled. Without the `libm` feature,
@@ -214,11 +240,42 @@
 //!
 //! - Trigonometric: `sin`, `cos`, `tan`, `asin`, `acos`, `atan`, `atan2`
 //! - Hyperbolic: `sinh`, `cosh`, `tanh`
-//! - Exponential/Logarithmic: `exp`, `log`, `log10`, `ln`
-//! - Power/Root: `sqrt`, `pow`
-//! - Rounding: `ceil`, `floor`
-//! - Comparison: `max`, `min`
-//! - Misc: `abs`, `sign`
+//! - Exponential/Logarithmic: `exp`, `expm1`, `log` (base 10, or `log(x, base)`), `log10`, `log2`,
+//!   `log1p`, `ln`
+//! - Power/Root: `sqrt`, `pow`, `hypot`, `fma(a, b, c)` (see `simplify::fuse_multiply_add`)
+//! - Rounding: `ceil`, `floor`, `round`, `trunc`, `fract`, `round_to(x, step)`,
+//!   `quantize(x, step, mode)` (mode 0 nearest, 1 down, 2 up, 3 toward zero)
+//! - Comparison: `max`, `min` (any number of arguments, or an array name as in `max(samples)`
+//!   or a slice as in `max(samples[0:64])`),
+//!   `argmax`, `argmin` (zero-based index of the extreme value)
+//! - Misc: `abs`, `sign`, `copysign`
+//! - Saturation and wrapping: `sat_add(a, b, min, max)` (sum clamped to `[min, max]`),
+//!   `wrap(x, lo, hi)` (into `[lo, hi)`), `angle_wrap(x)` (radians into `[-pi, pi)`)
+//! - Integers: `idiv(a, b)` (rounding toward negative infinity), `imod(a, b)` (with the sign of
+//!   `b`), `iabs(x)`; NaN for non-integer arguments (see the `value` module for exact `i64`
+//!   evaluation with the `int64` feature)
+//! - Bits, of integers in two's complement: `bitand(a, b)`, `bitor(a, b)`, `bitxor(a, b)`,
+//!   `bitnot(x)`, `getbit(x, n)` (bit `n`, 0 or 1), `setbit(x, n, v)` (bit `n` set if `v` is
+//!   non-zero, cleared otherwise), `popcount(x)`; NaN for non-integer arguments or bit
+//!   positions outside `0..64`
+//! - Approximations (with the `fast-math` feature, see the `fastmath` module): `fast_sin`,
+//!   `fast_cos`, `fast_exp`, `fast_ln`, `fast_log`
+//! - Invalid values: `isnan`, `isinf`, `isfinite` (1 or 0), `coalesce(a, b, ...)` (first finite
+//!   argument), `nanfallback(x, fallback)` (`fallback` if `x` is NaN)
+//! - Missing values (see the `missing` module): `is_missing(x)` (1 or 0), `default(x, v)` (`v`
+//!   if `x` is missing)
+//! - Tables: `lut(x, xs, ys)` interpolates linearly in the context arrays `xs` and `ys`, clamping
+//!   outside them, or `lut(x, xs, ys, 1)` extrapolating
+//! - Time (after `EvalContext::set_clock`): `now()`, `dt()`, `elapsed(timer)`
+//! - Histories (after `EvalContext::add_history`): `history(name, n)` (the sample `n` samples
+//!   ago), `window_mean(name, n)`, `window_min`, `window_max`, `window_sum` (over the latest
+//!   `n` samples)
+//! - Triggers, remembering a value per call between evaluations: `rising(cond)`, `falling(cond)`,
+//!   `changed(x)`, `latch(set, reset)` (reset wins), `hysteresis(x, low, high)` (1 above `high`
+//!   until below `low`), `debounce(cond, n)` (follows `cond` once it held for `n` evaluations);
+//!   see `Expression::reset_triggers`
+//! - Random: `rand()`, `rand_range(a, b)`, `randn()` (`randn` requires `libm`), seeded with
+//!   `EvalContext::set_seed`
 //!
 //! ## Built-in Constants
 //!
@@ -229,6 +286,57 @@
 //!
 //! - `libm`: Enables built-in math functions using the libm library. Without this feature, you must register your own math functions.
 //! - `f32`: Use 32-bit floating point (single precision) for calculations
+//! - `std`: Back the context containers (variables, constants, arrays, attributes and
+//!   function registries) with growable `HashMap`s instead of fixed-capacity heapless maps,
+//!   removing the `EXP_RS_MAX_*` entry limits. Intended for host tools and servers.
+//! - `complex`: Adds the `complex` module for evaluating expressions over complex numbers,
+//!   with `i`/`j` as the imaginary unit and `abs`, `arg`, `re`, `im` and `conj` builtins.
+//! - `autodiff`: Adds the `autodiff` module, whose `eval_with_derivative` returns the value
+//!   of an expression and its derivative with respect to one variable in a single pass.
+//! - `linalg`: Adds the `linalg` module for evaluating expressions over matrices and
+//!   vectors of up to 4x4 stored in the context, with the `matmul`, `transpose`, `det`,
+//!   `norm`, `dot`, `cross` and `vec` builtins.
+//! - `quaternion`: Adds the `qmul`, `qrotate`, `qnorm`, `euler_to_q` and `q_to_euler`
+//!   builtins to the `linalg` evaluator, for attitude math over 4-element quaternion
+//!   arrays. Implies `linalg` and `libm`.
+//! - `rayon`: Adds `Expression::eval_all_parallel`, which evaluates independent batch
+//!   expressions on the rayon thread pool. Implies `std`.
+//! - `wasm`: Adds the `wasm` module with `wasm-bindgen` bindings (`interp`, `Context` and
+//!   `CompiledExpression`) for using the engine from JavaScript. Implies `std`.
+//! - `stats`: Adds the `mean`, `variance`, `stddev`, `median` and `percentile` builtins,
+//!   which accept values or an array name (`percentile(samples, 95)`).
+//! - `special-functions`: Adds the `tgamma`, `lgamma`, `erf`, `erfc`, `j0` and `j1`
+//!   builtins from libm. Implies `libm`.
+//! - `serde`: Implements `Serialize` and `Deserialize` for `EvalContext`, snapshotting its
+//!   variables, constants, arrays, attributes and settings.
+//! - `compat`: Adds the `compat` module, which runs a fixture of expressions with results
+//!   recorded from a reference implementation (such as tinyexpr++) and reports the cases
+//!   where exp-rs differs.
+//! - `macros`: Re-exports the `expr!` macro of the `exp-rs-macros` crate, which parses an
+//!   expression at compile time into a `&'static AstExpr<'static>`, failing the build on
+//!   syntax errors and leaving no parsing to do at run time.
+//! - `remote`: Adds the `remote` module, which encodes evaluate, compile and set-parameter
+//!   requests and their responses as length-prefixed binary frames, and a `Server` handling
+//!   them on the device, for driving the evaluator over UART or USB.
+//! - `cli`: Builds the `exp-rs` binary, which evaluates expressions from its arguments,
+//!   batch files or stdin, with `--var NAME=VALUE`, `--f32` rounding and `--ast` output,
+//!   for checking expression semantics on the host. From a terminal it runs a REPL that
+//!   keeps variables and expression functions defined across lines. Implies `std`.
+//! - `fuzzing`: Adds the `fuzzing` module, which generates valid expressions, ASTs and
+//!   edge-case values from fuzzer bytes, over the builtins or the functions of a context.
+//! - `shadow`: Adds the `shadow` module, which evaluates an expression in both `f32` and
+//!   `f64` and reports their relative divergence, to decide per expression whether the
+//!   `f32` firmware build is accurate enough. Only available in `f64` builds. Implies `std`.
+//! - `rational`: Adds the `rational` module, which evaluates `+`, `-`, `*`, `/` and
+//!   integer powers exactly over `i128` fractions and reports whether the floating-point
+//!   result of an expression depends on rounding, for validating constants at
+//!   configuration time. Implies `std`.
+//! - `softfloat`: Computes the arithmetic operators and `sqrt` in software with the
+//!   `softfloat` module and builds libm without architecture-specific instructions, so
+//!   results match bit for bit between host simulation and the device. Implies `libm`.
+//! - `bench`: Adds the `bench` module, a corpus of representative expressions with a
+//!   harness timing their parsing, compilation and evaluation in caller-provided ticks,
+//!   and comparison against recorded baselines. Implies `libm`.
 //!
 //! When `f32` is not specified, 64-bit floating point (double precision) is used by default.
 //!
@@ -238,6 +346,9 @@
 //!
 //! - `no_std` compatible with the `alloc` crate
 //! - Configurable precision with `f32`/`f64` options
+//! - Build-time configurable capacity limits: set `EXP_RS_MAX_VARIABLES`,
+//!   `EXP_RS_MAX_NATIVE_FUNCTIONS`, `EXP_RS_MAX_STACK_DEPTH`, etc. as environment variables
+//!   (e.g. in `.cargo/config.toml` under `[env]`) to resize the heapless containers
 //! - Option to disable built-in math functions and provide custom implementations
 //! - Tested example using qemu CMSIS-DSP math functions (test in repo)
 //! - Meson build system integration for cross-compilation
@@ -273,7 +384,7 @@
 //! extern crate alloc;
 //! use exp_rs::interp;
 //! use exp_rs::context::EvalContext;
-//! use heapless::FnvIndexMap;
+//! use exp_rs::types::AttributeKeyMap;
 //! use alloc::rc::Rc;
 //!
 //! // Create an evaluation context
@@ -282,7 +393,7 @@
 //! ctx.arrays.insert("data".try_into().unwrap(), vec![10.0, 20.0, 30.0, 40.0, 50.0]).unwrap();
 //!
 //! // Add an object with attributes
-//! let mut point = FnvIndexMap::new();
+//! let mut point = AttributeKeyMap::new();
 //! point.insert("x".try_into().unwrap(), 3.0).unwrap();
 //! point.insert("y".try_into().unwrap(), 4.0).unwrap();
 //! ctx.attributes.insert("point".try_into().unwrap(), point).unwrap();
@@ -290,6 +401,8 @@
 //!
 //! // Access array elements in expressions
 //! interp("data[2]", Some(Rc::clone(&ctx_rc))).unwrap(); // Returns 30.0
+//! // Aggregates accept a slice of elements `start` to `end - 1`
+//! interp("max(data[1:3])", Some(Rc::clone(&ctx_rc))).unwrap(); // Returns 30.0
 //!
 //! // Access attributes in expressions
 //! interp("point.x + point.y", Some(Rc::clone(&ctx_rc))).unwrap(); // Returns 7.0
@@ -302,6 +415,10 @@
 //! # }
 //! ```
 //!
+//! Objects can be nested and hold arrays: `cfg.limits.max` reads the attribute `max`
+//! of the object `cfg.limits`, and `point.coords[1]` the array `point.coords`. See
+//! [`EvalContext::set_path`] and [`EvalContext::set_array`].
+//!
 //! # Custom Functions
 //!
 //! exp-rs allows you to define custom functions in two ways:
@@ -426,6 +543,10 @@
 //! }
 //! ```
 //!
+//! For interrupt handlers and other contexts that cannot share state,
+//! [`eval::reentrant`] evaluates a pre-parsed AST against a `const`-constructible
+//! [`eval::StaticContext`] without statics or heap allocation.
+//!
 //! # Disabling Built-in Math Functions
 //!
 //! For embedded systems where you want to provide your own math implementations:
@@ -467,7 +588,7 @@
 //!     // Handle syntax errors
 //!     match interp("2 + * 3", Some(Rc::new(ctx.clone()))) {
 //!         Ok(_) => println!("Unexpected success"),
-//!         Err(ExprError::Syntax(msg)) => println!("Syntax error: {}", msg),
+//!         Err(ExprError::Syntax { message, .. }) => println!("Syntax error: {}", message),
 //!         Err(e) => println!("Unexpected error: {:?}", e),
 //!     }
 //!
@@ -533,6 +654,18 @@ pub use alloc::vec::Vec;
 
 // Ensure core::result::Result, core::result::Result::Ok, and core::result::Result::Err are in scope for no_std/serde
 
+#[cfg(feature = "autodiff")]
+pub mod autodiff;
+#[cfg(feature = "bench")]
+pub mod bench;
+pub mod clock;
+#[cfg(feature = "cmsis-dsp")]
+pub mod cmsis;
+pub mod compare;
+#[cfg(feature = "compat")]
+pub mod compat;
+#[cfg(feature = "complex")]
+pub mod complex;
 pub mod context;
 pub mod engine;
 pub mod error;
@@ -540,10 +673,45 @@ pub mod eval;
 pub mod evaluator;
 pub mod expression;
 pub mod expression_functions;
+#[cfg(feature = "fast-math")]
+pub mod fastmath;
 pub mod ffi;
+pub mod format;
 pub mod functions;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+pub mod graph;
+pub mod history;
+pub mod intern;
 pub mod lexer;
+#[cfg(feature = "linalg")]
+pub mod linalg;
+pub mod log;
+pub mod memo;
+pub mod missing;
+mod printer;
+pub mod profile;
+pub mod program;
+#[cfg(feature = "quaternion")]
+pub mod quaternion;
+pub mod random;
+#[cfg(feature = "rational")]
+pub mod rational;
+#[cfg(feature = "remote")]
+pub mod remote;
+#[cfg(all(feature = "shadow", not(feature = "f32")))]
+pub mod shadow;
+pub mod simplify;
+#[cfg(feature = "softfloat")]
+pub mod softfloat;
+#[cfg(feature = "stats")]
+pub mod stats;
 pub mod types;
+pub mod units;
+pub mod value;
+pub mod visit;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 pub use context::*;
 pub use engine::*;
@@ -553,12 +721,14 @@ pub use types::*;
 
 pub use ffi::*;
 
-// Re-export recursion depth tracking functions for testing
-#[cfg(test)]
-pub use eval::recursion::{get_recursion_depth, reset_recursion_depth, set_max_recursion_depth};
+#[cfg(feature = "macros")]
+pub use exp_rs_macros::expr;
+// Lets `expr!` name this crate as `::exp_rs` in its own tests
+#[cfg(all(test, feature = "macros"))]
+extern crate self as exp_rs;
 
 // Re-export iterative evaluation components for batch processing
-pub use eval::iterative::{EvalEngine, eval_with_engine};
+pub use eval::iterative::{EvalEngine, EvalStep, eval_with_engine};
 
 // Compile-time check: only one of f32 or f64 can be enabled
 /// Define the floating-point type based on feature flags
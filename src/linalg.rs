@@ -0,0 +1,725 @@
+//! Small matrix and vector math (requires the `linalg` feature).
+//!
+//! [`eval_linalg`] evaluates a parsed expression whose values are either numbers or
+//! matrices of up to 4x4. Matrices are stored in the context with
+//! [`EvalContext::set_matrix`] and referred to by name; vectors are matrices with one
+//! column (or one row). This covers the rotation and transform formulas common in
+//! robotics, such as `norm(matmul(R, v))`, without allocating.
+
+use crate::Real;
+use crate::context::EvalContext;
+use crate::error::ExprError;
+use crate::functions;
+use crate::types::{AstExpr, LogicalOperator, TryIntoHeaplessString};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// The largest number of rows or columns a matrix can have.
+pub const MAX_DIM: usize = 4;
+
+/// A matrix of up to `MAX_DIM` x `MAX_DIM` `Real` values, stored inline in row-major order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix {
+    rows: usize,
+    cols: usize,
+    data: [Real; MAX_DIM * MAX_DIM],
+}
+
+impl Matrix {
+    /// Creates a `rows` x `cols` matrix from its values in row-major order.
+    ///
+    /// Fails with `ExprError::CapacityExceeded` if a dimension is zero or larger than
+    /// [`MAX_DIM`], and with `ExprError::DimensionMismatch` if the number of values does
+    /// not match the shape.
+    pub fn new(rows: usize, cols: usize, values: &[Real]) -> Result<Self, ExprError> {
+        if !(1..=MAX_DIM).contains(&rows) || !(1..=MAX_DIM).contains(&cols) {
+            return Err(ExprError::CapacityExceeded {
+                container: "matrix",
+            });
+        }
+        if values.len() != rows * cols {
+            return Err(ExprError::DimensionMismatch {
+                operation: "matrix".to_string(),
+                left: format!("{} values", values.len()),
+                right: format!("{}x{}", rows, cols),
+            });
+        }
+        let mut data = [0.0; MAX_DIM * MAX_DIM];
+        data[..values.len()].copy_from_slice(values);
+        Ok(Self { rows, cols, data })
+    }
+
+    /// Creates a column vector.
+    pub fn vector(values: &[Real]) -> Result<Self, ExprError> {
+        Self::new(values.len(), 1, values)
+    }
+
+    /// Creates the `n` x `n` identity matrix.
+    pub fn identity(n: usize) -> Result<Self, ExprError> {
+        if !(1..=MAX_DIM).contains(&n) {
+            return Err(ExprError::CapacityExceeded {
+                container: "matrix",
+            });
+        }
+        let mut m = Matrix {
+            rows: n,
+            cols: n,
+            data: [0.0; MAX_DIM * MAX_DIM],
+        };
+        for i in 0..n {
+            m.data[i * n + i] = 1.0;
+        }
+        Ok(m)
+    }
+
+    /// Number of rows.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Number of columns.
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// The values in row-major order.
+    pub fn values(&self) -> &[Real] {
+        &self.data[..self.rows * self.cols]
+    }
+
+    /// Returns the value at `row`, `col`, or `None` if out of range.
+    pub fn get(&self, row: usize, col: usize) -> Option<Real> {
+        (row < self.rows && col < self.cols).then(|| self.data[row * self.cols + col])
+    }
+
+    /// Returns true if the matrix has a single row or a single column.
+    pub fn is_vector(&self) -> bool {
+        self.rows == 1 || self.cols == 1
+    }
+
+    /// The transpose.
+    pub fn transpose(&self) -> Matrix {
+        let mut t = Matrix {
+            rows: self.cols,
+            cols: self.rows,
+            data: [0.0; MAX_DIM * MAX_DIM],
+        };
+        for r in 0..self.rows {
+            for c in 0..self.cols {
+                t.data[c * self.rows + r] = self.data[r * self.cols + c];
+            }
+        }
+        t
+    }
+
+    /// The matrix product `self * other`.
+    pub fn matmul(&self, other: &Matrix) -> Result<Matrix, ExprError> {
+        if self.cols != other.rows {
+            return Err(self.mismatch("matmul", other));
+        }
+        let mut product = Matrix {
+            rows: self.rows,
+            cols: other.cols,
+            data: [0.0; MAX_DIM * MAX_DIM],
+        };
+        for r in 0..self.rows {
+            for c in 0..other.cols {
+                product.data[r * other.cols + c] = (0..self.cols)
+                    .map(|k| self.data[r * self.cols + k] * other.data[k * other.cols + c])
+                    .sum();
+            }
+        }
+        Ok(product)
+    }
+
+    /// The determinant of a square matrix.
+    pub fn det(&self) -> Result<Real, ExprError> {
+        if self.rows != self.cols {
+            return Err(ExprError::DimensionMismatch {
+                operation: "det".to_string(),
+                left: self.shape(),
+                right: "a square matrix".to_string(),
+            });
+        }
+        Ok(det(self.values(), self.rows))
+    }
+
+    /// The Frobenius norm, which for a vector is its Euclidean length.
+    pub fn norm(&self) -> Real {
+        let sum: Real = self.values().iter().map(|v| v * v).sum();
+        functions::sqrt(sum, 0.0)
+    }
+
+    /// The dot product of two vectors of the same length.
+    pub fn dot(&self, other: &Matrix) -> Result<Real, ExprError> {
+        if !self.is_vector() || !other.is_vector() || self.values().len() != other.values().len() {
+            return Err(self.mismatch("dot", other));
+        }
+        Ok(self
+            .values()
+            .iter()
+            .zip(other.values())
+            .map(|(a, b)| a * b)
+            .sum())
+    }
+
+    /// The cross product of two 3-vectors, with the orientation of `self`.
+    pub fn cross(&self, other: &Matrix) -> Result<Matrix, ExprError> {
+        let (&[a1, a2, a3], &[b1, b2, b3]) = (self.values(), other.values()) else {
+            return Err(self.mismatch("cross", other));
+        };
+        if !self.is_vector() || !other.is_vector() {
+            return Err(self.mismatch("cross", other));
+        }
+        let mut result = *self;
+        result.data[..3].copy_from_slice(&[
+            a2 * b3 - a3 * b2,
+            a3 * b1 - a1 * b3,
+            a1 * b2 - a2 * b1,
+        ]);
+        Ok(result)
+    }
+
+    fn map(mut self, f: impl Fn(Real) -> Real) -> Matrix {
+        let len = self.rows * self.cols;
+        for v in &mut self.data[..len] {
+            *v = f(*v);
+        }
+        self
+    }
+
+    fn zip(
+        self,
+        operation: &str,
+        other: &Matrix,
+        f: impl Fn(Real, Real) -> Real,
+    ) -> Result<Matrix, ExprError> {
+        if (self.rows, self.cols) != (other.rows, other.cols) {
+            return Err(self.mismatch(operation, other));
+        }
+        let mut result = self;
+        for (v, w) in result.data.iter_mut().zip(other.data.iter()) {
+            *v = f(*v, *w);
+        }
+        Ok(result)
+    }
+
+    fn shape(&self) -> String {
+        format!("{}x{}", self.rows, self.cols)
+    }
+
+    fn mismatch(&self, operation: &str, other: &Matrix) -> ExprError {
+        ExprError::DimensionMismatch {
+            operation: operation.to_string(),
+            left: self.shape(),
+            right: other.shape(),
+        }
+    }
+}
+
+/// Determinant by cofactor expansion along the first row, which is exact for integer
+/// entries and cheap at these sizes.
+fn det(values: &[Real], n: usize) -> Real {
+    match n {
+        1 => values[0],
+        2 => values[0] * values[3] - values[1] * values[2],
+        _ => {
+            let mut minor = [0.0; (MAX_DIM - 1) * (MAX_DIM - 1)];
+            let mut sum = 0.0;
+            for col in 0..n {
+                let mut k = 0;
+                for r in 1..n {
+                    for c in (0..n).filter(|&c| c != col) {
+                        minor[k] = values[r * n + c];
+                        k += 1;
+                    }
+                }
+                let sign = if col % 2 == 0 { 1.0 } else { -1.0 };
+                sum += sign * values[col] * det(&minor[..k], n - 1);
+            }
+            sum
+        }
+    }
+}
+
+/// A value produced by [`eval_linalg`]: a number or a matrix.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    /// A number
+    Scalar(Real),
+    /// A matrix or vector
+    Matrix(Matrix),
+}
+
+impl Value {
+    /// Returns the number, or `None` for a matrix.
+    pub fn as_scalar(&self) -> Option<Real> {
+        match self {
+            Value::Scalar(v) => Some(*v),
+            Value::Matrix(_) => None,
+        }
+    }
+
+    /// Returns the matrix, or `None` for a number.
+    pub fn as_matrix(&self) -> Option<&Matrix> {
+        match self {
+            Value::Scalar(_) => None,
+            Value::Matrix(m) => Some(m),
+        }
+    }
+}
+
+impl From<Real> for Value {
+    fn from(value: Real) -> Self {
+        Value::Scalar(value)
+    }
+}
+
+impl From<Matrix> for Value {
+    fn from(matrix: Matrix) -> Self {
+        Value::Matrix(matrix)
+    }
+}
+
+/// Evaluates an expression over numbers and matrices.
+///
+/// Names refer to matrices set with [`EvalContext::set_matrix`] before variables and
+/// constants, and context arrays of up to 4 elements are column vectors. `M[i]` reads
+/// element `i` of a matrix in row-major order. `+` and `-`
+/// apply elementwise to matrices of the same shape, `*` scales a matrix by a number or
+/// multiplies two matrices, and `/` divides a matrix by a number. The builtins are:
+///
+/// - `matmul(A, B)`, `transpose(A)` and `cross(u, v)`, which return matrices;
+/// - `det(A)`, `norm(A)` and `dot(u, v)`, which return numbers;
+/// - `vec(x, y, ...)`, which builds a column vector of up to 4 numbers.
+///
+/// With the `quaternion` feature, the [`quaternion`](crate::quaternion) builtins are
+/// available as well.
+///
+/// All other functions, including native functions registered in the context, require
+/// number arguments and fail with `ExprError::TypeError` otherwise, as do conditions.
+/// Operators and functions on numbers are called through the context like in
+/// [`EvalEngine`](crate::eval::iterative::EvalEngine), so overridden operators, the
+/// [`MathConfig`](crate::context::MathConfig) tolerances and the
+/// [`FunctionPolicy`](crate::context::FunctionPolicy) apply; without a context a default
+/// one is used. Expression functions are not supported by the matrix evaluator.
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::context::EvalContext;
+/// use exp_rs::engine::parse_expression;
+/// use exp_rs::linalg::{Matrix, eval_linalg};
+/// use bumpalo::Bump;
+///
+/// let mut ctx = EvalContext::new();
+/// // 90 degree rotation about z
+/// let r = Matrix::new(3, 3, &[0.0, -1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0]).unwrap();
+/// ctx.set_matrix("R", r).unwrap();
+/// ctx.set_parameter("x", 3.0).unwrap();
+///
+/// let arena = Bump::new();
+/// let ast = parse_expression("norm(matmul(R, vec(x, 4, 0)))", &arena).unwrap();
+/// assert_eq!(eval_linalg(&ast, Some(&ctx)).unwrap().as_scalar(), Some(5.0));
+/// ```
+pub fn eval_linalg(expr: &AstExpr, ctx: Option<&EvalContext>) -> Result<Value, ExprError> {
+    let default_ctx;
+    let ctx = match ctx {
+        Some(ctx) => ctx,
+        None => {
+            default_ctx = EvalContext::new();
+            &default_ctx
+        }
+    };
+    let limit = ctx
+        .max_eval_depth()
+        .unwrap_or(crate::types::EXP_RS_MAX_STACK_DEPTH);
+
+    let mut ops = alloc::vec![Op::Eval(expr)];
+    let mut values: Vec<Value> = Vec::new();
+    while let Some(op) = ops.pop() {
+        if ops.len() >= limit {
+            return Err(ExprError::RecursionLimit {
+                limit,
+                message: format!("Maximum evaluation depth {} exceeded", limit),
+            });
+        }
+        match op {
+            Op::Eval(expr) => match expr {
+                AstExpr::Constant(value) => values.push(Value::Scalar(*value)),
+                AstExpr::Variable(name) => values.push(lookup_variable(name, ctx)?),
+                AstExpr::Array { name, index } => {
+                    ops.push(Op::Index(name));
+                    ops.push(Op::Eval(index));
+                }
+                AstExpr::Attribute { base, attr } => values.push(
+                    ctx.get_attribute_map(base)
+                        .and_then(|m| m.get(&attr.try_into_heapless().ok()?).copied())
+                        .map(Value::Scalar)
+                        .ok_or_else(|| ExprError::AttributeNotFound {
+                            base: base.to_string(),
+                            attr: attr.to_string(),
+                        })?,
+                ),
+                AstExpr::LogicalOp { op, left, right } => {
+                    ops.push(Op::Logical { op: *op, right });
+                    ops.push(Op::Eval(left));
+                }
+                AstExpr::Conditional {
+                    condition,
+                    true_branch,
+                    false_branch,
+                } => {
+                    ops.push(Op::Branch {
+                        true_branch,
+                        false_branch,
+                    });
+                    ops.push(Op::Eval(condition));
+                }
+                AstExpr::Function { name, args } => {
+                    ctx.check_function_permitted(name)?;
+                    ops.push(Op::Call {
+                        name,
+                        arg_count: args.len(),
+                    });
+                    ops.extend(args.iter().rev().map(Op::Eval));
+                }
+            },
+            Op::Index(name) => {
+                let index = require_scalar("array index", pop(&mut values)?)?;
+                values.push(index_value(name, index as usize, ctx)?);
+            }
+            Op::Logical { op, right } => {
+                let left = require_scalar("operand of a logical operator", pop(&mut values)?)?;
+                match (op, left != 0.0) {
+                    (LogicalOperator::And, false) => values.push(Value::Scalar(0.0)),
+                    (LogicalOperator::Or, true) => values.push(Value::Scalar(1.0)),
+                    _ => {
+                        ops.push(Op::Truth);
+                        ops.push(Op::Eval(right));
+                    }
+                }
+            }
+            Op::Truth => {
+                let right = require_scalar("operand of a logical operator", pop(&mut values)?)?;
+                values.push(Value::Scalar(if right != 0.0 { 1.0 } else { 0.0 }));
+            }
+            Op::Branch {
+                true_branch,
+                false_branch,
+            } => {
+                let condition = require_scalar("condition", pop(&mut values)?)?;
+                ops.push(Op::Eval(if condition != 0.0 {
+                    true_branch
+                } else {
+                    false_branch
+                }));
+            }
+            Op::Call { name, arg_count } => {
+                let start = values.len().saturating_sub(arg_count);
+                let result = call_function(name, &values[start..], ctx)?;
+                values.truncate(start);
+                values.push(result);
+            }
+        }
+    }
+    pop(&mut values)
+}
+
+/// A pending step of [`eval_linalg`], which walks the tree with an explicit stack.
+enum Op<'a> {
+    /// Evaluate an expression and push its value
+    Eval(&'a AstExpr<'a>),
+    /// Replace the index on top of the stack with the element of the named matrix or array
+    Index(&'a str),
+    /// Short-circuit `&&` or `||` on the left operand on top of the stack
+    Logical {
+        op: LogicalOperator,
+        right: &'a AstExpr<'a>,
+    },
+    /// Replace the right operand of a logical operator on top of the stack with 0 or 1
+    Truth,
+    /// Evaluate one of the branches by the condition on top of the stack
+    Branch {
+        true_branch: &'a AstExpr<'a>,
+        false_branch: &'a AstExpr<'a>,
+    },
+    /// Replace the arguments on top of the stack with the result of the call
+    Call { name: &'a str, arg_count: usize },
+}
+
+fn pop(values: &mut Vec<Value>) -> Result<Value, ExprError> {
+    values.pop().ok_or_else(|| ExprError::Other {
+        message: "Value stack underflow".to_string(),
+    })
+}
+
+fn index_value(name: &str, idx: usize, ctx: &EvalContext) -> Result<Value, ExprError> {
+    if let Some(matrix) = ctx.get_matrix(name) {
+        return matrix
+            .values()
+            .get(idx)
+            .map(|v| Value::Scalar(*v))
+            .ok_or_else(|| ExprError::ArrayIndexOutOfBounds {
+                name: name.to_string(),
+                index: idx,
+                len: matrix.values().len(),
+            });
+    }
+    let array = ctx
+        .get_array(name)
+        .ok_or_else(|| ExprError::UnknownVariable {
+            name: name.to_string(),
+        })?;
+    array
+        .get(idx)
+        .map(|v| Value::Scalar(*v))
+        .ok_or_else(|| ExprError::ArrayIndexOutOfBounds {
+            name: name.to_string(),
+            index: idx,
+            len: array.len(),
+        })
+}
+
+fn lookup_variable(name: &str, ctx: &EvalContext) -> Result<Value, ExprError> {
+    if let Some(matrix) = ctx.get_matrix(name) {
+        return Ok(Value::Matrix(matrix));
+    }
+    if let Some(value) = ctx.get_variable(name).or_else(|| ctx.get_constant(name)) {
+        return Ok(Value::Scalar(value));
+    }
+    if let Some(array) = ctx.get_array(name)
+        && array.len() <= MAX_DIM
+    {
+        return Matrix::vector(array).map(Value::Matrix);
+    }
+    let value = match name {
+        "pi" | "PI" => core::f64::consts::PI as Real,
+        "e" | "E" => core::f64::consts::E as Real,
+        "tau" | "TAU" => 2.0 * core::f64::consts::PI as Real,
+        _ => ctx
+            .resolve_variable(name)
+            .ok_or_else(|| ExprError::UnknownVariable {
+                name: name.to_string(),
+            })?,
+    };
+    Ok(Value::Scalar(value))
+}
+
+fn call_function(name: &str, args: &[Value], ctx: &EvalContext) -> Result<Value, ExprError> {
+    use Value::{Matrix as M, Scalar as S};
+
+    let result = match (name, args) {
+        ("+", [M(a), M(b)]) => M(a.zip(name, b, |x, y| x + y)?),
+        ("-", [M(a), M(b)]) => M(a.zip(name, b, |x, y| x - y)?),
+        ("*", [M(a), M(b)]) | ("matmul", [M(a), M(b)]) => M(a.matmul(b)?),
+        ("*", [S(k), M(a)]) | ("*", [M(a), S(k)]) => M(a.map(|x| x * k)),
+        ("/", [M(a), S(k)]) => M(a.map(|x| x / k)),
+        ("neg", [M(a)]) => M(a.map(|x| -x)),
+        ("==", [a @ M(_), b] | [a, b @ M(_)]) => S(if a == b { 1.0 } else { 0.0 }),
+        ("!=" | "<>", [a @ M(_), b] | [a, b @ M(_)]) => S(if a != b { 1.0 } else { 0.0 }),
+        ("," | ";" | "comma", [.., last]) => *last,
+        ("transpose", [M(a)]) => M(a.transpose()),
+        ("det", [M(a)]) => S(a.det()?),
+        ("norm", [M(a)]) => S(a.norm()),
+        ("dot", [M(a), M(b)]) => S(a.dot(b)?),
+        ("cross", [M(a), M(b)]) => M(a.cross(b)?),
+        ("matmul" | "transpose" | "det" | "norm" | "dot" | "cross", _) => {
+            return Err(ExprError::TypeError {
+                operand: format!("argument of '{}'", name),
+                expected: "matrix",
+                found: "number",
+            });
+        }
+        #[cfg(feature = "quaternion")]
+        ("qmul", [M(a), M(b)]) => M(Matrix::vector(&crate::quaternion::qmul(
+            quaternion_arg(name, a)?,
+            quaternion_arg(name, b)?,
+        ))?),
+        #[cfg(feature = "quaternion")]
+        ("qrotate", [M(q), M(v)]) => {
+            let [x, y, z] = v.values() else {
+                return Err(v.mismatch(name, &Matrix::vector(&[0.0; 3])?));
+            };
+            let [x, y, z] = crate::quaternion::qrotate(quaternion_arg(name, q)?, [*x, *y, *z]);
+            M(Matrix::vector(&[x, y, z])?)
+        }
+        #[cfg(feature = "quaternion")]
+        ("qnorm", [M(q)]) => M(Matrix::vector(&crate::quaternion::qnorm(quaternion_arg(
+            name, q,
+        )?))?),
+        #[cfg(feature = "quaternion")]
+        ("euler_to_q", [S(roll), S(pitch), S(yaw)]) => M(Matrix::vector(
+            &crate::quaternion::euler_to_q(*roll, *pitch, *yaw),
+        )?),
+        #[cfg(feature = "quaternion")]
+        ("q_to_euler", [M(q)]) => M(Matrix::vector(&crate::quaternion::q_to_euler(
+            quaternion_arg(name, q)?,
+        ))?),
+        #[cfg(feature = "quaternion")]
+        ("qmul" | "qrotate" | "qnorm" | "q_to_euler", _) => {
+            return Err(ExprError::TypeError {
+                operand: format!("argument of '{}'", name),
+                expected: "matrix",
+                found: "number",
+            });
+        }
+        ("vec", _) => {
+            let mut values = [0.0; MAX_DIM];
+            if args.len() > MAX_DIM {
+                return Err(ExprError::CapacityExceeded {
+                    container: "matrix",
+                });
+            }
+            for (value, arg) in values.iter_mut().zip(args) {
+                *value = require_scalar("argument of 'vec'", *arg)?;
+            }
+            M(Matrix::vector(&values[..args.len()])?)
+        }
+        _ => return call_real_function(name, args, ctx).map(S),
+    };
+    Ok(result)
+}
+
+/// The components of a quaternion argument, which must be a 4-vector.
+#[cfg(feature = "quaternion")]
+fn quaternion_arg(name: &str, m: &Matrix) -> Result<crate::quaternion::Quaternion, ExprError> {
+    match m.values() {
+        [w, x, y, z] if m.is_vector() => Ok([*w, *x, *y, *z]),
+        _ => Err(ExprError::DimensionMismatch {
+            operation: name.to_string(),
+            left: m.shape(),
+            right: "a 4-vector".to_string(),
+        }),
+    }
+}
+
+/// Calls a number-valued operator or context function, rejecting matrix arguments.
+fn call_real_function(name: &str, args: &[Value], ctx: &EvalContext) -> Result<Real, ExprError> {
+    let mut reals = Vec::with_capacity(args.len());
+    for arg in args {
+        reals.push(require_scalar(&format!("argument of '{}'", name), *arg)?);
+    }
+    ctx.call_function(name, &reals)
+}
+
+fn require_scalar(what: &str, value: Value) -> Result<Real, ExprError> {
+    match value {
+        Value::Scalar(v) => Ok(v),
+        Value::Matrix(_) => Err(ExprError::TypeError {
+            operand: what.to_string(),
+            expected: "number",
+            found: "matrix",
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::parse_expression;
+    use bumpalo::Bump;
+
+    #[test]
+    fn test_matrix_builtins_and_errors() {
+        let mut ctx = EvalContext::new();
+        let a = Matrix::new(2, 3, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap();
+        ctx.set_matrix("A", a).unwrap();
+        ctx.set_matrix("u", Matrix::vector(&[1.0, 0.0, 0.0]).unwrap())
+            .unwrap();
+        ctx.set_matrix("v", Matrix::vector(&[0.0, 1.0, 0.0]).unwrap())
+            .unwrap();
+        let m = Matrix::new(3, 3, &[2.0, 0.0, 1.0, 1.0, 3.0, 2.0, 1.0, 1.0, 2.0]).unwrap();
+        ctx.set_matrix("M", m).unwrap();
+        ctx.set_parameter("k", 2.0).unwrap();
+
+        let arena = Bump::new();
+        let eval = |s: &str| eval_linalg(&parse_expression(s, &arena).unwrap(), Some(&ctx));
+        let matrix = |s: &str| *eval(s).unwrap().as_matrix().unwrap();
+        let scalar = |s: &str| eval(s).unwrap().as_scalar().unwrap();
+
+        assert_eq!(
+            matrix("transpose(A)").values(),
+            &[1.0, 4.0, 2.0, 5.0, 3.0, 6.0]
+        );
+        assert_eq!(matrix("transpose(A)").rows(), 3);
+        assert_eq!(matrix("matmul(A, u)").values(), &[1.0, 4.0]);
+        assert_eq!(
+            matrix("A * transpose(A)").values(),
+            &[14.0, 32.0, 32.0, 77.0]
+        );
+        assert_eq!(matrix("k * u - v / 2").values(), &[2.0, -0.5, 0.0]);
+        assert_eq!(matrix("cross(u, v)").values(), &[0.0, 0.0, 1.0]);
+        assert_eq!(scalar("det(M)"), 6.0);
+        assert_eq!(scalar("det(matmul(M, M))"), 36.0);
+        assert_eq!(scalar("dot(u + v, vec(3, 4, 5))"), 7.0);
+        assert_eq!(scalar("norm(vec(3, 4)) + A[5] + sqrt(k * 8)"), 15.0);
+        assert_eq!(scalar("cross(u, v) == vec(0, 0, 1) ? k : 0"), 2.0);
+        assert_eq!(Matrix::identity(3).unwrap().values()[4], 1.0);
+
+        for (input, mismatch) in [
+            ("matmul(u, A)", true),
+            ("u + A", true),
+            ("det(A)", true),
+            ("cross(u, vec(1, 2))", true),
+            ("sqrt(u)", false),
+            ("norm(k)", false),
+            ("u ? 1 : 0", false),
+        ] {
+            let err = eval(input).unwrap_err();
+            if mismatch {
+                assert!(
+                    matches!(err, ExprError::DimensionMismatch { .. }),
+                    "{input}"
+                );
+            } else {
+                assert!(matches!(err, ExprError::TypeError { .. }), "{input}");
+            }
+        }
+        assert!(matches!(
+            Matrix::new(5, 1, &[0.0; 5]),
+            Err(ExprError::CapacityExceeded { .. })
+        ));
+    }
+
+    #[test]
+    fn test_context_operators_and_policy() {
+        let mut ctx = EvalContext::new();
+        ctx.set_matrix("u", Matrix::vector(&[1.0, 2.0]).unwrap())
+            .unwrap();
+        ctx.register_native_function("+", 2, |args| args[0] + args[1] + 100.0)
+            .unwrap();
+        ctx.set_math_config(crate::context::MathConfig {
+            equality_epsilon: 1e-3,
+            ..Default::default()
+        });
+        ctx.set_function_policy(Some(crate::context::FunctionPolicy::deny(["det", "sqrt"])));
+
+        let arena = Bump::new();
+        let eval = |s: &str| eval_linalg(&parse_expression(s, &arena).unwrap(), Some(&ctx));
+        // Numbers go through the context, matrices through the builtins
+        assert_eq!(eval("1 + 2").unwrap().as_scalar(), Some(103.0));
+        assert_eq!(
+            eval("u + u").unwrap().as_matrix().unwrap().values(),
+            &[2.0, 4.0]
+        );
+        assert_eq!(eval("1 == 1.0001").unwrap().as_scalar(), Some(1.0));
+        for input in ["det(u)", "norm(u) + sqrt(4)", "sqrt(norm(u))"] {
+            assert!(
+                matches!(eval(input), Err(ExprError::FunctionNotPermitted { .. })),
+                "{input}"
+            );
+        }
+        assert_eq!(eval("norm(vec(3, 4))").unwrap().as_scalar(), Some(5.0));
+
+        // Deep nesting is limited by the evaluation depth rather than the native stack
+        ctx.set_max_eval_depth(Some(64));
+        let deep = format!("{}1{}", "(1 + ".repeat(100), ")".repeat(100));
+        let arena = Bump::new();
+        assert!(matches!(
+            eval_linalg(&parse_expression(&deep, &arena).unwrap(), Some(&ctx)),
+            Err(ExprError::RecursionLimit { .. })
+        ));
+    }
+}
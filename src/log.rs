@@ -0,0 +1,193 @@
+//! Diagnostic logging hooks.
+//!
+//! A [`Logger`] set on an [`EvalContext`](crate::context::EvalContext) with
+//! [`set_logger`](crate::context::EvalContext::set_logger) receives the diagnostics of
+//! parsing and evaluation with that context or its children:
+//!
+//! - [`Level::Warn`]: an expression failed to parse or to evaluate;
+//! - [`Level::Debug`]: a function returned NaN for arguments that were not NaN, which is
+//!   usually where a NaN result comes from;
+//! - [`Level::Trace`]: every parsed expression and every call of a native function,
+//!   with its arguments and result.
+//!
+//! Messages are passed as [`fmt::Arguments`] and only formatted if the logger asks for
+//! them, so logging needs no allocation and works without `std`; a logger can write
+//! them to RTT, defmt, a UART or a ring buffer.
+//!
+//! ```
+//! use exp_rs::context::EvalContext;
+//! use exp_rs::engine::interp;
+//! use exp_rs::log::Level;
+//! use std::cell::RefCell;
+//! use std::rc::Rc;
+//!
+//! let messages = Rc::new(RefCell::new(Vec::new()));
+//! let sink = messages.clone();
+//! let mut ctx = EvalContext::new();
+//! ctx.set_logger(move |level: Level, args: core::fmt::Arguments<'_>| {
+//!     if level <= Level::Debug {
+//!         sink.borrow_mut().push(format!("{level}: {args}"));
+//!     }
+//! });
+//!
+//! assert!(interp("2 * sqrt(-1)", Some(Rc::new(ctx))).unwrap().is_nan());
+//! assert_eq!(messages.borrow()[0], "DEBUG: sqrt(-1) returned NaN");
+//! ```
+
+use crate::Real;
+use core::fmt;
+
+/// Severity of a log message, from most to least severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    /// A failure the caller cannot recover from
+    Error,
+    /// A failed parse or evaluation
+    Warn,
+    /// Noteworthy events
+    Info,
+    /// Details useful when investigating a wrong result
+    Debug,
+    /// Every step, for following an evaluation in full
+    Trace,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        })
+    }
+}
+
+/// Receives diagnostics from the parser and evaluator.
+///
+/// Closures taking a [`Level`] and [`fmt::Arguments`] implement this trait.
+pub trait Logger {
+    /// Whether messages of `level` are wanted. Messages of disabled levels are not
+    /// produced at all, so filtering here saves the work of producing them.
+    fn enabled(&self, level: Level) -> bool {
+        let _ = level;
+        true
+    }
+
+    /// Handles one message.
+    fn log(&self, level: Level, args: fmt::Arguments<'_>);
+}
+
+impl<F: Fn(Level, fmt::Arguments<'_>)> Logger for F {
+    fn log(&self, level: Level, args: fmt::Arguments<'_>) {
+        self(level, args)
+    }
+}
+
+/// Logs a native function call: at `Debug` if it produced a NaN from non-NaN arguments,
+/// otherwise at `Trace`.
+pub(crate) fn log_call(logger: &dyn Logger, name: &str, args: &[Real], result: Real) {
+    if result.is_nan() && !args.iter().any(|a| a.is_nan()) {
+        if logger.enabled(Level::Debug) {
+            let args = Args(args);
+            logger.log(Level::Debug, format_args!("{name}({args}) returned NaN"));
+        }
+    } else if logger.enabled(Level::Trace) {
+        let args = Args(args);
+        logger.log(Level::Trace, format_args!("{name}({args}) = {result}"));
+    }
+}
+
+/// Comma-separated arguments of a call.
+struct Args<'a>(&'a [Real]);
+
+impl fmt::Display for Args<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, arg) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{arg}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::EvalContext;
+    use crate::engine::interp;
+    use alloc::rc::Rc;
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
+    use core::cell::RefCell;
+
+    struct Recorder {
+        max: Level,
+        messages: Rc<RefCell<Vec<(Level, String)>>>,
+    }
+
+    impl Logger for Recorder {
+        fn enabled(&self, level: Level) -> bool {
+            level <= self.max
+        }
+
+        fn log(&self, level: Level, args: fmt::Arguments<'_>) {
+            self.messages.borrow_mut().push((level, args.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_logger_receives_diagnostics() {
+        let messages = Rc::new(RefCell::new(Vec::new()));
+        let mut parent = EvalContext::new();
+        parent.set_logger(Recorder {
+            max: Level::Debug,
+            messages: messages.clone(),
+        });
+        parent.set_parameter("x", -4.0).unwrap();
+        let parent = Rc::new(parent);
+        // Children log through their parent's logger
+        let mut ctx = EvalContext::new();
+        ctx.parent = Some(parent);
+        let ctx = Rc::new(ctx);
+
+        assert!(
+            interp("abs(x) + sqrt(x)", Some(ctx.clone()))
+                .unwrap()
+                .is_nan()
+        );
+        assert!(interp("1 +", Some(ctx.clone())).is_err());
+        assert!(interp("y * 2", Some(ctx.clone())).is_err());
+        let messages = messages.borrow().clone();
+        assert_eq!(messages.len(), 3, "{messages:?}");
+        assert_eq!(
+            messages[0],
+            (Level::Debug, "sqrt(-4) returned NaN".to_string())
+        );
+        assert_eq!(messages[1].0, Level::Warn);
+        assert!(messages[1].1.starts_with("failed to parse '1 +'"));
+        assert_eq!(messages[2].0, Level::Warn);
+        assert!(messages[2].1.contains("Unknown variable"), "{messages:?}");
+
+        // Trace adds parsed expressions and every call
+        let messages = Rc::new(RefCell::new(Vec::new()));
+        let mut ctx = EvalContext::new();
+        ctx.set_logger(Recorder {
+            max: Level::Trace,
+            messages: messages.clone(),
+        });
+        assert_eq!(interp("max(1, 2) * 3", Some(Rc::new(ctx))).unwrap(), 6.0);
+        let messages: Vec<String> = messages.borrow().iter().map(|(_, m)| m.clone()).collect();
+        assert_eq!(
+            messages,
+            [
+                "parsed 'max(1, 2) * 3' as max(1, 2) * 3",
+                "max(1, 2) = 2",
+                "*(2, 3) = 6"
+            ]
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! Memoization of batch results.
+//!
+//! A [`MemoCache`] remembers the results of pure expressions together with the values
+//! of the inputs they were computed from, so an expression whose inputs repeat is not
+//! evaluated again. [`Expression::enable_memoization`](crate::expression::Expression::enable_memoization)
+//! attaches a cache to a batch; results are stored in a fixed number of slots. A hash
+//! of the expression and its input values selects two neighbouring slots, and a new
+//! result replaces the least recently used of them.
+//!
+//! Hits compare the stored input values exactly, so a hash collision never returns
+//! a wrong result. All storage is allocated when the cache is created and reused
+//! afterwards.
+
+use crate::Real;
+use crate::compare::Fnv;
+use alloc::vec::Vec;
+use core::hash::Hasher;
+
+/// Hit and miss counts of a [`MemoCache`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoStats {
+    /// Lookups answered from the cache
+    pub hits: u64,
+    /// Lookups that required an evaluation
+    pub misses: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    occupied: bool,
+    /// Value of the cache's clock when the entry was last used
+    used: u64,
+    hash: u64,
+    expression: usize,
+    inputs: Vec<Real>,
+    value: Real,
+}
+
+/// A fixed-size cache of expression results keyed by input values.
+#[derive(Debug, Clone)]
+pub struct MemoCache {
+    entries: Vec<Entry>,
+    clock: u64,
+    stats: MemoStats,
+}
+
+impl MemoCache {
+    /// Creates a cache with `capacity` slots, each holding the inputs of one result.
+    ///
+    /// `max_inputs` is the number of input values reserved per slot; results with
+    /// more inputs are still cached but grow their slot on first use.
+    pub fn new(capacity: usize, max_inputs: usize) -> Self {
+        let entries = (0..capacity)
+            .map(|_| Entry {
+                occupied: false,
+                used: 0,
+                hash: 0,
+                expression: 0,
+                inputs: Vec::with_capacity(max_inputs),
+                value: 0.0,
+            })
+            .collect();
+        Self {
+            entries,
+            clock: 0,
+            stats: MemoStats::default(),
+        }
+    }
+
+    /// Returns the number of slots.
+    pub fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns the cached result of `expression` for `inputs`, if any.
+    pub fn get(&mut self, expression: usize, inputs: &[Real]) -> Option<Real> {
+        let hash = key_hash(expression, inputs);
+        let found = self.slots(hash).find(|&i| {
+            let entry = &self.entries[i];
+            entry.occupied
+                && entry.hash == hash
+                && entry.expression == expression
+                && same_values(&entry.inputs, inputs)
+        });
+        match found {
+            Some(i) => {
+                self.stats.hits += 1;
+                self.clock += 1;
+                self.entries[i].used = self.clock;
+                Some(self.entries[i].value)
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Stores the result of `expression` for `inputs`, replacing the least recently
+    /// used result of its two slots.
+    pub fn insert(&mut self, expression: usize, inputs: &[Real], value: Real) {
+        let hash = key_hash(expression, inputs);
+        let Some(i) = self.slots(hash).min_by_key(|&i| {
+            let entry = &self.entries[i];
+            if entry.occupied { entry.used } else { 0 }
+        }) else {
+            return;
+        };
+        self.clock += 1;
+        let entry = &mut self.entries[i];
+        entry.occupied = true;
+        entry.used = self.clock;
+        entry.hash = hash;
+        entry.expression = expression;
+        entry.inputs.clear();
+        entry.inputs.extend_from_slice(inputs);
+        entry.value = value;
+    }
+
+    /// Forgets all results, keeping the statistics.
+    pub fn clear(&mut self) {
+        for entry in &mut self.entries {
+            entry.occupied = false;
+        }
+    }
+
+    /// Returns the hit and miss counts since the cache was created.
+    pub fn stats(&self) -> MemoStats {
+        self.stats
+    }
+
+    /// Positions of the slots that may hold the result with `hash`.
+    fn slots(&self, hash: u64) -> impl Iterator<Item = usize> + use<> {
+        let len = self.entries.len();
+        let first = if len == 0 {
+            0
+        } else {
+            (hash % len as u64) as usize
+        };
+        [first, first + 1]
+            .into_iter()
+            .take(len.min(2))
+            .map(move |i| i % len)
+    }
+}
+
+/// Inputs match bit for bit, so `-0` and `0` or different NaNs are distinct keys.
+#[allow(clippy::unnecessary_cast)] // `Real` may be `f32`
+fn same_values(a: &[Real], b: &[Real]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.to_bits() == y.to_bits())
+}
+
+#[allow(clippy::unnecessary_cast)] // `Real` may be `f32`
+fn key_hash(expression: usize, inputs: &[Real]) -> u64 {
+    let mut hasher = Fnv::default();
+    hasher.write_u64(expression as u64);
+    for value in inputs {
+        hasher.write_u64(value.to_bits() as u64);
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memo_cache_hits_and_replacement() {
+        let mut cache = MemoCache::new(1, 2);
+        assert_eq!(cache.get(0, &[1.0, 2.0]), None);
+        cache.insert(0, &[1.0, 2.0], 3.0);
+        assert_eq!(cache.get(0, &[1.0, 2.0]), Some(3.0));
+        assert_eq!(cache.get(1, &[1.0, 2.0]), None);
+        assert_eq!(cache.get(0, &[2.0, 1.0]), None);
+
+        // A single slot keeps only the latest result; two keep the most recently used
+        cache.insert(1, &[5.0], 10.0);
+        assert_eq!(cache.get(0, &[1.0, 2.0]), None);
+        assert_eq!(cache.get(1, &[5.0]), Some(10.0));
+        assert_eq!(cache.stats(), MemoStats { hits: 2, misses: 4 });
+
+        let mut cache = MemoCache::new(2, 1);
+        cache.insert(0, &[1.0], 1.0);
+        cache.insert(1, &[1.0], 2.0);
+        assert_eq!(cache.get(0, &[1.0]), Some(1.0));
+        cache.insert(2, &[1.0], 3.0);
+        assert_eq!(cache.get(0, &[1.0]), Some(1.0));
+        assert_eq!(cache.get(1, &[1.0]), None);
+        assert_eq!(cache.get(2, &[1.0]), Some(3.0));
+
+        cache.clear();
+        assert_eq!(cache.get(2, &[1.0]), None);
+        let mut empty = MemoCache::new(0, 0);
+        empty.insert(0, &[], 1.0);
+        assert_eq!(empty.get(0, &[]), None);
+    }
+}
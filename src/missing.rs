@@ -0,0 +1,116 @@
+//! Missing values, for inputs that are absent rather than invalid.
+//!
+//! [`MISSING`] is a NaN with a reserved payload, so a variable without a current reading
+//! can be told apart from one holding a NaN from a faulty sensor or a failed
+//! computation. Set it with [`EvalContext::set_missing`] and test it with the
+//! `is_missing(x)` builtin or [`is_missing`]; `default(x, v)` replaces it with `v`.
+//!
+//! Arithmetic does not reliably preserve NaN payloads, so by default a missing value
+//! behaves like any NaN once used. A context with
+//! [`set_missing_propagation`](crate::context::EvalContext::set_missing_propagation)
+//! enabled makes it propagate instead: a call with a missing argument, a logical
+//! operator or a condition on a missing value gives a missing result, so that a rule
+//! over an absent reading is neither true nor false.
+//!
+//! ```
+//! use exp_rs::context::EvalContext;
+//! use exp_rs::engine::interp;
+//! use exp_rs::missing::is_missing;
+//! use std::rc::Rc;
+//!
+//! let mut ctx = EvalContext::new();
+//! ctx.set_missing("pressure").unwrap();
+//! ctx.set_missing_propagation(true);
+//! let ctx = Rc::new(ctx);
+//!
+//! // Neither an alarm nor a clear condition
+//! assert!(is_missing(interp("pressure >= 5 ? 1 : 0", Some(ctx.clone())).unwrap()));
+//! assert_eq!(interp("default(pressure, 3) < 5", Some(ctx)).unwrap(), 1.0);
+//! ```
+//!
+//! [`EvalContext::set_missing`]: crate::context::EvalContext::set_missing
+
+use crate::Real;
+
+/// The missing value: a quiet NaN whose payload spells `MISS`.
+#[cfg(not(feature = "f32"))]
+pub const MISSING: Real = Real::from_bits(0x7ff8_0000_4d49_5353);
+
+/// The missing value: a quiet NaN whose payload spells `MS`.
+#[cfg(feature = "f32")]
+pub const MISSING: Real = Real::from_bits(0x7fc0_4d53);
+
+/// Returns whether `x` is the missing value, ignoring its sign.
+pub fn is_missing(x: Real) -> bool {
+    x.abs().to_bits() == MISSING.to_bits()
+}
+
+/// The builtins that receive missing arguments rather than propagating them.
+pub(crate) fn handles_missing(name: &str) -> bool {
+    matches!(name, "is_missing" | "default")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::EvalContext;
+    use crate::engine::interp;
+    use crate::expression::Expression;
+    use alloc::rc::Rc;
+
+    #[test]
+    fn test_missing_values() {
+        assert!(is_missing(MISSING) && is_missing(-MISSING));
+        assert!(!is_missing(Real::NAN) && !is_missing(0.0));
+
+        let mut ctx = EvalContext::new();
+        ctx.set_missing("flow").unwrap();
+        ctx.set_parameter("broken", Real::NAN).unwrap();
+        ctx.set_parameter("level", 4.0).unwrap();
+        let ctx = Rc::new(ctx);
+        let eval = |expr: &str| interp(expr, Some(ctx.clone())).unwrap();
+
+        assert_eq!(eval("is_missing(flow)"), 1.0);
+        assert_eq!(eval("is_missing(broken) + is_missing(level)"), 0.0);
+        assert_eq!(eval("default(flow, 2) + default(level, 9)"), 6.0);
+        assert!(eval("default(broken, 2)").is_nan());
+        // Without propagation a missing value is used like NaN
+        assert_eq!(eval("flow > 1 || 1"), 1.0);
+        assert_eq!(eval("flow ? 1 : 0"), 1.0);
+
+        // Propagation is inherited by child contexts
+        let mut parent = EvalContext::new();
+        parent.set_missing_propagation(true);
+        let mut child = EvalContext::new();
+        child.parent = Some(Rc::new(parent));
+        child.set_missing("flow").unwrap();
+        child.set_parameter("level", 4.0).unwrap();
+        assert!(child.missing_propagation());
+        let ctx = Rc::new(child);
+        let eval = |expr: &str| interp(expr, Some(ctx.clone())).unwrap();
+
+        for expr in [
+            "-flow",
+            "sin(flow) * 0",
+            "max(level, flow)",
+            "flow > 1 || 1",
+            "level > 1 && flow",
+            "flow ? 1 : 0",
+            "level > 1 ? flow : 0",
+        ] {
+            assert!(is_missing(eval(expr)), "{expr}");
+        }
+        assert_eq!(eval("level > 5 ? flow : 1"), 1.0);
+        assert_eq!(eval("level < 5 || flow"), 1.0);
+        assert_eq!(eval("is_missing(flow * 2)"), 1.0);
+        assert_eq!(eval("default(flow * 2, level)"), 4.0);
+
+        // Batches see missing parameters too
+        let arena = bumpalo::Bump::new();
+        let mut batch = Expression::new(&arena);
+        batch.add_parameter("rate", MISSING).unwrap();
+        batch.add_expression("default(rate, 1) + rate").unwrap();
+        batch.eval(&ctx).unwrap();
+        assert!(is_missing(batch.get_result(0).unwrap()));
+    }
+}
@@ -0,0 +1,357 @@
+//! Rendering of ASTs back to expression text.
+//!
+//! The printer uses the same binding powers as the parser in [`crate::engine`], so it
+//! only emits the parentheses needed for the text to parse back into the same tree.
+//! For every tree returned by [`parse_expression`], parsing the rendered text yields
+//! an equal tree, which [`canonicalize`] relies on to normalize formulas.
+
+extern crate alloc;
+
+use crate::engine::{infix_binding_power, parse_expression, prefix_binding_power};
+use crate::error::ExprError;
+use crate::types::AstExpr;
+use alloc::string::String;
+use bumpalo::Bump;
+use core::fmt::{self, Write};
+
+/// Precedence reported for atoms (constants, variables, calls, array and attribute access).
+const ATOM: u8 = u8::MAX;
+
+impl<'arena> AstExpr<'arena> {
+    /// Renders the AST back to expression text with minimal parentheses.
+    ///
+    /// Binary operators are written infix, `neg` with one argument as a prefix `-`,
+    /// logical operators as `&&`/`||` and conditionals as `c ? a : b`. Parentheses are
+    /// only added where the parser's precedence or associativity would otherwise
+    /// change the meaning, so parsing the output yields an equivalent tree.
+    ///
+    /// For trees returned by [`parse_expression`] the round trip is exact: parsing the
+    /// output gives a tree equal to `self`. Other trees may come back in the parser's
+    /// form, e.g. a negative constant as `neg` of a positive one.
+    ///
+    /// Infinite constants are rendered as `1e999`, which parses back as infinity. NaN
+    /// constants are rendered as `NaN`, which does not parse back as a number.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use exp_rs::engine::parse_expression;
+    /// use bumpalo::Bump;
+    ///
+    /// let arena = Bump::new();
+    /// let ast = parse_expression("((a + b)) * (c * d) - (-x)^2", &arena).unwrap();
+    /// assert_eq!(ast.to_expression_string(), "(a + b) * (c * d) - (-x)^2");
+    /// ```
+    pub fn to_expression_string(&self) -> String {
+        let mut out = String::new();
+        // Writing into a String cannot fail
+        let _ = write_expr(self, true, &mut out);
+        out
+    }
+}
+
+/// Normalizes the whitespace and parentheses of an expression.
+///
+/// Parses `expression` and renders it back with [`AstExpr::to_expression_string`], so
+/// formulas that parse to the same tree get the same text and canonicalizing is
+/// idempotent. Only redundant formatting is removed: operands are not reordered and
+/// numbers keep their value, so `b + a` and `a + b` stay different.
+///
+/// # Errors
+///
+/// Returns the parse error if `expression` does not parse.
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::engine::canonicalize;
+///
+/// assert_eq!(canonicalize("((a+b))*c").unwrap(), "(a + b) * c");
+/// assert_eq!(canonicalize("a + (b * 2.50)").unwrap(), "a + b * 2.5");
+/// assert!(canonicalize("a +").is_err());
+/// ```
+pub fn canonicalize(expression: &str) -> Result<String, ExprError> {
+    let arena = Bump::new();
+    Ok(parse_expression(expression, &arena)?.to_expression_string())
+}
+
+impl fmt::Display for AstExpr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_expr(self, true, f)
+    }
+}
+
+/// Infix operator of a node, if it is rendered as one.
+fn infix_op<'a>(expr: &AstExpr<'a>) -> Option<(&'a str, u8, u8)> {
+    match expr {
+        AstExpr::Function { name, args } if args.len() == 2 => {
+            infix_binding_power(name).map(|(l, r)| (*name, l, r))
+        }
+        _ => None,
+    }
+}
+
+/// Whether a node is rendered as a prefix negation.
+fn is_prefix(expr: &AstExpr<'_>) -> bool {
+    match expr {
+        AstExpr::Constant(val) => val.is_sign_negative() && !val.is_nan(),
+        AstExpr::Function { name, args } => *name == "neg" && args.len() == 1,
+        _ => false,
+    }
+}
+
+fn neg_power() -> u8 {
+    prefix_binding_power("-").unwrap_or(0)
+}
+
+/// Minimum binding power used when parsing the right operand of an infix operator.
+fn rhs_min_power(op: &str, right: u8) -> u8 {
+    // Power operators parse their right operand one level lower so that prefix
+    // negation is accepted, e.g. `2^-3`.
+    if op == "^" || op == "**" {
+        right - 1
+    } else {
+        right
+    }
+}
+
+/// Binding strength of a node when it appears as an operand.
+fn precedence(expr: &AstExpr<'_>) -> u8 {
+    if let Some((_, left, _)) = infix_op(expr) {
+        return left;
+    }
+    if is_prefix(expr) {
+        return neg_power();
+    }
+    match expr {
+        AstExpr::LogicalOp { op, .. } => logical_power(op).0,
+        AstExpr::Conditional { .. } => infix_binding_power("?").map_or(0, |(l, _)| l),
+        _ => ATOM,
+    }
+}
+
+/// Minimum binding power of the trailing operand of a node, i.e. how strongly the
+/// node's rightmost part would absorb an operator written after it.
+fn trailing_power(expr: &AstExpr<'_>) -> u8 {
+    if let Some((op, _, right)) = infix_op(expr) {
+        return rhs_min_power(op, right);
+    }
+    if is_prefix(expr) {
+        return neg_power();
+    }
+    match expr {
+        AstExpr::LogicalOp { op, .. } => logical_power(op).1,
+        // Conditional branches are parsed with the lowest binding power
+        AstExpr::Conditional { .. } => 0,
+        _ => ATOM,
+    }
+}
+
+fn logical_power(op: &crate::types::LogicalOperator) -> (u8, u8) {
+    let text = match op {
+        crate::types::LogicalOperator::And => "&&",
+        crate::types::LogicalOperator::Or => "||",
+    };
+    infix_binding_power(text).unwrap_or((0, 0))
+}
+
+fn is_comma(expr: &AstExpr<'_>) -> bool {
+    matches!(infix_op(expr), Some(("," | ";", _, _)))
+}
+
+/// Writes an operand that appears to the left of an operator with binding power `power`.
+fn write_left<W: Write>(
+    expr: &AstExpr<'_>,
+    power: u8,
+    allow_comma: bool,
+    out: &mut W,
+) -> fmt::Result {
+    let parens = precedence(expr) < power || trailing_power(expr) <= power;
+    write_operand(expr, parens, allow_comma, out)
+}
+
+/// Writes an operand parsed with minimum binding power `min_power`.
+fn write_right<W: Write>(
+    expr: &AstExpr<'_>,
+    min_power: u8,
+    allow_comma: bool,
+    out: &mut W,
+) -> fmt::Result {
+    // Prefix operators are accepted at any binding power
+    let parens = !is_prefix(expr) && precedence(expr) < min_power;
+    write_operand(expr, parens, allow_comma, out)
+}
+
+fn write_operand<W: Write>(
+    expr: &AstExpr<'_>,
+    parens: bool,
+    allow_comma: bool,
+    out: &mut W,
+) -> fmt::Result {
+    if parens || (!allow_comma && is_comma(expr)) {
+        out.write_char('(')?;
+        write_expr(expr, true, out)?;
+        out.write_char(')')
+    } else {
+        write_expr(expr, allow_comma, out)
+    }
+}
+
+fn write_expr<W: Write>(expr: &AstExpr<'_>, allow_comma: bool, out: &mut W) -> fmt::Result {
+    if let Some((op, left, right)) = infix_op(expr) {
+        let AstExpr::Function { args, .. } = expr else {
+            unreachable!("infix_op only matches functions")
+        };
+        write_left(&args[0], left, allow_comma, out)?;
+        match op {
+            "," | ";" => write!(out, "{} ", op)?,
+            "^" | "**" => out.write_str(op)?,
+            _ => write!(out, " {} ", op)?,
+        }
+        return write_right(&args[1], rhs_min_power(op, right), allow_comma, out);
+    }
+
+    match expr {
+        AstExpr::Constant(val) if val.is_infinite() => {
+            out.write_str(if *val > 0.0 { "1e999" } else { "-1e999" })
+        }
+        AstExpr::Constant(val) => write!(out, "{}", val),
+        AstExpr::Variable(name) => out.write_str(name),
+        AstExpr::Function { name, args } if *name == "neg" && args.len() == 1 => {
+            out.write_char('-')?;
+            // A nested prefix minus needs parentheses so it doesn't read as `--`
+            let parens = is_prefix(&args[0]) || precedence(&args[0]) < neg_power();
+            write_operand(&args[0], parens, allow_comma, out)
+        }
+        AstExpr::Function { name, args } => {
+            write!(out, "{}(", name)?;
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    out.write_str(", ")?;
+                }
+                write_operand(arg, false, false, out)?;
+            }
+            out.write_char(')')
+        }
+        AstExpr::Array { name, index } => {
+            write!(out, "{}[", name)?;
+            match index {
+                AstExpr::Function {
+                    name: ":",
+                    args: [start, end],
+                } => {
+                    write_expr(start, true, out)?;
+                    out.write_char(':')?;
+                    write_expr(end, true, out)?;
+                }
+                _ => write_expr(index, true, out)?,
+            }
+            out.write_char(']')
+        }
+        AstExpr::Attribute { base, attr } => write!(out, "{}.{}", base, attr),
+        AstExpr::LogicalOp { op, left, right } => {
+            let (l, r) = logical_power(op);
+            write_left(left, l, allow_comma, out)?;
+            write!(out, " {} ", op)?;
+            write_right(right, r, allow_comma, out)
+        }
+        AstExpr::Conditional {
+            condition,
+            true_branch,
+            false_branch,
+        } => {
+            let power = precedence(expr);
+            write_left(condition, power, allow_comma, out)?;
+            out.write_str(" ? ")?;
+            write_operand(true_branch, false, allow_comma, out)?;
+            out.write_str(" : ")?;
+            write_operand(false_branch, false, allow_comma, out)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::canonicalize;
+    use crate::engine::parse_expression;
+    use bumpalo::Bump;
+
+    fn render(input: &str) -> std::string::String {
+        let arena = Bump::new();
+        parse_expression(input, &arena)
+            .unwrap()
+            .to_expression_string()
+    }
+
+    #[test]
+    fn test_minimal_parentheses() {
+        assert_eq!(render("(a + b) * c"), "(a + b) * c");
+        assert_eq!(render("a + (b * c)"), "a + b * c");
+        assert_eq!(render("(a - b) - c"), "a - b - c");
+        assert_eq!(render("a - (b - c)"), "a - (b - c)");
+        assert_eq!(render("a / (b * c)"), "a / (b * c)");
+        assert_eq!(render("2^(3^4)"), "2^3^4");
+        assert_eq!(render("(2^3)^4"), "(2^3)^4");
+        assert_eq!(render("a ** (b ^ c)"), "a**b^c");
+        assert_eq!(render("(a ** b) ^ c"), "(a**b)^c");
+    }
+
+    #[test]
+    fn test_prefix_negation() {
+        assert_eq!(render("-x^2"), "-x^2");
+        assert_eq!(render("(-x)^2"), "(-x)^2");
+        assert_eq!(render("2^-3"), "2^-3");
+        assert_eq!(render("-(a + b)"), "-(a + b)");
+        assert_eq!(render("a * -b"), "a * -b");
+        assert_eq!(render("-(-a)"), "-(-a)");
+    }
+
+    #[test]
+    fn test_calls_logic_and_conditionals() {
+        assert_eq!(render("max(a, (b, c))"), "max(a, (b, c))");
+        assert_eq!(render("data[i + 1] * point.x"), "data[i + 1] * point.x");
+        assert_eq!(render("a && (b || c)"), "a && (b || c)");
+        assert_eq!(render("(a && b) || c"), "a && b || c");
+        assert_eq!(render("x > 0 ? 1 : -1"), "x > 0 ? 1 : -1");
+        assert_eq!(render("(c ? a : b) + 1"), "(c ? a : b) + 1");
+        assert_eq!(render("a ? b : (c ? d : e)"), "a ? b : c ? d : e");
+        assert_eq!(render("(a ? b : c) ? d : e"), "(a ? b : c) ? d : e");
+        assert_eq!(render("1.5 + 0.25"), "1.5 + 0.25");
+    }
+
+    #[test]
+    fn test_round_trip_and_canonicalize() {
+        let cases = [
+            "-(a + b) * c^-2 % 3",
+            "a - (b - c) - -d",
+            "(2^3)^4 + 2**3**4",
+            "x > 0 && y <= 1 || z == 0",
+            "a ? b : c ? d : (e ? f : g)",
+            "(a ? b : c) + max(a, (b, c), -1e999)",
+            "data[i + 1] * point.x + samples[0:2]",
+            "a, b; c",
+            "0.1 + 1e-320 - 1e999 * 1e300",
+        ];
+        for case in cases {
+            let arena = Bump::new();
+            let ast = parse_expression(case, &arena).unwrap();
+            let text = ast.to_expression_string();
+            assert_eq!(
+                parse_expression(&text, &arena).unwrap(),
+                ast,
+                "{case} -> {text}"
+            );
+            assert_eq!(canonicalize(case).unwrap(), text);
+            assert_eq!(canonicalize(&text).unwrap(), text);
+        }
+        assert_eq!(render("1e999 + -1e999"), "1e999 + -1e999");
+        assert_eq!(
+            canonicalize(" gain*( x+offset ) ").unwrap(),
+            canonicalize("gain * (x + offset)").unwrap()
+        );
+        assert_ne!(
+            canonicalize("a + b").unwrap(),
+            canonicalize("b + a").unwrap()
+        );
+    }
+}
@@ -0,0 +1,101 @@
+//! Per-expression evaluation statistics of batches.
+//!
+//! [`Expression::enable_stats`](crate::expression::Expression::enable_stats) times every
+//! evaluation of every expression of a batch with a caller-provided counter, like the
+//! [`bench`](crate::bench) harness: a cycle counter on a target or a nanosecond clock on
+//! a host. The [`ExpressionStats`] of an expression count its evaluations and errors,
+//! keep the fewest, most and total ticks taken, and the last error, so the formulas that
+//! exceed a time budget in production can be found.
+//!
+//! Results served from the memoization cache are not evaluations and are not counted.
+
+use crate::Real;
+use crate::error::ExprError;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+/// Evaluation counts and costs of one expression of a batch.
+#[derive(Debug, Clone, Default)]
+pub struct ExpressionStats {
+    /// Evaluations, including failed ones
+    pub evaluations: u64,
+    /// Evaluations that failed
+    pub errors: u64,
+    /// Fewest ticks an evaluation took, or 0 before the first evaluation
+    pub min_ticks: u32,
+    /// Most ticks an evaluation took
+    pub max_ticks: u32,
+    /// Ticks taken by all evaluations together
+    pub total_ticks: u64,
+    /// Error of the latest failed evaluation, if any failed
+    pub last_error: Option<ExprError>,
+}
+
+impl ExpressionStats {
+    /// Mean ticks per evaluation, or `None` before the first evaluation.
+    pub fn mean_ticks(&self) -> Option<Real> {
+        (self.evaluations > 0).then(|| self.total_ticks as Real / self.evaluations as Real)
+    }
+
+    fn record(&mut self, ticks: u32, error: Option<&ExprError>) {
+        self.min_ticks = if self.evaluations == 0 {
+            ticks
+        } else {
+            self.min_ticks.min(ticks)
+        };
+        self.max_ticks = self.max_ticks.max(ticks);
+        self.total_ticks += ticks as u64;
+        self.evaluations += 1;
+        if let Some(error) = error {
+            self.errors += 1;
+            self.last_error = Some(error.clone());
+        }
+    }
+}
+
+/// Counter and statistics of the expressions of a batch.
+pub(crate) struct Profiler<'a> {
+    clock: Box<dyn FnMut() -> u32 + 'a>,
+    stats: Vec<ExpressionStats>,
+}
+
+impl<'a> Profiler<'a> {
+    pub(crate) fn new(clock: Box<dyn FnMut() -> u32 + 'a>) -> Self {
+        Profiler {
+            clock,
+            stats: Vec::new(),
+        }
+    }
+
+    /// Reads the counter at the start of an evaluation.
+    pub(crate) fn start(&mut self) -> u32 {
+        (self.clock)()
+    }
+
+    /// Records an evaluation of expression `index` that started at `start`.
+    pub(crate) fn finish(&mut self, index: usize, start: u32, error: Option<&ExprError>) {
+        // A wrapping counter still gives the elapsed ticks
+        let ticks = (self.clock)().wrapping_sub(start);
+        if self.stats.len() <= index {
+            self.stats.resize_with(index + 1, ExpressionStats::default);
+        }
+        self.stats[index].record(ticks, error);
+    }
+
+    /// Statistics of expression `index`, which are empty until it is evaluated.
+    pub(crate) fn get(&self, index: usize) -> &ExpressionStats {
+        static NOT_EVALUATED: ExpressionStats = ExpressionStats {
+            evaluations: 0,
+            errors: 0,
+            min_ticks: 0,
+            max_ticks: 0,
+            total_ticks: 0,
+            last_error: None,
+        };
+        self.stats.get(index).unwrap_or(&NOT_EVALUATED)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.stats.clear();
+    }
+}
@@ -0,0 +1,271 @@
+//! Programs: several statements evaluated in order.
+//!
+//! A program is a sequence of expressions separated by newlines or `;`. A statement
+//! of the form `name = expression` stores its value in the context as a variable (or
+//! as an attribute for a dotted name, see [`EvalContext::set_path`]), which later
+//! statements (and later evaluations) can read. The value of a program is
+//! the value of its last statement.
+//!
+//! ```
+//! use exp_rs::EvalContext;
+//! use exp_rs::program::interp_program;
+//! use std::rc::Rc;
+//!
+//! let mut ctx = Rc::new(EvalContext::new());
+//! let source = "
+//!     width = 4; height = 2.5
+//!     area = width * height
+//!     area * 2
+//! ";
+//! assert_eq!(interp_program(source, &mut ctx).unwrap(), 20.0);
+//! assert_eq!(ctx.get_variable("area"), Some(10.0));
+//! ```
+//!
+//! A newline does not end a statement inside parentheses or brackets, or after a
+//! binary operator, so long formulas can be broken over several lines.
+
+use crate::Real;
+use crate::context::EvalContext;
+use crate::engine::parse_expression;
+use crate::error::ExprError;
+use crate::eval::iterative::eval_iterative;
+use crate::lexer::{is_identifier_continue, is_identifier_start};
+use crate::types::AstExpr;
+use alloc::rc::Rc;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use bumpalo::Bump;
+
+/// A statement of a [`Program`].
+#[derive(Debug, Clone, Copy)]
+pub struct Statement<'arena> {
+    /// Variable receiving the value, for `name = expression` statements
+    pub target: Option<&'arena str>,
+    /// Expression computing the value
+    pub expr: &'arena AstExpr<'arena>,
+}
+
+/// A parsed program, evaluated statement by statement.
+#[derive(Debug)]
+pub struct Program<'arena> {
+    statements: Vec<Statement<'arena>>,
+    arena: &'arena Bump,
+}
+
+impl<'arena> Program<'arena> {
+    /// Parses the statements of `source` into `arena`.
+    ///
+    /// Error positions are byte offsets into `source`. A program without any
+    /// statement is a syntax error.
+    pub fn parse(source: &str, arena: &'arena Bump) -> Result<Self, ExprError> {
+        let mut statements = Vec::new();
+        for (offset, text) in split_statements(source) {
+            let (target, expr_offset) = match split_assignment(text) {
+                Some((name, eq)) => {
+                    if !is_identifier(name) {
+                        return Err(ExprError::Syntax {
+                            message: "Expected a variable name before '='".to_string(),
+                            position: Some(offset),
+                        });
+                    }
+                    (Some(&*arena.alloc_str(name)), eq + 1)
+                }
+                None => (None, 0),
+            };
+            let expr = parse_expression(&text[expr_offset..], arena)
+                .map_err(|err| shift_position(err, offset + expr_offset))?;
+            statements.push(Statement {
+                target,
+                expr: arena.alloc(expr),
+            });
+        }
+
+        if statements.is_empty() {
+            return Err(ExprError::Syntax {
+                message: "Program has no statements".to_string(),
+                position: None,
+            });
+        }
+        Ok(Self { statements, arena })
+    }
+
+    /// Returns the statements in evaluation order.
+    pub fn statements(&self) -> &[Statement<'arena>] {
+        &self.statements
+    }
+
+    /// Evaluates the statements in order and returns the value of the last one.
+    ///
+    /// Assignments are stored in `ctx` as they happen. If `ctx` is shared with other
+    /// handles, it is copied first (see [`Rc::make_mut`]), so the assignments are only
+    /// visible through `ctx`. Evaluation stops at the first error, keeping the
+    /// assignments made before it.
+    pub fn eval(&self, ctx: &mut Rc<EvalContext>) -> Result<Real, ExprError> {
+        let mut value = Real::NAN;
+        for statement in &self.statements {
+            value = eval_iterative(statement.expr, Some(ctx.clone()), self.arena)?;
+            if let Some(name) = statement.target {
+                Rc::make_mut(ctx).set_path(name, value)?;
+            }
+        }
+        Ok(value)
+    }
+}
+
+/// Parses and evaluates the program `source`, storing its assignments in `ctx`.
+pub fn interp_program(source: &str, ctx: &mut Rc<EvalContext>) -> Result<Real, ExprError> {
+    let arena = Bump::new();
+    Program::parse(source, &arena)?.eval(ctx)
+}
+
+/// Splits `source` into non-blank statements with their byte offsets.
+fn split_statements(source: &str) -> Vec<(usize, &str)> {
+    let mut statements = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in source.char_indices() {
+        let ends_statement = match c {
+            '(' | '[' => {
+                depth += 1;
+                false
+            }
+            ')' | ']' => {
+                depth = depth.saturating_sub(1);
+                false
+            }
+            ';' => depth == 0,
+            '\n' => depth == 0 && !ends_with_operator(&source[start..i]),
+            _ => false,
+        };
+        if ends_statement {
+            push_statement(&mut statements, source, start, i);
+            start = i + 1;
+        }
+    }
+    push_statement(&mut statements, source, start, source.len());
+    statements
+}
+
+fn push_statement<'s>(
+    statements: &mut Vec<(usize, &'s str)>,
+    source: &'s str,
+    start: usize,
+    end: usize,
+) {
+    let text = &source[start..end];
+    let trimmed = text.trim_start();
+    if !trimmed.trim_end().is_empty() {
+        statements.push((start + text.len() - trimmed.len(), trimmed.trim_end()));
+    }
+}
+
+/// Whether a line continues on the next one because it ends with a binary operator.
+fn ends_with_operator(text: &str) -> bool {
+    text.trim_end().ends_with([
+        '+', '-', '*', '/', '%', '^', '&', '|', '<', '>', '=', '?', ':', ',',
+    ])
+}
+
+/// Finds the `=` of an assignment, returning the trimmed target and its offset.
+fn split_assignment(text: &str) -> Option<(&str, usize)> {
+    let bytes = text.as_bytes();
+    let mut depth = 0usize;
+    for (i, &b) in bytes.iter().enumerate() {
+        match b {
+            b'(' | b'[' => depth += 1,
+            b')' | b']' => depth = depth.saturating_sub(1),
+            b'=' if depth == 0 => {
+                let prev = i.checked_sub(1).map(|j| bytes[j]);
+                let next = bytes.get(i + 1).copied();
+                // Skip the comparison operators ==, !=, <= and >=
+                if matches!(prev, Some(b'=' | b'!' | b'<' | b'>')) || next == Some(b'=') {
+                    continue;
+                }
+                return Some((text[..i].trim(), i));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Whether `name` is a variable name or path, such as `gain`, `motor::kp` or
+/// `drive.motor1.current`.
+fn is_identifier(name: &str) -> bool {
+    name.split('.')
+        .flat_map(|part| part.split("::"))
+        .all(|segment| {
+            let mut chars = segment.chars();
+            chars.next().is_some_and(is_identifier_start) && chars.all(is_identifier_continue)
+        })
+}
+
+/// Makes the position of a statement's parse error relative to the whole program.
+fn shift_position(err: ExprError, offset: usize) -> ExprError {
+    match err {
+        ExprError::Syntax {
+            message,
+            position: Some(position),
+        } => ExprError::Syntax {
+            message,
+            position: Some(position + offset),
+        },
+        ExprError::Tokenizer { message, position } => ExprError::Tokenizer {
+            message,
+            position: position + offset,
+        },
+        ExprError::UnmatchedParenthesis { position, found } => ExprError::UnmatchedParenthesis {
+            position: position + offset,
+            found,
+        },
+        err => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_program_statements_and_assignments() {
+        let mut ctx = EvalContext::new();
+        ctx.set_parameter("x", 3.0).unwrap();
+        let mut ctx = Rc::new(ctx);
+
+        let source = "a = x * 2; b = a +\n  1\n\nc = max(a,\n  b, 0)\nc == 7 ? c : -1";
+        assert_eq!(interp_program(source, &mut ctx).unwrap(), 7.0);
+        assert_eq!(ctx.get_variable("a"), Some(6.0));
+        assert_eq!(ctx.get_variable("c"), Some(7.0));
+
+        // Comparisons are not assignments
+        let arena = Bump::new();
+        let program = Program::parse("x >= 3\ny = x != 3\nx <= 2 || x == 3", &arena).unwrap();
+        let targets: Vec<_> = program.statements().iter().map(|s| s.target).collect();
+        assert_eq!(targets, [None, Some("y"), None]);
+        assert_eq!(program.eval(&mut ctx).unwrap(), 1.0);
+        assert_eq!(ctx.get_variable("y"), Some(0.0));
+
+        // Errors point into the whole program
+        assert!(matches!(
+            Program::parse("a = 1\nb = 2 +* 3", &arena),
+            Err(ExprError::Syntax { position: Some(p), .. }) if p > 6
+        ));
+        assert!(matches!(
+            Program::parse("2 * a = 1", &arena),
+            Err(ExprError::Syntax {
+                position: Some(0),
+                ..
+            })
+        ));
+        assert!(Program::parse(" ;\n ", &arena).is_err());
+
+        // Dotted and namespaced targets
+        interp_program(
+            "drive.motor1.gain = 2; ctrl::kp = drive.motor1.gain * 3",
+            &mut ctx,
+        )
+        .unwrap();
+        assert_eq!(ctx.get_attribute("drive.motor1", "gain"), Some(2.0));
+        assert_eq!(ctx.get_variable("ctrl::kp"), Some(6.0));
+    }
+}
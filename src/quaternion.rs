@@ -0,0 +1,166 @@
+//! Quaternion helpers (requires the `quaternion` feature).
+//!
+//! Quaternions are 4-element arrays `[w, x, y, z]` with the scalar part first, and
+//! rotations follow the Hamilton convention. These functions back the `qmul`,
+//! `qrotate`, `qnorm`, `euler_to_q` and `q_to_euler` builtins of
+//! [`eval_linalg`](crate::linalg::eval_linalg), where context arrays of up to 4
+//! elements can be used as vectors:
+//!
+//! ```
+//! use exp_rs::context::EvalContext;
+//! use exp_rs::engine::parse_expression;
+//! use exp_rs::linalg::eval_linalg;
+//! use bumpalo::Bump;
+//!
+//! let mut ctx = EvalContext::new();
+//! // Attitude: 90 degrees of yaw
+//! let half = std::f64::consts::FRAC_PI_4;
+//! ctx.set_array("att", vec![half.cos(), 0.0, 0.0, half.sin()]).unwrap();
+//! ctx.set_array("body_x", vec![1.0, 0.0, 0.0]).unwrap();
+//!
+//! let arena = Bump::new();
+//! let ast = parse_expression("dot(qrotate(att, body_x), vec(0, 1, 0))", &arena).unwrap();
+//! let north = eval_linalg(&ast, Some(&ctx)).unwrap().as_scalar().unwrap();
+//! assert!((north - 1.0).abs() < 1e-12);
+//! ```
+//!
+//! Euler angles are roll, pitch and yaw in radians, applied in yaw-pitch-roll (Z-Y-X)
+//! order as is usual for aircraft.
+
+use crate::Real;
+use crate::functions;
+
+/// A quaternion `[w, x, y, z]`.
+pub type Quaternion = [Real; 4];
+
+/// The Hamilton product `a * b`, which composes the rotation `b` followed by `a`.
+pub fn qmul(a: Quaternion, b: Quaternion) -> Quaternion {
+    let [aw, ax, ay, az] = a;
+    let [bw, bx, by, bz] = b;
+    [
+        aw * bw - ax * bx - ay * by - az * bz,
+        aw * bx + ax * bw + ay * bz - az * by,
+        aw * by - ax * bz + ay * bw + az * bx,
+        aw * bz + ax * by - ay * bx + az * bw,
+    ]
+}
+
+/// Rotates the vector `v` by the unit quaternion `q`, computing `q v q*`.
+pub fn qrotate(q: Quaternion, v: [Real; 3]) -> [Real; 3] {
+    let [w, x, y, z] = q;
+    // t = 2 (u x v), v' = v + w t + u x t
+    let t = [
+        2.0 * (y * v[2] - z * v[1]),
+        2.0 * (z * v[0] - x * v[2]),
+        2.0 * (x * v[1] - y * v[0]),
+    ];
+    [
+        v[0] + w * t[0] + (y * t[2] - z * t[1]),
+        v[1] + w * t[1] + (z * t[0] - x * t[2]),
+        v[2] + w * t[2] + (x * t[1] - y * t[0]),
+    ]
+}
+
+/// Scales `q` to unit length. A zero quaternion gives NaN components.
+pub fn qnorm(q: Quaternion) -> Quaternion {
+    let len = functions::sqrt(q.iter().map(|c| c * c).sum(), 0.0);
+    q.map(|c| c / len)
+}
+
+/// The unit quaternion for the rotation by `roll`, `pitch` and `yaw`.
+pub fn euler_to_q(roll: Real, pitch: Real, yaw: Real) -> Quaternion {
+    let (sr, cr) = (
+        functions::sin(roll / 2.0, 0.0),
+        functions::cos(roll / 2.0, 0.0),
+    );
+    let (sp, cp) = (
+        functions::sin(pitch / 2.0, 0.0),
+        functions::cos(pitch / 2.0, 0.0),
+    );
+    let (sy, cy) = (
+        functions::sin(yaw / 2.0, 0.0),
+        functions::cos(yaw / 2.0, 0.0),
+    );
+    [
+        cr * cp * cy + sr * sp * sy,
+        sr * cp * cy - cr * sp * sy,
+        cr * sp * cy + sr * cp * sy,
+        cr * cp * sy - sr * sp * cy,
+    ]
+}
+
+/// The roll, pitch and yaw of the unit quaternion `q`.
+///
+/// Pitch is clamped to ±90 degrees, where roll and yaw are not uniquely defined.
+pub fn q_to_euler(q: Quaternion) -> [Real; 3] {
+    let [w, x, y, z] = q;
+    let sin_pitch = (2.0 * (w * y - z * x)).clamp(-1.0, 1.0);
+    [
+        functions::atan2(2.0 * (w * x + y * z), 1.0 - 2.0 * (x * x + y * y)),
+        functions::asin(sin_pitch, 0.0),
+        functions::atan2(2.0 * (w * z + x * y), 1.0 - 2.0 * (y * y + z * z)),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::EvalContext;
+    use crate::engine::parse_expression;
+    use crate::error::ExprError;
+    use crate::linalg::eval_linalg;
+    use bumpalo::Bump;
+
+    fn close(a: &[Real], b: &[Real]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-9)
+    }
+
+    #[test]
+    fn test_quaternion_builtins() {
+        let (roll, pitch, yaw) = (0.1, -0.4, 2.5);
+        let q = euler_to_q(roll, pitch, yaw);
+        assert!(close(&q_to_euler(q), &[roll, pitch, yaw]));
+        assert!(close(&qnorm(q.map(|c| c * 3.0)), &q));
+
+        // Composing two yaw rotations adds the angles
+        let q2 = qmul(euler_to_q(0.0, 0.0, 0.3), euler_to_q(0.0, 0.0, 0.2));
+        assert!(close(&q_to_euler(q2), &[0.0, 0.0, 0.5]));
+        let half_turn = euler_to_q(0.0, 0.0, core::f64::consts::PI as Real);
+        assert!(close(
+            &qrotate(half_turn, [1.0, 2.0, 3.0]),
+            &[-1.0, -2.0, 3.0]
+        ));
+
+        let mut ctx = EvalContext::new();
+        ctx.set_array("q", q.to_vec()).unwrap();
+        ctx.set_array("v", alloc::vec![0.0, 0.0, 1.0]).unwrap();
+        ctx.set_parameter("yaw", yaw).unwrap();
+        let arena = Bump::new();
+        let eval = |s: &str| eval_linalg(&parse_expression(s, &arena).unwrap(), Some(&ctx));
+        let values = |s: &str| eval(s).unwrap().as_matrix().unwrap().values().to_vec();
+
+        assert!(close(&values("q_to_euler(q)"), &[roll, pitch, yaw]));
+        assert!(close(&values("qmul(q, euler_to_q(0, 0, 0))"), &q));
+        assert!(close(
+            &values("qrotate(q, v)"),
+            &qrotate(q, [0.0, 0.0, 1.0])
+        ));
+        assert!(close(&values("qnorm(2 * q)"), &q));
+        assert!(close(
+            &[eval("dot(q_to_euler(euler_to_q(0, 0, yaw)), vec(0, 0, 1))")
+                .unwrap()
+                .as_scalar()
+                .unwrap()],
+            &[yaw]
+        ));
+
+        assert!(matches!(
+            eval("qmul(q, v)"),
+            Err(ExprError::DimensionMismatch { .. })
+        ));
+        assert!(matches!(
+            eval("qnorm(yaw)"),
+            Err(ExprError::TypeError { .. })
+        ));
+    }
+}
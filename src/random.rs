@@ -0,0 +1,116 @@
+//! Seedable pseudo-random numbers for the `rand`, `rand_range` and `randn` builtins.
+//!
+//! [`Rng`] is a xoshiro256** generator: small, fast, without dependencies and
+//! reproducible across platforms for a given seed. It is not suitable for cryptography.
+
+use crate::Real;
+use core::cell::Cell;
+
+/// Seed used by contexts until [`EvalContext::set_seed`](crate::EvalContext::set_seed)
+/// is called.
+pub const DEFAULT_SEED: u64 = 0x853C_49E6_748F_EA9B;
+
+/// A xoshiro256** pseudo-random number generator.
+///
+/// The state sits in a [`Cell`], so numbers can be drawn through a shared reference
+/// as expression functions require.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: Cell<[u64; 4]>,
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self::new(DEFAULT_SEED)
+    }
+}
+
+impl Rng {
+    /// Creates a generator with the given seed.
+    pub fn new(seed: u64) -> Self {
+        let rng = Self {
+            state: Cell::new([0; 4]),
+        };
+        rng.seed(seed);
+        rng
+    }
+
+    /// Restarts the sequence from `seed`.
+    pub fn seed(&self, seed: u64) {
+        // Expand the seed with splitmix64, as recommended for xoshiro
+        let mut x = seed;
+        let mut next = || {
+            x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        };
+        self.state.set([next(), next(), next(), next()]);
+    }
+
+    /// Returns the next 64 random bits.
+    pub fn next_u64(&self) -> u64 {
+        let mut s = self.state.get();
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        self.state.set(s);
+        result
+    }
+
+    /// Returns a uniformly distributed value in `[0, 1)`.
+    pub fn next_real(&self) -> Real {
+        #[cfg(feature = "f32")]
+        return (self.next_u64() >> 40) as Real * (1.0 / (1u32 << 24) as Real);
+        #[cfg(not(feature = "f32"))]
+        return (self.next_u64() >> 11) as Real * (1.0 / (1u64 << 53) as Real);
+    }
+
+    /// Returns a uniformly distributed value in `[low, high)`.
+    pub fn range(&self, low: Real, high: Real) -> Real {
+        low + (high - low) * self.next_real()
+    }
+
+    /// Returns a standard normally distributed value (mean 0, standard deviation 1).
+    #[cfg(feature = "libm")]
+    pub fn normal(&self) -> Real {
+        // Box-Muller transform; 1 - u keeps the logarithm's argument in (0, 1]
+        let u1 = 1.0 - self.next_real();
+        let u2 = self.next_real();
+        let radius = crate::functions::sqrt(-2.0 * crate::functions::ln(u1, 0.0), 0.0);
+        radius * crate::functions::cos(2.0 * crate::constants::PI * u2, 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rng_is_reproducible_and_in_range() {
+        let a = Rng::new(42);
+        let b = Rng::new(42);
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+        b.seed(7);
+        assert_ne!(a.next_u64(), b.next_u64());
+
+        let rng = Rng::default();
+        let mut sum = 0.0;
+        for _ in 0..10_000 {
+            let x = rng.next_real();
+            assert!((0.0..1.0).contains(&x));
+            let y = rng.range(-2.0, 3.0);
+            assert!((-2.0..3.0).contains(&y));
+            sum += x;
+        }
+        assert!((sum / 10_000.0 - 0.5).abs() < 0.02);
+    }
+}
@@ -0,0 +1,572 @@
+//! Exact rational evaluation (requires the `rational` feature).
+//!
+//! [`eval_rational`] evaluates a parsed expression over fractions of `i128`, so
+//! `1/3 * 3` is exactly 1 and `0.1 + 0.2 == 0.3` is true. Numbers, from literals or
+//! the context, stand for the shortest decimal that converts to them, which is what
+//! was written in the expression or the configuration file.
+//!
+//! [`check_rounding`] compares the exact result with normal floating-point evaluation,
+//! finding the expressions whose result depends on rounding, e.g. when validating user
+//! constants at configuration time.
+//!
+//! Only the operators that keep fractions exact are supported: `+`, `-`, `*`, `/`,
+//! `^` (also `**` and `pow`) with integer exponents, negation, comparisons, `&&`, `||`,
+//! `?:` and lists. Other functions, values that are not finite and results that do not
+//! fit in `i128` fail the evaluation.
+
+use crate::Real;
+use crate::context::EvalContext;
+use crate::error::ExprError;
+use crate::types::{AstExpr, LogicalOperator, TryIntoHeaplessString};
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt;
+
+/// A fraction in lowest terms with a positive denominator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rational {
+    num: i128,
+    den: i128,
+}
+
+impl Rational {
+    /// Zero.
+    pub const ZERO: Rational = Rational::integer(0);
+    /// One.
+    pub const ONE: Rational = Rational::integer(1);
+
+    /// Creates the fraction `num / den` in lowest terms, or `None` if `den` is zero or
+    /// the reduced fraction does not fit.
+    pub fn new(num: i128, den: i128) -> Option<Self> {
+        if den == 0 {
+            return None;
+        }
+        let g = gcd(num.unsigned_abs(), den.unsigned_abs());
+        let (num, den) = (num / g as i128, den / g as i128);
+        if den < 0 {
+            Some(Rational {
+                num: num.checked_neg()?,
+                den: den.checked_neg()?,
+            })
+        } else {
+            Some(Rational { num, den })
+        }
+    }
+
+    /// Creates the integer `n`.
+    pub const fn integer(n: i128) -> Self {
+        Rational { num: n, den: 1 }
+    }
+
+    /// The shortest decimal that converts to `x`, so `0.1` gives 1/10, or `None` if `x`
+    /// is not finite or the decimal does not fit.
+    pub fn from_real(x: Real) -> Option<Self> {
+        if !x.is_finite() {
+            return None;
+        }
+        // Scientific notation prints the shortest digits that round-trip
+        let text = format!("{:e}", x);
+        let (mantissa, exponent) = text.split_once('e')?;
+        let exponent: i32 = exponent.parse().ok()?;
+        let fraction_digits = mantissa.split_once('.').map_or(0, |(_, f)| f.len() as i32);
+        let digits: i128 = mantissa.replace('.', "").parse().ok()?;
+        let scale = exponent - fraction_digits;
+        let power = 10i128.checked_pow(scale.unsigned_abs())?;
+        if scale >= 0 {
+            Some(Rational::integer(digits.checked_mul(power)?))
+        } else {
+            Rational::new(digits, power)
+        }
+    }
+
+    /// The numerator.
+    pub const fn numer(&self) -> i128 {
+        self.num
+    }
+
+    /// The denominator, always positive.
+    pub const fn denom(&self) -> i128 {
+        self.den
+    }
+
+    /// Returns whether the value is an integer.
+    pub const fn is_integer(&self) -> bool {
+        self.den == 1
+    }
+
+    /// The value rounded to the nearest `Real`, ties to even.
+    pub fn to_real(self) -> Real {
+        if self.num == 0 {
+            return 0.0;
+        }
+        let (n, d) = (self.num.unsigned_abs(), self.den as u128);
+        // Long division until at least three bits beyond the precision of Real are
+        // known, the remainder deciding ties as a sticky bit
+        let (mut sig, mut rem) = (n / d, n % d);
+        let mut exp = 0;
+        while sig < 1 << (Real::MANTISSA_DIGITS + 1) {
+            rem <<= 1;
+            sig = sig << 1 | (rem >= d) as u128;
+            if rem >= d {
+                rem -= d;
+            }
+            exp += 1;
+        }
+        // Converting an integer rounds to nearest, ties to even
+        let mut value = (sig | (rem != 0) as u128) as Real;
+        for _ in 0..exp {
+            value *= 0.5;
+        }
+        if self.num < 0 { -value } else { value }
+    }
+
+    /// Returns `self + other`, or `None` on overflow.
+    pub fn checked_add(self, other: Rational) -> Option<Rational> {
+        let g = gcd(self.den as u128, other.den as u128) as i128;
+        let num = self
+            .num
+            .checked_mul(other.den / g)?
+            .checked_add(other.num.checked_mul(self.den / g)?)?;
+        Rational::new(num, self.den.checked_mul(other.den / g)?)
+    }
+
+    /// Returns `self - other`, or `None` on overflow.
+    pub fn checked_sub(self, other: Rational) -> Option<Rational> {
+        self.checked_add(other.checked_neg()?)
+    }
+
+    /// Returns `self * other`, or `None` on overflow.
+    pub fn checked_mul(self, other: Rational) -> Option<Rational> {
+        // Cancelling crosswise first keeps the products small
+        let g1 = gcd(self.num.unsigned_abs(), other.den as u128) as i128;
+        let g2 = gcd(other.num.unsigned_abs(), self.den as u128) as i128;
+        Rational::new(
+            (self.num / g1).checked_mul(other.num / g2)?,
+            (self.den / g2).checked_mul(other.den / g1)?,
+        )
+    }
+
+    /// Returns `self / other`, or `None` if `other` is zero or on overflow.
+    pub fn checked_div(self, other: Rational) -> Option<Rational> {
+        self.checked_mul(other.checked_recip()?)
+    }
+
+    /// Returns `-self`, or `None` on overflow.
+    pub fn checked_neg(self) -> Option<Rational> {
+        Some(Rational {
+            num: self.num.checked_neg()?,
+            den: self.den,
+        })
+    }
+
+    /// Returns `1 / self`, or `None` if `self` is zero or on overflow.
+    pub fn checked_recip(self) -> Option<Rational> {
+        Rational::new(self.den, self.num)
+    }
+
+    /// Returns `self` raised to the integer power `exp`, or `None` for a negative power
+    /// of zero or on overflow. `0^0` is 1.
+    pub fn checked_pow(self, exp: i32) -> Option<Rational> {
+        let base = if exp < 0 { self.checked_recip()? } else { self };
+        // Reduced fractions stay reduced when raised to a power
+        Some(Rational {
+            num: base.num.checked_pow(exp.unsigned_abs())?,
+            den: base.den.checked_pow(exp.unsigned_abs())?,
+        })
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Compares a/b with c/d through their continued fractions, which cannot overflow
+        let (mut a, mut b, mut c, mut d) = (self.num, self.den, other.num, other.den);
+        loop {
+            let (qa, ra) = (a.div_euclid(b), a.rem_euclid(b));
+            let (qc, rc) = (c.div_euclid(d), c.rem_euclid(d));
+            if qa != qc {
+                return qa.cmp(&qc);
+            }
+            match (ra, rc) {
+                (0, 0) => return Ordering::Equal,
+                (0, _) => return Ordering::Less,
+                (_, 0) => return Ordering::Greater,
+                // ra/b < rc/d exactly when d/rc < b/ra
+                _ => (a, b, c, d) = (d, rc, b, ra),
+            }
+        }
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl From<i128> for Rational {
+    fn from(n: i128) -> Self {
+        Rational::integer(n)
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Evaluates an expression exactly over rational numbers.
+///
+/// Operators are chosen by name, so an operator overridden in the context keeps its
+/// exact meaning. Expression functions are not supported, as with the complex
+/// evaluator. Without a context, a default one is used.
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::engine::parse_expression;
+/// use exp_rs::rational::{Rational, eval_rational};
+/// use bumpalo::Bump;
+///
+/// let arena = Bump::new();
+/// let exact = |text| eval_rational(&parse_expression(text, &arena).unwrap(), None).unwrap();
+/// assert_eq!(exact("1/3 * 3"), Rational::ONE);
+/// assert_eq!(exact("0.1 + 0.2 == 0.3"), Rational::ONE);
+/// assert_eq!(exact("(2/3)^-2 - 0.25").to_string(), "2");
+/// assert!(eval_rational(&parse_expression("2^0.5", &arena).unwrap(), None).is_err());
+/// ```
+pub fn eval_rational(expr: &AstExpr, ctx: Option<&EvalContext>) -> Result<Rational, ExprError> {
+    let default_ctx;
+    let ctx = match ctx {
+        Some(ctx) => ctx,
+        None => {
+            default_ctx = EvalContext::new();
+            &default_ctx
+        }
+    };
+    Evaluator { ctx }.eval(expr)
+}
+
+/// Exact and floating-point results of an expression, from [`check_rounding`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RoundingCheck {
+    /// Exact result
+    pub exact: Rational,
+    /// Result of normal evaluation
+    pub float: Real,
+}
+
+impl RoundingCheck {
+    /// The exact result rounded to the nearest `Real`.
+    pub fn exact_real(&self) -> Real {
+        self.exact.to_real()
+    }
+
+    /// Returns whether normal evaluation missed the correctly rounded exact result.
+    pub fn depends_on_rounding(&self) -> bool {
+        self.float != self.exact_real()
+    }
+}
+
+/// Evaluates an expression exactly and with floating point, to find out whether its
+/// result depends on rounding.
+///
+/// The floating-point result is computed with the functions of the context, as by
+/// [`interp`](crate::engine::interp).
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::engine::parse_expression;
+/// use exp_rs::rational::check_rounding;
+/// use bumpalo::Bump;
+///
+/// let arena = Bump::new();
+/// let check = |text| check_rounding(&parse_expression(text, &arena).unwrap(), None).unwrap();
+/// assert!(!check("1/3 * 3").depends_on_rounding());
+/// assert!(check("0.1 + 0.2").depends_on_rounding());
+/// // A comparison can come out differently
+/// let equal = check("0.1 * 3 == 0.3");
+/// assert_eq!((equal.exact.to_string(), equal.float), ("1".to_string(), 0.0));
+/// ```
+pub fn check_rounding(
+    expr: &AstExpr,
+    ctx: Option<&EvalContext>,
+) -> Result<RoundingCheck, ExprError> {
+    let exact = eval_rational(expr, ctx)?;
+    let arena = bumpalo::Bump::new();
+    let float = crate::eval::iterative::eval_iterative(expr, ctx.cloned().map(Rc::new), &arena)?;
+    Ok(RoundingCheck { exact, float })
+}
+
+struct Evaluator<'a> {
+    ctx: &'a EvalContext,
+}
+
+impl Evaluator<'_> {
+    fn eval(&self, expr: &AstExpr) -> Result<Rational, ExprError> {
+        match expr {
+            AstExpr::Constant(value) => exact(*value, "constant"),
+            AstExpr::Variable(name) => exact(self.lookup_variable(name)?, name),
+            AstExpr::Array { name, index } => {
+                let index = self.eval(index)?;
+                let array = self
+                    .ctx
+                    .get_array(name)
+                    .ok_or_else(|| ExprError::UnknownVariable {
+                        name: name.to_string(),
+                    })?;
+                let idx = index.to_real() as usize;
+                let value =
+                    array
+                        .get(idx)
+                        .copied()
+                        .ok_or_else(|| ExprError::ArrayIndexOutOfBounds {
+                            name: name.to_string(),
+                            index: idx,
+                            len: array.len(),
+                        })?;
+                exact(value, name)
+            }
+            AstExpr::Attribute { base, attr } => {
+                let value = self
+                    .ctx
+                    .get_attribute_map(base)
+                    .and_then(|m| m.get(&attr.try_into_heapless().ok()?).copied())
+                    .ok_or_else(|| ExprError::AttributeNotFound {
+                        base: base.to_string(),
+                        attr: attr.to_string(),
+                    })?;
+                exact(value, attr)
+            }
+            AstExpr::LogicalOp { op, left, right } => {
+                let left = self.eval(left)? != Rational::ZERO;
+                let result = match op {
+                    LogicalOperator::And => left && self.eval(right)? != Rational::ZERO,
+                    LogicalOperator::Or => left || self.eval(right)? != Rational::ZERO,
+                };
+                Ok(truth(result))
+            }
+            AstExpr::Conditional {
+                condition,
+                true_branch,
+                false_branch,
+            } => {
+                if self.eval(condition)? != Rational::ZERO {
+                    self.eval(true_branch)
+                } else {
+                    self.eval(false_branch)
+                }
+            }
+            AstExpr::Function { name, args } => {
+                self.ctx.check_function_permitted(name)?;
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    values.push(self.eval(arg)?);
+                }
+                call(name, &values)
+            }
+        }
+    }
+
+    fn lookup_variable(&self, name: &str) -> Result<Real, ExprError> {
+        if let Some(value) = self
+            .ctx
+            .get_variable(name)
+            .or_else(|| self.ctx.get_constant(name))
+        {
+            return Ok(value);
+        }
+        match name {
+            "pi" | "PI" | "e" | "E" | "tau" | "TAU" => Err(ExprError::Other {
+                message: format!("'{}' is irrational and has no exact value", name),
+            }),
+            _ => self
+                .ctx
+                .resolve_variable(name)
+                .ok_or_else(|| ExprError::UnknownVariable {
+                    name: name.to_string(),
+                }),
+        }
+    }
+}
+
+/// The exact value of the number `value` read from `what`.
+fn exact(value: Real, what: &str) -> Result<Rational, ExprError> {
+    Rational::from_real(value).ok_or_else(|| {
+        if value.is_finite() {
+            overflow(what)
+        } else {
+            ExprError::TypeError {
+                operand: format!("value of '{}'", what),
+                expected: "finite number",
+                found: if value.is_nan() { "NaN" } else { "infinity" },
+            }
+        }
+    })
+}
+
+fn call(name: &str, args: &[Rational]) -> Result<Rational, ExprError> {
+    let result = match (name, args) {
+        ("+" | "add", [a, b]) => a.checked_add(*b),
+        ("-" | "sub", [a, b]) => a.checked_sub(*b),
+        ("*" | "mul", [a, b]) => a.checked_mul(*b),
+        ("/" | "div", [a, b]) => {
+            if *b == Rational::ZERO {
+                return Err(ExprError::DivideByZero);
+            }
+            a.checked_div(*b)
+        }
+        ("^" | "**" | "pow", [a, b]) => {
+            let exp = match (b.is_integer(), i32::try_from(b.numer())) {
+                (true, Ok(exp)) => exp,
+                _ => {
+                    return Err(ExprError::TypeError {
+                        operand: format!("exponent of '{}'", name),
+                        expected: "integer",
+                        found: "fraction",
+                    });
+                }
+            };
+            if *a == Rational::ZERO && exp < 0 {
+                return Err(ExprError::DivideByZero);
+            }
+            a.checked_pow(exp)
+        }
+        ("neg", [a]) => a.checked_neg(),
+        ("<", [a, b]) => Some(truth(a < b)),
+        (">", [a, b]) => Some(truth(a > b)),
+        ("<=", [a, b]) => Some(truth(a <= b)),
+        (">=", [a, b]) => Some(truth(a >= b)),
+        ("==", [a, b]) => Some(truth(a == b)),
+        ("!=" | "<>", [a, b]) => Some(truth(a != b)),
+        ("," | ";" | "comma", [.., last]) => Some(*last),
+        _ => {
+            return Err(ExprError::Other {
+                message: format!("'{}' has no exact rational evaluation", name),
+            });
+        }
+    };
+    result.ok_or_else(|| overflow(name))
+}
+
+fn truth(b: bool) -> Rational {
+    if b { Rational::ONE } else { Rational::ZERO }
+}
+
+fn overflow(what: &str) -> ExprError {
+    ExprError::Other {
+        message: format!("Result of '{}' does not fit in a 128-bit fraction", what),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::parse_expression;
+
+    fn exact(input: &str, ctx: Option<&EvalContext>) -> Result<Rational, ExprError> {
+        let arena = bumpalo::Bump::new();
+        eval_rational(&parse_expression(input, &arena).unwrap(), ctx)
+    }
+
+    #[test]
+    fn test_rational_arithmetic() {
+        let r = |n, d| Rational::new(n, d).unwrap();
+        assert_eq!(r(6, -4), r(-3, 2));
+        assert_eq!((r(-3, 2).numer(), r(-3, 2).denom()), (-3, 2));
+        assert_eq!(Rational::from_real(0.1), Some(r(1, 10)));
+        assert_eq!(Rational::from_real(-2.5e-3), Some(r(-1, 400)));
+        assert_eq!(
+            Rational::from_real(1e30),
+            Some(Rational::integer(10i128.pow(30)))
+        );
+        assert_eq!(Rational::from_real(1e300), None);
+        assert_eq!(Rational::from_real(Real::NAN), None);
+        assert!(r(1, 3) < r(34, 100) && r(-1, 2) < r(-1, 3));
+        // Cross products of these overflow
+        assert!(r(i128::MAX, i128::MAX - 1) < r(i128::MAX - 1, i128::MAX - 2));
+        assert_eq!(r(1, 3).to_real(), 1.0 / 3.0);
+        assert_eq!(r(-7, 10).to_real(), -0.7);
+        assert_eq!(r(i128::MAX, 1).to_real(), i128::MAX as Real);
+
+        let ctx = {
+            let mut ctx = EvalContext::new();
+            ctx.set_parameter("gain", 0.3).unwrap();
+            ctx.set_parameter("bad", Real::INFINITY).unwrap();
+            ctx
+        };
+        assert_eq!(exact("gain * 10 - 3", Some(&ctx)).unwrap(), Rational::ZERO);
+        assert_eq!(exact("-(1/4)^-2 + 2**3", None).unwrap(), r(-8, 1));
+        assert_eq!(exact("0^0", None).unwrap(), Rational::ONE);
+        assert_eq!(
+            exact("1/3 > 0.333 && 2/3 < 0.667 ? 1/7 : 0", None).unwrap(),
+            r(1, 7)
+        );
+        assert!(matches!(
+            exact("1 / (1 - 1)", None),
+            Err(ExprError::DivideByZero)
+        ));
+        assert!(matches!(exact("0^-1", None), Err(ExprError::DivideByZero)));
+        assert!(matches!(
+            exact("2^(1/2)", None),
+            Err(ExprError::TypeError { .. })
+        ));
+        assert!(matches!(
+            exact("bad + 1", Some(&ctx)),
+            Err(ExprError::TypeError { .. })
+        ));
+        assert!(matches!(exact("10^40", None), Err(ExprError::Other { .. })));
+        assert!(matches!(
+            exact("sin(1)", None),
+            Err(ExprError::Other { .. })
+        ));
+        assert!(matches!(
+            exact("pi / 2", None),
+            Err(ExprError::Other { .. })
+        ));
+    }
+
+    #[test]
+    fn test_check_rounding() {
+        let check = |input: &str| {
+            let arena = bumpalo::Bump::new();
+            check_rounding(&parse_expression(input, &arena).unwrap(), None).unwrap()
+        };
+        assert!(!check("1/3 * 3").depends_on_rounding());
+        assert!(!check("0.5 + 0.25").depends_on_rounding());
+        let sum = check("0.1 + 0.2");
+        assert!(sum.depends_on_rounding());
+        assert_eq!((sum.exact_real(), sum.float), (0.3, 0.1 + 0.2));
+        assert!(check("(0.1 + 0.2) * 10 == 3").depends_on_rounding());
+    }
+
+    #[test]
+    fn test_function_policy() {
+        let mut ctx = EvalContext::new();
+        ctx.set_function_policy(Some(crate::context::FunctionPolicy::deny(["pow"])));
+        assert!(matches!(
+            exact("1/2 + pow(2, 3)", Some(&ctx)),
+            Err(ExprError::FunctionNotPermitted { .. })
+        ));
+        // Operators stay permitted
+        assert_eq!(
+            exact("1/2 + 2^3", Some(&ctx)).unwrap(),
+            Rational::new(17, 2).unwrap()
+        );
+    }
+}
@@ -0,0 +1,399 @@
+//! Length-prefixed binary frames for driving an evaluator remotely, with the `remote`
+//! feature.
+//!
+//! A host (e.g. a CLI on a PC) sends [`Request`]s to a device over UART or USB, and a
+//! [`Server`] on the device answers each with a [`Response`]. Both ends use these types,
+//! so they agree on the encoding as long as they are built with the same `Real` (`f32`
+//! or `f64`).
+//!
+//! A frame is the length of its payload as a little-endian `u16`, followed by the
+//! payload: a tag byte naming the message, then its fields. Numbers are little-endian,
+//! `Real`s take 4 or 8 bytes, and strings are a `u16` length followed by UTF-8 bytes.
+//! [`Request::decode`] and [`Response::decode`] report [`FrameError::Incomplete`] until a
+//! whole frame has been received, so a receiver appends incoming bytes to a buffer and
+//! decodes from its start.
+//!
+//! ```
+//! use exp_rs::EvalContext;
+//! use exp_rs::remote::{Request, Response, Server};
+//! use bumpalo::Bump;
+//! use std::rc::Rc;
+//!
+//! // Host: encode requests into one buffer, as they would be written to the link
+//! let mut link = Vec::new();
+//! for request in [
+//!     Request::SetParam { name: "x".into(), value: 4.0 },
+//!     Request::Compile { expression: "x * 2 + 1".into() },
+//!     Request::EvaluateCompiled { id: 0 },
+//! ] {
+//!     request.encode(&mut link).unwrap();
+//! }
+//!
+//! // Device: decode and handle each frame
+//! let arena = Bump::new();
+//! let mut server = Server::new(Rc::new(EvalContext::new()), &arena);
+//! let mut responses = Vec::new();
+//! let mut received = &link[..];
+//! while let Ok((request, used)) = Request::decode(received) {
+//!     server.handle(&request).encode(&mut responses).unwrap();
+//!     received = &received[used..];
+//! }
+//!
+//! // Host: the response to the evaluation follows those to the other requests
+//! let (_, used) = Response::decode(&responses).unwrap();
+//! assert_eq!(Response::decode(&responses[used..]).unwrap().0, Response::Compiled { id: 0 });
+//! let last = responses.len() - 1 - core::mem::size_of::<exp_rs::Real>() - 2;
+//! assert_eq!(Response::decode(&responses[last..]).unwrap().0, Response::Value(9.0));
+//! ```
+
+use crate::Real;
+use crate::context::EvalContext;
+use crate::engine::parse_expression;
+use crate::error::ExprError;
+use crate::eval::iterative::{EvalEngine, eval_with_engine};
+use crate::types::AstExpr;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use bumpalo::Bump;
+
+/// A request from the host.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Request {
+    /// Parse and evaluate an expression, answered by [`Response::Value`]
+    Evaluate { expression: String },
+    /// Parse an expression and keep it on the device, answered by
+    /// [`Response::Compiled`] with the id to evaluate it by
+    Compile { expression: String },
+    /// Evaluate an expression kept by `Compile`, answered by [`Response::Value`]
+    EvaluateCompiled { id: u16 },
+    /// Set a variable of the device's context, answered by [`Response::Done`]
+    SetParam { name: String, value: Real },
+}
+
+/// A response of the device.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    /// The value of an evaluated expression
+    Value(Real),
+    /// The id of a compiled expression
+    Compiled { id: u16 },
+    /// A request without result succeeded
+    Done,
+    /// A request failed, with the [`ExprError::error_code`] and message of the error
+    Error { code: i32, message: String },
+}
+
+/// Failure to encode or decode a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// The buffer ends before the end of the frame; more bytes are needed
+    Incomplete,
+    /// The frame is malformed, e.g. with an unknown tag or invalid UTF-8
+    Invalid,
+    /// The payload or a string in it is longer than 65535 bytes
+    TooLong,
+}
+
+const EVALUATE: u8 = 1;
+const COMPILE: u8 = 2;
+const EVALUATE_COMPILED: u8 = 3;
+const SET_PARAM: u8 = 4;
+
+const VALUE: u8 = 0x81;
+const COMPILED: u8 = 0x82;
+const DONE: u8 = 0x83;
+const ERROR: u8 = 0x84;
+
+impl Request {
+    /// Appends the request to `out` as a frame.
+    pub fn encode(&self, out: &mut Vec<u8>) -> Result<(), FrameError> {
+        let mut payload = Vec::new();
+        match self {
+            Request::Evaluate { expression } => {
+                payload.push(EVALUATE);
+                put_str(&mut payload, expression)?;
+            }
+            Request::Compile { expression } => {
+                payload.push(COMPILE);
+                put_str(&mut payload, expression)?;
+            }
+            Request::EvaluateCompiled { id } => {
+                payload.push(EVALUATE_COMPILED);
+                payload.extend_from_slice(&id.to_le_bytes());
+            }
+            Request::SetParam { name, value } => {
+                payload.push(SET_PARAM);
+                put_str(&mut payload, name)?;
+                payload.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        put_frame(out, &payload)
+    }
+
+    /// Decodes the frame at the start of `bytes`, returning the request and the number
+    /// of bytes the frame takes.
+    pub fn decode(bytes: &[u8]) -> Result<(Self, usize), FrameError> {
+        let (mut payload, used) = take_frame(bytes)?;
+        let request = match payload.u8()? {
+            EVALUATE => Request::Evaluate {
+                expression: payload.string()?,
+            },
+            COMPILE => Request::Compile {
+                expression: payload.string()?,
+            },
+            EVALUATE_COMPILED => Request::EvaluateCompiled { id: payload.u16()? },
+            SET_PARAM => Request::SetParam {
+                name: payload.string()?,
+                value: payload.real()?,
+            },
+            _ => return Err(FrameError::Invalid),
+        };
+        payload.finish()?;
+        Ok((request, used))
+    }
+}
+
+impl Response {
+    /// Appends the response to `out` as a frame.
+    pub fn encode(&self, out: &mut Vec<u8>) -> Result<(), FrameError> {
+        let mut payload = Vec::new();
+        match self {
+            Response::Value(value) => {
+                payload.push(VALUE);
+                payload.extend_from_slice(&value.to_le_bytes());
+            }
+            Response::Compiled { id } => {
+                payload.push(COMPILED);
+                payload.extend_from_slice(&id.to_le_bytes());
+            }
+            Response::Done => payload.push(DONE),
+            Response::Error { code, message } => {
+                payload.push(ERROR);
+                payload.extend_from_slice(&code.to_le_bytes());
+                put_str(&mut payload, message)?;
+            }
+        }
+        put_frame(out, &payload)
+    }
+
+    /// Decodes the frame at the start of `bytes`, returning the response and the number
+    /// of bytes the frame takes.
+    pub fn decode(bytes: &[u8]) -> Result<(Self, usize), FrameError> {
+        let (mut payload, used) = take_frame(bytes)?;
+        let response = match payload.u8()? {
+            VALUE => Response::Value(payload.real()?),
+            COMPILED => Response::Compiled { id: payload.u16()? },
+            DONE => Response::Done,
+            ERROR => Response::Error {
+                code: i32::from_le_bytes(payload.array()?),
+                message: payload.string()?,
+            },
+            _ => return Err(FrameError::Invalid),
+        };
+        payload.finish()?;
+        Ok((response, used))
+    }
+}
+
+impl From<&ExprError> for Response {
+    fn from(error: &ExprError) -> Self {
+        Response::Error {
+            code: error.error_code(),
+            message: error.to_string(),
+        }
+    }
+}
+
+fn put_frame(out: &mut Vec<u8>, payload: &[u8]) -> Result<(), FrameError> {
+    let len = u16::try_from(payload.len()).map_err(|_| FrameError::TooLong)?;
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(payload);
+    Ok(())
+}
+
+fn put_str(payload: &mut Vec<u8>, value: &str) -> Result<(), FrameError> {
+    let len = u16::try_from(value.len()).map_err(|_| FrameError::TooLong)?;
+    payload.extend_from_slice(&len.to_le_bytes());
+    payload.extend_from_slice(value.as_bytes());
+    Ok(())
+}
+
+fn take_frame(bytes: &[u8]) -> Result<(Reader<'_>, usize), FrameError> {
+    let len = Reader(bytes).u16().map_err(|_| FrameError::Incomplete)? as usize;
+    let payload = bytes.get(2..2 + len).ok_or(FrameError::Incomplete)?;
+    Ok((Reader(payload), 2 + len))
+}
+
+/// Reads the fields of a payload; running out of bytes means a malformed frame.
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn bytes(&mut self, len: usize) -> Result<&[u8], FrameError> {
+        if self.0.len() < len {
+            return Err(FrameError::Invalid);
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], FrameError> {
+        Ok(self.bytes(N)?.try_into().unwrap_or([0; N]))
+    }
+
+    fn u8(&mut self) -> Result<u8, FrameError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, FrameError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn real(&mut self) -> Result<Real, FrameError> {
+        Ok(Real::from_le_bytes(self.array()?))
+    }
+
+    fn string(&mut self) -> Result<String, FrameError> {
+        let len = self.u16()? as usize;
+        let bytes = self.bytes(len)?;
+        core::str::from_utf8(bytes)
+            .map(String::from)
+            .map_err(|_| FrameError::Invalid)
+    }
+
+    fn finish(&self) -> Result<(), FrameError> {
+        match self.0 {
+            [] => Ok(()),
+            _ => Err(FrameError::Invalid),
+        }
+    }
+}
+
+/// The device end: handles requests with a context, keeping compiled expressions in an
+/// arena.
+pub struct Server<'arena> {
+    ctx: Rc<EvalContext>,
+    arena: &'arena Bump,
+    engine: EvalEngine<'arena>,
+    compiled: Vec<&'arena AstExpr<'arena>>,
+}
+
+impl<'arena> Server<'arena> {
+    /// Creates a server evaluating with `ctx`. Compiled expressions are parsed into
+    /// `arena`, which holds them for the lifetime of the server.
+    pub fn new(ctx: Rc<EvalContext>, arena: &'arena Bump) -> Self {
+        Server {
+            ctx,
+            arena,
+            engine: EvalEngine::new(arena),
+            compiled: Vec::new(),
+        }
+    }
+
+    /// Returns the context requests are evaluated with.
+    pub fn context(&self) -> &Rc<EvalContext> {
+        &self.ctx
+    }
+
+    /// Handles one request and returns the response to send back.
+    ///
+    /// An expression evaluated with `Evaluate` is parsed into a temporary arena, so
+    /// only `Compile` makes the server's arena grow.
+    pub fn handle(&mut self, request: &Request) -> Response {
+        let result = match request {
+            Request::Evaluate { expression } => {
+                let arena = Bump::new();
+                parse_expression(expression, &arena).and_then(|ast| {
+                    eval_with_engine(&ast, Some(self.ctx.clone()), &mut EvalEngine::new(&arena))
+                        .map(Response::Value)
+                })
+            }
+            Request::Compile { expression } => match u16::try_from(self.compiled.len()) {
+                Ok(id) => parse_expression(expression, self.arena).map(|ast| {
+                    self.compiled.push(self.arena.alloc(ast));
+                    Response::Compiled { id }
+                }),
+                Err(_) => Err(ExprError::CapacityExceeded {
+                    container: "compiled expressions",
+                }),
+            },
+            Request::EvaluateCompiled { id } => match self.compiled.get(*id as usize) {
+                Some(ast) => eval_with_engine(ast, Some(self.ctx.clone()), &mut self.engine)
+                    .map(Response::Value),
+                None => Err(ExprError::InvalidParameterIndex {
+                    index: *id as usize,
+                    len: self.compiled.len(),
+                }),
+            },
+            Request::SetParam { name, value } => Rc::make_mut(&mut self.ctx)
+                .set_parameter(name, *value)
+                .map(|_| Response::Done),
+        };
+        result.unwrap_or_else(|error| Response::from(&error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_and_server() {
+        let requests = [
+            Request::SetParam {
+                name: "gain".into(),
+                value: 2.5,
+            },
+            Request::Evaluate {
+                expression: "gain * 4".into(),
+            },
+            Request::Compile {
+                expression: "gain +".into(),
+            },
+            Request::EvaluateCompiled { id: 7 },
+        ];
+        let mut link = Vec::new();
+        for request in &requests {
+            request.encode(&mut link).unwrap();
+        }
+        // 2-byte length, tag, 2-byte string length, name, value
+        assert_eq!(
+            &link[..5],
+            &[7 + core::mem::size_of::<Real>() as u8, 0, SET_PARAM, 4, 0]
+        );
+
+        let arena = Bump::new();
+        let mut server = Server::new(Rc::new(EvalContext::new()), &arena);
+        let mut received = &link[..];
+        let mut responses = Vec::new();
+        for expected in &requests {
+            // A partial frame needs more bytes
+            assert_eq!(Request::decode(&received[..3]), Err(FrameError::Incomplete));
+            let (request, used) = Request::decode(received).unwrap();
+            assert_eq!(&request, expected);
+            received = &received[used..];
+            responses.push(server.handle(&request));
+        }
+        assert_eq!(responses[..2], [Response::Done, Response::Value(10.0)]);
+        assert!(matches!(responses[2], Response::Error { code: 3, .. }));
+        assert!(matches!(responses[3], Response::Error { code: 15, .. }));
+
+        for response in &responses {
+            let mut frame = Vec::new();
+            response.encode(&mut frame).unwrap();
+            assert_eq!(
+                Response::decode(&frame),
+                Ok((response.clone(), frame.len()))
+            );
+        }
+        assert_eq!(Request::decode(&[1, 0, 99]), Err(FrameError::Invalid));
+        assert_eq!(
+            Request::Evaluate {
+                expression: "1".repeat(70_000)
+            }
+            .encode(&mut Vec::new()),
+            Err(FrameError::TooLong)
+        );
+    }
+}
@@ -0,0 +1,415 @@
+//! Shadow evaluation in `f32` and `f64` (requires the `shadow` feature).
+//!
+//! [`eval_shadow`] evaluates a parsed expression twice in one pass: once rounding every
+//! value to `f32`, as the firmware built with the `f32` feature does, and once in `f64`.
+//! The [`ShadowResult`] reports how far the `f32` result diverges from the `f64` one,
+//! and the largest divergence of any intermediate call, so each expression can be
+//! checked against the accuracy it needs before choosing the `f32` build.
+//!
+//! Native functions are dispatched per precision: the `f64` side calls their normal
+//! implementation, and the `f32` side calls the implementation set with
+//! [`EvalContext::set_single_precision_function`] or, failing that, the normal one with
+//! arguments and result rounded to `f32`. For the arithmetic operators and `sqrt` this
+//! fallback gives exactly the `f32` result; for other math functions it gives the
+//! correctly rounded one, which the firmware's math library may miss by an ulp.
+
+use crate::context::EvalContext;
+use crate::error::ExprError;
+use crate::types::{AstExpr, LogicalOperator, TryIntoHeaplessString};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// Results of evaluating an expression in `f32` and in `f64`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShadowResult {
+    /// Result of the `f32` evaluation
+    pub single: f32,
+    /// Result of the `f64` evaluation
+    pub double: f64,
+    /// Relative divergence of the results, see [`relative_divergence`]
+    pub relative_divergence: f64,
+    /// Largest relative divergence of any call in the expression, including the
+    /// outermost one
+    pub max_relative_divergence: f64,
+    /// The call with the largest divergence, rendered as expression text, if any
+    /// call diverged
+    pub worst_call: Option<String>,
+}
+
+impl ShadowResult {
+    /// Returns whether the `f32` result is within `tolerance` of the `f64` one,
+    /// relative to the `f64` result.
+    pub fn within(&self, tolerance: f64) -> bool {
+        self.relative_divergence <= tolerance
+    }
+}
+
+/// Relative divergence of an `f32` value from the `f64` value it shadows.
+///
+/// It is 0 when the values are equal, including equal infinities and two NaNs, and
+/// `|single - double| / |double|` otherwise. Differences from a zero `f64` value, and
+/// a NaN or infinity on one side only, such as an `f32` overflow, are infinitely
+/// divergent.
+pub fn relative_divergence(single: f32, double: f64) -> f64 {
+    let single = single as f64;
+    if single == double || (single.is_nan() && double.is_nan()) {
+        return 0.0;
+    }
+    if !single.is_finite() || !double.is_finite() {
+        return f64::INFINITY;
+    }
+    (single - double).abs() / double.abs()
+}
+
+/// Evaluates an expression in `f32` and in `f64` and compares the results.
+///
+/// Variables, constants, arrays and attributes of the context are rounded to `f32` on
+/// the `f32` side, like literals. Each side takes its own branch of `?:`, `&&` and
+/// `||`, so a comparison that comes out differently in `f32` shows up as a divergence
+/// of the result.
+///
+/// Expression functions are not supported, as with the complex evaluator. Without a
+/// context, a default one is used.
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::context::EvalContext;
+/// use exp_rs::engine::parse_expression;
+/// use exp_rs::shadow::eval_shadow;
+/// use bumpalo::Bump;
+///
+/// let mut ctx = EvalContext::new();
+/// ctx.set_parameter("big", 1e8).unwrap();
+///
+/// let arena = Bump::new();
+/// // Fine in f32
+/// let ast = parse_expression("sqrt(2) * 3 + 1", &arena).unwrap();
+/// assert!(eval_shadow(&ast, Some(&ctx)).unwrap().within(1e-6));
+///
+/// // Cancellation: the 1 is lost next to 1e8 in f32
+/// let ast = parse_expression("(big + 1) - big", &arena).unwrap();
+/// let result = eval_shadow(&ast, Some(&ctx)).unwrap();
+/// assert_eq!((result.single, result.double), (0.0, 1.0));
+/// assert!(!result.within(1e-6));
+/// assert_eq!(result.worst_call.as_deref(), Some("big + 1 - big"));
+/// ```
+pub fn eval_shadow(expr: &AstExpr, ctx: Option<&EvalContext>) -> Result<ShadowResult, ExprError> {
+    let default_ctx;
+    let ctx = match ctx {
+        Some(ctx) => ctx,
+        None => {
+            default_ctx = EvalContext::new();
+            &default_ctx
+        }
+    };
+    let mut evaluator = Evaluator {
+        ctx,
+        max_divergence: 0.0,
+        worst_call: None,
+    };
+    let result = evaluator.eval(expr)?;
+    Ok(ShadowResult {
+        single: result.single,
+        double: result.double,
+        relative_divergence: relative_divergence(result.single, result.double),
+        max_relative_divergence: evaluator.max_divergence,
+        worst_call: evaluator.worst_call,
+    })
+}
+
+/// A value computed in both precisions.
+#[derive(Clone, Copy)]
+struct Pair {
+    single: f32,
+    double: f64,
+}
+
+impl Pair {
+    fn new(value: f64) -> Self {
+        Pair {
+            single: value as f32,
+            double: value,
+        }
+    }
+
+    fn truth(single: bool, double: bool) -> Self {
+        Pair {
+            single: single as u8 as f32,
+            double: double as u8 as f64,
+        }
+    }
+}
+
+struct Evaluator<'a> {
+    ctx: &'a EvalContext,
+    max_divergence: f64,
+    worst_call: Option<String>,
+}
+
+impl Evaluator<'_> {
+    fn eval(&mut self, expr: &AstExpr) -> Result<Pair, ExprError> {
+        match expr {
+            AstExpr::Constant(value) => Ok(Pair::new(*value)),
+            AstExpr::Variable(name) => self.lookup_variable(name).map(Pair::new),
+            AstExpr::Array { name, index } => {
+                let index = self.eval(index)?;
+                let array = self
+                    .ctx
+                    .get_array(name)
+                    .ok_or_else(|| ExprError::UnknownVariable {
+                        name: name.to_string(),
+                    })?;
+                let element = |idx: usize| {
+                    array
+                        .get(idx)
+                        .copied()
+                        .ok_or_else(|| ExprError::ArrayIndexOutOfBounds {
+                            name: name.to_string(),
+                            index: idx,
+                            len: array.len(),
+                        })
+                };
+                Ok(Pair {
+                    single: element(index.single as usize)? as f32,
+                    double: element(index.double as usize)?,
+                })
+            }
+            AstExpr::Attribute { base, attr } => self
+                .ctx
+                .get_attribute_map(base)
+                .and_then(|m| m.get(&attr.try_into_heapless().ok()?).copied())
+                .map(Pair::new)
+                .ok_or_else(|| ExprError::AttributeNotFound {
+                    base: base.to_string(),
+                    attr: attr.to_string(),
+                }),
+            AstExpr::LogicalOp { op, left, right } => {
+                let left = self.eval(left)?;
+                let (single, double) = (left.single != 0.0, left.double != 0.0);
+                // The right operand is only evaluated if a side needs it
+                let decided = match op {
+                    LogicalOperator::And => !single && !double,
+                    LogicalOperator::Or => single && double,
+                };
+                if decided {
+                    return Ok(Pair::truth(single, double));
+                }
+                let right = self.eval(right)?;
+                Ok(match op {
+                    LogicalOperator::And => {
+                        Pair::truth(single && right.single != 0.0, double && right.double != 0.0)
+                    }
+                    LogicalOperator::Or => {
+                        Pair::truth(single || right.single != 0.0, double || right.double != 0.0)
+                    }
+                })
+            }
+            AstExpr::Conditional {
+                condition,
+                true_branch,
+                false_branch,
+            } => {
+                let condition = self.eval(condition)?;
+                match (condition.single != 0.0, condition.double != 0.0) {
+                    (true, true) => self.eval(true_branch),
+                    (false, false) => self.eval(false_branch),
+                    (single, _) => {
+                        let taken = self.eval(true_branch)?;
+                        let other = self.eval(false_branch)?;
+                        let (for_single, for_double) = if single {
+                            (taken, other)
+                        } else {
+                            (other, taken)
+                        };
+                        Ok(Pair {
+                            single: for_single.single,
+                            double: for_double.double,
+                        })
+                    }
+                }
+            }
+            AstExpr::Function { name, args } => {
+                self.ctx.check_function_permitted(name)?;
+                let mut singles = Vec::with_capacity(args.len());
+                let mut doubles = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    let value = self.eval(arg)?;
+                    singles.push(value.single);
+                    doubles.push(value.double);
+                }
+                let result = self.call(name, &singles, &doubles)?;
+                let divergence = relative_divergence(result.single, result.double);
+                if divergence > self.max_divergence {
+                    self.max_divergence = divergence;
+                    self.worst_call = Some(expr.to_expression_string());
+                }
+                Ok(result)
+            }
+        }
+    }
+
+    fn lookup_variable(&self, name: &str) -> Result<f64, ExprError> {
+        if let Some(value) = self
+            .ctx
+            .get_variable(name)
+            .or_else(|| self.ctx.get_constant(name))
+        {
+            return Ok(value);
+        }
+        match name {
+            "pi" | "PI" => Ok(core::f64::consts::PI),
+            "e" | "E" => Ok(core::f64::consts::E),
+            "tau" | "TAU" => Ok(core::f64::consts::TAU),
+            _ => self
+                .ctx
+                .resolve_variable(name)
+                .ok_or_else(|| ExprError::UnknownVariable {
+                    name: name.to_string(),
+                }),
+        }
+    }
+
+    fn call(&self, name: &str, singles: &[f32], doubles: &[f64]) -> Result<Pair, ExprError> {
+        let widened: Vec<f64> = singles.iter().map(|&v| v as f64).collect();
+        let native = match self.ctx.permitted_native_function(name) {
+            Ok(func) => Some(func),
+            Err(ExprError::UnknownFunction { .. }) => None,
+            Err(err) => return Err(err),
+        };
+        if let Some(func) = native {
+            if !func.accepts(doubles.len()) {
+                return Err(ExprError::InvalidFunctionCall {
+                    name: name.to_string(),
+                    expected: func.arity,
+                    found: doubles.len(),
+                });
+            }
+            let single = match &func.single_precision {
+                Some(single_precision) => single_precision(singles),
+                None => (func.implementation)(&widened) as f32,
+            };
+            return Ok(Pair {
+                single,
+                double: (func.implementation)(doubles),
+            });
+        }
+        let unknown = || ExprError::UnknownFunction {
+            name: name.to_string(),
+        };
+        Ok(Pair {
+            single: self
+                .ctx
+                .resolve_function(name, &widened)
+                .ok_or_else(unknown)? as f32,
+            double: self
+                .ctx
+                .resolve_function(name, doubles)
+                .ok_or_else(unknown)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::parse_expression;
+    use bumpalo::Bump;
+
+    fn shadow(input: &str, ctx: &EvalContext) -> ShadowResult {
+        let arena = Bump::new();
+        let ast = parse_expression(input, &arena).unwrap();
+        eval_shadow(&ast, Some(ctx)).unwrap()
+    }
+
+    #[test]
+    fn test_shadow_divergence() {
+        let mut ctx = EvalContext::new();
+        ctx.set_parameter("x", 0.1).unwrap();
+        ctx.set_parameter("big", 1e8).unwrap();
+        ctx.set_parameter("huge", 1e30).unwrap();
+        ctx.set_array("samples", alloc::vec![0.5, 1.5, 2.5])
+            .unwrap();
+
+        // Exact in both precisions
+        let exact = shadow("samples[1] * 2 + 0.25", &ctx);
+        assert_eq!((exact.single, exact.double), (3.25, 3.25));
+        assert_eq!(exact.max_relative_divergence, 0.0);
+        assert_eq!(exact.worst_call, None);
+
+        // Rounding of the input only
+        let rounded = shadow("x * 3", &ctx);
+        assert_eq!(rounded.single, 0.1f32 * 3.0);
+        assert!(rounded.within(1e-7) && !rounded.within(1e-9));
+
+        // An intermediate overflow in f32 that the final result hides
+        let hidden = shadow("huge * huge > 0 ? 1 : 1", &ctx);
+        assert_eq!(hidden.relative_divergence, 0.0);
+        assert_eq!(hidden.max_relative_divergence, f64::INFINITY);
+        assert_eq!(hidden.worst_call.as_deref(), Some("huge * huge"));
+
+        // The sides take different branches
+        let branch = shadow("big + 1 > big ? 1 : -1", &ctx);
+        assert_eq!((branch.single, branch.double), (-1.0, 1.0));
+        let logic = shadow("big + 1 == big || 0", &ctx);
+        assert_eq!((logic.single, logic.double), (1.0, 0.0));
+
+        assert_eq!(relative_divergence(f32::NAN, f64::NAN), 0.0);
+        assert_eq!(relative_divergence(1.0, 0.0), f64::INFINITY);
+        assert_eq!(relative_divergence(1.5, 2.0), 0.25);
+    }
+
+    #[test]
+    fn test_single_precision_dispatch() {
+        let mut ctx = EvalContext::new();
+        ctx.register_native_function("third", 1, |args| args[0] / 3.0)
+            .unwrap();
+        // A firmware implementation that truncates instead of rounding
+        ctx.set_single_precision_function("third", |args| {
+            f32::from_bits((args[0] / 3.0).to_bits() & !1)
+        })
+        .unwrap();
+        let result = shadow("third(1)", &ctx);
+        assert_eq!(result.double, 1.0 / 3.0);
+        assert_eq!(result.single, f32::from_bits((1.0f32 / 3.0).to_bits() & !1));
+
+        assert!(matches!(
+            ctx.set_single_precision_function("nope", |args| args[0]),
+            Err(ExprError::UnknownFunction { .. })
+        ));
+        let arena = Bump::new();
+        let ast = parse_expression("third(1, 2)", &arena).unwrap();
+        assert!(matches!(
+            eval_shadow(&ast, Some(&ctx)),
+            Err(ExprError::InvalidFunctionCall { .. })
+        ));
+    }
+
+    #[test]
+    fn test_function_policy() {
+        use crate::context::FunctionPolicy;
+        use alloc::rc::Rc;
+        use core::cell::Cell;
+
+        let calls = Rc::new(Cell::new(0));
+        let mut ctx = EvalContext::new();
+        let counter = calls.clone();
+        ctx.register_native_function("write_reg", 1, move |args| {
+            counter.set(counter.get() + 1);
+            args[0]
+        })
+        .unwrap();
+        ctx.set_single_precision_function("write_reg", |args| args[0])
+            .unwrap();
+        ctx.set_function_policy(Some(FunctionPolicy::deny(["write_reg"])));
+
+        let arena = Bump::new();
+        let ast = parse_expression("1 + write_reg(2)", &arena).unwrap();
+        assert!(matches!(
+            eval_shadow(&ast, Some(&ctx)),
+            Err(ExprError::FunctionNotPermitted { .. })
+        ));
+        assert_eq!(calls.get(), 0);
+        assert_eq!(shadow("sqrt(4) + 1", &ctx).double, 3.0);
+    }
+}
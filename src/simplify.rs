@@ -0,0 +1,547 @@
+//! Algebraic simplification of ASTs.
+//!
+//! The simplifier removes redundant operations such as `x*1`, `x+0` or `x^1`, which are
+//! common in machine-generated expressions and in the output of symbolic transformations.
+//! It works on arena-allocated trees and shares unchanged subtrees with the input.
+//!
+//! [`fuse_multiply_add`] is a separate, opt-in pass that turns `a * b + c` into
+//! `fma(a, b, c)`, and [`specialize`] substitutes parameters that no longer change and
+//! folds the constant subtrees they leave behind.
+
+use crate::Real;
+use crate::context::EvalContext;
+use crate::eval::iterative::EvalEngine;
+use crate::types::AstExpr;
+use alloc::rc::Rc;
+use bumpalo::Bump;
+
+/// Built-in functions that have no side effects and always return the same result for the
+/// same arguments. Only subtrees made of these (plus constants and variable reads) may be
+/// dropped by the simplifier.
+const PURE_BUILTINS: &[&str] = &[
+    "+", "-", "*", "/", "%", "^", "**", "<", ">", "<=", ">=", "==", "!=", "<>", "&&", "||", ",",
+    ";", "neg", "add", "sub", "mul", "div", "fmod", "comma", "abs", "sign", "max", "min", "acos",
+    "asin", "atan", "atan2", "ceil", "cos", "cosh", "exp", "floor", "round", "ln", "log", "log10",
+    "pow", "sin", "sinh", "sqrt", "tan", "tanh", "hypot", "copysign", "fma", "e", "pi",
+];
+
+/// Simplifies an expression by applying algebraic identities.
+///
+/// The following rewrites are applied bottom-up until no more apply:
+///
+/// - `x + 0`, `0 + x`, `x - 0` → `x`, and `0 - x` → `-x`
+/// - `x * 1`, `1 * x`, `x / 1` → `x`
+/// - `x * 0`, `0 * x` → `0` when `x` is pure
+/// - `x ^ 1`, `pow(x, 1)` → `x`; `x ^ 0` and `1 ^ x` → `1` when `x` is pure
+/// - `-(-x)` → `x` and negation of a constant is folded
+/// - `c ? a : b` with a constant condition → the selected branch
+///
+/// A subtree is only dropped if it is pure, i.e. it calls nothing but the built-in math
+/// functions and operators. Calls to user functions (which may read hardware or keep state)
+/// are always kept, so `0 * adc_read()` is not simplified; [`simplify_with`] also takes the
+/// functions a context declares pure into account.
+///
+/// Operators are assumed to have their default meaning. Note that removing pure subtrees
+/// ignores IEEE special cases: `0 * x` becomes `0` even if `x` would evaluate to infinity.
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::engine::parse_expression;
+/// use exp_rs::simplify::simplify;
+/// use bumpalo::Bump;
+///
+/// let arena = Bump::new();
+/// let ast = arena.alloc(parse_expression("(x * 1 + 0) ^ 1 + 0 * sin(y)", &arena).unwrap());
+/// let simplified = simplify(ast, &arena);
+/// assert_eq!(simplified.to_expression_string(), "x");
+/// ```
+pub fn simplify<'arena>(
+    expr: &'arena AstExpr<'arena>,
+    arena: &'arena Bump,
+) -> &'arena AstExpr<'arena> {
+    simplify_with(expr, None, arena)
+}
+
+/// Simplifies an expression like [`simplify`], deciding which calls are pure with `ctx`.
+///
+/// With a context, a call of a native function of `ctx` is pure if the context declares
+/// it so (see [`EvalContext::set_function_pure`]), and only the other names are looked
+/// up in the built-in list. A user function marked pure may then be dropped, and a
+/// built-in replaced by an impure one is kept. [`specialize`] simplifies this way.
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::engine::parse_expression;
+/// use exp_rs::simplify::{simplify, simplify_with};
+/// use exp_rs::EvalContext;
+/// use bumpalo::Bump;
+///
+/// let arena = Bump::new();
+/// let mut ctx = EvalContext::new();
+/// ctx.register_native_function("gain", 1, |args| args[0] * 2.0).unwrap();
+/// ctx.set_function_pure("gain", true).unwrap();
+/// let ast = arena.alloc(parse_expression("x + 0 * gain(y)", &arena).unwrap());
+/// assert_eq!(simplify(ast, &arena).to_expression_string(), "x + 0 * gain(y)");
+/// assert_eq!(simplify_with(ast, Some(&ctx), &arena).to_expression_string(), "x");
+/// ```
+pub fn simplify_with<'arena>(
+    expr: &'arena AstExpr<'arena>,
+    ctx: Option<&EvalContext>,
+    arena: &'arena Bump,
+) -> &'arena AstExpr<'arena> {
+    match expr {
+        AstExpr::Function { name, args } => {
+            let mut changed = false;
+            let mut new_args = bumpalo::collections::Vec::with_capacity_in(args.len(), arena);
+            for arg in args.iter() {
+                let simplified = simplify_with(arg, ctx, arena);
+                changed |= !core::ptr::eq(simplified, arg);
+                new_args.push(simplified.clone());
+            }
+            let args: &'arena [AstExpr<'arena>] = if changed {
+                new_args.into_bump_slice()
+            } else {
+                args
+            };
+
+            match simplify_call(name, args, ctx, arena) {
+                Some(result) => result,
+                None if changed => arena.alloc(AstExpr::Function { name, args }),
+                None => expr,
+            }
+        }
+        AstExpr::Array { name, index } => {
+            let new_index = simplify_with(index, ctx, arena);
+            if core::ptr::eq(new_index, *index) {
+                expr
+            } else {
+                arena.alloc(AstExpr::Array {
+                    name,
+                    index: new_index,
+                })
+            }
+        }
+        AstExpr::LogicalOp { op, left, right } => {
+            let new_left = simplify_with(left, ctx, arena);
+            let new_right = simplify_with(right, ctx, arena);
+            if core::ptr::eq(new_left, *left) && core::ptr::eq(new_right, *right) {
+                expr
+            } else {
+                arena.alloc(AstExpr::LogicalOp {
+                    op: *op,
+                    left: new_left,
+                    right: new_right,
+                })
+            }
+        }
+        AstExpr::Conditional {
+            condition,
+            true_branch,
+            false_branch,
+        } => {
+            let new_condition = simplify_with(condition, ctx, arena);
+            if let AstExpr::Constant(c) = new_condition {
+                return if *c != 0.0 {
+                    simplify_with(true_branch, ctx, arena)
+                } else {
+                    simplify_with(false_branch, ctx, arena)
+                };
+            }
+            let new_true = simplify_with(true_branch, ctx, arena);
+            let new_false = simplify_with(false_branch, ctx, arena);
+            if core::ptr::eq(new_condition, *condition)
+                && core::ptr::eq(new_true, *true_branch)
+                && core::ptr::eq(new_false, *false_branch)
+            {
+                expr
+            } else {
+                arena.alloc(AstExpr::Conditional {
+                    condition: new_condition,
+                    true_branch: new_true,
+                    false_branch: new_false,
+                })
+            }
+        }
+        AstExpr::Constant(_) | AstExpr::Variable(_) | AstExpr::Attribute { .. } => expr,
+    }
+}
+
+/// Rewrites `a * b + c` and `c + a * b` into `fma(a, b, c)`.
+///
+/// A fused multiply-add rounds once instead of twice, which improves the accuracy of
+/// filter and polynomial formulas, and maps to a single instruction on targets with
+/// hardware FMA (such as the Cortex-M4F). Because the result can differ in the last bit
+/// from the unfused expression, this pass is not part of [`simplify`] and must be applied
+/// explicitly. The rewritten tree needs `fma` in the context, which is registered by
+/// default with the `libm` feature.
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::engine::parse_expression;
+/// use exp_rs::simplify::fuse_multiply_add;
+/// use bumpalo::Bump;
+///
+/// let arena = Bump::new();
+/// let ast = arena.alloc(parse_expression("b0 * x + a1 * y1", &arena).unwrap());
+/// let fused = fuse_multiply_add(ast, &arena);
+/// assert_eq!(fused.to_expression_string(), "fma(b0, x, a1 * y1)");
+/// ```
+pub fn fuse_multiply_add<'arena>(
+    expr: &'arena AstExpr<'arena>,
+    arena: &'arena Bump,
+) -> &'arena AstExpr<'arena> {
+    rewrite_calls(expr, arena, &|name, args, arena| match (name, args) {
+        (
+            "+",
+            [
+                AstExpr::Function {
+                    name: "*",
+                    args: [a, b],
+                },
+                c,
+            ],
+        )
+        | (
+            "+",
+            [
+                c,
+                AstExpr::Function {
+                    name: "*",
+                    args: [a, b],
+                },
+            ],
+        ) => {
+            let args = arena.alloc_slice_clone(&[a.clone(), b.clone(), c.clone()]);
+            Some(arena.alloc(AstExpr::Function { name: "fma", args }))
+        }
+        _ => None,
+    })
+}
+
+/// Substitutes the given variable values and folds the constant subtrees that result.
+///
+/// Every read of a variable in `values` becomes its value. Then every call of a
+/// function that `ctx` declares pure (see [`EvalContext::set_function_pure`]) and every
+/// `&&`/`||` whose operands are all constants is evaluated with `ctx` and replaced by its
+/// value, so folding uses the same functions as evaluation, and the result is passed
+/// through [`simplify_with`] with `ctx`. Subtrees whose evaluation fails are kept, so the
+/// error still surfaces at evaluation time.
+///
+/// This suits values that are fixed after start-up, such as calibration constants:
+/// the returned tree evaluates to the same result as `expr` with these values, with
+/// fewer operations.
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::engine::parse_expression;
+/// use exp_rs::simplify::specialize;
+/// use exp_rs::EvalContext;
+/// use bumpalo::Bump;
+/// use std::rc::Rc;
+///
+/// let arena = Bump::new();
+/// let ast = arena.alloc(parse_expression("raw * (gain / 1000) + offset * 2", &arena).unwrap());
+/// let ctx = Rc::new(EvalContext::new());
+/// let specialized = specialize(ast, &ctx, &[("gain", 2500.0), ("offset", 0.0)], &arena);
+/// assert_eq!(specialized.to_expression_string(), "raw * 2.5");
+/// ```
+pub fn specialize<'arena>(
+    expr: &'arena AstExpr<'arena>,
+    ctx: &Rc<EvalContext>,
+    values: &[(&str, Real)],
+    arena: &'arena Bump,
+) -> &'arena AstExpr<'arena> {
+    let mut engine = EvalEngine::new(arena);
+    let is_constant = |e: &AstExpr<'_>| matches!(e, AstExpr::Constant(_));
+    let folded = crate::visit::rewrite_ast(expr, arena, &mut |node| {
+        let foldable = match node {
+            AstExpr::Variable(name) => {
+                return values
+                    .iter()
+                    .find(|(n, _)| n == name)
+                    .map(|&(_, value)| AstExpr::Constant(value));
+            }
+            AstExpr::Function { name, args } => {
+                ctx.is_function_pure(name) && args.iter().all(is_constant)
+            }
+            AstExpr::LogicalOp { left, right, .. } => is_constant(left) && is_constant(right),
+            _ => false,
+        };
+        if !foldable {
+            return None;
+        }
+        engine
+            .eval(node, Some(ctx.clone()))
+            .ok()
+            .map(AstExpr::Constant)
+    });
+    simplify_with(folded, Some(ctx), arena)
+}
+
+type CallRule<'arena> =
+    dyn Fn(&'arena str, &'arena [AstExpr<'arena>], &'arena Bump) -> Option<&'arena AstExpr<'arena>>;
+
+/// Applies `rule` to every call bottom-up, sharing the subtrees it leaves unchanged.
+fn rewrite_calls<'arena>(
+    expr: &'arena AstExpr<'arena>,
+    arena: &'arena Bump,
+    rule: &CallRule<'arena>,
+) -> &'arena AstExpr<'arena> {
+    match expr {
+        AstExpr::Function { name, args } => {
+            let mut changed = false;
+            let mut new_args = bumpalo::collections::Vec::with_capacity_in(args.len(), arena);
+            for arg in args.iter() {
+                let rewritten = rewrite_calls(arg, arena, rule);
+                changed |= !core::ptr::eq(rewritten, arg);
+                new_args.push(rewritten.clone());
+            }
+            let args: &'arena [AstExpr<'arena>] = if changed {
+                new_args.into_bump_slice()
+            } else {
+                args
+            };
+
+            match rule(name, args, arena) {
+                Some(result) => result,
+                None if changed => arena.alloc(AstExpr::Function { name, args }),
+                None => expr,
+            }
+        }
+        AstExpr::Array { name, index } => {
+            let new_index = rewrite_calls(index, arena, rule);
+            if core::ptr::eq(new_index, *index) {
+                expr
+            } else {
+                arena.alloc(AstExpr::Array {
+                    name,
+                    index: new_index,
+                })
+            }
+        }
+        AstExpr::LogicalOp { op, left, right } => {
+            let new_left = rewrite_calls(left, arena, rule);
+            let new_right = rewrite_calls(right, arena, rule);
+            if core::ptr::eq(new_left, *left) && core::ptr::eq(new_right, *right) {
+                expr
+            } else {
+                arena.alloc(AstExpr::LogicalOp {
+                    op: *op,
+                    left: new_left,
+                    right: new_right,
+                })
+            }
+        }
+        AstExpr::Conditional {
+            condition,
+            true_branch,
+            false_branch,
+        } => {
+            let new_condition = rewrite_calls(condition, arena, rule);
+            let new_true = rewrite_calls(true_branch, arena, rule);
+            let new_false = rewrite_calls(false_branch, arena, rule);
+            if core::ptr::eq(new_condition, *condition)
+                && core::ptr::eq(new_true, *true_branch)
+                && core::ptr::eq(new_false, *false_branch)
+            {
+                expr
+            } else {
+                arena.alloc(AstExpr::Conditional {
+                    condition: new_condition,
+                    true_branch: new_true,
+                    false_branch: new_false,
+                })
+            }
+        }
+        AstExpr::Constant(_) | AstExpr::Variable(_) | AstExpr::Attribute { .. } => expr,
+    }
+}
+
+/// Returns true if evaluating the expression has no side effects, so it may be removed.
+///
+/// Only the built-in math functions and operators count as pure; see [`is_pure_with`]
+/// for the functions a context declares pure.
+pub fn is_pure(expr: &AstExpr<'_>) -> bool {
+    is_pure_with(expr, None)
+}
+
+/// Returns true if evaluating the expression has no side effects, deciding which calls
+/// are pure with `ctx` as [`simplify_with`] does.
+pub fn is_pure_with(expr: &AstExpr<'_>, ctx: Option<&EvalContext>) -> bool {
+    let pure = |expr: &AstExpr<'_>| is_pure_with(expr, ctx);
+    match expr {
+        AstExpr::Constant(_) | AstExpr::Variable(_) | AstExpr::Attribute { .. } => true,
+        AstExpr::Array { index, .. } => pure(index),
+        AstExpr::Function { name, args } => {
+            let pure_call = match ctx {
+                Some(ctx) if ctx.get_native_function(name).is_some() => ctx.is_function_pure(name),
+                _ => PURE_BUILTINS.contains(name),
+            };
+            pure_call && args.iter().all(pure)
+        }
+        AstExpr::LogicalOp { left, right, .. } => pure(left) && pure(right),
+        AstExpr::Conditional {
+            condition,
+            true_branch,
+            false_branch,
+        } => pure(condition) && pure(true_branch) && pure(false_branch),
+    }
+}
+
+fn is_constant(expr: &AstExpr<'_>, value: Real) -> bool {
+    matches!(expr, AstExpr::Constant(c) if *c == value)
+}
+
+/// Applies identities to a call whose arguments are already simplified.
+fn simplify_call<'arena>(
+    name: &'arena str,
+    args: &'arena [AstExpr<'arena>],
+    ctx: Option<&EvalContext>,
+    arena: &'arena Bump,
+) -> Option<&'arena AstExpr<'arena>> {
+    let is_pure = |expr: &AstExpr<'_>| is_pure_with(expr, ctx);
+    match (name, args) {
+        ("neg", [AstExpr::Constant(c)]) => Some(arena.alloc(AstExpr::Constant(-*c))),
+        (
+            "neg",
+            [
+                AstExpr::Function {
+                    name: "neg",
+                    args: [inner],
+                },
+            ],
+        ) => Some(inner),
+
+        ("+", [l, r]) if is_constant(r, 0.0) => Some(l),
+        ("+", [l, r]) if is_constant(l, 0.0) => Some(r),
+        ("-", [l, r]) if is_constant(r, 0.0) => Some(l),
+        ("-", [l, r]) if is_constant(l, 0.0) => {
+            let operand = core::slice::from_ref(r);
+            // Fold constants and double negation where possible
+            Some(
+                simplify_call("neg", operand, ctx, arena).unwrap_or_else(|| {
+                    arena.alloc(AstExpr::Function {
+                        name: "neg",
+                        args: operand,
+                    })
+                }),
+            )
+        }
+
+        ("*", [l, r]) if is_constant(r, 1.0) => Some(l),
+        ("*", [l, r]) if is_constant(l, 1.0) => Some(r),
+        ("*", [l, r]) if is_constant(l, 0.0) && is_pure(r) => Some(l),
+        ("*", [l, r]) if is_constant(r, 0.0) && is_pure(l) => Some(r),
+        ("/", [l, r]) if is_constant(r, 1.0) => Some(l),
+
+        ("^" | "**" | "pow", [l, r]) if is_constant(r, 1.0) => Some(l),
+        ("^" | "**" | "pow", [l, r]) if is_constant(r, 0.0) && is_pure(l) => {
+            Some(arena.alloc(AstExpr::Constant(1.0)))
+        }
+        ("^" | "**" | "pow", [l, r]) if is_constant(l, 1.0) && is_pure(r) => Some(l),
+
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::parse_expression;
+
+    fn simplified(input: &str) -> std::string::String {
+        let arena = Bump::new();
+        let ast = arena.alloc(parse_expression(input, &arena).unwrap());
+        simplify(ast, &arena).to_expression_string()
+    }
+
+    #[test]
+    fn test_identities() {
+        assert_eq!(simplified("x * 1"), "x");
+        assert_eq!(simplified("1 * x + 0"), "x");
+        assert_eq!(simplified("0 + x - 0"), "x");
+        assert_eq!(simplified("0 - x"), "-x");
+        assert_eq!(simplified("0 - 2"), "-2");
+        assert_eq!(simplified("0 - -x"), "x");
+        assert_eq!(simplified("x / 1"), "x");
+        assert_eq!(simplified("(a + b) ^ 1"), "a + b");
+        assert_eq!(simplified("pow(y, 1) * 2"), "y * 2");
+        assert_eq!(simplified("x ^ 0 + 1 ^ y"), "1 + 1");
+        assert_eq!(simplified("--x"), "x");
+        assert_eq!(simplified("1 ? a * 1 : b"), "a");
+        assert_eq!(simplified("max(x * 1, data[i + 0])"), "max(x, data[i])");
+    }
+
+    #[test]
+    fn test_purity_check() {
+        assert_eq!(simplified("0 * sin(x + y)"), "0");
+        assert_eq!(simplified("cos(x) * 0"), "0");
+        // User functions may have side effects and must be kept
+        assert_eq!(simplified("0 * adc_read(3)"), "0 * adc_read(3)");
+        assert_eq!(simplified("rand() ^ 0"), "rand()^0");
+
+        // With a context, its natives are pure as it declares them
+        let arena = Bump::new();
+        let mut ctx = EvalContext::new();
+        ctx.register_native_function("scale", 1, |args| args[0] * 2.0)
+            .unwrap();
+        ctx.set_function_pure("scale", true).unwrap();
+        ctx.register_native_function("sin", 1, |args| args[0])
+            .unwrap();
+        let with_ctx = |input: &str| {
+            let ast = arena.alloc(parse_expression(input, &arena).unwrap());
+            simplify_with(ast, Some(&ctx), &arena).to_expression_string()
+        };
+        assert_eq!(with_ctx("0 * scale(x) + y"), "y");
+        assert_eq!(with_ctx("0 * sin(x)"), "0 * sin(x)");
+        assert_eq!(with_ctx("cos(x) * 0"), "0");
+        assert_eq!(with_ctx("0 * adc_read(3)"), "0 * adc_read(3)");
+    }
+
+    #[test]
+    fn test_fuse_multiply_add() {
+        let fused = |input: &str| {
+            let arena = Bump::new();
+            let ast = arena.alloc(parse_expression(input, &arena).unwrap());
+            fuse_multiply_add(ast, &arena).to_expression_string()
+        };
+        assert_eq!(fused("a * b + c"), "fma(a, b, c)");
+        assert_eq!(fused("c + a * b"), "fma(a, b, c)");
+        assert_eq!(fused("a * b * c + d"), "fma(a * b, c, d)");
+        assert_eq!(fused("x > 0 ? k * x + 1 : 0"), "x > 0 ? fma(k, x, 1) : 0");
+        assert_eq!(fused("a * b - c"), "a * b - c");
+    }
+
+    #[test]
+    fn test_specialize_folds_frozen_values() {
+        let specialized = |input: &str, values: &[(&str, Real)]| {
+            let arena = Bump::new();
+            let ast = arena.alloc(parse_expression(input, &arena).unwrap());
+            let ctx = Rc::new(EvalContext::new());
+            specialize(ast, &ctx, values, &arena).to_expression_string()
+        };
+        assert_eq!(specialized("k > 1 ? x * k : x / k", &[("k", 2.0)]), "x * 2");
+        assert_eq!(
+            specialized("sqrt(c) * x + adc_read(c)", &[("c", 4.0)]),
+            "2 * x + adc_read(4)"
+        );
+        assert_eq!(specialized("(a && 0) || x", &[("a", 1.0)]), "0 || x");
+        // Calls that fail to evaluate are kept
+        assert_eq!(
+            specialized("sqrt(a, a) + x", &[("a", 1.0)]),
+            "sqrt(1, 1) + x"
+        );
+        assert_eq!(specialized("a + x", &[]), "a + x");
+    }
+
+    #[test]
+    fn test_unchanged_tree_is_shared() {
+        let arena = Bump::new();
+        let ast = arena.alloc(parse_expression("a * b + sin(c)", &arena).unwrap());
+        assert!(core::ptr::eq(simplify(ast, &arena), ast));
+    }
+}
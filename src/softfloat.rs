@@ -0,0 +1,445 @@
+//! Deterministic software floating point, with the `softfloat` feature.
+//!
+//! The arithmetic operators `+`, `-`, `*`, `/`, `%`, unary minus, their function
+//! aliases (`add`, `sub`, `mul`, `div`, `fmod`, `neg`) and `sqrt` are computed here with
+//! integer operations instead of the FPU, following IEEE 754 with rounding to nearest,
+//! ties to even. Results are therefore the same bit for bit on every target, whatever
+//! the FPU does: no x87 double rounding, no flushing of subnormals to zero by a
+//! Cortex-M whose `FPSCR.FZ` bit was set, and no target-specific NaN.
+//!
+//! Every NaN produced by an operator or returned by a function is replaced by
+//! [`NAN`], since the sign and payload of a NaN computed in hardware differ between
+//! x86 and ARM. The [missing value](crate::missing::MISSING) is kept as it is.
+//!
+//! The other builtins come from libm's portable implementation, which the feature
+//! builds without architecture-specific instructions. Native functions registered by
+//! the application run as compiled, so they are as reproducible as their code.
+//!
+//! ```
+//! use exp_rs::engine::interp;
+//! use exp_rs::softfloat;
+//!
+//! assert_eq!(interp("0.1 + 0.2", None).unwrap(), softfloat::add(0.1, 0.2));
+//! assert_eq!(softfloat::add(0.1, 0.2), 0.1 + 0.2);
+//! // Invalid operations give the same NaN everywhere
+//! assert_eq!(interp("0 / 0", None).unwrap().to_bits(), softfloat::NAN.to_bits());
+//! assert_eq!(interp("sqrt(-1)", None).unwrap().to_bits(), softfloat::NAN.to_bits());
+//! ```
+
+use crate::Real;
+use crate::context::EvalContext;
+
+#[cfg(not(feature = "f32"))]
+type Bits = u64;
+#[cfg(feature = "f32")]
+type Bits = u32;
+
+/// Stored fraction bits
+const MANT: u32 = Real::MANTISSA_DIGITS - 1;
+/// Biased exponent of infinities and NaNs
+const EXP_MAX: i32 = 2 * Real::MAX_EXP - 1;
+const BIAS: i32 = Real::MAX_EXP - 1;
+/// Exponent of the lowest significand bit of subnormal numbers
+const MIN_EXP: i32 = 1 - BIAS - MANT as i32;
+const SIGN_BIT: Bits = 1 << (Bits::BITS - 1);
+const INFINITY_BITS: Bits = (EXP_MAX as Bits) << MANT;
+
+/// The NaN of all invalid operations: positive and quiet, without payload.
+pub const NAN: Real = Real::from_bits(INFINITY_BITS | 1 << (MANT - 1));
+
+/// Replaces a NaN other than the missing value by [`NAN`].
+pub fn canonicalize(x: Real) -> Real {
+    if x.is_nan() && !crate::missing::is_missing(x) {
+        NAN
+    } else {
+        x
+    }
+}
+
+/// Returns `a + b`.
+pub fn add(a: Real, b: Real) -> Real {
+    if a.is_nan() || b.is_nan() {
+        return NAN;
+    }
+    if a.is_infinite() || b.is_infinite() {
+        if a.is_infinite() && b.is_infinite() && a.is_sign_negative() != b.is_sign_negative() {
+            return NAN;
+        }
+        return if a.is_infinite() { a } else { b };
+    }
+
+    let (mut a, mut b) = (unpack(a), unpack(b));
+    if a.exp < b.exp {
+        core::mem::swap(&mut a, &mut b);
+    }
+    // Align the significands at the exponent of `b`; beyond 64 bits of difference `b`
+    // only decides the rounding, so its bits shifted out are kept as a sticky bit.
+    // The exponent of `a` is then above that of subnormals and `a` is non-zero.
+    let distance = (a.exp - b.exp) as u32;
+    let shift = distance.min(64);
+    let (big, mut small) = (a.sig << shift, b.sig);
+    if distance > shift {
+        small = sticky_shift(small, distance - shift);
+    }
+    let exp = a.exp - shift as i32;
+
+    let (negative, sig) = if a.negative == b.negative {
+        (a.negative, big + small)
+    } else if big >= small {
+        (a.negative, big - small)
+    } else {
+        (b.negative, small - big)
+    };
+    if sig == 0 {
+        // An exact zero is positive unless both operands are negative zeros
+        return pack(a.negative && b.negative, 0, 0);
+    }
+    pack(negative, exp, sig)
+}
+
+/// Returns `a - b`.
+pub fn sub(a: Real, b: Real) -> Real {
+    add(a, neg(b))
+}
+
+/// Returns `a * b`.
+pub fn mul(a: Real, b: Real) -> Real {
+    if a.is_nan() || b.is_nan() {
+        return NAN;
+    }
+    let negative = a.is_sign_negative() != b.is_sign_negative();
+    if a.is_infinite() || b.is_infinite() {
+        if a == 0.0 || b == 0.0 {
+            return NAN;
+        }
+        return signed(negative, Real::INFINITY);
+    }
+    let (a, b) = (unpack(a), unpack(b));
+    pack(negative, a.exp + b.exp, a.sig * b.sig)
+}
+
+/// Returns `a / b`.
+pub fn div(a: Real, b: Real) -> Real {
+    if a.is_nan() || b.is_nan() {
+        return NAN;
+    }
+    let negative = a.is_sign_negative() != b.is_sign_negative();
+    match (a.is_infinite(), b.is_infinite()) {
+        (true, true) => return NAN,
+        (true, false) => return signed(negative, Real::INFINITY),
+        (false, true) => return signed(negative, 0.0),
+        (false, false) => {}
+    }
+    if b == 0.0 {
+        return if a == 0.0 {
+            NAN
+        } else {
+            signed(negative, Real::INFINITY)
+        };
+    }
+    if a == 0.0 {
+        return signed(negative, 0.0);
+    }
+
+    let (a, b) = (unpack(a).normalized(), unpack(b).normalized());
+    // Both significands have MANT + 1 bits, so the quotient has at least MANT + 3
+    const EXTRA: u32 = MANT + 3;
+    let numerator = a.sig << EXTRA;
+    let quotient = numerator / b.sig;
+    let inexact = !numerator.is_multiple_of(b.sig);
+    pack(
+        negative,
+        a.exp - b.exp - EXTRA as i32,
+        quotient | inexact as u128,
+    )
+}
+
+/// Returns the remainder of `a / b` with the sign of `a`, like `a % b`.
+pub fn rem(a: Real, b: Real) -> Real {
+    if a.is_nan() || b.is_nan() || a.is_infinite() || b == 0.0 {
+        return NAN;
+    }
+    if b.is_infinite() || a == 0.0 {
+        return a;
+    }
+    let (a, b) = (unpack(a), unpack(b));
+    if a.exp < b.exp {
+        // b is normal, so |a| < 2^(MANT + 1 + a.exp) <= |b|
+        return pack(a.negative, a.exp, a.sig);
+    }
+    // The remainder is exact: reduce a.sig * 2^(a.exp - b.exp) modulo b.sig
+    let mut remainder = a.sig % b.sig;
+    let mut distance = (a.exp - b.exp) as u32;
+    while distance > 0 {
+        let step = distance.min(64);
+        remainder = (remainder << step) % b.sig;
+        distance -= step;
+    }
+    pack(a.negative, b.exp, remainder)
+}
+
+/// Returns `-x`.
+pub fn neg(x: Real) -> Real {
+    if x.is_nan() { NAN } else { -x }
+}
+
+/// Returns the square root of `x`, NaN for negative numbers and `-0` for `-0`.
+pub fn sqrt(x: Real) -> Real {
+    if x.is_nan() || (x < 0.0) {
+        return NAN;
+    }
+    if x == 0.0 || x.is_infinite() {
+        return x;
+    }
+    let x = unpack(x).normalized();
+    // An even exponent halves exactly, and the extra bits give a root of at least
+    // MANT + 3 bits
+    let (mut sig, mut exp) = (x.sig, x.exp);
+    if exp % 2 != 0 {
+        sig <<= 1;
+        exp -= 1;
+    }
+    const EXTRA: u32 = (MANT + 6) & !1;
+    sig <<= EXTRA;
+    exp -= EXTRA as i32;
+    let root = sig.isqrt();
+    let inexact = root * root != sig;
+    pack(false, exp / 2, root | inexact as u128)
+}
+
+/// Registers the software operators in place of the builtins computed by the FPU.
+pub(crate) fn register_builtins(ctx: &mut EvalContext) {
+    for (name, op) in [
+        ("+", add as fn(Real, Real) -> Real),
+        ("add", add),
+        ("-", sub),
+        ("sub", sub),
+        ("*", mul),
+        ("mul", mul),
+        ("/", div),
+        ("div", div),
+        ("%", rem),
+        ("fmod", rem),
+    ] {
+        let _ = ctx.register_native_function(name, 2, move |args| op(args[0], args[1]));
+    }
+    let _ = ctx.register_native_function("neg", 1, |args| neg(args[0]));
+    let _ = ctx.register_native_function("sqrt", 1, |args| sqrt(args[0]));
+}
+
+/// A finite value `sig * 2^exp`.
+#[derive(Clone, Copy)]
+struct Unpacked {
+    negative: bool,
+    exp: i32,
+    sig: u128,
+}
+
+impl Unpacked {
+    /// Shifts the significand of a non-zero subnormal up to MANT + 1 bits.
+    fn normalized(self) -> Self {
+        let shift = self.sig.leading_zeros() - (127 - MANT);
+        Unpacked {
+            sig: self.sig << shift,
+            exp: self.exp - shift as i32,
+            ..self
+        }
+    }
+}
+
+fn unpack(x: Real) -> Unpacked {
+    let bits = x.to_bits();
+    let field = (bits >> MANT) as i32 & EXP_MAX;
+    let fraction = (bits & ((1 << MANT) - 1)) as u128;
+    let (exp, sig) = if field == 0 {
+        (MIN_EXP, fraction)
+    } else {
+        (field - BIAS - MANT as i32, fraction | 1 << MANT)
+    };
+    Unpacked {
+        negative: bits & SIGN_BIT != 0,
+        exp,
+        sig,
+    }
+}
+
+/// Rounds `sig * 2^exp` to the nearest representable value, ties to even.
+///
+/// An inexact `sig` must have its lowest bit set as a sticky bit and at least MANT + 3
+/// bits, so that the sticky bit lies below the rounding bit.
+fn pack(negative: bool, exp: i32, sig: u128) -> Real {
+    let sign = if negative { SIGN_BIT } else { 0 };
+    if sig == 0 {
+        return Real::from_bits(sign);
+    }
+    let top = 127 - sig.leading_zeros() as i32;
+    // Exponent of the lowest kept bit, fixed for subnormal results
+    let mut quantum = (exp + top - MANT as i32).max(MIN_EXP);
+    let mut sig = if quantum >= exp {
+        round_shift(sig, (quantum - exp) as u32)
+    } else {
+        sig << (exp - quantum)
+    };
+    if sig >> (MANT + 1) != 0 {
+        // Rounding carried into a new bit, and the bit shifted out is zero
+        sig >>= 1;
+        quantum += 1;
+    }
+    let biased = quantum + MANT as i32 + BIAS;
+    if biased >= EXP_MAX {
+        return Real::from_bits(sign | INFINITY_BITS);
+    }
+    // The implicit bit of a normal significand carries into the exponent field, and
+    // a subnormal that rounded up to 2^MANT becomes the smallest normal number
+    Real::from_bits(sign | ((((biased - 1) as Bits) << MANT) + sig as Bits))
+}
+
+/// Divides by `2^shift`, rounding to nearest with ties to even.
+fn round_shift(sig: u128, shift: u32) -> u128 {
+    if shift == 0 {
+        return sig;
+    }
+    if shift > 128 {
+        // Below half of the lowest bit
+        return 0;
+    }
+    let (kept, rest, half) = if shift == 128 {
+        (0, sig, 1 << 127)
+    } else {
+        (sig >> shift, sig & ((1 << shift) - 1), 1 << (shift - 1))
+    };
+    if rest > half || (rest == half && kept & 1 == 1) {
+        kept + 1
+    } else {
+        kept
+    }
+}
+
+/// Divides by `2^shift`, truncating and setting the lowest bit if any bit was lost.
+fn sticky_shift(sig: u128, shift: u32) -> u128 {
+    if shift >= 128 {
+        return (sig != 0) as u128;
+    }
+    let lost = sig & ((1 << shift) - 1) != 0;
+    (sig >> shift) | lost as u128
+}
+
+fn signed(negative: bool, x: Real) -> Real {
+    if negative { -x } else { x }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::interp;
+    use alloc::rc::Rc;
+
+    /// Edge values of every class, and random bit patterns of all magnitudes
+    fn samples() -> impl Iterator<Item = Real> {
+        let edges = [
+            0.0,
+            -0.0,
+            1.0,
+            -1.0,
+            0.1,
+            3.0,
+            Real::MIN_POSITIVE,
+            -Real::MIN_POSITIVE,
+            Real::from_bits(1),
+            Real::from_bits(3),
+            Real::from_bits((1 << MANT) - 1),
+            Real::MAX,
+            Real::MIN,
+            Real::EPSILON,
+            1.0 + Real::EPSILON,
+            Real::INFINITY,
+            Real::NEG_INFINITY,
+        ];
+        let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+        let random = core::iter::repeat_with(move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let x = Real::from_bits(state as Bits);
+            if x.is_nan() { 1.5 } else { x }
+        });
+        edges.into_iter().chain(random.take(300))
+    }
+
+    /// The FPU of the test host rounds as IEEE 754 requires
+    fn assert_same(soft: Real, hard: Real, what: &str) {
+        if hard.is_nan() {
+            assert_eq!(soft.to_bits(), NAN.to_bits(), "{}", what);
+        } else {
+            assert_eq!(
+                soft.to_bits(),
+                hard.to_bits(),
+                "{} gave {} instead of {}",
+                what,
+                soft,
+                hard
+            );
+        }
+    }
+
+    #[test]
+    fn test_operations_match_ieee() {
+        let values: alloc::vec::Vec<Real> = samples().collect();
+        for &a in &values {
+            assert_same(sqrt(a), a.sqrt(), &alloc::format!("sqrt({:e})", a));
+            assert_same(neg(a), -a, &alloc::format!("-{:e}", a));
+            for &b in &values {
+                let what = |op| alloc::format!("{:e} {} {:e}", a, op, b);
+                assert_same(add(a, b), a + b, &what("+"));
+                assert_same(sub(a, b), a - b, &what("-"));
+                assert_same(mul(a, b), a * b, &what("*"));
+                assert_same(div(a, b), a / b, &what("/"));
+                assert_same(rem(a, b), a % b, &what("%"));
+            }
+        }
+
+        // Ties round to even, also into and out of subnormals
+        let tiny = Real::from_bits(1);
+        assert_eq!(mul(tiny, 0.5), 0.0);
+        assert_eq!(mul(Real::from_bits(3), 0.5), Real::from_bits(2));
+        assert_eq!(add(1.0, Real::EPSILON / 2.0), 1.0);
+        assert_eq!(sub(Real::MIN_POSITIVE, tiny), Real::MIN_POSITIVE - tiny);
+        assert_eq!(mul(Real::MAX, 2.0), Real::INFINITY);
+        assert!(sub(-0.0, 0.0).is_sign_negative());
+        assert!(add(1.0, -1.0).is_sign_positive());
+    }
+
+    #[test]
+    fn test_builtins_use_software_arithmetic() {
+        let bits = |expr: &str| interp(expr, None).unwrap().to_bits();
+        assert_eq!(
+            bits("0.1 + 0.2 * 3 - 1 / 7"),
+            sub(add(0.1, mul(0.2, 3.0)), div(1.0, 7.0)).to_bits()
+        );
+        for expr in [
+            "0 / 0",
+            "sqrt(-2)",
+            "-(0 / 0)",
+            "inf - inf",
+            "7 % 0",
+            "fmod(1, 0)",
+        ] {
+            let mut ctx = EvalContext::new();
+            ctx.set_parameter("inf", Real::INFINITY).unwrap();
+            let value = interp(expr, Some(Rc::new(ctx))).unwrap();
+            assert_eq!(value.to_bits(), NAN.to_bits(), "{}", expr);
+        }
+
+        // NaNs returned by functions become canonical, the missing value stays
+        let mut ctx = EvalContext::new();
+        ctx.register_native_function("nan", 0, |_| -Real::NAN)
+            .unwrap();
+        ctx.set_missing("m").unwrap();
+        let ctx = Rc::new(ctx);
+        assert_eq!(
+            interp("nan()", Some(ctx.clone())).unwrap().to_bits(),
+            NAN.to_bits()
+        );
+        assert!(crate::missing::is_missing(interp("m", Some(ctx)).unwrap()));
+    }
+}
@@ -0,0 +1,91 @@
+//! Descriptive statistics over arrays (`stats` feature).
+//!
+//! These functions back the `mean`, `variance`, `stddev`, `median` and `percentile`
+//! builtins. In expressions they take either values or the name of a context array,
+//! which stands for all of its elements:
+//!
+//! ```
+//! use exp_rs::EvalContext;
+//! use exp_rs::engine::interp;
+//! use exp_rs::types::TryIntoHeaplessString;
+//! use std::rc::Rc;
+//!
+//! let mut ctx = EvalContext::new();
+//! let samples = vec![12.0, 15.0, 11.0, 40.0, 13.0];
+//! ctx.arrays.insert("samples".try_into_heapless().unwrap(), samples).unwrap();
+//! let ctx = Rc::new(ctx);
+//!
+//! assert_eq!(interp("median(samples)", Some(ctx.clone())).unwrap(), 13.0);
+//! assert_eq!(interp("percentile(samples, 50)", Some(ctx.clone())).unwrap(), 13.0);
+//! assert_eq!(interp("mean(1, 2, 6)", Some(ctx)).unwrap(), 3.0);
+//! ```
+//!
+//! All functions return NaN for an empty input or when any value is NaN.
+
+use crate::Real;
+use alloc::vec::Vec;
+
+/// Arithmetic mean of `values`.
+pub fn mean(values: &[Real]) -> Real {
+    if values.is_empty() {
+        return Real::NAN;
+    }
+    values.iter().sum::<Real>() / values.len() as Real
+}
+
+/// Population variance of `values`, the mean squared deviation from the mean.
+pub fn variance(values: &[Real]) -> Real {
+    let m = mean(values);
+    values.iter().map(|v| (v - m) * (v - m)).sum::<Real>() / values.len() as Real
+}
+
+/// Population standard deviation of `values`, the square root of [`variance`].
+pub fn stddev(values: &[Real]) -> Real {
+    crate::functions::sqrt(variance(values), 0.0)
+}
+
+/// Median of `values`; the mean of the two middle values for an even count.
+pub fn median(values: &[Real]) -> Real {
+    percentile(values, 50.0)
+}
+
+/// The `p`-th percentile of `values`, for `p` from 0 to 100.
+///
+/// Interpolates linearly between the two nearest ranks, so `percentile(v, 0)` is the
+/// minimum and `percentile(v, 100)` the maximum. Returns NaN if `p` is out of range.
+pub fn percentile(values: &[Real], p: Real) -> Real {
+    if values.is_empty() || values.iter().any(|v| v.is_nan()) || !(0.0..=100.0).contains(&p) {
+        return Real::NAN;
+    }
+    let mut sorted: Vec<Real> = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+
+    let rank = p / 100.0 * (sorted.len() - 1) as Real;
+    let lower = rank as usize;
+    let upper = (lower + 1).min(sorted.len() - 1);
+    let fraction = rank - lower as Real;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * fraction
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_descriptive_statistics() {
+        let values = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        assert_eq!(mean(&values), 5.0);
+        assert_eq!(variance(&values), 4.0);
+        assert_eq!(stddev(&values), 2.0);
+        assert_eq!(median(&values), 4.5);
+        assert_eq!(median(&[3.0, 1.0, 2.0]), 2.0);
+
+        assert_eq!(percentile(&values, 0.0), 2.0);
+        assert_eq!(percentile(&values, 100.0), 9.0);
+        assert_eq!(percentile(&[10.0, 20.0, 30.0, 40.0, 50.0], 90.0), 46.0);
+
+        assert!(mean(&[]).is_nan());
+        assert!(median(&[1.0, Real::NAN]).is_nan());
+        assert!(percentile(&values, 101.0).is_nan());
+    }
+}
@@ -10,20 +10,14 @@ extern crate alloc;
 // Heapless Migration - Type Aliases and Configuration
 // ============================================================================
 
-use heapless::{FnvIndexMap, String as HeaplessString};
 use alloc::string::ToString;
+#[cfg(not(feature = "std"))]
+use heapless::FnvIndexMap;
+use heapless::String as HeaplessString;
 
-// Configuration constants - can be adjusted based on target constraints
-pub const EXP_RS_MAX_VARIABLES: usize = 16;
-pub const EXP_RS_MAX_BATCH_PARAMS: usize = 64;
-pub const EXP_RS_MAX_CONSTANTS: usize = 8;
-pub const EXP_RS_MAX_ARRAYS: usize = 4;
-pub const EXP_RS_MAX_ATTRIBUTES: usize = 4;
-pub const EXP_RS_MAX_NESTED_ARRAYS: usize = 2;
-pub const EXP_RS_MAX_AST_CACHE: usize = 16;
-pub const EXP_RS_MAX_NATIVE_FUNCTIONS: usize = 64;
-pub const EXP_RS_MAX_EXPRESSION_FUNCTIONS: usize = 8;
-pub const EXP_RS_MAX_ATTR_KEYS: usize = 4;
+// Configuration constants - generated by build.rs. Each limit can be overridden at build
+// time by setting an environment variable of the same name, e.g. `EXP_RS_MAX_VARIABLES=64`.
+include!(concat!(env!("OUT_DIR"), "/capacity.rs"));
 
 // String length limits for embedded efficiency
 pub const EXP_RS_MAX_KEY_LENGTH: usize = 32;
@@ -37,24 +31,103 @@ pub type HString = HeaplessString<EXP_RS_MAX_KEY_LENGTH>;
 pub type FunctionName = HeaplessString<EXP_RS_MAX_FUNCTION_NAME_LENGTH>;
 
 // Container type aliases - using heapless FnvIndexMap
+#[cfg(not(feature = "std"))]
 pub type VariableMap = FnvIndexMap<HString, crate::Real, EXP_RS_MAX_VARIABLES>;
+#[cfg(not(feature = "std"))]
+pub type TypedValueMap = FnvIndexMap<HString, crate::value::Value, EXP_RS_MAX_VARIABLES>;
+#[cfg(not(feature = "std"))]
 pub type ConstantMap = FnvIndexMap<HString, crate::Real, EXP_RS_MAX_CONSTANTS>;
+#[cfg(not(feature = "std"))]
 pub type BatchParamMap = FnvIndexMap<HString, crate::Real, EXP_RS_MAX_BATCH_PARAMS>;
+#[cfg(not(feature = "std"))]
 pub type ArrayMap = FnvIndexMap<HString, alloc::vec::Vec<crate::Real>, EXP_RS_MAX_ARRAYS>;
-pub type AttributeMap = FnvIndexMap<
-    HString,
-    FnvIndexMap<HString, crate::Real, EXP_RS_MAX_ATTR_KEYS>,
-    EXP_RS_MAX_ATTRIBUTES,
->;
+#[cfg(not(feature = "std"))]
+pub type AttributeKeyMap = FnvIndexMap<HString, crate::Real, EXP_RS_MAX_ATTR_KEYS>;
+#[cfg(not(feature = "std"))]
+pub type AttributeMap = FnvIndexMap<HString, AttributeKeyMap, EXP_RS_MAX_ATTRIBUTES>;
+#[cfg(not(feature = "std"))]
 pub type NestedArrayMap = FnvIndexMap<
     HString,
     FnvIndexMap<usize, alloc::vec::Vec<crate::Real>, EXP_RS_MAX_NESTED_ARRAYS>,
     EXP_RS_MAX_NESTED_ARRAYS,
 >;
+#[cfg(not(feature = "std"))]
 pub type NativeFunctionMap = FnvIndexMap<FunctionName, NativeFunction, EXP_RS_MAX_NATIVE_FUNCTIONS>;
+#[cfg(not(feature = "std"))]
 pub type ExpressionFunctionMap =
     FnvIndexMap<FunctionName, ExpressionFunction, EXP_RS_MAX_EXPRESSION_FUNCTIONS>;
 
+// Container type aliases - using growable HashMaps when `std` is available.
+// The EXP_RS_MAX_* entry-count limits do not apply to these maps.
+#[cfg(feature = "std")]
+pub type VariableMap = UnboundedMap<HString, crate::Real>;
+#[cfg(feature = "std")]
+pub type TypedValueMap = UnboundedMap<HString, crate::value::Value>;
+#[cfg(feature = "std")]
+pub type ConstantMap = UnboundedMap<HString, crate::Real>;
+#[cfg(feature = "std")]
+pub type BatchParamMap = UnboundedMap<HString, crate::Real>;
+#[cfg(feature = "std")]
+pub type ArrayMap = UnboundedMap<HString, alloc::vec::Vec<crate::Real>>;
+#[cfg(feature = "std")]
+pub type AttributeKeyMap = UnboundedMap<HString, crate::Real>;
+#[cfg(feature = "std")]
+pub type AttributeMap = UnboundedMap<HString, AttributeKeyMap>;
+#[cfg(feature = "std")]
+pub type NestedArrayMap = UnboundedMap<HString, UnboundedMap<usize, alloc::vec::Vec<crate::Real>>>;
+#[cfg(feature = "std")]
+pub type NativeFunctionMap = UnboundedMap<FunctionName, NativeFunction>;
+#[cfg(feature = "std")]
+pub type ExpressionFunctionMap = UnboundedMap<FunctionName, ExpressionFunction>;
+
+/// A growable map used for the context containers when the `std` feature is enabled.
+///
+/// This is a thin wrapper around [`std::collections::HashMap`] that keeps the
+/// fallible `insert` signature of `heapless::FnvIndexMap`, so code written against
+/// the heapless containers compiles unchanged. Inserts never fail. All other
+/// `HashMap` methods are available through `Deref`.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct UnboundedMap<K, V>(std::collections::HashMap<K, V>);
+
+#[cfg(feature = "std")]
+impl<K: core::hash::Hash + Eq, V> UnboundedMap<K, V> {
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        UnboundedMap(std::collections::HashMap::new())
+    }
+
+    /// Inserts a key-value pair, returning the previous value for the key if any.
+    ///
+    /// Always returns `Ok`; the `Result` mirrors `heapless::FnvIndexMap::insert`.
+    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>, (K, V)> {
+        Ok(self.0.insert(key, value))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K: core::hash::Hash + Eq, V> Default for UnboundedMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, V> core::ops::Deref for UnboundedMap<K, V> {
+    type Target = std::collections::HashMap<K, V>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, V> core::ops::DerefMut for UnboundedMap<K, V> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
 // AST cache type - defined later after AstExpr is declared
 // pub type AstCacheMap = FnvIndexMap<HString, alloc::rc::Rc<AstExpr>, MAX_AST_CACHE>;
 
@@ -81,7 +154,9 @@ use std::vec::Vec;
 /// eliminating all dynamic allocations during evaluation.
 ///
 /// Using repr(C) with explicit discriminant type and alignment to avoid ARM alignment issues
-#[derive(Debug)]
+///
+/// Cloning is shallow: child nodes are shared references into the same arena.
+#[derive(Debug, Clone)]
 #[repr(C, align(8))]
 pub enum AstExpr<'arena> {
     /// A literal numerical value.
@@ -207,19 +282,32 @@ pub trait TryIntoHeaplessString {
 
 impl TryIntoHeaplessString for &str {
     fn try_into_heapless(self) -> Result<HString, crate::error::ExprError> {
-        HString::try_from(self).map_err(|_| {
-            crate::error::ExprError::StringTooLong(self.to_string(), EXP_RS_MAX_KEY_LENGTH)
+        HString::try_from(self).map_err(|_| crate::error::ExprError::StringTooLong {
+            value: self.to_string(),
+            max_len: EXP_RS_MAX_KEY_LENGTH,
         })
     }
 }
 
 impl TryIntoHeaplessString for alloc::string::String {
     fn try_into_heapless(self) -> Result<HString, crate::error::ExprError> {
-        HString::try_from(self.as_str())
-            .map_err(|_| crate::error::ExprError::StringTooLong(self, EXP_RS_MAX_KEY_LENGTH))
+        HString::try_from(self.as_str()).map_err(|_| crate::error::ExprError::StringTooLong {
+            value: self,
+            max_len: EXP_RS_MAX_KEY_LENGTH,
+        })
     }
 }
 
+/// Joins `base.attr` without allocating, or returns `None` if the path is longer than
+/// `N` bytes.
+pub(crate) fn dotted_path<const N: usize>(base: &str, attr: &str) -> Option<HeaplessString<N>> {
+    let mut path = HeaplessString::new();
+    path.push_str(base).ok()?;
+    path.push('.').ok()?;
+    path.push_str(attr).ok()?;
+    Some(path)
+}
+
 // Helper trait for function names
 pub trait TryIntoFunctionName {
     fn try_into_function_name(self) -> Result<FunctionName, crate::error::ExprError>;
@@ -227,19 +315,18 @@ pub trait TryIntoFunctionName {
 
 impl TryIntoFunctionName for &str {
     fn try_into_function_name(self) -> Result<FunctionName, crate::error::ExprError> {
-        FunctionName::try_from(self).map_err(|_| {
-            crate::error::ExprError::StringTooLong(
-                self.to_string(),
-                EXP_RS_MAX_FUNCTION_NAME_LENGTH,
-            )
+        FunctionName::try_from(self).map_err(|_| crate::error::ExprError::StringTooLong {
+            value: self.to_string(),
+            max_len: EXP_RS_MAX_FUNCTION_NAME_LENGTH,
         })
     }
 }
 
 impl TryIntoFunctionName for alloc::string::String {
     fn try_into_function_name(self) -> Result<FunctionName, crate::error::ExprError> {
-        FunctionName::try_from(self.as_str()).map_err(|_| {
-            crate::error::ExprError::StringTooLong(self, EXP_RS_MAX_FUNCTION_NAME_LENGTH)
+        FunctionName::try_from(self.as_str()).map_err(|_| crate::error::ExprError::StringTooLong {
+            value: self,
+            max_len: EXP_RS_MAX_FUNCTION_NAME_LENGTH,
         })
     }
 }
@@ -301,6 +388,36 @@ mod tests {
 
     use std::rc::Rc;
 
+    #[test]
+    #[cfg(not(feature = "std"))]
+    fn test_container_capacities_follow_build_config() {
+        assert_eq!(VariableMap::new().capacity(), EXP_RS_MAX_VARIABLES);
+        assert_eq!(ConstantMap::new().capacity(), EXP_RS_MAX_CONSTANTS);
+        assert_eq!(
+            NativeFunctionMap::new().capacity(),
+            EXP_RS_MAX_NATIVE_FUNCTIONS
+        );
+
+        let mut ctx = EvalContext::empty();
+        for i in 0..EXP_RS_MAX_VARIABLES {
+            ctx.set_parameter(&std::format!("v{}", i), i as Real)
+                .unwrap();
+        }
+        assert!(matches!(
+            ctx.set_parameter("overflow", 0.0),
+            Err(ExprError::CapacityExceeded { .. })
+        ));
+    }
+
+    #[test]
+    fn test_dotted_path() {
+        let path: HString = dotted_path("imu.axis", "x").unwrap();
+        assert_eq!(path.as_str(), "imu.axis.x");
+        let long = "a".repeat(EXP_RS_MAX_KEY_LENGTH);
+        assert!(dotted_path::<EXP_RS_MAX_KEY_LENGTH>(&long, "x").is_none());
+        assert!(dotted_path::<EXP_RS_MAX_KEY_LENGTH>(&long[2..], "x").is_some());
+    }
+
     #[test]
     fn test_eval_ast_array_and_attribute_errors() {
         let arena = Bump::new();
@@ -491,7 +608,7 @@ pub enum TokenKind {
 ///
 /// - `0.0` represents `false`
 /// - Any non-zero value (typically `1.0`) represents `true`
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum LogicalOperator {
     /// Logical AND (&&) - evaluates to true only if both operands are true.
     /// Short-circuits if the left operand is false.
@@ -549,11 +666,27 @@ impl core::fmt::Display for LogicalOperator {
 /// let result = interp("hypotenuse(3, 4)", Some(Rc::new(ctx))).unwrap();
 /// assert_eq!(result, 5.0);
 /// ```
+/// Callback checking the arguments of a call before evaluation, given the argument
+/// expressions. It returns a message explaining why the call is invalid.
+///
+/// See [`EvalContext::set_function_validator`](crate::EvalContext::set_function_validator).
+pub type ArgumentValidator = Rc<dyn Fn(&[AstExpr<'_>]) -> Result<(), String>>;
+
+/// Single-precision implementation of a native function, for [`shadow`](crate::shadow)
+/// evaluation.
+///
+/// See [`EvalContext::set_single_precision_function`](crate::EvalContext::set_single_precision_function).
+#[cfg(all(feature = "shadow", not(feature = "f32")))]
+pub type SinglePrecisionFunction = Rc<dyn Fn(&[f32]) -> f32>;
+
 #[derive(Clone)]
 pub struct NativeFunction {
-    /// Number of arguments the function takes.
+    /// Number of arguments the function takes, or the minimum number if `variadic`.
     pub arity: usize,
 
+    /// Whether the function accepts more than `arity` arguments.
+    pub variadic: bool,
+
     /// The actual implementation of the function as a Rust closure.
     pub implementation: Rc<dyn Fn(&[Real]) -> Real>,
 
@@ -562,6 +695,76 @@ pub struct NativeFunction {
 
     /// Optional description of what the function does.
     pub description: Option<String>,
+
+    /// Whether the function always returns the same result for the same arguments and
+    /// has no side effects, so calls may be constant folded and memoized.
+    pub pure: bool,
+
+    /// Optional check of the arguments of calls, run when expressions are validated.
+    pub validator: Option<ArgumentValidator>,
+
+    /// Whether this is the library's own implementation, registered by
+    /// `EvalContext::register_default_math_functions` and not replaced since.
+    pub builtin: bool,
+
+    /// Optional single-precision implementation, used for the `f32` side of
+    /// [`shadow`](crate::shadow) evaluation.
+    #[cfg(all(feature = "shadow", not(feature = "f32")))]
+    pub single_precision: Option<SinglePrecisionFunction>,
+}
+
+impl NativeFunction {
+    /// Returns whether the function can be called with `arg_count` arguments.
+    pub fn accepts(&self, arg_count: usize) -> bool {
+        if self.variadic {
+            arg_count >= self.arity
+        } else {
+            arg_count == self.arity
+        }
+    }
+}
+
+/// How a registered function is called, as returned by
+/// [`EvalContext::function_signature`](crate::EvalContext::function_signature).
+///
+/// Displays as a call template such as `atan2(x1, x2)` or `max(x1, ...)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionSignature {
+    /// The function name.
+    pub name: String,
+    /// Number of arguments, or the minimum number if `variadic`.
+    pub arity: usize,
+    /// Whether the function accepts more than `arity` arguments.
+    pub variadic: bool,
+    /// Description given at registration, if any.
+    pub description: Option<String>,
+}
+
+impl From<&NativeFunction> for FunctionSignature {
+    fn from(function: &NativeFunction) -> Self {
+        Self {
+            name: function.name.to_string(),
+            arity: function.arity,
+            variadic: function.variadic,
+            description: function.description.clone(),
+        }
+    }
+}
+
+impl core::fmt::Display for FunctionSignature {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}(", self.name)?;
+        for i in 1..=self.arity {
+            if i > 1 {
+                write!(f, ", ")?;
+            }
+            write!(f, "x{i}")?;
+        }
+        if self.variadic {
+            write!(f, "{}...", if self.arity > 0 { ", " } else { "" })?;
+        }
+        write!(f, ")")
+    }
 }
 
 /* We can't derive Clone for NativeFunction because Box<dyn Fn> doesn't implement Clone.
@@ -609,11 +812,51 @@ pub struct ExpressionFunction {
     /// Optional description of what the function does.
     pub description: Option<String>,
 
+    /// Default values of the trailing parameters: the last `defaults.len()`
+    /// arguments may be omitted in a call.
+    pub defaults: Vec<crate::Real>,
+
     /// Pre-allocated parameter buffer for zero-allocation evaluation.
     /// When available, this points to an arena-allocated slice that can be reused
     /// for every function call instead of allocating new parameter storage.
     /// The slice size matches params.len() and gets filled with actual values during evaluation.
     pub param_buffer: Option<*mut [(crate::types::HString, crate::Real)]>,
+
+    /// Hash of the name, parameters and body (see [`ExpressionFunction::definition_hash`])
+    /// and of `parse_options` if not the default, which keys the parsed body in the
+    /// evaluator's cache.
+    pub definition_hash: u64,
+
+    /// Options the body is parsed with: those of the batch when the function was
+    /// registered.
+    pub parse_options: crate::engine::ParseOptions,
+
+    /// Optional check of the arguments of calls, run when the batch is validated.
+    pub validator: Option<ArgumentValidator>,
+}
+
+impl ExpressionFunction {
+    /// Computes the hash identifying a function definition.
+    ///
+    /// Two definitions with the same name, parameter names and body share a hash, so a
+    /// function re-registered unchanged reuses its parsed body, while a changed body is
+    /// parsed again. This is 64-bit FNV-1a, which is stable across platforms and builds.
+    /// Equal hashes are confirmed by comparing the definitions, so a collision is never
+    /// mistaken for an unchanged function.
+    pub fn definition_hash(name: &str, params: &[String], body: &str) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let parts = core::iter::once(name)
+            .chain(params.iter().map(String::as_str))
+            .chain(core::iter::once(body));
+        for part in parts {
+            // A zero byte separates the parts, so ("ab", "c") and ("a", "bc") differ
+            for byte in part.bytes().chain(core::iter::once(0)) {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+        hash
+    }
 }
 
 impl Clone for ExpressionFunction {
@@ -623,7 +866,11 @@ impl Clone for ExpressionFunction {
             params: self.params.clone(),
             expression: self.expression.clone(),
             description: self.description.clone(),
+            defaults: self.defaults.clone(),
             param_buffer: self.param_buffer, // Share the same buffer pointer
+            definition_hash: self.definition_hash,
+            parse_options: self.parse_options,
+            validator: self.validator.clone(),
         }
     }
 }
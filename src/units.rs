@@ -0,0 +1,507 @@
+//! Physical units and dimensional analysis.
+//!
+//! With [`ParseOptions::unit_literals`](crate::engine::ParseOptions::unit_literals) enabled,
+//! a number directly followed by a unit name (`3.3V`, `10ms`, `2.5kHz`) parses as the
+//! product of the number and the unit. Units are looked up in the [`UnitRegistry`] attached
+//! to the evaluation context and evaluate to their scale factor, so every quantity is
+//! converted to SI base units: `10ms` evaluates to `0.01` and `2.5kHz` to `2500`.
+//!
+//! [`UnitRegistry::check`] walks an AST and verifies that the dimensions of its operands
+//! are compatible, e.g. that a voltage is never added to a time.
+
+extern crate alloc;
+
+use crate::Real;
+use crate::error::ExprError;
+use crate::types::AstExpr;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::{Div, Mul};
+
+/// Number of SI base dimensions tracked by [`Dimension`].
+const BASE_DIMENSIONS: usize = 7;
+
+/// Symbols of the SI base units, in the order of the exponents in [`Dimension`].
+const BASE_SYMBOLS: [&str; BASE_DIMENSIONS] = ["m", "kg", "s", "A", "K", "mol", "cd"];
+
+/// SI prefixes that may be combined with any registered unit.
+const PREFIXES: &[(&str, f64)] = &[
+    ("p", 1e-12),
+    ("n", 1e-9),
+    ("u", 1e-6),
+    ("m", 1e-3),
+    ("c", 1e-2),
+    ("k", 1e3),
+    ("M", 1e6),
+    ("G", 1e9),
+];
+
+/// The physical dimension of a quantity, as exponents of the seven SI base dimensions
+/// (length, mass, time, current, temperature, amount and luminous intensity).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Dimension {
+    exponents: [i8; BASE_DIMENSIONS],
+}
+
+impl Dimension {
+    /// A pure number.
+    pub const DIMENSIONLESS: Dimension = Dimension::new([0, 0, 0, 0, 0, 0, 0]);
+    /// Length (metre).
+    pub const LENGTH: Dimension = Dimension::new([1, 0, 0, 0, 0, 0, 0]);
+    /// Mass (kilogram).
+    pub const MASS: Dimension = Dimension::new([0, 1, 0, 0, 0, 0, 0]);
+    /// Time (second).
+    pub const TIME: Dimension = Dimension::new([0, 0, 1, 0, 0, 0, 0]);
+    /// Electric current (ampere).
+    pub const CURRENT: Dimension = Dimension::new([0, 0, 0, 1, 0, 0, 0]);
+    /// Temperature (kelvin).
+    pub const TEMPERATURE: Dimension = Dimension::new([0, 0, 0, 0, 1, 0, 0]);
+    /// Amount of substance (mole).
+    pub const AMOUNT: Dimension = Dimension::new([0, 0, 0, 0, 0, 1, 0]);
+    /// Luminous intensity (candela).
+    pub const LUMINOSITY: Dimension = Dimension::new([0, 0, 0, 0, 0, 0, 1]);
+
+    /// Creates a dimension from exponents of m, kg, s, A, K, mol and cd.
+    pub const fn new(exponents: [i8; BASE_DIMENSIONS]) -> Self {
+        Self { exponents }
+    }
+
+    /// Returns the exponents of m, kg, s, A, K, mol and cd.
+    pub const fn exponents(&self) -> [i8; BASE_DIMENSIONS] {
+        self.exponents
+    }
+
+    /// Returns true if this is the dimension of a pure number.
+    pub fn is_dimensionless(&self) -> bool {
+        self.exponents.iter().all(|&e| e == 0)
+    }
+
+    /// Raises the dimension to an integer power.
+    pub fn powi(self, n: i8) -> Self {
+        let mut exponents = self.exponents;
+        for e in exponents.iter_mut() {
+            *e *= n;
+        }
+        Self { exponents }
+    }
+
+    /// Takes the square root of the dimension, if every exponent is even.
+    pub fn sqrt(self) -> Option<Self> {
+        if self.exponents.iter().any(|e| e % 2 != 0) {
+            return None;
+        }
+        let mut exponents = self.exponents;
+        for e in exponents.iter_mut() {
+            *e /= 2;
+        }
+        Some(Self { exponents })
+    }
+}
+
+impl Mul for Dimension {
+    type Output = Dimension;
+
+    // Multiplying quantities adds the exponents of their dimensions
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn mul(self, rhs: Dimension) -> Dimension {
+        let mut exponents = self.exponents;
+        for (e, r) in exponents.iter_mut().zip(rhs.exponents) {
+            *e += r;
+        }
+        Dimension { exponents }
+    }
+}
+
+impl Div for Dimension {
+    type Output = Dimension;
+
+    fn div(self, rhs: Dimension) -> Dimension {
+        self * rhs.powi(-1)
+    }
+}
+
+impl fmt::Display for Dimension {
+    /// Formats the dimension in SI base units, e.g. `kg*m^2*s^-3` for power.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_dimensionless() {
+            return write!(f, "1");
+        }
+        // Mass first reads more naturally for derived units (kg*m^2*s^-2)
+        let mut first = true;
+        for i in [1, 0, 2, 3, 4, 5, 6] {
+            let e = self.exponents[i];
+            if e == 0 {
+                continue;
+            }
+            if !first {
+                write!(f, "*")?;
+            }
+            first = false;
+            write!(f, "{}", BASE_SYMBOLS[i])?;
+            if e != 1 {
+                write!(f, "^{}", e)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A unit of measurement: a scale factor to SI base units and a dimension.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Unit {
+    /// Value of one of this unit in SI base units (e.g. `0.001` for `ms`)
+    pub scale: Real,
+    /// Physical dimension of the unit
+    pub dimension: Dimension,
+}
+
+/// A set of named units, plus the declared dimensions of variables.
+///
+/// Unit names may carry an SI prefix (`p`, `n`, `u`, `m`, `c`, `k`, `M`, `G`): `kHz` is
+/// found as `k` + `Hz` without registering it. Names registered explicitly take precedence
+/// over prefixed names, so `min` is a minute and not a milli-inch.
+///
+/// # Examples
+///
+/// ```
+/// use exp_rs::units::{Dimension, UnitRegistry};
+///
+/// let units = UnitRegistry::si();
+/// let khz = units.lookup("kHz").unwrap();
+/// assert_eq!(khz.scale, 1000.0);
+/// assert_eq!(khz.dimension, Dimension::TIME.powi(-1));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct UnitRegistry {
+    units: Vec<(String, Unit)>,
+    variables: Vec<(String, Dimension)>,
+}
+
+impl UnitRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry with the SI base units, the common derived units
+    /// (`Hz`, `N`, `Pa`, `J`, `W`, `C`, `V`, `F`, `Ohm`, `S`, `Wb`, `T`, `H`) and
+    /// `min`/`h` for minutes and hours.
+    ///
+    /// Mass is registered as `g` with a scale of `0.001`, so `kg` is the base unit.
+    pub fn si() -> Self {
+        let d = Dimension::new;
+        let mut registry = Self::new();
+        registry.register("m", 1.0, Dimension::LENGTH);
+        registry.register("g", 0.001, Dimension::MASS);
+        registry.register("s", 1.0, Dimension::TIME);
+        registry.register("A", 1.0, Dimension::CURRENT);
+        registry.register("K", 1.0, Dimension::TEMPERATURE);
+        registry.register("mol", 1.0, Dimension::AMOUNT);
+        registry.register("cd", 1.0, Dimension::LUMINOSITY);
+        registry.register("Hz", 1.0, d([0, 0, -1, 0, 0, 0, 0]));
+        registry.register("N", 1.0, d([1, 1, -2, 0, 0, 0, 0]));
+        registry.register("Pa", 1.0, d([-1, 1, -2, 0, 0, 0, 0]));
+        registry.register("J", 1.0, d([2, 1, -2, 0, 0, 0, 0]));
+        registry.register("W", 1.0, d([2, 1, -3, 0, 0, 0, 0]));
+        registry.register("C", 1.0, d([0, 0, 1, 1, 0, 0, 0]));
+        registry.register("V", 1.0, d([2, 1, -3, -1, 0, 0, 0]));
+        registry.register("F", 1.0, d([-2, -1, 4, 2, 0, 0, 0]));
+        registry.register("Ohm", 1.0, d([2, 1, -3, -2, 0, 0, 0]));
+        registry.register("S", 1.0, d([-2, -1, 3, 2, 0, 0, 0]));
+        registry.register("Wb", 1.0, d([2, 1, -2, -1, 0, 0, 0]));
+        registry.register("T", 1.0, d([0, 1, -2, -1, 0, 0, 0]));
+        registry.register("H", 1.0, d([2, 1, -2, -2, 0, 0, 0]));
+        registry.register("min", 60.0, Dimension::TIME);
+        registry.register("h", 3600.0, Dimension::TIME);
+        registry
+    }
+
+    /// Registers a unit, replacing any unit of the same name.
+    pub fn register(&mut self, name: &str, scale: Real, dimension: Dimension) {
+        let unit = Unit { scale, dimension };
+        match self.units.iter_mut().find(|(n, _)| n == name) {
+            Some(entry) => entry.1 = unit,
+            None => self.units.push((name.to_string(), unit)),
+        }
+    }
+
+    /// Looks up a unit by name, trying registered names first and then SI prefixes.
+    pub fn lookup(&self, name: &str) -> Option<Unit> {
+        if let Some((_, unit)) = self.units.iter().find(|(n, _)| n == name) {
+            return Some(*unit);
+        }
+        PREFIXES.iter().find_map(|&(prefix, factor)| {
+            let base = name.strip_prefix(prefix)?;
+            let (_, unit) = self.units.iter().find(|(n, _)| n == base)?;
+            Some(Unit {
+                scale: unit.scale * factor as Real,
+                dimension: unit.dimension,
+            })
+        })
+    }
+
+    /// Declares the dimension of a variable for [`check`](Self::check).
+    ///
+    /// The variable's value is expected to be in SI base units.
+    pub fn declare_variable(&mut self, name: &str, dimension: Dimension) {
+        match self.variables.iter_mut().find(|(n, _)| n == name) {
+            Some(entry) => entry.1 = dimension,
+            None => self.variables.push((name.to_string(), dimension)),
+        }
+    }
+
+    /// Returns the declared dimension of a variable.
+    pub fn variable_dimension(&self, name: &str) -> Option<Dimension> {
+        self.variables
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, d)| *d)
+    }
+
+    /// Checks that an expression is dimensionally consistent and returns its dimension.
+    ///
+    /// Operands of `+`, `-`, `%`, `min`, `max`, comparisons and the branches of a
+    /// conditional must have the same dimension; `*` and `/` combine dimensions; powers
+    /// of dimensioned values need a constant integer exponent; `sqrt` halves even
+    /// exponents; `abs`, `floor`, `ceil`, `round` and negation keep the dimension; all
+    /// other functions require dimensionless arguments.
+    ///
+    /// Plain numbers, undeclared variables, arrays and attributes adapt to whatever
+    /// dimension they are combined with, so only quantities built from unit literals and
+    /// declared variables are checked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use exp_rs::engine::{ParseOptions, parse_expression_with_options};
+    /// use exp_rs::units::{Dimension, UnitRegistry};
+    /// use bumpalo::Bump;
+    ///
+    /// let options = ParseOptions { unit_literals: true, ..Default::default() };
+    /// let mut units = UnitRegistry::si();
+    /// units.declare_variable("t", Dimension::TIME);
+    ///
+    /// let arena = Bump::new();
+    /// let ok = parse_expression_with_options("3.3V / 10ms * t", &arena, &options).unwrap();
+    /// assert_eq!(units.check(&ok).unwrap(), units.lookup("V").unwrap().dimension);
+    ///
+    /// let bad = parse_expression_with_options("3.3V + t", &arena, &options).unwrap();
+    /// assert!(units.check(&bad).is_err());
+    /// ```
+    pub fn check(&self, expr: &AstExpr) -> Result<Dimension, ExprError> {
+        Ok(self.dimension_of(expr)?.unwrap_or(Dimension::DIMENSIONLESS))
+    }
+
+    /// Computes the dimension of a subtree; `None` means "any dimension".
+    fn dimension_of(&self, expr: &AstExpr) -> Result<Option<Dimension>, ExprError> {
+        match expr {
+            AstExpr::Constant(_) | AstExpr::Array { .. } | AstExpr::Attribute { .. } => Ok(None),
+            AstExpr::Variable(name) => Ok(self
+                .variable_dimension(name)
+                .or_else(|| self.lookup(name).map(|u| u.dimension))),
+            AstExpr::LogicalOp { left, right, .. } => {
+                self.dimension_of(left)?;
+                self.dimension_of(right)?;
+                Ok(None)
+            }
+            AstExpr::Conditional {
+                condition,
+                true_branch,
+                false_branch,
+            } => {
+                self.dimension_of(condition)?;
+                let t = self.dimension_of(true_branch)?;
+                let f = self.dimension_of(false_branch)?;
+                unify("?:", t, f)
+            }
+            AstExpr::Function { name, args } => self.function_dimension(name, args),
+        }
+    }
+
+    fn function_dimension(
+        &self,
+        name: &str,
+        args: &[AstExpr],
+    ) -> Result<Option<Dimension>, ExprError> {
+        let mut dims = Vec::with_capacity(args.len());
+        for arg in args {
+            dims.push(self.dimension_of(arg)?);
+        }
+
+        match (name, dims.as_slice()) {
+            ("+" | "-" | "%" | "fmod" | "min" | "max", [first, rest @ ..]) => {
+                rest.iter().try_fold(*first, |acc, d| unify(name, acc, *d))
+            }
+            ("<" | ">" | "<=" | ">=" | "==" | "!=" | "<>", [l, r]) => {
+                unify(name, *l, *r)?;
+                Ok(None)
+            }
+            ("*", [l, r]) => Ok(combine(*l, *r, |a, b| a * b)),
+            ("/", [l, r]) => Ok(combine(*l, *r, |a, b| a / b)),
+            ("neg" | "abs" | "floor" | "ceil" | "round", [d]) => Ok(*d),
+            ("sqrt", [Some(d)]) => d.sqrt().map(Some).ok_or_else(|| mismatch(name, *d)),
+            ("^" | "**" | "pow", [base, exponent]) => {
+                require_dimensionless(name, *exponent)?;
+                match base {
+                    Some(d) if !d.is_dimensionless() => match constant_integer(&args[1]) {
+                        Some(n) => Ok(Some(d.powi(n))),
+                        None => Err(mismatch(name, *d)),
+                    },
+                    _ => Ok(*base),
+                }
+            }
+            ("," | ";" | "comma", [.., last]) => Ok(*last),
+            _ => {
+                for d in &dims {
+                    require_dimensionless(name, *d)?;
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Returns the common dimension of two operands that must match.
+fn unify(
+    op: &str,
+    left: Option<Dimension>,
+    right: Option<Dimension>,
+) -> Result<Option<Dimension>, ExprError> {
+    match (left, right) {
+        (Some(l), Some(r)) if l != r => Err(ExprError::DimensionMismatch {
+            operation: op.to_string(),
+            left: l.to_string(),
+            right: r.to_string(),
+        }),
+        (Some(d), _) | (_, Some(d)) => Ok(Some(d)),
+        (None, None) => Ok(None),
+    }
+}
+
+fn combine(
+    left: Option<Dimension>,
+    right: Option<Dimension>,
+    op: impl Fn(Dimension, Dimension) -> Dimension,
+) -> Option<Dimension> {
+    match (left, right) {
+        (None, None) => None,
+        (l, r) => Some(op(
+            l.unwrap_or(Dimension::DIMENSIONLESS),
+            r.unwrap_or(Dimension::DIMENSIONLESS),
+        )),
+    }
+}
+
+fn require_dimensionless(op: &str, dim: Option<Dimension>) -> Result<(), ExprError> {
+    match dim {
+        Some(d) if !d.is_dimensionless() => Err(mismatch(op, d)),
+        _ => Ok(()),
+    }
+}
+
+fn mismatch(op: &str, found: Dimension) -> ExprError {
+    ExprError::DimensionMismatch {
+        operation: op.to_string(),
+        left: found.to_string(),
+        right: Dimension::DIMENSIONLESS.to_string(),
+    }
+}
+
+/// Returns the value of a (possibly negated) integer literal that fits an exponent.
+fn constant_integer(expr: &AstExpr) -> Option<i8> {
+    let value = match expr {
+        AstExpr::Constant(v) => *v,
+        AstExpr::Function {
+            name: "neg",
+            args: [AstExpr::Constant(v)],
+        } => -*v,
+        _ => return None,
+    };
+    let n = value as i8;
+    (n as Real == value).then_some(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::EvalContext;
+    use crate::engine::{ParseOptions, parse_expression_with_options};
+    use crate::eval::eval_ast;
+    use bumpalo::Bump;
+    use std::rc::Rc;
+
+    const UNITS: ParseOptions = ParseOptions {
+        implicit_multiplication: false,
+        unit_literals: true,
+        strict_booleans: false,
+        percent_literals: false,
+        relative_percent: false,
+        strict_arity: false,
+        caret: crate::engine::CaretOperator::Power,
+        disable_double_star: false,
+        limits: crate::engine::ParserLimits::DEFAULT,
+    };
+
+    fn eval_units(input: &str) -> Real {
+        let arena = Bump::new();
+        let ast = arena.alloc(parse_expression_with_options(input, &arena, &UNITS).unwrap());
+        let mut ctx = EvalContext::new();
+        ctx.set_unit_registry(UnitRegistry::si());
+        eval_ast(ast, Some(Rc::new(ctx)), &arena).unwrap()
+    }
+
+    fn check(units: &UnitRegistry, input: &str) -> Result<Dimension, ExprError> {
+        let arena = Bump::new();
+        let ast = parse_expression_with_options(input, &arena, &UNITS).unwrap();
+        units.check(&ast)
+    }
+
+    #[test]
+    fn test_prefixed_lookup() {
+        let units = UnitRegistry::si();
+        assert_eq!(units.lookup("ms").unwrap().scale, 0.001);
+        assert_eq!(units.lookup("kg").unwrap().scale, 1.0);
+        assert_eq!(units.lookup("min").unwrap().scale, 60.0);
+        assert_eq!(units.lookup("m").unwrap().dimension, Dimension::LENGTH);
+        assert!(units.lookup("furlong").is_none());
+    }
+
+    #[test]
+    fn test_unit_literals_scale_to_base_units() {
+        assert!((eval_units("10ms") - 0.01).abs() < 1e-9);
+        assert!((eval_units("2.5kHz") - 2500.0).abs() < 1e-6);
+        assert!((eval_units("3.3V") - 3.3).abs() < 1e-6);
+        // A unit literal binds tighter than division
+        assert!((eval_units("1 / 10ms") - 100.0).abs() < 1e-3);
+        assert!((eval_units("1min + 30s") - 90.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_dimension_checking() {
+        let mut units = UnitRegistry::si();
+        units.declare_variable("i", Dimension::CURRENT);
+
+        let volts = units.lookup("V").unwrap().dimension;
+        assert_eq!(
+            check(&units, "3.3V / 10mA").unwrap(),
+            volts / Dimension::CURRENT
+        );
+        assert_eq!(check(&units, "i * 5Ohm").unwrap(), volts);
+        assert_eq!(check(&units, "(2m)^2").unwrap(), Dimension::LENGTH.powi(2));
+        assert_eq!(check(&units, "sqrt(4m^2)").unwrap(), Dimension::LENGTH);
+        assert_eq!(check(&units, "1 / 1kHz").unwrap(), Dimension::TIME);
+        assert_eq!(check(&units, "x + 2").unwrap(), Dimension::DIMENSIONLESS);
+        assert!(check(&units, "i > 0 ? i : 0").is_ok());
+
+        let err = check(&units, "3.3V + 10ms").unwrap_err();
+        assert!(matches!(err, ExprError::DimensionMismatch { .. }));
+        assert_eq!(
+            err.to_string(),
+            "Dimension mismatch in '+': kg*m^2*s^-3*A^-1 vs s"
+        );
+        assert!(check(&units, "sin(10ms)").is_err());
+        assert!(check(&units, "1m ^ x").is_err());
+        assert!(check(&units, "i < 1V").is_err());
+    }
+}
@@ -0,0 +1,316 @@
+//! Typed context values: integers and booleans alongside [`Real`].
+//!
+//! Expressions compute with [`Real`], which cannot hold every integer: an `f32` is exact
+//! only up to 2^24 and an `f64` up to 2^53, so frame counters and timestamps kept as
+//! `Real` eventually stop counting or compare wrongly. [`EvalContext::set_value`] stores
+//! an [`Int`](Value::Int) or [`Bool`](Value::Bool) exactly, and
+//! [`EvalContext::get_value`] returns it unchanged however long the host keeps
+//! updating it. Expressions read such values converted with [`Value::to_real`].
+//!
+//! Operations on two values first [`promote`](Value::promote) them to the wider of their
+//! kinds, `Bool` < `Int` < `Real`: integers combine with integer arithmetic, wrapping on
+//! overflow like the counters they hold, and anything combined with a `Real` is a
+//! `Real`.
+//!
+//! With the `int64` feature, expressions compare and subtract such values exactly too:
+//! a comparison, difference, `idiv`/`imod`/`iabs` call or bit function call involving an
+//! integer context value is computed in `i64` if all its operands are integers and
+//! nothing overflows, so `now_us - last_us > 1000` holds for microsecond timestamps
+//! beyond 2^53 and `getbit(register, 0)` reads the lowest bit of a 64-bit register.
+//!
+//! ```
+//! use exp_rs::context::EvalContext;
+//! use exp_rs::engine::interp;
+//! use exp_rs::value::Value;
+//! use std::rc::Rc;
+//!
+//! let mut ctx = EvalContext::new();
+//! ctx.set_value("frame", (1i64 << 53) + 1).unwrap();
+//! ctx.set_value("armed", true).unwrap();
+//!
+//! // The counter is kept exactly, past the precision of f64
+//! let next = ctx.get_value("frame").unwrap() + Value::Int(1);
+//! assert_eq!(next, Value::Int((1i64 << 53) + 2));
+//! assert_eq!(Value::Int(3) * Value::Real(0.5), Value::Real(1.5));
+//!
+//! assert_eq!(interp("armed ? 2 : 0", Some(Rc::new(ctx))).unwrap(), 2.0);
+//! ```
+//!
+//! [`EvalContext::set_value`]: crate::context::EvalContext::set_value
+//! [`EvalContext::get_value`]: crate::context::EvalContext::get_value
+
+use crate::Real;
+use core::ops::{Add, Mul, Sub};
+
+/// 2^63, the bound of the range of `i64`.
+const TWO_POW_63: Real = 9_223_372_036_854_775_808.0;
+
+/// A context value of one of the kinds expressions can read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Value {
+    /// A real number, as expressions compute with
+    Real(Real),
+    /// A 64-bit integer, e.g. a counter or a timestamp in microseconds
+    Int(i64),
+    /// A flag, read by expressions as 1 or 0
+    Bool(bool),
+}
+
+impl Value {
+    /// Returns the value as expressions see it: integers converted to the nearest
+    /// [`Real`], `true` as 1 and `false` as 0.
+    pub fn to_real(self) -> Real {
+        match self {
+            Value::Real(x) => x,
+            Value::Int(n) => n as Real,
+            Value::Bool(b) => Real::from(u8::from(b)),
+        }
+    }
+
+    /// Returns the value as an integer if it is one exactly: an `Int`, a `Bool`, or a
+    /// `Real` without fractional part in the range of `i64`.
+    pub fn to_int(self) -> Option<i64> {
+        match self {
+            Value::Int(n) => Some(n),
+            Value::Bool(b) => Some(i64::from(b)),
+            // i64::MAX rounds up to 2^63, which is out of range
+            Value::Real(x) if (-TWO_POW_63..TWO_POW_63).contains(&x) => {
+                let n = x as i64;
+                (n as Real == x).then_some(n)
+            }
+            Value::Real(_) => None,
+        }
+    }
+
+    /// Returns whether the value is true in a condition, i.e. non-zero.
+    pub fn is_truthy(self) -> bool {
+        match self {
+            Value::Real(x) => x != 0.0,
+            Value::Int(n) => n != 0,
+            Value::Bool(b) => b,
+        }
+    }
+
+    /// Converts both values to the wider of their kinds, `Bool` < `Int` < `Real`.
+    pub fn promote(self, other: Value) -> (Value, Value) {
+        match (self, other) {
+            (Value::Real(_), _) | (_, Value::Real(_)) => {
+                (Value::Real(self.to_real()), Value::Real(other.to_real()))
+            }
+            (Value::Bool(a), Value::Bool(b)) => (Value::Bool(a), Value::Bool(b)),
+            _ => (
+                Value::Int(self.to_int().unwrap_or_default()),
+                Value::Int(other.to_int().unwrap_or_default()),
+            ),
+        }
+    }
+
+    /// Applies an arithmetic operation after promotion, treating booleans as integers.
+    fn arithmetic(
+        self,
+        rhs: Value,
+        int: fn(i64, i64) -> i64,
+        real: fn(Real, Real) -> Real,
+    ) -> Value {
+        match self.promote(rhs) {
+            (Value::Real(a), Value::Real(b)) => Value::Real(real(a, b)),
+            (a, b) => Value::Int(int(
+                a.to_int().unwrap_or_default(),
+                b.to_int().unwrap_or_default(),
+            )),
+        }
+    }
+}
+
+impl From<Real> for Value {
+    fn from(x: Real) -> Self {
+        Value::Real(x)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        Value::Int(n)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+/// Adds after promotion; integers wrap on overflow.
+impl Add for Value {
+    type Output = Value;
+
+    fn add(self, rhs: Value) -> Value {
+        self.arithmetic(rhs, i64::wrapping_add, |a, b| a + b)
+    }
+}
+
+/// Subtracts after promotion; integers wrap on overflow.
+impl Sub for Value {
+    type Output = Value;
+
+    fn sub(self, rhs: Value) -> Value {
+        self.arithmetic(rhs, i64::wrapping_sub, |a, b| a - b)
+    }
+}
+
+/// Multiplies after promotion; integers wrap on overflow.
+impl Mul for Value {
+    type Output = Value;
+
+    fn mul(self, rhs: Value) -> Value {
+        self.arithmetic(rhs, i64::wrapping_mul, |a, b| a * b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::EvalContext;
+    use crate::engine::interp;
+    use crate::error::ExprError;
+    use alloc::format;
+    use alloc::rc::Rc;
+
+    #[test]
+    fn test_typed_values() {
+        assert_eq!(
+            Value::Bool(true).promote(Value::Int(2)),
+            (Value::Int(1), Value::Int(2))
+        );
+        assert_eq!(
+            Value::Int(2).promote(Value::Real(0.5)),
+            (Value::Real(2.0), Value::Real(0.5))
+        );
+        assert_eq!(Value::Int(i64::MAX) + Value::Int(1), Value::Int(i64::MIN));
+        assert_eq!(Value::Bool(true) + Value::Bool(true), Value::Int(2));
+        assert_eq!(Value::Real(2.5).to_int(), None);
+        assert_eq!(Value::Real(-3.0).to_int(), Some(-3));
+        assert_eq!(Value::Real(1e19).to_int(), None);
+
+        let mut parent = EvalContext::new();
+        parent.set_value("frames", 1i64 << 40).unwrap();
+        parent.set_parameter("enabled", 1.0).unwrap();
+        let mut ctx = EvalContext::new();
+        ctx.parent = Some(Rc::new(parent));
+        // A typed value replaces a real variable of the same name, and the other way round
+        ctx.set_parameter("enabled", 0.0).unwrap();
+        assert_eq!(
+            ctx.set_value("enabled", true).unwrap(),
+            Some(Value::Real(0.0))
+        );
+        assert_eq!(ctx.get_value("enabled"), Some(Value::Bool(true)));
+        ctx.set_value("limit", 3.5).unwrap();
+        assert_eq!(ctx.get_variable("limit"), Some(3.5));
+        ctx.set_value("count", 7i64).unwrap();
+        ctx.set_parameter("count", 8.0).unwrap();
+        assert_eq!(ctx.get_value("count"), Some(Value::Real(8.0)));
+
+        assert_eq!(ctx.get_value("frames"), Some(Value::Int(1 << 40)));
+        assert_eq!(ctx.get_variable("enabled"), Some(1.0));
+        assert!(ctx.list_variables().contains(&"frames".into()));
+        let ctx = Rc::new(ctx);
+        assert_eq!(
+            interp("enabled * frames / 1024 + count", Some(ctx)).unwrap(),
+            (1u64 << 30) as Real + 8.0
+        );
+
+        // Typed values are bounded like variables, and a failed set changes nothing
+        let mut ctx = EvalContext::new();
+        ctx.set_parameter("spare", 1.0).unwrap();
+        for i in 0..crate::types::EXP_RS_MAX_VARIABLES {
+            ctx.set_value(&format!("n{i}"), i as i64).unwrap();
+        }
+        ctx.set_value("n0", true).unwrap();
+        let overflow = ctx.set_value("spare", 2i64);
+        if cfg!(feature = "std") {
+            assert!(overflow.is_ok());
+        } else {
+            assert!(matches!(
+                overflow,
+                Err(ExprError::CapacityExceeded {
+                    container: "typed values"
+                })
+            ));
+            assert_eq!(ctx.get_value("spare"), Some(Value::Real(1.0)));
+        }
+        assert_eq!(ctx.get_value("n0"), Some(Value::Bool(true)));
+    }
+
+    #[test]
+    fn test_integer_functions() {
+        let eval = |expr: &str, ctx: &Rc<EvalContext>| interp(expr, Some(ctx.clone())).unwrap();
+        let mut ctx = EvalContext::new();
+        ctx.set_value("now_us", (1i64 << 53) + 1).unwrap();
+        ctx.set_value("last_us", 1i64 << 53).unwrap();
+        ctx.set_parameter("period", 4.0).unwrap();
+        let ctx = Rc::new(ctx);
+
+        assert_eq!(eval("idiv(7, 2) + idiv(-7, 2)", &ctx), -1.0);
+        assert_eq!(eval("imod(-7, 2) * 10 + imod(7, -2)", &ctx), 9.0);
+        assert_eq!(eval("iabs(-5)", &ctx), 5.0);
+        for expr in ["idiv(7, 0)", "imod(1, 0)", "idiv(7.5, 2)", "iabs(0.5)"] {
+            assert!(eval(expr, &ctx).is_nan(), "{expr}");
+        }
+
+        // Rounded to Real, the timestamps are equal; the int64 path compares them exactly
+        let exact = cfg!(feature = "int64");
+        assert_eq!(eval("now_us > last_us", &ctx), exact as u8 as Real);
+        assert_eq!(eval("now_us - last_us", &ctx), exact as u8 as Real);
+        assert_eq!(eval("imod(now_us, period) == 1", &ctx), exact as u8 as Real);
+        assert_eq!(eval("getbit(now_us, 0)", &ctx), exact as u8 as Real);
+        assert_eq!(eval("popcount(now_us)", &ctx), 1.0 + exact as u8 as Real);
+        // Mixed with reals, the usual arithmetic applies
+        assert_eq!(
+            eval("now_us - last_us + 0.5", &ctx),
+            0.5 + exact as u8 as Real
+        );
+    }
+
+    #[test]
+    fn test_integer_path_defers_to_overrides_and_observers() {
+        use crate::context::MathConfig;
+        use crate::engine::parse_expression;
+        use crate::eval::iterative::EvalEngine;
+        use core::cell::Cell;
+
+        let mut ctx = EvalContext::new();
+        ctx.set_value("now_us", (1i64 << 53) + 1).unwrap();
+        ctx.set_value("last_us", 1i64 << 53).unwrap();
+        // User implementations and comparison tolerances replace the exact operations
+        ctx.register_native_function("bitand", 2, |_| 42.0).unwrap();
+        ctx.set_math_config(MathConfig {
+            equality_epsilon: 2.0,
+            ..Default::default()
+        });
+        let ctx = Rc::new(ctx);
+        let eval = |expr: &str| interp(expr, Some(ctx.clone())).unwrap();
+        assert_eq!(eval("bitand(now_us, 1)"), 42.0);
+        assert_eq!(eval("now_us == last_us + 2"), 1.0);
+
+        // Hooks see every call, and the watchdog every node
+        let arena = bumpalo::Bump::new();
+        let ast = arena.alloc(parse_expression("now_us - last_us", &arena).unwrap());
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let mut engine = EvalEngine::new(&arena);
+        engine.set_on_function_call(move |_, _, _| counter.set(counter.get() + 1));
+        engine.eval(ast, Some(ctx.clone())).unwrap();
+        assert_eq!(calls.get(), 1);
+
+        let nodes = Rc::new(Cell::new(0));
+        let counter = nodes.clone();
+        let mut ctx = (*ctx).clone();
+        ctx.set_watchdog(1, move || {
+            counter.set(counter.get() + 1);
+            core::ops::ControlFlow::Continue(())
+        });
+        assert_eq!(interp("now_us > last_us", Some(Rc::new(ctx))).unwrap(), 0.0);
+        assert_eq!(nodes.get(), 3);
+    }
+}
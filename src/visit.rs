@@ -0,0 +1,339 @@
+//! Traversal and rewriting of ASTs.
+//!
+//! [`AstVisitor`] has one method per node kind, each with a default implementation that
+//! visits the node's children. Implementors override only the methods for the nodes they
+//! care about, so visitors keep compiling when new node kinds are added. [`walk_ast`]
+//! dispatches a node to the matching method.
+//!
+//! [`rewrite_ast`] rebuilds a tree bottom-up in an arena, replacing the nodes for which a
+//! callback returns a new node.
+
+use crate::Real;
+use crate::types::{AstExpr, LogicalOperator};
+use bumpalo::Bump;
+
+/// A read-only visitor over an AST.
+///
+/// # Examples
+///
+/// Collecting the variables referenced by an expression:
+///
+/// ```
+/// use exp_rs::engine::parse_expression;
+/// use exp_rs::visit::{AstVisitor, walk_ast};
+/// use bumpalo::Bump;
+///
+/// #[derive(Default)]
+/// struct Variables<'a>(Vec<&'a str>);
+///
+/// impl<'a> AstVisitor<'a> for Variables<'a> {
+///     fn visit_variable(&mut self, name: &'a str) {
+///         self.0.push(name);
+///     }
+/// }
+///
+/// let arena = Bump::new();
+/// let ast = parse_expression("a * sin(b) + arr[c]", &arena).unwrap();
+/// let mut vars = Variables::default();
+/// walk_ast(&mut vars, &ast);
+/// assert_eq!(vars.0, ["a", "b", "c"]);
+/// ```
+pub trait AstVisitor<'arena> {
+    /// Called for every node. The default dispatches to the node-specific method via
+    /// [`walk_ast`]; override it to act on all nodes, and call `walk_ast` to descend.
+    fn visit_expr(&mut self, expr: &AstExpr<'arena>) {
+        walk_ast(self, expr);
+    }
+
+    /// Called for a numeric literal.
+    fn visit_constant(&mut self, _value: Real) {}
+
+    /// Called for a variable reference.
+    fn visit_variable(&mut self, _name: &'arena str) {}
+
+    /// Called for a function call or operator; visits the arguments by default.
+    fn visit_function(&mut self, _name: &'arena str, args: &'arena [AstExpr<'arena>]) {
+        for arg in args {
+            self.visit_expr(arg);
+        }
+    }
+
+    /// Called for an array access; visits the index by default.
+    fn visit_array(&mut self, _name: &'arena str, index: &'arena AstExpr<'arena>) {
+        self.visit_expr(index);
+    }
+
+    /// Called for an attribute access.
+    fn visit_attribute(&mut self, _base: &'arena str, _attr: &'arena str) {}
+
+    /// Called for `&&` and `||`; visits both operands by default.
+    fn visit_logical_op(
+        &mut self,
+        _op: LogicalOperator,
+        left: &'arena AstExpr<'arena>,
+        right: &'arena AstExpr<'arena>,
+    ) {
+        self.visit_expr(left);
+        self.visit_expr(right);
+    }
+
+    /// Called for `condition ? true_branch : false_branch`; visits all three by default.
+    fn visit_conditional(
+        &mut self,
+        condition: &'arena AstExpr<'arena>,
+        true_branch: &'arena AstExpr<'arena>,
+        false_branch: &'arena AstExpr<'arena>,
+    ) {
+        self.visit_expr(condition);
+        self.visit_expr(true_branch);
+        self.visit_expr(false_branch);
+    }
+}
+
+/// Dispatches `expr` to the [`AstVisitor`] method for its node kind.
+pub fn walk_ast<'arena, V: AstVisitor<'arena> + ?Sized>(visitor: &mut V, expr: &AstExpr<'arena>) {
+    match *expr {
+        AstExpr::Constant(value) => visitor.visit_constant(value),
+        AstExpr::Variable(name) => visitor.visit_variable(name),
+        AstExpr::Function { name, args } => visitor.visit_function(name, args),
+        AstExpr::Array { name, index } => visitor.visit_array(name, index),
+        AstExpr::Attribute { base, attr } => visitor.visit_attribute(base, attr),
+        AstExpr::LogicalOp { op, left, right } => visitor.visit_logical_op(op, left, right),
+        AstExpr::Conditional {
+            condition,
+            true_branch,
+            false_branch,
+        } => visitor.visit_conditional(condition, true_branch, false_branch),
+    }
+}
+
+/// Calls `check` for every function call and operator in `expr`, outermost first,
+/// stopping at the first error.
+pub(crate) fn try_for_each_call<'arena, E>(
+    expr: &AstExpr<'arena>,
+    check: &mut impl FnMut(&'arena str, &'arena [AstExpr<'arena>]) -> Result<(), E>,
+) -> Result<(), E> {
+    match *expr {
+        AstExpr::Function { name, args } => {
+            check(name, args)?;
+            args.iter()
+                .try_for_each(|arg| try_for_each_call(arg, check))
+        }
+        AstExpr::Array { index, .. } => try_for_each_call(index, check),
+        AstExpr::LogicalOp { left, right, .. } => {
+            try_for_each_call(left, check)?;
+            try_for_each_call(right, check)
+        }
+        AstExpr::Conditional {
+            condition,
+            true_branch,
+            false_branch,
+        } => {
+            try_for_each_call(condition, check)?;
+            try_for_each_call(true_branch, check)?;
+            try_for_each_call(false_branch, check)
+        }
+        AstExpr::Constant(_) | AstExpr::Variable(_) | AstExpr::Attribute { .. } => Ok(()),
+    }
+}
+
+/// Rebuilds an AST bottom-up, letting `rewrite` replace nodes.
+///
+/// `rewrite` is called for every node after its children have been rewritten. Returning
+/// `Some(node)` replaces the node; returning `None` keeps it. Subtrees in which nothing
+/// was replaced are shared with the input rather than copied.
+///
+/// # Examples
+///
+/// Renaming a variable:
+///
+/// ```
+/// use exp_rs::engine::parse_expression;
+/// use exp_rs::types::AstExpr;
+/// use exp_rs::visit::rewrite_ast;
+/// use bumpalo::Bump;
+///
+/// let arena = Bump::new();
+/// let ast = arena.alloc(parse_expression("temp * 2 + temp_offset", &arena).unwrap());
+/// let renamed = rewrite_ast(ast, &arena, &mut |node| match node {
+///     AstExpr::Variable("temp") => Some(AstExpr::Variable("t_celsius")),
+///     _ => None,
+/// });
+/// assert_eq!(renamed.to_expression_string(), "t_celsius * 2 + temp_offset");
+/// ```
+pub fn rewrite_ast<'arena, F>(
+    expr: &'arena AstExpr<'arena>,
+    arena: &'arena Bump,
+    rewrite: &mut F,
+) -> &'arena AstExpr<'arena>
+where
+    F: FnMut(&'arena AstExpr<'arena>) -> Option<AstExpr<'arena>>,
+{
+    let rebuilt: &'arena AstExpr<'arena> = match *expr {
+        AstExpr::Constant(_) | AstExpr::Variable(_) | AstExpr::Attribute { .. } => expr,
+        AstExpr::Function { name, args } => {
+            let mut new_args = bumpalo::collections::Vec::with_capacity_in(args.len(), arena);
+            let mut changed = false;
+            for arg in args {
+                let new_arg = rewrite_ast(arg, arena, rewrite);
+                changed |= !core::ptr::eq(new_arg, arg);
+                new_args.push(new_arg.clone());
+            }
+            if changed {
+                arena.alloc(AstExpr::Function {
+                    name,
+                    args: new_args.into_bump_slice(),
+                })
+            } else {
+                expr
+            }
+        }
+        AstExpr::Array { name, index } => {
+            let new_index = rewrite_ast(index, arena, rewrite);
+            if core::ptr::eq(new_index, index) {
+                expr
+            } else {
+                arena.alloc(AstExpr::Array {
+                    name,
+                    index: new_index,
+                })
+            }
+        }
+        AstExpr::LogicalOp { op, left, right } => {
+            let new_left = rewrite_ast(left, arena, rewrite);
+            let new_right = rewrite_ast(right, arena, rewrite);
+            if core::ptr::eq(new_left, left) && core::ptr::eq(new_right, right) {
+                expr
+            } else {
+                arena.alloc(AstExpr::LogicalOp {
+                    op,
+                    left: new_left,
+                    right: new_right,
+                })
+            }
+        }
+        AstExpr::Conditional {
+            condition,
+            true_branch,
+            false_branch,
+        } => {
+            let new_condition = rewrite_ast(condition, arena, rewrite);
+            let new_true = rewrite_ast(true_branch, arena, rewrite);
+            let new_false = rewrite_ast(false_branch, arena, rewrite);
+            if core::ptr::eq(new_condition, condition)
+                && core::ptr::eq(new_true, true_branch)
+                && core::ptr::eq(new_false, false_branch)
+            {
+                expr
+            } else {
+                arena.alloc(AstExpr::Conditional {
+                    condition: new_condition,
+                    true_branch: new_true,
+                    false_branch: new_false,
+                })
+            }
+        }
+    };
+
+    match rewrite(rebuilt) {
+        Some(replacement) => arena.alloc(replacement),
+        None => rebuilt,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::parse_expression;
+
+    #[derive(Default)]
+    struct Metrics {
+        nodes: usize,
+        calls: Vec<String>,
+        constants: usize,
+        max_depth: usize,
+        depth: usize,
+    }
+
+    impl<'a> AstVisitor<'a> for Metrics {
+        fn visit_expr(&mut self, expr: &AstExpr<'a>) {
+            self.nodes += 1;
+            self.depth += 1;
+            self.max_depth = self.max_depth.max(self.depth);
+            walk_ast(self, expr);
+            self.depth -= 1;
+        }
+
+        fn visit_constant(&mut self, _value: Real) {
+            self.constants += 1;
+        }
+
+        fn visit_function(&mut self, name: &'a str, args: &'a [AstExpr<'a>]) {
+            self.calls.push(name.to_string());
+            for arg in args {
+                self.visit_expr(arg);
+            }
+        }
+    }
+
+    #[test]
+    fn test_visitor_metrics() {
+        let arena = Bump::new();
+        let ast =
+            parse_expression("x > 0 && y < 1 ? max(x, 2) : obj.attr + arr[1]", &arena).unwrap();
+        let mut metrics = Metrics::default();
+        metrics.visit_expr(&ast);
+
+        assert_eq!(metrics.calls, [">", "<", "max", "+"]);
+        assert_eq!(metrics.constants, 4);
+        assert_eq!(metrics.nodes, 15);
+        assert_eq!(metrics.max_depth, 4);
+    }
+
+    #[test]
+    fn test_default_visitor_reaches_all_leaves() {
+        struct Leaves(usize);
+        impl<'a> AstVisitor<'a> for Leaves {
+            fn visit_variable(&mut self, _name: &'a str) {
+                self.0 += 1;
+            }
+            fn visit_attribute(&mut self, _base: &'a str, _attr: &'a str) {
+                self.0 += 1;
+            }
+        }
+
+        let arena = Bump::new();
+        let ast = parse_expression("a || (b ? c.d : e[f])", &arena).unwrap();
+        let mut leaves = Leaves(0);
+        walk_ast(&mut leaves, &ast);
+        // a, b, c.d and the index f (the array name e is not a variable)
+        assert_eq!(leaves.0, 4);
+    }
+
+    #[test]
+    fn test_rewrite_shares_unchanged_subtrees() {
+        let arena = Bump::new();
+        let ast: &AstExpr = arena.alloc(parse_expression("sin(x) + y * 2", &arena).unwrap());
+
+        let unchanged = rewrite_ast(ast, &arena, &mut |_| None);
+        assert!(core::ptr::eq(unchanged, ast));
+
+        let doubled = rewrite_ast(ast, &arena, &mut |node| match *node {
+            AstExpr::Constant(c) => Some(AstExpr::Constant(c * 2.0)),
+            _ => None,
+        });
+        assert_eq!(doubled.to_expression_string(), "sin(x) + y * 4");
+        // The untouched sin(x) call keeps pointing at the original argument slice
+        match (ast, doubled) {
+            (AstExpr::Function { args: old, .. }, AstExpr::Function { args: new, .. }) => {
+                match (&old[0], &new[0]) {
+                    (AstExpr::Function { args: a, .. }, AstExpr::Function { args: b, .. }) => {
+                        assert!(core::ptr::eq(*a, *b));
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! WebAssembly bindings built with `wasm-bindgen` (`wasm` feature).
+//!
+//! The bindings wrap the same parser and evaluator as the Rust and C APIs, so an
+//! expression gives the same result in the browser as on the device (as long as both
+//! are built with the same `f32` setting). Errors are thrown as JavaScript `Error`s
+//! carrying the [`ExprError`] message.
+//!
+//! ```js
+//! import { interp, Context, CompiledExpression } from "exp_rs";
+//!
+//! interp("2 * (3 + 4)"); // 14
+//!
+//! const ctx = new Context();
+//! ctx.setParameter("gain", 1.5);
+//! const expr = new CompiledExpression("x * gain", ["x"]);
+//! expr.setParameter("x", 4);
+//! expr.eval(ctx); // 6
+//! ```
+
+use crate::Real;
+use crate::context::EvalContext;
+use crate::error::ExprError;
+use crate::expression::Expression;
+use crate::types::TryIntoHeaplessString;
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use bumpalo::Bump;
+use wasm_bindgen::prelude::*;
+
+fn js_error(err: ExprError) -> JsError {
+    JsError::new(&err.to_string())
+}
+
+/// Evaluates an expression with the default functions and constants.
+#[wasm_bindgen]
+pub fn interp(expression: &str) -> Result<Real, JsError> {
+    crate::engine::interp(expression, None).map_err(js_error)
+}
+
+/// An evaluation context holding variables, constants, arrays and attributes.
+#[wasm_bindgen(js_name = Context)]
+pub struct WasmContext {
+    inner: Rc<EvalContext>,
+}
+
+impl Default for WasmContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen(js_class = Context)]
+impl WasmContext {
+    /// Creates a context with the default functions.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> WasmContext {
+        WasmContext {
+            inner: Rc::new(EvalContext::new()),
+        }
+    }
+
+    /// Sets a variable.
+    #[wasm_bindgen(js_name = setParameter)]
+    pub fn set_parameter(&mut self, name: &str, value: Real) -> Result<(), JsError> {
+        Rc::make_mut(&mut self.inner)
+            .set_parameter(name, value)
+            .map(|_| ())
+            .map_err(js_error)
+    }
+
+    /// Sets a constant.
+    #[wasm_bindgen(js_name = setConstant)]
+    pub fn set_constant(&mut self, name: &str, value: Real) -> Result<(), JsError> {
+        let key = name.try_into_heapless().map_err(js_error)?;
+        Rc::make_mut(&mut self.inner)
+            .constants
+            .insert(key, value)
+            .map(|_| ())
+            .map_err(|_| {
+                js_error(ExprError::CapacityExceeded {
+                    container: "constants",
+                })
+            })
+    }
+
+    /// Sets an array, read in expressions as `name[index]`.
+    #[wasm_bindgen(js_name = setArray)]
+    pub fn set_array(&mut self, name: &str, values: Vec<Real>) -> Result<(), JsError> {
+        let key = name.try_into_heapless().map_err(js_error)?;
+        Rc::make_mut(&mut self.inner)
+            .arrays
+            .insert(key, values)
+            .map(|_| ())
+            .map_err(|_| {
+                js_error(ExprError::CapacityExceeded {
+                    container: "arrays",
+                })
+            })
+    }
+
+    /// Sets an object attribute, read in expressions as `object.attribute`.
+    #[wasm_bindgen(js_name = setAttribute)]
+    pub fn set_attribute(
+        &mut self,
+        object: &str,
+        attribute: &str,
+        value: Real,
+    ) -> Result<(), JsError> {
+        Rc::make_mut(&mut self.inner)
+            .set_attribute(object, attribute, value)
+            .map(|_| ())
+            .map_err(js_error)
+    }
+
+    /// Evaluates an expression in this context.
+    pub fn eval(&self, expression: &str) -> Result<Real, JsError> {
+        crate::engine::interp(expression, Some(self.inner.clone())).map_err(js_error)
+    }
+}
+
+/// An expression parsed once and evaluated many times with changing parameters.
+#[wasm_bindgen]
+pub struct CompiledExpression {
+    // Declared before `arena` so that it is dropped first
+    batch: Box<Expression<'static>>,
+    arena: Box<Bump>,
+}
+
+#[wasm_bindgen]
+impl CompiledExpression {
+    /// Parses `expression` with the given parameters, which start at 0.
+    #[wasm_bindgen(constructor)]
+    pub fn new(expression: &str, parameters: Vec<String>) -> Result<CompiledExpression, JsError> {
+        let arena = Box::new(Bump::new());
+        // SAFETY: the arena is heap-allocated, never moved out of its box and outlives
+        // the batch, which is dropped first
+        let arena_ref: &'static Bump = unsafe { &*(arena.as_ref() as *const Bump) };
+        let mut batch = Box::new(Expression::new(arena_ref));
+        for name in &parameters {
+            batch.add_parameter(name, 0.0).map_err(js_error)?;
+        }
+        batch.add_expression(expression).map_err(js_error)?;
+        Ok(CompiledExpression { batch, arena })
+    }
+
+    /// Sets a parameter value for the following evaluations.
+    #[wasm_bindgen(js_name = setParameter)]
+    pub fn set_parameter(&mut self, name: &str, value: Real) -> Result<(), JsError> {
+        self.batch.set_param_by_name(name, value).map_err(js_error)
+    }
+
+    /// Evaluates the expression with the current parameters and `context`.
+    pub fn eval(&mut self, context: &WasmContext) -> Result<Real, JsError> {
+        self.batch.eval(&context.inner).map_err(js_error)?;
+        Ok(self.batch.get_result(0).unwrap_or(Real::NAN))
+    }
+
+    /// Replaces the named parameters by their current values and folds the constant
+    /// subtrees, using `context` for functions and for names that are not parameters.
+    pub fn specialize(
+        &mut self,
+        context: &WasmContext,
+        frozen_parameters: Vec<String>,
+    ) -> Result<(), JsError> {
+        let frozen: Vec<&str> = frozen_parameters.iter().map(String::as_str).collect();
+        self.batch
+            .specialize(&context.inner, &frozen)
+            .map_err(js_error)
+    }
+
+    /// Returns the number of bytes allocated by the expression's arena.
+    #[wasm_bindgen(js_name = allocatedBytes)]
+    pub fn allocated_bytes(&self) -> usize {
+        self.arena.allocated_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wasm_bindings_evaluate_like_native() {
+        assert_eq!(interp("2 * (3 + 4)").ok(), Some(14.0));
+
+        let mut ctx = WasmContext::new();
+        assert!(ctx.set_parameter("gain", 1.5).is_ok());
+        assert!(ctx.set_constant("offset", 1.0).is_ok());
+        assert!(ctx.set_array("table", alloc::vec![1.0, 2.0, 4.0]).is_ok());
+        assert!(ctx.set_attribute("motor", "rpm", 100.0).is_ok());
+        assert_eq!(
+            ctx.eval("gain * table[2] + offset + motor.rpm").ok(),
+            Some(107.0)
+        );
+
+        let mut expr = CompiledExpression::new(
+            "x * gain + y",
+            alloc::vec!["x".to_string(), "y".to_string()],
+        )
+        .ok()
+        .unwrap();
+        for x in 0..3 {
+            assert!(expr.set_parameter("x", x as Real).is_ok());
+            assert!(expr.set_parameter("y", 0.5).is_ok());
+            assert_eq!(expr.eval(&ctx).ok(), Some(x as Real * 1.5 + 0.5));
+        }
+        assert!(expr.allocated_bytes() > 0);
+        assert!(expr.specialize(&ctx, alloc::vec!["y".to_string()]).is_ok());
+        assert!(expr.set_parameter("x", 2.0).is_ok());
+        assert_eq!(expr.eval(&ctx).ok(), Some(3.5));
+    }
+}
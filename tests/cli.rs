@@ -0,0 +1,68 @@
+//! Tests of the exp-rs command-line evaluator, built with the `cli` feature
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn run(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_exp-rs"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("cannot start exp-rs");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_cli_arguments_stdin_and_batch_files() {
+    let output = run(&["--var", "x=1.5", "x * 2", "-x^2"], "");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3\n-2.25\n");
+
+    // Comments and blank lines are skipped; errors are reported by line
+    let output = run(&["--f32"], "# check\n0.1\n\nsin(\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("{}\n", 0.1f32 as f64)
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("stdin:4: "));
+
+    let path = std::env::temp_dir().join(format!("exp-rs-cli-{}.txt", std::process::id()));
+    std::fs::write(&path, "a + 1\n").unwrap();
+    let output = run(
+        &["--ast", "--file", path.to_str().unwrap(), "--var", "a=2"],
+        "",
+    );
+    std::fs::remove_file(&path).unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Variable(") && stdout.ends_with("\n3\n"),
+        "{stdout}"
+    );
+
+    assert_eq!(run(&["--bogus"], "").status.code(), Some(2));
+}
+
+#[test]
+fn test_cli_repl_keeps_definitions() {
+    let output = run(
+        &["--repl", "--var", "y=1"],
+        "x = 2\nf(a, k=10) = a * x + k\nf(3)\nbad(\n:vars\n:funcs\n:reset\n:vars\n:quit\n1\n",
+    );
+    // Errors at the prompt are reported without failing the session
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "x = 2\nf(a, k=10) = a * x + k\n16\nx = 2\ny = 1\nf(a, k=10) = a * x + k\n\
+         session reset\ny = 1\n"
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("error: "));
+}
@@ -624,7 +624,7 @@ mod unit {
     fn test_unknown_variable_and_function_eval() {
         let err = interp("sin", None).unwrap_err();
         match err {
-            ExprError::Syntax(msg) => {
+            ExprError::Syntax { message: msg, .. } => {
                 assert!(
                     msg.contains("Function 'sin' used without arguments"),
                     "Expected error about function used without arguments"
@@ -635,7 +635,7 @@ mod unit {
 
         let err2 = interp("abs", None).unwrap_err();
         match err2 {
-            ExprError::Syntax(msg) => {
+            ExprError::Syntax { message: msg, .. } => {
                 assert!(
                     msg.contains("Function 'abs' used without arguments"),
                     "Expected error about function used without arguments"